@@ -0,0 +1,91 @@
+use crate::directory::traversal::DirectoryTraverser;
+use crate::fuzzy::filter::filter_tree_nodes;
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Timings of one benchmark, printed as JSON so runs can be compared in PRs
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    pub benchmark: &'static str,
+    pub iterations: usize,
+    pub nodes: usize, // Scanned nodes, or nodes matching the query
+    pub min_ms: f64,
+    pub median_ms: f64,
+    pub p95_ms: f64,
+    pub nodes_per_sec: f64, // Tree size over the median run
+}
+
+impl BenchReport {
+    fn from_samples(benchmark: &'static str, nodes: usize, tree_size: usize, mut samples: Vec<Duration>) -> Self {
+        samples.sort();
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let percentile = |fraction: f64| samples[((samples.len() - 1) as f64 * fraction).round() as usize];
+        let median = percentile(0.5);
+        Self {
+            benchmark,
+            iterations: samples.len(),
+            nodes,
+            min_ms: millis(samples[0]),
+            median_ms: millis(median),
+            p95_ms: millis(percentile(0.95)),
+            nodes_per_sec: tree_size as f64 / median.as_secs_f64().max(f64::EPSILON),
+        }
+    }
+}
+
+/// Scan `root` `iterations` times with the given traverser
+pub fn bench_scan(traverser: &DirectoryTraverser, root: &Path, iterations: usize) -> Result<BenchReport> {
+    let mut samples = Vec::with_capacity(iterations);
+    let mut nodes = 0;
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        let tree = traverser.traverse(root)?;
+        samples.push(start.elapsed());
+        nodes = tree.nodes.len();
+    }
+    Ok(BenchReport::from_samples("scan", nodes, nodes, samples))
+}
+
+/// Scan `root` once, then run the fuzzy filter for `query` `iterations` times
+pub fn bench_filter(traverser: &DirectoryTraverser, root: &Path, query: &str, iterations: usize) -> Result<BenchReport> {
+    let tree = traverser.traverse(root)?;
+    let mut samples = Vec::with_capacity(iterations);
+    let mut nodes = 0;
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        let results = filter_tree_nodes(&tree, query);
+        samples.push(start.elapsed());
+        nodes = results.len();
+    }
+    Ok(BenchReport::from_samples("filter", nodes, tree.nodes.len(), samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_bench_reports() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        for directory in ["src", "tests", "docs"] {
+            fs::create_dir(temp_dir.path().join(directory))?;
+            for file in 0..20 {
+                fs::write(temp_dir.path().join(directory).join(format!("file_{}.rs", file)), "fn f() {}")?;
+            }
+        }
+        let traverser = DirectoryTraverser::new(true, false, 1024, false);
+
+        let scan = bench_scan(&traverser, temp_dir.path(), 5)?;
+        assert_eq!((scan.benchmark, scan.iterations, scan.nodes), ("scan", 5, 64));
+        assert!(scan.min_ms <= scan.median_ms && scan.median_ms <= scan.p95_ms);
+
+        let filter = bench_filter(&traverser, temp_dir.path(), "src/file_1", 5)?;
+        assert!(filter.nodes > 0 && filter.nodes < 64);
+        assert!(filter.nodes_per_sec > 0.0);
+        Ok(())
+    }
+}
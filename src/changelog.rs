@@ -0,0 +1,152 @@
+use crate::config::paths::paths;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// One release's user-facing changes, newest releases first in `RELEASES`
+#[derive(Debug, PartialEq)]
+pub struct Release {
+    pub version: &'static str,
+    pub changes: &'static [&'static str],
+}
+
+/// Shown once after an upgrade and printed by `--changelog`. Lead with changed keys:
+/// those are what people trip over after updating.
+pub const RELEASES: &[Release] = &[
+    Release {
+        version: "0.2.0",
+        changes: &[
+            "Letters are key bindings while the list has focus; / focuses the search bar (legacy_typeahead = true restores typing to search)",
+            "Alt+Enter includes a directory's files until the token budget is reached",
+            "+ / - / * include, exclude or invert all matches, previewed before they apply; X excludes an extension",
+            "f jumps to names without filtering the list",
+            "c pins a file to compare side by side, D diffs two files",
+            "g lists only files changed since git HEAD",
+            "O scans a different root, > / < re-root the view to a directory and back",
+            "l locks a node against bulk changes, # notes why a node is excluded",
+            "= gives siblings the hovered node's state, E selects project essentials, s adds the local files a Rust file uses",
+            "R picks an export recipe; gthr run <recipe> exports with one without the interface",
+            "Ctrl+B hides the status bar, Ctrl+Shift+D shows changes since the last export, Ctrl+Shift+Y copies the included paths",
+            "New options: --serve, --extract-matches, --patch, --set, --tracked-only, --file-perms, --ascii; gthr gc prunes old data",
+            "Entries sort naturally (file2 before file10); sort_order = \"bytewise\" keeps raw byte order",
+        ],
+    },
+    Release {
+        version: "0.1.0",
+        changes: &["First release: fuzzy-find files to include and export them as markdown to the clipboard or a file"],
+    },
+];
+
+/// The whole changelog as text, for `--changelog`
+pub fn render() -> String {
+    let mut output = String::new();
+    for release in RELEASES {
+        output.push_str(&format!("gthr {}\n", release.version));
+        for change in release.changes {
+            output.push_str(&format!("  - {}\n", change));
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// Releases newer than `last_seen`, up to the running one, newest first
+pub fn releases_since(last_seen: &str) -> Vec<&'static Release> {
+    let current = parse_version(env!("CARGO_PKG_VERSION"));
+    let last_seen = parse_version(last_seen);
+    RELEASES
+        .iter()
+        .filter(|release| {
+            let version = parse_version(release.version);
+            version > last_seen && version <= current
+        })
+        .collect()
+}
+
+fn parse_version(version: &str) -> Vec<u64> {
+    version.trim().split('.').map(|part| part.parse().unwrap_or(0)).collect()
+}
+
+/// The last version whose changes were shown, kept in the state directory
+#[derive(Debug, Clone)]
+pub struct VersionStamp {
+    path: PathBuf,
+}
+
+impl VersionStamp {
+    pub fn new() -> Self {
+        Self {
+            path: paths().state_dir.join("last-version"),
+        }
+    }
+
+    #[cfg(test)]
+    fn at(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// What's new since the version recorded last, recording the running one. Without a
+    /// record, only someone who `used_before` gthr sees the current release's changes.
+    /// Failing to read or write the record never gets in the way: at worst the changes
+    /// show again next time.
+    pub fn whats_new(&self, used_before: bool) -> Vec<&'static Release> {
+        let current = env!("CARGO_PKG_VERSION");
+        let releases = match fs::read_to_string(&self.path) {
+            Ok(last_seen) if last_seen.trim() == current => return Vec::new(),
+            Ok(last_seen) => releases_since(&last_seen),
+            Err(_) if used_before => RELEASES.iter().filter(|release| release.version == current).collect(),
+            Err(_) => Vec::new(),
+        };
+        let _ = self.write(current);
+        releases
+    }
+
+    /// Write to a temporary file and rename it over the record, so a crash or a second
+    /// gthr starting at the same time never leaves a half-written version behind
+    fn write(&self, version: &str) -> io::Result<()> {
+        let Some(dir) = self.path.parent() else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir)?;
+        let temp_path = dir.join(format!(".last-version.{}.tmp", std::process::id()));
+        fs::write(&temp_path, format!("{}\n", version))?;
+        fs::rename(&temp_path, &self.path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp_path);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_whats_new() {
+        let current = env!("CARGO_PKG_VERSION");
+        assert_eq!(RELEASES[0].version, current, "add the running version to RELEASES");
+        let versions = |releases: Vec<&Release>| releases.iter().map(|release| release.version).collect::<Vec<_>>();
+        assert_eq!(versions(releases_since("0.1.0")), [current]);
+        assert_eq!(versions(releases_since("0.0.9")), [current, "0.1.0"]);
+        assert!(releases_since(current).is_empty());
+
+        // A fresh install sees nothing; later runs only see what they haven't
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("gthr").join("last-version");
+        let stamp = VersionStamp::at(path.clone());
+        assert!(stamp.whats_new(false).is_empty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", current));
+        assert!(stamp.whats_new(true).is_empty());
+
+        fs::write(&path, "0.1.0\n").unwrap();
+        assert_eq!(versions(stamp.whats_new(false)), [current]);
+        assert!(stamp.whats_new(false).is_empty());
+
+        // Upgrading from before versions were recorded shows the current release only
+        fs::remove_file(&path).unwrap();
+        assert_eq!(versions(stamp.whats_new(true)), [current]);
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1, "no temporary files left");
+
+        assert!(render().starts_with(&format!("gthr {}\n  - ", current)));
+    }
+}
@@ -1,4 +1,5 @@
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand};
+use regex::Regex;
 use std::path::PathBuf;
 use crate::constants::DEFAULT_MAX_FILE_SIZE;
 
@@ -6,11 +7,12 @@ use crate::constants::DEFAULT_MAX_FILE_SIZE;
 #[command(name = "gthr")]
 #[command(about = "A CLI tool for directory text ingestion with fuzzy finder capabilities")]
 #[command(version = env!("CARGO_PKG_VERSION"))]
+#[command(group(ArgGroup::new("watch_target").args(["output", "headless", "slot"]).multiple(true)))]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
 
-    /// Root directory to process
+    /// Root directory (or .tar, .tar.gz, .zip archive) to process
     #[arg(short, long, default_value = ".")]
     pub root: PathBuf,
 
@@ -38,6 +40,19 @@ pub struct Cli {
     #[arg(long = "respect-gitignore", short = 'g', action = clap::ArgAction::Set)]
     pub respect_gitignore: Option<bool>,
 
+    /// List files .gitignore would hide (same as --respect-gitignore false)
+    #[arg(long = "no-gitignore", conflicts_with = "respect_gitignore")]
+    pub no_gitignore: bool,
+
+    /// List everything ignore files and `default_ignores` would hide (.gitignore, .ignore,
+    /// .git/info/exclude, the global gitignore); --ignore patterns still apply
+    #[arg(long = "no-ignore", conflicts_with = "respect_gitignore")]
+    pub no_ignore: bool,
+
+    /// Leave out paths matching PATTERN, in .gitignore syntax relative to the root (repeatable)
+    #[arg(long = "ignore", value_name = "PATTERN")]
+    pub ignore: Vec<String>,
+
     /// Show hidden files and directories
     #[arg(long = "show-hidden", short = 'H', action = clap::ArgAction::Set)]
     pub show_hidden: Option<bool>,
@@ -45,6 +60,149 @@ pub struct Cli {
     /// Maximum file size to include (in bytes)
     #[arg(long, default_value_t = DEFAULT_MAX_FILE_SIZE)]
     pub max_file_size: u64,
+
+    /// Don't skip lockfiles and build directories (`default_ignores` in the config)
+    #[arg(long = "no-default-ignores")]
+    pub no_default_ignores: bool,
+
+    /// Don't read or update the cache of line and token counts from earlier exports
+    #[arg(long = "no-cache")]
+    pub no_cache: bool,
+
+    /// Only include files tracked by git (no-op outside a git repository)
+    #[arg(long = "tracked-only")]
+    pub tracked_only: bool,
+
+    /// Export one git patch of the included files against HEAD instead of their content
+    #[arg(long = "patch", global = true)]
+    pub patch: bool,
+
+    /// Export only the lines matching REGEX, grep-style, leaving out files without a match
+    #[arg(long = "extract-matches", value_name = "REGEX", value_parser = Regex::new)]
+    pub extract_matches: Option<Regex>,
+
+    /// Lines of context around each extracted match
+    #[arg(long = "context", value_name = "N", default_value_t = 2, requires = "extract_matches")]
+    pub context: usize,
+
+    /// Serve the export on 127.0.0.1 until it's downloaded once, instead of copying or saving it
+    #[arg(long = "serve", value_name = "PORT", num_args = 0..=1, require_equals = true, default_missing_value = "0", conflicts_with = "output")]
+    pub serve: Option<u16>,
+
+    /// Export without the interface, to --output or else stdout, with a summary on stderr.
+    /// Implied when stdout isn't a terminal and no subcommand is given
+    #[arg(long = "headless", conflicts_with = "serve")]
+    pub headless: bool,
+
+    /// After exporting without the interface, watch the included files and export again
+    /// whenever they change, until Ctrl+C. Needs --output, --headless or --slot, so exports
+    /// never go to the clipboard or ask where to save
+    #[arg(long = "watch", conflicts_with = "serve", requires = "watch_target")]
+    pub watch: bool,
+
+    /// Milliseconds without a change before --watch exports again
+    #[arg(long = "watch-debounce", value_name = "MS", default_value_t = 300, requires = "watch")]
+    pub watch_debounce: u64,
+
+    /// Export to slot N (1-4) as F1-F4 do, without the interface
+    #[arg(long = "slot", value_name = "N", value_parser = clap::value_parser!(u8).range(1..=4), conflicts_with_all = ["output", "serve"])]
+    pub slot: Option<u8>,
+
+    /// Export format: markdown, xml, plain or json (overrides `format` in the config)
+    #[arg(long = "format", value_name = "FORMAT")]
+    pub format: Option<String>,
+
+    /// Token budget for this run: the status bar's estimate turns red past it (overrides `token_budget`)
+    #[arg(long = "max-tokens", value_name = "TOKENS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_tokens: Option<u64>,
+
+    /// Text appended after all file content ({date} and {root} are expanded)
+    #[arg(long = "footer", value_name = "TEXT")]
+    pub footer: Option<String>,
+
+    /// Also copy each export into this directory with a timestamped name
+    #[arg(long = "publish-dir", value_name = "DIR")]
+    pub publish_dir: Option<PathBuf>,
+
+    /// Add a file:// URI to the root and each file in JSON exports, and the zero-based
+    /// line range of files exported only in part
+    #[arg(long = "uris")]
+    pub uris: bool,
+
+    /// Export included images as a description: format, dimensions, size, and an SVG's
+    /// title and description
+    #[arg(long = "image-stubs")]
+    pub image_stubs: bool,
+
+    /// Like --image-stubs, and embed images up to `embed_image_max_size` as base64
+    #[arg(long = "embed-images")]
+    pub embed_images: bool,
+
+    /// Include each file's mode bits, owner and group in its export header (Unix only)
+    #[arg(long = "file-perms")]
+    pub file_perms: bool,
+
+    /// Global config file to use [default: $GTHR_CONFIG_DIR or $XDG_CONFIG_HOME/gthr/config.toml]
+    #[arg(long = "config", value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Append timestamped events (scans, slow searches, bulk changes, exports, crashes) to
+    /// FILE, for bug reports. Only paths, counts and sizes are logged, never file contents.
+    /// GTHR_LOG=error|warn|info|debug sets the detail, and on its own logs to the state directory
+    #[arg(long = "log-file", value_name = "FILE", global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Report housekeeping, such as files removed by the weekly cleanup
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// Use plain ASCII instead of unicode icons and symbols
+    #[arg(long, global = true)]
+    pub ascii: bool,
+
+    /// Don't turn file paths into clickable terminal links
+    #[arg(long = "no-hyperlinks", global = true)]
+    pub no_hyperlinks: bool,
+
+    /// Don't set the terminal title or report scan and export progress to the terminal
+    #[arg(long = "no-term-integration", global = true)]
+    pub no_term_integration: bool,
+
+    /// Rules file to apply after scanning [default: <root>/.gthr.rules]
+    #[arg(long = "rules", value_name = "PATH")]
+    pub rules: Option<PathBuf>,
+
+    /// Selection set to load instead of the default selection (see `S` to save one)
+    #[arg(long = "set", value_name = "PATH", conflicts_with_all = ["include", "exclude"])]
+    pub set: Option<PathBuf>,
+
+    /// Saved selection to start from, from .gthr/profiles/NAME.toml (see --save-profile)
+    #[arg(long = "profile", value_name = "NAME", conflicts_with_all = ["set", "include", "exclude"])]
+    pub profile: Option<String>,
+
+    /// Profile to save the selection to on export and quit [default: the one loaded]
+    #[arg(long = "save-profile", value_name = "NAME")]
+    pub save_profile: Option<String>,
+
+    /// Shell command, run in the root, printing the paths to start with included, one per line
+    #[arg(long = "select-cmd", value_name = "COMMAND", conflicts_with_all = ["set", "profile", "include", "exclude"])]
+    pub select_cmd: Option<String>,
+
+    /// Print what changed in each release and exit
+    #[arg(long = "changelog")]
+    pub changelog: bool,
+
+    /// Time scanning PATH and print statistics as JSON, without the interface
+    #[arg(long = "bench-scan", value_name = "PATH", hide = true)]
+    pub bench_scan: Option<PathBuf>,
+
+    /// Time filtering the tree at PATH for QUERY and print statistics as JSON
+    #[arg(long = "bench-filter", num_args = 2, value_names = ["PATH", "QUERY"], hide = true)]
+    pub bench_filter: Option<Vec<String>>,
+
+    /// Runs per benchmark
+    #[arg(long = "bench-iterations", value_name = "N", default_value_t = 10, hide = true)]
+    pub bench_iterations: usize,
 }
 
 #[derive(Subcommand)]
@@ -53,6 +211,17 @@ pub enum Commands {
     Interactive,
     /// Generate text ingest directly without interaction
     Direct,
+    /// Export with a recipe from the config, without the interface
+    Run {
+        /// Name of a [recipes.<name>] table in .gthr.toml or the global config
+        recipe: String,
+    },
+    /// Prune stale sessions, old caches and published exports beyond their limits
+    Gc {
+        /// Only print what would be removed
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+    },
 }
 
 impl Default for Cli {
@@ -66,8 +235,45 @@ impl Default for Cli {
             exclude: Vec::new(),
             output: None,
             respect_gitignore: None,
+            no_gitignore: false,
+            no_ignore: false,
+            ignore: Vec::new(),
             show_hidden: None,
             max_file_size: DEFAULT_MAX_FILE_SIZE,
+            no_default_ignores: false,
+            no_cache: false,
+            tracked_only: false,
+            patch: false,
+            extract_matches: None,
+            context: 2,
+            serve: None,
+            headless: false,
+            watch: false,
+            watch_debounce: 300,
+            slot: None,
+            format: None,
+            max_tokens: None,
+            footer: None,
+            publish_dir: None,
+            uris: false,
+            image_stubs: false,
+            embed_images: false,
+            file_perms: false,
+            config: None,
+            log_file: None,
+            ascii: false,
+            verbose: false,
+            no_hyperlinks: false,
+            no_term_integration: false,
+            rules: None,
+            set: None,
+            profile: None,
+            save_profile: None,
+            select_cmd: None,
+            changelog: false,
+            bench_scan: None,
+            bench_filter: None,
+            bench_iterations: 10,
         }
     }
 }
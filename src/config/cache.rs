@@ -0,0 +1,265 @@
+use super::paths::paths;
+use super::session::{Session, canonical_root, fnv1a_hash};
+use crate::output::tokens::tokenizer;
+use crate::ui::info::count_lines;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// First bytes of every cache file; bump the digit when the layout changes
+const MAGIC: &[u8] = b"GTHRCACHE1";
+
+/// What a file's content adds up to, valid while its size and mtime are unchanged
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContentSummary {
+    pub hash: u64, // FNV-1a of the content
+    pub lines: usize,
+    pub tokens: u64,
+}
+
+impl ContentSummary {
+    pub fn of(content: &[u8]) -> Self {
+        Self {
+            hash: fnv1a_hash(content),
+            lines: count_lines(content),
+            tokens: tokenizer().count(content),
+        }
+    }
+}
+
+/// The metadata a summary was computed for
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileStamp {
+    size: u64,
+    modified: u128, // Nanoseconds since the Unix epoch
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+        Some(Self {
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// Per-project content summaries kept between runs, so unchanged files aren't re-read
+/// for their line and token counts. Stored next to the session, outside the project.
+#[derive(Debug, Default)]
+pub struct ContentCache {
+    path: PathBuf,
+    entries: HashMap<String, (FileStamp, ContentSummary)>, // Relative path -> summary
+    dirty: bool,
+}
+
+impl ContentCache {
+    /// Load the cache for `project_root`. A missing, corrupt or outdated cache (e.g. written
+    /// with another tokenizer) starts out empty and is rewritten on the next save.
+    pub fn load(project_root: &Path) -> Self {
+        Self::load_from(Self::get_cache_path(&canonical_root(project_root)))
+    }
+
+    fn load_from(path: PathBuf) -> Self {
+        let entries = fs::read(&path).ok().and_then(|bytes| decode(&bytes)).unwrap_or_default();
+        Self {
+            path,
+            entries,
+            dirty: false,
+        }
+    }
+
+    /// Write the cache if anything changed since it was loaded
+    pub fn save(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write then rename, so an interrupted save never leaves a truncated cache
+        let temporary_path = self.path.with_extension("tmp");
+        fs::write(&temporary_path, encode(&self.entries))?;
+        fs::rename(&temporary_path, &self.path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Cache files are named like session files, in the cache directory
+    pub fn get_cache_path(root: &Path) -> PathBuf {
+        let session_path = Session::get_session_path(root);
+        let file_name = session_path.with_extension("bin");
+        let file_name = file_name.file_name().unwrap_or_default();
+        paths().cache_dir.join(file_name)
+    }
+
+    /// The cached summary for a file, if it hasn't changed on disk since it was recorded
+    pub fn get(&self, relative_path: &str, absolute_path: &Path) -> Option<ContentSummary> {
+        let (stamp, summary) = self.entries.get(relative_path)?;
+        (FileStamp::of(absolute_path)? == *stamp).then_some(*summary)
+    }
+
+    /// Size and content hash recorded for a path, even if the file is gone or changed since
+    pub fn fingerprint(&self, relative_path: &str) -> Option<(u64, u64)> {
+        self.entries.get(relative_path).map(|(stamp, summary)| (stamp.size, summary.hash))
+    }
+
+    /// Remember the summary of content that was just read from `absolute_path`. An unchanged
+    /// file keeps its recorded summary, so it isn't hashed and tokenized again.
+    pub fn record(&mut self, relative_path: &str, absolute_path: &Path, content: &[u8]) -> ContentSummary {
+        let stamp = FileStamp::of(absolute_path);
+        if let Some(stamp) = stamp
+            && let Some((recorded, summary)) = self.entries.get(relative_path)
+            && *recorded == stamp
+        {
+            return *summary;
+        }
+        let summary = ContentSummary::of(content);
+        if let Some(stamp) = stamp {
+            self.entries.insert(relative_path.to_string(), (stamp, summary));
+            self.dirty = true;
+        }
+        summary
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+fn encode(entries: &HashMap<String, (FileStamp, ContentSummary)>) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    let push_str = |bytes: &mut Vec<u8>, text: &str| {
+        bytes.extend((text.len() as u32).to_le_bytes());
+        bytes.extend(text.as_bytes());
+    };
+    push_str(&mut bytes, tokenizer().name());
+    bytes.extend((entries.len() as u64).to_le_bytes());
+    for (path, (stamp, summary)) in entries {
+        push_str(&mut bytes, path);
+        bytes.extend(stamp.size.to_le_bytes());
+        bytes.extend(stamp.modified.to_le_bytes());
+        bytes.extend(summary.hash.to_le_bytes());
+        bytes.extend((summary.lines as u64).to_le_bytes());
+        bytes.extend(summary.tokens.to_le_bytes());
+    }
+    bytes
+}
+
+/// `None` for anything that isn't a complete cache written with the current tokenizer
+fn decode(bytes: &[u8]) -> Option<HashMap<String, (FileStamp, ContentSummary)>> {
+    let mut reader = Reader { bytes };
+    if reader.take(MAGIC.len())? != MAGIC || reader.string()? != tokenizer().name() {
+        return None;
+    }
+
+    let count = reader.u64()?;
+    let mut entries = HashMap::new();
+    for _ in 0..count {
+        let path = reader.string()?;
+        let stamp = FileStamp {
+            size: reader.u64()?,
+            modified: u128::from_le_bytes(reader.take(16)?.try_into().ok()?),
+        };
+        let summary = ContentSummary {
+            hash: reader.u64()?,
+            lines: reader.u64()? as usize,
+            tokens: reader.u64()?,
+        };
+        entries.insert(path, (stamp, summary));
+    }
+    reader.bytes.is_empty().then_some(entries)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < length {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let length = u32::from_le_bytes(self.take(4)?.try_into().ok()?) as usize;
+        String::from_utf8(self.take(length)?.to_vec()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn cache_in(temp_dir: &TempDir) -> ContentCache {
+        ContentCache::load_from(temp_dir.path().join("cache.bin"))
+    }
+
+    fn reload(cache: &ContentCache) -> ContentCache {
+        ContentCache::load_from(cache.path.clone())
+    }
+
+    #[test]
+    fn test_cache_round_trip_and_invalidation() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file_path = temp_dir.path().join("main.rs");
+        fs::write(&file_path, "fn main() {\n}\n")?;
+
+        let mut cache = cache_in(&temp_dir);
+        let summary = cache.record("main.rs", &file_path, b"fn main() {\n}\n");
+        assert_eq!((summary.lines, summary.tokens), (2, 4));
+        cache.save()?;
+
+        let mut cache = reload(&cache);
+        assert_eq!(cache.get("main.rs", &file_path), Some(summary));
+
+        // Reading an unchanged file again keeps the recorded summary instead of recounting
+        assert_eq!(cache.record("main.rs", &file_path, b""), summary);
+        assert!(!cache.dirty);
+
+        // Any change to size or mtime means the file has to be read again
+        fs::write(&file_path, "fn main() { changed(); }\n")?;
+        assert_eq!(cache.get("main.rs", &file_path), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_cache_is_rebuilt() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file_path = temp_dir.path().join("main.rs");
+        fs::write(&file_path, "fn main() {}")?;
+        let mut cache = cache_in(&temp_dir);
+        cache.record("main.rs", &file_path, b"fn main() {}");
+        cache.save()?;
+        let valid = fs::read(&cache.path)?;
+
+        // Truncated, garbage, trailing bytes, or another tokenizer: all start empty
+        let mut other_tokenizer = valid.clone();
+        other_tokenizer[MAGIC.len() + 4] ^= 0xff;
+        let mut trailing = valid.clone();
+        trailing.push(0);
+        for bytes in [valid[..valid.len() - 3].to_vec(), b"garbage".to_vec(), trailing, other_tokenizer] {
+            fs::write(&cache.path, bytes)?;
+            let mut rebuilt = reload(&cache);
+            assert_eq!(rebuilt.len(), 0);
+
+            rebuilt.record("main.rs", &file_path, b"fn main() {}");
+            rebuilt.save()?;
+            assert_eq!(reload(&rebuilt).len(), 1);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Deepest chain of includes followed before giving up
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// A config file and its content, read once
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+/// The `include` key of a config file: one path or a list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IncludePaths {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct Includes {
+    #[serde(default)]
+    include: Option<IncludePaths>,
+}
+
+/// The config file at `path` and every file it pulls in with a top-level `include`,
+/// relative to the including file:
+///
+/// ```toml
+/// include = ["../shared/gthr.toml", "recipes.toml"]
+/// ```
+///
+/// Files come in the order they apply, each after the files it includes, so it overrides
+/// them. A file included twice (two files sharing a base) applies once, where it's first
+/// included. A cycle, a chain deeper than 8 or a missing file is an error naming the chain.
+pub fn resolve(path: &Path) -> Result<Vec<ConfigFile>> {
+    let mut files = Vec::new();
+    resolve_into(path, &mut Vec::new(), &mut HashSet::new(), &mut files)?;
+    Ok(files)
+}
+
+fn resolve_into(
+    path: &Path,
+    chain: &mut Vec<(PathBuf, PathBuf)>, // (canonical path, path as included) of the files leading here
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<ConfigFile>,
+) -> Result<()> {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if chain.iter().any(|(seen, _)| *seen == canonical) {
+        return Err(anyhow!("include cycle: {}", describe_chain(chain, path)));
+    }
+    if chain.len() >= MAX_INCLUDE_DEPTH {
+        return Err(anyhow!("includes nested more than {} deep: {}", MAX_INCLUDE_DEPTH, describe_chain(chain, path)));
+    }
+    if !visited.insert(canonical.clone()) {
+        return Ok(());
+    }
+
+    let content = std::fs::read_to_string(path).with_context(|| match chain.is_empty() {
+        true => format!("Failed to read {}", path.display()),
+        false => format!("Failed to read {} (included by {})", path.display(), describe_chain(chain, path)),
+    })?;
+    let includes: Includes = toml::from_str(&content).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    let includes = match includes.include {
+        None => Vec::new(),
+        Some(IncludePaths::One(include)) => vec![include],
+        Some(IncludePaths::Many(includes)) => includes,
+    };
+
+    chain.push((canonical, path.to_path_buf()));
+    let base = path.parent().unwrap_or(Path::new(""));
+    for include in includes {
+        resolve_into(&base.join(include), chain, visited, files)?;
+    }
+    chain.pop();
+    files.push(ConfigFile {
+        path: path.to_path_buf(),
+        content,
+    });
+    Ok(())
+}
+
+/// The files' keys as one table, each file's over those of the files before it. Tables
+/// are merged key by key, anything else is replaced whole, so an including file can set a
+/// key back to its default. `include` itself is left out.
+pub fn merge(files: &[ConfigFile]) -> Result<toml::Table> {
+    let mut merged = toml::Table::new();
+    for file in files {
+        let mut table: toml::Table = toml::from_str(&file.content).map_err(|e| anyhow!("{}: {}", file.path.display(), e))?;
+        table.remove("include");
+        merge_into(&mut merged, table);
+    }
+    Ok(merged)
+}
+
+fn merge_into(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge_into(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// `a.toml → b.toml → a.toml`
+fn describe_chain(chain: &[(PathBuf, PathBuf)], last: &Path) -> String {
+    chain
+        .iter()
+        .map(|(_, path)| path.as_path())
+        .chain([last])
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" → ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Write each `(name, content)` into a fresh directory
+    fn config_dir(files: &[(&str, &str)]) -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        for (name, content) in files {
+            let path = temp_dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        temp_dir
+    }
+
+    /// The error resolving `name`, with the directory left out of paths
+    fn error(temp_dir: &TempDir, name: &str) -> String {
+        let error = resolve(&temp_dir.path().join(name)).unwrap_err();
+        format!("{:#}", error).replace(&format!("{}/", temp_dir.path().display()), "")
+    }
+
+    fn names(temp_dir: &TempDir, files: &[ConfigFile]) -> Vec<String> {
+        files
+            .iter()
+            .map(|file| file.path.strip_prefix(temp_dir.path()).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_cycles() {
+        let temp_dir = config_dir(&[
+            ("self.toml", "include = \"self.toml\"\n"),
+            ("a.toml", "include = \"b.toml\"\n"),
+            ("b.toml", "include = [\"c.toml\", \"a.toml\"]\n"),
+            ("c.toml", "max_file_size = 1\n"),
+        ]);
+        assert_eq!(error(&temp_dir, "self.toml"), "include cycle: self.toml → self.toml");
+        assert_eq!(error(&temp_dir, "a.toml"), "include cycle: a.toml → b.toml → a.toml");
+        // The same file under another name is still the same file
+        let temp_dir = config_dir(&[("a.toml", "include = \"shared/../a.toml\"\n"), ("shared/.keep", "")]);
+        assert_eq!(error(&temp_dir, "a.toml"), "include cycle: a.toml → shared/../a.toml");
+    }
+
+    #[test]
+    fn test_diamond_includes() -> Result<()> {
+        let temp_dir = config_dir(&[
+            ("project.toml", "include = [\"left.toml\", \"right.toml\"]\n"),
+            ("left.toml", "include = \"shared/base.toml\"\n"),
+            ("right.toml", "include = \"shared/base.toml\"\n"),
+            ("shared/base.toml", "max_file_size = 1\n"),
+        ]);
+        let files = resolve(&temp_dir.path().join("project.toml"))?;
+        // The shared base applies once, before either file that includes it
+        assert_eq!(names(&temp_dir, &files), ["shared/base.toml", "left.toml", "right.toml", "project.toml"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_files() {
+        let temp_dir = config_dir(&[
+            ("a.toml", "include = \"b.toml\"\n"),
+            ("b.toml", "include = [\"c.toml\", \"gone.toml\"]\n"),
+            ("c.toml", ""),
+            ("broken.toml", "include = \"c.toml\"\n[recipes\n"),
+        ]);
+        assert!(error(&temp_dir, "gone.toml").starts_with("Failed to read gone.toml: "));
+        let error_at_depth = error(&temp_dir, "a.toml");
+        assert!(error_at_depth.starts_with("Failed to read gone.toml (included by a.toml → b.toml → gone.toml): "), "{}", error_at_depth);
+        assert!(error(&temp_dir, "broken.toml").starts_with("broken.toml: TOML parse error at line 2"));
+    }
+
+    #[test]
+    fn test_merge() -> Result<()> {
+        let temp_dir = config_dir(&[
+            ("base.toml", "show_hidden = true
+ignores = [\"a\", \"b\"]\n[slots.1]\nformat = \"xml\"\npatch = true\n"),
+            ("project.toml", "include = \"base.toml\"\nshow_hidden = false\nignores = [\"c\"]\n[slots.1]\nformat = \"json\"\n"),
+        ]);
+        let merged = merge(&resolve(&temp_dir.path().join("project.toml"))?)?;
+        let expected: toml::Table =
+            toml::from_str("show_hidden = false
+ignores = [\"c\"]
+[slots.1]
+format = \"json\"
+patch = true
+")?;
+        assert_eq!(merged, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_depth_cap() {
+        let files: Vec<(String, String)> =
+            (0..12).map(|level| (format!("{}.toml", level), format!("include = \"{}.toml\"\n", level + 1))).collect();
+        let files: Vec<(&str, &str)> = files.iter().map(|(name, content)| (name.as_str(), content.as_str())).collect();
+        let temp_dir = config_dir(&files);
+        let error = error(&temp_dir, "0.toml");
+        assert!(error.starts_with("includes nested more than 8 deep: 0.toml → 1.toml → "), "{}", error);
+        assert!(error.ends_with("7.toml → 8.toml"), "{}", error);
+    }
+}
@@ -1,2 +1,13 @@
+pub mod cache;
+pub mod include;
+pub mod paths;
+pub mod profile;
+pub mod recipes;
+pub mod rules;
+pub mod retention;
+pub mod selection_set;
+pub mod session;
 pub mod settings;
+pub mod slots;
+pub mod startup;
 
@@ -0,0 +1,259 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Environment variable naming the directory the global config is read from
+pub const CONFIG_DIR_ENV: &str = "GTHR_CONFIG_DIR";
+/// The global config's name inside the config directory
+pub const CONFIG_FILE: &str = "config.toml";
+
+const APP_DIR: &str = "gthr";
+
+/// Where gthr keeps what lives outside projects. Everything that reads or writes these
+/// goes through `paths()`, so one set of rules decides where they are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Paths {
+    pub config_file: PathBuf, // Global config
+    pub state_dir: PathBuf,   // Sessions, the last version seen, when cleanup last ran
+    pub cache_dir: PathBuf,   // Line and token counts of exported files
+    config_overridden: bool,  // Given with `--config` or `GTHR_CONFIG_DIR`
+}
+
+/// The platform's base directories, before `gthr` is appended. On Linux these already
+/// follow XDG; elsewhere they're the native locations (`~/Library/Application Support`,
+/// `%APPDATA%`, ...), which the XDG variables still take precedence over.
+#[derive(Debug, Clone, Default)]
+pub struct PlatformDirs {
+    pub home: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub state: Option<PathBuf>, // Only Linux has one
+    pub data_local: Option<PathBuf>,
+    pub cache: Option<PathBuf>,
+}
+
+impl PlatformDirs {
+    pub fn current() -> Self {
+        Self {
+            home: dirs::home_dir(),
+            config: dirs::config_dir(),
+            state: dirs::state_dir(),
+            data_local: dirs::data_local_dir(),
+            cache: dirs::cache_dir(),
+        }
+    }
+}
+
+impl Paths {
+    /// Resolve every location, first match winning:
+    ///
+    /// - Config file: `--config`, `$GTHR_CONFIG_DIR/config.toml`,
+    ///   `$XDG_CONFIG_HOME/gthr/config.toml`, the platform config directory, `~/.config`
+    /// - State: `$XDG_STATE_HOME/gthr`, the platform state or local data directory, `~/.local/state`
+    /// - Cache: `$XDG_CACHE_HOME/gthr`, the platform cache directory, `~/.cache`
+    ///
+    /// XDG variables that are empty or relative are ignored, as the spec asks.
+    pub fn resolve(
+        config_override: Option<&Path>,
+        env: impl Fn(&str) -> Option<OsString>,
+        platform: &PlatformDirs,
+    ) -> Self {
+        let non_empty = |name: &str| env(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+        let xdg = |name: &str| non_empty(name).filter(|path| path.is_absolute()).map(|path| path.join(APP_DIR));
+        let home = |relative: &str| platform.home.as_ref().map(|home| home.join(relative).join(APP_DIR));
+        let fallback = || PathBuf::from(".").join(APP_DIR);
+
+        let config_dir_override = non_empty(CONFIG_DIR_ENV);
+        let config_file = config_override
+            .map(Path::to_path_buf)
+            .or_else(|| config_dir_override.as_ref().map(|dir| dir.join(CONFIG_FILE)))
+            .unwrap_or_else(|| {
+                xdg("XDG_CONFIG_HOME")
+                    .or_else(|| platform.config.as_ref().map(|dir| dir.join(APP_DIR)))
+                    .or_else(|| home(".config"))
+                    .unwrap_or_else(fallback)
+                    .join(CONFIG_FILE)
+            });
+        let state_dir = xdg("XDG_STATE_HOME")
+            .or_else(|| platform.state.as_ref().or(platform.data_local.as_ref()).map(|dir| dir.join(APP_DIR)))
+            .or_else(|| home(".local/state"))
+            .unwrap_or_else(fallback);
+        let cache_dir = xdg("XDG_CACHE_HOME")
+            .or_else(|| platform.cache.as_ref().map(|dir| dir.join(APP_DIR)))
+            .or_else(|| home(".cache"))
+            .unwrap_or_else(fallback);
+
+        Self {
+            config_file,
+            state_dir,
+            cache_dir,
+            config_overridden: config_override.is_some() || config_dir_override.is_some(),
+        }
+    }
+
+    pub fn sessions_dir(&self) -> PathBuf {
+        self.state_dir.join("sessions")
+    }
+
+    /// Where earlier versions kept each file, paired with where it goes now
+    fn legacy_locations(&self, platform: &PlatformDirs) -> Vec<(PathBuf, PathBuf)> {
+        let mut moves = Vec::new();
+        // An explicitly chosen config is used as is, never filled from elsewhere
+        if !self.config_overridden
+            && let Some(config) = platform.config.as_ref().or(platform.home.as_ref())
+        {
+            let legacy = if platform.config.is_some() { config.join(".gthr.toml") } else { config.join(".config/.gthr.toml") };
+            moves.push((legacy, self.config_file.clone()));
+        }
+        if let Some(config) = &platform.config {
+            moves.push((config.join(APP_DIR).join("last-version"), self.state_dir.join("last-version")));
+        }
+        if let Some(data_local) = &platform.data_local {
+            let legacy_dir = data_local.join(APP_DIR);
+            moves.push((legacy_dir.join("sessions"), self.sessions_dir()));
+            moves.push((legacy_dir.join("last-gc"), self.state_dir.join("last-gc")));
+            moves.push((legacy_dir.join("cache"), self.cache_dir.clone()));
+        }
+        moves
+    }
+
+    /// Move files left at legacy locations to the current ones, returning a notice per
+    /// move (or failed move). Nothing is overwritten: where both exist the current one
+    /// wins and the legacy one is left alone. Once moved there's nothing left to find,
+    /// so each notice shows once.
+    pub fn migrate_legacy(&self, platform: &PlatformDirs) -> Vec<String> {
+        let mut notices = Vec::new();
+        for (from, to) in self.legacy_locations(platform) {
+            if from == to || !from.exists() || to.exists() {
+                continue;
+            }
+            let moved = to.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::rename(&from, &to));
+            notices.push(match moved {
+                Ok(()) => format!("Moved {} to {}", from.display(), to.display()),
+                Err(e) => format!("Couldn't move {} to {}: {}", from.display(), to.display(), e),
+            });
+        }
+        // The old data directory, if moving emptied it
+        if let Some(data_local) = &platform.data_local {
+            let _ = fs::remove_dir(data_local.join(APP_DIR));
+        }
+        notices
+    }
+}
+
+static PATHS: OnceLock<Paths> = OnceLock::new();
+
+/// Resolve the locations for this run from the environment and `--config`. Call once,
+/// before anything reads them; otherwise they're resolved without an override.
+pub fn init(config_override: Option<&Path>) -> &'static Paths {
+    PATHS.get_or_init(|| Paths::resolve(config_override, |name| std::env::var_os(name), &PlatformDirs::current()))
+}
+
+pub fn paths() -> &'static Paths {
+    init(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn platform(base: &Path) -> PlatformDirs {
+        PlatformDirs {
+            home: Some(base.join("home")),
+            config: Some(base.join("home/.config")),
+            state: Some(base.join("home/.local/state")),
+            data_local: Some(base.join("home/.local/share")),
+            cache: Some(base.join("home/.cache")),
+        }
+    }
+
+    fn resolve(config_override: Option<&str>, env: &[(&str, &str)], platform: &PlatformDirs) -> Paths {
+        let env: HashMap<String, OsString> = env.iter().map(|(name, value)| (name.to_string(), OsString::from(value))).collect();
+        Paths::resolve(config_override.map(Path::new), |name| env.get(name).cloned(), platform)
+    }
+
+    #[test]
+    fn test_resolve_precedence() {
+        let base = Path::new("/base");
+        let platform = platform(base);
+
+        // Platform directories by default
+        let paths = resolve(None, &[], &platform);
+        assert_eq!(paths.config_file, base.join("home/.config/gthr/config.toml"));
+        assert_eq!(paths.state_dir, base.join("home/.local/state/gthr"));
+        assert_eq!(paths.cache_dir, base.join("home/.cache/gthr"));
+        assert_eq!(paths.sessions_dir(), base.join("home/.local/state/gthr/sessions"));
+
+        // XDG variables win over the platform, on every platform
+        let xdg = [("XDG_CONFIG_HOME", "/xdg/config"), ("XDG_STATE_HOME", "/xdg/state"), ("XDG_CACHE_HOME", "/xdg/cache")];
+        let paths = resolve(None, &xdg, &platform);
+        assert_eq!(paths.config_file, Path::new("/xdg/config/gthr/config.toml"));
+        assert_eq!(paths.state_dir, Path::new("/xdg/state/gthr"));
+        assert_eq!(paths.cache_dir, Path::new("/xdg/cache/gthr"));
+
+        // Empty and relative values don't count
+        let paths = resolve(None, &[("XDG_CONFIG_HOME", ""), ("XDG_CACHE_HOME", "relative/cache")], &platform);
+        assert_eq!(paths.config_file, base.join("home/.config/gthr/config.toml"));
+        assert_eq!(paths.cache_dir, base.join("home/.cache/gthr"));
+
+        // GTHR_CONFIG_DIR beats XDG, and --config beats both; neither moves state or cache
+        let with_dir = [xdg.as_slice(), &[(CONFIG_DIR_ENV, "/custom")]].concat();
+        let paths = resolve(None, &with_dir, &platform);
+        assert_eq!(paths.config_file, Path::new("/custom/config.toml"));
+        assert_eq!(paths.state_dir, Path::new("/xdg/state/gthr"));
+        let paths = resolve(Some("test.toml"), &with_dir, &platform);
+        assert_eq!(paths.config_file, Path::new("test.toml"));
+        assert_eq!(paths.cache_dir, Path::new("/xdg/cache/gthr"));
+
+        // Without a state directory (macOS, Windows) state goes with local data
+        let paths = resolve(None, &[], &PlatformDirs { state: None, ..platform.clone() });
+        assert_eq!(paths.state_dir, base.join("home/.local/share/gthr"));
+        // With nothing but a home directory, the XDG defaults
+        let home_only = PlatformDirs { home: Some(base.join("home")), ..Default::default() };
+        let paths = resolve(None, &[], &home_only);
+        assert_eq!(paths.config_file, base.join("home/.config/gthr/config.toml"));
+        assert_eq!(paths.state_dir, base.join("home/.local/state/gthr"));
+        assert_eq!(paths.cache_dir, base.join("home/.cache/gthr"));
+    }
+
+    #[test]
+    fn test_migrate_legacy() {
+        let temp_dir = TempDir::new().unwrap();
+        let platform = platform(temp_dir.path());
+        let home = temp_dir.path().join("home");
+        for (path, content) in [
+            (".config/.gthr.toml", "show_hidden = true\n"),
+            (".config/gthr/last-version", "0.1.0\n"),
+            (".local/share/gthr/sessions/project-0123.toml", "root = \"/project\"\n"),
+            (".local/share/gthr/cache/project-0123.bin", "counts"),
+            (".local/share/gthr/last-gc", ""),
+        ] {
+            fs::create_dir_all(home.join(path).parent().unwrap()).unwrap();
+            fs::write(home.join(path), content).unwrap();
+        }
+
+        let paths = resolve(None, &[], &platform);
+        let notices = paths.migrate_legacy(&platform);
+        assert_eq!(notices.len(), 5, "{:?}", notices);
+        assert!(notices.iter().all(|notice| notice.starts_with("Moved ")));
+        assert_eq!(fs::read_to_string(&paths.config_file).unwrap(), "show_hidden = true\n");
+        assert!(paths.sessions_dir().join("project-0123.toml").exists());
+        assert!(paths.cache_dir.join("project-0123.bin").exists());
+        assert!(paths.state_dir.join("last-version").exists() && paths.state_dir.join("last-gc").exists());
+        assert!(!home.join(".config/.gthr.toml").exists());
+        assert!(!home.join(".local/share/gthr").exists(), "the emptied legacy directory is removed");
+
+        // Only once; and where both exist, the current file is kept
+        assert!(paths.migrate_legacy(&platform).is_empty());
+        fs::write(home.join(".config/.gthr.toml"), "old").unwrap();
+        assert!(paths.migrate_legacy(&platform).is_empty());
+        assert_eq!(fs::read_to_string(&paths.config_file).unwrap(), "show_hidden = true\n");
+
+        // An explicit config is never replaced by the legacy one
+        let overridden = resolve(Some(temp_dir.path().join("mine.toml").to_str().unwrap()), &[], &platform);
+        assert!(overridden.migrate_legacy(&platform).is_empty());
+        assert!(!temp_dir.path().join("mine.toml").exists());
+    }
+}
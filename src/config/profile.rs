@@ -0,0 +1,379 @@
+use crate::config::cache::ContentCache;
+use crate::config::selection_set::{self, FileStamp, Rename};
+use crate::config::session::fnv1a_hash;
+use crate::constants::PROJECT_DIR;
+use crate::directory::state::SelectionState;
+use crate::directory::tree::{DirectoryTree, FileNode};
+use crate::output::exporter::export_path;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The profile loaded and saved when none is named
+const DEFAULT_PROFILE_FILE: &str = "state.toml";
+
+/// A saved selection, kept in the project so the next run starts where this one left off.
+///
+/// ```toml
+/// locked = ["Cargo.toml"]
+/// sticky = ["src"]
+/// order = ["README.md"]
+///
+/// [states]
+/// src = "partial"
+/// "src/main.rs" = "included"
+/// "src/generated.rs" = "excluded"
+///
+/// [reasons]
+/// "src/generated.rs" = "built by build.rs"
+/// ```
+///
+/// Every node whose state differs from the default selection (the scan with the shared
+/// rules applied) is listed by its path relative to the root, with `/` separators. Anything
+/// not listed, files added since the save too, keeps its default state. Locked nodes,
+/// directories included as a whole, the manual export order and exclusion reasons are
+/// kept alongside.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(skip)]
+    pub path: PathBuf,
+    #[serde(skip)]
+    pub on_disk: Option<FileStamp>, // The file as last loaded or saved
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub locked: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sticky: Vec<String>, // Directories whose files found later are included too
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub order: Vec<String>, // Files placed by hand, in export order
+    #[serde(default)]
+    pub states: BTreeMap<String, SelectionState>, // Relative path -> state, where it isn't the default
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reasons: BTreeMap<String, String>, // Relative path -> why it's excluded
+}
+
+/// Outcome of applying a profile to a tree
+#[derive(Debug, Clone, Default)]
+pub struct ProfileSummary {
+    pub applied: usize,
+    pub missing: Vec<String>, // Saved paths that aren't in the tree, without those under a missing directory
+}
+
+impl Profile {
+    /// `.gthr/profiles/<name>.toml` in the project, or `.gthr/state.toml` without a name
+    pub fn get_profile_path(project_root: &Path, name: Option<&str>) -> Result<PathBuf> {
+        let dir = project_root.join(PROJECT_DIR);
+        let Some(name) = name else {
+            return Ok(dir.join(DEFAULT_PROFILE_FILE));
+        };
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(anyhow!("profile names can't be empty, start with `.` or contain slashes, not `{}`", name));
+        }
+        Ok(dir.join("profiles").join(format!("{}.toml", name)))
+    }
+
+    /// Whether the project has a saved selection: `.gthr/state.toml` or any named profile
+    pub fn any_saved(project_root: &Path) -> bool {
+        let dir = project_root.join(PROJECT_DIR);
+        dir.join(DEFAULT_PROFILE_FILE).exists()
+            || std::fs::read_dir(dir.join("profiles")).is_ok_and(|entries| {
+                entries.flatten().any(|entry| entry.path().extension().is_some_and(|ext| ext == "toml"))
+            })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read profile {}", path.display()))?;
+        let mut profile: Profile = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse profile {}", path.display()))?;
+        profile.path = path.to_path_buf();
+        profile.on_disk = Some(FileStamp {
+            modified: std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok(),
+            hash: fnv1a_hash(content.as_bytes()),
+        });
+        Ok(profile)
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write profile {}", self.path.display()))?;
+        self.on_disk = FileStamp::of(&self.path);
+        Ok(())
+    }
+
+    /// Save without asking: if someone else saved the profile since it was loaded, theirs is
+    /// kept and this one goes next to it instead. Returns the path theirs is at then.
+    pub fn save_keeping_theirs(&mut self) -> Result<Option<PathBuf>> {
+        let theirs = self.changed_on_disk().map(|_| self.path.clone());
+        if theirs.is_some() {
+            self.path = self.free_path();
+            self.on_disk = None;
+        }
+        self.save()?;
+        Ok(theirs)
+    }
+
+    /// The profile as someone else saved it since it was loaded or last saved here, if they did
+    pub fn changed_on_disk(&self) -> Option<Profile> {
+        if !FileStamp::changed_since(self.on_disk, &self.path) {
+            return None;
+        }
+        Self::load(&self.path).ok()
+    }
+
+    /// A path next to this one that no profile uses yet: `state-2.toml`, `api-3.toml`, ...
+    pub fn free_path(&self) -> PathBuf {
+        selection_set::free_path(&self.path)
+    }
+
+    /// The tree's selection, to be saved where this profile is. Reasons are kept for paths
+    /// that are still excluded.
+    pub fn with_selection(&self, tree: &DirectoryTree) -> Self {
+        let root_path = tree.scan_root_path();
+        let listed = |predicate: fn(&FileNode) -> bool| {
+            tree.nodes
+                .iter()
+                .enumerate()
+                .filter(|(index, node)| *index != tree.scan_root_index && predicate(node))
+                .map(|(_, node)| export_path(&node.path, root_path))
+                .collect()
+        };
+        let states = tree
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(index, node)| *index != tree.scan_root_index && node.state != tree.default_state(*index))
+            .map(|(_, node)| (export_path(&node.path, root_path), node.state))
+            .collect();
+        Self {
+            path: self.path.clone(),
+            on_disk: self.on_disk,
+            locked: listed(|node| node.locked),
+            sticky: listed(|node| node.sticky),
+            order: tree.manual_order.iter().map(|&index| export_path(&tree.nodes[index].path, root_path)).collect(),
+            states,
+            reasons: self.exclusion_reasons(tree).into_iter().collect(),
+        }
+    }
+
+    /// Saved reasons of the nodes that are still excluded
+    pub fn exclusion_reasons(&self, tree: &DirectoryTree) -> Vec<(String, String)> {
+        let root_path = tree.scan_root_path();
+        self.reasons
+            .iter()
+            .filter(|(relative_path, _)| {
+                tree.path_to_index
+                    .get(&root_path.join(relative_path))
+                    .is_some_and(|&index| tree.nodes[index].state == SelectionState::Excluded)
+            })
+            .map(|(relative_path, reason)| (relative_path.clone(), reason.clone()))
+            .collect()
+    }
+
+    /// Make the saved selection the tree's: everything goes back to its default state,
+    /// then listed files get their saved state, and directories are worked out again from
+    /// the files they hold now, so one that lost or gained files since the save can come
+    /// back partial. Locks, whole-directory includes and the manual order come back too.
+    /// Nodes locked before keep their state.
+    pub fn apply(&self, tree: &mut DirectoryTree) -> ProfileSummary {
+        let root_path = tree.scan_root_path().to_path_buf();
+        let mut summary = ProfileSummary::default();
+        tree.restore_default_selection();
+        for (relative_path, &state) in &self.states {
+            let Some(&index) = tree.path_to_index.get(&root_path.join(relative_path)) else {
+                // Sorted paths put a directory before what was in it
+                let under_missing = summary
+                    .missing
+                    .iter()
+                    .any(|missing| relative_path.strip_prefix(missing.as_str()).is_some_and(|rest| rest.starts_with('/')));
+                if !under_missing {
+                    summary.missing.push(relative_path.clone());
+                }
+                continue;
+            };
+            let node = &mut tree.nodes[index];
+            if node.locked || !node.children.is_empty() || state == SelectionState::Partial {
+                continue;
+            }
+            node.state = state;
+            if !node.is_directory {
+                summary.applied += 1;
+            }
+        }
+        tree.recompute_directories();
+
+        for relative_path in &self.locked {
+            if let Some(&index) = tree.path_to_index.get(&root_path.join(relative_path)) {
+                tree.nodes[index].locked = true;
+            }
+        }
+        for node in tree.nodes.iter_mut().filter(|node| node.is_directory) {
+            node.sticky = node.state == SelectionState::Included
+                && self.sticky.contains(&export_path(&node.path, &root_path));
+        }
+        if !self.order.is_empty() {
+            tree.restore_manual_order(&self.order);
+        }
+        summary
+    }
+
+    /// Guess where missing files went, among the files the profile doesn't list
+    pub fn detect_renames(&self, missing: &[String], tree: &DirectoryTree, content_cache: &ContentCache) -> Vec<Rename> {
+        selection_set::detect_renames(missing, |relative_path| self.states.contains_key(relative_path), tree, content_cache)
+    }
+
+    /// Move what's saved for renamed files to their new paths
+    pub fn remap(&mut self, renames: &[Rename]) {
+        for rename in renames {
+            if let Some(state) = self.states.remove(&rename.from) {
+                self.states.insert(rename.to.clone(), state);
+            }
+            if let Some(reason) = self.reasons.remove(&rename.from) {
+                self.reasons.insert(rename.to.clone(), reason);
+            }
+            for relative_path in self.locked.iter_mut().chain(&mut self.order) {
+                if *relative_path == rename.from {
+                    relative_path.clone_from(&rename.to);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tree of `paths` under `/project`, directories added as needed, everything excluded
+    fn tree(paths: &[&str]) -> DirectoryTree {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        for path in paths {
+            let mut parent = root.clone();
+            for directory in Path::new(path).parent().into_iter().flat_map(Path::iter) {
+                tree.add_node(parent.join(directory), true, &parent);
+                parent.push(directory);
+            }
+            let index = tree.add_node(root.join(path), false, &parent).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        tree
+    }
+
+    fn state(tree: &DirectoryTree, path: &str) -> SelectionState {
+        tree.nodes[tree.path_to_index[&PathBuf::from("/project").join(path)]].state
+    }
+
+    fn index(tree: &DirectoryTree, path: &str) -> usize {
+        tree.path_to_index[&PathBuf::from("/project").join(path)]
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        // The default selection includes everything; some of it is excluded by hand
+        let mut saved = tree(&["src/a.rs", "src/b.rs", "src/c.rs", "docs/guide.md", "old.rs", "lib/x.rs"]);
+        saved.set_state(saved.root_index, SelectionState::Included);
+        saved.record_default_selection();
+        for path in ["src/b.rs", "docs/guide.md", "old.rs"] {
+            saved.set_state(index(&saved, path), SelectionState::Excluded);
+        }
+        saved.set_state(index(&saved, "lib"), SelectionState::Included);
+        let locked = index(&saved, "src/a.rs");
+        saved.nodes[locked].locked = true;
+        saved.manual_order = vec![index(&saved, "src/c.rs")];
+
+        let path = Profile::get_profile_path(temp_dir.path(), None)?;
+        let mut reasons = BTreeMap::new();
+        reasons.insert("old.rs".to_string(), "superseded".to_string());
+        reasons.insert("src/a.rs".to_string(), "included again since".to_string());
+        let mut profile = Profile { path: path.clone(), reasons, ..Profile::default() }.with_selection(&saved);
+        profile.save()?;
+        assert!(std::fs::read_to_string(&path)?.contains("\"src/b.rs\" = \"excluded\""));
+        let loaded = Profile::load(&path)?;
+        assert_eq!(loaded, profile);
+        // What differs from the default: the excluded files and the directories holding them
+        assert_eq!(loaded.states.keys().collect::<Vec<_>>(), ["docs", "docs/guide.md", "old.rs", "src", "src/b.rs"]);
+        assert_eq!((loaded.locked.as_slice(), loaded.sticky.as_slice()), (["src/a.rs".to_string()].as_slice(), ["lib".to_string()].as_slice()));
+        assert_eq!(loaded.order, ["src/c.rs"]);
+        assert_eq!(loaded.reasons.keys().collect::<Vec<_>>(), ["old.rs"]);
+
+        // Next run: old.rs was renamed, src/b.rs deleted and src/d.rs and lib/y.rs added
+        let mut current = tree(&["src/a.rs", "src/c.rs", "src/d.rs", "docs/guide.md", "new.rs", "lib/x.rs", "lib/y.rs"]);
+        current.set_state(current.root_index, SelectionState::Included);
+        current.record_default_selection();
+        let lib = index(&current, "lib");
+        current.nodes[lib].sticky = false;
+        let summary = loaded.apply(&mut current);
+        assert_eq!(summary.applied, 1);
+        assert_eq!(summary.missing, ["old.rs", "src/b.rs"]);
+        assert_eq!(state(&current, "docs"), SelectionState::Excluded);
+        // New files keep their default state, renamed or not, so src is wholly included again
+        assert_eq!(state(&current, "src/d.rs"), SelectionState::Included);
+        assert_eq!(state(&current, "new.rs"), SelectionState::Included);
+        assert_eq!(state(&current, "src"), SelectionState::Included);
+        assert_eq!(current.nodes[current.root_index].state, SelectionState::Partial);
+        assert!(current.nodes[index(&current, "src/a.rs")].locked);
+        assert!(current.nodes[index(&current, "lib")].sticky);
+        assert_eq!(current.manual_order, [index(&current, "src/c.rs")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_conflict() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let path = Profile::get_profile_path(temp_dir.path(), Some("api"))?;
+        let saved = tree(&["a.rs", "b.rs"]);
+        Profile { path: path.clone(), ..Profile::default() }.with_selection(&saved).save()?;
+
+        // Two instances load it, the first saves, then saves again: no conflict for its own save
+        let mut first = Profile::load(&path)?;
+        let mut second = Profile::load(&path)?;
+        first.states.insert("a.rs".to_string(), SelectionState::Included);
+        first.save()?;
+        assert!(first.changed_on_disk().is_none());
+        assert_eq!(first.save_keeping_theirs()?, None);
+
+        // The second would destroy that, so it's saved next to it
+        second.states.insert("b.rs".to_string(), SelectionState::Included);
+        assert_eq!(second.changed_on_disk().map(|theirs| theirs.states), Some(first.states.clone()));
+        assert_eq!(second.save_keeping_theirs()?, Some(path.clone()));
+        assert_eq!(second.path, path.with_file_name("api-2.toml"));
+        assert_eq!(Profile::load(&path)?.states, first.states);
+        assert_eq!(Profile::load(&second.path)?.states, second.states);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_to_shrunken_directory() {
+        let mut saved = tree(&["src/a.rs", "src/b.rs", "src/nested/c.rs", "README.md"]);
+        saved.set_state(saved.path_to_index[&PathBuf::from("/project/src")], SelectionState::Included);
+        let profile = Profile::default().with_selection(&saved);
+
+        // The nested directory is gone with everything in it: one warning for it, and
+        // what's left of src is still wholly included
+        let mut current = tree(&["src/a.rs", "src/b.rs", "README.md"]);
+        let locked = current.path_to_index[&PathBuf::from("/project/README.md")];
+        current.set_state(locked, SelectionState::Included);
+        current.nodes[locked].locked = true;
+        let summary = profile.apply(&mut current);
+        assert_eq!(summary.missing, ["src/nested"]);
+        assert_eq!(state(&current, "src"), SelectionState::Included);
+        // Locked nodes keep their state either way
+        assert_eq!(state(&current, "README.md"), SelectionState::Included);
+        assert_eq!(current.nodes[current.root_index].state, SelectionState::Included);
+    }
+
+    #[test]
+    fn test_profile_path() {
+        let root = Path::new("/project");
+        assert_eq!(Profile::get_profile_path(root, None).unwrap(), root.join(".gthr/state.toml"));
+        assert_eq!(Profile::get_profile_path(root, Some("api")).unwrap(), root.join(".gthr/profiles/api.toml"));
+        for name in ["", ".hidden", "../escape", r"a\b"] {
+            assert!(Profile::get_profile_path(root, Some(name)).is_err(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use super::include;
+use super::profile::Profile;
+use super::settings::Settings;
+use super::startup::StartupStep;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Longest chain of recipes extending each other
+const MAX_EXTENDS_DEPTH: usize = 8;
+
+/// Where a recipe's export goes
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecipeOutput {
+    Clipboard,
+    File(PathBuf), // Relative paths are relative to the current directory, as with `-o`
+}
+
+/// A named export bundle from the `[recipes.<name>]` tables of a config file:
+///
+/// ```toml
+/// [recipes.review]
+/// steps = ["exclude-all", "changed-only", "include-matches"]
+/// format = "markdown"
+/// output = "clipboard"
+/// ```
+///
+/// The recipes it `extends` are applied first, each once, then its `profile` (a saved
+/// selection, see `Profile`), its rules file (if any) and its steps, which are the same as
+/// the `startup` setting's; `format`, `patch` and `tail_lines` override the export options.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Recipe {
+    #[serde(skip)]
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub extends: Vec<String>, // Recipes applied first, by name
+    #[serde(skip)]
+    pub bases: Vec<Recipe>, // What `extends` comes to, every recipe once, in the order they apply
+    #[serde(default)]
+    pub profile: Option<String>, // Saved selection to start from, by name
+    #[serde(default)]
+    pub rules: Option<PathBuf>, // Relative to the root, instead of the project's rules file
+    #[serde(default)]
+    pub steps: Vec<String>,
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub output: Option<String>, // "clipboard" or a file path
+    #[serde(default)]
+    pub patch: bool,
+    #[serde(default)]
+    pub tail_lines: Option<usize>, // Only the last lines of each file
+}
+
+impl Recipe {
+    pub fn output(&self) -> RecipeOutput {
+        match self.output.as_deref() {
+            None | Some("clipboard") => RecipeOutput::Clipboard,
+            Some(path) => RecipeOutput::File(PathBuf::from(path)),
+        }
+    }
+
+    /// What the recipe does, for the picker: its description or a summary of its options
+    pub fn summary(&self) -> String {
+        if let Some(description) = &self.description {
+            return description.clone();
+        }
+        let mut parts = Vec::new();
+        if !self.extends.is_empty() {
+            parts.push(format!("extends {}", self.extends.join(" ")));
+        }
+        if let Some(profile) = &self.profile {
+            parts.push(format!("profile {}", profile));
+        }
+        if let Some(rules) = &self.rules {
+            parts.push(format!("rules {}", rules.display()));
+        }
+        parts.extend(self.steps.iter().cloned());
+        if self.patch {
+            parts.push("patch".to_string());
+        }
+        if let Some(format) = &self.format {
+            parts.push(format.clone());
+        }
+        if let Some(tail_lines) = self.tail_lines {
+            parts.push(format!("last {} lines", tail_lines));
+        }
+        parts.push(match self.output() {
+            RecipeOutput::Clipboard => "clipboard".to_string(),
+            RecipeOutput::File(path) => path.display().to_string(),
+        });
+        parts.join(", ")
+    }
+
+    /// Check what deserializing can't: steps, the format name, line counts and profile names
+    fn validate(&self, formats: &[&str]) -> Result<()> {
+        if let Some(profile) = &self.profile {
+            Profile::get_profile_path(Path::new(""), Some(profile)).map_err(|e| anyhow!("profile: {:#}", e))?;
+        }
+        for (index, step) in self.steps.iter().enumerate() {
+            StartupStep::parse(step).map_err(|e| anyhow!("steps[{}]: {:#}", index, e))?;
+        }
+        if let Some(format) = &self.format
+            && !formats.contains(&format.as_str())
+        {
+            return Err(anyhow!("format: unknown format `{}` (available: {})", format, formats.join(", ")));
+        }
+        if self.tail_lines == Some(0) {
+            return Err(anyhow!("tail_lines: must be at least 1"));
+        }
+        if self.output.as_deref().is_some_and(|output| output.trim().is_empty()) {
+            return Err(anyhow!("output: expected \"clipboard\" or a file path"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct RecipesFile {
+    #[serde(default)]
+    recipes: BTreeMap<String, Recipe>,
+}
+
+/// Recipes from the global config and the project's `.gthr.toml`; a project recipe
+/// replaces a global one of the same name
+#[derive(Debug, Clone, Default)]
+pub struct Recipes {
+    recipes: BTreeMap<String, Recipe>,
+}
+
+impl Recipes {
+    /// Load and validate every recipe, failing on the first invalid one with its file,
+    /// recipe and field (or line and column, for TOML errors)
+    pub fn load(project_root: &Path, formats: &[&str]) -> Result<Self> {
+        let mut recipes = Self::default();
+        let project_path = Settings::get_project_config_path(project_root);
+        for path in [Settings::get_global_config_path(), project_path] {
+            if !path.is_file() {
+                continue;
+            }
+            for file in include::resolve(&path)? {
+                recipes.recipes.extend(Self::parse(&file.content, &file.path, formats)?);
+            }
+        }
+        recipes.resolve_extends()?;
+        Ok(recipes)
+    }
+
+    /// Work out each recipe's `bases`, failing on unknown names, cycles and chains deeper
+    /// than 8, with the chain that led there
+    fn resolve_extends(&mut self) -> Result<()> {
+        let mut resolved = BTreeMap::new();
+        for name in self.recipes.keys() {
+            let mut bases = Vec::new();
+            self.resolve_into(name, &mut Vec::new(), &mut HashSet::new(), &mut bases)
+                .map_err(|e| anyhow!("recipe `{}`, extends: {:#}", name, e))?;
+            bases.pop(); // The recipe itself
+            resolved.insert(name.clone(), bases);
+        }
+        for (name, bases) in resolved {
+            if let Some(recipe) = self.recipes.get_mut(&name) {
+                recipe.bases = bases;
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_into<'a>(
+        &'a self,
+        name: &'a str,
+        chain: &mut Vec<&'a str>,
+        visited: &mut HashSet<&'a str>,
+        bases: &mut Vec<Recipe>,
+    ) -> Result<()> {
+        if chain.contains(&name) {
+            return Err(anyhow!("recipe cycle: {} → {}", chain.join(" → "), name));
+        }
+        if chain.len() >= MAX_EXTENDS_DEPTH {
+            return Err(anyhow!("recipes extend each other more than {} deep: {} → {}", MAX_EXTENDS_DEPTH, chain.join(" → "), name));
+        }
+        if !visited.insert(name) {
+            return Ok(());
+        }
+        let recipe = self.get(name)?;
+        chain.push(name);
+        for base in &recipe.extends {
+            self.resolve_into(base, chain, visited, bases)?;
+        }
+        chain.pop();
+        bases.push(Recipe { bases: Vec::new(), ..recipe.clone() });
+        Ok(())
+    }
+
+    fn parse(content: &str, path: &Path, formats: &[&str]) -> Result<BTreeMap<String, Recipe>> {
+        let file: RecipesFile = toml::from_str(content).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        let mut recipes = file.recipes;
+        for (name, recipe) in &mut recipes {
+            recipe
+                .validate(formats)
+                .map_err(|e| anyhow!("{}: recipe `{}`, {:#}", path.display(), name, e))?;
+            recipe.name = name.clone();
+        }
+        Ok(recipes)
+    }
+
+    /// The recipe called `name`, or an error listing the ones there are
+    pub fn get(&self, name: &str) -> Result<&Recipe> {
+        self.recipes.get(name).ok_or_else(|| {
+            if self.recipes.is_empty() {
+                anyhow!("unknown recipe `{}`: no recipes defined, add a [recipes.{}] table to .gthr.toml", name, name)
+            } else {
+                let names: Vec<&str> = self.recipes.keys().map(String::as_str).collect();
+                anyhow!("unknown recipe `{}` (available: {})", name, names.join(", "))
+            }
+        })
+    }
+
+    /// All recipes, by name
+    pub fn all(&self) -> Vec<Recipe> {
+        self.recipes.values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [&str; 2] = ["markdown", "xml"];
+
+    #[test]
+    fn test_parse_recipes() -> Result<()> {
+        let content = r#"
+max_file_size = 1000
+
+[recipes.review]
+steps = ["exclude-all", "changed-only", "include-matches"]
+output = "clipboard"
+
+[recipes.debug]
+description = "Recent logs"
+steps = ["exclude-all", "include logs/**"]
+format = "xml"
+output = "debug.xml"
+tail_lines = 200
+"#;
+        let recipes = Recipes {
+            recipes: Recipes::parse(content, Path::new(".gthr.toml"), &FORMATS)?,
+        };
+        let review = recipes.get("review")?;
+        assert_eq!((review.name.as_str(), review.output()), ("review", RecipeOutput::Clipboard));
+        assert_eq!(review.summary(), "exclude-all, changed-only, include-matches, clipboard");
+
+        let debug = recipes.get("debug")?;
+        assert_eq!(debug.output(), RecipeOutput::File(PathBuf::from("debug.xml")));
+        assert_eq!((debug.tail_lines, debug.summary().as_str()), (Some(200), "Recent logs"));
+
+        let error = recipes.get("onboarding").unwrap_err().to_string();
+        assert_eq!(error, "unknown recipe `onboarding` (available: debug, review)");
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_recipes() {
+        let parse = |content: &str| Recipes::parse(content, Path::new("/project/.gthr.toml"), &FORMATS).unwrap_err().to_string();
+
+        // TOML errors point at the line and column
+        let error = parse("[recipes.review]\nsteps = []\noutptu = \"review.md\"\n");
+        assert!(error.starts_with("/project/.gthr.toml: TOML parse error at line 3, column 1"), "{}", error);
+        assert!(error.contains("unknown field `outptu`"), "{}", error);
+
+        // Everything else names the recipe and the field
+        let error = parse("[recipes.review]\nsteps = [\"exclude-all\", \"open preview\"]\n");
+        assert!(error.starts_with("/project/.gthr.toml: recipe `review`, steps[1]: unknown step `open preview`"), "{}", error);
+        let error = parse("[recipes.review]\nformat = \"html\"\n");
+        assert_eq!(error, "/project/.gthr.toml: recipe `review`, format: unknown format `html` (available: markdown, xml)");
+        assert!(parse("[recipes.logs]\ntail_lines = 0\n").contains("recipe `logs`, tail_lines"));
+        assert!(parse("[recipes.api]\nprofile = \"../api\"\n").contains("recipe `api`, profile: profile names can't"));
+    }
+
+    fn resolve(content: &str) -> Result<Recipes> {
+        let mut recipes = Recipes {
+            recipes: Recipes::parse(content, Path::new(".gthr.toml"), &FORMATS)?,
+        };
+        recipes.resolve_extends()?;
+        Ok(recipes)
+    }
+
+    #[test]
+    fn test_extends() -> Result<()> {
+        // A shared base applies once, before either recipe extending it
+        let recipes = resolve(
+            "[recipes.base]\nprofile = \"api\"\n[recipes.docs]\nextends = [\"base\"]\n\
+             [recipes.tests]\nextends = [\"base\"]\n[recipes.review]\nextends = [\"docs\", \"tests\"]\n",
+        )?;
+        let review = recipes.get("review")?;
+        let bases: Vec<&str> = review.bases.iter().map(|recipe| recipe.name.as_str()).collect();
+        assert_eq!(bases, ["base", "docs", "tests"]);
+        assert!(review.bases.iter().all(|recipe| recipe.bases.is_empty()));
+        assert_eq!(review.summary(), "extends docs tests, clipboard");
+        assert_eq!(recipes.get("base")?.summary(), "profile api, clipboard");
+
+        let error = |content: &str| resolve(content).unwrap_err().to_string();
+        assert_eq!(error("[recipes.a]\nextends = [\"a\"]\n"), "recipe `a`, extends: recipe cycle: a → a");
+        assert_eq!(
+            error("[recipes.a]\nextends = [\"b\"]\n[recipes.b]\nextends = [\"c\", \"a\"]\n[recipes.c]\n"),
+            "recipe `a`, extends: recipe cycle: a → b → a"
+        );
+        assert!(error("[recipes.a]\nextends = [\"gone\"]\n").starts_with("recipe `a`, extends: unknown recipe `gone`"));
+        let deep: String = (0..12).map(|level| format!("[recipes.r{}]\nextends = [\"r{}\"]\n", level, level + 1)).collect();
+        let error = error(&format!("{}[recipes.r12]\n", deep));
+        assert!(error.starts_with("recipe `r0`, extends: recipes extend each other more than 8 deep: r0 → r1 → "), "{}", error);
+        Ok(())
+    }
+}
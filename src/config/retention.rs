@@ -0,0 +1,177 @@
+use super::paths::paths;
+use super::session::Session;
+use crate::output::publish::Publisher;
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How often the retention pass runs on its own at startup
+const AUTOMATIC_INTERVAL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Something the retention pass removed (or would remove, in a dry run), and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct Removal {
+    pub path: PathBuf,
+    pub reason: &'static str,
+}
+
+/// Prunes the data gthr keeps outside projects: sessions of roots that no longer exist
+/// (with their caches), caches unused for `cache_keep_days`, and published exports beyond
+/// the publish limits. Only files gthr wrote are considered; rules files and configs live
+/// in projects and the config directory, which are never looked at.
+#[derive(Debug, Clone)]
+pub struct Retention {
+    sessions_dir: PathBuf,
+    cache_dir: PathBuf,
+    cache_keep_days: u64, // 0 keeps caches forever
+    publisher: Option<Publisher>,
+}
+
+impl Retention {
+    pub fn new(cache_keep_days: u64, publisher: Option<Publisher>) -> Self {
+        Self {
+            sessions_dir: Session::get_sessions_dir(),
+            cache_dir: paths().cache_dir.clone(),
+            cache_keep_days,
+            publisher,
+        }
+    }
+
+    #[cfg(test)]
+    fn in_dirs(sessions_dir: PathBuf, cache_dir: PathBuf, cache_keep_days: u64) -> Self {
+        Self {
+            sessions_dir,
+            cache_dir,
+            cache_keep_days,
+            publisher: None,
+        }
+    }
+
+    /// Run the pass if it hasn't run in the last week, as recorded by a stamp file
+    pub fn run_if_due(&self) -> Result<Option<Vec<Removal>>> {
+        let stamp = self.sessions_dir.with_file_name("last-gc");
+        let due = fs::metadata(&stamp)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_none_or(|elapsed| elapsed >= AUTOMATIC_INTERVAL);
+        if !due {
+            return Ok(None);
+        }
+
+        let removals = self.run(false)?;
+        if let Some(parent) = stamp.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&stamp, "")?;
+        Ok(Some(removals))
+    }
+
+    /// Remove what's past retention, or with `dry_run` only list it
+    pub fn run(&self, dry_run: bool) -> Result<Vec<Removal>> {
+        let mut candidates: Vec<Removal> = Vec::new();
+        for path in files_with_extension(&self.sessions_dir, "toml") {
+            // Unreadable files aren't known to be sessions; leave them be
+            let Some(session) = fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| toml::from_str::<Session>(&content).ok())
+            else {
+                continue;
+            };
+            if session.root.as_os_str().is_empty() || session.root.exists() {
+                continue;
+            }
+            // A root's cache is named like its session
+            let cache_path = path.with_extension("bin").file_name().map(|name| self.cache_dir.join(name));
+            candidates.push(Removal { path, reason: "root no longer exists" });
+            if let Some(cache_path) = cache_path.filter(|cache_path| cache_path.exists()) {
+                candidates.push(Removal { path: cache_path, reason: "root no longer exists" });
+            }
+        }
+
+        if self.cache_keep_days > 0
+            && let Some(cutoff) = SystemTime::now().checked_sub(Duration::from_secs(self.cache_keep_days * 24 * 60 * 60))
+        {
+            for path in files_with_extension(&self.cache_dir, "bin") {
+                let modified = fs::metadata(&path).and_then(|metadata| metadata.modified());
+                if modified.is_ok_and(|modified| modified < cutoff)
+                    && !candidates.iter().any(|candidate| candidate.path == path)
+                {
+                    candidates.push(Removal { path, reason: "cache unused" });
+                }
+            }
+        }
+
+        let mut removals: Vec<Removal> = candidates
+            .into_iter()
+            .filter(|candidate| dry_run || fs::remove_file(&candidate.path).is_ok())
+            .collect();
+        if let Some(publisher) = &self.publisher {
+            for path in publisher.prune_exports(dry_run)? {
+                removals.push(Removal {
+                    path,
+                    reason: "past publish retention",
+                });
+            }
+        }
+        Ok(removals)
+    }
+}
+
+fn files_with_extension(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_retention() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let sessions_dir = temp_dir.path().join("sessions");
+        let cache_dir = temp_dir.path().join("cache");
+        fs::create_dir_all(&sessions_dir)?;
+        fs::create_dir_all(&cache_dir)?;
+
+        let live_root = temp_dir.path().join("project");
+        fs::create_dir(&live_root)?;
+        for (name, root) in [("live", live_root.clone()), ("gone", temp_dir.path().join("deleted"))] {
+            fs::write(sessions_dir.join(format!("{}.toml", name)), format!("root = {:?}\n", root))?;
+        }
+        fs::write(sessions_dir.join("notes.toml"), "not = [a session")?;
+        let old_cache = cache_dir.join("old.bin");
+        fs::write(&old_cache, "cache")?;
+        fs::File::options()
+            .write(true)
+            .open(&old_cache)?
+            .set_modified(SystemTime::now() - Duration::from_secs(60 * 24 * 60 * 60))?;
+        fs::write(cache_dir.join("fresh.bin"), "cache")?;
+        fs::write(cache_dir.join("gone.bin"), "cache")?;
+
+        let retention = Retention::in_dirs(sessions_dir.clone(), cache_dir.clone(), 30);
+        let planned = retention.run(true)?;
+        let names: Vec<_> = planned.iter().map(|removal| removal.path.file_name().unwrap().to_owned()).collect();
+        assert_eq!(names, ["gone.toml", "gone.bin", "old.bin"]);
+        assert!(planned.iter().all(|removal| removal.path.exists()));
+
+        assert_eq!(retention.run(false)?, planned);
+        assert!(planned.iter().all(|removal| !removal.path.exists()));
+        for kept in ["live.toml", "notes.toml"] {
+            assert!(sessions_dir.join(kept).exists());
+        }
+        assert!(cache_dir.join("fresh.bin").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,408 @@
+use crate::directory::state::SelectionState;
+use crate::directory::tree::DirectoryTree;
+use anyhow::{Context, Result, anyhow};
+use globset::{GlobBuilder, GlobMatcher};
+use std::path::{Path, PathBuf};
+
+/// Name of the shared rules file looked up in the project root
+pub const RULES_FILE_NAME: &str = ".gthr.rules";
+
+/// One line of a rules file: a glob and the state it assigns
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub pattern: String,
+    pub state: SelectionState,
+    pub line: usize,
+    pub reason: Option<String>, // Trailing comment, explaining why a path is excluded
+    matcher: GlobMatcher,
+    directory_only: bool, // Pattern ended with `/`
+    match_name: bool,     // Pattern has no `/`, so it matches basenames anywhere
+}
+
+/// An ordered list of include/exclude rules. Later rules win over earlier ones.
+///
+/// ```text
+/// # Shared baseline for context exports
+/// + src/**
+/// + docs/adr/**
+/// - **/fixtures/**
+/// exclude *.lock
+/// - vendor/  # vendored, regenerate with `make vendor`
+/// ```
+///
+/// A comment after an exclude rule naming a single path is that path's exclusion
+/// reason, shown next to it in the list and optionally listed in the export footer.
+#[derive(Debug, Clone)]
+pub struct Rules {
+    pub path: PathBuf,
+    pub rules: Vec<Rule>,
+}
+
+/// Outcome of applying rules to a tree
+#[derive(Debug, Clone, Default)]
+pub struct RulesSummary {
+    pub applied: usize,
+    pub unmatched: Vec<(usize, String)>, // Line and pattern of rules that matched nothing (likely typos)
+}
+
+impl Rules {
+    pub fn get_project_rules_path(project_root: &Path) -> PathBuf {
+        project_root.join(RULES_FILE_NAME)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read rules file {}", path.display()))?;
+        Self::parse(&content, path)
+    }
+
+    pub fn parse(content: &str, path: &Path) -> Result<Self> {
+        let mut rules = Vec::new();
+
+        for (index, raw_line) in content.lines().enumerate() {
+            let line_number = index + 1;
+            let (line, comment) = split_comment(raw_line);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((state, pattern)) = parse_rule(line) else {
+                return Err(anyhow!(
+                    "{}:{}: expected `+ <glob>` or `- <glob>`, found `{}`",
+                    path.display(),
+                    line_number,
+                    line
+                ));
+            };
+
+            let directory_only = pattern.ends_with('/');
+            let glob = pattern.trim_end_matches('/');
+            let matcher = GlobBuilder::new(glob)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| anyhow!("{}:{}: invalid glob `{}`: {}", path.display(), line_number, pattern, e))?
+                .compile_matcher();
+
+            rules.push(Rule {
+                pattern: pattern.to_string(),
+                state,
+                line: line_number,
+                reason: comment.map(str::to_string),
+                matcher,
+                directory_only,
+                match_name: !glob.contains('/'),
+            });
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            rules,
+        })
+    }
+
+    /// Apply every rule in order, setting the state of matching nodes (and their children)
+    pub fn apply(&self, tree: &mut DirectoryTree) -> RulesSummary {
+        let root_path = tree.scan_root_path().to_path_buf();
+        let mut summary = RulesSummary::default();
+
+        for rule in &self.rules {
+            let mut matched = false;
+
+            for index in 0..tree.nodes.len() {
+                if index == tree.scan_root_index {
+                    continue;
+                }
+
+                let node = &tree.nodes[index];
+                if rule.directory_only && !node.is_directory {
+                    continue;
+                }
+
+                let is_match = if rule.match_name {
+                    rule.matcher.is_match(&node.name)
+                } else {
+                    let relative_path = node.path.strip_prefix(&root_path).unwrap_or(&node.path);
+                    rule.matcher.is_match(normalize_separators(relative_path))
+                };
+
+                if is_match {
+                    tree.set_state_bulk(index, rule.state);
+                    matched = true;
+                }
+            }
+
+            if matched {
+                summary.applied += 1;
+            } else {
+                summary.unmatched.push((rule.line, rule.pattern.clone()));
+            }
+        }
+
+        summary
+    }
+
+    /// Why a path (relative to the scan root, `/`-separated) is excluded, if a rule names it
+    pub fn reason_for(&self, relative_path: &str) -> Option<&str> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.names_path(relative_path))
+            .and_then(|rule| rule.reason.as_deref())
+    }
+
+    /// Path and reason of every excluded node with an exclusion reason, for the export footer
+    pub fn exclusion_reasons(&self, tree: &DirectoryTree) -> Vec<(String, String)> {
+        self.reasoned_paths(tree)
+            .filter(|(_, state, _)| *state == SelectionState::Excluded)
+            .map(|(path, _, reason)| (path, reason))
+            .collect()
+    }
+
+    fn reasoned_paths<'a>(
+        &'a self,
+        tree: &'a DirectoryTree,
+    ) -> impl Iterator<Item = (String, SelectionState, String)> + 'a {
+        let root_path = tree.scan_root_path().to_path_buf();
+        self.rules.iter().filter_map(move |rule| {
+            let reason = rule.reason.clone()?;
+            let relative_path = rule.literal_path()?;
+            if self.reason_for(relative_path) != Some(reason.as_str()) {
+                return None; // Superseded by a later rule for the same path
+            }
+            let index = *tree.path_to_index.get(&root_path.join(relative_path))?;
+            Some((relative_path.to_string(), tree.nodes[index].state, reason))
+        })
+    }
+
+    /// Set or clear the exclusion reason for a path in the rules file at `path`. An existing
+    /// exclude rule naming the path gets the reason as its comment, otherwise one is appended.
+    pub fn save_reason(path: &Path, relative_path: &str, is_directory: bool, reason: Option<&str>) -> Result<()> {
+        let mut lines = read_lines(path)?;
+        let reason = reason.map(str::trim).filter(|reason| !reason.is_empty());
+        match (exclude_rule_line(&lines, relative_path), reason) {
+            (Some(index), reason) => {
+                let rule = split_comment(&lines[index]).0.trim_end().to_string();
+                lines[index] = match reason {
+                    Some(reason) => format!("{}  # {}", rule, reason),
+                    None => rule,
+                };
+            }
+            (None, Some(reason)) => {
+                let suffix = if is_directory { "/" } else { "" };
+                lines.push(format!("- {}{}  # {}", relative_path, suffix, reason));
+            }
+            (None, None) => return Ok(()),
+        }
+        write_lines(path, &lines)
+    }
+
+    /// Remove the exclude rule naming a path, and its reason with it, from the rules file at
+    /// `path`. Returns whether there was one.
+    pub fn remove_exclusion(path: &Path, relative_path: &str) -> Result<bool> {
+        let mut lines = read_lines(path)?;
+        let Some(index) = exclude_rule_line(&lines, relative_path) else {
+            return Ok(false);
+        };
+        lines.remove(index);
+        write_lines(path, &lines)?;
+        Ok(true)
+    }
+}
+
+/// The lines of the rules file at `path`, none if it doesn't exist yet
+fn read_lines(path: &Path) -> Result<Vec<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(content.lines().map(str::to_string).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read rules file {}", path.display())),
+    }
+}
+
+fn write_lines(path: &Path, lines: &[String]) -> Result<()> {
+    let mut content = lines.join("\n");
+    content.push('\n');
+    std::fs::write(path, content).with_context(|| format!("Failed to write rules file {}", path.display()))
+}
+
+/// The last exclude rule naming `relative_path` itself
+fn exclude_rule_line(lines: &[String], relative_path: &str) -> Option<usize> {
+    lines.iter().rposition(|line| {
+        matches!(parse_rule(split_comment(line).0.trim()), Some((SelectionState::Excluded, pattern))
+            if pattern.trim().trim_end_matches('/') == relative_path)
+    })
+}
+
+impl Rule {
+    /// The path an exclude rule names, when its pattern is a plain path rather than a glob
+    fn literal_path(&self) -> Option<&str> {
+        let is_literal = !self.pattern.contains(['*', '?', '[', '{']);
+        (self.state == SelectionState::Excluded && is_literal).then(|| self.pattern.trim_end_matches('/'))
+    }
+
+    fn names_path(&self, relative_path: &str) -> bool {
+        self.literal_path() == Some(relative_path)
+    }
+}
+
+/// The state and pattern of a rule line without its comment
+fn parse_rule(line: &str) -> Option<(SelectionState, &str)> {
+    let (state, pattern) = if let Some(pattern) = line.strip_prefix('+') {
+        (SelectionState::Included, pattern)
+    } else if let Some(pattern) = line.strip_prefix('-') {
+        (SelectionState::Excluded, pattern)
+    } else if let Some(pattern) = line.strip_prefix("include ") {
+        (SelectionState::Included, pattern)
+    } else if let Some(pattern) = line.strip_prefix("exclude ") {
+        (SelectionState::Excluded, pattern)
+    } else {
+        return None;
+    };
+    Some((state, pattern.trim()))
+}
+
+/// `#` starts a comment at the beginning of a line or after whitespace. Returns the
+/// line without it, and the comment text if there is any.
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    if line.trim_start().starts_with('#') {
+        return ("", None);
+    }
+    match line.find(" #") {
+        Some(index) => {
+            let comment = line[index + 2..].trim();
+            (&line[..index], (!comment.is_empty()).then_some(comment))
+        }
+        None => (line, None),
+    }
+}
+
+fn normalize_separators(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn fixture_tree(root: &Path) -> DirectoryTree {
+        let mut tree = DirectoryTree::new(root.to_path_buf());
+        for (path, is_directory) in [
+            ("src", true),
+            ("src/main.rs", false),
+            ("src/fixtures", true),
+            ("src/fixtures/data.json", false),
+            ("docs", true),
+            ("docs/guide.md", false),
+            ("Cargo.lock", false),
+        ] {
+            let path = root.join(path);
+            let parent = path.parent().unwrap().to_path_buf();
+            tree.add_node(path, is_directory, &parent);
+        }
+        tree
+    }
+
+    fn state_of(tree: &DirectoryTree, root: &Path, path: &str) -> SelectionState {
+        tree.nodes[tree.path_to_index[&root.join(path)]].state
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let content = "# baseline\n+ src/**\n\nexclude **/fixtures/**  # test data\n- docs/\n";
+        let rules = Rules::parse(content, Path::new(RULES_FILE_NAME)).unwrap();
+
+        assert_eq!(rules.rules.len(), 3);
+        assert_eq!(rules.rules[0].pattern, "src/**");
+        assert_eq!(rules.rules[1].state, SelectionState::Excluded);
+        assert_eq!(rules.rules[1].line, 4);
+        assert!(rules.rules[2].directory_only);
+
+        let error = Rules::parse("+ src/**\nsrc/main.rs\n", Path::new("team.rules")).unwrap_err();
+        assert!(error.to_string().starts_with("team.rules:2:"));
+    }
+
+    #[test]
+    fn test_apply_rules_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let mut tree = fixture_tree(root);
+
+        let content = "+ src/**\n- **/fixtures/**\n+ docs/\n- *.lock\n+ vendor/**\n";
+        let rules = Rules::parse(content, Path::new(RULES_FILE_NAME)).unwrap();
+        let summary = rules.apply(&mut tree);
+
+        assert_eq!(state_of(&tree, root, "src/main.rs"), SelectionState::Included);
+        assert_eq!(state_of(&tree, root, "src/fixtures/data.json"), SelectionState::Excluded);
+        assert_eq!(state_of(&tree, root, "src"), SelectionState::Partial);
+        assert_eq!(state_of(&tree, root, "docs/guide.md"), SelectionState::Included);
+        assert_eq!(state_of(&tree, root, "Cargo.lock"), SelectionState::Excluded);
+
+        assert_eq!(summary.applied, 4);
+        assert_eq!(summary.unmatched, vec![(5, "vendor/**".to_string())]);
+    }
+
+    #[test]
+    fn test_rules_skip_locked_nodes() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let mut tree = fixture_tree(root);
+        let data_index = tree.path_to_index[&root.join("src/fixtures/data.json")];
+        tree.set_state(data_index, SelectionState::Included);
+        tree.toggle_lock(data_index);
+
+        let rules = Rules::parse("- src/**
+- **/*.json
+", Path::new(RULES_FILE_NAME)).unwrap();
+        rules.apply(&mut tree);
+
+        assert_eq!(state_of(&tree, root, "src/fixtures/data.json"), SelectionState::Included);
+        assert_eq!(state_of(&tree, root, "src/main.rs"), SelectionState::Excluded);
+        assert_eq!(state_of(&tree, root, "src/fixtures"), SelectionState::Included);
+        assert_eq!(state_of(&tree, root, "src"), SelectionState::Partial);
+
+        // Locked nodes can still be changed one at a time
+        tree.toggle_state(data_index);
+        assert_eq!(state_of(&tree, root, "src"), SelectionState::Excluded);
+    }
+
+    #[test]
+    fn test_exclusion_reasons() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let mut tree = fixture_tree(root);
+        let rules_path = root.join(RULES_FILE_NAME);
+        std::fs::write(&rules_path, "+ **\nexclude docs/  # moved to the wiki\n- **/fixtures/**  # test data\n").unwrap();
+
+        let rules = Rules::load(&rules_path).unwrap();
+        rules.apply(&mut tree);
+        assert_eq!(rules.reason_for("docs"), Some("moved to the wiki"));
+        // Globs cover many paths, their comments aren't reasons
+        assert_eq!(rules.reason_for("src/fixtures"), None);
+
+        // Reasons are added to new rules, and replace existing comments
+        Rules::save_reason(&rules_path, "Cargo.lock", false, Some("generated")).unwrap();
+        Rules::save_reason(&rules_path, "docs", true, Some("see the wiki")).unwrap();
+        let content = std::fs::read_to_string(&rules_path).unwrap();
+        assert!(content.contains("exclude docs/  # see the wiki\n"));
+        assert!(content.ends_with("- Cargo.lock  # generated\n"));
+
+        let rules = Rules::load(&rules_path).unwrap();
+        rules.apply(&mut tree);
+        let reasons = rules.exclusion_reasons(&tree);
+        assert_eq!(reasons, vec![
+            ("docs".to_string(), "see the wiki".to_string()),
+            ("Cargo.lock".to_string(), "generated".to_string()),
+        ]);
+
+        // Clearing a reason keeps the rule; removing the rule takes the reason with it
+        Rules::save_reason(&rules_path, "Cargo.lock", false, None).unwrap();
+        assert!(std::fs::read_to_string(&rules_path).unwrap().ends_with("- Cargo.lock\n"));
+        assert!(Rules::remove_exclusion(&rules_path, "docs").unwrap());
+        assert!(!Rules::remove_exclusion(&rules_path, "docs").unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&rules_path).unwrap(),
+            "+ **\n- **/fixtures/**  # test data\n- Cargo.lock\n"
+        );
+    }
+}
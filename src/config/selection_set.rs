@@ -0,0 +1,447 @@
+use super::cache::ContentCache;
+use super::session::fnv1a_hash;
+use crate::directory::state::SelectionState;
+use crate::directory::tree::DirectoryTree;
+use crate::output::exporter::export_path;
+use anyhow::{Context, Result, anyhow};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Name of the selection set file looked up in the project root
+pub const SET_FILE_NAME: &str = ".gthr-set";
+
+/// One included file, with the line ranges and note it was listed with
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SetEntry {
+    pub ranges: Vec<(usize, usize)>, // 1-based, inclusive; empty means the whole file
+    pub note: Option<String>,
+}
+
+/// A shareable list of included files, meant to be committed next to the code.
+///
+/// ```text
+/// # Context for the parser rewrite
+/// src/parser.rs
+/// src/lexer.rs:1-80,120  # token definitions only
+/// "docs/notes: draft.md"  # quoted because of the colon
+/// ```
+///
+/// One path per line, relative to the root with `/` separators and sorted, so diffs stay
+/// small. Paths containing `:`, `#`, `"`, `\` or surrounding spaces are written quoted.
+/// Lines starting with `#` are comments and aren't kept when the set is saved again.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SelectionSet {
+    pub path: PathBuf,
+    pub entries: BTreeMap<String, SetEntry>, // Relative path -> ranges and note
+    pub on_disk: Option<FileStamp>,          // The file as last loaded or saved
+}
+
+/// A file's modification time and content hash, to notice someone else writing it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileStamp {
+    pub modified: Option<SystemTime>,
+    pub hash: u64,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> Option<Self> {
+        let bytes = std::fs::read(path).ok()?;
+        Some(Self {
+            modified: std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok(),
+            hash: fnv1a_hash(&bytes),
+        })
+    }
+
+    /// Whether someone else saved `path` since `stamp` was taken. A file that's only been
+    /// touched, or was deleted, isn't a change worth keeping.
+    pub fn changed_since(stamp: Option<Self>, path: &Path) -> bool {
+        let Some(current) = Self::of(path) else {
+            return false;
+        };
+        !matches!(stamp, Some(stamp) if stamp.modified == current.modified || stamp.hash == current.hash)
+    }
+}
+
+/// A path next to `path` that no file uses yet: `.gthr-set-2`, `api-3.toml`, ...
+pub fn free_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy()));
+    (2..)
+        .map(|number| path.with_file_name(format!("{}-{}{}", stem, number, extension.as_deref().unwrap_or_default())))
+        .find(|path| !path.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Outcome of applying a set to a tree
+#[derive(Debug, Clone, Default)]
+pub struct SetSummary {
+    pub included: usize,
+    pub missing: Vec<String>, // Listed paths that aren't in the tree
+}
+
+/// A listed path that's gone, and the file that looks like it under a new name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+}
+
+impl SelectionSet {
+    pub fn get_project_set_path(project_root: &Path) -> PathBuf {
+        project_root.join(SET_FILE_NAME)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read selection set {}", path.display()))?;
+        let mut set = Self::parse(&content, path)?;
+        set.on_disk = Some(FileStamp {
+            modified: std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok(),
+            hash: fnv1a_hash(content.as_bytes()),
+        });
+        Ok(set)
+    }
+
+    pub fn parse(content: &str, path: &Path) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+        for (index, line) in content.lines().enumerate() {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let (relative_path, entry) = parse_line(line)
+                .map_err(|e| anyhow!("{}:{}: {}", path.display(), index + 1, e))?;
+            entries.insert(relative_path, entry);
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+            on_disk: None,
+        })
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        std::fs::write(&self.path, self.to_string())
+            .with_context(|| format!("Failed to write selection set {}", self.path.display()))?;
+        self.on_disk = FileStamp::of(&self.path);
+        Ok(())
+    }
+
+    /// The set as someone else saved it since it was loaded or last saved here, if they did
+    pub fn changed_on_disk(&self) -> Option<SelectionSet> {
+        if !FileStamp::changed_since(self.on_disk, &self.path) {
+            return None;
+        }
+        Self::load(&self.path).ok()
+    }
+
+    /// A path next to this one that no file uses yet
+    pub fn free_path(&self) -> PathBuf {
+        free_path(&self.path)
+    }
+
+    /// The tree's included files, keeping ranges and notes of those already in `self`
+    pub fn with_selection(&self, tree: &DirectoryTree) -> Self {
+        let root_path = tree.scan_root_path();
+        let entries = tree
+            .get_all_included_files()
+            .into_iter()
+            .map(|node| {
+                let relative_path = export_path(&node.path, root_path);
+                let entry = self.entries.get(&relative_path).cloned().unwrap_or_default();
+                (relative_path, entry)
+            })
+            .collect();
+        Self {
+            path: self.path.clone(),
+            entries,
+            on_disk: self.on_disk,
+        }
+    }
+
+    /// Make the listed files the selection: everything else is excluded. Locked nodes keep their state.
+    pub fn apply(&self, tree: &mut DirectoryTree) -> SetSummary {
+        let root_path = tree.scan_root_path().to_path_buf();
+        let mut summary = SetSummary::default();
+        tree.set_state_bulk(tree.scan_root_index, SelectionState::Excluded);
+        for relative_path in self.entries.keys() {
+            match tree.path_to_index.get(&root_path.join(relative_path)) {
+                Some(&index) => {
+                    tree.set_state_bulk(index, SelectionState::Included);
+                    summary.included += 1;
+                }
+                None => summary.missing.push(relative_path.clone()),
+            }
+        }
+        summary
+    }
+
+    /// Guess where missing paths went, among the files not in the set (see `detect_renames`)
+    pub fn detect_renames(&self, missing: &[String], tree: &DirectoryTree, content_cache: &ContentCache) -> Vec<Rename> {
+        detect_renames(missing, |relative_path| self.entries.contains_key(relative_path), tree, content_cache)
+    }
+
+    /// List renamed files under their new paths, keeping their ranges and notes
+    pub fn remap(&mut self, renames: &[Rename]) {
+        for rename in renames {
+            if let Some(entry) = self.entries.remove(&rename.from) {
+                self.entries.insert(rename.to.clone(), entry);
+            }
+        }
+    }
+}
+
+/// Guess where missing paths went: a file that isn't `listed` whose size and content hash
+/// are those `content_cache` recorded for the missing path. Candidates the cache has
+/// unchanged aren't read to hash them. Paths with no recorded content,
+/// no candidate or several (or whose candidate fits another path too) are left out, as are
+/// files that aren't in the tree.
+pub fn detect_renames(
+    missing: &[String],
+    listed: impl Fn(&str) -> bool,
+    tree: &DirectoryTree,
+    content_cache: &ContentCache,
+) -> Vec<Rename> {
+    let fingerprints: Vec<(&String, (u64, u64))> = missing
+        .iter()
+        .filter_map(|relative_path| Some((relative_path, content_cache.fingerprint(relative_path)?)))
+        .collect();
+    if fingerprints.is_empty() {
+        return Vec::new();
+    }
+
+    // Unlisted files by size; sizes may not be collected yet, so ask the file system
+    let root_path = tree.scan_root_path();
+    let mut unlisted_by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (index, node) in tree.nodes.iter().enumerate() {
+        if node.is_directory || listed(&export_path(&node.path, root_path)) {
+            continue;
+        }
+        let size = node.size.or_else(|| std::fs::metadata(&node.path).ok().map(|metadata| metadata.len()));
+        if let Some(size) = size {
+            unlisted_by_size.entry(size).or_default().push(index);
+        }
+    }
+
+    let mut hashes: HashMap<usize, Option<u64>> = HashMap::new();
+    let mut candidates: Vec<(&String, Vec<usize>)> = Vec::new();
+    for (relative_path, (size, hash)) in fingerprints {
+        let same_content: Vec<usize> = unlisted_by_size
+            .get(&size)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|&index| {
+                let content_hash = hashes.entry(index).or_insert_with(|| {
+                    let path = &tree.nodes[index].path;
+                    match content_cache.get(&export_path(path, root_path), path) {
+                        Some(summary) => Some(summary.hash),
+                        None => tree.read_file(path).ok().map(|bytes| fnv1a_hash(&bytes)),
+                    }
+                });
+                *content_hash == Some(hash)
+            })
+            .collect();
+        candidates.push((relative_path, same_content));
+    }
+
+    let claimed = |index: usize| candidates.iter().filter(|(_, indices)| indices.contains(&index)).count();
+    candidates
+        .iter()
+        .filter_map(|(relative_path, indices)| match indices.as_slice() {
+            [index] if claimed(*index) == 1 => Some(Rename {
+                from: relative_path.to_string(),
+                to: export_path(&tree.nodes[*index].path, root_path),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+impl fmt::Display for SelectionSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (relative_path, entry) in &self.entries {
+            write_path(f, relative_path)?;
+            if !entry.ranges.is_empty() {
+                let ranges: Vec<String> = entry
+                    .ranges
+                    .iter()
+                    .map(|&(start, end)| if start == end { start.to_string() } else { format!("{}-{}", start, end) })
+                    .collect();
+                write!(f, ":{}", ranges.join(","))?;
+            }
+            if let Some(note) = &entry.note {
+                write!(f, "  # {}", note)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn needs_quotes(path: &str) -> bool {
+    path.contains([':', '#', '"', '\\']) || path.trim() != path
+}
+
+fn write_path(f: &mut fmt::Formatter<'_>, path: &str) -> fmt::Result {
+    if !needs_quotes(path) {
+        return write!(f, "{}", path);
+    }
+    write!(f, "\"")?;
+    for c in path.chars() {
+        if c == '"' || c == '\\' {
+            write!(f, "\\")?;
+        }
+        write!(f, "{}", c)?;
+    }
+    write!(f, "\"")
+}
+
+/// `path[:ranges][  # note]`, where the path may be quoted
+fn parse_line(line: &str) -> std::result::Result<(String, SetEntry), String> {
+    let line = line.trim_start();
+    let (path, rest) = if let Some(quoted) = line.strip_prefix('"') {
+        let mut path = String::new();
+        let mut chars = quoted.char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((_, '\\')) => path.push(chars.next().ok_or("unfinished escape")?.1),
+                Some((index, '"')) => break index + 1,
+                Some((_, c)) => path.push(c),
+                None => return Err("missing closing quote".to_string()),
+            }
+        };
+        (path, &quoted[end..])
+    } else {
+        let end = line
+            .char_indices()
+            .find(|&(index, c)| c == ':' || (c == '#' && line[..index].ends_with(char::is_whitespace)))
+            .map_or(line.len(), |(index, _)| index);
+        (line[..end].trim_end().to_string(), &line[end..])
+    };
+    if path.is_empty() {
+        return Err("expected a path".to_string());
+    }
+
+    let (ranges, note) = match rest.find('#') {
+        Some(index) => (&rest[..index], Some(rest[index + 1..].trim().to_string())),
+        None => (rest, None),
+    };
+    let ranges = match ranges.trim().strip_prefix(':') {
+        Some(ranges) => ranges.split(',').map(parse_range).collect::<std::result::Result<_, _>>()?,
+        None if ranges.trim().is_empty() => Vec::new(),
+        None => return Err(format!("unexpected `{}` after the path", ranges.trim())),
+    };
+
+    Ok((path, SetEntry { ranges, note }))
+}
+
+fn parse_range(range: &str) -> std::result::Result<(usize, usize), String> {
+    let range = range.trim();
+    let (start, end) = range.split_once('-').unwrap_or((range, range));
+    let number = |text: &str| text.trim().parse::<usize>().ok().filter(|&line| line > 0);
+    match (number(start), number(end)) {
+        (Some(start), Some(end)) if start <= end => Ok((start, end)),
+        _ => Err(format!("invalid line range `{}`", range)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let content = "\
+# Context for the parser rewrite
+src/lexer.rs:1-80,120  # token definitions only
+src/parser.rs
+\"docs/notes: \\\"draft\\\" #2.md\":3-4  # quoted
+";
+        let set = SelectionSet::parse(content, Path::new(".gthr-set"))?;
+        assert_eq!(
+            set.entries["src/lexer.rs"],
+            SetEntry {
+                ranges: vec![(1, 80), (120, 120)],
+                note: Some("token definitions only".to_string()),
+            }
+        );
+        assert_eq!(set.entries["docs/notes: \"draft\" #2.md"].ranges, vec![(3, 4)]);
+
+        // Sorted, comments dropped, and parsing the output gives back the same set
+        let saved = set.to_string();
+        assert!(saved.starts_with("\"docs/notes"));
+        assert_eq!(SelectionSet::parse(&saved, Path::new(".gthr-set"))?, set);
+
+        assert!(SelectionSet::parse("src/a.rs:9-2", Path::new("x")).is_err());
+        assert!(SelectionSet::parse("\"unterminated", Path::new("x")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_and_capture() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("src"), true, &root).unwrap();
+        for path in ["src/main.rs", "src/lib.rs"] {
+            let index = tree.add_node(root.join(path), false, &root.join("src")).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+
+        tree.toggle_state(tree.root_index);
+        let set = SelectionSet::parse("src/main.rs:1-3  # entry point\ngone.rs\n", Path::new(".gthr-set")).unwrap();
+        let summary = set.apply(&mut tree);
+        assert_eq!((summary.included, summary.missing), (1, vec!["gone.rs".to_string()]));
+        assert_eq!(tree.get_all_included_files().len(), 1);
+
+        // Notes survive for files still included; the missing one is dropped
+        tree.toggle_state(tree.path_to_index[&root.join("src/lib.rs")]);
+        let captured = set.with_selection(&tree);
+        assert_eq!(captured.to_string(), "src/lib.rs\nsrc/main.rs:1-3  # entry point\n");
+    }
+
+    #[test]
+    fn test_detect_renames() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let root = temp_dir.path().to_path_buf();
+        std::fs::create_dir(root.join("src"))?;
+        let files = [
+            ("src/old.rs", "fn renamed() {}\n"),
+            ("src/twin_a.rs", "// same\n"),
+            ("src/twin_b.rs", "// same\n"),
+            ("src/edited.rs", "fn before() {}\n"),
+        ];
+        let mut content_cache = ContentCache::default();
+        for (path, content) in files {
+            std::fs::write(root.join(path), content)?;
+            content_cache.record(path, &root.join(path), content.as_bytes());
+        }
+
+        // `git mv` one file, move one of two identical files, and rename-and-edit another
+        std::fs::rename(root.join("src/old.rs"), root.join("src/new.rs"))?;
+        std::fs::rename(root.join("src/twin_a.rs"), root.join("src/twin_c.rs"))?;
+        std::fs::remove_file(root.join("src/edited.rs"))?;
+        std::fs::write(root.join("src/edited_too.rs"), "fn after() {}\n")?;
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("src"), true, &root).unwrap();
+        for path in ["src/new.rs", "src/twin_b.rs", "src/twin_c.rs", "src/edited_too.rs"] {
+            tree.add_node(root.join(path), false, &root.join("src")).unwrap();
+        }
+
+        let mut set = SelectionSet::parse(
+            "src/old.rs:2  # moved\nsrc/twin_a.rs\nsrc/edited.rs\n",
+            Path::new(".gthr-set"),
+        )?;
+        let summary = set.apply(&mut tree);
+        assert_eq!(summary.missing.len(), 3);
+
+        // Only the unambiguous one is proposed: twin_b and twin_c both have twin_a's content
+        let renames = set.detect_renames(&summary.missing, &tree, &content_cache);
+        assert_eq!(renames, vec![Rename { from: "src/old.rs".to_string(), to: "src/new.rs".to_string() }]);
+
+        set.remap(&renames);
+        assert_eq!(set.to_string(), "src/edited.rs\nsrc/new.rs:2  # moved\nsrc/twin_a.rs\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+use super::paths::paths;
+use super::slots::ExportSlot;
+use crate::directory::archive::ArchiveSource;
+use crate::directory::tree::DirectoryTree;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Per-project state remembered between runs, stored outside the project
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(default)]
+    pub root: PathBuf,
+    #[serde(default)]
+    pub manual_order: Vec<String>, // Relative paths of the files placed by hand, in export order
+    #[serde(default)]
+    pub last_export: Option<ExportSnapshot>,
+    #[serde(default)]
+    pub locked: BTreeMap<String, bool>, // Relative path of each locked node -> whether it is included
+    #[serde(default)]
+    pub slots: BTreeMap<String, ExportSlot>, // Export slots set in the interface, by number
+    #[serde(default)]
+    pub collapsed: Option<Vec<String>>, // Relative paths of the folded directories; unset until first saved
+}
+
+impl Session {
+    pub fn load(project_root: &Path) -> Self {
+        let root = canonical_root(project_root);
+        std::fs::read_to_string(Self::get_session_path(&root))
+            .ok()
+            .and_then(|content| toml::from_str::<Session>(&content).ok())
+            .unwrap_or_else(|| Session {
+                root,
+                ..Session::default()
+            })
+    }
+
+    /// Write the session, unless its root is an archive: those are read-only snapshots, and
+    /// nothing is persisted for them
+    pub fn save(&self) -> Result<()> {
+        if ArchiveSource::open(&self.root).is_some() {
+            return Ok(());
+        }
+        let path = Self::get_session_path(&self.root);
+        let content = toml::to_string_pretty(self)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+
+    pub fn get_sessions_dir() -> PathBuf {
+        paths().sessions_dir()
+    }
+
+    /// Session files are named after the root directory plus a hash of its full path
+    pub fn get_session_path(root: &Path) -> PathBuf {
+        let name = root
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "root".to_string());
+        let hash = fnv1a_hash(root.to_string_lossy().as_bytes());
+        Self::get_sessions_dir().join(format!("{}-{:016x}.toml", name, hash))
+    }
+}
+
+/// The included files (with size and mtime) at the moment an export completed
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ExportSnapshot {
+    pub exported_at: String,
+    #[serde(default)]
+    pub files: BTreeMap<String, SnapshotEntry>, // Relative path -> metadata
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct SnapshotEntry {
+    pub size: u64,
+    #[serde(default)]
+    pub modified: Option<i64>, // Seconds since the Unix epoch
+}
+
+impl ExportSnapshot {
+    pub fn capture(tree: &DirectoryTree) -> Self {
+        Self::capture_files(tree, tree.get_all_included_files().into_iter().map(|node| node.path.as_path()))
+    }
+
+    /// A snapshot of only `files`, the ones an export actually contained
+    pub fn capture_files<'a>(tree: &DirectoryTree, files: impl IntoIterator<Item = &'a Path>) -> Self {
+        let root_path = tree.scan_root_path();
+        let files = files
+            .into_iter()
+            .map(|path| {
+                let relative_path = path.strip_prefix(root_path).unwrap_or(path);
+                let scanned_size = tree.path_to_index.get(path).and_then(|&index| tree.nodes[index].size);
+                let metadata = std::fs::metadata(path).ok();
+                let entry = SnapshotEntry {
+                    size: metadata.as_ref().map_or(scanned_size.unwrap_or(0), |m| m.len()),
+                    modified: metadata
+                        .and_then(|m| m.modified().ok())
+                        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map(|duration| duration.as_secs() as i64),
+                };
+                (relative_path.to_string_lossy().to_string(), entry)
+            })
+            .collect();
+
+        Self {
+            exported_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            files,
+        }
+    }
+
+    /// Compare an earlier snapshot (`self`) against the current one
+    pub fn diff(&self, current: &ExportSnapshot) -> SnapshotDiff {
+        let mut diff = SnapshotDiff::default();
+
+        for (path, entry) in &current.files {
+            match self.files.get(path) {
+                None => diff.added.push((path.clone(), entry.size)),
+                Some(previous) if previous != entry => diff.modified.push((path.clone(), entry.size)),
+                Some(_) => {}
+            }
+        }
+        for (path, entry) in &self.files {
+            if !current.files.contains_key(path) {
+                diff.removed.push((path.clone(), entry.size));
+            }
+        }
+
+        diff
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SnapshotDiff {
+    pub added: Vec<(String, u64)>,
+    pub removed: Vec<(String, u64)>,
+    pub modified: Vec<(String, u64)>, // Same path, different size or mtime
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+pub fn canonical_root(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+pub fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(size: u64, modified: i64) -> SnapshotEntry {
+        SnapshotEntry {
+            size,
+            modified: Some(modified),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let mut previous = ExportSnapshot::default();
+        previous.files.insert("a.rs".to_string(), entry(10, 1));
+        previous.files.insert("b.rs".to_string(), entry(20, 1));
+        previous.files.insert("c.rs".to_string(), entry(30, 1));
+
+        let mut current = ExportSnapshot::default();
+        current.files.insert("a.rs".to_string(), entry(10, 1));
+        current.files.insert("c.rs".to_string(), entry(30, 2));
+        current.files.insert("d.rs".to_string(), entry(40, 1));
+
+        let diff = previous.diff(&current);
+        assert_eq!(diff.added, vec![("d.rs".to_string(), 40)]);
+        assert_eq!(diff.removed, vec![("b.rs".to_string(), 20)]);
+        assert_eq!(diff.modified, vec![("c.rs".to_string(), 30)]);
+
+        assert!(current.diff(&current).is_empty());
+    }
+
+    #[test]
+    fn test_archive_session_not_saved() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let root = temp_dir.path().join("snapshot.zip");
+        std::fs::write(&root, "")?;
+        let session = Session::load(&root);
+        session.save()?;
+        assert!(!Session::get_session_path(&session.root).exists());
+        Ok(())
+    }
+}
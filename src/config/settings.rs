@@ -1,7 +1,20 @@
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use anyhow::Result;
-use crate::constants::DEFAULT_MAX_FILE_SIZE;
+use super::include;
+use super::paths::paths;
+use super::slots::ExportSlot;
+use crate::constants::{DEFAULT_IGNORES, DEFAULT_MAX_FILE_SIZE};
+use std::collections::BTreeMap;
+use crate::directory::sort::SortOrder;
+use crate::model::FillOrder;
+use crate::output::formatter::{DEFAULT_FORMAT, FinalNewline};
+use crate::output::image::DEFAULT_EMBED_MAX_SIZE;
+use crate::ui::colors::Theme;
+use crate::ui::app::PREVIEW_DEBOUNCE;
+use crate::ui::info::LINE_SAMPLE_BYTES;
+use crate::ui::interface::SIZE_COLUMN_MIN_WIDTH;
+use crate::ui::preview::PREVIEW_CACHE_BYTES;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
@@ -9,46 +22,165 @@ pub struct Settings {
     pub max_file_size: u64,
     #[serde(default = "default_max_clipboard_size")]
     pub max_clipboard_size: usize,
+    #[serde(default)]
+    pub include_all: bool,
     #[serde(default = "default_respect_gitignore")]
     pub respect_gitignore: bool,
     #[serde(default = "default_show_hidden")]
     pub show_hidden: bool,
+    #[serde(default = "default_ignores")]
+    pub default_ignores: Vec<String>,
     #[serde(default = "default_include_metadata")]
     pub include_metadata: bool,
     #[serde(default = "default_include_line_numbers")]
     pub include_line_numbers: bool,
     #[serde(default)]
     pub default_output_dir: Option<PathBuf>,
+    #[serde(default = "default_legacy_typeahead")]
+    pub legacy_typeahead: bool,
+    #[serde(default = "default_dim_common_prefix")]
+    pub dim_common_prefix: bool,
+    #[serde(default = "default_size_column_min_width")]
+    pub size_column_min_width: u16,
+    #[serde(default)]
+    pub footer: Option<String>,
+    #[serde(default = "default_show_status_bar")]
+    pub show_status_bar: bool,
+    #[serde(default)]
+    pub match_sibling_directories: bool,
+    #[serde(default)]
+    pub match_file_names: bool,
+    #[serde(default = "default_smart_include_auto")]
+    pub smart_include_auto: bool,
+    #[serde(default = "default_blank_before_header")]
+    pub blank_before_header: bool,
+    #[serde(default = "default_blank_after_header")]
+    pub blank_after_header: bool,
+    #[serde(default)]
+    pub final_newline: FinalNewline,
+    #[serde(default)]
+    pub theme: Theme,
+    #[serde(default)]
+    pub sort_order: SortOrder,
+    #[serde(default)]
+    pub list_exclusion_reasons: bool,
+    #[serde(default = "default_keep_selection_on_rescan")]
+    pub keep_selection_on_rescan: bool,
+    #[serde(default)]
+    pub publish_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub publish_keep_days: Option<u64>,
+    #[serde(default)]
+    pub publish_keep_files: Option<usize>,
+    #[serde(default)]
+    pub publish_max_bytes: Option<u64>,
+    #[serde(default = "default_cache_keep_days")]
+    pub cache_keep_days: u64,
+    #[serde(default = "default_format")]
+    pub format: String,
+    #[serde(default)]
+    pub image_stubs: bool,
+    #[serde(default = "default_embed_image_max_size")]
+    pub embed_image_max_size: u64,
+    #[serde(default)]
+    pub token_budget: Option<u64>,
+    #[serde(default)]
+    pub budget_fill_order: FillOrder,
+    #[serde(default = "default_first_run_wizard")]
+    pub first_run_wizard: bool,
+    #[serde(default = "default_preview_debounce_ms")]
+    pub preview_debounce_ms: u64,
+    #[serde(default = "default_line_sample_bytes")]
+    pub line_sample_bytes: u64,
+    #[serde(default = "default_preview_cache_bytes")]
+    pub preview_cache_bytes: usize,
+    #[serde(default)]
+    pub fold_level: Option<usize>,
+    #[serde(default)]
+    pub startup: Vec<String>,
+    #[serde(default)]
+    pub slots: BTreeMap<String, ExportSlot>, // [slots.N] tables: output and format of F1-F4
 }
 
 fn default_max_file_size() -> u64 { DEFAULT_MAX_FILE_SIZE }
 fn default_max_clipboard_size() -> usize { 2 * 1024 * 1024 }
 fn default_respect_gitignore() -> bool { true }
 fn default_show_hidden() -> bool { false }
+fn default_ignores() -> Vec<String> { DEFAULT_IGNORES.iter().map(|name| name.to_string()).collect() }
 fn default_include_metadata() -> bool { true }
 fn default_include_line_numbers() -> bool { false }
+fn default_legacy_typeahead() -> bool { false }
+fn default_dim_common_prefix() -> bool { false }
+fn default_size_column_min_width() -> u16 { SIZE_COLUMN_MIN_WIDTH }
+fn default_show_status_bar() -> bool { true }
+fn default_smart_include_auto() -> bool { false }
+fn default_blank_before_header() -> bool { true }
+fn default_blank_after_header() -> bool { true }
+fn default_keep_selection_on_rescan() -> bool { true }
+fn default_format() -> String { DEFAULT_FORMAT.to_string() }
+fn default_embed_image_max_size() -> u64 { DEFAULT_EMBED_MAX_SIZE }
+fn default_first_run_wizard() -> bool { true }
+fn default_cache_keep_days() -> u64 { 30 }
+fn default_preview_debounce_ms() -> u64 { PREVIEW_DEBOUNCE.as_millis() as u64 }
+fn default_line_sample_bytes() -> u64 { LINE_SAMPLE_BYTES }
+fn default_preview_cache_bytes() -> usize { PREVIEW_CACHE_BYTES }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
             max_file_size: default_max_file_size(),
             max_clipboard_size: default_max_clipboard_size(),
+            include_all: false,
             respect_gitignore: default_respect_gitignore(),
             show_hidden: default_show_hidden(),
+            default_ignores: default_ignores(),
             include_metadata: default_include_metadata(),
             include_line_numbers: default_include_line_numbers(),
             default_output_dir: None,
+            legacy_typeahead: default_legacy_typeahead(),
+            dim_common_prefix: default_dim_common_prefix(),
+            size_column_min_width: default_size_column_min_width(),
+            footer: None,
+            show_status_bar: default_show_status_bar(),
+            match_sibling_directories: false,
+            match_file_names: false,
+            smart_include_auto: default_smart_include_auto(),
+            blank_before_header: default_blank_before_header(),
+            blank_after_header: default_blank_after_header(),
+            final_newline: FinalNewline::default(),
+            theme: Theme::default(),
+            sort_order: SortOrder::default(),
+            list_exclusion_reasons: false,
+            keep_selection_on_rescan: default_keep_selection_on_rescan(),
+            publish_dir: None,
+            publish_keep_days: None,
+            publish_keep_files: None,
+            publish_max_bytes: None,
+            cache_keep_days: default_cache_keep_days(),
+            format: default_format(),
+            image_stubs: false,
+            embed_image_max_size: default_embed_image_max_size(),
+            token_budget: None,
+            budget_fill_order: FillOrder::default(),
+            first_run_wizard: default_first_run_wizard(),
+            preview_debounce_ms: default_preview_debounce_ms(),
+            line_sample_bytes: default_line_sample_bytes(),
+            preview_cache_bytes: default_preview_cache_bytes(),
+            fold_level: None,
+            startup: Vec::new(),
+            slots: BTreeMap::new(),
         }
     }
 }
 
 impl Settings {
+    /// Settings from the file at `path`, over those of the files it includes
     pub fn load_from_file(path: &std::path::Path) -> Result<Self> {
-        let content = std::fs::read_to_string(path)?;
-        let settings: Settings = toml::from_str(&content)?;
-        Ok(settings)
+        let merged = include::merge(&include::resolve(path)?)?;
+        merged.try_into().map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
     }
 
+    #[allow(dead_code)]
     pub fn save_to_file(&self, path: &std::path::Path) -> Result<()> {
         let content = toml::to_string_pretty(self)?;
         if let Some(parent) = path.parent() {
@@ -58,20 +190,16 @@ impl Settings {
         Ok(())
     }
 
+    /// `--config`, or `config.toml` in the config directory (see `paths`)
     pub fn get_global_config_path() -> PathBuf {
-        if let Some(config_dir) = dirs::config_dir() {
-            config_dir.join(".gthr.toml")
-        } else if let Some(home_dir) = dirs::home_dir() {
-            home_dir.join(".config").join(".gthr.toml")
-        } else {
-            PathBuf::from(".gthr.toml")
-        }
+        paths().config_file.clone()
     }
 
     pub fn get_project_config_path(project_root: &std::path::Path) -> PathBuf {
         project_root.join(".gthr.toml")
     }
 
+    #[allow(dead_code)]
     pub fn load_or_default() -> Self {
         Self::load_with_project_root(&std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
     }
@@ -104,12 +232,18 @@ impl Settings {
         if project.max_clipboard_size != default_max_clipboard_size() {
             global.max_clipboard_size = project.max_clipboard_size;
         }
+        if project.include_all {
+            global.include_all = true;
+        }
         if project.respect_gitignore != default_respect_gitignore() {
             global.respect_gitignore = project.respect_gitignore;
         }
         if project.show_hidden != default_show_hidden() {
             global.show_hidden = project.show_hidden;
         }
+        if project.default_ignores != default_ignores() {
+            global.default_ignores = project.default_ignores;
+        }
         if project.include_metadata != default_include_metadata() {
             global.include_metadata = project.include_metadata;
         }
@@ -119,6 +253,100 @@ impl Settings {
         if project.default_output_dir.is_some() {
             global.default_output_dir = project.default_output_dir;
         }
+        if project.legacy_typeahead != default_legacy_typeahead() {
+            global.legacy_typeahead = project.legacy_typeahead;
+        }
+        if project.dim_common_prefix != default_dim_common_prefix() {
+            global.dim_common_prefix = project.dim_common_prefix;
+        }
+        if project.size_column_min_width != default_size_column_min_width() {
+            global.size_column_min_width = project.size_column_min_width;
+        }
+        if project.footer.is_some() {
+            global.footer = project.footer;
+        }
+        if project.show_status_bar != default_show_status_bar() {
+            global.show_status_bar = project.show_status_bar;
+        }
+        if project.match_sibling_directories {
+            global.match_sibling_directories = true;
+        }
+        if project.match_file_names {
+            global.match_file_names = true;
+        }
+        if project.smart_include_auto != default_smart_include_auto() {
+            global.smart_include_auto = project.smart_include_auto;
+        }
+        if project.blank_before_header != default_blank_before_header() {
+            global.blank_before_header = project.blank_before_header;
+        }
+        if project.blank_after_header != default_blank_after_header() {
+            global.blank_after_header = project.blank_after_header;
+        }
+        if project.final_newline != FinalNewline::default() {
+            global.final_newline = project.final_newline;
+        }
+        if project.theme != Theme::default() {
+            global.theme = project.theme;
+        }
+        if project.sort_order != SortOrder::default() {
+            global.sort_order = project.sort_order;
+        }
+        if project.list_exclusion_reasons {
+            global.list_exclusion_reasons = true;
+        }
+        if project.keep_selection_on_rescan != default_keep_selection_on_rescan() {
+            global.keep_selection_on_rescan = project.keep_selection_on_rescan;
+        }
+        if project.publish_dir.is_some() {
+            global.publish_dir = project.publish_dir;
+        }
+        if project.publish_keep_days.is_some() {
+            global.publish_keep_days = project.publish_keep_days;
+        }
+        if project.publish_keep_files.is_some() {
+            global.publish_keep_files = project.publish_keep_files;
+        }
+        if project.publish_max_bytes.is_some() {
+            global.publish_max_bytes = project.publish_max_bytes;
+        }
+        if project.cache_keep_days != default_cache_keep_days() {
+            global.cache_keep_days = project.cache_keep_days;
+        }
+        if project.format != default_format() {
+            global.format = project.format;
+        }
+        if project.image_stubs {
+            global.image_stubs = true;
+        }
+        if project.embed_image_max_size != default_embed_image_max_size() {
+            global.embed_image_max_size = project.embed_image_max_size;
+        }
+        if project.token_budget.is_some() {
+            global.token_budget = project.token_budget;
+        }
+        if project.budget_fill_order != FillOrder::default() {
+            global.budget_fill_order = project.budget_fill_order;
+        }
+        if project.first_run_wizard != default_first_run_wizard() {
+            global.first_run_wizard = project.first_run_wizard;
+        }
+        if project.preview_debounce_ms != default_preview_debounce_ms() {
+            global.preview_debounce_ms = project.preview_debounce_ms;
+        }
+        if project.line_sample_bytes != default_line_sample_bytes() {
+            global.line_sample_bytes = project.line_sample_bytes;
+        }
+        if project.preview_cache_bytes != default_preview_cache_bytes() {
+            global.preview_cache_bytes = project.preview_cache_bytes;
+        }
+        if project.fold_level.is_some() {
+            global.fold_level = project.fold_level;
+        }
+        if !project.startup.is_empty() {
+            global.startup = project.startup;
+        }
+        global.slots.extend(project.slots);
         global
     }
 
@@ -154,4 +382,27 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_included_settings() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let shared = temp_dir.path().join("shared");
+        std::fs::create_dir(&shared)?;
+        std::fs::write(shared.join("base.toml"), "max_file_size = 1000\nshow_hidden = true\n")?;
+        std::fs::write(temp_dir.path().join(".gthr.toml"), "include = \"shared/base.toml\"\nmax_file_size = 5000\n")?;
+
+        // The including file wins where both set something
+        let settings = Settings::load_from_file(&temp_dir.path().join(".gthr.toml"))?;
+        assert_eq!((settings.max_file_size, settings.show_hidden), (5000, true));
+
+        // Even when it sets a key back to its default
+        std::fs::write(temp_dir.path().join(".gthr.toml"), "include = \"shared/base.toml\"\nshow_hidden = false\n")?;
+        let settings = Settings::load_from_file(&temp_dir.path().join(".gthr.toml"))?;
+        assert_eq!((settings.max_file_size, settings.show_hidden), (1000, false));
+
+        std::fs::write(shared.join("base.toml"), "include = \"../.gthr.toml\"\n")?;
+        let error = Settings::load_from_file(&temp_dir.path().join(".gthr.toml")).unwrap_err();
+        assert!(error.to_string().starts_with("include cycle: "), "{}", error);
+        Ok(())
+    }
 }
\ No newline at end of file
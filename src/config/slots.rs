@@ -0,0 +1,64 @@
+use super::session::Session;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Slots bound to F1-F4 and `--slot`
+pub const SLOT_COUNT: u8 = 4;
+
+/// Where an export slot writes, and in which format
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportSlot {
+    pub output: PathBuf, // Relative to the project root unless absolute
+    #[serde(default)]
+    pub format: Option<String>, // The configured format if unset
+}
+
+impl ExportSlot {
+    pub fn output_path(&self, project_root: &Path) -> PathBuf {
+        project_root.join(&self.output)
+    }
+}
+
+/// Where a slot set up without typing a path exports to
+pub fn default_output(number: u8) -> PathBuf {
+    PathBuf::from(format!("gthr-slot-{}.md", number))
+}
+
+/// Slot `number` as configured in `[slots.N]`, else as set when it was first used in
+/// this project. Configuration wins, so a committed `.gthr.toml` is what everyone gets.
+pub fn resolve(number: u8, configured: &BTreeMap<String, ExportSlot>, session: &Session) -> Option<ExportSlot> {
+    let key = number.to_string();
+    configured.get(&key).or_else(|| session.slots.get(&key)).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_slot() {
+        let slot = |output: &str, format: Option<&str>| ExportSlot {
+            output: PathBuf::from(output),
+            format: format.map(str::to_string),
+        };
+        let configured: BTreeMap<String, ExportSlot> =
+            toml::from_str("[1]\noutput = \"context/api.md\"\n\n[2]\noutput = \"/tmp/all.xml\"\nformat = \"xml\"\n").unwrap();
+        let mut session = Session::default();
+        session.slots.insert("1".to_string(), slot("mine.md", None));
+        session.slots.insert("3".to_string(), slot("notes.txt", Some("plain")));
+
+        assert_eq!(resolve(1, &configured, &session), Some(slot("context/api.md", None)));
+        assert_eq!(resolve(2, &configured, &session), Some(slot("/tmp/all.xml", Some("xml"))));
+        assert_eq!(resolve(3, &configured, &session), Some(slot("notes.txt", Some("plain"))));
+        assert_eq!(resolve(4, &configured, &session), None);
+
+        let root = Path::new("/project");
+        assert_eq!(slot("context/api.md", None).output_path(root), PathBuf::from("/project/context/api.md"));
+        assert_eq!(slot("/tmp/all.xml", None).output_path(root), PathBuf::from("/tmp/all.xml"));
+
+        // Slots set in the interface survive a session round trip
+        let saved = toml::to_string_pretty(&session).unwrap();
+        assert_eq!(toml::from_str::<Session>(&saved).unwrap().slots, session.slots);
+    }
+}
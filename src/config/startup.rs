@@ -0,0 +1,90 @@
+use super::rules::Rules;
+use anyhow::{Result, anyhow};
+use std::path::Path;
+
+/// Something the interface can do on its own, named for the `startup` setting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StartupAction {
+    IncludeAll,
+    ExcludeAll,
+    IncludeMatches,
+    SelectEssentials,
+    ChangedOnly,
+    ToggleStatusBar,
+    FocusSearch,
+    Bottom,
+    ComparePin,
+    Info,
+}
+
+const ACTIONS: [(&str, StartupAction); 10] = [
+    ("include-all", StartupAction::IncludeAll),
+    ("exclude-all", StartupAction::ExcludeAll),
+    ("include-matches", StartupAction::IncludeMatches),
+    ("select-essentials", StartupAction::SelectEssentials),
+    ("changed-only", StartupAction::ChangedOnly),
+    ("toggle-status-bar", StartupAction::ToggleStatusBar),
+    ("focus-search", StartupAction::FocusSearch),
+    ("bottom", StartupAction::Bottom),
+    ("compare-pin", StartupAction::ComparePin),
+    ("info", StartupAction::Info),
+];
+
+/// One entry of the `startup` setting, run in order once the tree is scanned:
+///
+/// ```toml
+/// startup = ["exclude **/tests/**", "include src/**", "search main", "compare-pin"]
+/// ```
+///
+/// `include`/`exclude` (or `+`/`-`) take a glob, as in the rules file; `search` sets the
+/// query; anything else names an action.
+#[derive(Debug, Clone)]
+pub enum StartupStep {
+    Rule(Rules), // Always a single rule
+    Search(String),
+    Action(StartupAction),
+}
+
+impl StartupStep {
+    pub fn parse(entry: &str) -> Result<Self> {
+        let entry = entry.trim();
+        if let Some(query) = entry.strip_prefix("search ") {
+            return Ok(Self::Search(query.trim().to_string()));
+        }
+        if let Some((_, action)) = ACTIONS.iter().find(|(name, _)| *name == entry) {
+            return Ok(Self::Action(*action));
+        }
+        if ["include ", "exclude ", "+", "-"].iter().any(|prefix| entry.starts_with(prefix)) {
+            let rules = Rules::parse(entry, Path::new("startup"))?;
+            return Ok(Self::Rule(rules));
+        }
+
+        let names: Vec<&str> = ACTIONS.iter().map(|(name, _)| *name).collect();
+        Err(anyhow!(
+            "unknown step `{}`; expected `include <glob>`, `exclude <glob>`, `search <query>` or one of {}",
+            entry,
+            names.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_steps() {
+        assert!(matches!(StartupStep::parse(" search main rs "), Ok(StartupStep::Search(query)) if query == "main rs"));
+        assert!(matches!(StartupStep::parse("compare-pin"), Ok(StartupStep::Action(StartupAction::ComparePin))));
+
+        let Ok(StartupStep::Rule(rules)) = StartupStep::parse("exclude **/tests/**") else {
+            panic!("expected a rule");
+        };
+        assert_eq!(rules.rules.len(), 1);
+        assert_eq!(rules.rules[0].pattern, "**/tests/**");
+
+        let error = StartupStep::parse("open preview").unwrap_err().to_string();
+        assert!(error.contains("unknown step `open preview`"));
+        assert!(StartupStep::parse("include [").is_err());
+    }
+}
@@ -1,5 +1,12 @@
 /// Default maximum size for clipboard operations (2MB) - can be overridden in config
+#[allow(dead_code)]
 pub const DEFAULT_MAX_CLIPBOARD_SIZE: usize = 2 * 1024 * 1024;
 
 /// Default maximum file size to include (2MB) - can be overridden in config
 pub const DEFAULT_MAX_FILE_SIZE: u64 = 2 * 1024 * 1024;
+
+/// Directory in the project root holding gthr's own files: saved profiles and export progress
+pub const PROJECT_DIR: &str = ".gthr";
+
+/// Names skipped during the scan unless `--no-default-ignores` is passed - can be overridden in config
+pub const DEFAULT_IGNORES: &[&str] = &["target", "node_modules", ".git", PROJECT_DIR, "dist", "build", "*.lock"];
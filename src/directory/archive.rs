@@ -0,0 +1,372 @@
+use super::sort::SortOrder;
+use super::state::SelectionState;
+use super::tree::{DirectoryTree, is_text_sample};
+use anyhow::{Context, Result, anyhow};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Bytes sampled from each entry to decide whether it is text
+const TEXT_SAMPLE_SIZE: u64 = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveKind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// A read-only archive used as the scan root. Entries appear in the tree under the
+/// archive's own path (`snapshot.tar.gz/src/main.rs`) and are read on demand.
+#[derive(Debug)]
+pub struct ArchiveSource {
+    pub path: PathBuf,
+    pub kind: ArchiveKind,
+    prefetched: Mutex<HashMap<PathBuf, Vec<u8>>>, // Entries `prefetch` read, by relative path
+}
+
+struct EntryInfo {
+    is_directory: bool,
+    size: u64,
+    is_text_file: bool,
+}
+
+impl ArchiveSource {
+    /// Recognize `path` as an archive root, if it is a file with a supported extension
+    pub fn open(path: &Path) -> Option<Self> {
+        if !path.is_file() {
+            return None;
+        }
+        Some(Self {
+            path: path.to_path_buf(),
+            kind: ArchiveKind::from_path(path)?,
+            prefetched: Mutex::default(),
+        })
+    }
+
+    /// Enumerate entries into a virtual tree. `include` sees each path component
+    /// the way the directory walker would, so hidden files are filtered the same way.
+    pub fn build_tree(
+        self,
+        initial_state: SelectionState,
+        max_file_size: u64,
+        sort_order: SortOrder,
+        include: impl Fn(&Path) -> bool,
+    ) -> Result<DirectoryTree> {
+        // Parents still come before their entries: a path sorts after its prefixes
+        let mut entries: Vec<_> = self.list_entries()?.into_iter().collect();
+        entries.sort_by(|(a, _), (b, _)| sort_order.compare_paths(a, b));
+        let root_path = self.path.clone();
+        let mut tree = DirectoryTree::new(root_path.clone());
+        tree.set_state(tree.root_index, initial_state);
+
+        for (relative_path, entry) in entries {
+            let path = root_path.join(&relative_path);
+            let mut prefix = root_path.clone();
+            let is_visible = relative_path.components().all(|component| {
+                prefix.push(component);
+                include(&prefix)
+            });
+            if !is_visible || (!entry.is_directory && entry.size > max_file_size) {
+                continue;
+            }
+
+            let parent_path = path.parent().unwrap_or(&root_path).to_path_buf();
+            if let Some(node_index) = tree.add_node(path, entry.is_directory, &parent_path) {
+                if let Some(node) = tree.get_node_mut(node_index)
+                    && !entry.is_directory
+                {
+                    node.size = Some(entry.size);
+                    node.is_text_file = entry.is_text_file;
+                }
+                tree.set_state(node_index, initial_state);
+            }
+        }
+
+        tree.archive = Some(Arc::new(self));
+        Ok(tree)
+    }
+
+    /// Read the full contents of the entry at `path` (a path inside the virtual tree)
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.read_head(path, u64::MAX)
+    }
+
+    /// Read at most the first `limit` bytes of the entry at `path`
+    pub fn read_head(&self, path: &Path, limit: u64) -> Result<Vec<u8>> {
+        let relative_path = path.strip_prefix(&self.path).unwrap_or(path);
+        if let Ok(prefetched) = self.prefetched.lock()
+            && let Some(content) = prefetched.get(relative_path)
+        {
+            return Ok(content[..content.len().min(limit.try_into().unwrap_or(usize::MAX))].to_vec());
+        }
+        self.read_entries(&[relative_path], limit)?
+            .remove(relative_path)
+            .ok_or_else(|| anyhow!("{} not found in {}", relative_path.display(), self.path.display()))
+    }
+
+    /// Read the entries at `paths` in a single pass over the archive and keep them, so
+    /// reading each afterwards doesn't decompress the archive from the start again
+    pub fn prefetch(&self, paths: &[&Path]) -> Result<()> {
+        let Ok(mut prefetched) = self.prefetched.lock() else {
+            return Ok(());
+        };
+        let wanted: Vec<&Path> = paths
+            .iter()
+            .map(|path| path.strip_prefix(&self.path).unwrap_or(path))
+            .filter(|relative_path| !prefetched.contains_key(*relative_path))
+            .collect();
+        if !wanted.is_empty() {
+            prefetched.extend(self.read_entries(&wanted, u64::MAX)?);
+        }
+        Ok(())
+    }
+
+    /// Up to `limit` bytes of each entry at `relative_paths` that the archive has, walking
+    /// it once and stopping as soon as all of them are read
+    fn read_entries(&self, relative_paths: &[&Path], limit: u64) -> Result<HashMap<PathBuf, Vec<u8>>> {
+        let wanted: HashSet<&Path> = relative_paths.iter().copied().collect();
+        let mut contents = HashMap::new();
+        let mut take = |relative_path: Option<PathBuf>, reader: &mut dyn Read| -> Result<bool> {
+            if let Some(relative_path) = relative_path
+                && wanted.contains(relative_path.as_path())
+                && !contents.contains_key(&relative_path)
+            {
+                let mut content = Vec::new();
+                reader.take(limit).read_to_end(&mut content)?;
+                contents.insert(relative_path, content);
+            }
+            Ok(contents.len() == wanted.len())
+        };
+
+        match self.kind {
+            ArchiveKind::Tar | ArchiveKind::TarGz => {
+                let mut archive = tar::Archive::new(self.open_tar_reader()?);
+                for entry in archive.entries()? {
+                    let mut entry = entry?;
+                    let relative_path = normalize_entry_path(&entry.path()?);
+                    if take(relative_path, &mut entry)? {
+                        break;
+                    }
+                }
+            }
+            ArchiveKind::Zip => {
+                let mut archive = zip::ZipArchive::new(BufReader::new(File::open(&self.path)?))?;
+                for index in 0..archive.len() {
+                    let mut file = archive.by_index(index)?;
+                    let name = file.name()?.to_string();
+                    if take(normalize_entry_path(Path::new(&name)), &mut file)? {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(contents)
+    }
+
+    fn open_tar_reader(&self) -> Result<Box<dyn Read>> {
+        let file = BufReader::new(
+            File::open(&self.path).with_context(|| format!("Failed to open {}", self.path.display()))?,
+        );
+        Ok(match self.kind {
+            ArchiveKind::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+            _ => Box::new(file),
+        })
+    }
+
+    /// All entries keyed by normalized relative path, including directories that are
+    /// only implied by the paths of their files
+    fn list_entries(&self) -> Result<BTreeMap<PathBuf, EntryInfo>> {
+        let mut entries = BTreeMap::new();
+
+        match self.kind {
+            ArchiveKind::Tar | ArchiveKind::TarGz => {
+                let mut archive = tar::Archive::new(self.open_tar_reader()?);
+                for entry in archive.entries()? {
+                    let entry = entry?;
+                    let entry_type = entry.header().entry_type();
+                    if !entry_type.is_dir() && !entry_type.is_file() {
+                        continue; // Links and special files
+                    }
+                    let Some(relative_path) = normalize_entry_path(&entry.path()?) else {
+                        continue;
+                    };
+                    let is_directory = entry_type.is_dir();
+                    let size = entry.size();
+                    let is_text_file = !is_directory && sample_is_text(entry)?;
+                    insert_entry(&mut entries, relative_path, is_directory, size, is_text_file);
+                }
+            }
+            ArchiveKind::Zip => {
+                let mut archive = zip::ZipArchive::new(BufReader::new(File::open(&self.path)?))
+                    .with_context(|| format!("Failed to read {}", self.path.display()))?;
+                for index in 0..archive.len() {
+                    let file = archive.by_index(index)?;
+                    let name = file.name()?.to_string();
+                    let Some(relative_path) = normalize_entry_path(Path::new(&name)) else {
+                        continue;
+                    };
+                    let is_directory = file.is_dir();
+                    let size = file.size();
+                    let is_text_file = !is_directory && sample_is_text(file)?;
+                    insert_entry(&mut entries, relative_path, is_directory, size, is_text_file);
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+fn insert_entry(
+    entries: &mut BTreeMap<PathBuf, EntryInfo>,
+    relative_path: PathBuf,
+    is_directory: bool,
+    size: u64,
+    is_text_file: bool,
+) {
+    for ancestor in relative_path.ancestors().skip(1) {
+        if ancestor.as_os_str().is_empty() {
+            break;
+        }
+        entries.entry(ancestor.to_path_buf()).or_insert(EntryInfo {
+            is_directory: true,
+            size: 0,
+            is_text_file: false,
+        });
+    }
+    entries.insert(
+        relative_path,
+        EntryInfo {
+            is_directory,
+            size,
+            is_text_file,
+        },
+    );
+}
+
+fn sample_is_text(reader: impl Read) -> Result<bool> {
+    let mut sample = Vec::new();
+    reader.take(TEXT_SAMPLE_SIZE).read_to_end(&mut sample)?;
+    Ok(is_text_sample(&sample))
+}
+
+/// Entry path relative to the archive root; `None` for the root itself or for paths
+/// that would escape it (absolute or containing `..`)
+fn normalize_entry_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!normalized.as_os_str().is_empty()).then_some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn write_tar_gz(path: &Path, files: &[(&str, &[u8])]) {
+        let encoder = flate2::write::GzEncoder::new(File::create(path).unwrap(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *content).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn write_zip(path: &Path, files: &[(&str, &[u8])]) {
+        let mut writer = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in files {
+            writer.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    fn build(path: &Path) -> DirectoryTree {
+        ArchiveSource::open(path)
+            .unwrap()
+            .build_tree(SelectionState::Included, u64::MAX, SortOrder::Natural, |_| true)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_tar_gz_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("snapshot.tar.gz");
+        write_tar_gz(
+            &path,
+            &[("./src/main.rs", b"fn main() {}"), ("logo.png", &[0x89, b'P', b'N', b'G', 0, 0])],
+        );
+
+        let tree = build(&path);
+        let main_path = path.join("src").join("main.rs");
+        let main_node = &tree.nodes[tree.path_to_index[&main_path]];
+        assert!(main_node.is_text_file);
+        assert_eq!(main_node.size, Some(12));
+        assert!(tree.nodes[tree.path_to_index[&path.join("src")]].is_directory);
+        assert!(!tree.nodes[tree.path_to_index[&path.join("logo.png")]].is_text_file);
+
+        assert_eq!(tree.read_to_string(&main_path).unwrap(), "fn main() {}");
+        assert_eq!(tree.get_all_included_files().len(), 1);
+    }
+
+    #[test]
+    fn test_prefetch_and_read_head() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("snapshot.tar.gz");
+        write_tar_gz(&path, &[("a.rs", b"fn a() {}"), ("b.rs", b"fn b() {}"), ("c.rs", b"fn c() {}")]);
+        let archive = ArchiveSource::open(&path).unwrap();
+
+        assert_eq!(archive.read_head(&path.join("b.rs"), 4).unwrap(), b"fn b");
+        let (a, c) = (path.join("a.rs"), path.join("c.rs"));
+        archive.prefetch(&[&a, &c, &path.join("missing.rs")]).unwrap();
+        assert_eq!(archive.prefetched.lock().unwrap().len(), 2);
+
+        // Prefetched entries are served from memory, even once the archive is gone
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(archive.read(&c).unwrap(), b"fn c() {}");
+        assert_eq!(archive.read_head(&a, 2).unwrap(), b"fn");
+        assert!(archive.read(&path.join("b.rs")).is_err());
+    }
+
+    #[test]
+    fn test_zip_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("snapshot.zip");
+        write_zip(&path, &[("docs/guide.md", b"# Guide"), ("../escape.txt", b"nope")]);
+
+        let tree = build(&path);
+        let guide_path = path.join("docs").join("guide.md");
+        assert_eq!(tree.read_to_string(&guide_path).unwrap(), "# Guide");
+        assert_eq!(tree.nodes.len(), 3); // Root, docs, guide.md
+    }
+}
@@ -0,0 +1,295 @@
+use super::tree::DirectoryTree;
+use std::path::{Path, PathBuf};
+
+/// Best-effort lookup of the local files a source file depends on, as tree indices.
+/// Only Rust is supported for now: `mod foo;` declarations and `use crate::`,
+/// `use self::` and `use super::` paths are resolved against the files in the tree.
+pub fn find_local_dependencies(tree: &DirectoryTree, file_index: usize) -> Vec<usize> {
+    let Some(node) = tree.get_node(file_index) else {
+        return Vec::new();
+    };
+    if node.is_directory || node.path.extension().is_none_or(|ext| ext != "rs") {
+        return Vec::new();
+    }
+    let Ok(source) = tree.read_to_string(&node.path) else {
+        return Vec::new();
+    };
+    let Some(module) = RustModule::locate(&node.path) else {
+        return Vec::new();
+    };
+
+    let mut dependencies = Vec::new();
+    for candidates in rust_dependency_candidates(&source, &module) {
+        // The first candidate that exists in the tree wins
+        if let Some(&index) = candidates.iter().find_map(|path| tree.path_to_index.get(path))
+            && index != file_index
+            && !dependencies.contains(&index)
+        {
+            dependencies.push(index);
+        }
+    }
+    dependencies
+}
+
+/// Where a Rust file sits in its crate's module tree
+struct RustModule {
+    crate_dir: PathBuf,   // Directory holding main.rs/lib.rs
+    path: Vec<String>,    // Module path below the crate root
+    child_dir: PathBuf,   // Where `mod foo;` declared in this file looks for foo.rs
+}
+
+impl RustModule {
+    fn locate(file_path: &Path) -> Option<Self> {
+        let crate_dir = file_path
+            .ancestors()
+            .skip(1)
+            .find(|dir| dir.join("main.rs").is_file() || dir.join("lib.rs").is_file())?
+            .to_path_buf();
+
+        let relative_path = file_path.strip_prefix(&crate_dir).ok()?;
+        let mut path: Vec<String> = relative_path
+            .with_extension("")
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect();
+
+        // main.rs, lib.rs and mod.rs declare their submodules next to themselves
+        let is_crate_root = path == ["main"] || path == ["lib"];
+        let child_dir = if is_crate_root || path.last().is_some_and(|stem| stem == "mod") {
+            path.pop();
+            file_path.parent()?.to_path_buf()
+        } else {
+            file_path.with_extension("")
+        };
+
+        Some(Self {
+            crate_dir,
+            path,
+            child_dir,
+        })
+    }
+
+    /// Candidate files for a module path, longest prefix first, since a `use` path
+    /// usually ends in an item rather than a module
+    fn candidates_for(&self, module_path: &[String]) -> Vec<PathBuf> {
+        let mut candidates = Vec::new();
+        for length in (1..=module_path.len()).rev() {
+            let dir = module_path[..length - 1]
+                .iter()
+                .fold(self.crate_dir.clone(), |dir, part| dir.join(part));
+            let name = &module_path[length - 1];
+            candidates.push(dir.join(format!("{}.rs", name)));
+            candidates.push(dir.join(name).join("mod.rs"));
+        }
+        candidates
+    }
+}
+
+fn rust_dependency_candidates(source: &str, module: &RustModule) -> Vec<Vec<PathBuf>> {
+    let mut dependencies = Vec::new();
+
+    for statement in rust_statements(source) {
+        let statement = strip_visibility(&statement);
+
+        if let Some(name) = statement.strip_prefix("mod ") {
+            let name = name.trim();
+            if is_identifier(name) {
+                dependencies.push(vec![
+                    module.child_dir.join(format!("{}.rs", name)),
+                    module.child_dir.join(name).join("mod.rs"),
+                ]);
+            }
+        } else if let Some(tree) = statement.strip_prefix("use ") {
+            for use_path in expand_use_tree(tree.trim()) {
+                if let Some(module_path) = resolve_use_path(&use_path, module) {
+                    dependencies.push(module.candidates_for(&module_path));
+                }
+            }
+        }
+    }
+
+    dependencies
+}
+
+/// `;`-terminated statements starting with `mod`/`use`, with comments removed and
+/// multi-line `use` trees joined
+fn rust_statements(source: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current: Option<String> = None;
+
+    for line in source.lines() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+        if let Some(statement) = current.as_mut() {
+            statement.push(' ');
+            statement.push_str(line);
+        } else {
+            let start = strip_visibility(line);
+            let is_inline_module = start.starts_with("mod ") && line.contains('{');
+            if start.starts_with("use ") || (start.starts_with("mod ") && !is_inline_module) {
+                current = Some(line.to_string());
+            }
+        }
+
+        if let Some(statement) = current.take_if(|statement| statement.contains(';')) {
+            let statement = statement.split(';').next().unwrap_or_default().to_string();
+            statements.push(statement);
+        }
+    }
+
+    statements
+}
+
+fn strip_visibility(statement: &str) -> &str {
+    let statement = statement.trim();
+    if let Some(rest) = statement.strip_prefix("pub") {
+        let rest = rest.trim_start();
+        // pub(crate), pub(super), pub(in path)
+        if let Some(rest) = rest.strip_prefix('(') {
+            return rest.split_once(')').map_or(rest, |(_, rest)| rest).trim_start();
+        }
+        return rest;
+    }
+    statement
+}
+
+/// Flatten `a::{b, c::{d, e}}` into `a::b`, `a::c::d`, `a::c::e`, dropping `as` renames
+fn expand_use_tree(tree: &str) -> Vec<String> {
+    let tree: String = tree
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace(" as ", "@") // Keep the rename attached to its path while removing spaces
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let Some(open) = tree.find('{') else {
+        return vec![tree.split('@').next().unwrap_or_default().to_string()];
+    };
+    let Some(close) = tree.rfind('}') else {
+        return Vec::new();
+    };
+
+    let prefix = &tree[..open];
+    let inner = &tree[open + 1..close];
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, c) in inner.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(&inner[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&inner[start..]);
+
+    items
+        .into_iter()
+        .filter(|item| !item.is_empty())
+        .flat_map(expand_use_tree)
+        .map(|path| match path.as_str() {
+            "self" => prefix.trim_end_matches("::").to_string(),
+            _ => format!("{}{}", prefix, path),
+        })
+        .collect()
+}
+
+/// Module path (from the crate root) named by a `use` path, or `None` for external crates
+fn resolve_use_path(use_path: &str, module: &RustModule) -> Option<Vec<String>> {
+    let mut segments = use_path.split("::").filter(|segment| !segment.is_empty() && *segment != "*");
+
+    let mut module_path = match segments.next()? {
+        "crate" => Vec::new(),
+        "self" => module.path.clone(),
+        "super" => {
+            let mut path = module.path.clone();
+            path.pop()?;
+            path
+        }
+        _ => return None,
+    };
+
+    for segment in segments {
+        if segment == "super" {
+            module_path.pop()?;
+        } else {
+            module_path.push(segment.to_string());
+        }
+    }
+
+    (!module_path.is_empty()).then_some(module_path)
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn fixture_tree(root: &Path, files: &[(&str, &str)]) -> DirectoryTree {
+        let mut tree = DirectoryTree::new(root.to_path_buf());
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, content).unwrap();
+
+            // Add missing parent directories first
+            let relative_path = path.strip_prefix(root).unwrap().to_path_buf();
+            let mut dir = root.to_path_buf();
+            for component in relative_path.parent().unwrap().components() {
+                let child = dir.join(component);
+                tree.add_node(child.clone(), true, &dir);
+                dir = child;
+            }
+            tree.add_node(path, false, &dir);
+        }
+        tree
+    }
+
+    fn names(tree: &DirectoryTree, root: &Path, indices: Vec<usize>) -> Vec<String> {
+        indices
+            .into_iter()
+            .map(|index| tree.nodes[index].path.strip_prefix(root).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_rust_dependencies() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let tree = fixture_tree(
+            root,
+            &[
+                ("src/main.rs", "mod cli;\npub mod ui;\nuse crate::cli::Cli;\nuse std::io;\n"),
+                ("src/cli.rs", "use crate::ui::{\n    app::App, // the app\n    colors,\n};\n"),
+                ("src/ui/mod.rs", "pub mod app;\npub mod colors;\n"),
+                ("src/ui/app.rs", "use super::colors::ColorScheme;\nuse self::inner::*;\n"),
+                ("src/ui/colors.rs", ""),
+            ],
+        );
+        let index = |path: &str| tree.path_to_index[&root.join(path)];
+
+        let dependencies = find_local_dependencies(&tree, index("src/main.rs"));
+        assert_eq!(names(&tree, root, dependencies), ["src/cli.rs", "src/ui/mod.rs"]);
+
+        let dependencies = find_local_dependencies(&tree, index("src/cli.rs"));
+        assert_eq!(names(&tree, root, dependencies), ["src/ui/app.rs", "src/ui/colors.rs"]);
+
+        let dependencies = find_local_dependencies(&tree, index("src/ui/app.rs"));
+        assert_eq!(names(&tree, root, dependencies), ["src/ui/colors.rs"]);
+    }
+
+    #[test]
+    fn test_expand_use_tree() {
+        assert_eq!(expand_use_tree("crate::a::{b, c::{d, self}}"), ["crate::a::b", "crate::a::c::d", "crate::a::c"]);
+        assert_eq!(expand_use_tree("super::x as y"), ["super::x"]);
+    }
+}
@@ -0,0 +1,203 @@
+use super::state::SelectionState;
+use super::tree::DirectoryTree;
+use std::path::{Component, Path};
+
+/// Source files above this size are left out of the essentials selection
+pub const ESSENTIALS_SIZE_CAP: u64 = 100 * 1024;
+
+/// Top-level manifests and config files that describe a project
+const PROJECT_FILES: &[&str] = &[
+    "Cargo.toml",
+    "package.json",
+    "tsconfig.json",
+    "pyproject.toml",
+    "setup.py",
+    "setup.cfg",
+    "requirements.txt",
+    "go.mod",
+    "Makefile",
+    "Dockerfile",
+];
+
+/// Directories whose contents are the project's own source
+const SOURCE_DIRS: &[&str] = &["src", "lib"];
+
+/// Directory names holding tests, fixtures or build output, at any depth
+const EXCLUDED_DIRS: &[&str] = &[
+    "test", "tests", "__tests__", "spec", "fixtures", "testdata", "__snapshots__",
+    "target", "dist", "build", "node_modules", "__pycache__", "generated",
+];
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "svg", "webp", "ico", "bmp"];
+
+/// Whether a file belongs in the essentials selection, by its path relative to the root
+pub fn is_essential(relative_path: &Path, size: Option<u64>) -> bool {
+    let components: Vec<String> = relative_path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    let Some((name, dirs)) = components.split_last() else {
+        return false;
+    };
+
+    if is_excluded(name, dirs) {
+        return false;
+    }
+
+    if dirs.is_empty() {
+        return name.to_lowercase().starts_with("readme") || PROJECT_FILES.contains(&name.as_str());
+    }
+
+    SOURCE_DIRS.contains(&dirs[0].as_str()) && size.is_none_or(|size| size <= ESSENTIALS_SIZE_CAP)
+}
+
+fn is_excluded(name: &str, dirs: &[String]) -> bool {
+    if dirs.iter().any(|dir| EXCLUDED_DIRS.contains(&dir.as_str())) {
+        return true;
+    }
+
+    let lower_name = name.to_lowercase();
+    let extension = lower_name.rsplit_once('.').map(|(_, ext)| ext).unwrap_or_default();
+    let stem = lower_name.split('.').next().unwrap_or_default();
+
+    // Test files by naming convention
+    let is_test = stem.ends_with("_test")
+        || stem.starts_with("test_")
+        || lower_name.contains(".test.")
+        || lower_name.contains(".spec.");
+    // Images that only decorate documentation
+    let is_doc_image = dirs.first().is_some_and(|dir| dir == "docs") && IMAGE_EXTENSIONS.contains(&extension);
+
+    is_test || is_doc_image || is_generated(name)
+}
+
+/// Lock files, minified bundles and generated code, by file name
+pub fn is_generated(name: &str) -> bool {
+    let lower_name = name.to_lowercase();
+    let extension = lower_name.rsplit_once('.').map(|(_, ext)| ext).unwrap_or_default();
+    let stem = lower_name.split('.').next().unwrap_or_default();
+
+    extension == "lock"
+        || lower_name == "package-lock.json"
+        || lower_name.contains(".min.")
+        || lower_name.contains(".generated.")
+        || stem.ends_with("_pb2")
+}
+
+/// Replace the current selection with the essentials heuristic, returning how many
+/// files ended up included. The result is an ordinary selection and can be edited freely.
+pub fn select_essentials(tree: &mut DirectoryTree) -> usize {
+    let root_path = tree.nodes[tree.root_index].path.clone();
+    tree.set_state_bulk(tree.root_index, SelectionState::Excluded);
+
+    let essentials: Vec<usize> = tree
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(index, node)| !node.is_directory && tree.is_in_view(*index))
+        .filter(|(_, node)| {
+            let relative_path = node.path.strip_prefix(&root_path).unwrap_or(&node.path);
+            is_essential(relative_path, node.size)
+        })
+        .map(|(index, _)| index)
+        .collect();
+
+    for &index in &essentials {
+        tree.set_state_bulk(index, SelectionState::Included);
+    }
+    essentials.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a tree from relative paths, without touching the filesystem
+    fn fixture_tree(files: &[&str]) -> DirectoryTree {
+        let root = Path::new("/project");
+        let mut tree = DirectoryTree::new(root.to_path_buf());
+        for file in files {
+            let path = root.join(file);
+            let mut dir = root.to_path_buf();
+            for component in Path::new(file).parent().unwrap().components() {
+                let child = dir.join(component);
+                tree.add_node(child.clone(), true, &dir);
+                dir = child;
+            }
+            let index = tree.add_node(path, false, &dir).unwrap();
+            tree.nodes[index].size = Some(if file.contains("huge") { ESSENTIALS_SIZE_CAP + 1 } else { 100 });
+        }
+        tree
+    }
+
+    fn included(files: &[&str]) -> Vec<String> {
+        let mut tree = fixture_tree(files);
+        let count = select_essentials(&mut tree);
+        let included: Vec<String> = files
+            .iter()
+            .filter(|file| tree.nodes[tree.path_to_index[&Path::new("/project").join(file)]].state == SelectionState::Included)
+            .map(|file| file.to_string())
+            .collect();
+        assert_eq!(count, included.len());
+        included
+    }
+
+    #[test]
+    fn test_rust_layout() {
+        let files = [
+            "Cargo.toml",
+            "Cargo.lock",
+            "README.md",
+            "src/main.rs",
+            "src/ui/app.rs",
+            "src/huge_table.rs",
+            "src/parser_test.rs",
+            "tests/integration.rs",
+            "benches/bench.rs",
+            "docs/screenshot.png",
+            "target/debug/build.rs",
+        ];
+        assert_eq!(included(&files), ["Cargo.toml", "README.md", "src/main.rs", "src/ui/app.rs"]);
+    }
+
+    #[test]
+    fn test_node_layout() {
+        let files = [
+            "package.json",
+            "package-lock.json",
+            "tsconfig.json",
+            "readme.md",
+            "src/index.ts",
+            "src/index.test.ts",
+            "src/__tests__/app.tsx",
+            "src/vendor.min.js",
+            "lib/util.js",
+            "dist/index.js",
+            "node_modules/react/index.js",
+        ];
+        assert_eq!(included(&files), ["package.json", "tsconfig.json", "readme.md", "src/index.ts", "lib/util.js"]);
+    }
+
+    #[test]
+    fn test_python_layout() {
+        let files = [
+            "pyproject.toml",
+            "requirements.txt",
+            "README.rst",
+            "src/app/__init__.py",
+            "src/app/models.py",
+            "src/app/models_pb2.py",
+            "src/app/test_models.py",
+            "src/app/fixtures/data.json",
+            "tests/test_app.py",
+            "docs/index.md",
+        ];
+        assert_eq!(
+            included(&files),
+            ["pyproject.toml", "requirements.txt", "README.rst", "src/app/__init__.py", "src/app/models.py"]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use super::tree::{DirectoryTree, is_text_by_content};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// Size and text detection for one node, collected after the tree is already on screen
+#[derive(Debug, Clone)]
+pub struct MetadataUpdate {
+    pub index: usize,
+    pub size: Option<u64>,
+    pub is_text_file: bool,
+}
+
+impl MetadataUpdate {
+    /// Fill in the node, returning whether it appeared or disappeared from the list
+    pub fn apply(&self, tree: &mut DirectoryTree) -> bool {
+        let Some(node) = tree.get_node_mut(self.index) else {
+            return false;
+        };
+        let was_text_file = node.is_text_file;
+        node.size = self.size;
+        node.is_text_file = self.is_text_file;
+        node.metadata_pending = false;
+        was_text_file != self.is_text_file
+    }
+}
+
+/// Collect metadata for every node added with `add_node_deferred` on a background
+/// thread. Files over `max_file_size` come back as non-text, so they drop out of the
+/// list and the export just like files skipped during the scan. The receiver is
+/// disconnected once every pending node has been reported.
+pub fn spawn_metadata_pass(tree: &DirectoryTree, max_file_size: u64) -> Option<Receiver<MetadataUpdate>> {
+    let pending: Vec<(usize, PathBuf, bool)> = tree
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| node.metadata_pending)
+        .map(|(index, node)| (index, node.path.clone(), node.is_text_file))
+        .collect();
+    if pending.is_empty() {
+        return None;
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for (index, path, is_text_by_extension) in pending {
+            let size = std::fs::metadata(&path).ok().map(|metadata| metadata.len());
+            let is_text_file = size.is_some_and(|size| size <= max_file_size)
+                && (is_text_by_extension || is_text_by_content(&path));
+
+            let update = MetadataUpdate {
+                index,
+                size,
+                is_text_file,
+            };
+            if sender.send(update).is_err() {
+                break; // The app has gone away
+            }
+        }
+    });
+    Some(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directory::traversal::DirectoryTraverser;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_deferred_metadata_pass() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("NOTES"), "plain text without an extension").unwrap();
+        fs::write(root.join("blob"), [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0]).unwrap();
+        fs::write(root.join("huge.rs"), "x".repeat(200)).unwrap();
+
+        let tree_of = |path: &str, tree: &DirectoryTree| tree.path_to_index[&root.join(path)];
+        let mut tree = DirectoryTraverser::new(true, false, 100, false)
+            .with_deferred_metadata(true)
+            .traverse(root)
+            .unwrap();
+
+        // Only the extension is known until the pass runs
+        let main = tree_of("main.rs", &tree);
+        assert!(tree.nodes[main].metadata_pending);
+        assert_eq!(tree.nodes[main].size, None);
+        assert!(tree.nodes[tree_of("huge.rs", &tree)].is_text_file);
+        assert!(!tree.nodes[tree_of("NOTES", &tree)].is_text_file);
+
+        let receiver = spawn_metadata_pass(&tree, 100).unwrap();
+        for update in receiver {
+            update.apply(&mut tree);
+        }
+
+        assert!(tree.nodes.iter().all(|node| !node.metadata_pending));
+        assert_eq!(tree.nodes[main].size, Some(12));
+        assert!(tree.nodes[tree_of("NOTES", &tree)].is_text_file);
+        assert!(!tree.nodes[tree_of("blob", &tree)].is_text_file);
+        assert!(!tree.nodes[tree_of("huge.rs", &tree)].is_text_file);
+    }
+}
@@ -1,4 +1,13 @@
+pub mod archive;
+pub mod dependencies;
+pub mod essentials;
+pub mod metadata;
+pub mod permissions;
+pub mod scan;
+pub mod sort;
+pub mod suggest;
 pub mod tree;
 pub mod traversal;
+pub mod watch;
 pub mod state;
 
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// Mode bits and ownership of a file, read during the scan for `--file-perms`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilePermissions {
+    pub mode: u32,
+    pub owner: String,
+    pub group: String,
+}
+
+impl fmt::Display for FilePermissions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags: String = "rwxrwxrwx"
+            .chars()
+            .enumerate()
+            .map(|(position, flag)| if self.mode & (0o400 >> position) != 0 { flag } else { '-' })
+            .collect();
+        write!(f, "{} ({:o}) {}:{}", flags, self.mode & 0o7777, self.owner, self.group)
+    }
+}
+
+/// Resolves user and group ids to names, reading the account databases once per scan
+#[derive(Debug, Default)]
+pub struct PermissionReader {
+    users: Option<HashMap<u32, String>>,
+    groups: Option<HashMap<u32, String>>,
+}
+
+impl PermissionReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(unix)]
+    pub fn read(&mut self, path: &Path) -> Option<FilePermissions> {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = std::fs::symlink_metadata(path).ok()?;
+        let users = self.users.get_or_insert_with(|| read_id_names("/etc/passwd"));
+        let owner = users.get(&metadata.uid()).cloned().unwrap_or_else(|| metadata.uid().to_string());
+        let groups = self.groups.get_or_insert_with(|| read_id_names("/etc/group"));
+        let group = groups.get(&metadata.gid()).cloned().unwrap_or_else(|| metadata.gid().to_string());
+
+        Some(FilePermissions {
+            mode: metadata.mode(),
+            owner,
+            group,
+        })
+    }
+
+    /// Windows has no mode bits or owner/group pair worth exporting
+    #[cfg(not(unix))]
+    pub fn read(&mut self, _path: &Path) -> Option<FilePermissions> {
+        None
+    }
+}
+
+/// `name:password:id:...` lines, as in `/etc/passwd` and `/etc/group`. Accounts only
+/// known to NSS services such as LDAP fall back to their numeric id.
+#[cfg(unix)]
+fn read_id_names(database: &str) -> HashMap<u32, String> {
+    std::fs::read_to_string(database)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((id, name.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_read_permissions() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("deploy.sh");
+        fs::write(&path, "#!/bin/sh").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+
+        let permissions = PermissionReader::new().read(&path).unwrap();
+        assert_eq!(permissions.mode & 0o777, 0o750);
+        assert!(!permissions.owner.is_empty() && !permissions.group.is_empty());
+        assert!(permissions.to_string().starts_with("rwxr-x--- (750) "));
+    }
+}
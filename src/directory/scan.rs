@@ -0,0 +1,90 @@
+use super::traversal::DirectoryTraverser;
+use super::tree::DirectoryTree;
+use anyhow::{Result, anyhow};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// A scan of a new root on a background thread, so the interface stays responsive while it
+/// runs. Dropping it cancels the scan; starting another in its place restarts it.
+#[derive(Debug)]
+pub struct BackgroundScan {
+    root: PathBuf,
+    cancel: Arc<AtomicBool>,
+    receiver: Receiver<Result<DirectoryTree>>,
+}
+
+impl BackgroundScan {
+    pub fn spawn(traverser: DirectoryTraverser, root: &Path) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let traverser = traverser.with_cancel(cancel.clone());
+        let (sender, receiver) = mpsc::channel();
+        let scan_root = root.to_path_buf();
+        thread::spawn(move || {
+            // Nobody is waiting for a cancelled scan
+            let _ = sender.send(traverser.traverse(&scan_root));
+        });
+        Self {
+            root: root.to_path_buf(),
+            cancel,
+            receiver,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The scanned tree once the scan is done, or why it failed
+    pub fn try_finish(&self) -> Option<Result<DirectoryTree>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(anyhow!("scan of {} stopped", self.root.display()))),
+        }
+    }
+}
+
+impl Drop for BackgroundScan {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+
+    fn wait(scan: &BackgroundScan) -> Result<DirectoryTree> {
+        let started = Instant::now();
+        loop {
+            if let Some(result) = scan.try_finish() {
+                return result;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10), "scan didn't finish");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_background_scan() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("main.rs"), "fn main() {}")?;
+        let traverser = || DirectoryTraverser::new(true, false, 1024, false);
+
+        let scan = BackgroundScan::spawn(traverser(), temp_dir.path());
+        assert_eq!(scan.root(), temp_dir.path());
+        assert_eq!(wait(&scan)?.nodes.len(), 2);
+
+        // Dropping a scan cancels it: the traverser sees the flag it was given
+        let scan = BackgroundScan::spawn(traverser(), temp_dir.path());
+        let cancel = scan.cancel.clone();
+        drop(scan);
+        assert!(cancel.load(Ordering::Relaxed));
+        Ok(())
+    }
+}
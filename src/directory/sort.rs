@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::ffi::OsStr;
+use std::path::Path;
+
+/// How entries are ordered in the tree, and so in the list and in exports
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// `file2.rs` before `file10.rs`, ignoring case and accents until names tie
+    #[default]
+    Natural,
+    /// Raw bytes of the name, the same on every platform and locale
+    Bytewise,
+}
+
+impl SortOrder {
+    pub fn compare(self, a: &OsStr, b: &OsStr) -> Ordering {
+        match self {
+            Self::Natural => natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()),
+            Self::Bytewise => a.as_encoded_bytes().cmp(b.as_encoded_bytes()),
+        }
+    }
+
+    /// Paths compared component by component, so a directory sorts before its contents
+    pub fn compare_paths(self, a: &Path, b: &Path) -> Ordering {
+        let mut a_components = a.components();
+        let mut b_components = b.components();
+        loop {
+            match (a_components.next(), b_components.next()) {
+                (Some(a), Some(b)) => {
+                    let ordering = self.compare(a.as_os_str(), b.as_os_str());
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                }
+                (a, b) => return a.is_some().cmp(&b.is_some()),
+            }
+        }
+    }
+}
+
+/// Numeric-aware, case-insensitive comparison. Runs of ASCII digits compare by value;
+/// other characters by their case fold with accents removed, then with accents, and names
+/// still equal (`Readme` and `README`, `file2` and `file02`) by their bytes.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    compare_units(&units(a, true), &units(b, true))
+        .then_with(|| compare_units(&units(a, false), &units(b, false)))
+        .then_with(|| a.as_bytes().cmp(b.as_bytes()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit<'a> {
+    Number(&'a str), // Digits without leading zeros
+    Char(char),
+}
+
+fn units(name: &str, strip_accents: bool) -> Vec<Unit<'_>> {
+    let mut units = Vec::new();
+    let mut rest = name;
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            let digits = rest[..end].trim_start_matches('0');
+            units.push(Unit::Number(digits));
+            rest = &rest[end..];
+            continue;
+        }
+        for folded in case_fold(c) {
+            let folded = if strip_accents { strip_accent(folded) } else { folded };
+            match folded {
+                'æ' => units.extend([Unit::Char('a'), Unit::Char('e')]),
+                'œ' => units.extend([Unit::Char('o'), Unit::Char('e')]),
+                _ => units.push(Unit::Char(folded)),
+            }
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    units
+}
+
+fn compare_units(a: &[Unit], b: &[Unit]) -> Ordering {
+    for (a, b) in a.iter().zip(b) {
+        let ordering = match (a, b) {
+            (Unit::Number(a), Unit::Number(b)) => a.len().cmp(&b.len()).then_with(|| a.cmp(b)),
+            // Numbers sort where their first digit would
+            (Unit::Number(_), Unit::Char(c)) => '0'.cmp(c),
+            (Unit::Char(c), Unit::Number(_)) => c.cmp(&'0'),
+            (Unit::Char(a), Unit::Char(b)) => a.cmp(b),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+/// Full case folding where it differs from lowercasing (`ß` is `ss`, final `ς` is `σ`)
+fn case_fold(c: char) -> impl Iterator<Item = char> {
+    let special: Option<&'static str> = match c {
+        'ß' | 'ẞ' => Some("ss"),
+        'ς' => Some("σ"),
+        'ſ' => Some("s"),
+        _ => None,
+    };
+    let lowercase = special.is_none().then(|| c.to_lowercase()).into_iter().flatten();
+    special.into_iter().flat_map(str::chars).chain(lowercase)
+}
+
+/// The base letter of a lowercase Latin letter with a diacritic
+fn strip_accent(c: char) -> char {
+    const ACCENTED: [(&str, char); 22] = [
+        ("àáâãäåāăą", 'a'),
+        ("çćĉċč", 'c'),
+        ("ďđ", 'd'),
+        ("èéêëēĕėęě", 'e'),
+        ("ĝğġģ", 'g'),
+        ("ĥħ", 'h'),
+        ("ìíîïĩīĭįı", 'i'),
+        ("ĵ", 'j'),
+        ("ķ", 'k'),
+        ("ĺļľŀł", 'l'),
+        ("ñńņňŉ", 'n'),
+        ("òóôõöøōŏő", 'o'),
+        ("ŕŗř", 'r'),
+        ("śŝşš", 's'),
+        ("ţťŧ", 't'),
+        ("ùúûüũūŭůűų", 'u'),
+        ("ŵ", 'w'),
+        ("ýÿŷ", 'y'),
+        ("źżž", 'z'),
+        ("ð", 'd'),
+        ("þ", 't'),
+        ("ǎ", 'a'),
+    ];
+    if c.is_ascii() {
+        return c;
+    }
+    ACCENTED
+        .iter()
+        .find(|(accented, _)| accented.contains(c))
+        .map_or(c, |&(_, base)| base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_natural_order() {
+        // Each pair sorts as written
+        let cases = [
+            ("file2.rs", "file10.rs"),     // Numbers by value
+            ("file9", "file09a"),          // Leading zeros don't change the value
+            ("file02", "file2"),           // Only the bytes tell these apart
+            ("v1.9.0", "v1.10.0"),         // Every digit run counts
+            ("a", "B"),                    // Case is ignored
+            ("apple", "Banana"),
+            ("README", "Readme"),          // Until names tie, then uppercase first
+            ("Straße", "strasse2"),        // ß folds to ss
+            ("σοφος", "ΣΟΦΟΣα"),           // So does final sigma
+            ("résumé.md", "resume2.md"),   // Accents are ignored
+            ("resume.md", "résumé.md"),    // Until names tie, then unaccented first
+            ("émile", "f"),                // é sorts with e, not after z
+            ("Æsir", "aesirs"),            // Ligatures expand
+            (".env", "a.rs"),              // Leading dots first
+            (".gitignore", "_build"),
+            ("_build", "main.rs"),
+            ("9lives", "apple"),           // Digits before letters
+            ("src", "src.rs"),             // A prefix sorts first
+            ("x100", "x1000"),
+            ("x18446744073709551616", "x18446744073709551617"), // Beyond u64
+        ];
+        for (a, b) in cases {
+            assert_eq!(natural_cmp(a, b), Ordering::Less, "{} < {}", a, b);
+            assert_eq!(natural_cmp(b, a), Ordering::Greater, "{} > {}", b, a);
+        }
+        assert_eq!(natural_cmp("same", "same"), Ordering::Equal);
+
+        // Bytewise keeps the raw order
+        let bytewise = |a: &str, b: &str| SortOrder::Bytewise.compare(OsStr::new(a), OsStr::new(b));
+        assert_eq!(bytewise("file10.rs", "file2.rs"), Ordering::Less);
+        assert_eq!(bytewise("B", "a"), Ordering::Less);
+
+        let compare_paths = |a: &str, b: &str| SortOrder::Natural.compare_paths(Path::new(a), Path::new(b));
+        assert_eq!(compare_paths("src", "src/a.rs"), Ordering::Less);
+        assert_eq!(compare_paths("src/b2/z.rs", "src/b10"), Ordering::Less);
+        // `src` is a prefix of `src-old`, whatever follows either
+        assert_eq!(compare_paths("src/a.rs", "src-old/a.rs"), Ordering::Less);
+    }
+}
@@ -1,25 +1,26 @@
-#[derive(Debug, Clone, Copy, PartialEq)]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum SelectionState {
     Included,
+    #[default]
     Excluded,
     Partial, // Some children included, some excluded
 }
 
-impl Default for SelectionState {
-    fn default() -> Self {
-        SelectionState::Excluded
-    }
-}
-
 impl SelectionState {
     pub fn is_included(&self) -> bool {
         matches!(self, SelectionState::Included | SelectionState::Partial)
     }
 
+    #[allow(dead_code)]
     pub fn is_excluded(&self) -> bool {
         matches!(self, SelectionState::Excluded)
     }
 
+    #[allow(dead_code)]
     pub fn is_partial(&self) -> bool {
         matches!(self, SelectionState::Partial)
     }
@@ -31,4 +32,14 @@ impl SelectionState {
             SelectionState::Partial => SelectionState::Included,
         }
     }
-}
\ No newline at end of file
+}
+
+impl fmt::Display for SelectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectionState::Included => write!(f, "included"),
+            SelectionState::Excluded => write!(f, "excluded"),
+            SelectionState::Partial => write!(f, "partial"),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use super::essentials::is_generated;
+use super::tree::DirectoryTree;
+use crate::output::tokens::estimate_tokens;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path};
+use std::time::SystemTime;
+
+/// Suggestions offered at most, cheapest wins first
+pub const SUGGESTION_LIMIT: usize = 20;
+
+/// Data files above this many tokens are rarely worth their place in a prompt
+const LARGE_DATA_TOKENS: u64 = 5_000;
+
+const DATA_EXTENSIONS: &[&str] = &["json", "csv", "tsv", "xml", "yaml", "yml", "ndjson", "jsonl"];
+
+/// Why a file is suggested, strongest reason first
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    Lockfile,
+    Snapshot,
+    Generated,
+    LargeData,
+    Stale(u64), // Days since the file was modified
+    Deep(usize), // Directories between the root and the file
+    Large,
+}
+
+impl Reason {
+    /// How much more a file's tokens weigh for this reason
+    fn weight(self) -> f64 {
+        match self {
+            Reason::Lockfile => 8.0,
+            Reason::Snapshot | Reason::Generated => 6.0,
+            Reason::LargeData => 4.0,
+            // A year untouched doubles a file's weight, and each level of nesting adds a half
+            Reason::Stale(days) => 1.0 + days as f64 / 365.0,
+            Reason::Deep(depth) => 1.0 + depth as f64 / 2.0,
+            Reason::Large => 1.0,
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Lockfile => write!(f, "lockfile"),
+            Reason::Snapshot => write!(f, "test snapshot"),
+            Reason::Generated => write!(f, "generated"),
+            Reason::LargeData => write!(f, "large data file"),
+            Reason::Stale(days) => write!(f, "unchanged for {} days", days),
+            Reason::Deep(depth) => write!(f, "{} directories deep", depth),
+            Reason::Large => write!(f, "large"),
+        }
+    }
+}
+
+/// An included file worth excluding to get under the token budget
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub index: usize,
+    pub tokens: u64,
+    pub reason: Reason,
+}
+
+/// The reason a file is a cheap win and its score: its tokens, weighted by the reason.
+/// Lockfiles, snapshots, generated code and big data files are almost never what a prompt
+/// needs; other files weigh more the longer they've gone unchanged and the deeper they are.
+pub fn rank(relative_path: &Path, tokens: u64, age_days: Option<u64>) -> (Reason, f64) {
+    let dirs: Vec<String> = relative_path
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().to_lowercase()),
+            _ => None,
+        })
+        .collect();
+    let name = relative_path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let extension = name.rsplit_once('.').map(|(_, extension)| extension).unwrap_or_default();
+
+    let is_lockfile = extension == "lock" || name.ends_with("-lock.json") || name.ends_with("-lock.yaml");
+    let is_snapshot = extension == "snap" || dirs.iter().any(|dir| dir == "__snapshots__" || dir == "snapshots");
+    let reason = if is_lockfile {
+        Reason::Lockfile
+    } else if is_snapshot {
+        Reason::Snapshot
+    } else if is_generated(&name) || dirs.iter().any(|dir| dir == "generated") {
+        Reason::Generated
+    } else if DATA_EXTENSIONS.contains(&extension) && tokens > LARGE_DATA_TOKENS {
+        Reason::LargeData
+    } else {
+        let stale = Reason::Stale(age_days.unwrap_or(0));
+        let deep = Reason::Deep(dirs.len());
+        match stale.weight().max(deep.weight()) {
+            weight if weight <= 1.0 => Reason::Large,
+            _ if stale.weight() >= deep.weight() => stale,
+            _ => deep,
+        }
+    };
+    (reason, tokens as f64 * reason.weight())
+}
+
+/// Included, unlocked text files in view, best candidates for exclusion first
+pub fn suggest_exclusions(tree: &DirectoryTree, limit: usize) -> Vec<Suggestion> {
+    let root_path = &tree.nodes[tree.root_index].path;
+    let now = SystemTime::now();
+    let mut ranked: Vec<(f64, Suggestion)> = tree
+        .get_all_included_files()
+        .into_iter()
+        .filter(|node| !node.locked)
+        .map(|node| {
+            let tokens = estimate_tokens(node.size.unwrap_or(0));
+            // Archive entries have no modification time of their own
+            let age_days = if tree.archive.is_some() {
+                None
+            } else {
+                fs::metadata(&node.path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|modified| now.duration_since(modified).ok())
+                    .map(|age| age.as_secs() / 86_400)
+            };
+            let relative_path = node.path.strip_prefix(root_path).unwrap_or(&node.path);
+            let (reason, score) = rank(relative_path, tokens, age_days);
+            let index = tree.path_to_index[&node.path];
+            (score, Suggestion { index, tokens, reason })
+        })
+        .collect();
+    ranked.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    ranked.into_iter().take(limit).map(|(_, suggestion)| suggestion).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directory::state::SelectionState;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_rank_reasons() {
+        let reason = |path: &str, tokens: u64, age_days: Option<u64>| rank(Path::new(path), tokens, age_days).0;
+        assert_eq!(reason("Cargo.lock", 100, None), Reason::Lockfile);
+        assert_eq!(reason("web/package-lock.json", 100, None), Reason::Lockfile);
+        assert_eq!(reason("pnpm-lock.yaml", 100, None), Reason::Lockfile);
+        assert_eq!(reason("src/__snapshots__/app.test.js.snap", 100, None), Reason::Snapshot);
+        assert_eq!(reason("tests/snapshots/render.txt", 100, None), Reason::Snapshot);
+        assert_eq!(reason("dist/app.min.js", 100, None), Reason::Generated);
+        assert_eq!(reason("src/generated/api.rs", 100, None), Reason::Generated);
+        assert_eq!(reason("fixtures/users.json", 10_000, None), Reason::LargeData);
+        // Small data files are judged like any other file
+        assert_eq!(reason("config.json", 100, None), Reason::Large);
+        assert_eq!(reason("src/main.rs", 100, Some(730)), Reason::Stale(730));
+        assert_eq!(reason("src/a/b/c/mod.rs", 100, Some(10)), Reason::Deep(4));
+        assert_eq!(reason("main.rs", 100, Some(0)), Reason::Large);
+
+        assert_eq!(Reason::Stale(30).to_string(), "unchanged for 30 days");
+        assert_eq!(Reason::Deep(3).to_string(), "3 directories deep");
+    }
+
+    #[test]
+    fn test_rank_order() {
+        let score = |path: &str, tokens: u64, age_days: Option<u64>| rank(Path::new(path), tokens, age_days).1;
+        // A lockfile beats a larger source file that's in active use
+        assert!(score("Cargo.lock", 2_000, None) > score("src/main.rs", 10_000, Some(1)));
+        // Among ordinary files, the same tokens weigh more when stale or deeply nested
+        assert!(score("src/old.rs", 1_000, Some(400)) > score("src/new.rs", 1_000, Some(2)));
+        assert!(score("src/a/b/c/d.rs", 1_000, Some(0)) > score("src/d.rs", 1_000, Some(0)));
+        // But tokens still dominate small differences
+        assert!(score("src/big.rs", 20_000, Some(0)) > score("src/a/small.rs", 1_000, Some(30)));
+        assert_eq!(score("empty.lock", 0, None), 0.0);
+    }
+
+    #[test]
+    fn test_suggest_exclusions() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("src"), true, &root);
+        for (path, size) in [("Cargo.lock", 8_000), ("src/main.rs", 20_000), ("src/lib.rs", 400), ("README.md", 4_000)] {
+            let parent = root.join(path).parent().unwrap().to_path_buf();
+            let index = tree.add_node(root.join(path), false, &parent).unwrap();
+            tree.nodes[index].is_text_file = true;
+            tree.nodes[index].size = Some(size);
+        }
+        tree.set_state(tree.root_index, SelectionState::Included);
+        let readme = tree.path_to_index[&root.join("README.md")];
+        tree.set_state(readme, SelectionState::Excluded);
+        let lib = tree.path_to_index[&root.join("src/lib.rs")];
+        tree.nodes[lib].locked = true;
+
+        // Excluded and locked files aren't offered; the lockfile outranks the bigger source
+        let suggestions = suggest_exclusions(&tree, SUGGESTION_LIMIT);
+        let paths: Vec<_> = suggestions.iter().map(|suggestion| tree.nodes[suggestion.index].name.as_str()).collect();
+        assert_eq!(paths, ["Cargo.lock", "main.rs"]);
+        assert_eq!(suggestions[0].tokens, 2_000);
+        assert_eq!(suggest_exclusions(&tree, 1).len(), 1);
+    }
+}
@@ -1,53 +1,211 @@
 use std::path::Path;
-use anyhow::Result;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use anyhow::{Result, bail};
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use ignore::WalkBuilder;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use super::archive::ArchiveSource;
+use super::permissions::PermissionReader;
+use super::sort::SortOrder;
 use super::tree::DirectoryTree;
 use super::state::SelectionState;
+use crate::event_log;
+use crate::git::TrackedFiles;
 
 pub struct DirectoryTraverser {
     respect_gitignore: bool,
+    ignore_files: bool, // `.ignore` files, which apply even when gitignores don't
     show_hidden: bool,
     max_file_size: u64,
     include_all: bool,
+    tracked_files: Option<TrackedFiles>,
+    defer_metadata: bool,
+    file_permissions: bool,
+    default_ignores: GlobSet,
+    ignore_patterns: Gitignore,
+    sort_order: SortOrder,
+    image_stubs: bool,
+    cancel: Option<Arc<AtomicBool>>, // Set from elsewhere to stop a scan part way
 }
 
 impl DirectoryTraverser {
     pub fn new(respect_gitignore: bool, show_hidden: bool, max_file_size: u64, include_all: bool) -> Self {
         Self {
             respect_gitignore,
+            ignore_files: true,
             show_hidden,
             max_file_size,
             include_all,
+            tracked_files: None,
+            defer_metadata: false,
+            file_permissions: false,
+            default_ignores: GlobSet::empty(),
+            ignore_patterns: Gitignore::empty(),
+            sort_order: SortOrder::default(),
+            image_stubs: false,
+            cancel: None,
         }
     }
 
+    /// Skip files and directories whose name matches one of these globs (e.g. `target`,
+    /// `*.lock`), independent of gitignore. Invalid patterns are returned, not applied.
+    pub fn with_default_ignores(mut self, patterns: &[String]) -> (Self, Vec<String>) {
+        let mut builder = GlobSetBuilder::new();
+        let mut invalid = Vec::new();
+        for pattern in patterns {
+            match Glob::new(pattern) {
+                Ok(glob) => {
+                    builder.add(glob);
+                }
+                Err(_) => invalid.push(pattern.clone()),
+            }
+        }
+        self.default_ignores = builder.build().unwrap_or_else(|_| GlobSet::empty());
+        (self, invalid)
+    }
+
+    /// Leave out paths matching these patterns (`--ignore`), written as lines of a
+    /// `.gitignore` at `root`: `build/`, `/docs/*.pdf`, `!keep.txt` and so on. They add
+    /// to the ignore files rather than override them. Invalid patterns are returned.
+    pub fn with_ignore_patterns(mut self, root: &Path, patterns: &[String]) -> (Self, Vec<String>) {
+        let mut builder = GitignoreBuilder::new(root);
+        let mut invalid = Vec::new();
+        for pattern in patterns {
+            if builder.add_line(None, pattern).is_err() {
+                invalid.push(pattern.clone());
+            }
+        }
+        self.ignore_patterns = builder.build().unwrap_or_else(|_| Gitignore::empty());
+        (self, invalid)
+    }
+
+    /// Honor `.ignore` files (and, with `respect_gitignore`, gitignores) while walking;
+    /// off for `--no-ignore`, which lists everything
+    pub fn with_ignore_files(mut self, ignore_files: bool) -> Self {
+        self.ignore_files = ignore_files;
+        self
+    }
+
+    /// Restrict the tree to files tracked by git (and the directories containing them)
+    pub fn with_tracked_files(mut self, tracked_files: Option<TrackedFiles>) -> Self {
+        self.tracked_files = tracked_files;
+        self
+    }
+
+    /// Skip stat calls and content sniffing during the walk, leaving them to
+    /// `spawn_metadata_pass`; the size limit is then enforced by that pass
+    pub fn with_deferred_metadata(mut self, defer_metadata: bool) -> Self {
+        self.defer_metadata = defer_metadata;
+        self
+    }
+
+    /// Record each file's mode bits, owner and group for the export headers (Unix only)
+    pub fn with_file_permissions(mut self, file_permissions: bool) -> Self {
+        self.file_permissions = file_permissions;
+        self
+    }
+
+    /// Export images as descriptions of their format, size and dimensions
+    pub fn with_image_stubs(mut self, image_stubs: bool) -> Self {
+        self.image_stubs = image_stubs;
+        self
+    }
+
+    /// Order of the entries in each directory, which the list and exports follow
+    pub fn with_sort_order(mut self, sort_order: SortOrder) -> Self {
+        self.sort_order = sort_order;
+        self
+    }
+
+    /// Give up with an error as soon as `cancel` is set
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
     pub fn traverse(&self, root_path: &Path) -> Result<DirectoryTree> {
-        let mut tree = DirectoryTree::new(root_path.to_path_buf());
+        event_log::info("scan.start", &[("root", &root_path.display())]);
+        let started = Instant::now();
+        let result = self.build_tree(root_path).map(|mut tree| {
+            tree.export_images = self.image_stubs;
+            tree
+        });
+        let elapsed_ms = started.elapsed().as_millis();
+        match &result {
+            Ok(tree) => {
+                let directories = tree.nodes.iter().filter(|node| node.is_directory).count();
+                event_log::info("scan.end", &[
+                    ("root", &root_path.display()),
+                    ("files", &(tree.nodes.len() - directories)),
+                    ("directories", &directories),
+                    ("ms", &elapsed_ms),
+                ]);
+            }
+            Err(e) => event_log::error("scan.error", &[("root", &root_path.display()), ("error", &format!("{:#}", e))]),
+        }
+        result
+    }
 
-        // Set initial state for root
+    fn build_tree(&self, root_path: &Path) -> Result<DirectoryTree> {
         let initial_state = if self.include_all {
             SelectionState::Included
         } else {
             SelectionState::Excluded
         };
+
+        // Archives are enumerated as a read-only virtual tree
+        if let Some(archive) = ArchiveSource::open(root_path) {
+            return archive.build_tree(initial_state, self.max_file_size, self.sort_order, |path| {
+                self.should_include_entry_by_path(path)
+            });
+        }
+
+        let mut tree = DirectoryTree::new(root_path.to_path_buf());
+
+        // Set initial state for root
         tree.set_state(tree.root_index, initial_state);
 
         let mut builder = WalkBuilder::new(root_path);
 
-        // Configure the walker based on our settings
+        // Configure the walker based on our settings. A .gitignore counts outside a git
+        // repository too: a project unpacked from a tarball still means it.
+        builder.require_git(false);
         if !self.respect_gitignore {
             builder.git_ignore(false)
                    .git_global(false)
                    .git_exclude(false);
         }
+        if !self.ignore_files {
+            builder.ignore(false).parents(false);
+        }
 
         // Configure hidden files visibility
         builder.hidden(!self.show_hidden);
 
+        let sort_order = self.sort_order;
+        builder.sort_by_file_name(move |a, b| sort_order.compare(a, b));
+
+        // Prune ignored directories instead of walking and discarding their contents
+        if !self.default_ignores.is_empty() || !self.ignore_patterns.is_empty() {
+            let default_ignores = self.default_ignores.clone();
+            let ignore_patterns = self.ignore_patterns.clone();
+            builder.filter_entry(move |entry| {
+                let is_directory = entry.file_type().is_some_and(|file_type| file_type.is_dir());
+                !default_ignores.is_match(entry.file_name())
+                    && !ignore_patterns.matched(entry.path(), is_directory).is_ignore()
+            });
+        }
+
         // Build the walker and iterate
         let walker = builder.build();
+        let mut permission_reader = PermissionReader::new();
 
         for result in walker {
+            if self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+                bail!("scan of {} cancelled", root_path.display());
+            }
             let entry = match result {
                 Ok(entry) => entry,
                 Err(_) => continue, // Skip entries we can't read
@@ -64,26 +222,45 @@ impl DirectoryTraverser {
                 continue;
             }
 
-            let is_directory = entry.file_type().map_or(false, |ft| ft.is_dir());
+            let is_directory = entry.file_type().is_some_and(|ft| ft.is_dir());
             let parent_path = path.parent().unwrap_or(root_path);
 
-            // Check file size before adding to tree
-            if !is_directory {
-                if let Ok(metadata) = std::fs::metadata(path) {
-                    if metadata.len() > self.max_file_size {
-                        // Skip files that are too large
-                        continue;
+            // Skip untracked and ignored entries when limited to git-tracked files
+            if let Some(tracked_files) = &self.tracked_files {
+                let relative_path = path.strip_prefix(root_path).unwrap_or(path);
+                if !tracked_files.contains(relative_path, is_directory) {
+                    continue;
+                }
+            }
+
+            if self.defer_metadata {
+                if let Some(node_index) = tree.add_node_deferred(path.to_path_buf(), is_directory, parent_path) {
+                    if self.file_permissions && !is_directory {
+                        tree.nodes[node_index].permissions = permission_reader.read(path);
                     }
+                    tree.set_state(node_index, initial_state);
                 }
+                continue;
+            }
+
+            // Check file size before adding to tree
+            if !is_directory
+                && let Ok(metadata) = std::fs::metadata(path)
+                && metadata.len() > self.max_file_size
+            {
+                // Skip files that are too large
+                continue;
             }
 
             if let Some(node_index) = tree.add_node(path.to_path_buf(), is_directory, parent_path) {
                 // Set file size for files
-                if !is_directory {
-                    if let Ok(metadata) = std::fs::metadata(path) {
-                        if let Some(node) = tree.get_node_mut(node_index) {
-                            node.size = Some(metadata.len());
-                        }
+                if !is_directory
+                    && let Ok(metadata) = std::fs::metadata(path)
+                    && let Some(node) = tree.get_node_mut(node_index)
+                {
+                    node.size = Some(metadata.len());
+                    if self.file_permissions {
+                        node.permissions = permission_reader.read(path);
                     }
                 }
 
@@ -97,21 +274,34 @@ impl DirectoryTraverser {
 
     fn should_include_entry_by_path(&self, path: &Path) -> bool {
         // Skip hidden files and directories unless show_hidden is enabled
-        if !self.show_hidden {
-            if let Some(name) = path.file_name() {
-                let name_str = name.to_string_lossy();
-                if name_str.starts_with('.') && name_str != "." && name_str != ".." {
-                    // Allow some common config files
-                    if !matches!(
-                        name_str.as_ref(),
-                        ".gitignore" | ".gitattributes" | ".editorconfig" | ".env" | ".env.example"
-                    ) {
-                        return false;
-                    }
+        if !self.show_hidden
+            && let Some(name) = path.file_name()
+        {
+            let name_str = name.to_string_lossy();
+            if name_str.starts_with('.') && name_str != "." && name_str != ".." {
+                // Allow some common config files
+                if !matches!(
+                    name_str.as_ref(),
+                    ".gitignore" | ".gitattributes" | ".editorconfig" | ".env" | ".env.example"
+                ) {
+                    return false;
                 }
             }
         }
 
+        // Archive entries aren't walked, so ignores are checked here as well
+        if let Some(name) = path.file_name()
+            && self.default_ignores.is_match(name)
+        {
+            return false;
+        }
+        if !self.ignore_patterns.is_empty()
+            && path.starts_with(self.ignore_patterns.path())
+            && self.ignore_patterns.matched_path_or_any_parents(path, false).is_ignore()
+        {
+            return false;
+        }
+
         // Note: gitignore filtering is now handled by the ignore crate's WalkBuilder
         // File size filtering is handled in the main loop
 
@@ -144,4 +334,138 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_cancel() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("main.rs"), "fn main() {}")?;
+        let cancel = Arc::new(AtomicBool::new(false));
+        let traverser = DirectoryTraverser::new(true, false, 1024, false).with_cancel(cancel.clone());
+        assert_eq!(traverser.traverse(temp_dir.path())?.nodes.len(), 2);
+
+        cancel.store(true, Ordering::Relaxed);
+        let error = traverser.traverse(temp_dir.path()).unwrap_err();
+        assert!(error.to_string().ends_with("cancelled"), "{}", error);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_permissions() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path();
+        fs::write(root_path.join("main.rs"), "fn main() {}")?;
+
+        for defer_metadata in [false, true] {
+            let tree = DirectoryTraverser::new(true, false, 1024, false)
+                .with_deferred_metadata(defer_metadata)
+                .with_file_permissions(true)
+                .traverse(root_path)?;
+            let node = &tree.nodes[tree.path_to_index[&root_path.join("main.rs")]];
+            assert!(node.permissions.is_some());
+        }
+
+        let tree = DirectoryTraverser::new(true, false, 1024, false).traverse(root_path)?;
+        assert!(tree.nodes.iter().all(|node| node.permissions.is_none()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_ignores() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path();
+        fs::create_dir_all(root_path.join("node_modules").join("left-pad"))?;
+        fs::write(root_path.join("node_modules").join("left-pad").join("index.js"), "")?;
+        fs::write(root_path.join("Cargo.lock"), "")?;
+        fs::write(root_path.join("main.rs"), "fn main() {}")?;
+
+        let patterns: Vec<String> = crate::constants::DEFAULT_IGNORES.iter().map(|name| name.to_string()).collect();
+        let (traverser, invalid) = DirectoryTraverser::new(true, false, 1024, false).with_default_ignores(&patterns);
+        assert!(invalid.is_empty());
+        let tree = traverser.traverse(root_path)?;
+        assert!(tree.path_to_index.contains_key(&root_path.join("main.rs")));
+        assert!(!tree.path_to_index.contains_key(&root_path.join("Cargo.lock")));
+        assert!(tree.nodes.iter().all(|node| !node.path.starts_with(root_path.join("node_modules"))));
+
+        // Without the defaults (`--no-default-ignores`) everything is listed
+        let tree = DirectoryTraverser::new(true, false, 1024, false).traverse(root_path)?;
+        assert!(tree.path_to_index.contains_key(&root_path.join("node_modules").join("left-pad").join("index.js")));
+
+        let (_, invalid) = DirectoryTraverser::new(true, false, 1024, false).with_default_ignores(&["a[".to_string()]);
+        assert_eq!(invalid, vec!["a[".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gitignore_and_ignore_patterns() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path();
+        fs::create_dir_all(root_path.join(".git"))?;
+        fs::write(root_path.join(".git").join("HEAD"), "ref: refs/heads/main")?;
+        fs::write(root_path.join(".gitignore"), "target/\n*.log\n!keep.log\n")?;
+        fs::write(root_path.join(".ignore"), "scratch/\n")?;
+        fs::create_dir_all(root_path.join("scratch"))?;
+        fs::write(root_path.join("scratch").join("notes.md"), "text")?;
+        fs::create_dir_all(root_path.join("target").join("debug"))?;
+        fs::write(root_path.join("target").join("debug").join("app"), "binary")?;
+        fs::create_dir_all(root_path.join("web").join("dist"))?;
+        fs::write(root_path.join("web").join(".gitignore"), "dist/\n")?;
+        fs::write(root_path.join("web").join("dist").join("bundle.js"), "text")?;
+        fs::write(root_path.join("web").join("app.js"), "text")?;
+        for name in ["main.rs", "debug.log", "keep.log", "notes.tmp"] {
+            fs::write(root_path.join(name), "text")?;
+        }
+        let listed = |tree: &DirectoryTree, path: &str| tree.path_to_index.contains_key(&root_path.join(path));
+
+        // Root and nested .gitignore files apply, negations included, and .git stays hidden
+        let tree = DirectoryTraverser::new(true, false, 1024, false).traverse(root_path)?;
+        assert!(listed(&tree, "main.rs") && listed(&tree, "keep.log") && listed(&tree, "web/app.js"));
+        assert!(!listed(&tree, "target") && !listed(&tree, "debug.log") && !listed(&tree, "web/dist"));
+        assert!(!listed(&tree, ".git") && !listed(&tree, "scratch"));
+        let counts = tree.file_counts(tree.root_index);
+        assert_eq!(counts.total_files, 4); // main.rs, keep.log, notes.tmp, web/app.js
+
+        // `--no-gitignore` lists what gitignores hide, `--no-ignore` what .ignore hides too
+        let tree = DirectoryTraverser::new(false, false, 1024, false).traverse(root_path)?;
+        assert!(listed(&tree, "target/debug/app") && listed(&tree, "debug.log") && listed(&tree, "web/dist/bundle.js"));
+        assert!(!listed(&tree, "scratch"));
+        let tree = DirectoryTraverser::new(false, false, 1024, false).with_ignore_files(false).traverse(root_path)?;
+        assert!(listed(&tree, "scratch/notes.md") && listed(&tree, "target/debug/app"));
+
+        // Outside a git repository .gitignore still applies
+        fs::remove_dir_all(root_path.join(".git"))?;
+        let tree = DirectoryTraverser::new(true, false, 1024, false).traverse(root_path)?;
+        assert!(!listed(&tree, "target") && !listed(&tree, "web/dist") && listed(&tree, "keep.log"));
+
+        // `--ignore` leaves out more, in gitignore syntax
+        let patterns = ["*.tmp", "web/", "!web/app.js", "[z-a]"].map(str::to_string);
+        let (traverser, invalid) = DirectoryTraverser::new(true, false, 1024, false).with_ignore_patterns(root_path, &patterns);
+        assert_eq!(invalid, ["[z-a]"]);
+        let tree = traverser.traverse(root_path)?;
+        assert!(!listed(&tree, "notes.tmp") && !listed(&tree, "web"));
+        assert!(listed(&tree, "main.rs") && listed(&tree, "keep.log"));
+        assert_eq!(tree.file_counts(tree.root_index).total_files, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_order() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path();
+        for name in ["file10.rs", "file2.rs", "B.rs", "a.rs"] {
+            fs::write(root_path.join(name), "")?;
+        }
+
+        let names = |sort_order: SortOrder| -> Result<Vec<String>> {
+            let tree = DirectoryTraverser::new(true, false, 1024, false).with_sort_order(sort_order).traverse(root_path)?;
+            let root = &tree.nodes[tree.root_index];
+            Ok(root.children.iter().map(|&child| tree.nodes[child].name.clone()).collect())
+        };
+        assert_eq!(names(SortOrder::Natural)?, ["a.rs", "B.rs", "file2.rs", "file10.rs"]);
+        assert_eq!(names(SortOrder::Bytewise)?, ["B.rs", "a.rs", "file10.rs", "file2.rs"]);
+        Ok(())
+    }
+}
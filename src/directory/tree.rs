@@ -1,8 +1,12 @@
+use super::archive::ArchiveSource;
+use super::permissions::FilePermissions;
 use super::state::SelectionState;
+use crate::output::image::is_image;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::fs;
 use std::io::Read;
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct FileNode {
@@ -14,6 +18,10 @@ pub struct FileNode {
     pub parent: Option<usize>,
     pub state: SelectionState,
     pub is_text_file: bool,
+    pub locked: bool, // Exempt from bulk operations, still toggleable on its own
+    pub sticky: bool, // Directory included as a whole; children found by a rescan are included too
+    pub metadata_pending: bool, // Size and text detection not collected yet
+    pub permissions: Option<FilePermissions>, // Only read with `--file-perms`
 }
 
 impl FileNode {
@@ -33,6 +41,10 @@ impl FileNode {
             parent,
             state: SelectionState::default(),
             is_text_file: false,
+            locked: false,
+            sticky: false,
+            metadata_pending: false,
+            permissions: None,
         }
     }
 
@@ -41,11 +53,26 @@ impl FileNode {
     }
 }
 
+/// File counts under a node. Only files are counted, never directories, and whether a
+/// file counts as included depends on its own state alone: a directory's state (and so
+/// whether it's partial) is derived from its files and doesn't add anything.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FileCounts {
+    pub total_files: usize,    // Text files, the ones an export can carry
+    pub included_files: usize,
+    pub included_size: u64,    // Known sizes only; pending metadata counts as zero
+}
+
 #[derive(Debug)]
 pub struct DirectoryTree {
     pub nodes: Vec<FileNode>,
-    pub root_index: usize,
+    pub root_index: usize,      // Root of the current view; exports and stats are scoped to it
+    pub scan_root_index: usize, // The directory (or archive) that was scanned
     pub path_to_index: HashMap<PathBuf, usize>,
+    pub archive: Option<Arc<ArchiveSource>>, // Set when the root is an archive rather than a directory
+    pub manual_order: Vec<usize>, // Files placed by hand, exported in this order before the rest
+    pub default_states: Vec<SelectionState>, // Each node's state before any saved selection, by index
+    pub export_images: bool, // Included images are exported as descriptions (`image_stubs`)
 }
 
 impl DirectoryTree {
@@ -60,15 +87,86 @@ impl DirectoryTree {
         Self {
             nodes,
             root_index: 0,
+            scan_root_index: 0,
             path_to_index,
+            archive: None,
+            manual_order: Vec::new(),
+            default_states: Vec::new(),
+            export_images: false,
+        }
+    }
+
+    /// Path of the scanned root, which rules and saved sessions are relative to
+    pub fn scan_root_path(&self) -> &Path {
+        &self.nodes[self.scan_root_index].path
+    }
+
+    /// Whether a node lies within the current view root (including the root itself)
+    pub fn is_in_view(&self, index: usize) -> bool {
+        self.root_index == self.scan_root_index
+            || self.nodes.get(index).is_some_and(|node| node.path.starts_with(&self.nodes[self.root_index].path))
+    }
+
+    /// Read a file's contents from disk, or from the archive the tree was built from
+    pub fn read_file(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        match &self.archive {
+            Some(archive) => archive.read(path).map_err(std::io::Error::other),
+            None => fs::read(path),
+        }
+    }
+
+    /// Read at most the first `limit` bytes of a file
+    pub fn read_file_head(&self, path: &Path, limit: u64) -> std::io::Result<Vec<u8>> {
+        match &self.archive {
+            Some(archive) => archive.read_head(path, limit).map_err(std::io::Error::other),
+            None => {
+                let mut head = Vec::new();
+                fs::File::open(path)?.take(limit).read_to_end(&mut head)?;
+                Ok(head)
+            }
+        }
+    }
+
+    /// Read files that are about to be read one after another in one go, where that's
+    /// cheaper: an archive is walked once rather than once per file
+    pub fn prefetch_files(&self, paths: &[&Path]) -> std::io::Result<()> {
+        match &self.archive {
+            Some(archive) => archive.prefetch(paths).map_err(std::io::Error::other),
+            None => Ok(()),
         }
     }
 
+    pub fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        String::from_utf8(self.read_file(path)?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
     pub fn add_node(
         &mut self,
         path: PathBuf,
         is_directory: bool,
         parent_path: &Path,
+    ) -> Option<usize> {
+        self.insert_node(path, is_directory, parent_path, false)
+    }
+
+    /// Like `add_node`, but without opening the file: text detection goes by extension
+    /// until the background metadata pass fills in size and content checks
+    pub fn add_node_deferred(
+        &mut self,
+        path: PathBuf,
+        is_directory: bool,
+        parent_path: &Path,
+    ) -> Option<usize> {
+        self.insert_node(path, is_directory, parent_path, true)
+    }
+
+    fn insert_node(
+        &mut self,
+        path: PathBuf,
+        is_directory: bool,
+        parent_path: &Path,
+        defer_metadata: bool,
     ) -> Option<usize> {
         if self.path_to_index.contains_key(&path) {
             return self.path_to_index.get(&path).copied();
@@ -80,7 +178,10 @@ impl DirectoryTree {
         let mut node = FileNode::new(path.clone(), is_directory, Some(parent_index));
 
         // Determine if it's a text file
-        if !is_directory {
+        if !is_directory && defer_metadata {
+            node.is_text_file = is_text_by_extension(&path);
+            node.metadata_pending = true;
+        } else if !is_directory {
             node.is_text_file = is_text_file(&path);
         }
 
@@ -105,14 +206,26 @@ impl DirectoryTree {
         let parent_index = {
             if let Some(node) = self.nodes.get_mut(index) {
                 node.state = state;
+                node.sticky = node.is_directory && state == SelectionState::Included;
                 node.parent
             } else {
                 return;
             }
         };
 
-        // Propagate state to children
-        self.propagate_to_children(index, state);
+        // Propagate state to children; locked descendants keep theirs
+        if self.propagate_to_children(index, state) {
+            self.recompute_from_children(index);
+            let node = &mut self.nodes[index];
+            node.sticky &= node.state == SelectionState::Included;
+        }
+
+        // Changing something inside a directory means it's hand-tuned, no longer included as a whole
+        let mut ancestor = parent_index;
+        while let Some(ancestor_index) = ancestor {
+            self.nodes[ancestor_index].sticky = false;
+            ancestor = self.nodes[ancestor_index].parent;
+        }
 
         // Update parent state based on children
         if let Some(parent_index) = parent_index {
@@ -120,21 +233,95 @@ impl DirectoryTree {
         }
     }
 
-    fn propagate_to_children(&mut self, parent_index: usize, state: SelectionState) {
+    /// Derive every directory's state from its children, deepest first, once leaf states
+    /// have been set directly. A node is always added after its parent, so a reverse walk
+    /// reaches children before the directories holding them.
+    pub fn recompute_directories(&mut self) {
+        for index in (0..self.nodes.len()).rev() {
+            if self.nodes[index].is_directory {
+                self.recompute_from_children(index);
+            }
+        }
+    }
+
+    /// `set_state` as part of a bulk operation (select all, rules, ...): locked nodes are left alone
+    pub fn set_state_bulk(&mut self, index: usize, state: SelectionState) {
+        if self.nodes.get(index).is_some_and(|node| !node.locked) {
+            self.set_state(index, state);
+        }
+    }
+
+    /// Give every direct sibling the node's own state, skipping locked siblings and, unless
+    /// `include_directories` is set, sibling directories. Returns how many changed.
+    pub fn match_siblings(&mut self, index: usize, include_directories: bool) -> usize {
+        let Some(node) = self.nodes.get(index) else {
+            return 0;
+        };
+        let (Some(parent_index), state) = (node.parent, node.state) else {
+            return 0;
+        };
         if state == SelectionState::Partial {
-            return; // Don't propagate partial state
+            return 0; // Only a directory can be partial, and only because of its children
         }
 
+        let mut changed = 0;
+        for sibling_index in self.nodes[parent_index].children.clone() {
+            let sibling = &self.nodes[sibling_index];
+            if sibling_index == index
+                || sibling.locked
+                || sibling.state == state
+                || (sibling.is_directory && !include_directories)
+            {
+                continue;
+            }
+            self.set_state(sibling_index, state);
+            changed += 1;
+        }
+        changed
+    }
+
+    pub fn toggle_lock(&mut self, index: usize) {
+        if let Some(node) = self.nodes.get_mut(index) {
+            node.locked = !node.locked;
+        }
+    }
+
+    /// Returns whether any locked descendant was skipped
+    fn propagate_to_children(&mut self, parent_index: usize, state: SelectionState) -> bool {
+        if state == SelectionState::Partial {
+            return false; // Don't propagate partial state
+        }
+
+        let mut skipped_locked = false;
         let children: Vec<usize> = self.nodes[parent_index].children.clone();
         for child_index in children {
             if let Some(child) = self.nodes.get_mut(child_index) {
+                if child.locked {
+                    skipped_locked = true;
+                    continue;
+                }
                 child.state = state;
+                child.sticky = false; // Covered by the directory being set
+            }
+            if self.propagate_to_children(child_index, state) {
+                self.recompute_from_children(child_index);
+                skipped_locked = true;
             }
-            self.propagate_to_children(child_index, state);
         }
+        skipped_locked
     }
 
     fn update_parent_state(&mut self, parent_index: usize) {
+        self.recompute_from_children(parent_index);
+
+        // Recursively update grandparent
+        if let Some(grandparent_index) = self.nodes[parent_index].parent {
+            self.update_parent_state(grandparent_index);
+        }
+    }
+
+    /// Derive a directory's state from its direct children
+    fn recompute_from_children(&mut self, parent_index: usize) {
         let children: Vec<usize> = self.nodes[parent_index].children.clone();
 
         if children.is_empty() {
@@ -166,29 +353,196 @@ impl DirectoryTree {
         if let Some(parent) = self.nodes.get_mut(parent_index) {
             parent.state = new_state;
         }
+    }
 
-        // Recursively update grandparent
-        if let Some(grandparent_index) = self.nodes[parent_index].parent {
-            self.update_parent_state(grandparent_index);
-        }
+    /// Toggle a node as Enter does, returning the state it was given
+    pub fn toggle_state(&mut self, index: usize) -> Option<SelectionState> {
+        let new_state = self.toggle_target(index)?;
+        self.set_state(index, new_state);
+        Some(new_state)
     }
 
-    pub fn toggle_state(&mut self, index: usize) {
-        if let Some(node) = self.nodes.get(index) {
-            let new_state = node.state.toggle();
-            self.set_state(index, new_state);
+    /// The state a toggle gives a node. Included and Excluded swap, and a Partial directory
+    /// becomes Included, or Excluded if everything it can change is included already.
+    /// Locked descendants keep their state, so a directory can stay Partial after a toggle;
+    /// this keeps presses alternating between included and excluded instead of sticking.
+    pub fn toggle_target(&self, index: usize) -> Option<SelectionState> {
+        let node = self.nodes.get(index)?;
+        if node.state != SelectionState::Partial {
+            return Some(node.state.toggle());
         }
+        let mut stack = node.children.clone();
+        while let Some(index) = stack.pop() {
+            let child = &self.nodes[index];
+            if child.locked {
+                continue;
+            }
+            if !child.children.is_empty() {
+                stack.extend(&child.children);
+            } else if child.state != SelectionState::Included {
+                return Some(SelectionState::Included);
+            }
+        }
+        Some(SelectionState::Excluded)
     }
 
+    /// The files an export carries, in export order: those placed by hand first, in
+    /// their manual order, then the rest in tree order
     pub fn get_all_included_files(&self) -> Vec<&FileNode> {
         let mut included_files = Vec::new();
         self.collect_included_files(self.root_index, &mut included_files);
+        if !self.manual_order.is_empty() {
+            let rank: HashMap<&Path, usize> = self
+                .manual_order
+                .iter()
+                .enumerate()
+                .filter_map(|(rank, &index)| Some((self.nodes.get(index)?.path.as_path(), rank)))
+                .collect();
+            included_files.sort_by_key(|node| rank.get(node.path.as_path()).copied().unwrap_or(usize::MAX));
+        }
         included_files
     }
 
+    /// Move an included file one place earlier (`delta` < 0) or later in the export order.
+    /// A file moved up is placed by hand, after the files already placed; one moved down
+    /// past them returns to tree order. Returns whether anything moved.
+    pub fn move_in_export_order(&mut self, index: usize, delta: isize) -> bool {
+        let before: Vec<usize> =
+            self.get_all_included_files().iter().map(|node| self.path_to_index[&node.path]).collect();
+        let manual_order = self.manual_order.clone();
+        let Some(position) = before.iter().position(|&file| file == index) else {
+            return false;
+        };
+        // Only the placed files that are exported count; others keep their slot for later
+        let placed: Vec<usize> = before.iter().copied().filter(|file| self.manual_order.contains(file)).collect();
+        let slot = |tree: &Self, file: usize| tree.manual_order.iter().position(|&placed| placed == file);
+
+        match (placed.iter().position(|&file| file == index), delta < 0) {
+            (Some(0), true) => return false,
+            (Some(rank), true) => {
+                let (a, b) = (slot(self, index).unwrap(), slot(self, placed[rank - 1]).unwrap());
+                self.manual_order.swap(a, b);
+            }
+            (Some(rank), false) if rank + 1 < placed.len() => {
+                let (a, b) = (slot(self, index).unwrap(), slot(self, placed[rank + 1]).unwrap());
+                self.manual_order.swap(a, b);
+            }
+            (Some(_), false) => self.manual_order.retain(|&file| file != index),
+            (None, true) => {
+                let after = placed.last().and_then(|&last| slot(self, last)).map_or(0, |slot| slot + 1);
+                self.manual_order.insert(after, index);
+                // Already first after the placed files: placing it alone wouldn't move it
+                if position == placed.len() && let Some(&last) = placed.last() {
+                    let (a, b) = (slot(self, index).unwrap(), slot(self, last).unwrap());
+                    self.manual_order.swap(a, b);
+                }
+            }
+            (None, false) => return false,
+        }
+        self.manual_order != manual_order
+    }
+
+    /// The manual order as paths relative to the scanned root, for saving in the session
+    pub fn manual_order_paths(&self) -> Vec<String> {
+        let root_path = self.scan_root_path();
+        self.manual_order
+            .iter()
+            .filter_map(|&index| self.nodes.get(index))
+            .map(|node| node.path.strip_prefix(root_path).unwrap_or(&node.path).to_string_lossy().to_string())
+            .collect()
+    }
+
+    /// Remember the current selection as the default one, which profiles record changes from
+    pub fn record_default_selection(&mut self) {
+        self.default_states = self.nodes.iter().map(|node| node.state).collect();
+    }
+
+    /// A node's state in the default selection; nodes it doesn't know of are excluded
+    pub fn default_state(&self, index: usize) -> SelectionState {
+        self.default_states.get(index).copied().unwrap_or_default()
+    }
+
+    /// Put every unlocked file back in its default state, and directories with it
+    pub fn restore_default_selection(&mut self) {
+        for index in 0..self.nodes.len() {
+            let default_state = self.default_state(index);
+            let node = &mut self.nodes[index];
+            if !node.locked && node.children.is_empty() {
+                node.state = default_state;
+            }
+        }
+        self.recompute_directories();
+    }
+
+    /// Adopt a saved manual order, skipping paths the scan no longer has
+    pub fn restore_manual_order(&mut self, relative_paths: &[String]) {
+        let root_path = self.scan_root_path().to_path_buf();
+        self.manual_order = relative_paths
+            .iter()
+            .filter_map(|relative_path| self.path_to_index.get(&root_path.join(relative_path)).copied())
+            .collect();
+    }
+
+    /// Counts over the files under `index` (the node itself if it's a file). Everything
+    /// reporting file counts goes through here, so the status bar, the stats and the node
+    /// info can't disagree.
+    pub fn file_counts(&self, index: usize) -> FileCounts {
+        let mut counts = FileCounts::default();
+        let mut stack = vec![index];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.nodes.get(index) else {
+                continue;
+            };
+            if node.is_directory {
+                stack.extend(&node.children);
+                continue;
+            }
+            if !self.is_exportable(node) {
+                continue;
+            }
+            counts.total_files += 1;
+            if self.is_exported(node) {
+                counts.included_files += 1;
+                counts.included_size += node.size.unwrap_or(0);
+            }
+        }
+        counts
+    }
+
+    /// Each directory's size, the sum of the sizes of every file under it, by node index.
+    /// `None` while any of those files is waiting for its metadata.
+    pub fn directory_sizes(&self) -> Vec<Option<u64>> {
+        let mut sizes: Vec<Option<u64>> = self
+            .nodes
+            .iter()
+            .map(|node| match node.is_directory {
+                true => Some(0),
+                false if node.metadata_pending => None,
+                false => Some(node.size.unwrap_or(0)),
+            })
+            .collect();
+        // A node always comes after its parent, so a reverse walk finishes each directory first
+        for index in (0..self.nodes.len()).rev() {
+            if let Some(parent) = self.nodes[index].parent {
+                sizes[parent] = sizes[parent].zip(sizes[index]).map(|(total, size)| total + size);
+            }
+        }
+        sizes
+    }
+
+    /// A file an export carries; `file_counts` and `get_all_included_files` must agree on it
+    fn is_exported(&self, node: &FileNode) -> bool {
+        self.is_exportable(node) && node.state.is_included()
+    }
+
+    /// A file an export would carry if included: text, or an image it can describe
+    pub fn is_exportable(&self, node: &FileNode) -> bool {
+        !node.is_directory && (node.is_text_file || self.export_images && is_image(&node.path))
+    }
+
     fn collect_included_files<'a>(&'a self, index: usize, included_files: &mut Vec<&'a FileNode>) {
         if let Some(node) = self.nodes.get(index) {
-            if node.state.is_included() && !node.is_directory && node.is_text_file {
+            if self.is_exported(node) {
                 included_files.push(node);
             }
 
@@ -200,6 +554,7 @@ impl DirectoryTree {
     }
 }
 
+
 fn is_text_file(path: &Path) -> bool {
     // Quick extension-based check for common text file extensions
     if is_text_by_extension(path) {
@@ -211,7 +566,7 @@ fn is_text_file(path: &Path) -> bool {
     is_text_by_content(path)
 }
 
-fn is_text_by_extension(path: &Path) -> bool {
+pub fn is_text_by_extension(path: &Path) -> bool {
     if let Some(extension) = path.extension() {
         let ext = extension.to_string_lossy().to_lowercase();
         matches!(
@@ -261,7 +616,7 @@ fn is_text_by_extension(path: &Path) -> bool {
     }
 }
 
-fn is_text_by_content(path: &Path) -> bool {
+pub fn is_text_by_content(path: &Path) -> bool {
     // Read first few KB to determine if file is text or binary
     const SAMPLE_SIZE: usize = 8192; // 8KB sample
 
@@ -270,20 +625,8 @@ fn is_text_by_content(path: &Path) -> bool {
             let mut buffer = vec![0; SAMPLE_SIZE];
             match file.read(&mut buffer) {
                 Ok(bytes_read) => {
-                    if bytes_read == 0 {
-                        return false; // Empty file, treat as non-text
-                    }
-
                     buffer.truncate(bytes_read);
-
-                    // First try infer crate for magic number detection
-                    if let Some(_kind) = infer::get(&buffer) {
-                        // If infer detects it as a known binary type, it's not text
-                        return false;
-                    }
-
-                    // If infer doesn't detect it, use heuristic to check if it's text
-                    is_likely_text(&buffer)
+                    is_text_sample(&buffer)
                 }
                 Err(_) => false, // Can't read file
             }
@@ -292,6 +635,22 @@ fn is_text_by_content(path: &Path) -> bool {
     }
 }
 
+/// Decide from the first few KB of a file whether it is text
+pub fn is_text_sample(buffer: &[u8]) -> bool {
+    if buffer.is_empty() {
+        return false; // Empty file, treat as non-text
+    }
+
+    // First try infer crate for magic number detection
+    if let Some(_kind) = infer::get(buffer) {
+        // If infer detects it as a known binary type, it's not text
+        return false;
+    }
+
+    // If infer doesn't detect it, use heuristic to check if it's text
+    is_likely_text(buffer)
+}
+
 fn is_likely_text(buffer: &[u8]) -> bool {
     // Check for null bytes (strong indicator of binary content)
     if buffer.contains(&0) {
@@ -306,7 +665,7 @@ fn is_likely_text(buffer: &[u8]) -> bool {
         let byte = buffer[i];
 
         // ASCII printable characters and common whitespace
-        if (byte >= 32 && byte <= 126) || byte == b'\n' || byte == b'\r' || byte == b'\t' {
+        if (32..=126).contains(&byte) || byte == b'\n' || byte == b'\r' || byte == b'\t' {
             printable_count += 1;
             i += 1;
         }
@@ -351,3 +710,196 @@ fn get_utf8_char_length(first_byte: u8) -> Option<usize> {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// xorshift64: reproducible random numbers without a dependency
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+    }
+
+    /// A few directories deep, with a binary file and an empty directory among them
+    fn sample_tree() -> DirectoryTree {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        for directory in ["src", "src/ui", "src/ui/widgets", "docs", "empty"] {
+            let path = root.join(directory);
+            tree.add_node(path.clone(), true, path.parent().unwrap());
+        }
+        for (index, file) in ["a.rs", "src/b.rs", "src/c.rs", "src/ui/d.rs", "src/ui/e.png", "src/ui/widgets/f.rs", "docs/g.md", "docs/h.md"]
+            .iter()
+            .enumerate()
+        {
+            let path = root.join(file);
+            let node_index = tree.add_node_deferred(path.clone(), false, path.parent().unwrap()).unwrap();
+            tree.nodes[node_index].size = Some(index as u64 + 1);
+        }
+        tree
+    }
+
+    /// Recount from the leaves alone, ignoring every directory's state
+    fn brute_force(tree: &DirectoryTree) -> FileCounts {
+        let mut counts = FileCounts::default();
+        for (index, node) in tree.nodes.iter().enumerate() {
+            if node.is_directory || !node.is_text_file || !tree.is_in_view(index) {
+                continue;
+            }
+            counts.total_files += 1;
+            if node.state == SelectionState::Included {
+                counts.included_files += 1;
+                counts.included_size += node.size.unwrap_or(0);
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn test_file_counts_match_recount() {
+        for seed in 1..=200 {
+            let mut rng = Rng(seed);
+            let mut tree = sample_tree();
+            for step in 0..40 {
+                let index = rng.below(tree.nodes.len());
+                match rng.below(6) {
+                    0 | 1 => {
+                        tree.toggle_state(index);
+                    }
+                    2 => tree.set_state_bulk(index, SelectionState::Included),
+                    3 => tree.set_state_bulk(index, SelectionState::Excluded),
+                    4 => tree.toggle_lock(index),
+                    _ => {
+                        tree.match_siblings(index, rng.below(2) == 0);
+                    }
+                }
+                if rng.below(8) == 0 {
+                    tree.root_index = if tree.nodes[index].is_directory { index } else { tree.scan_root_index };
+                }
+
+                let counts = tree.file_counts(tree.root_index);
+                assert!(counts.included_files <= counts.total_files, "seed {} step {}", seed, step);
+                assert_eq!(counts, brute_force(&tree), "seed {} step {}", seed, step);
+                assert_eq!(counts.included_files, tree.get_all_included_files().len(), "seed {} step {}", seed, step);
+                assert!(tree.nodes.iter().all(|node| node.is_directory || node.state != SelectionState::Partial));
+            }
+        }
+    }
+
+    #[test]
+    fn test_file_counts_ignore_directories() {
+        let mut tree = sample_tree();
+        let root = tree.scan_root_path().to_path_buf();
+        let empty = tree.path_to_index[&root.join("empty")];
+        tree.set_state(empty, SelectionState::Included);
+        assert_eq!(tree.file_counts(tree.root_index).included_files, 0);
+
+        // A partial directory counts exactly its included files
+        let ui = tree.path_to_index[&root.join("src/ui")];
+        tree.toggle_state(tree.path_to_index[&root.join("src/ui/d.rs")]);
+        assert_eq!(tree.nodes[ui].state, SelectionState::Partial);
+        assert_eq!(tree.file_counts(ui), FileCounts { total_files: 2, included_files: 1, included_size: 4 });
+        let src = tree.path_to_index[&root.join("src")];
+        assert_eq!(tree.file_counts(src).included_files, 1);
+        assert_eq!(tree.file_counts(tree.root_index).total_files, 7); // Not the image
+    }
+
+    #[test]
+    fn test_toggle_partial_directory() {
+        let mut tree = sample_tree();
+        let root = tree.scan_root_path().to_path_buf();
+        let index = |tree: &DirectoryTree, path: &str| tree.path_to_index[&root.join(path)];
+        let (src, ui) = (index(&tree, "src"), index(&tree, "src/ui"));
+        let state = |tree: &DirectoryTree, path: &str| tree.nodes[index(tree, path)].state;
+
+        // Partial goes to included whichever child is included, then strictly alternates
+        for included in ["src/b.rs", "src/ui/widgets/f.rs"] {
+            let mut tree = sample_tree();
+            tree.toggle_state(index(&tree, included));
+            assert_eq!(tree.nodes[src].state, SelectionState::Partial);
+            let states: Vec<_> = (0..4).map(|_| tree.toggle_state(src).unwrap()).collect();
+            use SelectionState::{Excluded, Included};
+            assert_eq!(states, [Included, Excluded, Included, Excluded], "{}", included);
+            assert_eq!(tree.file_counts(src).included_files, 0);
+        }
+
+        // A locked excluded file keeps the directory partial once included; the next
+        // press excludes rather than including again
+        tree.toggle_lock(index(&tree, "src/c.rs"));
+        tree.toggle_state(index(&tree, "src/b.rs"));
+        assert_eq!(tree.toggle_state(src), Some(SelectionState::Included));
+        assert_eq!(tree.nodes[src].state, SelectionState::Partial);
+        assert_eq!(tree.file_counts(src).included_files, 3);
+        assert_eq!(tree.toggle_state(src), Some(SelectionState::Excluded));
+        assert_eq!(tree.nodes[src].state, SelectionState::Excluded);
+
+        // Likewise a locked included file deep down: included, excluded (still partial), included
+        tree.toggle_lock(index(&tree, "src/c.rs"));
+        let f = index(&tree, "src/ui/widgets/f.rs");
+        tree.toggle_state(f);
+        tree.toggle_lock(f);
+        assert_eq!(state(&tree, "src/ui"), SelectionState::Partial);
+        assert_eq!(tree.toggle_state(ui), Some(SelectionState::Included));
+        assert_eq!(tree.toggle_state(ui), Some(SelectionState::Excluded));
+        assert_eq!(tree.nodes[ui].state, SelectionState::Partial);
+        assert_eq!(tree.toggle_state(ui), Some(SelectionState::Included));
+        assert_eq!(tree.nodes[ui].state, SelectionState::Included);
+        assert_eq!(state(&tree, "src/ui/e.png"), SelectionState::Included);
+    }
+
+    #[test]
+    fn test_manual_order() {
+        let mut tree = sample_tree();
+        let root = tree.scan_root_path().to_path_buf();
+        tree.set_state(tree.root_index, SelectionState::Included);
+        tree.set_state(tree.path_to_index[&root.join("docs")], SelectionState::Excluded);
+        let index = |path: &str| tree.path_to_index[&root.join(path)];
+        let (a, b, c, d, f) = (index("a.rs"), index("src/b.rs"), index("src/c.rs"), index("src/ui/d.rs"), index("src/ui/widgets/f.rs"));
+        let names = |tree: &DirectoryTree| -> Vec<String> {
+            tree.get_all_included_files().iter().map(|node| node.name.clone()).collect()
+        };
+        assert_eq!(names(&tree), ["f.rs", "d.rs", "b.rs", "c.rs", "a.rs"]);
+
+        // Moving up places a file by hand, ahead of the rest
+        assert!(tree.move_in_export_order(c, -1));
+        assert_eq!(names(&tree), ["c.rs", "f.rs", "d.rs", "b.rs", "a.rs"]);
+        assert!(tree.move_in_export_order(b, -1));
+        assert_eq!(names(&tree), ["c.rs", "b.rs", "f.rs", "d.rs", "a.rs"]);
+        assert!(tree.move_in_export_order(b, -1));
+        assert_eq!(names(&tree), ["b.rs", "c.rs", "f.rs", "d.rs", "a.rs"]);
+        assert!(!tree.move_in_export_order(b, -1));
+        // The first file after the placed ones still moves on its first step
+        assert!(tree.move_in_export_order(f, -1));
+        assert_eq!(names(&tree), ["b.rs", "f.rs", "c.rs", "d.rs", "a.rs"]);
+        // Files in tree order can't move down; placed ones return to it past the end
+        assert!(!tree.move_in_export_order(d, 1));
+        assert!(tree.move_in_export_order(f, 1));
+        assert!(tree.move_in_export_order(f, 1));
+        assert_eq!(names(&tree), ["b.rs", "c.rs", "f.rs", "d.rs", "a.rs"]);
+        assert_eq!(tree.manual_order, [b, c]);
+
+        // Excluded files keep their place for when they're included again
+        tree.set_state(b, SelectionState::Excluded);
+        assert_eq!(names(&tree), ["c.rs", "f.rs", "d.rs", "a.rs"]);
+        assert!(tree.move_in_export_order(a, -1));
+        tree.set_state(b, SelectionState::Included);
+        assert_eq!(names(&tree), ["b.rs", "c.rs", "a.rs", "f.rs", "d.rs"]);
+        assert_eq!(tree.get_all_included_files().len(), tree.file_counts(tree.root_index).included_files);
+
+        // The order survives a rescan by path
+        let saved = tree.manual_order_paths();
+        assert_eq!(saved, ["src/b.rs", "src/c.rs", "a.rs"]);
+        let mut rescanned = sample_tree();
+        rescanned.set_state(rescanned.root_index, SelectionState::Included);
+        rescanned.restore_manual_order(&[saved, vec!["gone.rs".to_string()]].concat());
+        assert_eq!(names(&rescanned)[..3], ["b.rs", "c.rs", "a.rs"]);
+        assert_eq!(rescanned.manual_order.len(), 3);
+    }
+}
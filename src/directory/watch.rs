@@ -0,0 +1,148 @@
+use super::state::SelectionState;
+use super::tree::DirectoryTree;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often `--watch` looks for changes
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Least time between two exports, however often files change
+pub const MIN_EXPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Size and mtime of each path worth watching: the included files, and the directories a
+/// new file could be included from (their mtime changes when entries come and go).
+/// Excluded and ignored paths aren't watched, so their churn (`target/` during a build)
+/// never turns into events.
+#[derive(Debug, Default)]
+pub struct ChangePoller {
+    stamps: HashMap<PathBuf, Option<(u64, SystemTime)>>,
+}
+
+impl ChangePoller {
+    pub fn new(tree: &DirectoryTree) -> Self {
+        let stamps = tree
+            .nodes
+            .iter()
+            .filter(|node| if node.is_directory { node.state != SelectionState::Excluded } else { node.state.is_included() })
+            .map(|node| (node.path.clone(), stamp(&node.path)))
+            .collect();
+        Self { stamps }
+    }
+
+    /// Paths changed, created or removed since the last poll
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, recorded) in &mut self.stamps {
+            let current = stamp(path);
+            if current != *recorded {
+                *recorded = current;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
+
+fn stamp(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// Changes gathered into batches, so a burst of them leads to one export. A batch is ready
+/// once no change has come for the debounce window and the last batch is at least
+/// `MIN_EXPORT_INTERVAL` old; until then the changes are pending. A path changed
+/// repeatedly counts once.
+#[derive(Debug)]
+pub struct EventBatcher {
+    debounce: Duration,
+    pending: BTreeSet<PathBuf>,
+    last_change: Option<Instant>,
+    last_batch: Option<Instant>,
+}
+
+impl EventBatcher {
+    pub fn new(debounce: Duration) -> Self {
+        Self {
+            debounce,
+            pending: BTreeSet::new(),
+            last_change: None,
+            last_batch: None,
+        }
+    }
+
+    /// Add a change seen at `now`; `true` if it starts a new batch
+    pub fn push(&mut self, path: PathBuf, now: Instant) -> bool {
+        let started = self.pending.is_empty();
+        self.pending.insert(path);
+        self.last_change = Some(now);
+        started
+    }
+
+    /// The paths of the pending batch, sorted, once it's ready at `now`
+    pub fn take_ready(&mut self, now: Instant) -> Option<Vec<PathBuf>> {
+        let settled = self.last_change.is_some_and(|last_change| now.duration_since(last_change) >= self.debounce);
+        let spaced = self.last_batch.is_none_or(|last_batch| now.duration_since(last_batch) >= MIN_EXPORT_INTERVAL);
+        if self.pending.is_empty() || !settled || !spaced {
+            return None;
+        }
+        self.last_batch = Some(now);
+        Some(std::mem::take(&mut self.pending).into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_batches_bursts() {
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let mut batcher = EventBatcher::new(Duration::from_millis(100));
+
+        // A build rewriting the same files over and over: one batch, each path once
+        assert!(batcher.push(PathBuf::from("src/b.rs"), at(0)));
+        for millis in (10..500).step_by(10) {
+            let path = if millis % 20 == 0 { "src/a.rs" } else { "src/b.rs" };
+            assert!(!batcher.push(PathBuf::from(path), at(millis)));
+            assert_eq!(batcher.take_ready(at(millis)), None); // Still changing
+        }
+        assert_eq!(batcher.take_ready(at(550)), None);
+        assert_eq!(batcher.take_ready(at(590)), Some(vec![PathBuf::from("src/a.rs"), PathBuf::from("src/b.rs")]));
+        assert_eq!(batcher.take_ready(at(2000)), None);
+
+        // Changes that settle soon after an export stay pending until the interval has passed
+        assert!(batcher.push(PathBuf::from("README.md"), at(600)));
+        assert_eq!(batcher.take_ready(at(800)), None);
+        assert_eq!(batcher.take_ready(at(1590)), Some(vec![PathBuf::from("README.md")]));
+    }
+
+    #[test]
+    fn test_polls_watched_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let mut tree = DirectoryTree::new(root.to_path_buf());
+        let mut add = |name: &str, state| {
+            fs::write(root.join(name), "before").unwrap();
+            let index = tree.add_node(root.join(name), false, root).unwrap();
+            tree.set_state(index, state);
+        };
+        add("main.rs", SelectionState::Included);
+        add("debug.log", SelectionState::Excluded);
+        let mut poller = ChangePoller::new(&tree);
+        assert!(poller.poll().is_empty());
+
+        // The excluded file's changes never show up
+        fs::write(root.join("debug.log"), "more output").unwrap();
+        fs::write(root.join("main.rs"), "after, and longer").unwrap();
+        assert_eq!(poller.poll(), [root.join("main.rs")]);
+        assert!(poller.poll().is_empty());
+
+        // Removing it changes the root directory too
+        fs::remove_file(root.join("main.rs")).unwrap();
+        assert!(poller.poll().contains(&root.join("main.rs")));
+        assert!(!poller.stamps.contains_key(&root.join("debug.log")));
+    }
+}
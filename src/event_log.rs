@@ -0,0 +1,280 @@
+use anyhow::{Context, Result, anyhow};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Environment variable turning the event log on, with the most detailed level to record
+pub const LOG_ENV: &str = "GTHR_LOG";
+/// Where the log goes when only `GTHR_LOG` is set, inside the state directory
+pub const LOG_FILE: &str = "gthr.log";
+
+/// Events waiting for the writer; past this, new ones are dropped rather than wait
+const QUEUE_LENGTH: usize = 1024;
+/// How long a panic or exit waits for queued events to reach the file
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" | "warning" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+enum Message {
+    Line(String),
+    Flush(mpsc::Sender<()>),
+}
+
+/// An append-only log of what gthr did, for making sense of "it froze" or "my selection
+/// vanished". Events are paths, counts, sizes and timings, never file contents. Logging
+/// only queues the line; a writer thread appends it, so a slow disk never stalls the UI.
+struct EventLog {
+    level: Level,
+    sender: SyncSender<Message>,
+    dropped: Arc<AtomicUsize>, // Events lost to a full queue, reported by the writer
+}
+
+static LOG: OnceLock<EventLog> = OnceLock::new();
+
+/// The file and level to log with: `--log-file` wins for the file, `GTHR_LOG` sets the
+/// level (`error`, `warn`, `info` or `debug`, `info` if unset) and on its own logs to the
+/// state directory. `GTHR_LOG=off` turns logging off unless `--log-file` is given.
+pub fn resolve(log_file: Option<&Path>, env: Option<&str>, state_dir: &Path) -> Result<Option<(PathBuf, Level)>> {
+    let env = env.map(str::trim).filter(|value| !value.is_empty());
+    let level = match env {
+        None | Some("off") => Level::Info,
+        Some(value) => Level::parse(value)
+            .ok_or_else(|| anyhow!("{} must be error, warn, info, debug or off, not `{}`", LOG_ENV, value))?,
+    };
+    Ok(match (log_file, env) {
+        (Some(path), _) => Some((path.to_path_buf(), level)),
+        (None, None | Some("off")) => None,
+        (None, Some(_)) => Some((state_dir.join(LOG_FILE), level)),
+    })
+}
+
+/// Start appending events to `path`, and record panics before the usual report
+pub fn init(path: &Path, level: Level) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("opening log file {}", path.display()))?;
+
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_LENGTH);
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let writer_dropped = Arc::clone(&dropped);
+    thread::spawn(move || write_events(BufWriter::new(file), receiver, &writer_dropped));
+    if LOG.set(EventLog { level, sender, dropped }).is_err() {
+        return Err(anyhow!("the event log was already started"));
+    }
+
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        let location = info.location().map(|location| location.to_string()).unwrap_or_default();
+        error("panic", &[("message", &message), ("location", &location)]);
+        flush();
+        previous(info);
+    }));
+    Ok(())
+}
+
+fn write_events(mut file: BufWriter<fs::File>, receiver: Receiver<Message>, dropped: &AtomicUsize) {
+    while let Ok(message) = receiver.recv() {
+        // Write whatever else is queued, then flush: each burst is on disk before the next
+        let mut acks = Vec::new();
+        for message in std::iter::once(message).chain(receiver.try_iter()) {
+            match message {
+                Message::Line(line) => {
+                    let _ = file.write_all(line.as_bytes());
+                }
+                Message::Flush(ack) => acks.push(ack),
+            }
+        }
+        let lost = dropped.swap(0, Ordering::Relaxed);
+        if lost > 0 {
+            let line = format_line(&timestamp(), Level::Warn, "log.dropped", &[("events", &lost)]);
+            let _ = file.write_all(line.as_bytes());
+        }
+        let _ = file.flush();
+        for ack in acks {
+            let _ = ack.send(());
+        }
+    }
+}
+
+fn timestamp() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+/// `2026-10-15T09:30:00.125Z INFO scan.end files=120 root="/my project"`: values with
+/// spaces, quotes, `=` or line breaks are quoted and escaped, so every event is one line
+fn format_line(timestamp: &str, level: Level, event: &str, fields: &[(&str, &dyn fmt::Display)]) -> String {
+    let mut line = format!("{} {} {}", timestamp, level, event);
+    for (key, value) in fields {
+        let value = value.to_string();
+        let plain = !value.is_empty() && !value.chars().any(|c| c.is_whitespace() || c == '"' || c == '=' || c == '\\');
+        if plain {
+            line.push_str(&format!(" {}={}", key, value));
+        } else {
+            let escaped = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\r', "\\r");
+            line.push_str(&format!(" {}=\"{}\"", key, escaped));
+        }
+    }
+    line.push('\n');
+    line
+}
+
+/// Whether events at `level` are recorded, to skip gathering fields nobody reads
+pub fn enabled(level: Level) -> bool {
+    LOG.get().is_some_and(|log| level <= log.level)
+}
+
+/// Queue an event. When the writer falls behind it's dropped and counted, never waited for.
+pub fn event(level: Level, event: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    let Some(log) = LOG.get().filter(|log| level <= log.level) else {
+        return;
+    };
+    let line = format_line(&timestamp(), level, event, fields);
+    if let Err(TrySendError::Full(_)) = log.sender.try_send(Message::Line(line)) {
+        log.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn error(name: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    event(Level::Error, name, fields);
+}
+
+pub fn warn(name: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    event(Level::Warn, name, fields);
+}
+
+pub fn info(name: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    event(Level::Info, name, fields);
+}
+
+/// Wait briefly for queued events to reach the file, before exiting or after a panic
+pub fn flush() {
+    let Some(log) = LOG.get() else { return };
+    let (ack, done) = mpsc::channel();
+    let started = Instant::now();
+    let mut message = Message::Flush(ack);
+    // The queue may be full; give the writer a moment rather than block on it
+    loop {
+        match log.sender.try_send(message) {
+            Ok(()) => break,
+            Err(TrySendError::Full(returned)) if started.elapsed() < FLUSH_TIMEOUT => {
+                message = returned;
+                thread::sleep(Duration::from_millis(5));
+            }
+            Err(_) => return,
+        }
+    }
+    let _ = done.recv_timeout(FLUSH_TIMEOUT.saturating_sub(started.elapsed()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let state = Path::new("/state/gthr");
+        let file = Path::new("/tmp/gthr.log");
+        assert_eq!(resolve(None, None, state).unwrap(), None);
+        assert_eq!(resolve(None, Some("off"), state).unwrap(), None);
+        assert_eq!(resolve(None, Some(""), state).unwrap(), None);
+        assert_eq!(resolve(Some(file), None, state).unwrap(), Some((file.to_path_buf(), Level::Info)));
+        assert_eq!(resolve(Some(file), Some("off"), state).unwrap(), Some((file.to_path_buf(), Level::Info)));
+        assert_eq!(resolve(Some(file), Some("DEBUG"), state).unwrap(), Some((file.to_path_buf(), Level::Debug)));
+        assert_eq!(resolve(None, Some("warn"), state).unwrap(), Some((state.join(LOG_FILE), Level::Warn)));
+
+        let error = resolve(Some(file), Some("verbose"), state).unwrap_err().to_string();
+        assert!(error.contains("GTHR_LOG") && error.contains("`verbose`"), "{}", error);
+
+        assert!(Level::Error < Level::Info && Level::Info < Level::Debug);
+    }
+
+    #[test]
+    fn test_format_line() {
+        let line = format_line(
+            "2026-10-15T09:30:00.125Z",
+            Level::Info,
+            "scan.end",
+            &[("files", &120), ("root", &"/my project"), ("query", &"a=\"b\"\\c\nd"), ("empty", &"")],
+        );
+        assert_eq!(
+            line,
+            "2026-10-15T09:30:00.125Z INFO scan.end files=120 root=\"/my project\" query=\"a=\\\"b\\\"\\\\c\\nd\" empty=\"\"\n"
+        );
+        assert_eq!(line.lines().count(), 1);
+    }
+
+    #[test]
+    fn test_writer_drains_and_reports_drops() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("events.log");
+        let file = BufWriter::new(fs::File::create(&path).unwrap());
+        let (sender, receiver) = mpsc::sync_channel(4);
+        let dropped = Arc::new(AtomicUsize::new(2));
+
+        sender.send(Message::Line("first\n".to_string())).unwrap();
+        sender.send(Message::Line("second\n".to_string())).unwrap();
+        let (ack, done) = mpsc::channel();
+        sender.send(Message::Flush(ack)).unwrap();
+        let writer_dropped = Arc::clone(&dropped);
+        let writer = thread::spawn(move || write_events(file, receiver, &writer_dropped));
+
+        // A flush is acknowledged once everything before it is on disk
+        done.recv_timeout(Duration::from_secs(5)).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(&lines[..2], ["first", "second"]);
+        assert!(lines[2].ends_with("WARN log.dropped events=2"), "{}", lines[2]);
+        assert_eq!(dropped.load(Ordering::Relaxed), 0);
+
+        drop(sender);
+        writer.join().unwrap();
+    }
+}
@@ -1,9 +1,15 @@
-use super::matcher::{search_items, MatchResult};
+use super::matcher::{search_items, search_items_regex, MatchResult};
+use super::query::Query;
 use crate::directory::tree::{DirectoryTree, FileNode};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
 
 pub struct FilteredResults {
     pub matches: Vec<MatchResult>,
     pub visible_items: Vec<usize>, // Indices into the original tree
+    pub common_prefix_len: usize,  // Byte length of the directory prefix shared by all visible paths
+    pub disambiguations: HashMap<usize, String>, // Tree index -> parent directories, for names shown twice
+    pub context: HashSet<usize>, // Tree view rows shown only as the way to a match
 }
 
 impl FilteredResults {
@@ -11,27 +17,95 @@ impl FilteredResults {
         Self {
             matches: Vec::new(),
             visible_items: Vec::new(),
+            common_prefix_len: 0,
+            disambiguations: HashMap::new(),
+            context: HashSet::new(),
         }
     }
 
+    #[allow(dead_code)]
     pub fn is_empty(&self) -> bool {
         self.matches.is_empty()
     }
 
     pub fn len(&self) -> usize {
-        self.matches.len()
+        self.visible_items.len()
+    }
+
+    /// Put the rows in the tree's order for the tree view, each directory followed by
+    /// what's in it, leaving out everything under a `collapsed` directory. While searching,
+    /// collapsing is set aside and the directories leading to each match are listed too,
+    /// as `context`, so no match is hidden or shown without where it is.
+    pub fn arrange_as_tree(&mut self, tree: &DirectoryTree, collapsed: &HashSet<usize>, searching: bool) {
+        let matched: HashSet<usize> = self.visible_items.iter().copied().collect();
+        self.context.clear();
+        if searching {
+            for &index in &matched {
+                let mut parent = tree.nodes[index].parent;
+                while let Some(ancestor) = parent.filter(|&ancestor| ancestor != tree.root_index) {
+                    if matched.contains(&ancestor) || !self.context.insert(ancestor) {
+                        break;
+                    }
+                    parent = tree.nodes[ancestor].parent;
+                }
+            }
+        }
+
+        let mut rows = Vec::with_capacity(matched.len() + self.context.len());
+        let mut stack = vec![tree.root_index];
+        while let Some(index) = stack.pop() {
+            let shown = matched.contains(&index) || self.context.contains(&index);
+            if shown {
+                rows.push(index);
+            }
+            let expanded = match searching {
+                _ if index == tree.root_index => true,
+                true => shown,
+                false => !collapsed.contains(&index),
+            };
+            if expanded {
+                stack.extend(tree.nodes[index].children.iter().rev());
+            }
+        }
+        self.visible_items = rows;
+        self.common_prefix_len = 0;
+        self.disambiguations.clear();
     }
 }
 
 pub fn filter_tree_nodes(tree: &DirectoryTree, query: &str) -> FilteredResults {
+    filter_tree_nodes_where(tree, query, None, false, |_, _| true)
+}
+
+/// Like `filter_tree_nodes`, only searching nodes that `keep` accepts. With `names_only`
+/// the query is matched against file names instead of paths relative to the root;
+/// `depth:` and `dir:` terms always look at the path. A `regex` (the query compiled by a
+/// substring or regex `MatchMode`) is matched instead of the query's syntax, those terms
+/// included.
+pub fn filter_tree_nodes_where(
+    tree: &DirectoryTree,
+    query: &str,
+    regex: Option<&Regex>,
+    names_only: bool,
+    keep: impl Fn(usize, &FileNode) -> bool,
+) -> FilteredResults {
+    let root_path = &tree.nodes[tree.root_index].path;
+    let path_query = match regex {
+        Some(_) => Query::default(),
+        None => Query::parse(query),
+    };
+
     // Collect all nodes that should be searchable
     let searchable_nodes: Vec<(usize, &FileNode)> = tree
         .nodes
         .iter()
         .enumerate()
-        .filter(|(_, node)| {
-            // Include directories and text files
-            node.is_directory || node.is_text_file
+        .filter(|(index, node)| {
+            // Include directories and files an export can carry, plus files not yet checked
+            (node.is_directory || tree.is_exportable(node) || node.metadata_pending)
+                && tree.is_in_view(*index)
+                && keep(*index, node)
+                && path_query.matches_path(node.path.strip_prefix(root_path).unwrap_or(&node.path))
         })
         .collect();
 
@@ -40,7 +114,7 @@ pub fn filter_tree_nodes(tree: &DirectoryTree, query: &str) -> FilteredResults {
         .iter()
         .map(|(_, node)| {
             // Create a display path relative to the root
-            if let Ok(relative_path) = node.path.strip_prefix(&tree.nodes[tree.root_index].path) {
+            if let Ok(relative_path) = node.path.strip_prefix(root_path) {
                 relative_path.to_string_lossy().to_string()
             } else {
                 node.name.clone()
@@ -48,8 +122,12 @@ pub fn filter_tree_nodes(tree: &DirectoryTree, query: &str) -> FilteredResults {
         })
         .collect();
 
-    // Perform fuzzy search
-    let matches = search_items(&node_texts, query, |text| text.as_str());
+    let matches = match (regex, names_only) {
+        (Some(regex), true) => search_items_regex(&searchable_nodes, regex, |(_, node)| node.name.as_str()),
+        (Some(regex), false) => search_items_regex(&node_texts, regex, |text| text.as_str()),
+        (None, true) => search_items(&searchable_nodes, query, |(_, node)| node.name.as_str()),
+        (None, false) => search_items(&node_texts, query, |text| text.as_str()),
+    };
 
     // Map results back to tree indices
     let visible_items: Vec<usize> = matches
@@ -57,10 +135,93 @@ pub fn filter_tree_nodes(tree: &DirectoryTree, query: &str) -> FilteredResults {
         .map(|match_result| searchable_nodes[match_result.item_index].0)
         .collect();
 
+    let common_prefix_len = common_directory_prefix_len(
+        matches
+            .iter()
+            .map(|match_result| node_texts[match_result.item_index].as_str()),
+    );
+
+    // Names alone can be ambiguous, paths never are
+    let disambiguations = if names_only {
+        let paths = matches.iter().map(|match_result| node_texts[match_result.item_index].as_str());
+        disambiguate_names(visible_items.iter().copied().zip(paths))
+    } else {
+        HashMap::new()
+    };
+
     FilteredResults {
         matches,
         visible_items,
+        common_prefix_len,
+        disambiguations,
+        context: HashSet::new(),
+    }
+}
+
+/// For results sharing a file name, the fewest trailing parent directories that tell
+/// them apart, like editors show `index.ts — packages/auth/src`. Files directly in the
+/// root get `.`. Takes tree indices with paths relative to the root.
+pub fn disambiguate_names<'a>(paths: impl Iterator<Item = (usize, &'a str)>) -> HashMap<usize, String> {
+    let separator = std::path::MAIN_SEPARATOR;
+    let mut by_name: HashMap<&str, Vec<(usize, Vec<&str>)>> = HashMap::new();
+    for (index, path) in paths {
+        let mut segments: Vec<&str> = path.split(separator).collect();
+        let name = segments.pop().unwrap_or_default();
+        by_name.entry(name).or_default().push((index, segments));
+    }
+
+    fn tail<'s>(parents: &'s [&'s str], count: usize) -> &'s [&'s str] {
+        &parents[parents.len().saturating_sub(count)..]
+    }
+
+    let mut disambiguations = HashMap::new();
+    for group in by_name.values().filter(|group| group.len() > 1) {
+        for (index, parents) in group {
+            // Grow the suffix until no other result's parents end the same way
+            let count = (1..=parents.len())
+                .find(|&count| {
+                    group.iter().all(|(other, other_parents)| {
+                        other == index || tail(other_parents, count) != tail(parents, count)
+                    })
+                })
+                .unwrap_or(parents.len());
+            let suffix = tail(parents, count).join(&separator.to_string());
+            disambiguations.insert(*index, if suffix.is_empty() { ".".to_string() } else { suffix });
+        }
     }
+    disambiguations
+}
+
+/// Length of the longest directory prefix (ending in a separator) shared by
+/// every path. The final component of each path is never part of the prefix.
+pub fn common_directory_prefix_len<'a>(paths: impl Iterator<Item = &'a str>) -> usize {
+    let separator = std::path::MAIN_SEPARATOR;
+    let mut prefix: Option<&str> = None;
+
+    for path in paths {
+        let parent = match path.rfind(separator) {
+            Some(index) => &path[..=index],
+            None => return 0,
+        };
+
+        let shared = match prefix {
+            None => parent,
+            Some(prefix) => {
+                let shared_len = prefix
+                    .char_indices()
+                    .zip(parent.chars())
+                    .find(|((_, a), b)| a != b)
+                    .map_or(prefix.len().min(parent.len()), |((index, _), _)| index);
+                &prefix[..shared_len]
+            }
+        };
+        prefix = Some(shared);
+    }
+
+    // Only keep whole directory components
+    prefix
+        .and_then(|prefix| prefix.rfind(separator))
+        .map_or(0, |index| index + 1)
 }
 
 pub fn get_node_display_path(tree: &DirectoryTree, node_index: usize) -> String {
@@ -78,7 +239,6 @@ pub fn get_node_display_path(tree: &DirectoryTree, node_index: usize) -> String
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
     use tempfile::TempDir;
 
     #[test]
@@ -89,4 +249,73 @@ mod tests {
         let results = filter_tree_nodes(&tree, "");
         assert_eq!(results.len(), 1); // Should include the root directory
     }
+
+    #[test]
+    fn test_common_directory_prefix_len() {
+        let sep = std::path::MAIN_SEPARATOR;
+        let app = format!("src{sep}ui{sep}app.rs");
+        let events = format!("src{sep}ui{sep}events.rs");
+        let ui = format!("src{sep}ui");
+        let uber = format!("src{sep}uber{sep}mod.rs");
+
+        let prefix_len = |paths: &[&str]| common_directory_prefix_len(paths.iter().copied());
+
+        assert_eq!(prefix_len(&[&app, &events]), "src/ui/".len());
+        assert_eq!(prefix_len(&[&app, &events, &ui]), "src/".len());
+        // Shared characters that don't end on a separator don't count
+        assert_eq!(prefix_len(&[&app, &uber]), "src/".len());
+        assert_eq!(prefix_len(&[&app, "README.md"]), 0);
+        assert_eq!(prefix_len(&[]), 0);
+    }
+
+    #[test]
+    fn test_disambiguate_names() {
+        let sep = std::path::MAIN_SEPARATOR;
+        let path = |path: &str| path.replace('/', &sep.to_string());
+        let paths = [
+            path("packages/auth/src/index.ts"),
+            path("packages/ui/src/index.ts"),
+            path("apps/web/src/index.ts"),
+            path("src/index.ts"),
+            "index.ts".to_string(),
+            path("src/main.rs"),
+        ];
+        let names = disambiguate_names(paths.iter().enumerate().map(|(index, path)| (index, path.as_str())));
+
+        // Only as many parents as needed, and nothing for unique names
+        assert_eq!(names[&0], path("auth/src"));
+        assert_eq!(names[&1], path("ui/src"));
+        assert_eq!(names[&2], path("web/src"));
+        assert_eq!(names[&3], "src"); // No other result's parents are exactly `src`
+        assert_eq!(names[&4], ".");
+        assert!(!names.contains_key(&5));
+    }
+
+    #[test]
+    fn test_names_only_matching() {
+        let root = std::path::PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        for directory in ["auth", "ui"] {
+            tree.add_node(root.join(directory), true, &root).unwrap();
+            let index = tree.add_node(root.join(directory).join("index.ts"), false, &root.join(directory)).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+
+        // `auth` is only in a directory name, so it doesn't find files by name
+        let results = filter_tree_nodes_where(&tree, "auth", None, true, |_, node| !node.is_directory);
+        assert!(results.is_empty());
+        let results = filter_tree_nodes_where(&tree, "index", None, true, |_, _| true);
+        assert_eq!(results.len(), 2);
+        let mut suffixes: Vec<_> = results.disambiguations.values().cloned().collect();
+        suffixes.sort();
+        assert_eq!(suffixes, vec!["auth".to_string(), "ui".to_string()]);
+
+        // Path terms look at where a file is, even when only names are matched
+        let results = filter_tree_nodes_where(&tree, "index !dir:auth", None, true, |_, _| true);
+        assert_eq!(results.len(), 1);
+        assert_eq!(tree.nodes[results.visible_items[0]].path, root.join("ui/index.ts"));
+        let results = filter_tree_nodes_where(&tree, "depth:1", None, false, |_, _| true);
+        let names: Vec<_> = results.visible_items.iter().map(|&index| tree.nodes[index].name.as_str()).collect();
+        assert_eq!(names, ["auth", "ui"]);
+    }
 }
\ No newline at end of file
@@ -1,4 +1,46 @@
+use super::query::Query;
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use regex::{Regex, RegexBuilder};
+
+/// How the search query is read, cycled with Ctrl+R
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    #[default]
+    Fuzzy,     // In the syntax of `Query`
+    Substring, // The query as typed, spaces and all
+    Regex,
+}
+
+impl MatchMode {
+    pub fn next(self) -> Self {
+        match self {
+            MatchMode::Fuzzy => MatchMode::Substring,
+            MatchMode::Substring => MatchMode::Regex,
+            MatchMode::Regex => MatchMode::Fuzzy,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MatchMode::Fuzzy => "fuzzy",
+            MatchMode::Substring => "substring",
+            MatchMode::Regex => "regex",
+        }
+    }
+
+    /// The query as a regex for the substring and regex modes, `None` for fuzzy. A substring
+    /// in lowercase ignores case, like a query term; a regex is taken as written.
+    pub fn compile(self, query: &str) -> Result<Option<Regex>, regex::Error> {
+        match self {
+            MatchMode::Fuzzy => Ok(None),
+            MatchMode::Substring => RegexBuilder::new(&regex::escape(query))
+                .case_insensitive(!query.chars().any(char::is_uppercase))
+                .build()
+                .map(Some),
+            MatchMode::Regex => Regex::new(query).map(Some),
+        }
+    }
+}
 
 pub struct FuzzySearch {
     matcher: SkimMatcherV2,
@@ -21,6 +63,7 @@ impl FuzzySearch {
         self.matcher.fuzzy_indices(text, query)
     }
 
+    #[allow(dead_code)]
     pub fn search_score(&self, query: &str, text: &str) -> Option<i64> {
         self.matcher.fuzzy_match(text, query)
     }
@@ -29,6 +72,7 @@ impl FuzzySearch {
 #[derive(Debug, Clone)]
 pub struct MatchResult {
     pub score: i64,
+    #[allow(dead_code)]
     pub indices: Vec<usize>,
     pub item_index: usize,
 }
@@ -43,6 +87,8 @@ impl MatchResult {
     }
 }
 
+/// Items matching `query`, in the syntax of `Query`, best first. Items that score the same
+/// keep their order, so a query of only `!` terms filters without reordering.
 pub fn search_items<T, F>(
     items: &[T],
     query: &str,
@@ -51,6 +97,7 @@ pub fn search_items<T, F>(
 where
     F: Fn(&T) -> &str,
 {
+    let query = Query::parse(query);
     if query.is_empty() {
         return (0..items.len())
             .map(|i| MatchResult::new(0, Vec::new(), i))
@@ -62,16 +109,35 @@ where
 
     for (index, item) in items.iter().enumerate() {
         let text = extract_text(item);
-        if let Some((score, indices)) = fuzzy_search.search(query, text) {
+        if let Some((score, indices)) = query.matches(&fuzzy_search, text) {
             results.push(MatchResult::new(score, indices, index));
         }
     }
 
     // Sort by score (descending)
-    results.sort_by(|a, b| b.score.cmp(&a.score));
+    results.sort_by_key(|result| std::cmp::Reverse(result.score));
     results
 }
 
+/// Items `regex` finds a match in, in their order, with the first match's characters as
+/// the indices to highlight
+pub fn search_items_regex<T, F>(items: &[T], regex: &Regex, extract_text: F) -> Vec<MatchResult>
+where
+    F: Fn(&T) -> &str,
+{
+    items
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| {
+            let text = extract_text(item);
+            let found = regex.find(text)?;
+            let start = text[..found.start()].chars().count();
+            let length = found.as_str().chars().count();
+            Some(MatchResult::new(0, (start..start + length).collect(), index))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -96,4 +162,34 @@ mod tests {
         assert_eq!(results.len(), 2);
         assert!(results[0].score >= results[1].score);
     }
+
+    #[test]
+    fn test_search_items_query_syntax() {
+        let items = vec!["main.rs", "lib.rs", "config.toml", "README.md"];
+
+        // Query syntax applies, and ties keep the items' order
+        let results = search_items(&items, "!rs", |item| item);
+        let found: Vec<_> = results.iter().map(|result| items[result.item_index]).collect();
+        assert_eq!(found, vec!["config.toml", "README.md"]);
+    }
+
+    #[test]
+    fn test_match_modes() {
+        let items = vec!["src/config.rs", "src/config.test.ts", "tests/config_test.ts", "Config.test.ts"];
+        let search = |mode: MatchMode, query: &str| -> Vec<&str> {
+            let regex = mode.compile(query).unwrap().unwrap();
+            search_items_regex(&items, &regex, |item| item).iter().map(|result| items[result.item_index]).collect()
+        };
+
+        // Substrings are literal, `.` included, and ignore case unless they have capitals
+        assert_eq!(search(MatchMode::Substring, "config.test.ts"), ["src/config.test.ts", "Config.test.ts"]);
+        assert_eq!(search(MatchMode::Substring, "Config."), ["Config.test.ts"]);
+        assert_eq!(search(MatchMode::Regex, r"config[._]test\.ts$"), ["src/config.test.ts", "tests/config_test.ts"]);
+        let results = search_items_regex(&items, &MatchMode::Regex.compile("c.nfig").unwrap().unwrap(), |item| item);
+        assert_eq!(results[0].indices, vec![4, 5, 6, 7, 8, 9]);
+
+        assert!(MatchMode::Regex.compile("config(").is_err());
+        assert!(MatchMode::Fuzzy.compile("config(").unwrap().is_none());
+        assert_eq!(MatchMode::Fuzzy.next().next().next(), MatchMode::Fuzzy);
+    }
 }
\ No newline at end of file
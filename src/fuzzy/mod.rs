@@ -1,3 +1,4 @@
 pub mod matcher;
 pub mod filter;
+pub mod query;
 
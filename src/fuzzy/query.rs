@@ -0,0 +1,429 @@
+use super::matcher::FuzzySearch;
+use std::path::{Component, Path};
+
+/// How one term of a query matches
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TermKind {
+    Fuzzy,
+    Exact,  // 'app: a substring
+    Word,   // 'app': a substring with no letter, digit or `_` on either side
+    Prefix, // ^src
+    Suffix, // .rs$
+    Equal,  // ^main.rs$: the whole text
+    Depth(Comparison, usize), // depth:<=3: how many components the path has
+    Dir,    // dir:internal: a directory the item is under, by name
+}
+
+/// How `depth:` compares a path's depth
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Less,    // depth:<2
+    AtMost,  // depth:<=2
+    Equal,   // depth:2
+    AtLeast, // depth:>=2
+    Greater, // depth:>2
+}
+
+impl Comparison {
+    fn holds(self, value: usize, bound: usize) -> bool {
+        match self {
+            Comparison::Less => value < bound,
+            Comparison::AtMost => value <= bound,
+            Comparison::Equal => value == bound,
+            Comparison::AtLeast => value >= bound,
+            Comparison::Greater => value > bound,
+        }
+    }
+}
+
+/// One whitespace-separated term of a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub kind: TermKind,
+    pub inverse: bool, // !vendor: the text must not match
+    pub text: String,
+}
+
+/// A search in fzf's syntax. Terms are separated by spaces and all must match:
+///
+/// | Term        | Matches                                 |
+/// |-------------|-----------------------------------------|
+/// | `app`       | Fuzzily                                 |
+/// | `'app`      | The exact substring                     |
+/// | `'app'`     | The exact substring as a whole word     |
+/// | `^src`      | At the start                            |
+/// | `.rs$`      | At the end                              |
+/// | `!vendor`   | Anything without the exact substring    |
+/// | `depth:<=3` | Paths of at most 3 components           |
+/// | `dir:gen`   | Anything under a directory named `gen`  |
+///
+/// `!` combines with the others (`!^test`, `!.md$`, `!dir:internal`), `\ ` is a literal
+/// space, and a term in lowercase ignores case while one with any uppercase letter doesn't.
+/// `depth:` takes `<`, `<=`, `>=`, `>` or a bare number; `dir:` takes a name or several
+/// (`dir:src/gen`, consecutive directories), with any trailing `/` ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub terms: Vec<Term>,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Self {
+        let terms = split_terms(query).into_iter().filter_map(|term| parse_term(&term)).collect();
+        Self { terms }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Whether every `depth:` and `dir:` term holds for a path relative to the root (the root
+    /// itself is the empty path, at depth 0). Text terms aren't checked.
+    pub fn matches_path(&self, relative_path: &Path) -> bool {
+        self.terms.iter().all(|term| {
+            let found = match term.kind {
+                TermKind::Depth(comparison, bound) => comparison.holds(relative_path.components().count(), bound),
+                TermKind::Dir => under_directory(&term.text, relative_path),
+                _ => return true,
+            };
+            found != term.inverse
+        })
+    }
+
+    /// The combined score and matched character indices, or `None` unless every term matches.
+    /// Exact terms score what the fuzzy scorer gives their text, so they rank alongside
+    /// fuzzy ones; inverse terms only filter. Path terms are left to `matches_path`.
+    pub fn matches(&self, fuzzy_search: &FuzzySearch, text: &str) -> Option<(i64, Vec<usize>)> {
+        let mut score = 0;
+        let mut indices = Vec::new();
+        for term in &self.terms {
+            let found = match term.kind {
+                TermKind::Depth(..) | TermKind::Dir => continue,
+                TermKind::Fuzzy => fuzzy_search.search(&term.text, text),
+                _ => exact_match(term, text).map(|range| {
+                    let start = text[..range.start].chars().count();
+                    let length = text[range].chars().count();
+                    let score = fuzzy_search.search(&term.text, text).map_or(0, |(score, _)| score);
+                    (score, (start..start + length).collect())
+                }),
+            };
+            match (found, term.inverse) {
+                (Some(_), true) | (None, false) => return None,
+                (None, true) => {}
+                (Some((term_score, term_indices)), false) => {
+                    score += term_score;
+                    indices.extend(term_indices);
+                }
+            }
+        }
+        indices.sort_unstable();
+        indices.dedup();
+        Some((score, indices))
+    }
+}
+
+/// Split on unescaped whitespace; `\ ` stands for a space and `\\` for a backslash
+fn split_terms(query: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek().is_some_and(|next| next.is_whitespace() || *next == '\\') => {
+                current.push(chars.next().unwrap_or(c));
+            }
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    terms.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        terms.push(current);
+    }
+    terms
+}
+
+/// A term's kind from its markers. A term that is only markers (`!`, `'`, `^`) matches
+/// everything, so it's dropped rather than filtering while it's being typed, as is a
+/// `depth:` or `dir:` term without a valid value yet.
+fn parse_term(term: &str) -> Option<Term> {
+    let (inverse, rest) = match term.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, term),
+    };
+    let (kind, text) = if let Some(value) = rest.strip_prefix("depth:") {
+        (parse_depth(value)?, "")
+    } else if let Some(name) = rest.strip_prefix("dir:") {
+        let name = name.trim_matches('/');
+        if name.is_empty() {
+            return None;
+        }
+        (TermKind::Dir, name)
+    } else if let Some(rest) = rest.strip_prefix('\'') {
+        match rest.strip_suffix('\'') {
+            Some(word) if !word.is_empty() => (TermKind::Word, word),
+            _ => (TermKind::Exact, rest),
+        }
+    } else {
+        let (prefix, rest) = match rest.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let (suffix, rest) = match rest.strip_suffix('$') {
+            Some(rest) if !rest.is_empty() || prefix => (true, rest),
+            _ => (false, rest),
+        };
+        let kind = match (prefix, suffix) {
+            (true, true) => TermKind::Equal,
+            (true, false) => TermKind::Prefix,
+            (false, true) => TermKind::Suffix,
+            // Like fzf, inverting a fuzzy term makes it exact: a fuzzy miss is too rare to be useful
+            (false, false) if inverse => TermKind::Exact,
+            (false, false) => TermKind::Fuzzy,
+        };
+        (kind, rest)
+    };
+    if text.is_empty() && !matches!(kind, TermKind::Equal | TermKind::Depth(..)) {
+        return None;
+    }
+    Some(Term {
+        kind,
+        inverse,
+        text: text.to_string(),
+    })
+}
+
+/// `<=3` and the like, after `depth:`
+fn parse_depth(value: &str) -> Option<TermKind> {
+    let (comparison, bound) = [
+        ("<=", Comparison::AtMost),
+        (">=", Comparison::AtLeast),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+        ("=", Comparison::Equal),
+    ]
+    .into_iter()
+    .find_map(|(operator, comparison)| value.strip_prefix(operator).map(|bound| (comparison, bound)))
+    .unwrap_or((Comparison::Equal, value));
+    bound.parse().ok().map(|bound| TermKind::Depth(comparison, bound))
+}
+
+/// Whether the directories a path is in include `name`'s components, consecutively.
+/// Only directories count, not the item itself, so the root and its children are under none.
+fn under_directory(name: &str, relative_path: &Path) -> bool {
+    let ignore_case = !name.chars().any(char::is_uppercase);
+    let fold = |part: &str| if ignore_case { part.to_lowercase() } else { part.to_string() };
+    let wanted: Vec<String> = name.split('/').filter(|part| !part.is_empty()).map(fold).collect();
+    let directories: Vec<String> = relative_path
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(fold(&part.to_string_lossy())),
+            _ => None,
+        })
+        .collect();
+    directories.windows(wanted.len()).any(|window| window == wanted.as_slice())
+}
+
+/// The byte range where an exact term matches, ignoring case unless the term has uppercase
+fn exact_match(term: &Term, text: &str) -> Option<std::ops::Range<usize>> {
+    let ignore_case = !term.text.chars().any(char::is_uppercase);
+    // ASCII folding keeps byte offsets the same in both strings
+    let (haystack, needle) = if ignore_case {
+        (text.to_ascii_lowercase(), term.text.to_ascii_lowercase())
+    } else {
+        (text.to_string(), term.text.clone())
+    };
+    let end = |start: usize| start + needle.len();
+    match term.kind {
+        TermKind::Fuzzy | TermKind::Depth(..) | TermKind::Dir => None,
+        TermKind::Exact => haystack.find(&needle).map(|start| start..end(start)),
+        TermKind::Word => haystack
+            .match_indices(&needle)
+            .map(|(start, _)| start)
+            .find(|&start| {
+                let before = haystack[..start].chars().next_back();
+                let after = haystack[end(start)..].chars().next();
+                !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+            })
+            .map(|start| start..end(start)),
+        TermKind::Prefix => haystack.starts_with(&needle).then_some(0..needle.len()),
+        TermKind::Suffix => haystack
+            .ends_with(&needle)
+            .then(|| haystack.len() - needle.len()..haystack.len()),
+        TermKind::Equal => (haystack == needle).then_some(0..needle.len()),
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(kind: TermKind, inverse: bool, text: &str) -> Term {
+        Term {
+            kind,
+            inverse,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_query() {
+        use TermKind::*;
+        let cases = [
+            ("app", vec![term(Fuzzy, false, "app")]),
+            ("'app", vec![term(Exact, false, "app")]),
+            ("'app'", vec![term(Word, false, "app")]),
+            ("^src", vec![term(Prefix, false, "src")]),
+            (".rs$", vec![term(Suffix, false, ".rs")]),
+            ("^main.rs$", vec![term(Equal, false, "main.rs")]),
+            ("!vendor", vec![term(Exact, true, "vendor")]),
+            ("!^test", vec![term(Prefix, true, "test")]),
+            ("!.md$", vec![term(Suffix, true, ".md")]),
+            ("!'gen'", vec![term(Word, true, "gen")]),
+            ("'^src", vec![term(Exact, false, "^src")]), // Only the first marker counts
+            ("src ui  .rs$", vec![term(Fuzzy, false, "src"), term(Fuzzy, false, "ui"), term(Suffix, false, ".rs")]),
+            (r"my\ file", vec![term(Fuzzy, false, "my file")]),
+            (r"a\\b", vec![term(Fuzzy, false, r"a\b")]),
+            (r"a\b", vec![term(Fuzzy, false, r"a\b")]),
+            ("$", vec![term(Fuzzy, false, "$")]),
+            ("^$", vec![term(Equal, false, "")]),
+            ("depth:<=3", vec![term(Depth(Comparison::AtMost, 3), false, "")]),
+            ("depth:<3 depth:>1", vec![term(Depth(Comparison::Less, 3), false, ""), term(Depth(Comparison::Greater, 1), false, "")]),
+            ("depth:2", vec![term(Depth(Comparison::Equal, 2), false, "")]),
+            ("!depth:>=4", vec![term(Depth(Comparison::AtLeast, 4), true, "")]),
+            ("dir:internal", vec![term(Dir, false, "internal")]),
+            ("!dir:gen/", vec![term(Dir, true, "gen")]),
+            ("dir:src/gen", vec![term(Dir, false, "src/gen")]),
+            ("'dir:x", vec![term(Exact, false, "dir:x")]),
+            // Markers alone are still being typed
+            ("!", vec![]),
+            ("'", vec![]),
+            ("^", vec![]),
+            ("!'", vec![]),
+            ("  ", vec![]),
+            ("", vec![]),
+            ("depth:", vec![]),
+            ("depth:<=", vec![]),
+            ("depth:deep", vec![]),
+            ("dir:", vec![]),
+            ("!dir:/", vec![]),
+        ];
+        for (query, terms) in cases {
+            assert_eq!(Query::parse(query).terms, terms, "{:?}", query);
+        }
+    }
+
+    #[test]
+    fn test_query_matches() {
+        let fuzzy_search = FuzzySearch::new();
+        let matches = |query: &str, text: &str| Query::parse(query).matches(&fuzzy_search, text).is_some();
+
+        // Fuzzy finds scattered letters, exact terms don't
+        assert!(matches("app", "wrapper.rs"));
+        assert!(matches("app", "src/a_p_p.rs"));
+        assert!(!matches("'app", "src/a_p_p.rs"));
+        assert!(matches("'app", "wrapper.rs"));
+        assert!(!matches("'app'", "wrapper.rs"));
+        assert!(matches("'app'", "src/app.rs"));
+        assert!(matches("'app'", "app"));
+        assert!(!matches("'app'", "app_state.rs")); // `_` is part of a word
+        assert!(matches("'app'", "wrapper/app-state.rs"));
+
+        assert!(matches("^src", "src/main.rs"));
+        assert!(!matches("^src", "tests/src.rs"));
+        assert!(matches(".rs$", "src/main.rs"));
+        assert!(!matches(".rs$", "src/main.rs.bak"));
+        assert!(matches("^main.rs$", "main.rs"));
+        assert!(!matches("^main.rs$", "src/main.rs"));
+
+        assert!(!matches("!vendor", "vendor/lib.rs"));
+        assert!(matches("!vendor", "src/lib.rs"));
+        assert!(matches("!vndr", "vendor/lib.rs")); // Inverse terms are exact
+        assert!(!matches("!^test", "tests/a.rs"));
+        assert!(matches("!^test", "src/test.rs"));
+        assert!(!matches("!.md$", "README.md"));
+
+        // All terms must match
+        assert!(matches("^src .rs$ !test", "src/ui/app.rs"));
+        assert!(!matches("^src .rs$ !test", "src/ui/test.rs"));
+        assert!(!matches("^src .rs$", "src/ui/app.toml"));
+        assert!(matches(r"my\ file", "docs/my file.txt"));
+        assert!(!matches(r"'my\ file", "docs/my_file.txt"));
+
+        // Smart case
+        assert!(matches("'readme", "README.md"));
+        assert!(matches("'README", "README.md"));
+        assert!(!matches("'README", "readme.md"));
+        assert!(!matches("!readme", "README.md"));
+
+        // Nothing to match on matches everything
+        assert!(matches("", "anything"));
+        assert!(matches("! ^", "anything"));
+    }
+
+    #[test]
+    fn test_query_matches_path() {
+        let matches = |query: &str, path: &str| Query::parse(query).matches_path(Path::new(path));
+
+        // The root is the empty path, its children are one deep
+        assert!(matches("depth:0", ""));
+        assert!(matches("depth:<=1", "README.md"));
+        assert!(!matches("depth:>1", "README.md"));
+        assert!(matches("depth:<=3", "src/main/java"));
+        assert!(!matches("depth:<=3", "src/main/java/App.java"));
+        assert!(matches("!depth:<=3", "src/main/java/App.java"));
+        assert!(matches("depth:>=2 depth:<4", "src/lib.rs"));
+
+        // Only the directories an item is in, never the item itself
+        assert!(matches("dir:internal", "pkg/internal/auth.go"));
+        assert!(matches("dir:internal", "internal/deep/er/x.go"));
+        assert!(!matches("dir:internal", "internal"));
+        assert!(!matches("dir:internal", "pkg/internal.go"));
+        assert!(!matches("dir:internal", ""));
+        assert!(!matches("dir:intern", "pkg/internal/auth.go")); // Whole names only
+        assert!(matches("!dir:internal", "pkg/public/auth.go"));
+        assert!(!matches("!dir:internal/", "pkg/internal/auth.go"));
+
+        // Several names must be consecutive directories
+        assert!(matches("dir:src/gen", "app/src/gen/api.rs"));
+        assert!(!matches("dir:src/gen", "src/x/gen/api.rs"));
+        assert!(!matches("dir:src/gen", "src/gen"));
+
+        // Smart case, as for exact terms
+        assert!(matches("dir:generated", "src/Generated/a.rs"));
+        assert!(matches("dir:Generated", "src/Generated/a.rs"));
+        assert!(!matches("dir:Generated", "src/generated/a.rs"));
+
+        // Text terms are for `matches`, and path terms don't stop them
+        assert!(matches("zzz", "src/a.rs"));
+        let fuzzy_search = FuzzySearch::new();
+        assert!(Query::parse("dir:zzz main").matches(&fuzzy_search, "src/main.rs").is_some());
+    }
+
+    #[test]
+    fn test_query_indices_and_score() {
+        let fuzzy_search = FuzzySearch::new();
+        let query = |query: &str, text: &str| Query::parse(query).matches(&fuzzy_search, text).unwrap();
+
+        // Character indices, whatever the bytes
+        assert_eq!(query("'app'", "wrapper/app.rs").1, vec![8, 9, 10]);
+        assert_eq!(query(".rs$", "é/main.rs").1, vec![6, 7, 8]);
+        assert_eq!(query("^é", "é/main.rs").1, vec![0]);
+        assert_eq!(query("!test", "src/main.rs").1, Vec::<usize>::new());
+        // Overlapping terms report each index once
+        assert_eq!(query("^src 'src", "src/x").1, vec![0, 1, 2]);
+
+        // Exact terms add to the score like fuzzy ones
+        let (fuzzy, _) = query("main", "src/main.rs");
+        let (both, _) = query("main .rs$", "src/main.rs");
+        assert!(fuzzy > 0 && both > fuzzy);
+    }
+}
@@ -0,0 +1,286 @@
+use anyhow::{Result, anyhow};
+use git2::{Delta, DiffFormat, DiffOptions, Repository, Status, StatusOptions};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Files relative to the scan root, plus every directory containing one of them
+#[derive(Debug, Default)]
+struct PathSet {
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+}
+
+/// Files tracked by git, relative to the scan root
+#[derive(Debug, Default)]
+pub struct TrackedFiles {
+    paths: PathSet,
+}
+
+/// Files that differ from HEAD (modified, added or untracked), relative to the scan root
+#[derive(Debug, Default)]
+pub struct ChangedFiles {
+    paths: PathSet,
+}
+
+/// One patch against HEAD covering a set of files, as `git diff HEAD` would print it
+#[derive(Debug, Default)]
+pub struct SelectionPatch {
+    pub patch: String,
+    pub changed: usize,
+    pub new_files: Vec<String>, // Untracked or newly added, relative to the repository
+}
+
+/// The repository containing `root`, with its canonical workdir and the canonical root
+fn open_repository(root: &Path) -> Option<(Repository, PathBuf, PathBuf)> {
+    let repo = Repository::discover(root).ok()?;
+    let workdir = repo.workdir()?.canonicalize().ok()?;
+    let root = root.canonicalize().ok()?;
+    Some((repo, workdir, root))
+}
+
+impl TrackedFiles {
+    /// Read the git index of the repository containing `root`.
+    /// Returns `None` when `root` is not inside a git repository.
+    pub fn load(root: &Path) -> Option<Self> {
+        let (repo, workdir, root) = open_repository(root)?;
+        let index = repo.index().ok()?;
+
+        let mut tracked = Self::default();
+        for entry in index.iter() {
+            let entry_path = String::from_utf8_lossy(&entry.path).to_string();
+            tracked.paths.insert_under(&workdir.join(&entry_path), &root);
+        }
+
+        Some(tracked)
+    }
+
+    /// Whether a path relative to the scan root is tracked (directories count
+    /// as tracked when they contain at least one tracked file)
+    pub fn contains(&self, relative_path: &Path, is_directory: bool) -> bool {
+        self.paths.contains(relative_path, is_directory)
+    }
+}
+
+impl ChangedFiles {
+    /// Compare the working tree and index of the repository containing `root` with HEAD.
+    /// Returns `None` when `root` is not inside a git repository.
+    pub fn load(root: &Path) -> Option<Self> {
+        let (repo, workdir, root) = open_repository(root)?;
+        let mut options = StatusOptions::new();
+        options.include_untracked(true).recurse_untracked_dirs(true).include_ignored(false);
+        let statuses = repo.statuses(Some(&mut options)).ok()?;
+
+        let mut changed = Self::default();
+        for entry in statuses.iter() {
+            let status = entry.status();
+            if status.is_empty() || status.intersects(Status::IGNORED | Status::WT_DELETED | Status::INDEX_DELETED) {
+                continue; // Deleted files aren't in the tree to show
+            }
+            if let Ok(entry_path) = entry.path() {
+                changed.paths.insert_under(&workdir.join(entry_path), &root);
+            }
+        }
+
+        Some(changed)
+    }
+
+    /// Whether a path relative to the scan root changed (directories count as
+    /// changed when they contain at least one changed file)
+    pub fn contains(&self, relative_path: &Path, is_directory: bool) -> bool {
+        self.paths.contains(relative_path, is_directory)
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.files.len()
+    }
+}
+
+impl SelectionPatch {
+    /// Diff `files` (absolute paths) in the working tree against HEAD. Untracked files show
+    /// up as new files; files without changes and files outside the repository are left out.
+    pub fn load(root: &Path, files: &[PathBuf]) -> Result<Self> {
+        let (repo, workdir, _) =
+            open_repository(root).ok_or_else(|| anyhow!("{} is not inside a git repository", root.display()))?;
+        let head = repo.head().ok().and_then(|head| head.peel_to_tree().ok()); // None before the first commit
+
+        let mut options = DiffOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .show_untracked_content(true)
+            .disable_pathspec_match(true);
+        let mut any = false;
+        for file in files {
+            let Ok(path) = file.canonicalize() else { continue };
+            if let Ok(relative_path) = path.strip_prefix(&workdir) {
+                options.pathspec(relative_path.to_string_lossy().replace('\\', "/"));
+                any = true;
+            }
+        }
+        if !any {
+            return Ok(Self::default()); // An empty pathspec would match everything
+        }
+
+        let diff = repo.diff_tree_to_workdir_with_index(head.as_ref(), Some(&mut options))?;
+        let mut selection_patch = Self {
+            changed: diff.deltas().len(),
+            ..Self::default()
+        };
+        for delta in diff.deltas() {
+            if matches!(delta.status(), Delta::Untracked | Delta::Added)
+                && let Some(path) = delta.new_file().path()
+            {
+                selection_patch.new_files.push(path.to_string_lossy().to_string());
+            }
+        }
+        diff.print(DiffFormat::Patch, |_, _, line| {
+            if matches!(line.origin(), '+' | '-' | ' ') {
+                selection_patch.patch.push(line.origin());
+            }
+            selection_patch.patch.push_str(&String::from_utf8_lossy(line.content()));
+            true
+        })?;
+        Ok(selection_patch)
+    }
+}
+
+impl PathSet {
+    /// Only keep files under the scan root, keyed relative to it
+    fn insert_under(&mut self, absolute_path: &Path, root: &Path) {
+        if let Ok(relative_path) = absolute_path.strip_prefix(root) {
+            self.insert(relative_path.to_path_buf());
+        }
+    }
+
+    fn insert(&mut self, relative_path: PathBuf) {
+        let mut ancestor = relative_path.parent();
+        while let Some(dir) = ancestor {
+            if dir.as_os_str().is_empty() || !self.dirs.insert(dir.to_path_buf()) {
+                break;
+            }
+            ancestor = dir.parent();
+        }
+        self.files.insert(relative_path);
+    }
+
+    fn contains(&self, relative_path: &Path, is_directory: bool) -> bool {
+        if is_directory {
+            self.dirs.contains(relative_path)
+        } else {
+            self.files.contains(relative_path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_tracked_files() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path();
+        let repo = Repository::init(root_path)?;
+
+        fs::create_dir(root_path.join("src"))?;
+        fs::write(root_path.join("src").join("main.rs"), "fn main() {}")?;
+        fs::write(root_path.join("scratch.txt"), "notes")?;
+
+        let mut index = repo.index()?;
+        index.add_path(Path::new("src/main.rs"))?;
+        index.write()?;
+
+        let tracked = TrackedFiles::load(root_path).expect("inside a repository");
+        assert!(tracked.contains(Path::new("src/main.rs"), false));
+        assert!(tracked.contains(Path::new("src"), true));
+        assert!(!tracked.contains(Path::new("scratch.txt"), false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_changed_files() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path();
+        let repo = Repository::init(root_path)?;
+
+        fs::create_dir(root_path.join("src"))?;
+        fs::write(root_path.join("src").join("main.rs"), "fn main() {}")?;
+        fs::write(root_path.join("src").join("lib.rs"), "")?;
+        fs::write(root_path.join(".gitignore"), "target/\n")?;
+
+        // Commit everything once
+        let mut index = repo.index()?;
+        index.add_all(["."], git2::IndexAddOption::DEFAULT, None)?;
+        index.write()?;
+        let tree = repo.find_tree(index.write_tree()?)?;
+        let signature = git2::Signature::now("gthr", "gthr@example.com")?;
+        repo.commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[])?;
+
+        fs::write(root_path.join("src").join("main.rs"), "fn main() { changed(); }")?;
+        fs::write(root_path.join("notes.md"), "untracked")?;
+        fs::create_dir(root_path.join("target"))?;
+        fs::write(root_path.join("target").join("out"), "ignored")?;
+
+        let changed = ChangedFiles::load(root_path).expect("inside a repository");
+        assert!(changed.contains(Path::new("src/main.rs"), false));
+        assert!(changed.contains(Path::new("src"), true));
+        assert!(changed.contains(Path::new("notes.md"), false));
+        assert!(!changed.contains(Path::new("src/lib.rs"), false));
+        assert!(!changed.contains(Path::new("target/out"), false));
+        assert_eq!(changed.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_selection_patch() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path();
+        let repo = Repository::init(root_path)?;
+
+        fs::write(root_path.join("main.rs"), "fn main() {}\n")?;
+        fs::write(root_path.join("lib.rs"), "pub fn lib() {}\n")?;
+        let mut index = repo.index()?;
+        index.add_all(["."], git2::IndexAddOption::DEFAULT, None)?;
+        index.write()?;
+        let tree = repo.find_tree(index.write_tree()?)?;
+        let signature = git2::Signature::now("gthr", "gthr@example.com")?;
+        repo.commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[])?;
+
+        fs::write(root_path.join("main.rs"), "fn main() { changed(); }\n")?;
+        fs::write(root_path.join("lib.rs"), "pub fn lib() { changed(); }\n")?;
+        fs::write(root_path.join("notes.md"), "untracked\n")?;
+
+        // lib.rs changed too, but only selected files are in the patch
+        let files = [root_path.join("main.rs"), root_path.join("notes.md")];
+        let patch = SelectionPatch::load(root_path, &files)?;
+        assert_eq!(patch.changed, 2);
+        assert_eq!(patch.new_files, vec!["notes.md".to_string()]);
+        assert!(patch.patch.contains("+fn main() { changed(); }"));
+        assert!(patch.patch.contains("new file mode"));
+        assert!(!patch.patch.contains("lib.rs"));
+
+        // It applies cleanly to HEAD
+        fs::write(root_path.join("main.rs"), "fn main() {}\n")?;
+        fs::remove_file(root_path.join("notes.md"))?;
+        let diff = git2::Diff::from_buffer(patch.patch.as_bytes())?;
+        repo.apply(&diff, git2::ApplyLocation::WorkDir, None)?;
+        assert_eq!(fs::read_to_string(root_path.join("main.rs"))?, "fn main() { changed(); }\n");
+        assert_eq!(fs::read_to_string(root_path.join("notes.md"))?, "untracked\n");
+
+        assert_eq!(SelectionPatch::load(root_path, &[])?.patch, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_outside_repository() {
+        let temp_dir = TempDir::new().unwrap();
+        // A temp dir may itself live inside a repository; only assert when it doesn't
+        if Repository::discover(temp_dir.path()).is_err() {
+            assert!(TrackedFiles::load(temp_dir.path()).is_none());
+        }
+    }
+}
@@ -1,89 +1,406 @@
+mod bench;
+mod changelog;
 mod cli;
 mod config;
 mod constants;
 mod directory;
+mod event_log;
 mod fuzzy;
+mod git;
+mod model;
 mod output;
+mod select_cmd;
 mod ui;
 
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow};
 use clap::Parser;
 use cli::{Cli, Commands};
+use changelog::VersionStamp;
+use config::cache::ContentCache;
+use config::recipes::{Recipe, RecipeOutput, Recipes};
+use config::retention::Retention;
+use config::rules::Rules;
+use config::profile::Profile;
+use config::selection_set::{FileStamp, Rename, SelectionSet};
+use config::session::{ExportSnapshot, Session};
 use config::settings::Settings;
+use config::slots::ExportSlot;
 use constants::DEFAULT_MAX_FILE_SIZE;
 use crossterm::{
+    cursor::SetCursorStyle,
     event::{DisableMouseCapture, EnableMouseCapture},
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
+use directory::metadata::spawn_metadata_pass;
+use directory::scan::BackgroundScan;
 use directory::traversal::DirectoryTraverser;
-use output::formatter::OutputFormatter;
+use directory::watch::{ChangePoller, EventBatcher, POLL_INTERVAL};
+use git::{SelectionPatch, TrackedFiles};
+use model::SelectionModel;
+use output::extract::ExtractMatches;
+use output::formatter::{OutputFormatter, Spacing, format_file_size};
+use output::publish::{ExportOutput, Published, Publisher};
+use output::resume::{ExportProgress, ExportState};
+use output::serve::{Bundle, BundleServer, SERVE_TIMEOUT, Served};
+use output::tokens::{format_tokens, tokenizer};
 use output::writer::OutputWriter;
 use ratatui::{
     Terminal,
     backend::{Backend, CrosstermBackend},
 };
-use std::io;
-use std::time::Duration;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use ui::app::{App, AppMode};
 use ui::events::{AppAction, AppEvent, EventHandler, handle_key_event};
+use ui::hyperlink::{file_url, hyperlink, supports_hyperlinks, write_hyperlinks};
+use ui::terminal_title::{Progress, TerminalIntegration, window_title};
+use ui::icons::IconSet;
 use ui::interface::draw_ui;
+use ui::wizard::Wizard;
+
+/// How a run ended
+enum Outcome {
+    Exported(ExportSummary),
+    Aborted,
+}
+
+/// What an export wrote and where, for its summary line
+struct ExportSummary {
+    files: usize,
+    destination: String,
+    totals: Option<ExportTotals>,
+}
+
+/// Bytes and tokens a headless export wrote, for its summary line
+struct ExportTotals {
+    bytes: u64,
+    tokens: u64,
+}
+
+/// Failures split by phase, so wrapping scripts can tell bad input from a crash
+enum Failure {
+    Startup(anyhow::Error), // Invalid root, unreadable rules file, ...
+    Runtime(anyhow::Error),
+}
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
-    let settings = Settings::load_with_project_root(&cli.root);
+    if let Some(config) = cli.config.as_deref().filter(|config| !config.is_file()) {
+        let error = anyhow!("config file {} does not exist", config.display());
+        return shutdown(Err(Failure::Startup(error)), !cli.no_hyperlinks);
+    }
+    let paths = config::paths::init(cli.config.as_deref());
+    for notice in paths.migrate_legacy(&config::paths::PlatformDirs::current()) {
+        eprintln!("gthr: {}", notice);
+    }
+    let log_env = std::env::var(event_log::LOG_ENV).ok();
+    let log = event_log::resolve(cli.log_file.as_deref(), log_env.as_deref(), &paths.state_dir)
+        .and_then(|log| log.map_or(Ok(()), |(path, level)| event_log::init(&path, level)));
+    if let Err(e) = log {
+        return shutdown(Err(Failure::Startup(e)), !cli.no_hyperlinks);
+    }
+    event_log::info("start", &[("version", &env!("CARGO_PKG_VERSION")), ("root", &cli.root.display())]);
+    let mut settings = Settings::load_with_project_root(&cli.root);
+    if let Some(format) = &cli.format {
+        match export_format(format) {
+            Ok(format) => settings.format = format,
+            Err(e) => return shutdown(Err(Failure::Startup(e)), !cli.no_hyperlinks),
+        }
+    }
+
+    if cli.changelog {
+        print!("{}", changelog::render());
+        return ExitCode::SUCCESS;
+    }
+    if cli.bench_scan.is_some() || cli.bench_filter.is_some() {
+        return run_benchmark(&cli);
+    }
+    if let Some(Commands::Gc { dry_run }) = cli.command {
+        return run_gc(&cli, &settings, dry_run);
+    }
+    run_weekly_gc(&cli, &settings);
 
-    match cli.command.as_ref().unwrap_or(&Commands::Interactive) {
-        Commands::Interactive => {
-            run_interactive_mode(&cli, &settings).await?;
+    let result = if !cli.root.exists() {
+        Err(Failure::Startup(anyhow!("root {} does not exist", cli.root.display())))
+    } else {
+        // Without a terminal to draw on there's no interface to run, so export to stdout
+        let headless = cli.headless || (cli.command.is_none() && !io::stdout().is_terminal());
+        // A slot exports headlessly, so it implies direct mode
+        let default_command = if cli.slot.is_some() || cli.watch || headless { Commands::Direct } else { Commands::Interactive };
+        match cli.command.as_ref().unwrap_or(&default_command) {
+            Commands::Interactive => run_interactive_mode(&cli, &settings).await,
+            Commands::Direct => run_direct_mode(&cli, &settings, headless).await,
+            Commands::Run { recipe } => run_recipe(&cli, &settings, recipe),
+            Commands::Gc { .. } => unreachable!("handled above"),
         }
-        Commands::Direct => {
-            run_direct_mode(&cli, &settings).await?;
+    };
+
+    shutdown(result, !cli.no_hyperlinks)
+}
+
+/// The summary line of an export, on stderr
+fn report_export(summary: ExportSummary, hyperlinks: bool) {
+    let ExportSummary { files, mut destination, totals } = summary;
+    let noun = if files == 1 { "file" } else { "files" };
+    // The visible text stays the same, so scripts parsing the line aren't affected
+    if hyperlinks
+        && io::stderr().is_terminal()
+        && supports_hyperlinks()
+        && let Ok(path) = std::fs::canonicalize(&destination)
+        && path.is_file()
+    {
+        destination = hyperlink(&destination, &file_url(&path));
+    }
+    match totals {
+        Some(ExportTotals { bytes, tokens }) => eprintln!(
+            "gthr: exported {} {} ({}, ~{} tokens) to {}",
+            files,
+            noun,
+            format_file_size(bytes),
+            format_tokens(tokens),
+            destination
+        ),
+        None => eprintln!("gthr: exported {} {} to {}", files, noun, destination),
+    }
+}
+
+/// The registered format `--format` names, in any case
+fn export_format(format: &str) -> Result<String> {
+    let formats = OutputFormatter::new().formats();
+    let format = format.trim().to_lowercase();
+    if !formats.contains(&format.as_str()) {
+        return Err(anyhow!("--format: unknown format `{}` (available: {})", format, formats.join(", ")));
+    }
+    Ok(format)
+}
+
+/// Report how the run ended on stderr in one parsable line and map it to an exit code:
+/// 0 exported, 3 aborted by the user, 2 startup or validation error, 1 runtime failure
+fn shutdown(result: std::result::Result<Outcome, Failure>, hyperlinks: bool) -> ExitCode {
+    let code = match &result {
+        Ok(Outcome::Exported(_)) => 0,
+        Ok(Outcome::Aborted) => 3,
+        Err(Failure::Startup(_)) => 2,
+        Err(Failure::Runtime(_)) => 1,
+    };
+    if let Err(Failure::Startup(e) | Failure::Runtime(e)) = &result {
+        event_log::error("exit", &[("code", &code), ("error", &format!("{:#}", e))]);
+    } else {
+        event_log::info("exit", &[("code", &code)]);
+    }
+    event_log::flush();
+
+    match result {
+        Ok(Outcome::Exported(summary)) => {
+            report_export(summary, hyperlinks);
+            ExitCode::SUCCESS
+        }
+        Ok(Outcome::Aborted) => {
+            eprintln!("gthr: aborted by user");
+            ExitCode::from(3)
+        }
+        Err(Failure::Startup(e)) => {
+            eprintln!("gthr: error: {:#}", e);
+            ExitCode::from(2)
+        }
+        Err(Failure::Runtime(e)) => {
+            eprintln!("gthr: error: {:#}", e);
+            ExitCode::from(1)
         }
     }
+}
 
-    Ok(())
+/// `gthr gc`: prune now and list every removal on stdout
+fn run_gc(cli: &Cli, settings: &Settings, dry_run: bool) -> ExitCode {
+    match Retention::new(settings.cache_keep_days, create_publisher(cli, settings)).run(dry_run) {
+        Ok(removals) => {
+            let verb = if dry_run { "Would remove" } else { "Removed" };
+            for removal in &removals {
+                println!("{} {} ({})", verb, removal.path.display(), removal.reason);
+            }
+            println!("{} {} file(s)", verb, removals.len());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("gthr: error: {:#}", e);
+            ExitCode::from(1)
+        }
+    }
 }
 
-async fn run_interactive_mode(cli: &Cli, settings: &Settings) -> Result<()> {
-    // Setup terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+/// The automatic pass, at most once a week. It never fails a run; with `--verbose`
+/// removals and errors are reported on stderr.
+fn run_weekly_gc(cli: &Cli, settings: &Settings) {
+    let result = Retention::new(settings.cache_keep_days, create_publisher(cli, settings)).run_if_due();
+    if !cli.verbose {
+        return;
+    }
+    match result {
+        Ok(Some(removals)) => {
+            for removal in &removals {
+                eprintln!("gthr: removed {} ({})", removal.path.display(), removal.reason);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!("{} Cleanup failed: {:#}", IconSet::new(cli.ascii).warning, e),
+    }
+}
 
-    // Create application state
-    let max_file_size = if cli.max_file_size == DEFAULT_MAX_FILE_SIZE { // If using default CLI value
-        settings.max_file_size // Use config file value
-    } else {
-        cli.max_file_size // Use explicitly set CLI value
+/// Time a scan or filter of the root given to `--bench-scan` or `--bench-filter`, with that
+/// root's settings
+fn run_benchmark(cli: &Cli) -> ExitCode {
+    let root_and_query = match (&cli.bench_scan, cli.bench_filter.as_deref()) {
+        (Some(path), _) => Ok((path.as_path(), None)),
+        (None, Some([path, query])) => Ok((Path::new(path), Some(query.as_str()))),
+        _ => Err(anyhow!("--bench-filter takes a path and a query")),
     };
-    let respect_gitignore = cli.respect_gitignore.unwrap_or(settings.respect_gitignore);
-    let show_hidden = cli.show_hidden.unwrap_or(settings.show_hidden);
-    let traverser =
-        DirectoryTraverser::new(respect_gitignore, show_hidden, max_file_size, cli.include_all);
-    let mut tree = traverser.traverse(&cli.root)?;
+    let report = root_and_query.and_then(|(root, query)| {
+        let settings = Settings::load_with_project_root(root);
+        let traverser = create_traverser(cli, &settings, root);
+        match query {
+            None => bench::bench_scan(&traverser, root, cli.bench_iterations),
+            Some(query) => bench::bench_filter(&traverser, root, query, cli.bench_iterations),
+        }
+    });
+    match report.and_then(|report| Ok(serde_json::to_string_pretty(&report)?)) {
+        Ok(json) => {
+            println!("{}", json);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("gthr: error: {:#}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+async fn run_interactive_mode(cli: &Cli, settings: &Settings) -> std::result::Result<Outcome, Failure> {
+    // Create application state
+    // Sizes and binary detection are filled in once the UI is up
+    // Dropping the integration clears the progress again, so returning early on a startup
+    // error doesn't leave the terminal's indicator spinning
+    let mut terminal_integration = TerminalIntegration::detect(!cli.no_term_integration);
+    let _ = terminal_integration.set_progress(&mut io::stdout(), Progress::Indeterminate);
+    let traverser = create_traverser(cli, settings, &cli.root).with_deferred_metadata(true);
+    let mut tree = traverser.traverse(&cli.root).map_err(Failure::Startup)?;
+    let metadata_updates = spawn_metadata_pass(&tree, max_file_size(cli, settings));
+
+    // Shared rules first, command-line patterns override them
+    let rules = load_rules(cli, &cli.root).map_err(Failure::Startup)?;
+    let rules_summary = rules.as_ref().map(|rules| rules.apply(&mut tree));
+    // Profiles save what differs from this
+    tree.record_default_selection();
+    let content_cache = load_content_cache(cli, &cli.root);
+    let (selection_set, set_warnings, mut renames) =
+        load_selection_set(cli, &mut tree, content_cache.as_ref()).map_err(Failure::Startup)?;
+    let command_warnings = load_command_selection(cli, &mut tree).map_err(Failure::Startup)?;
+    let (profile, profile_warnings, profile_renames) =
+        load_profile(cli, &cli.root, &mut tree, true, content_cache.as_ref()).map_err(Failure::Startup)?;
+    renames.extend(profile_renames);
 
     // Apply include/exclude patterns if provided
     if !cli.include.is_empty() || !cli.exclude.is_empty() {
         apply_patterns(&mut tree, &cli.include, &cli.exclude);
     }
 
-    let mut app = App::new(tree);
+    let mut app = App::new(tree)
+        .with_rules(rules, rules_summary)
+        .with_selection_set(selection_set)
+        .with_profile(profile)
+        .with_select_command(cli.select_cmd.clone())
+        .with_legacy_typeahead(settings.legacy_typeahead)
+        .with_dim_common_prefix(settings.dim_common_prefix)
+        .with_size_column_min_width(settings.size_column_min_width)
+        .with_show_status_bar(settings.show_status_bar)
+        .with_match_sibling_directories(settings.match_sibling_directories)
+        .with_match_file_names(settings.match_file_names)
+        .with_token_budget(cli.max_tokens.or(settings.token_budget))
+        .with_fill_order(settings.budget_fill_order)
+        .with_preview_debounce(Duration::from_millis(settings.preview_debounce_ms))
+        .with_fold_level(settings.fold_level)
+        .with_line_sample_bytes(settings.line_sample_bytes)
+        .with_preview_cache_bytes(settings.preview_cache_bytes)
+        .with_theme(settings.theme)
+        .with_smart_include_auto(settings.smart_include_auto)
+        .with_icons(IconSet::new(cli.ascii))
+        .with_hyperlinks(!cli.no_hyperlinks && io::stdout().is_terminal() && supports_hyperlinks())
+        .with_terminal_integration(terminal_integration)
+        .with_session(Session::load(&cli.root))
+        .with_formatter(create_formatter(cli, settings, content_cache))
+        .with_metadata_updates(metadata_updates);
+    app.deferred_output.extend(set_warnings);
+    app.deferred_output.extend(command_warnings);
+    app.deferred_output.extend(profile_warnings);
+    app.offer_renames(renames);
+    load_recipes(&mut app, &cli.root);
+    app.run_startup(&settings.startup);
+    if Wizard::is_due(&cli.root, settings) && app.model.tree.archive.is_none() {
+        app.start_wizard(Wizard::new(settings, app.formatter.formats()));
+    }
+    // Another popup first (the wizard, renames) leaves the changes for the next launch
+    if app.view.mode == AppMode::Main {
+        let used_before = Session::get_sessions_dir().exists();
+        app.show_whats_new(VersionStamp::new().whats_new(used_before));
+    }
+
+    let result = run_terminal(&mut app, cli, settings).await;
+    if cli.verbose {
+        eprintln!("Preview cache: {}", app.recent_previews.describe());
+    }
+    save_profile(&mut app);
+    save_folds(&mut app);
+    save_content_cache(app.formatter.content_cache(), cli);
+    result.map_err(Failure::Runtime)?;
+    if let (Some(bundle), Some(port)) = (app.served_bundle.take(), cli.serve) {
+        app.exported_to = Some(serve_export(&bundle, port, app.icons).map_err(Failure::Runtime)?);
+    }
+
+    Ok(match app.exported_to.take() {
+        Some(destination) => Outcome::Exported(ExportSummary {
+            files: app.model.tree.get_all_included_files().len(),
+            destination,
+            totals: None,
+        }),
+        None => Outcome::Aborted,
+    })
+}
+
+async fn run_terminal(app: &mut App, cli: &Cli, settings: &Settings) -> Result<()> {
+    // Setup terminal
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        SetCursorStyle::SteadyBlock
+    )?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
 
     let event_handler = EventHandler::new();
-    let result = run_app(&mut terminal, &mut app, &event_handler, cli, settings).await;
+    let result = run_app(&mut terminal, app, &event_handler, cli, settings).await;
 
     // Restore terminal
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        SetCursorStyle::DefaultUserShape
     )?;
     terminal.show_cursor()?;
+    app.terminal_integration.finish(&mut io::stdout())?;
+
+    for output in &app.deferred_output {
+        eprintln!("{}", output);
+    }
 
     result
 }
@@ -96,7 +413,14 @@ async fn run_app<B: Backend>(
     settings: &Settings,
 ) -> Result<()> {
     loop {
-        terminal.draw(|f| draw_ui(f, app))?;
+        finish_rescan(app, cli, settings);
+        app.apply_metadata_updates();
+        app.refresh_selection_totals();
+        let frame = terminal.draw(|f| draw_ui(f, app))?;
+        if app.hyperlinks_enabled {
+            write_hyperlinks(&mut io::stdout(), frame.buffer, &app.hyperlinks)?;
+        }
+        update_terminal_title(app)?;
 
         if app.should_quit {
             break;
@@ -105,19 +429,24 @@ async fn run_app<B: Backend>(
         if let Some(event) = event_handler.next_event(Duration::from_millis(50))? {
             match event {
                 AppEvent::Key(key_event) => {
-                    if app.mode == AppMode::Help {
+                    if app.view.mode == AppMode::Help {
                         app.set_mode(AppMode::Main);
                         continue;
                     }
 
-                    if let Some(action) = handle_key_event(key_event, &app.mode) {
+                    if let Some(action) = handle_key_event(key_event, app) {
                         match action {
                             AppAction::Escape => app.handle_escape(),
+                            AppAction::Quit => app.quit(),
                             AppAction::Export => {
                                 handle_export(app, cli, settings)?;
                             }
+                            AppAction::ExportSlot(number) => handle_slot_export(app, cli, settings, number),
+                            AppAction::CopyExport => handle_clipboard_export(app, cli, settings),
+                            AppAction::SaveProfile => app.save_profile(),
                             AppAction::ShowHelp => app.set_mode(AppMode::Help),
                             AppAction::ToggleSelection => app.toggle_selection(),
+                            AppAction::BudgetFill => app.budget_fill(),
                             AppAction::MoveUp => app.move_up(),
                             AppAction::MoveDown => app.move_down(),
                             AppAction::PageUp => app.page_up(),
@@ -126,17 +455,149 @@ async fn run_app<B: Backend>(
                             AppAction::MoveToBottom => app.move_to_bottom(),
                             AppAction::SearchChar(c) => app.add_search_char(c),
                             AppAction::SearchBackspace => app.search_backspace(),
+                            AppAction::SearchDelete => app.search_delete(),
+                            AppAction::SearchCursorMove(direction) => app.move_search_cursor(direction),
+                            AppAction::SearchCursorHome => app.search_cursor_home(),
+                            AppAction::SearchCursorEnd => app.search_cursor_end(),
+                            AppAction::FocusSearch => app.focus_search(),
+                            AppAction::FocusList => app.focus_list(),
+                            AppAction::Jump => app.start_or_repeat_jump(),
+                            AppAction::JumpChar(c) => app.add_jump_char(c),
+                            AppAction::JumpBackspace => app.jump_backspace(),
                             AppAction::FileSaveChar(c) => app.add_file_save_char(c),
                             AppAction::FileSaveBackspace => app.file_save_backspace(),
                             AppAction::FileSaveConfirm => {
-                                if let Some(content) = &app.pending_content.clone() {
-                                    save_file_from_dialog(&app, content)?;
+                                if let Some(number) = app.file_save_slot.take() {
+                                    let input = app.file_save_input.trim().to_string();
+                                    let output = if input.is_empty() { config::slots::default_output(number) } else { input.into() };
+                                    app.file_save_input.clear();
+                                    app.set_mode(AppMode::Main);
+                                    match app.set_slot(number, output) {
+                                        Ok(_) => handle_slot_export(app, cli, settings, number),
+                                        Err(e) => app.set_status_message(format!("{} Failed to save slot {}: {:#}", app.icons.warning, number, e)),
+                                    }
+                                } else if let Some(content) = &app.pending_content.clone() {
+                                    let extension = export_extension(cli.patch, &app.formatter);
+                                    let path = save_file_from_dialog(app, content, extension)?;
+                                    let output = ExportOutput::File(std::path::Path::new(&path));
+                                    if let Some(message) = publish_export(cli, settings, &app.model.tree, output, extension, app.icons) {
+                                        app.deferred_output.push(message);
+                                    }
+                                    app.exported_to = Some(path);
+                                    record_export(app);
                                     app.quit();
                                 }
                             }
+                            AppAction::ShowExportDiff => app.show_export_diff(),
+                            AppAction::ShowInfo => app.show_node_info(),
+                            AppAction::ToggleLock => app.toggle_lock(),
+                            AppAction::MatchSiblings => app.match_siblings(),
+                            AppAction::PlanBulk(operation) => app.plan_bulk(operation),
+                            AppAction::PlanExcludeExtension => app.plan_exclude_extension(),
+                            AppAction::BulkConfirm => app.confirm_bulk(),
+                            AppAction::RenamesConfirm => app.confirm_renames(),
+                            AppAction::SetConflictOverwrite => app.overwrite_set(),
+                            AppAction::SetConflictSaveAsNew => app.save_set_as_new(),
+                            AppAction::SetConflictDiff => app.toggle_set_conflict_diff(),
+                            AppAction::EditExclusionReason => app.start_exclusion_reason(),
+                            AppAction::ReasonChar(c) => app.add_reason_char(c),
+                            AppAction::ReasonBackspace => app.reason_backspace(),
+                            AppAction::ReasonConfirm => app.confirm_exclusion_reason(),
+                            AppAction::ToggleStatusBar => app.toggle_status_bar(),
+                            AppAction::SetViewRoot => app.set_view_root(),
+                            AppAction::ResetViewRoot => app.reset_view_root(),
+                            AppAction::ReexportIfChanged => {
+                                if app.changed_since_last_export() {
+                                    app.handle_escape();
+                                    handle_export(app, cli, settings)?;
+                                } else {
+                                    app.export_diff_note =
+                                        Some("Nothing changed since the last export".to_string());
+                                }
+                            }
+                            AppAction::ToggleComparePin => app.toggle_compare_pin(),
+                            AppAction::ReloadRules => app.reload_rules(),
+                            AppAction::SaveSelectionSet => app.save_selection_set(),
+                            AppAction::SmartInclude => app.start_smart_include(),
+                            AppAction::SelectEssentials => app.select_essentials(),
+                            AppAction::SmartIncludeMove(delta) => app.move_smart_include(delta),
+                            AppAction::SmartIncludeToggle => app.toggle_smart_include_candidate(),
+                            AppAction::SmartIncludeConfirm => app.confirm_smart_include(),
+                            AppAction::ShowSuggestions => app.start_suggestions(),
+                            AppAction::TogglePreview => app.toggle_preview(),
+                            AppAction::ToggleTreeView => app.toggle_tree_view(),
+                            AppAction::CollapseDirectory => app.collapse_directory(),
+                            AppAction::ExpandDirectory => app.expand_directory(),
+                            AppAction::ToggleFold => app.toggle_fold(),
+                            AppAction::CycleMatchMode => app.cycle_match_mode(),
+                            AppAction::ToggleAll => app.toggle_all(),
+                            AppAction::ToggleVisual => app.toggle_visual(),
+                            AppAction::ApplyVisual => app.apply_visual(),
+                            AppAction::SelectNone => app.select_none(),
+                            AppAction::InvertSelection => app.invert_selection(),
+                            AppAction::SuggestionMove(delta) => app.move_suggestion(delta),
+                            AppAction::ExcludeSuggestion(position) => app.exclude_suggestion(position),
+                            AppAction::ShowExportOrder => app.start_export_order(),
+                            AppAction::CycleFormat => app.cycle_format(),
+                            AppAction::OrderMove(delta) => app.move_in_export_order(delta),
+                            AppAction::ToggleOrderMoving => app.toggle_order_moving(),
+                            AppAction::ClearManualOrder => app.clear_manual_order(),
+                            AppAction::ShowRecipes => app.show_recipes(),
+                            AppAction::RecipeMove(delta) => app.move_recipe_choice(delta),
+                            AppAction::RecipeConfirm => {
+                                if let Some(recipe) = app.confirm_recipe() {
+                                    handle_recipe_export(app, cli, settings, &recipe)?;
+                                }
+                            }
+                            AppAction::CopyPath => {
+                                let paths = app.current_path(false).into_iter().collect();
+                                copy_paths(app, paths);
+                            }
+                            AppAction::CopyAbsolutePath => {
+                                let paths = app.current_path(true).into_iter().collect();
+                                copy_paths(app, paths);
+                            }
+                            AppAction::CopyIncludedPaths => {
+                                app.finish_metadata();
+                                let paths = app.included_paths();
+                                copy_paths(app, paths);
+                            }
+                            AppAction::ScrollUp => app.scroll_diff(-1),
+                            AppAction::ScrollDown => app.scroll_diff(1),
+                            AppAction::ScrollPageUp => app.scroll_diff(-(app.view.viewport_height as isize)),
+                            AppAction::ScrollPageDown => app.scroll_diff(app.view.viewport_height as isize),
+                            AppAction::MarkForDiff => app.mark_for_diff(),
+                            AppAction::ToggleChangedFilter => app.toggle_changed_filter(),
+                            AppAction::ChangeRoot => app.start_change_root(),
+                            AppAction::RootChar(c) => app.add_root_char(c),
+                            AppAction::RootBackspace => app.root_backspace(),
+                            AppAction::WizardMove(delta) => app.move_wizard_choice(delta),
+                            AppAction::WizardChar(c) => app.add_wizard_char(c),
+                            AppAction::WizardBackspace => app.wizard_backspace(),
+                            AppAction::WizardConfirm => app.confirm_wizard_step(),
+                            AppAction::StartCommand => app.start_command(),
+                            AppAction::CommandChar(c) => app.add_command_char(c),
+                            AppAction::CommandBackspace => app.command_backspace(),
+                            AppAction::CommandConfirm => {
+                                app.confirm_command();
+                                if let Some(command) = app.pending_command.take() {
+                                    terminal.draw(|f| draw_ui(f, app))?; // Show the closed dialog while it runs
+                                    app.run_select_command(command);
+                                }
+                                if let Some(root) = app.pending_root.take() {
+                                    start_rescan(app, &root, cli, settings);
+                                }
+                            }
+                            AppAction::RootConfirm => {
+                                app.confirm_change_root();
+                                if let Some(root) = app.pending_root.take() {
+                                    start_rescan(app, &root, cli, settings);
+                                }
+                            }
                         }
                     }
                 }
+                AppEvent::Click(column, row) => app.handle_click(column, row),
                 AppEvent::Tick => {
                     // Handle periodic updates if needed
                 }
@@ -148,23 +609,495 @@ async fn run_app<B: Backend>(
     Ok(())
 }
 
-async fn run_direct_mode(cli: &Cli, settings: &Settings) -> Result<()> {
-    let max_file_size = if cli.max_file_size == DEFAULT_MAX_FILE_SIZE { // If using default CLI value
+/// Show the root and selection in the window title, and progress while the metadata pass runs
+fn update_terminal_title(app: &mut App) -> Result<()> {
+    let stats = app.get_stats();
+    // The session's root is canonical; the tree's is as given on the command line
+    let root = if app.session.root.as_os_str().is_empty() {
+        app.model.tree.scan_root_path()
+    } else {
+        app.session.root.as_path()
+    };
+    let title = window_title(root, stats.included_files, stats.total_size);
+    let progress = if stats.approximate || app.scan.is_some() { Progress::Indeterminate } else { Progress::Hidden };
+    app.terminal_integration.update(&mut io::stdout(), &title, progress)?;
+    Ok(())
+}
+
+/// Scan `root` in the background; a scan already running is cancelled and replaced
+fn start_rescan(app: &mut App, root: &Path, cli: &Cli, settings: &Settings) {
+    let traverser = create_traverser(cli, settings, root).with_deferred_metadata(true);
+    app.scan = Some(BackgroundScan::spawn(traverser, root));
+    app.set_status_message(format!("Scanning {} (Esc cancels)", root.display()));
+}
+
+/// Once the background scan is done, replace the app's tree with it, as if gthr had been
+/// started there: the new root's rules, session and content cache are loaded, the old tree's
+/// are saved
+fn finish_rescan(app: &mut App, cli: &Cli, settings: &Settings) {
+    let Some(result) = app.scan.as_ref().and_then(|scan| scan.try_finish()) else {
+        return;
+    };
+    let root = app.scan.take().map(|scan| scan.root().to_path_buf()).unwrap_or_default();
+    let root = root.as_path();
+    let mut tree = match result {
+        Ok(tree) => tree,
+        Err(e) => {
+            app.set_status_message(format!("{} Failed to scan {}: {}", app.icons.warning, root.display(), e));
+            return;
+        }
+    };
+    let metadata_updates = spawn_metadata_pass(&tree, max_file_size(cli, settings));
+    let rules = match load_rules(cli, root) {
+        Ok(rules) => rules,
+        Err(e) => {
+            app.set_status_message(format!("{} {:#}", app.icons.warning, e));
+            None
+        }
+    };
+    let rules_summary = rules.as_ref().map(|rules| rules.apply(&mut tree));
+    tree.record_default_selection();
+    // The old root's selection is saved before the new root's saved selection takes over
+    save_profile(app);
+    save_folds(app);
+    save_content_cache(app.formatter.content_cache(), cli);
+    let content_cache = load_content_cache(cli, root);
+    let (profile, renames) = match load_profile(cli, root, &mut tree, true, content_cache.as_ref()) {
+        Ok((profile, _, renames)) => (profile, renames),
+        Err(e) => {
+            app.set_status_message(format!("{} {:#}", app.icons.warning, e));
+            (None, Vec::new())
+        }
+    };
+
+    app.formatter = app.formatter.clone().with_content_cache(content_cache);
+    app.replace_tree(tree, metadata_updates, settings.keep_selection_on_rescan);
+    app.rules = rules;
+    app.rules_summary = rules_summary;
+    app.profile = profile;
+    app.adopt_session(Session::load(root));
+    app.set_status_message(format!("Scanned {}", root.display()));
+    app.offer_renames(renames);
+    load_recipes(app, root);
+}
+
+/// Export without the interface. Headless, the export goes to `--output` or stdout and
+/// nothing else is printed there, and a selection with no files in it is an error.
+async fn run_direct_mode(cli: &Cli, settings: &Settings, headless: bool) -> std::result::Result<Outcome, Failure> {
+    let (summary, tree) = export_direct(cli, settings, headless).await?;
+    if !cli.watch {
+        return Ok(Outcome::Exported(summary));
+    }
+    report_export(summary, !cli.no_hyperlinks);
+
+    // Poll what the export covered, and export again once a batch of changes settles
+    let mut poller = ChangePoller::new(&tree);
+    let mut batcher = EventBatcher::new(Duration::from_millis(cli.watch_debounce));
+    eprintln!("gthr: watching {} for changes, Ctrl+C stops", cli.root.display());
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let now = Instant::now();
+        for path in poller.poll() {
+            if batcher.push(path, now) {
+                eprintln!("gthr: changes pending");
+            }
+        }
+        let Some(changed) = batcher.take_ready(now) else {
+            continue;
+        };
+        let noun = if changed.len() == 1 { "path" } else { "paths" };
+        eprintln!("gthr: {} {} changed, exporting again", changed.len(), noun);
+        // One rescan per batch, so new files and rules changes are picked up too
+        match export_direct(cli, settings, headless).await {
+            Ok((summary, tree)) => {
+                report_export(summary, !cli.no_hyperlinks);
+                poller = ChangePoller::new(&tree);
+            }
+            Err(Failure::Startup(e) | Failure::Runtime(e)) => eprintln!("gthr: error: {:#}", e),
+        }
+    }
+}
+
+/// Scan, select and export once without the interface, returning the tree that was exported
+async fn export_direct(
+    cli: &Cli,
+    settings: &Settings,
+    headless: bool,
+) -> std::result::Result<(ExportSummary, directory::tree::DirectoryTree), Failure> {
+    let traverser = create_traverser(cli, settings, &cli.root);
+    let mut tree = traverser.traverse(&cli.root).map_err(Failure::Startup)?;
+
+    // Shared rules first, command-line patterns override them, as in the interface
+    let rules = load_rules(cli, &cli.root).map_err(Failure::Startup)?;
+    if let Some(rules) = &rules {
+        let summary = rules.apply(&mut tree);
+        for (line, pattern) in &summary.unmatched {
+            eprintln!(
+                "{} {}:{}: `{}` matched nothing",
+                IconSet::new(cli.ascii).warning,
+                rules.path.display(),
+                line,
+                pattern
+            );
+        }
+    }
+    tree.record_default_selection();
+
+    let content_cache = load_content_cache(cli, &cli.root);
+    let (_, set_warnings, mut renames) =
+        load_selection_set(cli, &mut tree, content_cache.as_ref()).map_err(Failure::Startup)?;
+    let command_warnings = load_command_selection(cli, &mut tree).map_err(Failure::Startup)?;
+    let (profile, profile_warnings, profile_renames) =
+        load_profile(cli, &cli.root, &mut tree, false, content_cache.as_ref()).map_err(Failure::Startup)?;
+    renames.extend(profile_renames);
+    apply_patterns(&mut tree, &cli.include, &cli.exclude);
+    // Files placed by hand in the interface go first here too, unless the profile set an order
+    if tree.manual_order.is_empty() {
+        tree.restore_manual_order(&Session::load(&cli.root).manual_order);
+    }
+    for warning in set_warnings.into_iter().chain(command_warnings).chain(profile_warnings) {
+        eprintln!("{}", warning);
+    }
+    // Remapping needs confirming, which only the interface can ask for
+    for rename in renames {
+        eprintln!(
+            "{} `{}` not found; it looks renamed to `{}`, start gthr interactively to remap it",
+            IconSet::new(cli.ascii).warning,
+            rename.from,
+            rename.to
+        );
+    }
+
+    let slot = match cli.slot {
+        Some(number) => Some(config::slots::resolve(number, &settings.slots, &Session::load(&cli.root)).ok_or_else(|| {
+            Failure::Startup(anyhow!(
+                "slot {} isn't set up: add a [slots.{}] table to .gthr.toml, or press F{} in the interface",
+                number,
+                number,
+                number
+            ))
+        })?),
+        None => None,
+    };
+    let output_path = match &slot {
+        Some(slot) => Some(slot.output_path(&cli.root)),
+        None => cli.output.clone(),
+    };
+
+    let mut model = SelectionModel::new(tree);
+    let exclusion_reasons = exclusion_reasons(rules.as_ref(), profile.as_ref(), &model.tree, settings);
+    let formatter = match &slot {
+        Some(slot) => create_slot_formatter(cli, settings, slot, content_cache.clone()),
+        None => create_formatter(cli, settings, content_cache.clone()),
+    };
+    let formatter = formatter.with_exclusion_reasons(exclusion_reasons);
+    let shared_names = model.recompute_totals(&formatter);
+    if !shared_names.is_empty() {
+        eprintln!(
+            "{} Included files share the name {}, exports use full paths to tell them apart",
+            IconSet::new(cli.ascii).warning,
+            shared_names.join(", ")
+        );
+    }
+    let files = model.stats().included_files;
+    if headless && files == 0 {
+        return Err(Failure::Startup(anyhow!(
+            "no files are included; check the --include and --exclude patterns, the rules or the profile"
+        )));
+    }
+    // With `--extract-matches`, files without a match are left out of the export
+    let exported = formatter.exported_files(&model.tree);
+    let (destination, totals) = if headless {
+        let (destination, totals) = handle_headless_output(&model.tree, cli, settings, &formatter, output_path.as_deref())
+            .map_err(Failure::Runtime)?;
+        (destination, Some(totals))
+    } else {
+        let destination = handle_direct_output(&model.tree, cli, settings, formatter, output_path.as_deref())
+            .map_err(Failure::Runtime)?;
+        (destination, None)
+    };
+    save_content_cache(content_cache, cli);
+
+    save_export_snapshot(&model.tree, &exported, cli);
+    if let Some(profile) = profile {
+        let mut profile = profile.with_selection(&model.tree);
+        profile.reasons.extend(rules.iter().flat_map(|rules| rules.exclusion_reasons(&model.tree)));
+        match profile.save_keeping_theirs() {
+            Ok(None) => {}
+            Ok(Some(theirs)) => eprintln!(
+                "{} {} was saved by someone else since it was loaded; this selection went to {}",
+                IconSet::new(cli.ascii).warning,
+                theirs.display(),
+                profile.path.display()
+            ),
+            Err(e) => eprintln!("{} {:#}", IconSet::new(cli.ascii).warning, e),
+        }
+    }
+
+    let summary = ExportSummary {
+        files: exported.len(),
+        destination,
+        totals,
+    };
+    Ok((summary, model.tree))
+}
+
+/// Remember what a headless export contained, for the next export diff
+fn save_export_snapshot(tree: &directory::tree::DirectoryTree, exported: &[PathBuf], cli: &Cli) {
+    let mut session = Session::load(&cli.root);
+    session.last_export = Some(ExportSnapshot::capture_files(tree, exported.iter().map(PathBuf::as_path)));
+    if let Err(e) = session.save() {
+        eprintln!("{} Failed to save export snapshot: {}", IconSet::new(cli.ascii).warning, e);
+    }
+}
+
+/// `gthr run <recipe>`: scan, apply the project's rules (or the recipe's), run the recipe's
+/// steps and export where it says. Nothing is prompted for: a clipboard that is unavailable
+/// or too small for the export is an error.
+fn run_recipe(cli: &Cli, settings: &Settings, name: &str) -> std::result::Result<Outcome, Failure> {
+    let formats = create_formatter(cli, settings, None).formats();
+    let recipes = Recipes::load(&cli.root, &formats).map_err(Failure::Startup)?;
+    let recipe = recipes.get(name).map_err(Failure::Startup)?.clone();
+    let icons = IconSet::new(cli.ascii);
+
+    let traverser = create_traverser(cli, settings, &cli.root);
+    let mut tree = traverser.traverse(&cli.root).map_err(Failure::Startup)?;
+    let rules = if recipe.rules.is_none() {
+        load_rules(cli, &cli.root).map_err(Failure::Startup)?
+    } else {
+        None
+    };
+    let rules_summary = rules.as_ref().map(|rules| rules.apply(&mut tree));
+
+    // The steps are interface actions, so they run against an app that is never drawn
+    let mut app = App::new(tree).with_icons(icons).with_rules(rules, rules_summary);
+    for warning in app.apply_recipe(&recipe).map_err(Failure::Startup)? {
+        eprintln!("{}", warning);
+    }
+
+    let content_cache = load_content_cache(cli, &cli.root);
+    let formatter = create_recipe_formatter(cli, settings, &recipe, content_cache.clone())
+        .with_exclusion_reasons(exclusion_reasons(app.rules.as_ref(), app.profile.as_ref(), &app.model.tree, settings));
+    let tree = &app.model.tree;
+    let (content, note) = export_content(tree, cli.patch || recipe.patch, &formatter).map_err(Failure::Runtime)?;
+    if let Some(note) = note {
+        eprintln!("{}", note);
+    }
+
+    let (destination, output) = match recipe.output() {
+        RecipeOutput::File(path) => {
+            write_export(&path, &content).map_err(Failure::Runtime)?;
+            println!("{} Output written to: {}", icons.success, path.display());
+            (path.display().to_string(), Some(path))
+        }
+        RecipeOutput::Clipboard => {
+            if content.len() > settings.max_clipboard_size {
+                return Err(Failure::Runtime(anyhow!(
+                    "export is too large for the clipboard ({} bytes > {}); give recipe `{}` an output file",
+                    content.len(),
+                    settings.format_clipboard_size(),
+                    recipe.name
+                )));
+            }
+            copy_to_clipboard(&content).map_err(|e| {
+                Failure::Runtime(anyhow!("clipboard unavailable ({}); give recipe `{}` an output file", e, recipe.name))
+            })?;
+            println!("{} Output copied to clipboard ({} bytes)", icons.success, content.len());
+            ("clipboard".to_string(), None)
+        }
+    };
+    let published = match &output {
+        Some(path) => ExportOutput::File(path),
+        None => ExportOutput::Content(&content),
+    };
+    let extension = export_extension(cli.patch || recipe.patch, &formatter);
+    if let Some(message) = publish_export(cli, settings, tree, published, extension, icons) {
+        eprintln!("{}", message);
+    }
+    let exported = formatter.exported_files(tree);
+    save_content_cache(content_cache, cli);
+    save_export_snapshot(tree, &exported, cli);
+
+    Ok(Outcome::Exported(ExportSummary {
+        files: exported.len(),
+        destination,
+        totals: None,
+    }))
+}
+
+fn max_file_size(cli: &Cli, settings: &Settings) -> u64 {
+    if cli.max_file_size == DEFAULT_MAX_FILE_SIZE { // If using default CLI value
         settings.max_file_size // Use config file value
     } else {
         cli.max_file_size // Use explicitly set CLI value
-    };
-    let respect_gitignore = cli.respect_gitignore.unwrap_or(settings.respect_gitignore);
+    }
+}
+
+fn create_traverser(cli: &Cli, settings: &Settings, root: &Path) -> DirectoryTraverser {
+    let max_file_size = max_file_size(cli, settings);
+    let respect_gitignore = !cli.no_gitignore && !cli.no_ignore && cli.respect_gitignore.unwrap_or(settings.respect_gitignore);
     let show_hidden = cli.show_hidden.unwrap_or(settings.show_hidden);
-    let traverser =
-        DirectoryTraverser::new(respect_gitignore, show_hidden, max_file_size, cli.include_all);
-    let mut tree = traverser.traverse(&cli.root)?;
+    let include_all = cli.include_all || (settings.include_all && !cli.exclude_all);
 
-    // Apply include/exclude patterns to the tree
-    apply_patterns(&mut tree, &cli.include, &cli.exclude);
+    let tracked_files = if cli.tracked_only {
+        let tracked_files = TrackedFiles::load(root);
+        if tracked_files.is_none() {
+            eprintln!(
+                "{} --tracked-only ignored: {} is not inside a git repository",
+                IconSet::new(cli.ascii).warning,
+                root.display()
+            );
+        }
+        tracked_files
+    } else {
+        None
+    };
 
-    handle_direct_output(&tree, cli, settings)?;
-    Ok(())
+    let default_ignores = if cli.no_default_ignores || cli.no_ignore { &[][..] } else { &settings.default_ignores[..] };
+    let (traverser, invalid_ignores) =
+        DirectoryTraverser::new(respect_gitignore, show_hidden, max_file_size, include_all)
+            .with_tracked_files(tracked_files)
+            .with_ignore_files(!cli.no_ignore)
+            .with_file_permissions(cli.file_perms)
+            .with_image_stubs(cli.image_stubs || cli.embed_images || settings.image_stubs)
+            .with_sort_order(settings.sort_order)
+            .with_default_ignores(default_ignores);
+    for pattern in invalid_ignores {
+        eprintln!("{} Ignoring invalid default_ignores pattern: {}", IconSet::new(cli.ascii).warning, pattern);
+    }
+    let (traverser, invalid_ignores) = traverser.with_ignore_patterns(root, &cli.ignore);
+    for pattern in invalid_ignores {
+        eprintln!("{} Ignoring invalid --ignore pattern: {}", IconSet::new(cli.ascii).warning, pattern);
+    }
+    traverser
+}
+
+/// Load and apply the `--set` file. Returns warnings for the paths that are missing, and
+/// separately the missing paths that look renamed, going by the content cache.
+fn load_selection_set(
+    cli: &Cli,
+    tree: &mut directory::tree::DirectoryTree,
+    content_cache: Option<&Arc<Mutex<ContentCache>>>,
+) -> Result<(Option<SelectionSet>, Vec<String>, Vec<Rename>)> {
+    let Some(path) = &cli.set else {
+        return Ok((None, Vec::new(), Vec::new()));
+    };
+    let selection_set = SelectionSet::load(path)?;
+    let summary = selection_set.apply(tree);
+    let renames = match content_cache.and_then(|content_cache| content_cache.lock().ok()) {
+        Some(content_cache) => selection_set.detect_renames(&summary.missing, tree, &content_cache),
+        None => Vec::new(),
+    };
+    let warnings = summary
+        .missing
+        .iter()
+        .filter(|relative_path| !renames.iter().any(|rename| &rename.from == *relative_path))
+        .map(|relative_path| {
+            format!("{} {}: `{}` not found", IconSet::new(cli.ascii).warning, path.display(), relative_path)
+        })
+        .collect();
+    Ok((Some(selection_set), warnings, renames))
+}
+
+/// Run `--select-cmd` and make the paths it prints the selection. Returns a warning for each
+/// path that isn't in the tree; a command that fails stops startup with its stderr.
+fn load_command_selection(cli: &Cli, tree: &mut directory::tree::DirectoryTree) -> Result<Vec<String>> {
+    let Some(command) = &cli.select_cmd else {
+        return Ok(Vec::new());
+    };
+    let paths = select_cmd::run(command, &cli.root).context("--select-cmd")?;
+    let summary = select_cmd::apply(&paths, tree);
+    let icons = IconSet::new(cli.ascii);
+    Ok(summary
+        .missing
+        .iter()
+        .map(|path| format!("{} --select-cmd: `{}` not found", icons.warning, path))
+        .collect())
+}
+
+/// Apply the profile to start from: the one named with `--profile`, else, with
+/// `use_default` and no `--set`, the project's default profile if one was saved. Returns
+/// the profile the selection is saved to (the one named with `--save-profile`, else the one
+/// started from; without `use_default`, only a named one), a warning for each saved path
+/// that's gone and, separately, the gone files that look renamed. Archives have no profiles.
+fn load_profile(
+    cli: &Cli,
+    root: &Path,
+    tree: &mut directory::tree::DirectoryTree,
+    use_default: bool,
+    content_cache: Option<&Arc<Mutex<ContentCache>>>,
+) -> Result<(Option<Profile>, Vec<String>, Vec<Rename>)> {
+    if tree.archive.is_some() {
+        return Ok((None, Vec::new(), Vec::new()));
+    }
+    let icons = IconSet::new(cli.ascii);
+    let path = Profile::get_profile_path(root, cli.profile.as_deref())?;
+    let mut warnings = Vec::new();
+    let loaded = if cli.profile.is_some() {
+        Some(Profile::load(&path)?)
+    } else if use_default && cli.set.is_none() && cli.select_cmd.is_none() && path.exists() {
+        // An unreadable default profile shouldn't keep gthr from starting
+        Profile::load(&path).map_err(|e| warnings.push(format!("{} {:#}", icons.warning, e))).ok()
+    } else {
+        None
+    };
+    let mut renames = Vec::new();
+    if let Some(profile) = &loaded {
+        let summary = profile.apply(tree);
+        if let Some(content_cache) = content_cache.and_then(|content_cache| content_cache.lock().ok()) {
+            renames = profile.detect_renames(&summary.missing, tree, &content_cache);
+        }
+        warnings.extend(
+            summary
+                .missing
+                .iter()
+                .filter(|relative_path| !renames.iter().any(|rename: &Rename| &rename.from == *relative_path))
+                .map(|relative_path| format!("{} {}: `{}` not found", icons.warning, path.display(), relative_path)),
+        );
+    }
+
+    let save_path = match &cli.save_profile {
+        Some(name) => Some(Profile::get_profile_path(root, Some(name))?),
+        None if use_default || loaded.is_some() => Some(path),
+        None => None,
+    };
+    // Saving elsewhere than the profile started from overwrites whatever is there now
+    let profile = save_path.map(|save_path| match loaded {
+        Some(loaded) if loaded.path == save_path => loaded,
+        loaded => Profile {
+            on_disk: FileStamp::of(&save_path),
+            path: save_path,
+            ..loaded.unwrap_or_default()
+        },
+    });
+    Ok((profile, warnings, renames))
+}
+
+/// Save the selection to the app's profile, once the terminal is back or before a rescan
+fn save_profile(app: &mut App) {
+    if let Err(e) = app.write_profile() {
+        eprintln!("{} {:#}", app.icons.warning, e);
+    }
+}
+
+fn save_folds(app: &mut App) {
+    if let Err(e) = app.save_folds() {
+        eprintln!("{} Failed to save folded directories: {}", app.icons.warning, e);
+    }
+}
+
+/// Load the rules file given with `--rules`, or the project's rules file if it exists
+fn load_rules(cli: &Cli, root: &Path) -> Result<Option<Rules>> {
+    if let Some(path) = &cli.rules {
+        return Rules::load(path).map(Some);
+    }
+
+    let path = Rules::get_project_rules_path(root);
+    if path.exists() {
+        Rules::load(&path).map(Some)
+    } else {
+        Ok(None)
+    }
 }
 
 fn apply_patterns(
@@ -229,13 +1162,11 @@ fn path_matches_pattern(path: &str, pattern: &str) -> bool {
     }
 
     // Handle common patterns
-    if pattern.ends_with("*") {
-        let prefix = &pattern[..pattern.len() - 1];
+    if let Some(prefix) = pattern.strip_suffix("*") {
         return path.starts_with(prefix);
     }
 
-    if pattern.starts_with("*") {
-        let suffix = &pattern[1..];
+    if let Some(suffix) = pattern.strip_prefix("*") {
         return path.ends_with(suffix);
     }
 
@@ -254,21 +1185,133 @@ fn path_matches_pattern(path: &str, pattern: &str) -> bool {
     }
 }
 
-fn handle_export(app: &mut App, _cli: &Cli, settings: &Settings) -> Result<()> {
-    let formatter = OutputFormatter::new()
+fn create_formatter(
+    cli: &Cli,
+    settings: &Settings,
+    content_cache: Option<Arc<Mutex<ContentCache>>>,
+) -> OutputFormatter {
+    OutputFormatter::new()
         .with_metadata(false)
-        .with_line_numbers(false);
+        .with_line_numbers(false)
+        .with_footer(cli.footer.clone().or_else(|| settings.footer.clone()))
+        .with_spacing(Spacing {
+            blank_before_header: settings.blank_before_header,
+            blank_after_header: settings.blank_after_header,
+        })
+        .with_final_newline(settings.final_newline)
+        .with_format(&settings.format)
+        .with_content_cache(content_cache)
+        .with_extract_matches(cli.extract_matches.clone().map(|pattern| ExtractMatches::new(pattern, cli.context)))
+        .with_uris(cli.uris)
+        .with_embedded_images(cli.embed_images.then_some(settings.embed_image_max_size))
+        .with_ascii(cli.ascii)
+}
 
-    let content = formatter.format_output(&app.tree)?;
+/// The project's cache of line and token counts, unless `--no-cache` is given or the root is an archive
+fn load_content_cache(cli: &Cli, root: &Path) -> Option<Arc<Mutex<ContentCache>>> {
+    (!cli.no_cache && root.is_dir()).then(|| Arc::new(Mutex::new(ContentCache::load(root))))
+}
+
+fn save_content_cache(content_cache: Option<Arc<Mutex<ContentCache>>>, cli: &Cli) {
+    let Some(Ok(mut content_cache)) = content_cache.as_ref().map(|content_cache| content_cache.lock()) else {
+        return;
+    };
+    if let Err(e) = content_cache.save() {
+        eprintln!("{} Failed to save content cache: {}", IconSet::new(cli.ascii).warning, e);
+    }
+}
+
+/// Exclusion reasons from the rules file, then those saved with the profile for other paths,
+/// for the export footer if `list_exclusion_reasons` is set
+fn exclusion_reasons(
+    rules: Option<&Rules>,
+    profile: Option<&Profile>,
+    tree: &directory::tree::DirectoryTree,
+    settings: &Settings,
+) -> Vec<(String, String)> {
+    if !settings.list_exclusion_reasons {
+        return Vec::new();
+    }
+    let mut reasons = rules.map(|rules| rules.exclusion_reasons(tree)).unwrap_or_default();
+    for (relative_path, reason) in profile.map(|profile| profile.exclusion_reasons(tree)).unwrap_or_default() {
+        if !reasons.iter().any(|(listed, _)| *listed == relative_path) {
+            reasons.push((relative_path, reason));
+        }
+    }
+    reasons
+}
+
+fn copy_to_clipboard(text: &str) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new()?;
+    clipboard.set_text(text)?;
+    Ok(())
+}
+
+/// Copy newline-separated paths, deferring them to stderr if no clipboard is available
+fn copy_paths(app: &mut App, paths: Vec<String>) {
+    if paths.is_empty() {
+        app.set_status_message("No paths to copy".to_string());
+        return;
+    }
+
+    let count = paths.len();
+    let noun = if count == 1 { "path" } else { "paths" };
+    let text = paths.join("\n");
+
+    match copy_to_clipboard(&text) {
+        Ok(()) => app.set_status_message(format!("Copied {} {}", count, noun)),
+        Err(_) => {
+            app.deferred_output.push(text);
+            app.set_status_message(format!(
+                "{} Clipboard unavailable, {} {} will be printed on exit",
+                app.icons.warning, count, noun
+            ));
+        }
+    }
+}
+
+fn handle_export(app: &mut App, cli: &Cli, settings: &Settings) -> Result<()> {
+    // Sizes and text detection must be final before anything is written
+    app.finish_metadata();
+    let formatter = create_formatter(cli, settings, app.formatter.content_cache())
+        .with_format(app.formatter.format())
+        .with_exclusion_reasons(exclusion_reasons(app.rules.as_ref(), app.profile.as_ref(), &app.model.tree, settings));
+
+    let _ = app.terminal_integration.set_progress(&mut io::stdout(), Progress::Indeterminate);
+    let (content, note) = match export_content(&app.model.tree, cli.patch, &formatter) {
+        Ok(export) => export,
+        Err(e) if cli.patch => {
+            app.set_status_message(format!("{} {:#}", app.icons.warning, e));
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+    app.deferred_output.extend(note);
+
+    if cli.serve.is_some() {
+        // Served once the interface is gone, so the URL stays on screen
+        let extension = export_extension(cli.patch, &formatter);
+        if let Some(message) = publish_export(cli, settings, &app.model.tree, ExportOutput::Content(&content), extension, app.icons) {
+            app.deferred_output.push(message);
+        }
+        app.served_bundle = Some(Bundle::new(&app.model.tree, content, extension));
+        record_export(app);
+        app.quit();
+        return Ok(());
+    }
 
     if content.len() <= settings.max_clipboard_size {
         // Try clipboard first
-        if let Ok(mut clipboard) = arboard::Clipboard::new() {
-            if clipboard.set_text(&content).is_ok() {
-                println!("✓ Output copied to clipboard ({} bytes)", content.len());
-                app.quit();
-                return Ok(());
+        if copy_to_clipboard(&content).is_ok() {
+            println!("{} Output copied to clipboard ({} bytes)", app.icons.success, content.len());
+            app.exported_to = Some("clipboard".to_string());
+            let extension = export_extension(cli.patch, &formatter);
+            if let Some(message) = publish_export(cli, settings, &app.model.tree, ExportOutput::Content(&content), extension, app.icons) {
+                app.deferred_output.push(message);
             }
+            record_export(app);
+            app.quit();
+            return Ok(());
         }
     }
 
@@ -277,45 +1320,472 @@ fn handle_export(app: &mut App, _cli: &Cli, settings: &Settings) -> Result<()> {
     Ok(())
 }
 
-fn handle_direct_output(tree: &directory::tree::DirectoryTree, cli: &Cli, settings: &Settings) -> Result<()> {
-    if let Some(output_path) = &cli.output {
-        let formatter = OutputFormatter::new()
-            .with_metadata(false)
-            .with_line_numbers(false);
+/// Ctrl+Y: copy the export to the clipboard and stay in the interface. An export too large
+/// for the clipboard, or no clipboard at all (a headless SSH session), is reported instead.
+fn handle_clipboard_export(app: &mut App, cli: &Cli, settings: &Settings) {
+    app.finish_metadata();
+    let files = app.model.tree.get_all_included_files().len();
+    if files == 0 {
+        app.set_status_message("Nothing included to copy".to_string());
+        return;
+    }
+    let formatter = create_formatter(cli, settings, app.formatter.content_cache())
+        .with_format(app.formatter.format())
+        .with_exclusion_reasons(exclusion_reasons(app.rules.as_ref(), app.profile.as_ref(), &app.model.tree, settings));
+    let copied = export_content(&app.model.tree, cli.patch, &formatter).and_then(|(content, _)| {
+        if content.len() > settings.max_clipboard_size {
+            return Err(anyhow!(
+                "export is too large for the clipboard ({} > {}), Ctrl+E saves it to a file",
+                format_file_size(content.len() as u64),
+                settings.format_clipboard_size()
+            ));
+        }
+        copy_to_clipboard(&content).map_err(|e| anyhow!("clipboard unavailable ({}), Ctrl+E saves to a file", e))?;
+        Ok(content)
+    });
+    match copied {
+        Ok(content) => {
+            app.set_status_message(format!(
+                "{} Copied {} {} to clipboard ({})",
+                app.icons.success,
+                files,
+                if files == 1 { "file" } else { "files" },
+                format_file_size(content.len() as u64)
+            ));
+            app.exported_to = Some("clipboard".to_string());
+            let extension = export_extension(cli.patch, &formatter);
+            if let Some(message) = publish_export(cli, settings, &app.model.tree, ExportOutput::Content(&content), extension, app.icons) {
+                app.deferred_output.push(message);
+            }
+            record_export(app);
+        }
+        Err(e) => app.set_status_message(format!("{} {:#}", app.icons.warning, e)),
+    }
+}
+
+/// F1-F4: export to the slot's file in its format and stay in the interface. A slot used
+/// for the first time asks for its file.
+fn handle_slot_export(app: &mut App, cli: &Cli, settings: &Settings, number: u8) {
+    let Some(slot) = config::slots::resolve(number, &settings.slots, &app.session) else {
+        app.start_slot_setup(number);
+        return;
+    };
+    app.finish_metadata();
+    let formatter = create_slot_formatter(cli, settings, &slot, app.formatter.content_cache())
+        .with_exclusion_reasons(exclusion_reasons(app.rules.as_ref(), app.profile.as_ref(), &app.model.tree, settings));
+    let path = slot.output_path(&cli.root);
+    let written = export_content(&app.model.tree, cli.patch, &formatter)
+        .and_then(|(content, _)| write_export(&path, &content));
+    match written {
+        Ok(()) => {
+            let files = app.model.tree.get_all_included_files().len();
+            app.set_status_message(format!(
+                "{} Slot {} wrote {} {} to {}",
+                app.icons.success,
+                number,
+                files,
+                if files == 1 { "file" } else { "files" },
+                path.display()
+            ));
+            app.exported_to = Some(path.display().to_string());
+            record_export(app);
+        }
+        Err(e) => app.set_status_message(format!("{} Slot {}: {:#}", app.icons.warning, number, e)),
+    }
+}
+
+fn create_slot_formatter(
+    cli: &Cli,
+    settings: &Settings,
+    slot: &ExportSlot,
+    content_cache: Option<Arc<Mutex<ContentCache>>>,
+) -> OutputFormatter {
+    let formatter = create_formatter(cli, settings, content_cache);
+    match &slot.format {
+        Some(format) => formatter.with_format(format),
+        None => formatter,
+    }
+}
+
+/// The recipes defined for `root`. An invalid one is reported and none are offered, rather
+/// than keeping the interface from starting.
+fn load_recipes(app: &mut App, root: &Path) {
+    match Recipes::load(root, &app.formatter.formats()) {
+        Ok(recipes) => app.recipes = recipes.all(),
+        Err(e) => {
+            let message = format!("{} {:#}", app.icons.warning, e);
+            app.set_status_message(message.clone());
+            app.deferred_output.push(message);
+            app.recipes.clear();
+        }
+    }
+}
+
+fn create_recipe_formatter(
+    cli: &Cli,
+    settings: &Settings,
+    recipe: &Recipe,
+    content_cache: Option<Arc<Mutex<ContentCache>>>,
+) -> OutputFormatter {
+    let formatter = create_formatter(cli, settings, content_cache).with_tail_lines(recipe.tail_lines);
+    match &recipe.format {
+        Some(format) => formatter.with_format(format),
+        None => formatter,
+    }
+}
+
+/// Apply a recipe picked in the interface to the selection and export as it says. A recipe
+/// that can't be applied or exported leaves the interface open with a warning.
+fn handle_recipe_export(app: &mut App, cli: &Cli, settings: &Settings, recipe: &Recipe) -> Result<()> {
+    match app.apply_recipe(recipe) {
+        Ok(warnings) => app.deferred_output.extend(warnings),
+        Err(e) => {
+            app.set_status_message(format!("{} {:#}", app.icons.warning, e));
+            return Ok(());
+        }
+    }
+    app.finish_metadata();
+    let formatter = create_recipe_formatter(cli, settings, recipe, app.formatter.content_cache())
+        .with_exclusion_reasons(exclusion_reasons(app.rules.as_ref(), app.profile.as_ref(), &app.model.tree, settings));
+    let (content, note) = match export_content(&app.model.tree, cli.patch || recipe.patch, &formatter) {
+        Ok(export) => export,
+        Err(e) => {
+            app.set_status_message(format!("{} Recipe `{}`: {:#}", app.icons.warning, recipe.name, e));
+            return Ok(());
+        }
+    };
+    app.deferred_output.extend(note);
+
+    let extension = export_extension(cli.patch || recipe.patch, &formatter);
+    let publish_message = match recipe.output() {
+        RecipeOutput::File(path) => {
+            write_export(&path, &content)?;
+            println!("{} Output written to: {}", app.icons.success, path.display());
+            app.exported_to = Some(path.display().to_string());
+            publish_export(cli, settings, &app.model.tree, ExportOutput::File(&path), extension, app.icons)
+        }
+        RecipeOutput::Clipboard if content.len() <= settings.max_clipboard_size && copy_to_clipboard(&content).is_ok() => {
+            println!("{} Output copied to clipboard ({} bytes)", app.icons.success, content.len());
+            app.exported_to = Some("clipboard".to_string());
+            publish_export(cli, settings, &app.model.tree, ExportOutput::Content(&content), extension, app.icons)
+        }
+        RecipeOutput::Clipboard => {
+            // Too large or no clipboard: ask for a file, as a plain export would
+            app.start_file_save(content);
+            return Ok(());
+        }
+    };
+    app.deferred_output.extend(publish_message);
+    record_export(app);
+    app.quit();
+    Ok(())
+}
+
+/// Extension of saved and published exports: `patch` for patches, else the format's
+fn export_extension(patch: bool, formatter: &OutputFormatter) -> &'static str {
+    if patch { "patch" } else { formatter.extension() }
+}
+
+/// The export text: the formatted selection, or with `--patch` one diff of it against HEAD,
+/// along with a note saying what the patch covers
+fn export_content(
+    tree: &directory::tree::DirectoryTree,
+    patch: bool,
+    formatter: &OutputFormatter,
+) -> Result<(String, Option<String>)> {
+    let started = Instant::now();
+    let kind = export_extension(patch, formatter);
+    if event_log::enabled(event_log::Level::Info) {
+        let files = tree.get_all_included_files().len();
+        event_log::info("export.begin", &[("format", &kind), ("files", &files)]);
+    }
+    let result = build_export(tree, patch, formatter);
+    match &result {
+        Ok((content, _)) => event_log::info("export.end", &[
+            ("format", &kind),
+            ("bytes", &content.len()),
+            ("ms", &started.elapsed().as_millis()),
+        ]),
+        Err(e) => event_log::error("export.error", &[("format", &kind), ("error", &e)]),
+    }
+    result
+}
+
+fn build_export(
+    tree: &directory::tree::DirectoryTree,
+    patch: bool,
+    formatter: &OutputFormatter,
+) -> Result<(String, Option<String>)> {
+    if !patch {
+        return Ok((formatter.format_output(tree)?, None));
+    }
+    if tree.archive.is_some() {
+        return Err(anyhow!("--patch needs a git working tree, not an archive"));
+    }
+
+    let files: Vec<_> = tree.get_all_included_files().iter().map(|node| node.path.clone()).collect();
+    let patch = SelectionPatch::load(tree.scan_root_path(), &files)?;
+    if patch.changed == 0 {
+        return Err(anyhow!("none of the included files differ from HEAD"));
+    }
+    let mut note = format!(
+        "Patch covers {} changed {}",
+        patch.changed,
+        if patch.changed == 1 { "file" } else { "files" }
+    );
+    if !patch.new_files.is_empty() {
+        note.push_str(&format!(", new: {}", patch.new_files.join(", ")));
+    }
+    Ok((patch.patch, Some(note)))
+}
+
+fn record_export(app: &mut App) {
+    if let Err(e) = app.record_export() {
+        eprintln!("{} Failed to save export snapshot: {}", app.icons.warning, e);
+    }
+    save_profile(app);
+}
+
+/// Write the export and return where it went
+fn handle_direct_output(
+    tree: &directory::tree::DirectoryTree,
+    cli: &Cli,
+    settings: &Settings,
+    formatter: OutputFormatter,
+    output_path: Option<&Path>,
+) -> Result<String> {
+    let icons = IconSet::new(cli.ascii);
+    let extension = export_extension(cli.patch, &formatter);
+    if let Some(port) = cli.serve {
+        let (content, note) = export_content(tree, cli.patch, &formatter)?;
+        if let Some(note) = note {
+            eprintln!("{}", note);
+        }
+        if let Some(message) = publish_export(cli, settings, tree, ExportOutput::Content(&content), extension, icons) {
+            eprintln!("{}", message);
+        }
+        return serve_export(&Bundle::new(tree, content, extension), port, icons);
+    }
+    if cli.patch {
+        return handle_direct_patch(tree, cli, settings, &formatter, output_path);
+    }
+    let (destination, publish_message) = if let Some(output_path) = output_path {
+        let started = Instant::now();
+        event_log::info("export.begin", &[("output", &output_path.display())]);
         let writer = OutputWriter::new().with_formatter(formatter);
-        writer.write_to_file(tree, output_path)?;
-        println!("✓ Output written to: {}", output_path.display());
+        let written = if writer.append_safe() {
+            let progress = ExportProgress::new(tree.scan_root_path());
+            let resume = writer
+                .resumable(tree, output_path, &progress)
+                .filter(|state| !cli.watch && confirm_resume(state, output_path)); // Watching never asks
+            writer.stream_to_file(tree, output_path, &progress, resume.as_ref())
+        } else {
+            writer.write_to_file(tree, output_path)
+        };
+        if let Err(e) = &written {
+            event_log::error("export.error", &[("output", &output_path.display()), ("error", &e)]);
+        }
+        written?;
+        event_log::info("export.end", &[
+            ("output", &output_path.display()),
+            ("ms", &started.elapsed().as_millis()),
+        ]);
+        println!("{} Output written to: {}", icons.success, output_path.display());
+        let publish_message = publish_export(cli, settings, tree, ExportOutput::File(output_path), extension, icons);
+        (output_path.display().to_string(), publish_message)
     } else {
-        let formatter = OutputFormatter::new()
-            .with_metadata(false)
-            .with_line_numbers(false);
-        let content = formatter.format_output(tree)?;
-
-        if content.len() <= settings.max_clipboard_size {
-            // Try clipboard first
-            if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                if clipboard.set_text(&content).is_ok() {
-                    println!("✓ Output copied to clipboard ({} bytes)", content.len());
-                    return Ok(());
-                }
-            }
+        let (content, _) = export_content(tree, false, &formatter)?;
+
+        // Try clipboard first; if it's too large or unavailable, use text prompt
+        if content.len() <= settings.max_clipboard_size && copy_to_clipboard(&content).is_ok() {
+            println!("{} Output copied to clipboard ({} bytes)", icons.success, content.len());
+            let publish_message = publish_export(cli, settings, tree, ExportOutput::Content(&content), extension, icons);
+            ("clipboard".to_string(), publish_message)
+        } else {
+            let path = save_file_with_text_prompt(tree, &content, extension, settings, icons)?;
+            let output = ExportOutput::File(std::path::Path::new(&path));
+            let publish_message = publish_export(cli, settings, tree, output, extension, icons);
+            (path, publish_message)
         }
+    };
 
-        // Either too large or clipboard failed - use text prompt
-        save_file_with_text_prompt(tree, &content, settings)?;
+    if let Some(message) = publish_message {
+        eprintln!("{}", message);
     }
+    Ok(destination)
+}
+
+/// Ask whether to finish an interrupted export to `output_path` rather than start over.
+/// Without a terminal to ask on, it starts over.
+fn confirm_resume(state: &ExportState, output_path: &Path) -> bool {
+    use std::io::Write;
+
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+    print!(
+        "An export to {} was interrupted after {} of {} files{}. Resume it? [Y/n] ",
+        output_path.display(),
+        state.completed_entries,
+        state.total_entries,
+        state.last_entry.as_ref().map(|path| format!(" ({})", path)).unwrap_or_default()
+    );
+    let _ = io::stdout().flush();
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input).is_err() {
+        return false;
+    }
+    matches!(input.trim().to_lowercase().as_str(), "" | "y" | "yes")
+}
+
+/// `--patch` in direct mode: the patch goes to `-o`, else to the clipboard or a prompted file
+fn handle_direct_patch(
+    tree: &directory::tree::DirectoryTree,
+    cli: &Cli,
+    settings: &Settings,
+    formatter: &OutputFormatter,
+    output_path: Option<&Path>,
+) -> Result<String> {
+    let icons = IconSet::new(cli.ascii);
+    let (content, note) = export_content(tree, cli.patch, formatter)?;
+    if let Some(note) = note {
+        eprintln!("{}", note);
+    }
+
+    if let Some(output_path) = output_path {
+        write_export(output_path, &content)?;
+        println!("{} Output written to: {}", icons.success, output_path.display());
+        Ok(output_path.display().to_string())
+    } else if content.len() <= settings.max_clipboard_size && copy_to_clipboard(&content).is_ok() {
+        println!("{} Output copied to clipboard ({} bytes)", icons.success, content.len());
+        Ok("clipboard".to_string())
+    } else {
+        save_file_with_text_prompt(tree, &content, "patch", settings, icons)
+    }
+}
+
+/// `--headless`: write the export to `output_path`, or else to stdout, keeping stdout free
+/// of anything else so it can be piped
+fn handle_headless_output(
+    tree: &directory::tree::DirectoryTree,
+    cli: &Cli,
+    settings: &Settings,
+    formatter: &OutputFormatter,
+    output_path: Option<&Path>,
+) -> Result<(String, ExportTotals)> {
+    use std::io::Write;
+
+    let icons = IconSet::new(cli.ascii);
+    let (content, note) = export_content(tree, cli.patch, formatter)?;
+    if let Some(note) = note {
+        eprintln!("{}", note);
+    }
+    let (destination, published) = match output_path {
+        Some(output_path) => {
+            write_export(output_path, &content)?;
+            (output_path.display().to_string(), ExportOutput::File(output_path))
+        }
+        None => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(content.as_bytes()).and_then(|_| stdout.flush()).context("Failed to write to stdout")?;
+            ("stdout".to_string(), ExportOutput::Content(&content))
+        }
+    };
+    if let Some(message) = publish_export(cli, settings, tree, published, export_extension(cli.patch, formatter), icons) {
+        eprintln!("{}", message);
+    }
+    let totals = ExportTotals {
+        bytes: content.len() as u64,
+        tokens: tokenizer().count(content.as_bytes()),
+    };
+    Ok((destination, totals))
+}
+
+/// `--serve`: offer the export on localhost until it's downloaded, the timeout passes or
+/// Ctrl+C is pressed, and return its URL
+fn serve_export(bundle: &Bundle, port: u16, icons: &IconSet) -> Result<String> {
+    let server = BundleServer::bind(port)?;
+    let addr = server.local_addr()?;
+    let url = format!("http://{}{}", addr, bundle.path());
+    println!("{} Serving the export at {} (manifest at http://{}{})", icons.success, url, addr, bundle.manifest_path());
+    println!("  Stops after one download or {} minutes; press Ctrl+C to stop now", SERVE_TIMEOUT.as_secs() / 60);
 
+    let stop = Arc::new(AtomicBool::new(false));
+    let ctrl_c = {
+        let stop = Arc::clone(&stop);
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                stop.store(true, Ordering::Relaxed);
+            }
+        })
+    };
+    let served = tokio::task::block_in_place(|| server.serve(bundle, &stop));
+    ctrl_c.abort();
+    match served? {
+        Served::Downloaded => Ok(url),
+        Served::TimedOut => Err(anyhow!("{} wasn't downloaded within {} minutes", url, SERVE_TIMEOUT.as_secs() / 60)),
+        Served::Stopped => Err(anyhow!("stopped serving {} before it was downloaded", url)),
+    }
+}
+
+/// Write an export to `path`, creating its directory if needed
+fn write_export(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, content)?;
     Ok(())
 }
 
-fn save_file_with_text_prompt(tree: &directory::tree::DirectoryTree, content: &str, settings: &Settings) -> Result<()> {
+/// Copy the export into the publish directory, if one is configured. Publishing never
+/// fails the export: the result is reported as a success line or a warning.
+fn publish_export(
+    cli: &Cli,
+    settings: &Settings,
+    tree: &directory::tree::DirectoryTree,
+    output: ExportOutput,
+    extension: &str,
+    icons: &IconSet,
+) -> Option<String> {
+    let publisher = create_publisher(cli, settings)?;
+
+    Some(match publisher.publish(&OutputWriter::generate_default_filename(tree, extension), output) {
+        Ok(Published { path, prune_error: None }) => format!("{} Published to: {}", icons.success, path.display()),
+        Ok(Published { path, prune_error: Some(e) }) => format!(
+            "{} Published to: {} ({} failed to prune old exports: {:#})",
+            icons.success,
+            path.display(),
+            icons.warning,
+            e
+        ),
+        Err(e) => format!("{} Failed to publish export: {:#}", icons.warning, e),
+    })
+}
+
+fn create_publisher(cli: &Cli, settings: &Settings) -> Option<Publisher> {
+    let dir = cli.publish_dir.clone().or_else(|| settings.publish_dir.clone())?;
+    Some(
+        Publisher::new(dir)
+            .with_retention(settings.publish_keep_days, settings.publish_keep_files)
+            .with_max_bytes(settings.publish_max_bytes),
+    )
+}
+
+fn save_file_with_text_prompt(
+    tree: &directory::tree::DirectoryTree,
+    content: &str,
+    extension: &str,
+    settings: &Settings,
+    icons: &IconSet,
+) -> Result<String> {
     use std::fs;
     use std::io::{self, Write};
     use std::path::Path;
 
     if content.len() > settings.max_clipboard_size {
         println!(
-            "⚠ Output is too large for clipboard ({} bytes > {})",
+            "{} Output is too large for clipboard ({} bytes > {})",
+            icons.warning,
             content.len(),
             settings.format_clipboard_size()
         );
@@ -329,11 +1799,11 @@ fn save_file_with_text_prompt(tree: &directory::tree::DirectoryTree, content: &s
     let input = input.trim();
 
     let filename = if input.is_empty() {
-        OutputWriter::generate_default_filename(tree)
+        OutputWriter::generate_default_filename(tree, extension)
     } else {
-        // Add .md extension if not present and doesn't have any extension
+        // Add the export's extension if not present and doesn't have any extension
         if !input.contains('.') {
-            format!("{}.md", input)
+            format!("{}.{}", input, extension)
         } else {
             input.to_string()
         }
@@ -347,22 +1817,22 @@ fn save_file_with_text_prompt(tree: &directory::tree::DirectoryTree, content: &s
     }
 
     fs::write(path, content)?;
-    println!("✓ Output saved to: {}", path.display());
-    Ok(())
+    println!("{} Output saved to: {}", icons.success, path.display());
+    Ok(path.display().to_string())
 }
 
-fn save_file_from_dialog(app: &App, content: &str) -> Result<()> {
+fn save_file_from_dialog(app: &App, content: &str, extension: &str) -> Result<String> {
     use std::fs;
     use std::path::Path;
 
     let filename = if app.file_save_input.trim().is_empty() {
         // Generate default filename
-        OutputWriter::generate_default_filename(&app.tree)
+        OutputWriter::generate_default_filename(&app.model.tree, extension)
     } else {
         let input = app.file_save_input.trim();
-        // Add .md extension if not present and doesn't have any extension
+        // Add the export's extension if not present and doesn't have any extension
         if !input.contains('.') {
-            format!("{}.md", input)
+            format!("{}.{}", input, extension)
         } else {
             input.to_string()
         }
@@ -376,7 +1846,7 @@ fn save_file_from_dialog(app: &App, content: &str) -> Result<()> {
     }
 
     fs::write(path, content)?;
-    println!("✓ Output saved to: {}", path.display());
-    Ok(())
+    println!("{} Output saved to: {}", app.icons.success, path.display());
+    Ok(path.display().to_string())
 }
 
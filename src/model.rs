@@ -0,0 +1,597 @@
+use crate::directory::state::SelectionState;
+use crate::directory::tree::{DirectoryTree, FileCounts, FileNode};
+use crate::fuzzy::filter::{FilteredResults, filter_tree_nodes_where};
+use crate::fuzzy::matcher::MatchMode;
+use crate::git::ChangedFiles;
+use crate::output::formatter::{OutputFormatter, format_file_size};
+use crate::output::tokens::{estimate_tokens, format_tokens};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// What gets exported, independent of any terminal: the tree with its selection states,
+/// the query and the files it matches, and totals over both. The interface drives one
+/// through `App`; direct mode uses one on its own.
+pub struct SelectionModel {
+    pub tree: DirectoryTree,
+    pub query: String,
+    pub filtered_results: FilteredResults,
+    pub changed_files: Option<ChangedFiles>, // Only files differing from git HEAD match
+    pub match_file_names: bool, // Match names instead of paths relative to the root
+    pub match_mode: MatchMode,
+    pub invalid_query: bool, // The query doesn't compile in `match_mode`; the last matches stay listed
+    compiled_query: Option<(MatchMode, String, Option<Regex>)>, // Reused until the query or mode changes
+    selection_totals: SelectionTotals,
+    filter_totals: Option<FilterTotals>, // Totals over the matches while a query is active
+    directory_sizes: Vec<Option<u64>>, // By node index, see `DirectoryTree::directory_sizes`
+}
+
+impl SelectionModel {
+    pub fn new(tree: DirectoryTree) -> Self {
+        let mut model = Self {
+            tree,
+            query: String::new(),
+            filtered_results: FilteredResults::new(),
+            changed_files: None,
+            match_file_names: false,
+            match_mode: MatchMode::default(),
+            invalid_query: false,
+            compiled_query: None,
+            selection_totals: SelectionTotals::default(),
+            filter_totals: None,
+            directory_sizes: Vec::new(),
+        };
+        model.refresh_directory_sizes();
+        model.refilter();
+        model
+    }
+
+    /// Add up directory sizes again, after the tree changed or file sizes came in
+    pub fn refresh_directory_sizes(&mut self) {
+        self.directory_sizes = self.tree.directory_sizes();
+    }
+
+    /// Total size of the files under a directory, `None` while some are still being measured
+    pub fn directory_size(&self, index: usize) -> Option<u64> {
+        self.directory_sizes.get(index).copied().flatten()
+    }
+
+    /// The query compiled for `match_mode`, compiling it only when it or the mode changed
+    fn compiled_query(&mut self) -> Result<Option<Regex>, regex::Error> {
+        if let Some((mode, query, regex)) = &self.compiled_query
+            && *mode == self.match_mode
+            && *query == self.query
+        {
+            return Ok(regex.clone());
+        }
+        let regex = self.match_mode.compile(&self.query)?;
+        self.compiled_query = Some((self.match_mode, self.query.clone(), regex.clone()));
+        Ok(regex)
+    }
+
+    /// Match the query against the tree again, e.g. after the query or the tree changed.
+    /// A query that doesn't compile leaves the matches as they were.
+    pub fn refilter(&mut self) {
+        let regex = self.compiled_query();
+        self.invalid_query = regex.is_err();
+        let Ok(regex) = regex else { return };
+        let root_path = self.tree.scan_root_path();
+        let changed_files = self.changed_files.as_ref();
+        self.filtered_results = filter_tree_nodes_where(&self.tree, &self.query, regex.as_ref(), self.match_file_names, |_, node| {
+            changed_files.is_none_or(|changed_files| {
+                let relative_path = node.path.strip_prefix(root_path).unwrap_or(&node.path);
+                changed_files.contains(relative_path, node.is_directory)
+            })
+        });
+        self.recompute_filter_totals();
+    }
+
+    /// Listed nodes that match the query, leaving out the tree view's context rows
+    fn matching_items(&self) -> impl Iterator<Item = usize> + '_ {
+        let results = &self.filtered_results;
+        results.visible_items.iter().copied().filter(|index| !results.context.contains(index))
+    }
+
+    /// Set every matching node to `state`, skipping locked ones
+    pub fn set_matches(&mut self, state: SelectionState) {
+        for tree_index in self.matching_items().collect::<Vec<_>>() {
+            self.tree.set_state_bulk(tree_index, state);
+        }
+    }
+
+    /// Flip every unlocked matching node
+    pub fn invert_matches(&mut self) {
+        for tree_index in self.matching_items().collect::<Vec<_>>() {
+            if !self.tree.nodes[tree_index].locked {
+                self.tree.toggle_state(tree_index);
+            }
+        }
+    }
+
+    /// Give each of `nodes` `state`, skipping locked ones, as toggling them one by one
+    /// would: a directory's contents go with it and the directories holding them follow
+    pub fn set_nodes(&mut self, nodes: &[usize], state: SelectionState) {
+        for &index in nodes {
+            self.tree.set_state_bulk(index, state);
+        }
+    }
+
+    /// Flip each of `files` between included and excluded, skipping locked ones
+    pub fn invert_files(&mut self, files: &[usize]) {
+        for &index in files {
+            if !self.tree.nodes[index].locked {
+                self.tree.toggle_state(index);
+            }
+        }
+    }
+
+    /// Apply a bulk operation
+    pub fn apply(&mut self, operation: &BulkOperation) {
+        match operation {
+            BulkOperation::IncludeMatches => self.set_matches(SelectionState::Included),
+            BulkOperation::ExcludeMatches => self.set_matches(SelectionState::Excluded),
+            BulkOperation::InvertMatches => self.invert_matches(),
+            BulkOperation::ExcludeExtension(extension) => {
+                for index in self.files_with_extension(extension) {
+                    self.tree.set_state_bulk(index, SelectionState::Excluded);
+                }
+            }
+        }
+    }
+
+    /// Work out what `operation` would change without changing anything: it runs on the
+    /// tree and every node's state is put back afterwards
+    pub fn plan(&mut self, operation: BulkOperation) -> BulkPlan {
+        let before: Vec<(SelectionState, bool)> = self.tree.nodes.iter().map(|node| (node.state, node.sticky)).collect();
+        self.apply(&operation);
+
+        let mut plan = BulkPlan {
+            operation,
+            changed: HashSet::new(),
+            included_files: 0,
+            excluded_files: 0,
+            size_delta: 0,
+        };
+        let exportable: Vec<bool> = self.tree.nodes.iter().map(|node| self.tree.is_exportable(node)).collect();
+        for (index, (node, &(state, sticky))) in self.tree.nodes.iter_mut().zip(&before).enumerate() {
+            // Stickiness can change without the state, e.g. for ancestors of a changed node
+            node.sticky = sticky;
+            if node.state == state {
+                continue;
+            }
+            plan.changed.insert(index);
+            if exportable[index] {
+                let size = node.size.unwrap_or(0) as i64;
+                if node.state.is_included() {
+                    plan.included_files += 1;
+                    plan.size_delta += size;
+                } else {
+                    plan.excluded_files += 1;
+                    plan.size_delta -= size;
+                }
+            }
+            node.state = state;
+        }
+        plan
+    }
+
+    /// Files in view whose extension is `extension`
+    fn files_with_extension(&self, extension: &str) -> Vec<usize> {
+        (0..self.tree.nodes.len())
+            .filter(|&index| {
+                let node = &self.tree.nodes[index];
+                !node.is_directory
+                    && self.tree.is_in_view(index)
+                    && node.path.extension().is_some_and(|ext| ext.to_string_lossy() == extension)
+            })
+            .collect()
+    }
+
+    /// Include the files under directory `index` one by one, in `order`, until the next
+    /// would take the export's estimated tokens past `budget`; the rest end up excluded.
+    /// Locked and binary files keep their state.
+    pub fn fill_to_budget(
+        &mut self,
+        index: usize,
+        budget: u64,
+        order: FillOrder,
+        formatter: &OutputFormatter,
+    ) -> BudgetFill {
+        let mut files = Vec::new();
+        self.collect_fill_candidates(index, &mut files);
+        if order == FillOrder::SmallestFirst {
+            files.sort_by_key(|&file| self.tree.nodes[file].size.unwrap_or(0));
+        }
+
+        // Headers, fences and line numbers add to each file; measure their average once
+        self.tree.set_state_bulk(index, SelectionState::Included);
+        let full_size = formatter.estimate_size(&self.tree);
+        self.tree.set_state_bulk(index, SelectionState::Excluded);
+        let base_size = formatter.estimate_size(&self.tree);
+        let content_size: u64 = files.iter().map(|&file| self.tree.nodes[file].size.unwrap_or(0)).sum();
+        let overhead = full_size.saturating_sub(base_size + content_size) / files.len().max(1) as u64;
+
+        let mut included = Vec::new();
+        let mut stopped_at = None;
+        let mut export_size = base_size;
+        for &file in &files {
+            let next_size = export_size + self.tree.nodes[file].size.unwrap_or(0) + overhead;
+            if estimate_tokens(next_size) > budget {
+                stopped_at = Some(file);
+                break;
+            }
+            export_size = next_size;
+            included.push(file);
+        }
+        for &file in &included {
+            self.tree.set_state(file, SelectionState::Included);
+        }
+
+        // The overhead is an average, so check against the real estimate
+        let mut export_size = formatter.estimate_size(&self.tree);
+        while estimate_tokens(export_size) > budget
+            && let Some(file) = included.pop()
+        {
+            self.tree.set_state(file, SelectionState::Excluded);
+            stopped_at = Some(file);
+            export_size = formatter.estimate_size(&self.tree);
+        }
+
+        BudgetFill {
+            included_files: included.len(),
+            candidates: files.len(),
+            tokens: estimate_tokens(export_size),
+            budget,
+            stopped_at,
+        }
+    }
+
+    /// Unlocked text files under `index`, in tree order
+    fn collect_fill_candidates(&self, index: usize, files: &mut Vec<usize>) {
+        let node = &self.tree.nodes[index];
+        if !node.is_directory {
+            if node.is_text_file && !node.locked {
+                files.push(index);
+            }
+            return;
+        }
+        for &child in &node.children {
+            self.collect_fill_candidates(child, files);
+        }
+    }
+
+    /// Recompute the selection totals, with `formatter` estimating the export size.
+    /// Returns the names that started being shared by included files.
+    pub fn recompute_totals(&mut self, formatter: &OutputFormatter) -> Vec<String> {
+        let included_files = self.tree.get_all_included_files();
+        let estimate = formatter.estimate(&self.tree);
+        let totals = SelectionTotals {
+            files: self.tree.file_counts(self.tree.root_index),
+            export_size: estimate.size,
+            tokens: estimate.tokens,
+            shared_names: shared_names(&included_files),
+        };
+
+        let new_names = totals
+            .shared_names
+            .iter()
+            .filter(|name| !self.selection_totals.shared_names.contains(name))
+            .cloned()
+            .collect();
+        self.selection_totals = totals;
+        self.recompute_filter_totals();
+        new_names
+    }
+
+    /// Totals scoped to the files matching the query, only walking the matches
+    fn recompute_filter_totals(&mut self) {
+        if self.query.is_empty() {
+            self.filter_totals = None;
+            return;
+        }
+
+        let mut totals = FilterTotals::default();
+        for index in self.matching_items() {
+            let node = &self.tree.nodes[index];
+            if node.is_directory {
+                continue;
+            }
+            totals.matched_files += 1;
+            if node.state.is_included() && self.tree.is_exportable(node) {
+                totals.included_files += 1;
+                totals.included_size += node.size.unwrap_or(0);
+            }
+        }
+        self.filter_totals = Some(totals);
+    }
+
+    /// Totals as of the last `recompute_totals`. Both file counts come from the same pass,
+    /// so they never mix a fresh total with stale inclusions.
+    pub fn stats(&self) -> SelectionStats {
+        let files = self.selection_totals.files;
+        SelectionStats {
+            total_files: files.total_files,
+            included_files: files.included_files,
+            total_size: files.included_size,
+            export_size: self.selection_totals.export_size,
+            tokens: self.selection_totals.tokens,
+            filtered_count: self.filtered_results.matches.len(),
+            calculating: false,
+            approximate: false,
+            filter: self.filter_totals,
+            shared_names: self.selection_totals.shared_names.len(),
+        }
+    }
+}
+
+/// Names used by more than one of the files, sorted
+fn shared_names(files: &[&FileNode]) -> Vec<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for node in files {
+        *counts.entry(node.name.as_str()).or_default() += 1;
+    }
+    let mut names: Vec<String> = counts
+        .into_iter()
+        .filter(|&(_, count)| count > 1)
+        .map(|(name, _)| name.to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+/// A selection change applied to many nodes at once, previewed before it's applied
+#[derive(Debug, Clone, PartialEq)]
+pub enum BulkOperation {
+    IncludeMatches,
+    ExcludeMatches,
+    InvertMatches,
+    ExcludeExtension(String), // Every file in view with the extension, not only matches
+}
+
+impl BulkOperation {
+    fn describe(&self) -> String {
+        match self {
+            Self::IncludeMatches => "Include matches".to_string(),
+            Self::ExcludeMatches => "Exclude matches".to_string(),
+            Self::InvertMatches => "Invert matches".to_string(),
+            Self::ExcludeExtension(extension) => format!("Exclude .{} files", extension),
+        }
+    }
+}
+
+/// What a bulk operation would do: the nodes whose state would change and the net effect
+/// on the included files
+#[derive(Debug, Clone)]
+pub struct BulkPlan {
+    pub operation: BulkOperation,
+    pub changed: HashSet<usize>, // Tree indices, directories included
+    pub included_files: usize, // Files that would become included
+    pub excluded_files: usize, // Files that would stop being included
+    pub size_delta: i64,
+}
+
+impl BulkPlan {
+    /// `Invert matches: +214 included, −12 excluded, +1.2 MB`
+    pub fn describe(&self) -> String {
+        let sign = if self.size_delta < 0 { "−" } else { "+" };
+        format!(
+            "{}: +{} included, −{} excluded, {}{}",
+            self.operation.describe(),
+            self.included_files,
+            self.excluded_files,
+            sign,
+            format_file_size(self.size_delta.unsigned_abs())
+        )
+    }
+}
+
+/// The order `fill_to_budget` includes a directory's files in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FillOrder {
+    #[default]
+    SmallestFirst, // As many files as fit
+    Path,          // As listed, stopping at the first that doesn't fit
+}
+
+/// What `fill_to_budget` did
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetFill {
+    pub included_files: usize,
+    pub candidates: usize,
+    pub tokens: u64, // Estimated tokens of the whole export afterwards
+    pub budget: u64,
+    pub stopped_at: Option<usize>, // First file left out for the budget
+}
+
+impl BudgetFill {
+    /// `Included 118 of 342 files (98.0k/100.0k tokens)`
+    pub fn describe(&self) -> String {
+        format!(
+            "Included {} of {} files ({}/{} tokens)",
+            self.included_files,
+            self.candidates,
+            format_tokens(self.tokens),
+            format_tokens(self.budget)
+        )
+    }
+}
+
+/// Totals derived from the current selection, cached between recomputes
+#[derive(Debug, Default, Clone)]
+struct SelectionTotals {
+    files: FileCounts, // Over the view root
+    export_size: u64, // Estimated size of the export, with headers and fences
+    tokens: u64, // Estimated tokens of the export
+    shared_names: Vec<String>, // Names of included files that aren't unique
+}
+
+/// Totals over the files matching the active query
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FilterTotals {
+    pub matched_files: usize,
+    pub included_files: usize,
+    pub included_size: u64,
+}
+
+#[derive(Debug)]
+pub struct SelectionStats {
+    pub total_files: usize,
+    pub included_files: usize,
+    pub total_size: u64,
+    pub export_size: u64,
+    pub tokens: u64, // Estimated from sizes, or counted for files read before
+    pub filtered_count: usize,
+    pub calculating: bool, // Totals are stale until pending selection changes settle
+    pub approximate: bool, // Sizes and file counts are incomplete until the metadata pass completes
+    pub filter: Option<FilterTotals>,
+    pub shared_names: usize, // Names shared by more than one included file
+}
+
+impl SelectionStats {
+    /// `match: 12/214 · 340.0 KB`, while a query is active
+    pub fn format_filter(&self) -> Option<String> {
+        let filter = self.filter?;
+        Some(format!(
+            "match: {}/{} · {}",
+            filter.included_files,
+            filter.matched_files,
+            format_file_size(filter.included_size)
+        ))
+    }
+
+    pub fn format_size(&self) -> String {
+        if self.calculating {
+            return "calculating…".to_string();
+        }
+        let approximate = if self.approximate { "~" } else { "" };
+        if self.included_files == 0 {
+            return format!("{}{}", approximate, format_file_size(self.total_size));
+        }
+        // The export itself is always an estimate: content is measured by size on disk
+        format!(
+            "{}{} (export ~{})",
+            approximate,
+            format_file_size(self.total_size),
+            format_file_size(self.export_size)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_model_without_interface() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("src"), true, &root).unwrap();
+        for path in ["src/main.rs", "src/lib.rs", "README.md"] {
+            let parent = root.join(path).parent().unwrap().to_path_buf();
+            let index = tree.add_node(root.join(path), false, &parent).unwrap();
+            tree.nodes[index].is_text_file = true;
+            tree.nodes[index].size = Some(10);
+        }
+
+        // Query, select the matches and read the totals, with no cursor or viewport involved
+        let mut model = SelectionModel::new(tree);
+        model.query = ".rs".to_string();
+        model.refilter();
+        model.set_matches(SelectionState::Included);
+        assert!(model.recompute_totals(&OutputFormatter::new()).is_empty());
+
+        let stats = model.stats();
+        assert_eq!((stats.included_files, stats.total_size, stats.total_files), (2, 20, 3));
+        assert_eq!(stats.filter.map(|filter| filter.matched_files), Some(2));
+
+        model.query = "README".to_string();
+        model.refilter();
+        model.invert_matches();
+        model.recompute_totals(&OutputFormatter::new());
+        assert_eq!(model.stats().included_files, 3);
+
+        // Metadata turning a file binary moves both counts together, at the next recompute
+        let main = model.tree.path_to_index[&root.join("src/main.rs")];
+        model.tree.nodes[main].is_text_file = false;
+        assert_eq!((model.stats().included_files, model.stats().total_files), (3, 3));
+        model.recompute_totals(&OutputFormatter::new());
+        assert_eq!((model.stats().included_files, model.stats().total_files), (2, 2));
+
+        model.query.clear();
+        model.refilter();
+        assert_eq!(model.stats().filter, None);
+    }
+
+    #[test]
+    fn test_plan_bulk_operation() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        let src = tree.add_node(root.join("src"), true, &root).unwrap();
+        for (path, size) in [("src/main.rs", 100), ("src/lib.rs", 200), ("README.md", 50)] {
+            let parent = root.join(path).parent().unwrap().to_path_buf();
+            let index = tree.add_node(root.join(path), false, &parent).unwrap();
+            tree.nodes[index].is_text_file = true;
+            tree.nodes[index].size = Some(size);
+        }
+        let mut model = SelectionModel::new(tree);
+        model.tree.set_state(src, SelectionState::Included);
+        let before: Vec<_> = model.tree.nodes.iter().map(|node| (node.state, node.sticky)).collect();
+
+        // Planning leaves the tree as it was, stickiness included
+        model.query = "README".to_string();
+        model.refilter();
+        let plan = model.plan(BulkOperation::InvertMatches);
+        let after: Vec<_> = model.tree.nodes.iter().map(|node| (node.state, node.sticky)).collect();
+        assert_eq!(before, after);
+        assert_eq!((plan.included_files, plan.excluded_files, plan.size_delta), (1, 0, 50));
+        assert!(plan.changed.contains(&model.tree.root_index));
+        assert_eq!(plan.describe(), "Invert matches: +1 included, −0 excluded, +50 B");
+
+        model.query.clear();
+        model.refilter();
+        let plan = model.plan(BulkOperation::ExcludeExtension("rs".to_string()));
+        assert_eq!((plan.included_files, plan.excluded_files, plan.changed.len()), (0, 2, 4));
+        model.apply(&plan.operation);
+        model.recompute_totals(&OutputFormatter::new());
+        assert_eq!(model.stats().included_files, 0);
+    }
+
+    #[test]
+    fn test_fill_to_budget() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        let src = tree.add_node(root.join("src"), true, &root).unwrap();
+        let mut files = Vec::new();
+        for (path, size) in [("src/a.rs", 400), ("src/b.rs", 4000), ("src/c.rs", 40), ("src/d.rs", 800), ("src/e.rs", 10)] {
+            let index = tree.add_node(root.join(path), false, &root.join("src")).unwrap();
+            tree.nodes[index].is_text_file = true;
+            tree.nodes[index].size = Some(size);
+            files.push(index);
+        }
+        tree.nodes[files[4]].locked = true;
+        let mut model = SelectionModel::new(tree);
+        let formatter = OutputFormatter::new().with_metadata(false);
+        let included = |model: &SelectionModel| -> Vec<usize> {
+            files.iter().copied().filter(|&file| model.tree.nodes[file].state.is_included()).collect()
+        };
+
+        // Smallest first fits every file but the big one
+        let fill = model.fill_to_budget(src, 500, FillOrder::SmallestFirst, &formatter);
+        assert_eq!((fill.included_files, fill.candidates, fill.stopped_at), (3, 4, Some(files[1])));
+        assert!(fill.tokens <= 500);
+        assert_eq!(included(&model), [files[0], files[2], files[3]]);
+        assert_eq!(model.tree.nodes[src].state, SelectionState::Partial);
+        assert_eq!(fill.describe(), format!("Included 3 of 4 files ({}/500 tokens)", fill.tokens));
+
+        // In path order it stops at the first file that doesn't fit; the locked one is left alone
+        let fill = model.fill_to_budget(src, 500, FillOrder::Path, &formatter);
+        assert_eq!((fill.included_files, fill.stopped_at), (1, Some(files[1])));
+        assert_eq!(included(&model), [files[0]]);
+
+        // Everything fits in a large budget
+        let fill = model.fill_to_budget(src, 100_000, FillOrder::Path, &formatter);
+        assert_eq!((fill.included_files, fill.stopped_at), (4, None));
+    }
+}
@@ -0,0 +1,341 @@
+use super::formatter::Spacing;
+use super::image::ImageInfo;
+use super::{json, markdown, plain, xml};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// One included file, as listed up front before any content is read
+#[derive(Debug, Clone)]
+pub struct ExportEntry {
+    pub path: String, // Relative to the export root, always with `/` separators
+    pub absolute_path: PathBuf,
+    pub size: Option<u64>,
+    pub permissions: Option<String>, // Mode, owner and group, with `--file-perms`
+}
+
+/// Lines of a file, zero-based and both inclusive like a language server's folding ranges
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A file's content, after transforms such as line numbering
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileContent<'a> {
+    Text(&'a str),
+    Excerpt(&'a str, LineRange), // Only some lines: a recipe's `tail_lines`, `--extract-matches`
+    Binary, // Not valid UTF-8; exporters emit a placeholder instead
+    Image(&'a ImageInfo), // An image described rather than exported (`image_stubs`)
+    Error(&'a str),
+}
+
+/// Everything an exporter may need besides the file being emitted
+#[derive(Debug, Clone)]
+pub struct ExportContext<'a> {
+    pub root: &'a Path,
+    pub files: &'a [ExportEntry],
+    pub include_metadata: bool,
+    pub spacing: Spacing,
+    pub footer: Option<String>, // Already expanded
+    pub generated_at: String,
+    pub uris: bool, // `--uris`: add `file://` URIs where the format supports them
+}
+
+/// An output format. `begin` and `end` run once per export, `emit_file` once per
+/// included file, in order, all appending to the same output buffer.
+pub trait Exporter: Send + Sync {
+    /// Registry key, as given to `--format`
+    fn name(&self) -> &'static str;
+    /// File extension for saved exports, without the dot
+    fn extension(&self) -> &'static str;
+    fn begin(&self, output: &mut String, context: &ExportContext);
+    fn emit_file(&self, output: &mut String, index: usize, content: FileContent, context: &ExportContext);
+    fn end(&self, output: &mut String, context: &ExportContext);
+    /// Whether output cut off after any file can be finished by appending the rest, which
+    /// lets an interrupted export resume. Formats that wrap files in a document can't.
+    fn append_safe(&self) -> bool {
+        false
+    }
+}
+
+/// Export formats by name. Adding a format means implementing `Exporter` and
+/// registering it, either in `builtin` or at runtime with `register`.
+pub struct ExporterRegistry {
+    exporters: Vec<Box<dyn Exporter>>,
+}
+
+impl ExporterRegistry {
+    pub fn empty() -> Self {
+        Self { exporters: Vec::new() }
+    }
+
+    pub fn builtin() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Box::new(markdown::MarkdownExporter));
+        registry.register(Box::new(plain::PlainExporter));
+        registry.register(Box::new(json::JsonExporter));
+        registry.register(Box::new(xml::XmlExporter));
+        registry
+    }
+
+    /// Add a format, replacing any registered under the same name
+    pub fn register(&mut self, exporter: Box<dyn Exporter>) {
+        self.exporters.retain(|existing| existing.name() != exporter.name());
+        self.exporters.push(exporter);
+    }
+
+    /// The first format saving with `extension`, ignoring case
+    pub fn for_extension(&self, extension: &str) -> Option<&dyn Exporter> {
+        self.exporters
+            .iter()
+            .find(|exporter| exporter.extension().eq_ignore_ascii_case(extension))
+            .map(|exporter| exporter.as_ref())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Exporter> {
+        self.exporters
+            .iter()
+            .find(|exporter| exporter.name().eq_ignore_ascii_case(name))
+            .map(|exporter| exporter.as_ref())
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.exporters.iter().map(|exporter| exporter.name()).collect()
+    }
+}
+
+impl Default for ExporterRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl fmt::Debug for ExporterRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+/// Relative path with `/` separators, so exports are identical across platforms
+pub fn export_path(path: &Path, root: &Path) -> String {
+    let relative_path = path.strip_prefix(root).unwrap_or(path);
+    relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Conformance suite: every built-in exporter must pass these
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directory::tree::DirectoryTree;
+    use crate::output::formatter::OutputFormatter;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const TRICKY_CONTENT: &str = "</file> <file path=\"x\"> & \"quoted\" \\ ``` \u{0}\n";
+
+    fn fixture(files: &[(&str, &[u8])]) -> (TempDir, DirectoryTree) {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root.clone());
+        for (name, content) in files {
+            let path = root.join(name);
+            fs::write(&path, content).unwrap();
+            let index = tree.add_node(path, false, &root).unwrap();
+            tree.nodes[index].is_text_file = true; // Included as text, even the binary one
+            tree.nodes[index].size = Some(content.len() as u64);
+            tree.toggle_state(index);
+        }
+        (temp_dir, tree)
+    }
+
+    fn export(tree: &DirectoryTree, format: &str) -> String {
+        OutputFormatter::new()
+            .with_format(format)
+            .with_metadata(false)
+            .with_footer(Some("footer & <notes>".to_string()))
+            .format_output(tree)
+            .unwrap()
+    }
+
+    /// Check `xml` is well-formed: only characters XML allows, known entities, quoted
+    /// attributes and one root element with every element closed in order
+    fn assert_well_formed(xml: &str) {
+        let allowed = |c: char| matches!(c, '\t' | '\n' | '\r' | ' '..='\u{d7ff}' | '\u{e000}'..='\u{fffd}' | '\u{10000}'..);
+        assert!(xml.chars().all(allowed), "illegal character in {:?}", xml);
+        let entities = regex::Regex::new(r"&(amp|lt|gt|quot|apos|#[0-9]+|#x[0-9a-fA-F]+);").unwrap();
+        assert!(!entities.replace_all(xml, "").contains('&'), "bare & in {:?}", xml);
+        let attribute = regex::Regex::new(r#"^\s+[\w:-]+="[^"<]*""#).unwrap();
+
+        let mut open: Vec<&str> = Vec::new();
+        let mut roots = 0;
+        let mut rest = xml;
+        while let Some(start) = rest.find('<') {
+            let end = start + rest[start..].find('>').expect("unclosed tag");
+            let tag = &rest[start + 1..end];
+            rest = &rest[end + 1..];
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(open.pop(), Some(name), "mismatched </{}>", name);
+                continue;
+            }
+            let (tag, closed) = tag.strip_suffix('/').map_or((tag, false), |tag| (tag, true));
+            let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+            let mut attributes = &tag[name_end..];
+            while let Some(found) = attribute.find(attributes) {
+                attributes = &attributes[found.end()..];
+            }
+            assert!(attributes.trim().is_empty(), "bad attributes in <{}>", tag);
+            roots += open.is_empty() as usize;
+            if !closed {
+                open.push(&tag[..name_end]);
+            }
+        }
+        assert!(open.is_empty() && roots == 1, "unbalanced: {:?}", xml);
+    }
+
+    fn for_each_exporter(check: impl Fn(&str, &str)) {
+        for name in ExporterRegistry::builtin().names() {
+            let extension = ExporterRegistry::builtin().get(name).unwrap().extension();
+            check(name, extension);
+        }
+    }
+
+    #[test]
+    fn test_empty_selection() {
+        let (_temp_dir, tree) = fixture(&[]);
+        for_each_exporter(|name, extension| {
+            let output = export(&tree, name);
+            if extension == "json" {
+                let value: serde_json::Value = serde_json::from_str(&output).unwrap();
+                assert_eq!(value["files"].as_array().unwrap().len(), 0);
+            }
+            assert!(!output.contains("main.rs"), "{}", name);
+        });
+    }
+
+    #[test]
+    fn test_binary_placeholder() {
+        let (_temp_dir, tree) = fixture(&[("blob.bin", &[0xff, 0xfe, 0x00, 0x80])]);
+        for_each_exporter(|name, _| {
+            let output = export(&tree, name);
+            assert!(output.contains("blob.bin"), "{}", name);
+            assert!(output.to_lowercase().contains("binary"), "{}", name);
+            assert!(!output.contains('\u{fffd}'), "{}", name);
+        });
+    }
+
+    #[test]
+    fn test_image_description() {
+        let svg = b"<svg width=\"64\" height=\"32\"><title>Logo & <mark></title><desc>Blue</desc></svg>";
+        let (_temp_dir, mut tree) = fixture(&[("logo.svg", svg)]);
+        tree.export_images = true;
+        for_each_exporter(|name, extension| {
+            let output = export(&tree, name);
+            match extension {
+                "json" => {
+                    let value: serde_json::Value = serde_json::from_str(&output).unwrap();
+                    let image = &value["files"][0]["image"];
+                    assert_eq!((image["format"].as_str(), image["width"].as_u64()), (Some("SVG"), Some(64)));
+                    assert_eq!(image["title"], "Logo & <mark>");
+                    assert!(value["files"][0].get("content").is_none());
+                }
+                "xml" => {
+                    assert_well_formed(&output);
+                    assert!(output.contains("image=\"SVG\" width=\"64\" height=\"32\">"), "{}", output);
+                    assert!(output.contains("<title>Logo &amp; &lt;mark&gt;</title>"), "{}", output);
+                }
+                _ => {
+                    assert!(output.contains("SVG, 64x32"), "{}: {}", name, output);
+                    assert!(output.contains("Blue"), "{}", name);
+                }
+            }
+            // The markup itself isn't exported, nor base64 unless asked for
+            assert!(!output.contains("<svg") && !output.contains("base64"), "{}", name);
+        });
+
+        let embedded = OutputFormatter::new().with_format("markdown").with_embedded_images(Some(1024));
+        assert!(embedded.format_output(&tree).unwrap().contains("![logo.svg](data:image/svg+xml;base64,"));
+        // Without stubs an image is exported like any other file
+        tree.export_images = false;
+        assert!(export(&tree, "plain").contains("<svg"));
+    }
+
+    #[test]
+    fn test_deterministic() {
+        // Metadata is off in these exports, so there's no timestamp to differ
+        let (_temp_dir, tree) = fixture(&[("a.rs", b"fn a() {}"), ("b.rs", b"fn b() {}")]);
+        for_each_exporter(|name, _| {
+            let output = export(&tree, name);
+            assert_eq!(output, export(&tree, name), "{}", name);
+            assert!(output.find("a.rs").unwrap() < output.find("b.rs").unwrap(), "{}", name);
+        });
+    }
+
+    #[test]
+    fn test_escaping() {
+        let (_temp_dir, tree) = fixture(&[("a&b \"c\".txt", TRICKY_CONTENT.as_bytes())]);
+        for_each_exporter(|name, extension| {
+            let output = export(&tree, name);
+            match extension {
+                "json" => {
+                    let value: serde_json::Value = serde_json::from_str(&output).unwrap();
+                    assert_eq!(value["files"][0]["path"], "a&b \"c\".txt");
+                    assert_eq!(value["files"][0]["content"], TRICKY_CONTENT);
+                }
+                "xml" => {
+                    // The content can't close or open elements of its own, and characters
+                    // XML can't hold are replaced
+                    assert_well_formed(&output);
+                    assert!(output.contains("``` \u{fffd}\n"), "{}", output);
+                    assert_eq!(output.matches("</file>").count(), 1, "{}", name);
+                    assert_eq!(output.matches("<file ").count(), 1, "{}", name);
+                    assert!(output.contains("path=\"a&amp;b &quot;c&quot;.txt\""));
+                }
+                "md" => {
+                    // The fence is longer than any backtick run in the content
+                    assert!(output.contains("````\n"), "{}", name);
+                    assert!(output.contains(TRICKY_CONTENT));
+                }
+                _ => assert!(output.contains(TRICKY_CONTENT), "{}", name),
+            }
+        });
+    }
+
+    #[test]
+    fn test_register_at_runtime() {
+        struct CountExporter;
+        impl Exporter for CountExporter {
+            fn name(&self) -> &'static str {
+                "count"
+            }
+            fn extension(&self) -> &'static str {
+                "txt"
+            }
+            fn begin(&self, output: &mut String, context: &ExportContext) {
+                output.push_str(&context.files.len().to_string());
+            }
+            fn emit_file(&self, _: &mut String, _: usize, _: FileContent, _: &ExportContext) {}
+            fn end(&self, _: &mut String, _: &ExportContext) {}
+        }
+
+        let mut registry = ExporterRegistry::builtin();
+        registry.register(Box::new(CountExporter));
+        assert!(registry.names().contains(&"count"));
+        // Extensions pick the first format registered with them, as F1-F4 slots do
+        assert_eq!(registry.for_extension("TXT").map(|exporter| exporter.name()), Some("plain"));
+        assert_eq!(registry.for_extension("md").map(|exporter| exporter.name()), Some("markdown"));
+        assert!(registry.for_extension("html").is_none());
+
+        let (_temp_dir, tree) = fixture(&[("a.rs", b"")]);
+        let output = OutputFormatter::new()
+            .with_registry(std::sync::Arc::new(registry))
+            .with_format("count")
+            .format_output(&tree)
+            .unwrap();
+        assert_eq!(output, "1\n");
+    }
+}
@@ -0,0 +1,92 @@
+use super::exporter::LineRange;
+use regex::Regex;
+use std::ops::Range;
+
+/// Export only the lines matching a pattern, with some context, in grep's style:
+///
+/// ```text
+/// src/main.rs-11-fn main() {
+/// src/main.rs:12:    // TODO: parse arguments
+/// src/main.rs-13-    run();
+/// --
+/// src/main.rs:40:    // TODO: exit code
+/// ```
+///
+/// Matching lines are `path:line:`, context lines `path-line-`, and `--` separates hunks.
+/// Context windows that touch or overlap are merged into one hunk.
+#[derive(Debug, Clone)]
+pub struct ExtractMatches {
+    pattern: Regex,
+    context: usize, // Lines before and after each match
+}
+
+impl ExtractMatches {
+    pub fn new(pattern: Regex, context: usize) -> Self {
+        Self { pattern, context }
+    }
+
+    /// The hunks of `content` around its matches and the lines from the first hunk to the
+    /// last, or `None` without any match
+    pub fn extract(&self, path: &str, content: &str) -> Option<(String, LineRange)> {
+        let lines: Vec<&str> = content.lines().collect();
+        let matches: Vec<usize> = (0..lines.len()).filter(|&index| self.pattern.is_match(lines[index])).collect();
+
+        let mut hunks: Vec<Range<usize>> = Vec::new();
+        for &index in &matches {
+            let start = index.saturating_sub(self.context);
+            let end = (index + self.context + 1).min(lines.len());
+            match hunks.last_mut() {
+                Some(last) if start <= last.end => last.end = last.end.max(end),
+                _ => hunks.push(start..end),
+            }
+        }
+        let lines_range = LineRange {
+            start: hunks.first()?.start,
+            end: hunks.last()?.end - 1,
+        };
+
+        let mut output = String::new();
+        for (hunk_index, hunk) in hunks.into_iter().enumerate() {
+            if hunk_index > 0 {
+                output.push_str("--\n");
+            }
+            for index in hunk {
+                let separator = if matches.binary_search(&index).is_ok() { ':' } else { '-' };
+                output.push_str(&format!("{}{}{}{}{}\n", path, separator, index + 1, separator, lines[index]));
+            }
+        }
+        Some((output, lines_range))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_matches() {
+        let content: String = (1..=20).map(|line| match line {
+            5 | 7 | 16 => format!("// TODO {}\n", line),
+            _ => format!("line {}\n", line),
+        }).collect();
+        let extract = ExtractMatches::new(Regex::new("TODO").unwrap(), 1);
+
+        // The windows of lines 5 and 7 touch, so they make one hunk
+        let (output, lines) = extract.extract("a.rs", &content).unwrap();
+        assert_eq!(lines, LineRange { start: 3, end: 16 });
+        assert_eq!(
+            output,
+            "a.rs-4-line 4\na.rs:5:// TODO 5\na.rs-6-line 6\na.rs:7:// TODO 7\na.rs-8-line 8\n\
+             --\na.rs-15-line 15\na.rs:16:// TODO 16\na.rs-17-line 17\n"
+        );
+
+        // Windows are clipped at either end of the file
+        let extract = ExtractMatches::new(Regex::new("line (1|20)$").unwrap(), 2);
+        let (output, lines) = extract.extract("a.rs", &content).unwrap();
+        assert_eq!(lines, LineRange { start: 0, end: 19 });
+        assert!(output.starts_with("a.rs:1:line 1\na.rs-2-line 2\na.rs-3-line 3\n--\n"));
+        assert!(output.ends_with("a.rs-19-line 19\na.rs:20:line 20\n"));
+
+        assert_eq!(extract.extract("b.rs", "nothing here\n"), None);
+    }
+}
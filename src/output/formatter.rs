@@ -1,10 +1,129 @@
-use crate::directory::tree::{DirectoryTree, FileNode};
-use anyhow::Result;
-use std::fs;
+use super::exporter::{ExportContext, ExportEntry, Exporter, ExporterRegistry, FileContent, LineRange, export_path};
+use super::extract::ExtractMatches;
+use super::image::{self, ImageInfo};
+use crate::config::cache::{ContentCache, ContentSummary};
+use crate::config::session::fnv1a_hash;
+use crate::directory::tree::DirectoryTree;
+use crate::output::tokens::estimate_tokens;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
 
+/// Registry name of the format used unless another is chosen
+pub const DEFAULT_FORMAT: &str = "markdown";
+
+/// Assumed average line length, for estimating line number overhead without reading files
+const ESTIMATED_LINE_LENGTH: u64 = 40;
+/// Bytes added to each line by line numbering: `{:4} | `
+const LINE_NUMBER_WIDTH: u64 = 7;
+
+/// Blank lines around each file's `# path` heading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spacing {
+    pub blank_before_header: bool, // Between the previous file's closing fence and the heading
+    pub blank_after_header: bool,  // Between the heading and what follows it
+}
+
+impl Default for Spacing {
+    fn default() -> Self {
+        Self {
+            blank_before_header: true,
+            blank_after_header: true,
+        }
+    }
+}
+
+/// How the export ends: downstream tools differ on whether they expect a final newline
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FinalNewline {
+    #[default]
+    Single, // Exactly one
+    #[serde(rename = "none")]
+    Strip,
+    Preserve, // Whatever the last file or the footer produced
+}
+
+impl FinalNewline {
+    fn apply(self, output: &mut String) {
+        if self == FinalNewline::Preserve || output.is_empty() {
+            return;
+        }
+        output.truncate(output.trim_end_matches(['\n', '\r']).len());
+        if self == FinalNewline::Single {
+            output.push('\n');
+        }
+    }
+
+    /// `apply` for an export streamed to `file`, which is left positioned at its end
+    pub fn apply_to_file(self, file: &mut File) -> io::Result<()> {
+        let length = file.seek(SeekFrom::End(0))?;
+        if self == FinalNewline::Preserve || length == 0 {
+            return Ok(());
+        }
+        // Walk back over the trailing newlines a block at a time
+        let mut end = length;
+        let mut block = [0; 256];
+        while end > 0 {
+            let start = end.saturating_sub(block.len() as u64);
+            let block = &mut block[..(end - start) as usize];
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(block)?;
+            let kept = block.iter().rposition(|byte| !matches!(byte, b'\n' | b'\r'));
+            end = start + kept.map_or(0, |index| index as u64 + 1);
+            if kept.is_some() {
+                break;
+            }
+        }
+        file.set_len(end)?;
+        file.seek(SeekFrom::End(0))?;
+        if self == FinalNewline::Single {
+            file.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// What a piece of streamed output holds
+#[derive(Debug, Clone, Copy)]
+pub enum Piece<'a> {
+    Header,
+    File(&'a ExportEntry, usize), // The entry and how many files are done with it
+    Footer,
+}
+
+/// What an export would come to, without reading the files
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExportEstimate {
+    pub size: u64,
+    pub tokens: u64,
+}
+
+impl Spacing {
+    pub fn file_separator(&self) -> &'static str {
+        if self.blank_before_header { "\n\n" } else { "\n" }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct OutputFormatter {
     include_metadata: bool,
     include_line_numbers: bool,
+    footer: Option<String>,
+    spacing: Spacing,
+    format: String,
+    registry: Arc<ExporterRegistry>,
+    exclusion_reasons: Vec<(String, String)>,
+    final_newline: FinalNewline,
+    content_cache: Option<Arc<Mutex<ContentCache>>>,
+    tail_lines: Option<usize>,
+    extract_matches: Option<ExtractMatches>,
+    uris: bool,
+    embed_images: Option<u64>, // Largest image embedded as base64, with `--embed-images`
+    ascii: bool,
 }
 
 impl Default for OutputFormatter {
@@ -18,6 +137,18 @@ impl OutputFormatter {
         Self {
             include_metadata: true,
             include_line_numbers: false,
+            footer: None,
+            spacing: Spacing::default(),
+            format: DEFAULT_FORMAT.to_string(),
+            registry: Arc::new(ExporterRegistry::builtin()),
+            exclusion_reasons: Vec::new(),
+            final_newline: FinalNewline::default(),
+            content_cache: None,
+            tail_lines: None,
+            extract_matches: None,
+            uris: false,
+            embed_images: None,
+            ascii: false,
         }
     }
 
@@ -31,145 +162,495 @@ impl OutputFormatter {
         self
     }
 
+    /// Text emitted after all file content; `{date}` and `{root}` are expanded
+    pub fn with_footer(mut self, footer: Option<String>) -> Self {
+        self.footer = footer;
+        self
+    }
+
+    pub fn with_spacing(mut self, spacing: Spacing) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    /// Only export the last `tail_lines` lines of each file, e.g. of logs
+    pub fn with_tail_lines(mut self, tail_lines: Option<usize>) -> Self {
+        self.tail_lines = tail_lines;
+        self
+    }
+
+    /// Only export the lines matching a pattern, with context; files without a match are left out
+    pub fn with_extract_matches(mut self, extract_matches: Option<ExtractMatches>) -> Self {
+        self.extract_matches = extract_matches;
+        self
+    }
+
+    /// Give each file a `file://` URI next to its relative path, in formats that support it
+    pub fn with_uris(mut self, uris: bool) -> Self {
+        self.uris = uris;
+        self
+    }
+
+    /// Embed images up to this many bytes as base64 in their descriptions
+    pub fn with_embedded_images(mut self, embed_images: Option<u64>) -> Self {
+        self.embed_images = embed_images;
+        self
+    }
+
+    /// Write the symbols gthr adds, like the ellipsis ending a cut-short SVG title, in plain ASCII
+    pub fn with_ascii(mut self, ascii: bool) -> Self {
+        self.ascii = ascii;
+        self
+    }
+
+    pub fn with_final_newline(mut self, final_newline: FinalNewline) -> Self {
+        self.final_newline = final_newline;
+        self
+    }
+
+    /// Record each exported file's line and token counts, and use recorded counts in estimates
+    pub fn with_content_cache(mut self, content_cache: Option<Arc<Mutex<ContentCache>>>) -> Self {
+        self.content_cache = content_cache;
+        self
+    }
+
+    pub fn content_cache(&self) -> Option<Arc<Mutex<ContentCache>>> {
+        self.content_cache.clone()
+    }
+
+    /// Paths and reasons listed as intentionally excluded after the footer text
+    pub fn with_exclusion_reasons(mut self, exclusion_reasons: Vec<(String, String)>) -> Self {
+        self.exclusion_reasons = exclusion_reasons;
+        self
+    }
+
+    /// Export format, by registry name
+    pub fn with_format(mut self, format: &str) -> Self {
+        self.format = format.to_string();
+        self
+    }
+
+    /// Formats to choose from. gthr itself only uses the built-in ones; tests register their own.
+    #[cfg(test)]
+    pub fn with_registry(mut self, registry: Arc<ExporterRegistry>) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// The format exports are written in
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    /// Names of the formats that can be exported to
+    pub fn formats(&self) -> Vec<&'static str> {
+        self.registry.names()
+    }
+
+    /// File extension for saved exports in the current format
+    pub fn extension(&self) -> &'static str {
+        self.exporter().map_or("md", |exporter| exporter.extension())
+    }
+
+    /// The registered format whose files end in `extension`
+    pub fn format_for_extension(&self, extension: &str) -> Option<&'static str> {
+        self.registry.for_extension(extension).map(|exporter| exporter.name())
+    }
+
+    pub fn final_newline(&self) -> FinalNewline {
+        self.final_newline
+    }
+
+    /// Whether the format's output stays valid when cut after any file and appended to
+    pub fn append_safe(&self) -> bool {
+        self.exporter().is_ok_and(|exporter| exporter.append_safe())
+    }
+
+    /// Identifies what an export of `tree` would contain: the options that shape the output
+    /// and the files in it. Two exports with the same hash differ only in their timestamps.
+    pub fn options_hash(&self, tree: &DirectoryTree) -> String {
+        let options = format!(
+            "{}|{}|{}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{}|{:?}|{}|{:?}",
+            self.format,
+            self.include_metadata,
+            self.include_line_numbers,
+            self.footer,
+            self.spacing,
+            self.exclusion_reasons,
+            self.final_newline,
+            self.tail_lines,
+            self.extract_matches,
+            self.uris,
+            self.embed_images,
+            tree.export_images,
+            tree.nodes[tree.root_index].path,
+        );
+        let mut bytes = options.into_bytes();
+        for entry in export_entries(tree) {
+            bytes.extend(format!("\0{}\0{:?}\0{:?}", entry.path, entry.size, entry.permissions).bytes());
+        }
+        format!("{:016x}", fnv1a_hash(&bytes))
+    }
+
+    fn exporter(&self) -> Result<&dyn Exporter> {
+        self.registry.get(&self.format).ok_or_else(|| {
+            anyhow!(
+                "Unknown export format '{}' (available: {})",
+                self.format,
+                self.registry.names().join(", ")
+            )
+        })
+    }
+
     pub fn format_output(&self, tree: &DirectoryTree) -> Result<String> {
-        let included_files = tree.get_all_included_files();
         let mut output = String::new();
+        self.stream_output(tree, 0, |piece, _| {
+            output.push_str(piece);
+            Ok(())
+        })?;
+        self.final_newline.apply(&mut output);
+        Ok(output)
+    }
 
-        if self.include_metadata {
-            // Add header
-            output.push_str(&self.format_header(tree, &included_files)?);
-            output.push_str("\n\n");
+    /// The export a piece at a time, passed to `sink` as soon as it's formatted: the header,
+    /// each file, then the footer. `format_output` is the pieces joined, with the final
+    /// newline applied. With `skip`, resuming an export, the header and the first `skip`
+    /// files are left out and those files aren't read.
+    pub fn stream_output(
+        &self,
+        tree: &DirectoryTree,
+        skip: usize,
+        mut sink: impl FnMut(&str, Piece) -> Result<()>,
+    ) -> Result<()> {
+        let exporter = self.exporter()?;
+        let mut entries = export_entries(tree);
+        let mut content_cache = self.locked_cache(tree);
+        // Extracting reads skipped files too. A failed prefetch shows up as each file's read error.
+        let first_read = if self.extract_matches.is_some() { 0 } else { skip };
+        let paths: Vec<&Path> = entries.iter().skip(first_read).map(|entry| entry.absolute_path.as_path()).collect();
+        let _ = tree.prefetch_files(&paths);
+
+        // Extracting needs every file read up front: those without a match aren't exported
+        let mut extracted = Vec::new();
+        if self.extract_matches.is_some() {
+            (entries, extracted) = self.extract(tree, entries, content_cache.as_deref_mut());
         }
 
-        // Add file contents
-        for (index, file_node) in included_files.iter().enumerate() {
-            if index > 0 {
-                output.push_str("\n\n");
+        let context = self.context(tree, &entries);
+        let mut output = String::new();
+        if skip == 0 {
+            exporter.begin(&mut output, &context);
+            sink(&output, Piece::Header)?;
+        }
+        let mut extracted = extracted.into_iter();
+        for (index, entry) in entries.iter().enumerate() {
+            if index < skip {
+                extracted.next();
+                continue;
+            }
+            if is_described(tree, entry) {
+                let image = self.describe_image(tree, entry);
+                let content = match &image {
+                    Ok(image) => FileContent::Image(image),
+                    Err(e) => FileContent::Error(e),
+                };
+                output.clear();
+                exporter.emit_file(&mut output, index, content, &context);
+                sink(&output, Piece::File(entry, index + 1))?;
+                continue;
             }
-            output.push_str(&self.format_file(tree, file_node)?);
+            let content = match extracted.next() {
+                Some(content) => content,
+                None => self.read_content(tree, entry, content_cache.as_deref_mut()),
+            };
+            let content = match &content {
+                Ok((text, None)) => FileContent::Text(text),
+                Ok((text, Some(lines))) => FileContent::Excerpt(text, *lines),
+                Err(None) => FileContent::Binary,
+                Err(Some(e)) => FileContent::Error(e),
+            };
+            output.clear();
+            exporter.emit_file(&mut output, index, content, &context);
+            sink(&output, Piece::File(entry, index + 1))?;
         }
+        output.clear();
+        exporter.end(&mut output, &context);
+        sink(&output, Piece::Footer)
+    }
 
-        Ok(output)
+    /// The files an export of `tree` contains: the included ones, less those without a
+    /// match when matches are extracted, which takes reading them
+    pub fn exported_files(&self, tree: &DirectoryTree) -> Vec<PathBuf> {
+        let mut entries = export_entries(tree);
+        if self.extract_matches.is_some() {
+            entries = self.extract(tree, entries, self.locked_cache(tree).as_deref_mut()).0;
+        }
+        entries.into_iter().map(|entry| entry.absolute_path).collect()
     }
 
-    fn format_header(&self, tree: &DirectoryTree, included_files: &[&FileNode]) -> Result<String> {
-        let root_path = &tree.nodes[tree.root_index].path;
-        let total_size: u64 = included_files.iter().filter_map(|node| node.size).sum();
-
-        let mut header = String::new();
-        header.push_str(&format!("# Text Ingest Report\n"));
-        header.push_str(&format!("**Root Directory:** {}\n", root_path.display()));
-        header.push_str(&format!("**Files Included:** {}\n", included_files.len()));
-        header.push_str(&format!(
-            "**Total Size:** {}\n",
-            format_file_size(total_size)
-        ));
-        header.push_str(&format!(
-            "**Generated:** {}\n",
-            chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
-        ));
-
-        if self.include_metadata {
-            header.push_str("\n## Included Files\n");
-            for file_node in included_files {
-                let relative_path = file_node
-                    .path
-                    .strip_prefix(root_path)
-                    .unwrap_or(&file_node.path);
-                let size_str = file_node
-                    .size
-                    .map(format_file_size)
-                    .unwrap_or_else(|| "Unknown".to_string());
-                header.push_str(&format!("- {} ({})\n", relative_path.display(), size_str));
+    /// The entries with a match to extract, and their excerpts
+    fn extract(
+        &self,
+        tree: &DirectoryTree,
+        entries: Vec<ExportEntry>,
+        mut content_cache: Option<&mut ContentCache>,
+    ) -> (Vec<ExportEntry>, Vec<FileText>) {
+        let Some(extract_matches) = &self.extract_matches else {
+            return (entries, Vec::new());
+        };
+        let mut kept = Vec::new();
+        let mut extracted = Vec::new();
+        for entry in entries {
+            if is_described(tree, &entry) {
+                continue; // No lines to match
             }
+            let content = self.read_content(tree, &entry, content_cache.as_deref_mut());
+            let content = match content {
+                Ok((text, _)) => match extract_matches.extract(&entry.path, &text) {
+                    Some((hunks, lines)) => Ok((hunks, Some(lines))),
+                    None => continue,
+                },
+                Err(e) => Err(e),
+            };
+            kept.push(entry);
+            extracted.push(content);
         }
-
-        Ok(header)
+        (kept, extracted)
     }
 
-    fn format_file(&self, tree: &DirectoryTree, file_node: &FileNode) -> Result<String> {
-        let root_path = &tree.nodes[tree.root_index].path;
-        let relative_path = file_node
-            .path
-            .strip_prefix(root_path)
-            .unwrap_or(&file_node.path);
+    /// What the export says about an image, read from its header
+    fn describe_image(&self, tree: &DirectoryTree, entry: &ExportEntry) -> std::result::Result<ImageInfo, String> {
+        let bytes = tree.read_file(&entry.absolute_path).map_err(|e| e.to_string())?;
+        let ellipsis = if self.ascii { "..." } else { "…" };
+        Ok(image::describe(&entry.absolute_path, &bytes, self.embed_images, ellipsis))
+    }
 
-        let mut output = String::new();
+    /// A file's text as exported with the lines it covers if that's not all of them,
+    /// `Err(None)` if it isn't UTF-8 or `Err(Some(e))` if it can't be read. Reading records
+    /// its line and token counts in `content_cache`.
+    fn read_content(
+        &self,
+        tree: &DirectoryTree,
+        entry: &ExportEntry,
+        content_cache: Option<&mut ContentCache>,
+    ) -> FileText {
+        let bytes = tree.read_file(&entry.absolute_path).map_err(|e| Some(e.to_string()))?;
+        if let Some(content_cache) = content_cache {
+            let relative_path = export_path(&entry.absolute_path, tree.scan_root_path());
+            content_cache.record(&relative_path, &entry.absolute_path, &bytes);
+        }
+        let text = String::from_utf8(bytes).map_err(|_| None)?;
+        if self.extract_matches.is_some() {
+            return Ok((text, None)); // Extracted lines carry their own numbers
+        }
 
-        // Always include file header for context
-        output.push_str(&format!("# {}\n\n", relative_path.display()));
+        // Numbered before the tail is cut, so numbers stay those of the whole file
+        let text = if self.include_line_numbers { number_lines(&text) } else { text };
+        let Some(count) = self.tail_lines else {
+            return Ok((text, None));
+        };
+        let tail = last_lines(&text, count);
+        let start = text[..text.len() - tail.len()].matches('\n').count();
+        let lines = (start > 0 && !tail.is_empty()).then(|| LineRange {
+            start,
+            end: start + tail.lines().count() - 1,
+        });
+        Ok((tail.to_string(), lines))
+    }
 
-        if self.include_metadata {
-            if let Some(size) = file_node.size {
-                output.push_str(&format!("**Size:** {}\n", format_file_size(size)));
-            }
-            output.push_str(&format!("**Path:** {}\n", file_node.path.display()));
-            output.push_str("\n");
-        }
-
-        // File content
-        match fs::read_to_string(&file_node.path) {
-            Ok(content) => {
-                output.push_str("```");
-
-                // Add language hint based on file extension
-                if let Some(ext) = file_node.path.extension() {
-                    let ext_str = ext.to_string_lossy().to_lowercase();
-                    let language = match ext_str.as_str() {
-                        "rs" => "rust",
-                        "py" => "python",
-                        "js" => "javascript",
-                        "ts" => "typescript",
-                        "jsx" => "jsx",
-                        "tsx" => "tsx",
-                        "html" => "html",
-                        "css" => "css",
-                        "scss" | "sass" => "scss",
-                        "json" => "json",
-                        "yaml" | "yml" => "yaml",
-                        "toml" => "toml",
-                        "xml" => "xml",
-                        "sql" => "sql",
-                        "sh" | "bash" => "bash",
-                        "c" => "c",
-                        "cpp" | "cc" | "cxx" => "cpp",
-                        "h" | "hpp" | "hxx" => "cpp",
-                        "java" => "java",
-                        "go" => "go",
-                        "rb" => "ruby",
-                        "php" => "php",
-                        "swift" => "swift",
-                        "kt" | "kts" => "kotlin",
-                        "scala" => "scala",
-                        "md" => "markdown",
-                        "typ" => "typst",
-                        _ => "",
-                    };
-                    output.push_str(language);
-                }
+    /// Expected length of `format_output`, from scan-time sizes and without reading any
+    /// file: everything the exporter adds is measured exactly, content by its size on disk.
+    /// Extracted matches depend on the content, so then it's the length of the export itself.
+    pub fn estimate_size(&self, tree: &DirectoryTree) -> u64 {
+        self.estimate(tree).size
+    }
 
-                output.push('\n');
+    /// `estimate_size`, and the export's tokens: counted when a file was read before and
+    /// hasn't changed since (the content cache has it), estimated from its size otherwise
+    pub fn estimate(&self, tree: &DirectoryTree) -> ExportEstimate {
+        let Ok(exporter) = self.exporter() else {
+            return ExportEstimate::default();
+        };
+        if self.extract_matches.is_some() {
+            let size = self.format_output(tree).map_or(0, |output| output.len() as u64);
+            return ExportEstimate { size, tokens: estimate_tokens(size) };
+        }
+        let entries = export_entries(tree);
+        let context = self.context(tree, &entries);
+        let mut output = String::new();
 
-                if self.include_line_numbers {
-                    for (line_num, line) in content.lines().enumerate() {
-                        output.push_str(&format!("{:4} | {}\n", line_num + 1, line));
-                    }
-                } else {
-                    output.push_str(&content);
+        let content_cache = self.locked_cache(tree);
+        exporter.begin(&mut output, &context);
+        let mut content_size = 0;
+        let (mut counted_size, mut counted_tokens) = (0, 0);
+        for (index, entry) in entries.iter().enumerate() {
+            if is_described(tree, entry) {
+                // A description is short, an embedded image base64 of its size
+                let size = entry.size.unwrap_or(0);
+                let image = ImageInfo {
+                    embedded: self.embed_images.filter(|&max_size| size <= max_size).map(|_| String::new()),
+                    ..ImageInfo::default()
+                };
+                exporter.emit_file(&mut output, index, FileContent::Image(&image), &context);
+                if image.embedded.is_some() {
+                    content_size += size.div_ceil(3) * 4;
                 }
-
-                output.push_str("\n```");
+                continue;
             }
-            Err(e) => {
-                output.push_str(&format!("*Error reading file: {}*", e));
+            exporter.emit_file(&mut output, index, FileContent::Text(""), &context);
+            let size = entry.size.unwrap_or(0);
+            content_size += size;
+            // Counts recorded by an earlier read are exact, as long as the file is unchanged
+            let summary = content_cache.as_ref().and_then(|content_cache| {
+                let relative_path = export_path(&entry.absolute_path, tree.scan_root_path());
+                content_cache.get(&relative_path, &entry.absolute_path)
+            });
+            if let Some(summary) = summary {
+                counted_size += size;
+                counted_tokens += summary.tokens;
+            }
+            if self.include_line_numbers {
+                let lines = summary.map_or(size.div_ceil(ESTIMATED_LINE_LENGTH), |summary| summary.lines as u64);
+                content_size += lines * LINE_NUMBER_WIDTH;
             }
         }
+        exporter.end(&mut output, &context);
+        self.final_newline.apply(&mut output);
 
-        Ok(output)
+        let size = output.len() as u64 + content_size;
+        ExportEstimate {
+            size,
+            tokens: estimate_tokens(size - counted_size) + counted_tokens,
+        }
+    }
+
+    /// What the content cache recorded for a file, if it's unchanged since; the file's stats
+    /// can then be shown without reading it
+    pub fn cached_summary(&self, tree: &DirectoryTree, absolute_path: &Path) -> Option<ContentSummary> {
+        let relative_path = export_path(absolute_path, tree.scan_root_path());
+        self.locked_cache(tree)?.get(&relative_path, absolute_path)
     }
+
+    /// The content cache, unless the tree is an archive: its entries have no mtime to check
+    fn locked_cache(&self, tree: &DirectoryTree) -> Option<MutexGuard<'_, ContentCache>> {
+        if tree.archive.is_some() {
+            return None;
+        }
+        self.content_cache.as_ref()?.lock().ok()
+    }
+
+    fn context<'a>(&self, tree: &'a DirectoryTree, entries: &'a [ExportEntry]) -> ExportContext<'a> {
+        let root = &tree.nodes[tree.root_index].path;
+        let generated_at = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string();
+        let mut footer = self.footer.as_ref().map(|footer| {
+            footer
+                .replace("{date}", &generated_at)
+                .replace("{root}", &root.display().to_string())
+                .trim_end()
+                .to_string()
+        });
+        if !self.exclusion_reasons.is_empty() {
+            let dash = if self.ascii { "-" } else { "—" };
+            let exclusions = self
+                .exclusion_reasons
+                .iter()
+                .map(|(path, reason)| format!("Intentionally excluded: {} {} {}", path, dash, reason))
+                .collect::<Vec<_>>()
+                .join("\n");
+            footer = Some(match footer {
+                Some(footer) => format!("{}\n\n{}", footer, exclusions),
+                None => exclusions,
+            });
+        }
+
+        ExportContext {
+            root,
+            files: entries,
+            include_metadata: self.include_metadata,
+            spacing: self.spacing,
+            footer,
+            generated_at,
+            uris: self.uris && tree.archive.is_none(), // Archive entries aren't files to open
+        }
+    }
+}
+
+/// A file's text as exported, as read by `OutputFormatter::read_content`
+type FileText = std::result::Result<(String, Option<LineRange>), Option<String>>;
+
+fn export_entries(tree: &DirectoryTree) -> Vec<ExportEntry> {
+    let root = &tree.nodes[tree.root_index].path;
+    tree.get_all_included_files()
+        .into_iter()
+        .map(|file_node| ExportEntry {
+            path: export_path(&file_node.path, root),
+            absolute_path: file_node.path.clone(),
+            size: file_node.size,
+            permissions: file_node.permissions.as_ref().map(|permissions| permissions.to_string()),
+        })
+        .collect()
 }
 
-fn format_file_size(size: u64) -> String {
+/// Whether an entry is an image exported as a description rather than as text
+fn is_described(tree: &DirectoryTree, entry: &ExportEntry) -> bool {
+    tree.export_images && image::is_image(&entry.absolute_path)
+}
+
+/// The last `count` lines of `content`, with their newlines
+fn last_lines(content: &str, count: usize) -> &str {
+    let body = content.strip_suffix('\n').unwrap_or(content);
+    match body.rmatch_indices('\n').nth(count.saturating_sub(1)) {
+        Some((index, _)) if count > 0 => &content[index + 1..],
+        _ => content,
+    }
+}
+
+fn number_lines(content: &str) -> String {
+    content
+        .lines()
+        .enumerate()
+        .map(|(line_num, line)| format!("{:4} | {}\n", line_num + 1, line))
+        .collect()
+}
+
+/// Code fence language for a file, by extension; empty when unknown
+pub fn language_hint(path: &Path) -> &'static str {
+    let Some(ext) = path.extension() else {
+        return "";
+    };
+    match ext.to_string_lossy().to_lowercase().as_str() {
+        "rs" => "rust",
+        "py" => "python",
+        "js" => "javascript",
+        "ts" => "typescript",
+        "jsx" => "jsx",
+        "tsx" => "tsx",
+        "html" => "html",
+        "css" => "css",
+        "scss" | "sass" => "scss",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "xml" => "xml",
+        "sql" => "sql",
+        "sh" | "bash" => "bash",
+        "c" => "c",
+        "cpp" | "cc" | "cxx" => "cpp",
+        "h" | "hpp" | "hxx" => "cpp",
+        "java" => "java",
+        "go" => "go",
+        "rb" => "ruby",
+        "php" => "php",
+        "swift" => "swift",
+        "kt" | "kts" => "kotlin",
+        "scala" => "scala",
+        "md" => "markdown",
+        "typ" => "typst",
+        _ => "",
+    }
+}
+
+pub fn format_file_size(size: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
     let mut size_f = size as f64;
     let mut unit_index = 0;
@@ -185,3 +666,225 @@ fn format_file_size(size: u64) -> String {
         format!("{:.1} {}", size_f, UNITS[unit_index])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::tokens::tokenizer;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_footer() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path().to_path_buf();
+        let file_path = root_path.join("main.rs");
+        fs::write(&file_path, "fn main() {}")?;
+
+        let mut tree = DirectoryTree::new(root_path.clone());
+        let index = tree.add_node(file_path, false, &root_path).unwrap();
+        tree.toggle_state(index);
+
+        let formatter = OutputFormatter::new()
+            .with_metadata(false)
+            .with_footer(Some("Context from {root}. Answer briefly.".to_string()));
+        let output = formatter.format_output(&tree)?;
+
+        let expected_footer = format!("\n\n---\n\nContext from {}. Answer briefly.\n", root_path.display());
+        assert!(output.starts_with("# main.rs"));
+        assert!(output.ends_with(&expected_footer));
+
+        let output = OutputFormatter::new().with_metadata(false).format_output(&tree)?;
+        assert!(output.ends_with("```\n"));
+
+        let reasons = vec![("vendor".to_string(), "vendored, see Cargo.toml".to_string())];
+        let formatter = OutputFormatter::new().with_metadata(false).with_exclusion_reasons(reasons);
+        let output = formatter.format_output(&tree)?;
+        assert!(output.ends_with("---\n\nIntentionally excluded: vendor — vendored, see Cargo.toml\n"));
+        let output = formatter.with_ascii(true).format_output(&tree)?;
+        assert!(output.ends_with("---\n\nIntentionally excluded: vendor - vendored, see Cargo.toml\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tail_lines() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path().to_path_buf();
+        let file_path = root_path.join("app.log");
+        fs::write(&file_path, "one\ntwo\nthree\nfour\n")?;
+
+        let mut tree = DirectoryTree::new(root_path.clone());
+        let index = tree.add_node(file_path, false, &root_path).unwrap();
+        tree.toggle_state(index);
+
+        let output = OutputFormatter::new().with_metadata(false).with_tail_lines(Some(2)).format_output(&tree)?;
+        assert!(output.contains("```\nthree\nfour\n") && !output.contains("two"));
+
+        // Line numbers are those of the whole file
+        let formatter = OutputFormatter::new().with_metadata(false).with_line_numbers(true).with_tail_lines(Some(1));
+        let output = formatter.format_output(&tree)?;
+        assert!(output.contains("```\n   4 | four\n") && !output.contains("three"));
+
+        assert_eq!(last_lines("a\nb", 5), "a\nb");
+        assert_eq!(last_lines("a\nb", 1), "b");
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_matches() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root_path.clone());
+        for (name, content) in [("a.rs", "fn a() {}\n// TODO: b\nfn c() {}\n"), ("d.rs", "fn d() {}\n")] {
+            let file_path = root_path.join(name);
+            fs::write(&file_path, content)?;
+            let index = tree.add_node(file_path, false, &root_path).unwrap();
+            tree.nodes[index].size = Some(content.len() as u64);
+            tree.toggle_state(index);
+        }
+
+        // Files without a match are left out, and the estimate is of the extracted lines
+        let extract_matches = ExtractMatches::new(regex::Regex::new("TODO").unwrap(), 0);
+        let formatter = OutputFormatter::new().with_metadata(false).with_extract_matches(Some(extract_matches));
+        let output = formatter.format_output(&tree)?;
+        assert!(output.contains("a.rs:2:// TODO: b\n") && !output.contains("fn a"));
+        assert!(!output.contains("d.rs"));
+        assert_eq!(formatter.estimate_size(&tree), output.len() as u64);
+        assert_eq!(formatter.exported_files(&tree), [root_path.join("a.rs")]);
+        assert_eq!(OutputFormatter::new().exported_files(&tree).len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_uris() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root_path.clone());
+        for (name, content) in [("my file#1.rs", "one\ntwo\n// TODO\nfour\nfive\n"), ("b.rs", "fn b() {}\n")] {
+            let file_path = root_path.join(name);
+            fs::write(&file_path, content)?;
+            let index = tree.add_node(file_path, false, &root_path).unwrap();
+            tree.toggle_state(index);
+        }
+        let export = |formatter: OutputFormatter| -> Result<serde_json::Value> {
+            Ok(serde_json::from_str(&formatter.with_format("json").with_metadata(false).format_output(&tree)?)?)
+        };
+
+        // Only files cut short get a range: the last two lines of five are lines 3-4
+        let value = export(OutputFormatter::new().with_uris(true).with_tail_lines(Some(2)))?;
+        assert_eq!(value["rootUri"], super::super::uri::file_uri(&root_path));
+        let file = &value["files"][0];
+        assert_eq!(file["path"], "my file#1.rs");
+        assert!(file["uri"].as_str().unwrap().ends_with("/my%20file%231.rs"));
+        assert_eq!(file["range"], serde_json::json!({ "startLine": 3, "endLine": 4 }));
+        assert_eq!(value["files"][1]["path"], "b.rs");
+        assert!(value["files"][1].get("range").is_none());
+
+        let extract_matches = ExtractMatches::new(regex::Regex::new("TODO").unwrap(), 1);
+        let value = export(OutputFormatter::new().with_uris(true).with_extract_matches(Some(extract_matches)))?;
+        assert_eq!(value["files"][0]["range"], serde_json::json!({ "startLine": 1, "endLine": 3 }));
+
+        // Without `--uris` the output is as before
+        let value = export(OutputFormatter::new().with_tail_lines(Some(2)))?;
+        assert!(value.get("rootUri").is_none());
+        assert!(value["files"][0].get("uri").is_none() && value["files"][0].get("range").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_estimate_size() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root_path.clone());
+        for (name, content) in [("main.rs", "fn main() {}\n"), ("notes.md", "# Notes\n\nSome text\n")] {
+            let file_path = root_path.join(name);
+            fs::write(&file_path, content)?;
+            let index = tree.add_node(file_path, false, &root_path).unwrap();
+            tree.nodes[index].size = Some(content.len() as u64);
+            tree.toggle_state(index);
+        }
+
+        // Headers, fences and footer are measured exactly
+        for formatter in [
+            OutputFormatter::new(),
+            OutputFormatter::new().with_metadata(false).with_footer(Some("Generated {date}".to_string())),
+        ] {
+            assert_eq!(formatter.estimate_size(&tree), formatter.format_output(&tree)?.len() as u64);
+        }
+
+        // Line numbers are approximated from an average line length
+        let formatter = OutputFormatter::new().with_line_numbers(true);
+        let actual = formatter.format_output(&tree)?.len() as u64;
+        assert!(formatter.estimate_size(&tree).abs_diff(actual) <= 3 * LINE_NUMBER_WIDTH);
+
+        // Once an export has recorded their line counts, unchanged files are estimated exactly
+        let content_cache = Arc::new(Mutex::new(ContentCache::default()));
+        let formatter = formatter.with_content_cache(Some(content_cache.clone()));
+        let actual = formatter.format_output(&tree)?.len() as u64;
+        assert_eq!(content_cache.lock().unwrap().len(), 2);
+        assert_eq!(formatter.estimate_size(&tree), actual);
+
+        // Tokens are estimated from sizes until files are read, then counted per file
+        let estimate = OutputFormatter::new().estimate(&tree);
+        assert_eq!(estimate.tokens, estimate_tokens(estimate.size));
+        let formatter = OutputFormatter::new().with_content_cache(Some(content_cache));
+        let estimate = formatter.estimate(&tree);
+        let counted = tokenizer().count(b"fn main() {}\n") + tokenizer().count(b"# Notes\n\nSome text\n");
+        assert_eq!(estimate.tokens, estimate_tokens(estimate.size - 33) + counted);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_spacing() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root_path.clone());
+        for name in ["a.rs", "b.rs"] {
+            let file_path = root_path.join(name);
+            fs::write(&file_path, "x")?;
+            let index = tree.add_node(file_path, false, &root_path).unwrap();
+            tree.nodes[index].size = Some(1);
+            tree.toggle_state(index);
+        }
+
+        let output = OutputFormatter::new().with_metadata(false).format_output(&tree)?;
+        assert_eq!(output, "# a.rs\n\n```rust\nx\n```\n\n# b.rs\n\n```rust\nx\n```\n");
+
+        let compact = Spacing {
+            blank_before_header: false,
+            blank_after_header: false,
+        };
+        let formatter = OutputFormatter::new().with_metadata(false).with_spacing(compact);
+        let output = formatter.format_output(&tree)?;
+        assert_eq!(output, "# a.rs\n```rust\nx\n```\n# b.rs\n```rust\nx\n```\n");
+        assert_eq!(formatter.estimate_size(&tree), output.len() as u64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_final_newline() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path().to_path_buf();
+        let file_path = root_path.join("notes.txt");
+        fs::write(&file_path, "last line\n\n")?;
+        let mut tree = DirectoryTree::new(root_path.clone());
+        let index = tree.add_node(file_path, false, &root_path).unwrap();
+        tree.toggle_state(index);
+
+        let output_with = |final_newline| {
+            OutputFormatter::new()
+                .with_metadata(false)
+                .with_format("plain")
+                .with_final_newline(final_newline)
+                .format_output(&tree)
+        };
+        assert!(output_with(FinalNewline::Single)?.ends_with("last line\n"));
+        assert!(output_with(FinalNewline::Strip)?.ends_with("last line"));
+        assert!(output_with(FinalNewline::Preserve)?.ends_with("last line\n\n"));
+
+        Ok(())
+    }
+}
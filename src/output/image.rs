@@ -0,0 +1,323 @@
+use super::formatter::format_file_size;
+use regex::Regex;
+use std::path::Path;
+
+/// Largest image embedded as base64 with `--embed-images`, by default
+pub const DEFAULT_EMBED_MAX_SIZE: u64 = 256 * 1024;
+
+/// Longest SVG title or description kept, in characters
+const MAX_TEXT_CHARS: usize = 200;
+
+/// What an export says about an image instead of its bytes (`image_stubs`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImageInfo {
+    pub format: &'static str, // "PNG", "JPEG", ...
+    pub mime: &'static str,
+    pub dimensions: Option<(u32, u32)>, // Unknown when the header is cut short or corrupt
+    pub size: u64,
+    pub title: Option<String>,       // SVG `<title>`
+    pub description: Option<String>, // SVG `<desc>`
+    pub embedded: Option<String>,    // Base64 of the file, with `--embed-images`
+}
+
+impl ImageInfo {
+    /// `PNG, 1280x720, 245.3 KB`
+    pub fn summary(&self) -> String {
+        let dimensions = match self.dimensions {
+            Some((width, height)) => format!("{}x{}", width, height),
+            None => "unknown dimensions".to_string(),
+        };
+        format!("{}, {}, {}", self.format, dimensions, format_file_size(self.size))
+    }
+
+    /// A `data:` URI of the embedded file
+    pub fn data_uri(&self) -> Option<String> {
+        self.embedded.as_ref().map(|data| format!("data:{};base64,{}", self.mime, data))
+    }
+}
+
+/// Whether a file is an image exported as a description, by extension
+pub fn is_image(path: &Path) -> bool {
+    kind_for_extension(path).is_some()
+}
+
+fn kind_for_extension(path: &Path) -> Option<(&'static str, &'static str)> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    Some(match extension.as_str() {
+        "png" => ("PNG", "image/png"),
+        "jpg" | "jpeg" => ("JPEG", "image/jpeg"),
+        "gif" => ("GIF", "image/gif"),
+        "webp" => ("WebP", "image/webp"),
+        "svg" => ("SVG", "image/svg+xml"),
+        _ => return None,
+    })
+}
+
+/// Read what the header says about an image. The format comes from the content when it's
+/// recognizable, else the extension; nothing here fails, a corrupt file just has less to say.
+/// Files up to `embed_max_size` bytes are embedded as base64, if given. SVG text cut short
+/// ends in `ellipsis`.
+pub fn describe(path: &Path, bytes: &[u8], embed_max_size: Option<u64>, ellipsis: &str) -> ImageInfo {
+    let (format, mime) = kind_for_extension(path).unwrap_or(("image", "application/octet-stream"));
+    let mut info = ImageInfo { format, mime, size: bytes.len() as u64, ..ImageInfo::default() };
+
+    if let Some(dimensions) = png_dimensions(bytes) {
+        (info.format, info.mime, info.dimensions) = ("PNG", "image/png", Some(dimensions));
+    } else if let Some(dimensions) = gif_dimensions(bytes) {
+        (info.format, info.mime, info.dimensions) = ("GIF", "image/gif", Some(dimensions));
+    } else if let Some(dimensions) = jpeg_dimensions(bytes) {
+        (info.format, info.mime, info.dimensions) = ("JPEG", "image/jpeg", Some(dimensions));
+    } else if let Some(dimensions) = webp_dimensions(bytes) {
+        (info.format, info.mime, info.dimensions) = ("WebP", "image/webp", Some(dimensions));
+    } else if format == "SVG" {
+        let text = String::from_utf8_lossy(bytes);
+        info.dimensions = svg_dimensions(&text);
+        info.title = svg_text("title", &text, ellipsis);
+        info.description = svg_text("desc", &text, ellipsis);
+    }
+
+    if embed_max_size.is_some_and(|max_size| info.size <= max_size) {
+        info.embedded = Some(base64(bytes));
+    }
+    info
+}
+
+fn be_u16(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32)
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32)
+}
+
+fn le_u24(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 3)?;
+    Some(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16)
+}
+
+/// Width and height from the IHDR chunk, which must come first
+fn png_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if !bytes.starts_with(b"\x89PNG\r\n\x1a\n") || bytes.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(bytes.get(16..20)?.try_into().ok()?);
+    let height = u32::from_be_bytes(bytes.get(20..24)?.try_into().ok()?);
+    Some((width, height))
+}
+
+fn gif_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if !bytes.starts_with(b"GIF87a") && !bytes.starts_with(b"GIF89a") {
+        return None;
+    }
+    Some((le_u16(bytes, 6)?, le_u16(bytes, 8)?))
+}
+
+/// Width and height from the first start-of-frame segment, skipping the others by length
+fn jpeg_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut at = 2;
+    loop {
+        // Markers may be padded with any number of 0xFF bytes
+        if *bytes.get(at)? != 0xFF {
+            return None;
+        }
+        while *bytes.get(at)? == 0xFF {
+            at += 1;
+        }
+        let marker = *bytes.get(at)?;
+        at += 1;
+        match marker {
+            0xD8 | 0x01 | 0xD0..=0xD7 => continue, // No length follows
+            0xD9 | 0xDA => return None,             // End of image, or compressed data before any frame
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                return Some((be_u16(bytes, at + 5)?, be_u16(bytes, at + 3)?));
+            }
+            _ => {
+                let length = be_u16(bytes, at)? as usize;
+                if length < 2 {
+                    return None;
+                }
+                at += length;
+            }
+        }
+    }
+}
+
+/// Width and height from the first chunk: lossy (`VP8 `), lossless (`VP8L`) or extended (`VP8X`)
+fn webp_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if !bytes.starts_with(b"RIFF") || bytes.get(8..12)? != b"WEBP" {
+        return None;
+    }
+    match bytes.get(12..16)? {
+        b"VP8 " => Some((le_u16(bytes, 26)? & 0x3FFF, le_u16(bytes, 28)? & 0x3FFF)),
+        b"VP8L" => {
+            let b = bytes.get(21..25)?;
+            let width = 1 + (((b[1] as u32 & 0x3F) << 8) | b[0] as u32);
+            let height = 1 + (((b[3] as u32 & 0x0F) << 10) | (b[2] as u32) << 2 | (b[1] as u32 & 0xC0) >> 6);
+            Some((width, height))
+        }
+        b"VP8X" => Some((1 + le_u24(bytes, 24)?, 1 + le_u24(bytes, 27)?)),
+        _ => None,
+    }
+}
+
+/// `width` and `height` of the root element when both are plain numbers (or pixels), else the
+/// size of its `viewBox`
+fn svg_dimensions(text: &str) -> Option<(u32, u32)> {
+    let attributes = Regex::new(r"(?s)<svg\b([^>]*)>").ok()?.captures(text)?.get(1)?.as_str();
+    let attribute = |name: &str| -> Option<String> {
+        let pattern = Regex::new(&format!(r#"(?:^|\s){}\s*=\s*["']([^"']*)["']"#, name)).ok()?;
+        Some(pattern.captures(attributes)?.get(1)?.as_str().trim().to_string())
+    };
+    let pixels = |value: String| -> Option<u32> {
+        let number: f64 = value.strip_suffix("px").unwrap_or(&value).trim().parse().ok()?;
+        (number.is_finite() && number >= 0.0 && number <= u32::MAX as f64).then_some(number.round() as u32)
+    };
+
+    if let (Some(width), Some(height)) = (attribute("width").and_then(pixels), attribute("height").and_then(pixels)) {
+        return Some((width, height));
+    }
+    let view_box = attribute("viewBox")?;
+    let numbers: Vec<&str> = view_box.split([' ', ',']).filter(|part| !part.is_empty()).collect();
+    match numbers[..] {
+        [_, _, width, height] => Some((pixels(width.to_string())?, pixels(height.to_string())?)),
+        _ => None,
+    }
+}
+
+/// The first `element`'s text, with entities and runs of whitespace tidied, cut to a sane length
+fn svg_text(element: &str, text: &str, ellipsis: &str) -> Option<String> {
+    let pattern = Regex::new(&format!(r"(?s)<{0}\b[^>]*>(.*?)</{0}>", element)).ok()?;
+    let raw = pattern.captures(text)?.get(1)?.as_str();
+    let raw = raw.strip_prefix("<![CDATA[").and_then(|raw| raw.strip_suffix("]]>")).unwrap_or(raw);
+    let text = raw
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+    if text.is_empty() {
+        return None;
+    }
+    Some(match text.char_indices().nth(MAX_TEXT_CHARS) {
+        Some((end, _)) => format!("{}{}", &text[..end], ellipsis),
+        None => text,
+    })
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for position in 0..4 {
+            if position <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * position) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        bytes.extend(width.to_be_bytes());
+        bytes.extend(height.to_be_bytes());
+        bytes.extend([8, 6, 0, 0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn test_describe_headers() {
+        let info = describe(Path::new("shot.png"), &png(1280, 720), None, "…");
+        assert_eq!((info.format, info.dimensions, info.size), ("PNG", Some((1280, 720)), 29));
+        assert_eq!(info.summary(), "PNG, 1280x720, 29 B");
+
+        let gif = b"GIF89a\x40\x01\xf0\x00\x00\x00\x00;";
+        assert_eq!(describe(Path::new("a.gif"), gif, None, "…").dimensions, Some((320, 240)));
+
+        // An APP0 segment to skip, then a baseline frame of 640x480
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00];
+        jpeg.extend([0xFF, 0xFF, 0xC0, 0x00, 0x11, 0x08, 0x01, 0xE0, 0x02, 0x80]);
+        assert_eq!(describe(Path::new("a.jpeg"), &jpeg, None, "…").dimensions, Some((640, 480)));
+
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X\x0a\0\0\0\0\0\0\0".to_vec();
+        webp.extend([0x7F, 0x02, 0x00, 0xDF, 0x01, 0x00]);
+        assert_eq!(describe(Path::new("a.webp"), &webp, None, "…").dimensions, Some((640, 480)));
+
+        // A PNG named .jpg is described by its content
+        assert_eq!(describe(Path::new("misnamed.jpg"), &png(2, 3), None, "…").format, "PNG");
+    }
+
+    #[test]
+    fn test_describe_svg() {
+        let svg = r#"<?xml version="1.0"?>
+            <svg xmlns="http://www.w3.org/2000/svg" width="120px" height="80" viewBox="0 0 12 8">
+              <title>Build   &amp; deploy</title>
+              <desc><![CDATA[Arrows from CI to prod]]></desc>
+            </svg>"#;
+        let info = describe(Path::new("flow.svg"), svg.as_bytes(), None, "…");
+        assert_eq!(info.dimensions, Some((120, 80)));
+        assert_eq!(info.title.as_deref(), Some("Build & deploy"));
+        assert_eq!(info.description.as_deref(), Some("Arrows from CI to prod"));
+
+        // Relative sizes fall back to the view box
+        let svg = r#"<svg width="100%" viewBox="0,0,300,150"><title> </title></svg>"#;
+        let info = describe(Path::new("a.svg"), svg.as_bytes(), None, "…");
+        assert_eq!((info.dimensions, info.title), (Some((300, 150)), None));
+
+        // Long text is cut short with the ellipsis given, e.g. ASCII under --ascii
+        let svg = format!("<svg><title>{}</title></svg>", "a".repeat(300));
+        let title = describe(Path::new("a.svg"), svg.as_bytes(), None, "...").title.unwrap();
+        assert_eq!(title, format!("{}...", "a".repeat(200)));
+    }
+
+    #[test]
+    fn test_corrupt_images() {
+        // Truncated or garbled headers leave the dimensions unknown, never panic
+        let full = png(10, 10);
+        for end in 0..24 {
+            let info = describe(Path::new("cut.png"), &full[..end], None, "…");
+            assert_eq!(info.dimensions, None, "{}", end);
+        }
+        let garbage: Vec<u8> = (0..=255).cycle().take(4096).map(|byte: u8| byte.wrapping_mul(37)).collect();
+        for (name, prefix) in [("a.jpg", &[0xFF, 0xD8][..]), ("a.webp", b"RIFF\0\0\0\0WEBPVP8L"), ("a.gif", b"GIF89a")] {
+            let bytes = [prefix, &garbage[..]].concat();
+            for end in 0..64 {
+                describe(Path::new(name), &bytes[..end], None, "…");
+            }
+            describe(Path::new(name), &bytes, None, "…");
+        }
+        // A JPEG whose segment lengths loop back on themselves ends rather than spins
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x00];
+        assert_eq!(describe(Path::new("a.jpg"), &jpeg, None, "…").dimensions, None);
+        let svg = describe(Path::new("a.svg"), b"<svg width=\"NaN\" height=\"-1\" viewBox=\"0 0\">", None, "…");
+        assert_eq!(svg.dimensions, None);
+        assert_eq!(describe(Path::new("a.svg"), &[0xFF, 0xFE], None, "…").format, "SVG");
+    }
+
+    #[test]
+    fn test_embedding() {
+        let bytes = png(1, 1);
+        assert_eq!(describe(Path::new("a.png"), &bytes, None, "…").embedded, None);
+        assert_eq!(describe(Path::new("a.png"), &bytes, Some(10), "…").embedded, None); // Over the cap
+        let info = describe(Path::new("a.png"), &bytes, Some(1024), "…");
+        assert!(info.data_uri().unwrap().starts_with("data:image/png;base64,iVBORw0KGgo"));
+
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+}
@@ -0,0 +1,88 @@
+use super::exporter::{ExportContext, Exporter, FileContent};
+use super::uri::file_uri;
+use serde_json::{Value, json};
+use std::path::Path;
+
+/// A single JSON object with a `files` array, for scripts and tooling. With `--uris`,
+/// the root and each file also get a `file://` URI for editors and language servers,
+/// and files exported only in part get the zero-based `range` of lines they cover.
+pub struct JsonExporter;
+
+impl Exporter for JsonExporter {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn begin(&self, output: &mut String, context: &ExportContext) {
+        output.push_str(&format!("{{\"root\":{}", Value::from(context.root.display().to_string())));
+        if context.uris {
+            output.push_str(&format!(",\"rootUri\":{}", Value::from(uri(context.root))));
+        }
+        if context.include_metadata {
+            output.push_str(&format!(",\"generated\":{}", Value::from(context.generated_at.as_str())));
+        }
+        output.push_str(",\"files\":[");
+    }
+
+    fn emit_file(&self, output: &mut String, index: usize, content: FileContent, context: &ExportContext) {
+        let entry = &context.files[index];
+        let mut file = json!({ "path": entry.path, "size": entry.size });
+        if context.uris {
+            file["uri"] = Value::from(uri(&entry.absolute_path));
+        }
+        if let Some(permissions) = &entry.permissions {
+            file["permissions"] = Value::from(permissions.as_str());
+        }
+        match content {
+            FileContent::Text(text) => file["content"] = Value::from(text),
+            FileContent::Excerpt(text, lines) => {
+                file["content"] = Value::from(text);
+                if context.uris {
+                    file["range"] = json!({ "startLine": lines.start, "endLine": lines.end });
+                }
+            }
+            FileContent::Binary => file["binary"] = Value::from(true),
+            FileContent::Image(image) => {
+                let mut description = json!({ "format": image.format });
+                if let Some((width, height)) = image.dimensions {
+                    description["width"] = Value::from(width);
+                    description["height"] = Value::from(height);
+                }
+                if let Some(title) = &image.title {
+                    description["title"] = Value::from(title.as_str());
+                }
+                if let Some(text) = &image.description {
+                    description["description"] = Value::from(text.as_str());
+                }
+                if let Some(data_uri) = image.data_uri() {
+                    description["data"] = Value::from(data_uri);
+                }
+                file["image"] = description;
+            }
+            FileContent::Error(e) => file["error"] = Value::from(e),
+        }
+
+        if index > 0 {
+            output.push(',');
+        }
+        output.push('\n');
+        output.push_str(&file.to_string());
+    }
+
+    fn end(&self, output: &mut String, context: &ExportContext) {
+        output.push_str("\n]");
+        if let Some(footer) = &context.footer {
+            output.push_str(&format!(",\"footer\":{}", Value::from(footer.as_str())));
+        }
+        output.push_str("}\n");
+    }
+}
+
+/// The root may have been given as a relative path such as `.`
+fn uri(path: &Path) -> String {
+    file_uri(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()))
+}
@@ -0,0 +1,111 @@
+use super::exporter::{ExportContext, Exporter, FileContent};
+use super::formatter::{format_file_size, language_hint};
+
+/// The default format: a report header, then each file under a `# path` heading in a code fence
+pub struct MarkdownExporter;
+
+impl Exporter for MarkdownExporter {
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+
+    fn extension(&self) -> &'static str {
+        "md"
+    }
+
+    fn append_safe(&self) -> bool {
+        true
+    }
+
+    fn begin(&self, output: &mut String, context: &ExportContext) {
+        if !context.include_metadata {
+            return;
+        }
+        let total_size: u64 = context.files.iter().filter_map(|entry| entry.size).sum();
+
+        output.push_str("# Text Ingest Report\n");
+        output.push_str(&format!("**Root Directory:** {}\n", context.root.display()));
+        output.push_str(&format!("**Files Included:** {}\n", context.files.len()));
+        output.push_str(&format!("**Total Size:** {}\n", format_file_size(total_size)));
+        output.push_str(&format!("**Generated:** {}\n", context.generated_at));
+
+        output.push_str("\n## Included Files\n");
+        for entry in context.files {
+            let size_str = entry
+                .size
+                .map(format_file_size)
+                .unwrap_or_else(|| "Unknown".to_string());
+            output.push_str(&format!("- {} ({})\n", entry.path, size_str));
+        }
+        output.push_str("\n\n");
+    }
+
+    fn emit_file(&self, output: &mut String, index: usize, content: FileContent, context: &ExportContext) {
+        let entry = &context.files[index];
+        if index > 0 {
+            output.push_str(context.spacing.file_separator());
+        }
+
+        // Always include file header for context
+        output.push_str(&format!("# {}\n", entry.path));
+        if context.spacing.blank_after_header {
+            output.push('\n');
+        }
+
+        if context.include_metadata {
+            if let Some(size) = entry.size {
+                output.push_str(&format!("**Size:** {}\n", format_file_size(size)));
+            }
+            output.push_str(&format!("**Path:** {}\n", entry.absolute_path.display()));
+        }
+        if let Some(permissions) = &entry.permissions {
+            output.push_str(&format!("**Permissions:** {}\n", permissions));
+        }
+        if context.include_metadata || entry.permissions.is_some() {
+            output.push('\n');
+        }
+
+        match content {
+            FileContent::Text(text) | FileContent::Excerpt(text, _) => {
+                let fence = fence_for(text);
+                output.push_str(&fence);
+                output.push_str(language_hint(&entry.absolute_path));
+                output.push('\n');
+                output.push_str(text);
+                output.push('\n');
+                output.push_str(&fence);
+            }
+            FileContent::Binary => output.push_str("*Binary file, content omitted*"),
+            FileContent::Image(image) => {
+                output.push_str(&format!("*Image: {}*", image.summary()));
+                if let Some(title) = &image.title {
+                    output.push_str(&format!("\n**Title:** {}", title));
+                }
+                if let Some(description) = &image.description {
+                    output.push_str(&format!("\n**Description:** {}", description));
+                }
+                if let Some(data_uri) = image.data_uri() {
+                    output.push_str(&format!("\n\n![{}]({})", entry.path, data_uri));
+                }
+            }
+            FileContent::Error(e) => output.push_str(&format!("*Error reading file: {}*", e)),
+        }
+    }
+
+    fn end(&self, output: &mut String, context: &ExportContext) {
+        // Separate the footer from the last file with a horizontal rule
+        if let Some(footer) = &context.footer {
+            output.push_str(&format!("\n\n---\n\n{}\n", footer));
+        }
+    }
+}
+
+/// A code fence longer than any run of backticks in the content, so it can't be closed early
+fn fence_for(content: &str) -> String {
+    let longest_run = content
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest_run.max(2) + 1)
+}
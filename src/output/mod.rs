@@ -1,3 +1,14 @@
+pub mod exporter;
+pub mod extract;
+pub mod image;
 pub mod formatter;
+pub mod json;
+pub mod markdown;
+pub mod plain;
+pub mod publish;
+pub mod resume;
+pub mod serve;
+pub mod tokens;
+pub mod uri;
 pub mod writer;
-
+pub mod xml;
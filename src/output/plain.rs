@@ -0,0 +1,65 @@
+use super::exporter::{ExportContext, Exporter, FileContent};
+
+/// Bare file contents between `===== path =====` separators, for tools that don't render markdown
+pub struct PlainExporter;
+
+impl Exporter for PlainExporter {
+    fn name(&self) -> &'static str {
+        "plain"
+    }
+
+    fn extension(&self) -> &'static str {
+        "txt"
+    }
+
+    fn append_safe(&self) -> bool {
+        true
+    }
+
+    fn begin(&self, output: &mut String, context: &ExportContext) {
+        if context.include_metadata {
+            output.push_str(&format!("Root: {}\n", context.root.display()));
+            output.push_str(&format!("Files: {}\n", context.files.len()));
+            output.push_str(&format!("Generated: {}\n\n", context.generated_at));
+        }
+    }
+
+    fn emit_file(&self, output: &mut String, index: usize, content: FileContent, context: &ExportContext) {
+        if index > 0 && context.spacing.blank_before_header {
+            output.push('\n');
+        }
+        let entry = &context.files[index];
+        match &entry.permissions {
+            Some(permissions) => output.push_str(&format!("===== {} ({}) =====\n", entry.path, permissions)),
+            None => output.push_str(&format!("===== {} =====\n", entry.path)),
+        }
+        match content {
+            FileContent::Text(text) | FileContent::Excerpt(text, _) => {
+                output.push_str(text);
+                if !text.ends_with('\n') {
+                    output.push('\n');
+                }
+            }
+            FileContent::Binary => output.push_str("[binary file, content omitted]\n"),
+            FileContent::Image(image) => {
+                output.push_str(&format!("[image: {}]\n", image.summary()));
+                if let Some(title) = &image.title {
+                    output.push_str(&format!("Title: {}\n", title));
+                }
+                if let Some(description) = &image.description {
+                    output.push_str(&format!("Description: {}\n", description));
+                }
+                if let Some(data_uri) = image.data_uri() {
+                    output.push_str(&format!("{}\n", data_uri));
+                }
+            }
+            FileContent::Error(e) => output.push_str(&format!("[error reading file: {}]\n", e)),
+        }
+    }
+
+    fn end(&self, output: &mut String, context: &ExportContext) {
+        if let Some(footer) = &context.footer {
+            output.push_str(&format!("\n{}\n", footer));
+        }
+    }
+}
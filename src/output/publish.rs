@@ -0,0 +1,187 @@
+use super::exporter::ExporterRegistry;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// What an export produced: a file on disk, or only text (clipboard exports)
+pub enum ExportOutput<'a> {
+    File(&'a Path),
+    Content(&'a str),
+}
+
+/// Where an export was published, and why old exports couldn't be pruned if they couldn't
+pub struct Published {
+    pub path: PathBuf,
+    pub prune_error: Option<anyhow::Error>,
+}
+
+/// Drops a copy of each export into a watched directory, e.g. for LLM tooling
+#[derive(Debug, Clone)]
+pub struct Publisher {
+    dir: PathBuf,
+    keep_days: Option<u64>,
+    keep_files: Option<usize>,
+    max_bytes: Option<u64>,
+}
+
+impl Publisher {
+    pub fn new(dir: PathBuf) -> Self {
+        // Config files can't rely on the shell to expand `~`
+        let dir = match (dir.strip_prefix("~"), dirs::home_dir()) {
+            (Ok(rest), Some(home)) => home.join(rest),
+            _ => dir,
+        };
+        Self {
+            dir,
+            keep_days: None,
+            keep_files: None,
+            max_bytes: None,
+        }
+    }
+
+    /// Prune published exports older than `keep_days`, and all but the newest `keep_files`
+    pub fn with_retention(mut self, keep_days: Option<u64>, keep_files: Option<usize>) -> Self {
+        self.keep_days = keep_days;
+        self.keep_files = keep_files;
+        self
+    }
+
+    /// Also prune the oldest exports until the rest take up at most `max_bytes`
+    pub fn with_max_bytes(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Publish under `file_name` (already timestamped), hard linking exported files where
+    /// possible, then prune old exports. Failing to prune doesn't undo the publish, so it's
+    /// returned alongside the published path rather than as an error.
+    pub fn publish(&self, file_name: &str, output: ExportOutput) -> Result<Published> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let target = self.dir.join(file_name);
+
+        match output {
+            ExportOutput::File(source) => {
+                // Hard links fail across filesystems; fall back to a copy
+                if fs::hard_link(source, &target).is_err() {
+                    fs::copy(source, &target)
+                        .with_context(|| format!("Failed to copy export to {}", target.display()))?;
+                }
+            }
+            ExportOutput::Content(content) => {
+                fs::write(&target, content)
+                    .with_context(|| format!("Failed to write {}", target.display()))?;
+            }
+        }
+
+        Ok(Published {
+            path: target,
+            prune_error: self.prune_exports(false).err(),
+        })
+    }
+
+    /// Remove published exports beyond the retention limits (or with `dry_run`, only list
+    /// them), returning their paths. Only files that look like gthr exports are considered.
+    pub fn prune_exports(&self, dry_run: bool) -> Result<Vec<PathBuf>> {
+        if self.keep_days.is_none() && self.keep_files.is_none() && self.max_bytes.is_none() {
+            return Ok(Vec::new());
+        }
+        if !self.dir.is_dir() {
+            return Ok(Vec::new()); // Nothing published yet
+        }
+
+        let mut exports: Vec<(PathBuf, SystemTime, u64)> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| is_published_export(&entry.file_name().to_string_lossy()))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((entry.path(), metadata.modified().ok()?, metadata.len()))
+            })
+            .collect();
+        // Newest first
+        exports.sort_by_key(|(_, modified, _)| std::cmp::Reverse(*modified));
+
+        let cutoff = self
+            .keep_days
+            .and_then(|days| SystemTime::now().checked_sub(Duration::from_secs(days * 24 * 60 * 60)));
+        let mut kept_bytes = 0;
+        let mut removed = Vec::new();
+        for (position, (path, modified, size)) in exports.into_iter().enumerate() {
+            let too_many = self.keep_files.is_some_and(|keep_files| position >= keep_files);
+            let too_old = cutoff.is_some_and(|cutoff| modified < cutoff);
+            let too_big = self.max_bytes.is_some_and(|max_bytes| kept_bytes + size > max_bytes);
+            if !(too_many || too_old || too_big) {
+                kept_bytes += size;
+            } else if dry_run || fs::remove_file(&path).is_ok() {
+                removed.push(path);
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Names produced by `OutputWriter::generate_default_filename`, in any export format or as
+/// a patch
+fn is_published_export(name: &str) -> bool {
+    let Some((stem, extension)) = name.rsplit_once('.') else {
+        return false;
+    };
+    stem.contains("_ingest_") && (extension == "patch" || ExporterRegistry::builtin().for_extension(extension).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_publish_and_prune() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let publish_dir = temp_dir.path().join("prompts");
+        let export_path = temp_dir.path().join("export.md");
+        fs::write(&export_path, "# export")?;
+
+        let publisher = Publisher::new(publish_dir.clone()).with_retention(Some(7), Some(2));
+        let linked = publisher.publish("repo_ingest_1.md", ExportOutput::File(&export_path))?;
+        assert!(linked.prune_error.is_none());
+        assert_eq!(fs::read_to_string(linked.path)?, "# export");
+
+        // An old export, in any format, is pruned by age; unrelated files are left alone
+        let age = |name: &str, secs: u64| -> Result<()> {
+            let file = fs::File::options().write(true).open(publish_dir.join(name))?;
+            Ok(file.set_modified(SystemTime::now() - Duration::from_secs(secs))?)
+        };
+        let old = publish_dir.join("repo_ingest_0.json");
+        fs::write(&old, "old")?;
+        age("repo_ingest_0.json", 30 * 24 * 60 * 60)?;
+        fs::write(publish_dir.join("notes.md"), "mine")?;
+
+        publisher.publish("repo_ingest_2.md", ExportOutput::Content("two"))?;
+        assert!(!old.exists());
+        assert!(publish_dir.join("notes.md").exists());
+
+        // Only the newest two exports are kept
+        age("repo_ingest_1.md", 120)?;
+        age("repo_ingest_2.md", 60)?;
+        publisher.publish("repo_ingest_3.md", ExportOutput::Content("three"))?;
+        let mut remaining: Vec<String> = fs::read_dir(&publish_dir)?
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| is_published_export(name))
+            .collect();
+        remaining.sort();
+        assert_eq!(remaining, ["repo_ingest_2.md", "repo_ingest_3.md"]);
+
+        // Size-bounded: the newest exports that fit are kept, and a dry run removes nothing
+        let bounded = Publisher::new(publish_dir.clone()).with_max_bytes(Some(5));
+        let would_remove = bounded.prune_exports(true)?;
+        assert_eq!(would_remove.len(), 1);
+        assert!(would_remove[0].exists());
+        assert_eq!(bounded.prune_exports(false)?, would_remove);
+        assert!(publish_dir.join("repo_ingest_3.md").exists());
+
+        assert!(is_published_export("repo_ingest_4.xml") && is_published_export("repo_ingest_5.patch"));
+        assert!(!is_published_export("repo_ingest_6.rs") && !is_published_export("repo_ingest"));
+        Ok(())
+    }
+}
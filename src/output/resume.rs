@@ -0,0 +1,66 @@
+use crate::constants::PROJECT_DIR;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where progress is recorded, in `PROJECT_DIR` under the scanned root
+pub const STATE_FILE: &str = "export-state.json";
+
+/// How far a streamed export to a file got, saved after every file written
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportState {
+    pub output_path: PathBuf, // Absolute
+    pub options_hash: String, // `OutputFormatter::options_hash`
+    pub completed_entries: usize,
+    pub total_entries: usize,
+    pub last_entry: Option<String>, // Path of the last file written, as exported
+    pub offset: u64,                // Bytes written up to and including `last_entry`
+}
+
+/// The state file of a root's interrupted export. It only exists while an export is being
+/// written: finishing one removes it.
+#[derive(Debug, Clone)]
+pub struct ExportProgress {
+    path: PathBuf,
+}
+
+impl ExportProgress {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            path: root.join(PROJECT_DIR).join(STATE_FILE),
+        }
+    }
+
+    /// The recorded state, if it's for the same output with the same options and the partial
+    /// output is still exactly as long as recorded. Anything else means starting over.
+    pub fn resumable(&self, output_path: &Path, options_hash: &str) -> Option<ExportState> {
+        let content = fs::read_to_string(&self.path).ok()?;
+        let state: ExportState = serde_json::from_str(&content).ok()?;
+        let written = fs::metadata(output_path).ok()?.len();
+        let same_output = std::path::absolute(output_path).is_ok_and(|path| path == state.output_path);
+        (same_output && state.options_hash == options_hash && written == state.offset).then_some(state)
+    }
+
+    /// Write to a temporary file and rename it over the state, so an interruption never
+    /// leaves a half-written one
+    pub fn save(&self, state: &ExportState) -> io::Result<()> {
+        let Some(dir) = self.path.parent() else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir)?;
+        let temp_path = dir.join(format!(".{}.{}.tmp", STATE_FILE, std::process::id()));
+        fs::write(&temp_path, serde_json::to_string_pretty(state)?)?;
+        fs::rename(&temp_path, &self.path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp_path);
+        })
+    }
+
+    /// Remove the state, and its directory if nothing else is in it
+    pub fn clear(&self) {
+        let _ = fs::remove_file(&self.path);
+        if let Some(dir) = self.path.parent() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+}
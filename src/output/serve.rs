@@ -0,0 +1,289 @@
+use super::exporter::export_path;
+use crate::directory::tree::DirectoryTree;
+use anyhow::{Context, Result};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long `--serve` waits for the bundle to be downloaded
+pub const SERVE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// How often the accept loop checks for Ctrl+C and the timeout
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// An export ready to be served: the bundle itself and a manifest describing it, both
+/// under a random token so only whoever was shown the URL can fetch them
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    content: String,
+    extension: &'static str,
+    manifest: String,
+    token: String,
+}
+
+impl Bundle {
+    /// `extension` is the format's (`md`, `json`, ...), or `patch` for `--patch` exports
+    pub fn new(tree: &DirectoryTree, content: String, extension: &'static str) -> Self {
+        let token = random_token();
+        let files: Vec<serde_json::Value> = tree
+            .get_all_included_files()
+            .into_iter()
+            .map(|file| {
+                serde_json::json!({
+                    "path": export_path(&file.path, tree.scan_root_path()),
+                    "size": file.size,
+                })
+            })
+            .collect();
+        let manifest = serde_json::json!({
+            "bundle": format!("/{}/bundle.{}", token, extension),
+            "content_type": content_type(extension),
+            "bytes": content.len(),
+            "files": files,
+        });
+        Self {
+            content,
+            extension,
+            manifest: format!("{:#}\n", manifest),
+            token,
+        }
+    }
+
+    pub fn path(&self) -> String {
+        format!("/{}/bundle.{}", self.token, self.extension)
+    }
+
+    pub fn manifest_path(&self) -> String {
+        format!("/{}/manifest.json", self.token)
+    }
+}
+
+/// 128 random bits as hex. std's hasher keys come from the OS's random source, so
+/// hashing nothing with two fresh ones gives 64 unguessable bits each.
+fn random_token() -> String {
+    (0..2).map(|_| format!("{:016x}", RandomState::new().build_hasher().finish())).collect()
+}
+
+/// How serving ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Served {
+    Downloaded,
+    TimedOut,
+    Stopped, // Ctrl+C
+}
+
+/// A minimal HTTP/1.1 server for one bundle, bound to 127.0.0.1 only. It answers
+/// `GET`/`HEAD` for the bundle and its manifest, one connection at a time, and stops
+/// after the bundle's first full download. Requests naming any host but this server's
+/// own address are refused, so a web page can't reach it through DNS rebinding. The token
+/// is the access control, so the bundle and manifest allow any origin, and answer CORS
+/// preflights, for browser-based LLM frontends to fetch them.
+#[derive(Debug)]
+pub struct BundleServer {
+    listener: TcpListener,
+    timeout: Duration,
+}
+
+impl BundleServer {
+    /// Bind to `port` on the loopback interface; 0 picks a free port
+    pub fn bind(port: u16) -> Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .with_context(|| format!("Failed to listen on 127.0.0.1:{}", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            timeout: SERVE_TIMEOUT,
+        })
+    }
+
+    #[cfg(test)]
+    fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serve until the bundle is downloaded, the timeout passes or `stop` is set
+    pub fn serve(&self, bundle: &Bundle, stop: &AtomicBool) -> Result<Served> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                return Ok(Served::Stopped);
+            }
+            if Instant::now() >= deadline {
+                return Ok(Served::TimedOut);
+            }
+            match self.listener.accept() {
+                // A client that misbehaves only loses its own connection
+                Ok((stream, _)) => {
+                    if let Ok(true) = respond(stream, bundle, self.local_addr()?.port()) {
+                        return Ok(Served::Downloaded);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+                Err(e) => return Err(e).context("Failed to accept a connection"),
+            }
+        }
+    }
+}
+
+/// Answer one request; true once the whole bundle was sent
+fn respond(mut stream: TcpStream, bundle: &Bundle, port: u16) -> io::Result<bool> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(30)))?;
+
+    // Only the request line and `Host` matter; other headers and any body are ignored
+    let mut head = Vec::new();
+    let mut buffer = [0; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") && head.len() < 16 * 1024 {
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut parts = head.lines().next().unwrap_or_default().split_whitespace();
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    let host = head
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("host"))
+        .map(|(_, value)| value.trim());
+    let own_host = host.is_some_and(|host| host == format!("127.0.0.1:{}", port) || host == format!("localhost:{}", port));
+    let scoped = own_host && (path == bundle.path() || path == bundle.manifest_path());
+
+    let (status, content_type, body, is_bundle) = if !own_host {
+        ("403 Forbidden", "text/plain; charset=utf-8", "Forbidden\n", false)
+    } else if scoped && method == "OPTIONS" {
+        ("204 No Content", "text/plain; charset=utf-8", "", false)
+    } else if method != "GET" && method != "HEAD" {
+        ("405 Method Not Allowed", "text/plain; charset=utf-8", "Method not allowed\n", false)
+    } else if path == bundle.path() {
+        ("200 OK", content_type(bundle.extension), bundle.content.as_str(), true)
+    } else if path == bundle.manifest_path() {
+        ("200 OK", "application/json", bundle.manifest.as_str(), false)
+    } else {
+        ("404 Not Found", "text/plain; charset=utf-8", "Not found\n", false)
+    };
+
+    let cors = if scoped {
+        "Access-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: GET, HEAD, OPTIONS\r\n\
+         Access-Control-Allow-Headers: *\r\n"
+    } else {
+        ""
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\n{}Content-Length: {}\r\nCache-Control: no-store\r\n\
+         Connection: close\r\n\r\n",
+        status,
+        content_type,
+        cors,
+        body.len()
+    )?;
+    if method == "HEAD" {
+        stream.flush()?;
+        return Ok(false);
+    }
+    stream.write_all(body.as_bytes())?;
+    stream.flush()?;
+    Ok(is_bundle)
+}
+
+/// The `Content-Type` for a bundle with `extension`
+pub fn content_type(extension: &str) -> &'static str {
+    match extension {
+        "md" => "text/markdown; charset=utf-8",
+        "json" => "application/json",
+        "xml" => "application/xml; charset=utf-8",
+        "patch" => "text/x-diff; charset=utf-8",
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::thread;
+    use tempfile::TempDir;
+
+    fn get(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_serve_bundle() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path().to_path_buf();
+        let file_path = root_path.join("main.rs");
+        fs::write(&file_path, "fn main() {}\n")?;
+        let mut tree = DirectoryTree::new(root_path.clone());
+        let index = tree.add_node(file_path, false, &root_path).unwrap();
+        tree.toggle_state(index);
+        let bundle = Bundle::new(&tree, "# main.rs\n".to_string(), "md");
+
+        let server = BundleServer::bind(0)?.with_timeout(Duration::from_secs(10));
+        let addr = server.local_addr()?;
+        assert!(addr.ip().is_loopback());
+        let stop = AtomicBool::new(false);
+        let (bundle_path, manifest_path) = (bundle.path(), bundle.manifest_path());
+        assert!(bundle_path.len() == "/bundle.md".len() + 33 && bundle_path.ends_with("/bundle.md"));
+        assert_ne!(bundle_path, Bundle::new(&tree, String::new(), "md").path());
+        let request = |method: &str, path: &str, host: &str| format!("{} {} HTTP/1.1\r\nHost: {}\r\n\r\n", method, path, host);
+        let own_host = format!("127.0.0.1:{}", addr.port());
+        let served = thread::scope(|scope| {
+            let serving = scope.spawn(|| server.serve(&bundle, &stop).unwrap());
+
+            let manifest = get(addr, &request("GET", &manifest_path, &format!("localhost:{}", addr.port())));
+            assert!(manifest.starts_with("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n"));
+            assert!(manifest.contains("\"path\": \"main.rs\""), "{}", manifest);
+            assert!(manifest.contains(&format!("\"bundle\": \"{}\"", bundle_path)), "{}", manifest);
+
+            // Browser frontends can read the token's responses, and preflight them
+            assert!(manifest.contains("Access-Control-Allow-Origin: *\r\n"), "{}", manifest);
+            let preflight = get(addr, &request("OPTIONS", &bundle_path, &own_host));
+            assert!(preflight.starts_with("HTTP/1.1 204 No Content"), "{}", preflight);
+            assert!(preflight.contains("Access-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: GET, HEAD, OPTIONS\r\n"));
+            let untokened = get(addr, &request("OPTIONS", "/bundle.md", &own_host));
+            assert!(untokened.starts_with("HTTP/1.1 405") && !untokened.contains("Access-Control"), "{}", untokened);
+
+            // Without the token there's nothing to find
+            assert!(get(addr, &request("GET", "/bundle.md", &own_host)).starts_with("HTTP/1.1 404 Not Found"));
+            assert!(get(addr, &request("GET", "/manifest.json", &own_host)).starts_with("HTTP/1.1 404 Not Found"));
+            assert!(get(addr, &request("POST", &bundle_path, &own_host)).starts_with("HTTP/1.1 405"));
+            // Another host name for this address, as a rebound DNS name would send, or none at all
+            assert!(get(addr, &request("GET", &bundle_path, &format!("evil.example:{}", addr.port()))).starts_with("HTTP/1.1 403"));
+            assert!(get(addr, &request("GET", &bundle_path, "127.0.0.1")).starts_with("HTTP/1.1 403"));
+            assert!(get(addr, &format!("GET {} HTTP/1.1\r\n\r\n", bundle_path)).starts_with("HTTP/1.1 403"));
+            let head = get(addr, &request("HEAD", &bundle_path, &own_host));
+            assert!(head.ends_with("Content-Length: 10\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n"), "{}", head);
+
+            // The first full download ends serving
+            let bundle_response = get(addr, &request("GET", &format!("{}?download", bundle_path), &own_host));
+            assert!(bundle_response.contains("Content-Type: text/markdown; charset=utf-8\r\n"));
+            assert!(bundle_response.ends_with("\r\n\r\n# main.rs\n"));
+            serving.join().unwrap()
+        });
+        assert_eq!(served, Served::Downloaded);
+
+        // So does the stop flag, as set on Ctrl+C
+        stop.store(true, Ordering::Relaxed);
+        assert_eq!(server.serve(&bundle, &stop)?, Served::Stopped);
+        Ok(())
+    }
+}
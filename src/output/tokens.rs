@@ -0,0 +1,67 @@
+/// How text is counted in tokens. Every count goes through `tokenizer()`, so plugging in
+/// a real tokenizer (cl100k and the like) changes them all at once.
+pub trait Tokenizer: Sync {
+    /// Names the tokenizer; caches of token counts are discarded when it changes
+    fn name(&self) -> &'static str;
+
+    /// Tokens in text that has been read
+    fn count(&self, text: &[u8]) -> u64;
+
+    /// Tokens in `bytes` of text known only by its size
+    fn estimate(&self, bytes: u64) -> u64;
+}
+
+/// Rough tokens per byte of source text; close enough to budget a prompt without a tokenizer
+const BYTES_PER_TOKEN: u64 = 4;
+
+/// A quarter of the bytes, read or not
+pub struct BytesPerToken;
+
+impl Tokenizer for BytesPerToken {
+    fn name(&self) -> &'static str {
+        "bytes/4"
+    }
+
+    fn count(&self, text: &[u8]) -> u64 {
+        self.estimate(text.len() as u64)
+    }
+
+    fn estimate(&self, bytes: u64) -> u64 {
+        bytes.div_ceil(BYTES_PER_TOKEN)
+    }
+}
+
+/// The tokenizer behind every token count shown
+pub fn tokenizer() -> &'static dyn Tokenizer {
+    &BytesPerToken
+}
+
+/// Estimated tokens for `bytes` of text. Every token estimate shown goes through here,
+/// so per-file and whole-export numbers always agree.
+pub fn estimate_tokens(bytes: u64) -> u64 {
+    tokenizer().estimate(bytes)
+}
+
+/// Compact token count: `950`, `8.2k`, `1.3M`
+pub fn format_tokens(tokens: u64) -> String {
+    match tokens {
+        0..1_000 => tokens.to_string(),
+        1_000..1_000_000 => format!("{:.1}k", tokens as f64 / 1_000.0),
+        _ => format!("{:.1}M", tokens as f64 / 1_000_000.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_and_format_tokens() {
+        assert_eq!(estimate_tokens(0), 0);
+        assert_eq!(estimate_tokens(5), 2);
+        assert_eq!(format_tokens(estimate_tokens(3_800)), "950");
+        assert_eq!(format_tokens(estimate_tokens(32_800)), "8.2k");
+        assert_eq!(format_tokens(1_250_000), "1.2M");
+        assert_eq!(tokenizer().count(b"fn main() {}"), estimate_tokens(12));
+    }
+}
@@ -0,0 +1,109 @@
+use std::path::Path;
+
+/// A `file://` URI for an absolute path, as editors and language servers expect
+/// (RFC 8089): separators become `/`, a Windows drive letter gets a leading slash
+/// (`file:///C:/src/main.rs`), a UNC path's server becomes the host, and everything
+/// but unreserved characters, `/` and the drive's `:` is percent-encoded as UTF-8.
+/// Backslashes only separate in Windows paths; in Unix ones they're part of a name.
+pub fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    let path = path.strip_prefix(r"\\?\").unwrap_or(&path);
+    let path = if is_drive(path) || path.starts_with(r"\\") { path.replace('\\', "/") } else { path.to_string() };
+
+    let (host, path) = match path.strip_prefix("//") {
+        Some(unc) => match unc.find('/') {
+            Some(end) => (&unc[..end], &unc[end..]),
+            None => (unc, "/"),
+        },
+        None => ("", path.as_str()),
+    };
+
+    let mut uri = format!("file://{}", encode(host));
+    if is_drive(path) {
+        uri.push('/');
+        uri.push_str(&path[..2]);
+        uri.push_str(&encode(&path[2..]));
+    } else {
+        uri.push_str(&encode(path));
+    }
+    uri
+}
+
+/// Whether a path starts with a Windows drive letter, as in `C:`
+fn is_drive(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+fn encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The path a `file://` URI names: Windows-style when it has a drive letter or a host
+    fn path_from_uri(uri: &str) -> String {
+        let rest = uri.strip_prefix("file://").unwrap();
+        let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let mut bytes = Vec::new();
+        let mut chars = path.bytes();
+        while let Some(byte) = chars.next() {
+            if byte == b'%' {
+                let hex = [chars.next().unwrap(), chars.next().unwrap()];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).unwrap(), 16).unwrap());
+            } else {
+                bytes.push(byte);
+            }
+        }
+        let path = String::from_utf8(bytes).unwrap();
+        let drive = path.len() >= 3 && path.as_bytes()[1].is_ascii_alphabetic() && path.as_bytes()[2] == b':';
+        if drive {
+            path[1..].replace('/', "\\")
+        } else if !host.is_empty() {
+            format!(r"\\{}{}", host, path.replace('/', "\\"))
+        } else {
+            path
+        }
+    }
+
+    #[test]
+    fn test_file_uri() {
+        let cases = [
+            ("/home/me/src/main.rs", "file:///home/me/src/main.rs"),
+            ("/home/me/my project/a#b%c?.rs", "file:///home/me/my%20project/a%23b%25c%3F.rs"),
+            ("/tmp/naïve/日本.md", "file:///tmp/na%C3%AFve/%E6%97%A5%E6%9C%AC.md"),
+            (r"/tmp/back\slash.rs", "file:///tmp/back%5Cslash.rs"),
+            (r"C:\Users\me\src\main.rs", "file:///C:/Users/me/src/main.rs"),
+            (r"d:\a b\c:d.txt", "file:///d:/a%20b/c%3Ad.txt"),
+            (r"\\?\C:\long\path.rs", "file:///C:/long/path.rs"),
+            (r"\\server\share\notes.md", "file://server/share/notes.md"),
+        ];
+        for (path, uri) in cases {
+            assert_eq!(file_uri(Path::new(path)), uri, "{}", path);
+        }
+
+        // Every URI parses back to the path it was made from
+        for path in [
+            "/home/me/my project/a#b%c?.rs",
+            "/tmp/naïve/日本.md",
+            "/weird/100%/+&=;,@!$'()*[]",
+            r"/tmp/back\slash.rs",
+            r"C:\Users\me\My Documents\résumé.txt",
+            r"d:\a b\c:d.txt",
+            r"\\server\share\notes.md",
+        ] {
+            assert_eq!(path_from_uri(&file_uri(Path::new(path))), path, "{}", path);
+        }
+        // Forward slashes on Windows come back in its native style
+        assert_eq!(path_from_uri(&file_uri(Path::new("C:/src/lib.rs"))), r"C:\src\lib.rs");
+    }
+}
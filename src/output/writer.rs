@@ -1,9 +1,10 @@
-use super::formatter::OutputFormatter;
+use super::formatter::{OutputFormatter, Piece};
+use super::resume::{ExportProgress, ExportState};
 use crate::config::settings::Settings;
 use crate::directory::tree::DirectoryTree;
 use anyhow::Result;
 use arboard::Clipboard;
-use std::fs;
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, Write};
 use std::path::Path;
 
@@ -41,12 +42,72 @@ impl OutputWriter {
         Ok(())
     }
 
+    pub fn append_safe(&self) -> bool {
+        self.formatter.append_safe()
+    }
+
+    /// An interrupted export to `output_path` that `stream_to_file` can pick up
+    pub fn resumable(&self, tree: &DirectoryTree, output_path: &Path, progress: &ExportProgress) -> Option<ExportState> {
+        progress.resumable(output_path, &self.formatter.options_hash(tree))
+    }
+
+    /// Write the export a file at a time, for append-safe formats, saving how far it got to
+    /// `progress` after each so an interrupted export can be resumed. With `resume`, the
+    /// files it records as written are skipped and the rest appended.
+    pub fn stream_to_file(
+        &self,
+        tree: &DirectoryTree,
+        output_path: &Path,
+        progress: &ExportProgress,
+        resume: Option<&ExportState>,
+    ) -> Result<()> {
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = match resume {
+            Some(_) => OpenOptions::new().read(true).append(true).open(output_path)?,
+            None => File::options().read(true).write(true).create(true).truncate(true).open(output_path)?,
+        };
+        let mut state = match resume {
+            Some(state) => state.clone(),
+            None => ExportState {
+                output_path: std::path::absolute(output_path)?,
+                options_hash: self.formatter.options_hash(tree),
+                completed_entries: 0,
+                total_entries: tree.get_all_included_files().len(),
+                last_entry: None,
+                offset: 0,
+            },
+        };
+
+        self.formatter.stream_output(tree, state.completed_entries, |text, piece| {
+            file.write_all(text.as_bytes())?;
+            state.offset += text.len() as u64;
+            match piece {
+                Piece::Header => {}
+                Piece::File(entry, completed) => {
+                    state.completed_entries = completed;
+                    state.last_entry = Some(entry.path.clone());
+                }
+                Piece::Footer => return Ok(()),
+            }
+            // Without a record the export still succeeds, it just can't be resumed
+            let _ = progress.save(&state);
+            Ok(())
+        })?;
+        self.formatter.final_newline().apply_to_file(&mut file)?;
+        progress.clear();
+        Ok(())
+    }
+
+    #[allow(dead_code)]
     pub fn write_to_stdout(&self, tree: &DirectoryTree) -> Result<()> {
         let content = self.formatter.format_output(tree)?;
         print!("{}", content);
         Ok(())
     }
 
+    #[allow(dead_code)]
     pub fn write_to_clipboard_or_prompt(&self, tree: &DirectoryTree, settings: &Settings) -> Result<()> {
         let content = self.formatter.format_output(tree)?;
 
@@ -67,12 +128,14 @@ impl OutputWriter {
         self.prompt_and_save_to_file(tree, &content, settings)
     }
 
+    #[allow(dead_code)]
     fn try_write_to_clipboard(&self, content: &str) -> Result<()> {
         let mut clipboard = Clipboard::new()?;
         clipboard.set_text(content)?;
         Ok(())
     }
 
+    #[allow(dead_code)]
     fn prompt_and_save_to_file(&self, tree: &DirectoryTree, content: &str, settings: &Settings) -> Result<()> {
         if content.len() > settings.max_clipboard_size {
             println!(
@@ -90,13 +153,14 @@ impl OutputWriter {
         let input = input.trim();
 
         let filename = if input.is_empty() {
-            Self::generate_default_filename(tree)
+            Self::generate_default_filename(tree, self.formatter.extension())
         } else {
-            // Add .md extension if not present
-            if input.ends_with(".md") {
+            // Add the format's extension if not present
+            let extension = format!(".{}", self.formatter.extension());
+            if input.ends_with(&extension) {
                 input.to_string()
             } else {
-                format!("{}.md", input)
+                format!("{}{}", input, extension)
             }
         };
 
@@ -106,7 +170,8 @@ impl OutputWriter {
         Ok(())
     }
 
-    pub fn generate_default_filename(tree: &DirectoryTree) -> String {
+    /// `<root>_ingest_<timestamp>.<extension>`, the name of unnamed and published exports
+    pub fn generate_default_filename(tree: &DirectoryTree, extension: &str) -> String {
         let root_name = tree.nodes[tree.root_index]
             .path
             .file_name()
@@ -114,14 +179,15 @@ impl OutputWriter {
             .to_string_lossy();
 
         let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
-        format!("{}_ingest_{}.md", root_name, timestamp)
+        format!("{}_ingest_{}.{}", root_name, timestamp, extension)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
+    use crate::constants::PROJECT_DIR;
+    use crate::output::resume::STATE_FILE;
     use tempfile::TempDir;
 
     #[test]
@@ -129,9 +195,76 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let tree = DirectoryTree::new(temp_dir.path().to_path_buf());
 
-        let filename = OutputWriter::generate_default_filename(&tree);
+        let filename = OutputWriter::generate_default_filename(&tree, "md");
         assert!(filename.ends_with(".md"));
+        assert!(OutputWriter::generate_default_filename(&tree, "json").ends_with(".json"));
         assert!(filename.contains("ingest"));
     }
+
+    #[test]
+    fn test_resume_interrupted_export() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root_path = temp_dir.path().join("project");
+        let mut tree = DirectoryTree::new(root_path.clone());
+        for (name, content) in [("a.rs", "fn a() {}\n"), ("b.rs", "fn b() {}\n"), ("c.rs", "fn c() {}\n\n\n")] {
+            fs::create_dir_all(&root_path)?;
+            fs::write(root_path.join(name), content)?;
+            let index = tree.add_node(root_path.join(name), false, &root_path).unwrap();
+            tree.toggle_state(index);
+        }
+        let formatter = OutputFormatter::new().with_metadata(false);
+        let expected = formatter.format_output(&tree)?;
+        let writer = OutputWriter::new().with_formatter(formatter.clone());
+        let progress = ExportProgress::new(&root_path);
+        let output_path = temp_dir.path().join("out").join("export.md");
+
+        // A finished export matches the unstreamed one and leaves no state behind
+        writer.stream_to_file(&tree, &output_path, &progress, None)?;
+        assert_eq!(fs::read_to_string(&output_path)?, expected);
+        assert!(!root_path.join(PROJECT_DIR).exists());
+
+        // Interrupted after a.rs: the output stops there and the state says so
+        let (mut written, mut offset) = (0, 0);
+        formatter.stream_output(&tree, 0, |text, piece| {
+            written += text.len();
+            if let Piece::File(_, 1) = piece {
+                offset = written;
+            }
+            Ok(())
+        })?;
+        assert_eq!(&expected[offset..offset + 8], "\n\n# b.rs");
+        fs::write(&output_path, &expected[..offset])?;
+        let state = ExportState {
+            output_path: std::path::absolute(&output_path)?,
+            options_hash: formatter.options_hash(&tree),
+            completed_entries: 1,
+            total_entries: 3,
+            last_entry: Some("a.rs".to_string()),
+            offset: offset as u64,
+        };
+        progress.save(&state)?;
+        assert!(root_path.join(PROJECT_DIR).join(STATE_FILE).exists());
+
+        let resume = writer.resumable(&tree, &output_path, &progress);
+        assert_eq!(resume.as_ref(), Some(&state));
+        writer.stream_to_file(&tree, &output_path, &progress, resume.as_ref())?;
+        assert_eq!(fs::read_to_string(&output_path)?, expected);
+        assert!(!root_path.join(PROJECT_DIR).exists());
+
+        // Output changed since, other options or another destination: start over
+        progress.save(&state)?;
+        fs::write(&output_path, &expected[..offset - 1])?;
+        assert_eq!(writer.resumable(&tree, &output_path, &progress), None);
+        fs::write(&output_path, &expected[..offset])?;
+        let numbered = OutputWriter::new().with_formatter(formatter.clone().with_line_numbers(true));
+        assert_eq!(numbered.resumable(&tree, &output_path, &progress), None);
+        assert_eq!(writer.resumable(&tree, &temp_dir.path().join("other.md"), &progress), None);
+        assert!(writer.resumable(&tree, &output_path, &progress).is_some());
+
+        // Only formats that can be appended to are streamed
+        assert!(writer.append_safe());
+        assert!(!OutputWriter::new().with_formatter(formatter.with_format("json")).append_safe());
+        Ok(())
+    }
 }
 
@@ -0,0 +1,84 @@
+use super::exporter::{ExportContext, Exporter, FileContent};
+
+/// Each file in a `<file path="...">` element, a layout some models follow more reliably
+pub struct XmlExporter;
+
+impl Exporter for XmlExporter {
+    fn name(&self) -> &'static str {
+        "xml"
+    }
+
+    fn extension(&self) -> &'static str {
+        "xml"
+    }
+
+    fn begin(&self, output: &mut String, context: &ExportContext) {
+        output.push_str(&format!("<files root=\"{}\"", escape(&context.root.display().to_string())));
+        if context.include_metadata {
+            output.push_str(&format!(" generated=\"{}\"", escape(&context.generated_at)));
+        }
+        output.push_str(">\n");
+    }
+
+    fn emit_file(&self, output: &mut String, index: usize, content: FileContent, context: &ExportContext) {
+        let entry = &context.files[index];
+        output.push_str(&format!("<file path=\"{}\"", escape(&entry.path)));
+        if let Some(size) = entry.size {
+            output.push_str(&format!(" size=\"{}\"", size));
+        }
+        if let Some(permissions) = &entry.permissions {
+            output.push_str(&format!(" permissions=\"{}\"", escape(permissions)));
+        }
+        match content {
+            FileContent::Text(text) | FileContent::Excerpt(text, _) => output.push_str(&format!(">\n{}\n</file>\n", escape(text))),
+            FileContent::Binary => output.push_str(" binary=\"true\"/>\n"),
+            FileContent::Image(image) => {
+                output.push_str(&format!(" image=\"{}\"", escape(image.format)));
+                if let Some((width, height)) = image.dimensions {
+                    output.push_str(&format!(" width=\"{}\" height=\"{}\"", width, height));
+                }
+                if image.title.is_none() && image.description.is_none() && image.embedded.is_none() {
+                    output.push_str("/>\n");
+                    return;
+                }
+                output.push_str(">\n");
+                if let Some(title) = &image.title {
+                    output.push_str(&format!("<title>{}</title>\n", escape(title)));
+                }
+                if let Some(description) = &image.description {
+                    output.push_str(&format!("<desc>{}</desc>\n", escape(description)));
+                }
+                if let Some(data) = &image.embedded {
+                    output.push_str(&format!("<data type=\"{}\" encoding=\"base64\">{}</data>\n", image.mime, data));
+                }
+                output.push_str("</file>\n");
+            }
+            FileContent::Error(e) => output.push_str(&format!(" error=\"{}\"/>\n", escape(e))),
+        }
+    }
+
+    fn end(&self, output: &mut String, context: &ExportContext) {
+        if let Some(footer) = &context.footer {
+            output.push_str(&format!("<footer>{}</footer>\n", escape(footer)));
+        }
+        output.push_str("</files>\n");
+    }
+}
+
+/// Text safe in content and attribute values. Control characters other than tab and line
+/// breaks can't appear in XML 1.0 at all, not even as references, so they're replaced.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' || c == '\u{fffe}' || c == '\u{ffff}' => escaped.push('\u{fffd}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,108 @@
+use crate::directory::state::SelectionState;
+use crate::directory::tree::DirectoryTree;
+use anyhow::{Context, Result, anyhow};
+use std::path::Path;
+use std::process::Command;
+
+/// Outcome of applying a command's paths to a tree
+#[derive(Debug, Clone, Default)]
+pub struct CommandSummary {
+    pub included: usize,
+    pub missing: Vec<String>, // Printed paths that aren't in the tree
+}
+
+/// Run `command` through the shell in `root` and return the non-empty lines of its output.
+/// A command that can't be started or exits non-zero is an error carrying its stderr.
+pub fn run(command: &str, root: &Path) -> Result<Vec<String>> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let output = shell
+        .arg(command)
+        .current_dir(root)
+        .output()
+        .with_context(|| format!("Failed to run `{}`", command))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = stderr.trim();
+        return Err(match stderr.is_empty() {
+            true => anyhow!("`{}` failed ({})", command, output.status),
+            false => anyhow!("`{}` failed ({}): {}", command, output.status, stderr),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Make the printed paths the selection: everything else is excluded. Paths are relative
+/// to the root (`./` or not) or absolute; directories include everything in them. Locked
+/// nodes keep their state.
+pub fn apply(paths: &[String], tree: &mut DirectoryTree) -> CommandSummary {
+    let root_path = tree.scan_root_path().to_path_buf();
+    let canonical_root = root_path.canonicalize().ok();
+    let mut summary = CommandSummary::default();
+    tree.set_state_bulk(tree.scan_root_index, SelectionState::Excluded);
+    for printed in paths {
+        // Tools tend to print canonical paths, while the root may have been given through a symlink
+        let path = Path::new(printed);
+        let path = canonical_root.as_deref().and_then(|root| path.strip_prefix(root).ok()).unwrap_or(path);
+        match tree.path_to_index.get(&root_path.join(path)) {
+            Some(&index) => {
+                tree.set_state_bulk(index, SelectionState::Included);
+                summary.included += 1;
+            }
+            None => summary.missing.push(printed.clone()),
+        }
+    }
+    summary
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_and_apply() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let root = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("src"), true, &root);
+        for path in ["src/main.rs", "src/lib.rs", "README.md", "notes.md"] {
+            let parent = root.join(path).parent().unwrap().to_path_buf();
+            let index = tree.add_node(root.join(path), false, &parent).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        tree.set_state(tree.path_to_index[&root.join("notes.md")], SelectionState::Included);
+
+        // Relative, `./` and absolute paths, a directory, blank lines and a path that's gone
+        let command = format!("printf 'src\\n\\n./README.md\\n{}/notes.md\\ngone.rs\\n'", root.display());
+        let paths = run(&command, &root)?;
+        assert_eq!(paths.len(), 4);
+        let summary = apply(&paths, &mut tree);
+        assert_eq!((summary.included, summary.missing), (3, vec!["gone.rs".to_string()]));
+        assert_eq!(tree.get_all_included_files().len(), 4);
+
+        // The listing replaces the selection
+        let summary = apply(&["README.md".to_string()], &mut tree);
+        assert_eq!(summary.included, 1);
+        assert_eq!(tree.get_all_included_files().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_failure() {
+        let root = std::env::temp_dir();
+        let error = run("echo 'no such team' >&2; exit 3", &root).unwrap_err().to_string();
+        assert!(error.contains("no such team") && error.contains('3'), "{}", error);
+    }
+}
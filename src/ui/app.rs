@@ -1,289 +1,3825 @@
+use crate::changelog::Release;
+use crate::config::profile::Profile;
+use crate::config::recipes::Recipe;
+use crate::config::rules::{Rules, RulesSummary};
+use crate::config::selection_set::{Rename, SelectionSet};
+use crate::config::session::{ExportSnapshot, Session, SnapshotDiff};
+use crate::config::slots::ExportSlot;
+use crate::config::startup::{StartupAction, StartupStep};
+use crate::directory::dependencies::find_local_dependencies;
+use crate::directory::essentials::select_essentials;
+use crate::directory::metadata::MetadataUpdate;
+use crate::directory::scan::BackgroundScan;
 use crate::directory::state::SelectionState;
+use crate::directory::suggest::{SUGGESTION_LIMIT, Suggestion, suggest_exclusions};
 use crate::directory::tree::DirectoryTree;
-use crate::fuzzy::filter::{FilteredResults, filter_tree_nodes};
-use crate::ui::colors::ColorScheme;
+use crate::event_log;
+use crate::output::exporter::export_path;
+use crate::output::formatter::OutputFormatter;
+use crate::output::serve::Bundle;
+use crate::output::tokens::format_tokens;
+use crate::fuzzy::filter::{filter_tree_nodes, get_node_display_path};
+use crate::git::ChangedFiles;
+use crate::model::{BulkOperation, BulkPlan, FillOrder, SelectionModel, SelectionStats};
+use crate::select_cmd;
+use crate::ui::colors::{ColorScheme, Theme};
+use crate::ui::icons::{IconSet, UNICODE_ICONS};
+use crate::ui::file_diff::FileDiff;
+use crate::ui::hyperlink::Hyperlink;
+use crate::ui::info::{LINE_SAMPLE_BYTES, NodeInfo};
+use crate::ui::interface::SIZE_COLUMN_MIN_WIDTH;
+use crate::ui::preview::{FilePreview, PREVIEW_CACHE_BYTES, PendingPreview, PreviewCache, PreviewContent, preview_limit};
+use crate::ui::terminal_title::TerminalIntegration;
+use crate::ui::view::ViewState;
+use crate::ui::wizard::Wizard;
+use ratatui::layout::Rect;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// How long a transient status message stays in the status bar
+const STATUS_MESSAGE_DURATION: Duration = Duration::from_secs(3);
+/// Searches slower than this are written to the event log
+const SLOW_FILTER: Duration = Duration::from_millis(50);
+/// Quiet period after the last selection change before totals are recomputed
+const SELECTION_TOTALS_DEBOUNCE: Duration = Duration::from_millis(150);
+/// How long the cursor rests on a file before its compare preview is read, by default
+pub const PREVIEW_DEBOUNCE: Duration = Duration::from_millis(100);
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum AppMode {
     Main,
     Help,
     FileSave,
+    ExportDiff,
+    SmartInclude,
+    Info,
+    ExclusionReason,
+    FileDiff,
+    ChangeRoot,
+    Wizard,
+    Recipes,
+    Renames,
+    WhatsNew,
+    SetConflict,
+    Suggestions,
+    Preview, // The file list beside the file under the cursor
+    Order,   // The included files in export order, to place some by hand
+    Command, // A `:` command being typed
+}
+
+/// Local files a source file depends on, offered for inclusion
+#[derive(Debug, Clone)]
+pub struct SmartInclude {
+    pub source_index: usize,
+    pub candidates: Vec<(usize, bool)>, // Tree index and whether it will be included
+    pub selected: usize,
+}
+
+/// Included files worth excluding to get under the token budget, shown by `T`
+#[derive(Debug, Clone)]
+pub struct ExclusionSuggestions {
+    pub items: Vec<Suggestion>,
+    pub selected: usize,
+}
+
+/// The included files in export order, shown by `M`; while `moving`, Up/Down move the
+/// highlighted file instead of the highlight
+#[derive(Debug, Clone)]
+pub struct ExportOrder {
+    pub selected: usize,
+    pub moving: bool,
+}
+
+/// A selection set or profile save held back because another gthr saved the file since
+/// it was loaded
+#[derive(Debug, Clone)]
+pub struct SetConflict {
+    pub ours: SavedSelection,
+    pub theirs: SavedSelection, // As it is on disk now
+    pub show_diff: bool,
+    pub scroll: usize,
+}
+
+/// A selection saved to a file of its own, which another gthr can save over
+#[derive(Debug, Clone)]
+pub enum SavedSelection {
+    Set(SelectionSet),
+    Profile(Profile),
+}
+
+impl SavedSelection {
+    pub fn path(&self) -> &Path {
+        match self {
+            SavedSelection::Set(selection_set) => &selection_set.path,
+            SavedSelection::Profile(profile) => &profile.path,
+        }
+    }
+
+    pub fn free_path(&self) -> PathBuf {
+        match self {
+            SavedSelection::Set(selection_set) => selection_set.free_path(),
+            SavedSelection::Profile(profile) => profile.free_path(),
+        }
+    }
+
+    /// "Selection set" or "Profile", to start a sentence with
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SavedSelection::Set(_) => "Selection set",
+            SavedSelection::Profile(_) => "Profile",
+        }
+    }
+
+    /// What the file lists: set paths, or profile paths with their saved state
+    pub fn entries(&self) -> BTreeSet<String> {
+        match self {
+            SavedSelection::Set(selection_set) => selection_set.entries.keys().cloned().collect(),
+            SavedSelection::Profile(profile) => {
+                profile.states.iter().map(|(path, state)| format!("{} ({})", path, state)).collect()
+            }
+        }
+    }
+
+    /// Entries only in `self`, then entries only in `other`
+    pub fn diff(&self, other: &SavedSelection) -> (Vec<String>, Vec<String>) {
+        let (ours, theirs) = (self.entries(), other.entries());
+        (ours.difference(&theirs).cloned().collect(), theirs.difference(&ours).cloned().collect())
+    }
+}
+
+/// Which pane receives keyboard input in the main view
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Focus {
+    Search, // Only query editing keys apply
+    List,   // Navigation and single-letter bindings apply
 }
 
+/// The interactive session: a `SelectionModel` for what gets exported, a `ViewState` for
+/// where the interface is looking, and the dialogs, settings and caches around them
 pub struct App {
-    pub tree: DirectoryTree,
-    pub filtered_results: FilteredResults,
-    pub selected_index: usize,
-    pub scroll_offset: usize,
-    pub search_query: String,
-    pub mode: AppMode,
+    pub model: SelectionModel,
+    pub view: ViewState,
+    pub search_cursor: usize, // Byte offset of the caret in the query, always on a grapheme boundary
+    pub jump_prefix: Option<String>, // Typed after `f`: the cursor jumps to rows whose name starts with it
     pub color_scheme: ColorScheme,
+    pub icons: &'static IconSet,
     pub should_quit: bool,
-    pub viewport_height: usize,
     pub file_save_input: String,
     pub pending_content: Option<String>,
+    pub file_save_slot: Option<u8>, // Export slot the file dialog is setting up, rather than saving to
+    pub legacy_typeahead: bool, // Every printable key goes to the search query
+    pub dim_common_prefix: bool,
+    pub size_column_min_width: u16, // Narrower list panes leave out the size column
+    pub tree_view: bool, // Rows nested under their directories instead of listed by path
+    pub collapsed: HashSet<usize>, // Directories whose contents the tree view hides
+    pub fold_level: Option<usize>, // Levels a project's tree view shows unfolded until its folds are saved
+    restored_folds: Vec<String>, // Folded paths as restored, saved to the session only once they differ
+    pub search_area: Rect,      // Last rendered search bar area, for mouse focus
+    pub session: Session,
+    pub export_diff: Option<SnapshotDiff>, // Current selection vs the last export
+    pub export_diff_scroll: usize,
+    pub export_diff_note: Option<String>,
+    pub compare_pin: Option<FilePreview>,   // File pinned for side-by-side comparison
+    pub preview_cache: Option<FilePreview>, // Last previewed node under the cursor
+    pub recent_previews: PreviewCache, // Earlier previews, shown again without reading
+    pub preview_scroll: usize, // First line shown in the preview pane
+    preview_hover: Option<(usize, Instant)>, // Node under the cursor and when the cursor got there
+    pending_preview: Option<PendingPreview>, // Read in flight for the node under the cursor
+    pub preview_debounce: Duration,
+    pub line_sample_bytes: u64, // Files larger than this get approximate line counts
+    pub hyperlinks_enabled: bool,   // Link list rows to their files (OSC 8)
+    pub hyperlinks: Vec<Hyperlink>, // Rows linked in the last frame
+    pub terminal_integration: TerminalIntegration, // Window title and taskbar progress
+    pub rules: Option<Rules>,
+    pub rules_summary: Option<RulesSummary>,
+    pub selection_set: Option<SelectionSet>, // Loaded with `--set`; `S` writes back to it
+    pub profile: Option<Profile>, // Saved to on export, quit and Ctrl+S; none for archives
+    pub pending_renames: Vec<Rename>, // Missing set paths that look renamed, to confirm
+    pub set_conflict: Option<SetConflict>,
+    pub whats_new: Vec<&'static Release>, // Changes since the version last run, shown once
+    pub whats_new_scroll: usize,
+    pub status_message: Option<(String, Instant)>,
+    pub deferred_output: Vec<String>, // Printed to stderr once the terminal is restored
+    pub exported_to: Option<String>,  // Where the export went, once one has been written
+    pub served_bundle: Option<Bundle>, // Export to serve with `--serve` once the terminal is restored
+    pub smart_include: Option<SmartInclude>,
+    pub smart_include_auto: bool, // Include dependencies directly instead of asking
+    pub suggestions: Option<ExclusionSuggestions>,
+    pub export_order: Option<ExportOrder>,
+    pub formatter: OutputFormatter, // Export settings, for estimating the export size
+    pub node_info: Option<NodeInfo>,
+    pub diff_mark: Option<usize>, // First file of a pending `D` diff
+    pub bulk_plan: Option<BulkPlan>, // Bulk operation shown on the list, waiting for confirmation
+    pub visual_anchor: Option<usize>, // Tree index of the row `v` started a range on
+    pub file_diff: Option<FileDiff>,
+    pub file_diff_scroll: usize,
+    pub reason_input: String,
+    pub reason_target: Option<usize>, // Node whose exclusion reason is being edited
+    pub root_input: String,
+    pub pending_root: Option<PathBuf>, // Confirmed new root, scanned by the main loop
+    pub scan: Option<BackgroundScan>, // Running scan of a new root; replacing it restarts, dropping it cancels
+    pub command_input: String,
+    pub pending_command: Option<String>, // Confirmed `:select-cmd` command, run by the main loop
+    pub select_command: Option<String>, // From `--select-cmd` or the last `:select-cmd`, run again by a bare one
+    pub show_status_bar: bool,
+    pub match_sibling_directories: bool, // `=` also sets sibling directories, not just files
+    pub token_budget: Option<u64>, // Estimated tokens the export should stay under
+    pub fill_order: FillOrder, // Which files Alt+Enter includes first
+    pub wizard: Option<Wizard>,
+    pub recipes: Vec<Recipe>, // From the config, picked with `R`
+    pub recipe_choice: usize, // Highlighted recipe in the picker
+    stats_flashed_at: Option<Instant>, // Selection change shown while the status bar is hidden
+    selection_changed_at: Option<Instant>, // Pending recompute of `selection_totals`
+    metadata_updates: Option<Receiver<MetadataUpdate>>, // Background metadata pass, until it completes
 }
 
 impl App {
     pub fn new(tree: DirectoryTree) -> Self {
         let mut app = Self {
-            filtered_results: FilteredResults::new(),
-            tree,
-            selected_index: 0,
-            scroll_offset: 0,
-            search_query: String::new(),
-            mode: AppMode::Main,
+            model: SelectionModel::new(tree),
+            view: ViewState::default(),
+            search_cursor: 0,
+            jump_prefix: None,
             color_scheme: ColorScheme::default(),
+            icons: &UNICODE_ICONS,
             should_quit: false,
-            viewport_height: 20, // Default, will be updated by UI
             file_save_input: String::new(),
+            file_save_slot: None,
             pending_content: None,
+            legacy_typeahead: false,
+            dim_common_prefix: false,
+            size_column_min_width: SIZE_COLUMN_MIN_WIDTH,
+            tree_view: false,
+            collapsed: HashSet::new(),
+            fold_level: None,
+            restored_folds: Vec::new(),
+            search_area: Rect::default(),
+            session: Session::default(),
+            export_diff: None,
+            export_diff_scroll: 0,
+            export_diff_note: None,
+            compare_pin: None,
+            preview_cache: None,
+            recent_previews: PreviewCache::new(PREVIEW_CACHE_BYTES),
+            preview_scroll: 0,
+            preview_hover: None,
+            pending_preview: None,
+            preview_debounce: PREVIEW_DEBOUNCE,
+            line_sample_bytes: LINE_SAMPLE_BYTES,
+            hyperlinks_enabled: false,
+            hyperlinks: Vec::new(),
+            terminal_integration: TerminalIntegration::detect(false),
+            rules: None,
+            selection_set: None,
+            profile: None,
+            pending_renames: Vec::new(),
+            set_conflict: None,
+            whats_new: Vec::new(),
+            whats_new_scroll: 0,
+            rules_summary: None,
+            status_message: None,
+            deferred_output: Vec::new(),
+            exported_to: None,
+            served_bundle: None,
+            smart_include: None,
+            smart_include_auto: false,
+            suggestions: None,
+            export_order: None,
+            formatter: OutputFormatter::new(),
+            node_info: None,
+            diff_mark: None,
+            bulk_plan: None,
+            visual_anchor: None,
+            file_diff: None,
+            file_diff_scroll: 0,
+            reason_input: String::new(),
+            reason_target: None,
+            root_input: String::new(),
+            pending_root: None,
+            scan: None,
+            command_input: String::new(),
+            pending_command: None,
+            select_command: None,
+            show_status_bar: true,
+            match_sibling_directories: false,
+            token_budget: None,
+            fill_order: FillOrder::default(),
+            wizard: None,
+            recipes: Vec::new(),
+            recipe_choice: 0,
+            stats_flashed_at: None,
+            selection_changed_at: None,
+            metadata_updates: None,
         };
 
         app.update_filtered_results();
+        app.recompute_selection_totals();
         app
     }
 
-    pub fn update_filtered_results(&mut self) {
-        self.filtered_results = filter_tree_nodes(&self.tree, &self.search_query);
+    pub fn with_legacy_typeahead(mut self, legacy_typeahead: bool) -> Self {
+        self.legacy_typeahead = legacy_typeahead;
+        self
+    }
 
-        // Reset scroll position when search changes
-        self.scroll_offset = 0;
+    pub fn with_dim_common_prefix(mut self, dim_common_prefix: bool) -> Self {
+        self.dim_common_prefix = dim_common_prefix;
+        self
+    }
+
+    pub fn with_match_sibling_directories(mut self, match_sibling_directories: bool) -> Self {
+        self.match_sibling_directories = match_sibling_directories;
+        self
+    }
+
+    pub fn with_show_status_bar(mut self, show_status_bar: bool) -> Self {
+        self.show_status_bar = show_status_bar;
+        self
+    }
+
+    pub fn with_icons(mut self, icons: &'static IconSet) -> Self {
+        self.icons = icons;
+        self
+    }
+
+    pub fn with_match_file_names(mut self, match_file_names: bool) -> Self {
+        self.model.match_file_names = match_file_names;
+        self.update_filtered_results();
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.color_scheme = ColorScheme::new(theme);
+        self
+    }
+
+    pub fn with_fold_level(mut self, fold_level: Option<usize>) -> Self {
+        self.fold_level = fold_level;
+        self
+    }
+
+    pub fn with_preview_debounce(mut self, preview_debounce: Duration) -> Self {
+        self.preview_debounce = preview_debounce;
+        self
+    }
+
+    pub fn with_size_column_min_width(mut self, size_column_min_width: u16) -> Self {
+        self.size_column_min_width = size_column_min_width;
+        self
+    }
+
+    pub fn with_line_sample_bytes(mut self, line_sample_bytes: u64) -> Self {
+        self.line_sample_bytes = line_sample_bytes;
+        self
+    }
+
+    pub fn with_preview_cache_bytes(mut self, preview_cache_bytes: usize) -> Self {
+        self.recent_previews = PreviewCache::new(preview_cache_bytes);
+        self
+    }
+
+    pub fn with_hyperlinks(mut self, hyperlinks_enabled: bool) -> Self {
+        self.hyperlinks_enabled = hyperlinks_enabled;
+        self
+    }
+
+    pub fn with_terminal_integration(mut self, terminal_integration: TerminalIntegration) -> Self {
+        self.terminal_integration = terminal_integration;
+        self
+    }
+
+    pub fn with_token_budget(mut self, token_budget: Option<u64>) -> Self {
+        self.token_budget = token_budget;
+        self
+    }
+
+    pub fn with_fill_order(mut self, fill_order: FillOrder) -> Self {
+        self.fill_order = fill_order;
+        self
+    }
+
+    pub fn with_smart_include_auto(mut self, smart_include_auto: bool) -> Self {
+        self.smart_include_auto = smart_include_auto;
+        self
+    }
 
-        // Adjust selected index if it's out of bounds
-        if self.selected_index >= self.filtered_results.len() && !self.filtered_results.is_empty() {
-            self.selected_index = self.filtered_results.len() - 1;
-        } else if self.filtered_results.is_empty() {
-            self.selected_index = 0;
+    /// Adopt the saved session, restoring locked nodes along with their state
+    pub fn with_session(mut self, session: Session) -> Self {
+        self.adopt_session(session);
+        self
+    }
+
+    pub fn adopt_session(&mut self, session: Session) {
+        let root_path = self.model.tree.scan_root_path().to_path_buf();
+        for (relative_path, included) in &session.locked {
+            if let Some(&index) = self.model.tree.path_to_index.get(&root_path.join(relative_path)) {
+                let state = if *included { SelectionState::Included } else { SelectionState::Excluded };
+                self.model.tree.set_state(index, state);
+                self.model.tree.nodes[index].locked = true;
+            }
+        }
+        if !session.locked.is_empty() {
+            self.mark_selection_changed();
+        }
+        // A profile's order, applied before, wins over the session's
+        if self.model.tree.manual_order.is_empty() {
+            self.model.tree.restore_manual_order(&session.manual_order);
         }
 
-        self.update_scroll();
+        self.session = session;
+        self.restore_folds();
     }
 
-    pub fn move_up(&mut self) {
-        if self.selected_index > 0 {
-            self.selected_index -= 1;
-            self.update_scroll_for_move_up();
+    /// Fold the directories the session saved as folded, skipping any that are gone. A
+    /// project without saved folds starts with those below `fold_level` folded.
+    fn restore_folds(&mut self) {
+        let tree = &self.model.tree;
+        match (&self.session.collapsed, self.fold_level) {
+            (Some(paths), _) => {
+                let root_path = tree.scan_root_path();
+                self.collapsed = paths.iter().filter_map(|path| tree.path_to_index.get(&root_path.join(path)).copied()).collect();
+            }
+            (None, Some(level)) => {
+                let depth = |mut index: usize| {
+                    let mut depth = 0;
+                    while let Some(parent) = tree.nodes[index].parent {
+                        depth += 1;
+                        index = parent;
+                    }
+                    depth
+                };
+                self.collapsed = (0..tree.nodes.len())
+                    .filter(|&index| {
+                        let node = &tree.nodes[index];
+                        node.is_directory && !node.children.is_empty() && depth(index) >= level.max(1)
+                    })
+                    .collect();
+            }
+            (None, None) => {}
+        }
+        self.restored_folds = self.collapsed_paths();
+        if !self.collapsed.is_empty() {
+            self.update_filtered_results();
         }
     }
 
-    pub fn move_down(&mut self) {
-        if self.selected_index + 1 < self.filtered_results.len() {
-            self.selected_index += 1;
-            self.update_scroll_for_move_down();
+    /// Relative paths of the folded directories. A search unfolds directories only to list
+    /// them, without touching the folds, so what it opens is never among these.
+    fn collapsed_paths(&self) -> Vec<String> {
+        let root_path = self.model.tree.scan_root_path();
+        let mut paths: Vec<String> = self
+            .collapsed
+            .iter()
+            .filter_map(|&index| self.model.tree.get_node(index))
+            .map(|node| export_path(&node.path, root_path))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Remember the folded directories in the session, if they changed since they were
+    /// restored. Until then a project follows `fold_level`, whatever it's later set to.
+    pub fn save_folds(&mut self) -> anyhow::Result<()> {
+        let paths = self.collapsed_paths();
+        if paths == self.restored_folds {
+            return Ok(());
         }
+        self.session.collapsed = Some(paths.clone());
+        self.restored_folds = paths;
+        self.session.save()
     }
 
-    pub fn page_up(&mut self) {
-        let page_size = self.viewport_height.saturating_sub(1);
-        let old_index = self.selected_index;
-        self.selected_index = self.selected_index.saturating_sub(page_size);
+    pub fn with_formatter(mut self, formatter: OutputFormatter) -> Self {
+        self.formatter = formatter;
+        self.recompute_selection_totals();
+        self
+    }
 
-        if old_index != self.selected_index {
-            // Scroll to show the selected item at the top of the viewport
-            self.scroll_offset = self.selected_index;
+    pub fn with_metadata_updates(mut self, metadata_updates: Option<Receiver<MetadataUpdate>>) -> Self {
+        self.metadata_updates = metadata_updates;
+        self
+    }
+
+    pub fn with_rules(mut self, rules: Option<Rules>, summary: Option<RulesSummary>) -> Self {
+        self.rules = rules;
+        self.rules_summary = summary;
+        self
+    }
+
+    pub fn with_selection_set(mut self, selection_set: Option<SelectionSet>) -> Self {
+        self.selection_set = selection_set;
+        self
+    }
+
+    pub fn with_profile(mut self, profile: Option<Profile>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_select_command(mut self, select_command: Option<String>) -> Self {
+        self.select_command = select_command;
+        self
+    }
+
+    pub fn update_filtered_results(&mut self) {
+        let started = Instant::now();
+        self.model.refilter();
+        // The last matches stay listed, cursor and all, until the query compiles again
+        if self.model.invalid_query {
+            return;
+        }
+        // A range can't start on a row that's no longer listed
+        if let Some(anchor) = self.visual_anchor
+            && !self.model.filtered_results.visible_items.contains(&anchor)
+        {
+            self.visual_anchor = None;
         }
+        if self.tree_view {
+            let searching = !self.model.query.is_empty();
+            self.model.filtered_results.arrange_as_tree(&self.model.tree, &self.collapsed, searching);
+        }
+        let elapsed = started.elapsed();
+        if elapsed > SLOW_FILTER {
+            // The query's length, not its text: it may name things the report shouldn't
+            event_log::warn("filter.slow", &[
+                ("ms", &elapsed.as_millis()),
+                ("query_chars", &self.model.query.chars().count()),
+                ("nodes", &self.model.tree.nodes.len()),
+                ("matches", &self.model.filtered_results.matches.len()),
+            ]);
+        }
+        // Reset scroll position when search changes
+        self.view.reset_scroll(self.model.filtered_results.len());
     }
 
-    pub fn page_down(&mut self) {
-        let page_size = self.viewport_height.saturating_sub(1);
-        let old_index = self.selected_index;
-        self.selected_index =
-            (self.selected_index + page_size).min(self.filtered_results.len().saturating_sub(1));
+    /// Switch between the flat list of paths and the tree view, keeping the cursor's node
+    pub fn toggle_tree_view(&mut self) {
+        self.tree_view = !self.tree_view;
+        self.refresh_rows();
+    }
 
-        if old_index != self.selected_index {
-            // Scroll to show the selected item at the bottom of the viewport
-            if self.selected_index >= self.viewport_height {
-                self.scroll_offset = self.selected_index.saturating_sub(self.viewport_height - 1);
-            } else {
-                self.scroll_offset = 0;
-            }
+    /// `←` in the tree view: fold the directory under the cursor, or from anything
+    /// already folded (or not a directory) move up to the directory it's in
+    pub fn collapse_directory(&mut self) {
+        let Some(tree_index) = self.get_selected_tree_index() else { return };
+        let node = &self.model.tree.nodes[tree_index];
+        let searching = !self.model.query.is_empty();
+        if node.is_directory && !node.children.is_empty() && !searching && self.collapsed.insert(tree_index) {
+            self.refresh_rows();
+        } else if let Some(position) = node.parent.and_then(|parent| {
+            self.model.filtered_results.visible_items.iter().position(|&index| index == parent)
+        }) {
+            self.view.selected_index = position;
+            self.view.update_scroll();
+        }
+    }
+
+    /// `→` in the tree view: unfold the directory under the cursor
+    pub fn expand_directory(&mut self) {
+        if let Some(tree_index) = self.get_selected_tree_index()
+            && self.collapsed.remove(&tree_index)
+        {
+            self.refresh_rows();
+        }
+    }
+
+    /// Space in the tree view: fold the directory under the cursor, or unfold it if it's
+    /// folded. Unlike `←`, the cursor never leaves the row.
+    pub fn toggle_fold(&mut self) {
+        let Some(tree_index) = self.get_selected_tree_index() else { return };
+        let node = &self.model.tree.nodes[tree_index];
+        if !node.is_directory || node.children.is_empty() || !self.model.query.is_empty() {
+            return;
         }
+        if !self.collapsed.remove(&tree_index) {
+            self.collapsed.insert(tree_index);
+        }
+        self.refresh_rows();
+    }
+
+    /// List the rows again, keeping the cursor on the same node when it's still listed
+    fn refresh_rows(&mut self) {
+        let selected = self.get_selected_tree_index();
+        let scroll_offset = self.view.scroll_offset;
+        self.update_filtered_results();
+        if let Some(position) = selected
+            .and_then(|index| self.model.filtered_results.visible_items.iter().position(|&item| item == index))
+        {
+            self.view.selected_index = position;
+        }
+        self.view.scroll_offset = scroll_offset;
+        self.view.update_scroll();
+    }
+
+    pub fn move_up(&mut self) {
+        self.view.move_up();
+    }
+
+    pub fn move_down(&mut self) {
+        self.view.move_down(self.model.filtered_results.len());
+    }
+
+    pub fn page_up(&mut self) {
+        self.view.page_up();
+    }
+
+    pub fn page_down(&mut self) {
+        self.view.page_down(self.model.filtered_results.len());
     }
 
     pub fn move_to_top(&mut self) {
-        self.selected_index = 0;
-        self.scroll_offset = 0;
+        self.view.move_to_top();
     }
 
     pub fn move_to_bottom(&mut self) {
-        if !self.filtered_results.is_empty() {
-            self.selected_index = self.filtered_results.len() - 1;
+        self.view.move_to_bottom(self.model.filtered_results.len());
+    }
 
-            // Position the last item at the bottom of the viewport
-            if self.selected_index >= self.viewport_height {
-                self.scroll_offset = self.selected_index.saturating_sub(self.viewport_height - 1);
-            } else {
-                self.scroll_offset = 0;
+    /// Toggle the node under the cursor. A directory reports the change, since a partial
+    /// one can go either way: `src/: partial → included, 87 files`.
+    pub fn toggle_selection(&mut self) {
+        if let Some(tree_index) = self.get_selected_tree_index() {
+            let tree = &mut self.model.tree;
+            let old_state = tree.nodes[tree_index].state;
+            let included_before = tree.file_counts(tree_index).included_files;
+            let new_state = tree.toggle_state(tree_index);
+            let node = &tree.nodes[tree_index];
+            if node.is_directory && let Some(new_state) = new_state {
+                let changed = tree.file_counts(tree_index).included_files.abs_diff(included_before);
+                let path = get_node_display_path(tree, tree_index);
+                let message = format!(
+                    "{}/: {} {} {}, {} {}",
+                    if path.is_empty() { &node.name } else { &path },
+                    old_state,
+                    self.icons.transition,
+                    new_state,
+                    changed,
+                    if changed == 1 { "file" } else { "files" }
+                );
+                self.set_status_message(message);
             }
+            if self.model.tree.nodes[tree_index].locked {
+                self.save_locks();
+            }
+            self.drop_reason(tree_index);
         }
+        self.mark_selection_changed();
     }
 
-    fn update_scroll_for_move_up(&mut self) {
-        // If the selected index is now above the visible area, scroll up
-        if self.selected_index < self.scroll_offset {
-            self.scroll_offset = self.selected_index;
+    /// Include as much of the directory under the cursor as fits in the token budget
+    pub fn budget_fill(&mut self) {
+        let Some(budget) = self.token_budget else {
+            self.set_status_message("No token budget set (token_budget in .gthr.toml)".to_string());
+            return;
+        };
+        let Some(tree_index) = self.get_selected_tree_index() else {
+            return;
+        };
+        let node = &self.model.tree.nodes[tree_index];
+        if !node.is_directory || node.locked {
+            self.set_status_message("Budget fill works on unlocked directories".to_string());
+            return;
         }
-    }
 
-    fn update_scroll_for_move_down(&mut self) {
-        // If the selected index is now below the visible area, scroll down
-        if self.selected_index >= self.scroll_offset + self.viewport_height {
-            self.scroll_offset = self.selected_index.saturating_sub(self.viewport_height - 1);
+        let fill = self.model.fill_to_budget(tree_index, budget, self.fill_order, &self.formatter);
+        self.mark_selection_changed();
+        let mut message = fill.describe();
+        if let Some(stopped_at) = fill.stopped_at {
+            message.push_str(&format!(", stopped at {}", get_node_display_path(&self.model.tree, stopped_at)));
         }
+        self.set_status_message(message);
     }
 
-    fn update_scroll(&mut self) {
-        // General scroll update - ensures selected item is visible
-        if self.selected_index < self.scroll_offset {
-            self.scroll_offset = self.selected_index;
-        } else if self.selected_index >= self.scroll_offset + self.viewport_height {
-            self.scroll_offset = self.selected_index.saturating_sub(self.viewport_height - 1);
-        }
+    /// Whether the export's estimated tokens are over the budget
+    pub fn over_budget(&self) -> bool {
+        self.token_budget.is_some_and(|budget| self.get_stats().tokens > budget)
     }
 
-    pub fn toggle_selection(&mut self) {
-        if let Some(tree_index) = self.get_selected_tree_index() {
-            self.tree.toggle_state(tree_index);
+    /// The export's estimated tokens, as the status bar shows them
+    pub fn projected_tokens(&self) -> u64 {
+        self.get_stats().tokens
+    }
+
+    /// Rank the included files by how cheaply excluding them gets the export under budget
+    pub fn start_suggestions(&mut self) {
+        if self.token_budget.is_none() {
+            self.set_status_message("No token budget set (token_budget in .gthr.toml)".to_string());
+            return;
+        }
+        self.finish_metadata(); // Sizes are what's ranked
+        let items = suggest_exclusions(&self.model.tree, SUGGESTION_LIMIT);
+        if items.is_empty() {
+            self.set_status_message("No unlocked included files to suggest".to_string());
+            return;
         }
+        self.recompute_selection_totals();
+        self.suggestions = Some(ExclusionSuggestions { items, selected: 0 });
+        self.view.mode = AppMode::Suggestions;
     }
 
-    pub fn get_selected_tree_index(&self) -> Option<usize> {
-        self.filtered_results
-            .visible_items
-            .get(self.selected_index)
-            .copied()
+    pub fn move_suggestion(&mut self, delta: isize) {
+        if let Some(suggestions) = &mut self.suggestions {
+            let last = suggestions.items.len().saturating_sub(1);
+            suggestions.selected = suggestions.selected.saturating_add_signed(delta).min(last);
+        }
     }
 
-    pub fn select_all(&mut self) {
-        for &tree_index in &self.filtered_results.visible_items {
-            self.tree.set_state(tree_index, SelectionState::Included);
+    /// Exclude the suggestion at `position` (the highlighted one without), as toggling it in
+    /// the list would, and update the projected total right away
+    pub fn exclude_suggestion(&mut self, position: Option<usize>) {
+        let Some(suggestions) = &mut self.suggestions else {
+            return;
+        };
+        let position = position.unwrap_or(suggestions.selected);
+        if position >= suggestions.items.len() {
+            return;
+        }
+        let suggestion = suggestions.items.remove(position);
+        suggestions.selected = suggestions.selected.min(suggestions.items.len().saturating_sub(1));
+        let done = suggestions.items.is_empty();
+
+        self.model.tree.set_state(suggestion.index, SelectionState::Excluded);
+        self.recompute_selection_totals();
+        self.set_status_message(format!(
+            "Excluded {} (~{} tokens)",
+            get_node_display_path(&self.model.tree, suggestion.index),
+            format_tokens(suggestion.tokens)
+        ));
+        if done {
+            self.suggestions = None;
+            self.view.mode = AppMode::Main;
         }
     }
 
-    pub fn select_none(&mut self) {
-        for &tree_index in &self.filtered_results.visible_items {
-            self.tree.set_state(tree_index, SelectionState::Excluded);
+    /// Show the included files in export order, highlighting the one under the cursor
+    pub fn start_export_order(&mut self) {
+        let files = self.export_order_files();
+        if files.is_empty() {
+            self.set_status_message("No included files to order".to_string());
+            return;
         }
+        let cursor = self.get_selected_tree_index();
+        let selected = files.iter().position(|&index| Some(index) == cursor).unwrap_or(0);
+        self.export_order = Some(ExportOrder { selected, moving: false });
+        self.view.mode = AppMode::Order;
     }
 
-    pub fn invert_selection(&mut self) {
-        for &tree_index in &self.filtered_results.visible_items {
-            self.tree.toggle_state(tree_index);
+    /// Tree indices of the included files, in the order they're exported
+    pub fn export_order_files(&self) -> Vec<usize> {
+        let tree = &self.model.tree;
+        tree.get_all_included_files().iter().map(|node| tree.path_to_index[&node.path]).collect()
+    }
+
+    /// Move the highlight, or the highlighted file while moving
+    pub fn move_in_export_order(&mut self, delta: isize) {
+        let files = self.export_order_files();
+        let Some(order) = &mut self.export_order else {
+            return;
+        };
+        let Some(&index) = files.get(order.selected) else {
+            return;
+        };
+        if !order.moving {
+            order.selected = order.selected.saturating_add_signed(delta).min(files.len() - 1);
+            return;
+        }
+        if !self.model.tree.move_in_export_order(index, delta) {
+            if delta > 0 {
+                self.set_status_message("Only files placed by hand move down".to_string());
+            }
+            return;
+        }
+        let position = self.export_order_files().iter().position(|&file| file == index).unwrap_or(0);
+        if let Some(order) = &mut self.export_order {
+            order.selected = position;
         }
+        self.save_manual_order();
     }
 
-    pub fn add_search_char(&mut self, c: char) {
-        self.search_query.push(c);
-        self.update_filtered_results();
+    pub fn toggle_order_moving(&mut self) {
+        if let Some(order) = &mut self.export_order {
+            order.moving = !order.moving;
+        }
     }
 
-    pub fn search_backspace(&mut self) {
-        self.search_query.pop();
-        self.update_filtered_results();
+    /// Export every file in tree order again
+    pub fn clear_manual_order(&mut self) {
+        if self.model.tree.manual_order.is_empty() {
+            return;
+        }
+        let index = self.export_order_files().get(self.export_order.as_ref().map_or(0, |order| order.selected)).copied();
+        self.model.tree.manual_order.clear();
+        let files = self.export_order_files();
+        if let Some(order) = &mut self.export_order {
+            order.selected = files.iter().position(|&file| Some(file) == index).unwrap_or(0);
+        }
+        self.save_manual_order();
+        self.set_status_message("Manual order cleared".to_string());
     }
 
-    pub fn clear_search(&mut self) {
-        self.search_query.clear();
-        self.update_filtered_results();
+    /// Lock or unlock the node under the cursor against bulk operations
+    pub fn toggle_lock(&mut self) {
+        let Some(tree_index) = self.get_selected_tree_index() else {
+            return;
+        };
+        self.model.tree.toggle_lock(tree_index);
+
+        let node = &self.model.tree.nodes[tree_index];
+        let message = if node.locked { "Locked" } else { "Unlocked" };
+        self.set_status_message(format!("{} {}", message, get_node_display_path(&self.model.tree, tree_index)));
+        self.save_locks();
     }
 
-    pub fn set_mode(&mut self, mode: AppMode) {
-        self.mode = mode;
+    /// Why the node is excluded, as noted in the rules file or else saved with the profile;
+    /// only shown while it is excluded
+    pub fn exclusion_reason(&self, tree_index: usize) -> Option<&str> {
+        let node = self.model.tree.get_node(tree_index)?;
+        if node.state != SelectionState::Excluded {
+            return None;
+        }
+        let relative_path = export_path(&node.path, self.model.tree.scan_root_path());
+        let saved = || Some(self.profile.as_ref()?.reasons.get(&relative_path)?.as_str());
+        self.rules.as_ref().and_then(|rules| rules.reason_for(&relative_path)).or_else(saved)
     }
 
-    pub fn quit(&mut self) {
-        self.should_quit = true;
+    /// Start editing the exclusion reason of the node under the cursor
+    pub fn start_exclusion_reason(&mut self) {
+        let Some(tree_index) = self.get_selected_tree_index() else {
+            return;
+        };
+        if self.model.tree.archive.is_some() {
+            self.set_status_message("Archives are read-only, reasons can't be saved".to_string());
+            return;
+        }
+        if tree_index == self.model.tree.scan_root_index || self.model.tree.nodes[tree_index].state != SelectionState::Excluded {
+            self.set_status_message("Only an excluded node can have an exclusion reason".to_string());
+            return;
+        }
+
+        self.reason_input = self.exclusion_reason(tree_index).unwrap_or_default().to_string();
+        self.reason_target = Some(tree_index);
+        self.view.mode = AppMode::ExclusionReason;
     }
 
-    pub fn handle_escape(&mut self) {
-        if self.mode == AppMode::Help {
-            self.mode = AppMode::Main;
-        } else if self.mode == AppMode::FileSave {
-            self.mode = AppMode::Main;
-            self.file_save_input.clear();
-            self.pending_content = None;
-        } else if !self.search_query.is_empty() {
-            // Clear search text if there is any
-            self.search_query.clear();
-            self.update_filtered_results();
-        } else {
-            // Quit if search is empty
-            self.quit();
+    pub fn add_reason_char(&mut self, c: char) {
+        if self.view.mode == AppMode::ExclusionReason {
+            self.reason_input.push(c);
         }
     }
 
-    pub fn start_file_save(&mut self, content: String) {
-        self.pending_content = Some(content);
-        self.file_save_input.clear();
-        self.mode = AppMode::FileSave;
+    pub fn reason_backspace(&mut self) {
+        if self.view.mode == AppMode::ExclusionReason {
+            self.reason_input.pop();
+        }
     }
 
-    pub fn add_file_save_char(&mut self, c: char) {
-        if self.mode == AppMode::FileSave {
-            self.file_save_input.push(c);
+    /// Save the edited reason to the rules file (an empty reason clears it)
+    pub fn confirm_exclusion_reason(&mut self) {
+        self.view.mode = AppMode::Main;
+        let Some(tree_index) = self.reason_target.take() else {
+            return;
+        };
+        let reason = std::mem::take(&mut self.reason_input);
+        let node = &self.model.tree.nodes[tree_index];
+        let relative_path = export_path(&node.path, self.model.tree.scan_root_path());
+        let is_directory = node.is_directory;
+
+        if let Some(profile) = &mut self.profile {
+            match reason.trim() {
+                "" => profile.reasons.remove(&relative_path),
+                reason => profile.reasons.insert(relative_path.clone(), reason.to_string()),
+            };
+        }
+        match self.save_reason(&relative_path, is_directory, Some(&reason)) {
+            Ok(()) if reason.trim().is_empty() => {
+                self.set_status_message(format!("Cleared the reason for {}", relative_path));
+            }
+            Ok(()) => self.set_status_message(format!("Noted why {} is excluded", relative_path)),
+            Err(e) => self.set_status_message(format!("{} {}", self.icons.warning, e)),
         }
     }
 
-    pub fn file_save_backspace(&mut self) {
-        if self.mode == AppMode::FileSave {
-            self.file_save_input.pop();
+    /// Write a reason to the rules file, creating the project's rules file if none is loaded,
+    /// and re-read it without applying it again
+    fn save_reason(&mut self, relative_path: &str, is_directory: bool, reason: Option<&str>) -> anyhow::Result<()> {
+        let path = match &self.rules {
+            Some(rules) => rules.path.clone(),
+            None => Rules::get_project_rules_path(self.model.tree.scan_root_path()),
+        };
+        Rules::save_reason(&path, relative_path, is_directory, reason)?;
+        self.rules = Some(Rules::load(&path)?);
+        Ok(())
+    }
+
+    /// Reasons only explain exclusions: when the user includes a node by toggling it, its
+    /// reason goes, and so does the exclude rule it's on, which would exclude it again on
+    /// the next run. Other changes to the selection (profiles, rules, bulk toggles) leave
+    /// the shared rules file alone.
+    fn drop_reason(&mut self, tree_index: usize) {
+        let node = &self.model.tree.nodes[tree_index];
+        if self.model.tree.archive.is_some() || node.state == SelectionState::Excluded {
+            return;
+        }
+        let relative_path = export_path(&node.path, self.model.tree.scan_root_path());
+        if let Some(profile) = &mut self.profile {
+            profile.reasons.remove(&relative_path);
+        }
+        let Some(rules) = &self.rules else {
+            return;
+        };
+        let Some(reason) = rules.reason_for(&relative_path).map(str::to_string) else {
+            return;
+        };
+        let path = rules.path.clone();
+        match Rules::remove_exclusion(&path, &relative_path).and_then(|_| Rules::load(&path)) {
+            Ok(rules) => {
+                self.rules = Some(rules);
+                self.set_status_message(format!(
+                    "Included {}, removed its exclude rule from {} (\"{}\")",
+                    relative_path,
+                    path.file_name().unwrap_or_default().to_string_lossy(),
+                    reason
+                ));
+            }
+            Err(e) => self.set_status_message(format!("{} {:#}", self.icons.warning, e)),
         }
     }
 
-    pub fn get_stats(&self) -> AppStats {
-        let total_files = self
+    /// Remember locked nodes and their current state in the session
+    fn save_locks(&mut self) {
+        let root_path = self.model.tree.scan_root_path();
+        self.session.locked = self.model
             .tree
             .nodes
             .iter()
-            .filter(|node| !node.is_directory && node.is_text_file)
-            .count();
+            .filter(|node| node.locked)
+            .map(|node| {
+                let relative_path = node.path.strip_prefix(root_path).unwrap_or(&node.path);
+                (relative_path.to_string_lossy().to_string(), node.state.is_included())
+            })
+            .collect();
+        if let Err(e) = self.session.save() {
+            self.set_status_message(format!("{} Failed to save locks: {}", self.icons.warning, e));
+        }
+    }
+
+    /// Remember the manual export order in the session, by path
+    fn save_manual_order(&mut self) {
+        self.session.manual_order = self.model.tree.manual_order_paths();
+        if let Err(e) = self.session.save() {
+            self.set_status_message(format!("{} Failed to save the export order: {}", self.icons.warning, e));
+        }
+    }
 
-        let included_files = self.tree.get_all_included_files().len();
+    /// Replace the selection with the built-in "project essentials" heuristic
+    pub fn select_essentials(&mut self) {
+        self.finish_metadata(); // The heuristic skips large files
+        let included = select_essentials(&mut self.model.tree);
+        self.mark_selection_changed();
+        self.set_status_message(format!("Essentials: {} files included, edit freely", included));
+    }
 
-        let total_size: u64 = self
-            .tree
-            .get_all_included_files()
-            .iter()
-            .filter_map(|node| node.size)
-            .sum();
+    /// Find the local files the file under the cursor depends on, and either include them
+    /// or open a popup to confirm which ones to include
+    pub fn start_smart_include(&mut self) {
+        let Some(source_index) = self.get_selected_tree_index() else {
+            return;
+        };
+
+        let dependencies = find_local_dependencies(&self.model.tree, source_index);
+        if dependencies.is_empty() {
+            self.set_status_message("No local dependencies found (only Rust is supported)".to_string());
+            return;
+        }
+
+        self.smart_include = Some(SmartInclude {
+            source_index,
+            candidates: dependencies.into_iter().map(|index| (index, true)).collect(),
+            selected: 0,
+        });
 
-        AppStats {
-            total_files,
-            included_files,
-            total_size,
-            filtered_count: self.filtered_results.len(),
+        if self.smart_include_auto {
+            self.confirm_smart_include();
+        } else {
+            self.view.mode = AppMode::SmartInclude;
         }
     }
-}
 
-#[derive(Debug)]
-pub struct AppStats {
-    pub total_files: usize,
-    pub included_files: usize,
-    pub total_size: u64,
-    pub filtered_count: usize,
-}
+    pub fn move_smart_include(&mut self, delta: isize) {
+        if let Some(smart_include) = &mut self.smart_include {
+            let last = smart_include.candidates.len().saturating_sub(1);
+            smart_include.selected = smart_include.selected.saturating_add_signed(delta).min(last);
+        }
+    }
+
+    pub fn toggle_smart_include_candidate(&mut self) {
+        if let Some(smart_include) = &mut self.smart_include
+            && let Some((_, checked)) = smart_include.candidates.get_mut(smart_include.selected)
+        {
+            *checked = !*checked;
+        }
+    }
+
+    /// Include the checked candidates, along with the source file itself
+    pub fn confirm_smart_include(&mut self) {
+        let Some(smart_include) = self.smart_include.take() else {
+            return;
+        };
+        self.view.mode = AppMode::Main;
 
-impl AppStats {
-    pub fn format_size(&self) -> String {
-        format_file_size(self.total_size)
+        let mut included = 0;
+        for index in std::iter::once(smart_include.source_index)
+            .chain(smart_include.candidates.iter().filter(|(_, checked)| *checked).map(|(index, _)| *index))
+        {
+            if self.model.tree.get_node(index).is_some_and(|node| node.state != SelectionState::Included) {
+                self.model.tree.set_state(index, SelectionState::Included);
+                included += 1;
+            }
+        }
+
+        self.mark_selection_changed();
+        self.set_status_message(format!("Smart include: {} file(s) newly included", included));
     }
-}
 
-fn format_file_size(size: u64) -> String {
-    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
-    let mut size_f = size as f64;
-    let mut unit_index = 0;
+    /// Scope the view to the directory under the cursor, like `cd`: filtering, stats and
+    /// export only see that subtree. Selection elsewhere is kept, just hidden.
+    pub fn set_view_root(&mut self) {
+        let Some(tree_index) = self.get_selected_tree_index() else {
+            return;
+        };
+        if !self.model.tree.nodes[tree_index].is_directory || tree_index == self.model.tree.root_index {
+            self.set_status_message("Only a directory can become the root".to_string());
+            return;
+        }
+
+        self.model.tree.root_index = tree_index;
+        self.on_view_root_changed();
+    }
+
+    /// Return to the directory that was scanned
+    pub fn reset_view_root(&mut self) {
+        if self.model.tree.root_index == self.model.tree.scan_root_index {
+            return;
+        }
+        let previous_root = self.model.tree.root_index;
+        self.model.tree.root_index = self.model.tree.scan_root_index;
+        self.on_view_root_changed();
+
+        // Keep the cursor on the directory we came back from
+        if let Some(position) = self.model.filtered_results.visible_items.iter().position(|&index| index == previous_root) {
+            self.view.selected_index = position;
+            self.view.update_scroll();
+        }
+    }
+
+    /// Open the first-run wizard over the file list
+    /// Run the steps of the `startup` setting in order. A step that fails is reported
+    /// (in the status bar and again on exit) and the rest still run.
+    pub fn run_startup(&mut self, steps: &[String]) {
+        let errors = self.run_steps(steps, "Startup step");
+        if let Some(error) = errors.last() {
+            self.set_status_message(error.clone());
+        }
+        self.deferred_output.extend(errors);
+    }
+
+    /// Run `steps` in order, skipping failed ones; returns a warning for each of those
+    fn run_steps(&mut self, steps: &[String], label: &str) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (index, entry) in steps.iter().enumerate() {
+            if let Err(e) = StartupStep::parse(entry).and_then(|step| self.run_startup_step(step)) {
+                errors.push(format!("{} {} {} `{}`: {:#}", self.icons.warning, label, index + 1, entry, e));
+            }
+        }
+        errors
+    }
 
-    while size_f >= 1024.0 && unit_index < UNITS.len() - 1 {
-        size_f /= 1024.0;
-        unit_index += 1;
+    /// Apply the recipes a recipe extends and then the recipe itself: each one's profile,
+    /// its rules file, which becomes the loaded one, and then its steps. Fails if a profile
+    /// or rules file can't be loaded; steps that fail and profile paths that are gone are
+    /// returned as warnings.
+    pub fn apply_recipe(&mut self, recipe: &Recipe) -> anyhow::Result<Vec<String>> {
+        let mut warnings = Vec::new();
+        for recipe in recipe.bases.iter().chain([recipe]) {
+            warnings.extend(self.apply_recipe_alone(recipe)?);
+        }
+        Ok(warnings)
+    }
+
+    fn apply_recipe_alone(&mut self, recipe: &Recipe) -> anyhow::Result<Vec<String>> {
+        let mut warnings = Vec::new();
+        if let Some(name) = &recipe.profile {
+            let root = self.model.tree.scan_root_path().to_path_buf();
+            let profile = Profile::get_profile_path(&root, Some(name))
+                .and_then(|path| Profile::load(&path))
+                .map_err(|e| anyhow::anyhow!("recipe `{}`: {:#}", recipe.name, e))?;
+            let summary = profile.apply(&mut self.model.tree);
+            self.mark_selection_changed();
+            warnings.extend(summary.missing.iter().map(|relative_path| {
+                format!("{} {}: `{}` not found", self.icons.warning, profile.path.display(), relative_path)
+            }));
+        }
+        if let Some(path) = &recipe.rules {
+            let path = self.model.tree.scan_root_path().join(path);
+            let rules = Rules::load(&path).map_err(|e| anyhow::anyhow!("recipe `{}`: {:#}", recipe.name, e))?;
+            let summary = rules.apply(&mut self.model.tree);
+            self.mark_selection_changed();
+            self.rules = Some(rules);
+            self.rules_summary = Some(summary);
+        }
+        warnings.extend(self.run_steps(&recipe.steps, &format!("Recipe `{}` step", recipe.name)));
+        Ok(warnings)
+    }
+
+    /// List what changed since the version last run
+    pub fn show_whats_new(&mut self, releases: Vec<&'static Release>) {
+        if !releases.is_empty() {
+            self.whats_new = releases;
+            self.whats_new_scroll = 0;
+            self.view.mode = AppMode::WhatsNew;
+        }
     }
 
-    if unit_index == 0 {
-        format!("{} {}", size, UNITS[unit_index])
-    } else {
-        format!("{:.1} {}", size_f, UNITS[unit_index])
+    /// Ask whether to remap set or profile paths that look renamed
+    pub fn offer_renames(&mut self, renames: Vec<Rename>) {
+        if !renames.is_empty() {
+            self.pending_renames = renames;
+            self.view.mode = AppMode::Renames;
+        }
+    }
+
+    /// Give the renamed files what was saved for them (included, for a set) and list them
+    /// under their new paths in the set or profile
+    pub fn confirm_renames(&mut self) {
+        let renames = std::mem::take(&mut self.pending_renames);
+        self.view.mode = AppMode::Main;
+        let root_path = self.model.tree.scan_root_path().to_path_buf();
+        for rename in &renames {
+            if let Some(&index) = self.model.tree.path_to_index.get(&root_path.join(&rename.to)) {
+                let saved = self.profile.as_ref().and_then(|profile| profile.states.get(&rename.from));
+                self.model.tree.set_state_bulk(index, saved.copied().unwrap_or(SelectionState::Included));
+                if self.profile.as_ref().is_some_and(|profile| profile.locked.contains(&rename.from)) {
+                    self.model.tree.nodes[index].locked = true;
+                }
+            }
+        }
+        if let Some(selection_set) = &mut self.selection_set {
+            selection_set.remap(&renames);
+        }
+        if let Some(profile) = &mut self.profile {
+            profile.remap(&renames);
+        }
+        self.mark_selection_changed();
+        self.set_status_message(format!("Remapped {} renamed path(s), S saves the set", renames.len()));
+    }
+
+    /// Keep renamed paths missing, reported like any other missing path
+    fn decline_renames(&mut self) {
+        self.view.mode = AppMode::Main;
+        for rename in std::mem::take(&mut self.pending_renames) {
+            let listed_in = match (&self.selection_set, &self.profile) {
+                (Some(selection_set), _) if selection_set.entries.contains_key(&rename.from) => selection_set.path.display(),
+                (_, Some(profile)) => profile.path.display(),
+                (Some(selection_set), None) => selection_set.path.display(),
+                (None, None) => Path::new("").display(),
+            };
+            self.deferred_output.push(format!("{} {}: `{}` not found", self.icons.warning, listed_in, rename.from));
+        }
+    }
+
+    /// Open the recipe picker
+    pub fn show_recipes(&mut self) {
+        if self.recipes.is_empty() {
+            self.set_status_message("No recipes, add a [recipes.<name>] table to .gthr.toml".to_string());
+            return;
+        }
+        self.recipe_choice = self.recipe_choice.min(self.recipes.len() - 1);
+        self.view.mode = AppMode::Recipes;
+    }
+
+    pub fn move_recipe_choice(&mut self, delta: isize) {
+        let last = self.recipes.len().saturating_sub(1);
+        self.recipe_choice = self.recipe_choice.saturating_add_signed(delta).min(last);
+    }
+
+    /// Close the picker and hand back the highlighted recipe, for the caller to export with
+    pub fn confirm_recipe(&mut self) -> Option<Recipe> {
+        self.view.mode = AppMode::Main;
+        self.recipes.get(self.recipe_choice).cloned()
+    }
+
+    fn run_startup_step(&mut self, step: StartupStep) -> anyhow::Result<()> {
+        match step {
+            StartupStep::Rule(rules) => {
+                let summary = rules.apply(&mut self.model.tree);
+                self.mark_selection_changed();
+                if !summary.unmatched.is_empty() {
+                    anyhow::bail!("matched nothing");
+                }
+            }
+            StartupStep::Search(query) => {
+                self.search_cursor = query.len();
+                self.model.query = query;
+                self.update_filtered_results();
+            }
+            StartupStep::Action(action) => match action {
+                StartupAction::IncludeAll | StartupAction::ExcludeAll => {
+                    let state = if action == StartupAction::IncludeAll {
+                        SelectionState::Included
+                    } else {
+                        SelectionState::Excluded
+                    };
+                    self.model.tree.set_state_bulk(self.model.tree.root_index, state);
+                    self.mark_selection_changed();
+                }
+                StartupAction::IncludeMatches => {
+                    self.model.set_matches(SelectionState::Included);
+                    self.mark_selection_changed();
+                }
+                StartupAction::SelectEssentials => self.select_essentials(),
+                StartupAction::ChangedOnly => {
+                    if self.model.changed_files.is_none() {
+                        self.toggle_changed_filter();
+                    }
+                    if self.model.changed_files.is_none() {
+                        anyhow::bail!("no git status to compare with");
+                    }
+                }
+                StartupAction::ToggleStatusBar => self.toggle_status_bar(),
+                StartupAction::FocusSearch => self.focus_search(),
+                StartupAction::Bottom => self.move_to_bottom(),
+                StartupAction::ComparePin => {
+                    self.toggle_compare_pin();
+                    if self.compare_pin.is_none() {
+                        anyhow::bail!("no file under the cursor to pin");
+                    }
+                }
+                StartupAction::Info => self.show_node_info(),
+            },
+        }
+        Ok(())
+    }
+
+    pub fn start_wizard(&mut self, wizard: Wizard) {
+        self.wizard = Some(wizard);
+        self.view.mode = AppMode::Wizard;
+    }
+
+    pub fn move_wizard_choice(&mut self, delta: isize) {
+        if let Some(wizard) = &mut self.wizard {
+            wizard.move_choice(delta);
+        }
+    }
+
+    pub fn add_wizard_char(&mut self, c: char) {
+        if let Some(wizard) = &mut self.wizard {
+            wizard.add_char(c);
+        }
+    }
+
+    pub fn wizard_backspace(&mut self) {
+        if let Some(wizard) = &mut self.wizard {
+            wizard.backspace();
+        }
+    }
+
+    /// Go to the next question; after the last one, write `.gthr.toml` and apply what can be
+    /// applied without rescanning
+    pub fn confirm_wizard_step(&mut self) {
+        let Some(wizard) = &mut self.wizard else {
+            return;
+        };
+        if !wizard.next() {
+            return;
+        }
+
+        let wizard = self.wizard.take().unwrap();
+        self.view.mode = AppMode::Main;
+        let path = match wizard.save(self.model.tree.scan_root_path()) {
+            Ok(path) => path,
+            Err(e) => {
+                self.set_status_message(format!("{} Failed to save settings: {:#}", self.icons.warning, e));
+                return;
+            }
+        };
+
+        if wizard.selection_changed() {
+            let state = if wizard.include_all { SelectionState::Included } else { SelectionState::Excluded };
+            self.model.tree.set_state_bulk(self.model.tree.scan_root_index, state);
+            self.mark_selection_changed();
+        }
+        self.formatter = self.formatter.clone().with_format(wizard.chosen_format());
+        self.token_budget = wizard.budget();
+        let rescan_note = if wizard.gitignore_changed() { ", the gitignore choice applies from the next scan" } else { "" };
+        self.set_status_message(format!("Saved {}{}", path.display(), rescan_note));
+    }
+
+    /// Ask for a directory (or archive) to scan instead of the current root
+    pub fn start_change_root(&mut self) {
+        self.root_input = self.model.tree.scan_root_path().display().to_string();
+        self.view.mode = AppMode::ChangeRoot;
+    }
+
+    pub fn add_root_char(&mut self, c: char) {
+        if self.view.mode == AppMode::ChangeRoot {
+            self.root_input.push(c);
+        }
+    }
+
+    pub fn root_backspace(&mut self) {
+        if self.view.mode == AppMode::ChangeRoot {
+            self.root_input.pop();
+        }
+    }
+
+    /// Queue a scan of the entered path; relative paths are taken from the current root
+    pub fn confirm_change_root(&mut self) {
+        let input = self.root_input.trim();
+        let path = match (input.strip_prefix("~/"), dirs::home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest),
+            _ => self.model.tree.scan_root_path().join(input),
+        };
+        if input.is_empty() || !path.exists() {
+            self.set_status_message(format!("{} {} does not exist", self.icons.warning, path.display()));
+            return;
+        }
+
+        self.view.mode = AppMode::Main;
+        self.root_input.clear();
+        self.pending_root = Some(path);
+    }
+
+    pub fn start_command(&mut self) {
+        self.command_input.clear();
+        self.view.mode = AppMode::Command;
+    }
+
+    pub fn add_command_char(&mut self, c: char) {
+        if self.view.mode == AppMode::Command {
+            self.command_input.push(c);
+        }
+    }
+
+    pub fn command_backspace(&mut self) {
+        if self.view.mode == AppMode::Command {
+            self.command_input.pop();
+        }
+    }
+
+    /// Run the entered command, or queue it for the main loop. `select-cmd <command>`
+    /// replaces the selection with the paths the command prints, and a bare `select-cmd`
+    /// runs the last one (or the `--select-cmd` one) again. `include <query>` and
+    /// `exclude <query>` change everything the query matches, `essentials` selects the
+    /// project essentials and `rules reload` re-reads the rules file.
+    pub fn confirm_command(&mut self) {
+        let input = self.command_input.trim().to_string();
+        let (name, argument) = input.split_once(char::is_whitespace).unwrap_or((&input, ""));
+        match (name, argument.trim()) {
+            ("", _) => {}
+            ("select-cmd", "") if self.select_command.is_none() => {
+                self.set_status_message("select-cmd needs a command to run".to_string());
+                return;
+            }
+            ("select-cmd", "") => self.pending_command = self.select_command.clone(),
+            ("select-cmd", command) => self.pending_command = Some(command.to_string()),
+            ("include" | "exclude", "") => {
+                self.set_status_message(format!("{} needs a query, e.g. `{} dir:generated`", name, name));
+                return;
+            }
+            ("include", query) => self.set_matching(query, SelectionState::Included),
+            ("exclude", query) => self.set_matching(query, SelectionState::Excluded),
+            ("essentials", _) => self.select_essentials(),
+            ("rules", "reload") => self.reload_rules(),
+            ("rules", _) => {
+                self.set_status_message("Usage: rules reload".to_string());
+                return;
+            }
+            ("root", "") => {
+                self.set_status_message("root needs a path, e.g. `root ../other-project`".to_string());
+                return;
+            }
+            ("root", path) => {
+                self.root_input = path.to_string();
+                self.confirm_change_root();
+                if self.pending_root.is_none() {
+                    self.root_input.clear();
+                    return;
+                }
+            }
+            _ => {
+                self.set_status_message(format!(
+                    "Unknown command `{}` (available: select-cmd, include, exclude, essentials, rules reload, root)",
+                    name
+                ));
+                return;
+            }
+        }
+        self.view.mode = AppMode::Main;
+        self.command_input.clear();
+    }
+
+    /// `:include` / `:exclude`: give everything `query` matches the state, without touching
+    /// the search. Locked nodes keep theirs.
+    fn set_matching(&mut self, query: &str, state: SelectionState) {
+        let matches = filter_tree_nodes(&self.model.tree, query).visible_items;
+        self.model.set_nodes(&matches, state);
+        self.mark_selection_changed();
+        let noun = if matches.len() == 1 { "match" } else { "matches" };
+        self.set_status_message(format!("{}: {} {} {}", query, matches.len(), noun, state));
+    }
+
+    /// Replace the selection with the paths `command` prints, run in the root
+    pub fn run_select_command(&mut self, command: String) {
+        let message = match select_cmd::run(&command, self.model.tree.scan_root_path()) {
+            Ok(paths) => {
+                let summary = select_cmd::apply(&paths, &mut self.model.tree);
+                self.mark_selection_changed();
+                match summary.missing.len() {
+                    0 => format!("select-cmd: {} paths included", summary.included),
+                    missing => format!("select-cmd: {} paths included, {} not found", summary.included, missing),
+                }
+            }
+            // Stderr can run over several lines; the status bar has one
+            Err(e) => format!("{} {:#}", self.icons.warning, e).replace('\n', " "),
+        };
+        self.set_status_message(message);
+        self.select_command = Some(command);
+    }
+
+    /// Swap in a freshly scanned tree. Everything indexed by the old tree is dropped, along
+    /// with its metadata pass; with `keep_selection`, files included under the old root stay
+    /// included where the same relative path exists under the new one, and directories
+    /// included as a whole include everything now in them.
+    pub fn replace_tree(
+        &mut self,
+        mut tree: DirectoryTree,
+        metadata_updates: Option<Receiver<MetadataUpdate>>,
+        keep_selection: bool,
+    ) {
+        if keep_selection {
+            let old_root = self.model.tree.scan_root_path();
+            let new_root = tree.scan_root_path().to_path_buf();
+            let included_files = self.model.tree.get_all_included_files().into_iter();
+            // Directories last: including a file inside one would clear its sticky flag
+            let sticky_directories = self.model.tree.nodes.iter().filter(|node| node.sticky);
+            for node in included_files.chain(sticky_directories) {
+                let relative_path = node.path.strip_prefix(old_root).unwrap_or(&node.path);
+                if let Some(&index) = tree.path_to_index.get(&new_root.join(relative_path)) {
+                    tree.set_state(index, SelectionState::Included);
+                }
+            }
+        }
+
+        self.model.tree = tree;
+        self.model.refresh_directory_sizes();
+        self.metadata_updates = metadata_updates;
+        self.view.selected_index = 0;
+        self.view.scroll_offset = 0;
+        self.model.query.clear();
+        self.search_cursor = 0;
+        self.compare_pin = None;
+        self.preview_cache = None;
+        self.recent_previews.clear();
+        self.preview_hover = None;
+        self.pending_preview = None;
+        self.node_info = None;
+        self.bulk_plan = None;
+        self.model.changed_files = None;
+        self.diff_mark = None;
+        self.file_diff = None;
+        self.smart_include = None;
+        self.suggestions = None;
+        self.export_order = None;
+        self.collapsed.clear();
+        self.export_diff = None;
+        self.selection_set = None;
+        self.update_filtered_results();
+        self.recompute_selection_totals();
+    }
+
+    fn on_view_root_changed(&mut self) {
+        self.view.selected_index = 0;
+        self.compare_pin = None;
+        self.update_filtered_results();
+        self.mark_selection_changed();
+        self.set_status_message(format!("Root: {}", self.view_root_breadcrumb()));
+    }
+
+    /// The view root relative to the scanned root, e.g. `gthr › src › ui`
+    pub fn view_root_breadcrumb(&self) -> String {
+        let scan_root = &self.model.tree.nodes[self.model.tree.scan_root_index];
+        let view_root = &self.model.tree.nodes[self.model.tree.root_index].path;
+        let relative_path = view_root.strip_prefix(&scan_root.path).unwrap_or(view_root);
+
+        std::iter::once(scan_root.name.clone())
+            .chain(relative_path.components().map(|part| part.as_os_str().to_string_lossy().to_string()))
+            .collect::<Vec<_>>()
+            .join(" › ")
+    }
+
+    /// Open the info popup for the node under the cursor
+    pub fn show_node_info(&mut self) {
+        if let Some(tree_index) = self.get_selected_tree_index() {
+            let cached_lines = self.cached_lines(tree_index);
+            self.node_info = NodeInfo::collect(&self.model.tree, tree_index, self.line_sample_bytes, cached_lines);
+            if self.node_info.is_some() {
+                self.view.mode = AppMode::Info;
+            }
+        }
+    }
+
+    /// Restrict the list to files that differ from git HEAD, or show everything again.
+    /// The git status is read each time the filter is turned on.
+    pub fn toggle_changed_filter(&mut self) {
+        if self.model.changed_files.take().is_some() {
+            self.set_status_message("Showing all files".to_string());
+        } else if self.model.tree.archive.is_some() {
+            self.set_status_message("Archives have no git status".to_string());
+        } else {
+            match ChangedFiles::load(self.model.tree.scan_root_path()) {
+                Some(changed_files) => {
+                    self.set_status_message(format!("{} file(s) changed since HEAD, g shows all", changed_files.len()));
+                    self.model.changed_files = Some(changed_files);
+                }
+                None => self.set_status_message("Not a git repository, nothing to compare with".to_string()),
+            }
+        }
+        self.update_filtered_results();
+    }
+
+    /// Mark the file under the cursor for diffing, or diff it against the marked file
+    pub fn mark_for_diff(&mut self) {
+        let Some(tree_index) = self.get_selected_tree_index() else {
+            return;
+        };
+        if self.model.tree.nodes[tree_index].is_directory {
+            self.set_status_message("Only files can be diffed".to_string());
+            return;
+        }
+
+        match self.diff_mark.take() {
+            None => {
+                self.diff_mark = Some(tree_index);
+                self.set_status_message(format!(
+                    "Marked {}, press D on another file to diff",
+                    get_node_display_path(&self.model.tree, tree_index)
+                ));
+            }
+            Some(marked) if marked == tree_index => self.set_status_message("Diff mark cleared".to_string()),
+            Some(marked) => match FileDiff::compute(&self.model.tree, marked, tree_index) {
+                Ok(diff) => {
+                    self.file_diff = Some(diff);
+                    self.file_diff_scroll = 0;
+                    self.view.mode = AppMode::FileDiff;
+                }
+                Err(message) => self.set_status_message(format!("{} {}", self.icons.warning, message)),
+            },
+        }
+    }
+
+    /// Pin the file under the cursor for comparison, or unpin it if already pinned
+    pub fn toggle_compare_pin(&mut self) {
+        let Some(tree_index) = self.get_selected_tree_index() else {
+            return;
+        };
+
+        if self.compare_pin.as_ref().is_some_and(|pin| pin.tree_index == tree_index) {
+            self.compare_pin = None;
+        } else if self.model.tree.get_node(tree_index).is_some_and(|node| !node.is_directory) {
+            self.compare_pin = self.load_preview(tree_index);
+        }
+    }
+
+    /// Preview of the node under the cursor, cached until the cursor moves. Called on every
+    /// redraw: the file is only read once the cursor has rested on it for `preview_debounce`,
+    /// on a background thread, and moving on cancels a read still in flight.
+    pub fn load_hovered_preview(&mut self) {
+        let Some(tree_index) = self.get_selected_tree_index() else {
+            self.preview_cache = None;
+            self.preview_hover = None;
+            self.pending_preview = None;
+            return;
+        };
+
+        let loaded = |preview: &FilePreview| preview.tree_index == tree_index && preview.content != PreviewContent::Loading;
+        if self.preview_cache.as_ref().is_some_and(loaded) {
+            return;
+        }
+
+        // The cursor moved: start waiting again, dropping (and so cancelling) any pending read,
+        // unless the node was previewed recently
+        if self.preview_hover.is_none_or(|(hovered, _)| hovered != tree_index) {
+            self.preview_hover = Some((tree_index, Instant::now()));
+            self.preview_scroll = 0;
+            self.pending_preview = None;
+            let path = self.model.tree.get_node(tree_index).map(|node| node.path.clone());
+            let recent = path.and_then(|path| self.recent_previews.get(tree_index, &path));
+            self.preview_cache = Some(recent.unwrap_or_else(|| FilePreview::placeholder(tree_index, PreviewContent::Loading)));
+            if self.preview_cache.as_ref().is_some_and(loaded) {
+                return;
+            }
+        }
+
+        if let Some(pending) = &self.pending_preview {
+            if let Some(preview) = pending.try_take() {
+                self.remember_preview(&preview);
+                self.preview_cache = Some(preview);
+                self.pending_preview = None;
+            }
+            return;
+        }
+
+        let rested = self.preview_hover.is_some_and(|(_, since)| since.elapsed() >= self.preview_debounce);
+        if !rested {
+            return;
+        }
+        match self.model.tree.get_node(tree_index) {
+            // Archive entries come out of the archive in memory, there's nothing to stream
+            Some(_) if self.model.tree.archive.is_some() => {
+                self.preview_cache = self.load_preview(tree_index);
+                if let Some(preview) = self.preview_cache.clone() {
+                    self.remember_preview(&preview);
+                }
+            }
+            Some(node) => {
+                let cached_lines = self.cached_lines(tree_index);
+                self.pending_preview = Some(PendingPreview::spawn(
+                    tree_index,
+                    node.path.clone(),
+                    node.is_directory,
+                    self.line_sample_bytes,
+                    cached_lines,
+                ));
+            }
+            None => self.preview_cache = None,
+        }
+    }
+
+    fn remember_preview(&mut self, preview: &FilePreview) {
+        if let Some(node) = self.model.tree.get_node(preview.tree_index) {
+            let path = node.path.clone();
+            self.recent_previews.insert(preview.clone(), &path);
+        }
+    }
+
+    fn load_preview(&self, tree_index: usize) -> Option<FilePreview> {
+        let node = self.model.tree.get_node(tree_index)?;
+        if self.model.tree.archive.is_some() && !node.is_directory {
+            let preview = match self.model.tree.read_file_head(&node.path, preview_limit(&node.path)) {
+                Ok(head) => FilePreview::from_head(tree_index, &node.path, &head, node.size.unwrap_or(0)),
+                Err(e) => FilePreview::error(tree_index, e.to_string()),
+            };
+            return Some(preview);
+        }
+        Some(FilePreview::load(tree_index, &node.path, node.is_directory, self.line_sample_bytes, self.cached_lines(tree_index)))
+    }
+
+    /// Lines of a file the content cache has unchanged, so info and preview needn't count them
+    fn cached_lines(&self, tree_index: usize) -> Option<usize> {
+        let node = self.model.tree.get_node(tree_index)?;
+        if node.is_directory {
+            return None;
+        }
+        self.formatter.cached_summary(&self.model.tree, &node.path).map(|summary| summary.lines)
+    }
+
+    /// Path of the node under the cursor, relative to the scan root unless `absolute`
+    pub fn current_path(&self, absolute: bool) -> Option<String> {
+        let node = self.model.tree.get_node(self.get_selected_tree_index()?)?;
+        if absolute {
+            let path = node.path.canonicalize().unwrap_or_else(|_| node.path.clone());
+            return Some(path.display().to_string());
+        }
+
+        let root_path = &self.model.tree.nodes[self.model.tree.root_index].path;
+        let relative_path = node.path.strip_prefix(root_path).unwrap_or(&node.path);
+        if relative_path.as_os_str().is_empty() {
+            Some(".".to_string())
+        } else {
+            Some(relative_path.display().to_string())
+        }
+    }
+
+    /// Relative paths of every included file, in export order
+    pub fn included_paths(&self) -> Vec<String> {
+        let root_path = &self.model.tree.nodes[self.model.tree.root_index].path;
+        self.model.tree
+            .get_all_included_files()
+            .into_iter()
+            .map(|node| node.path.strip_prefix(root_path).unwrap_or(&node.path).display().to_string())
+            .collect()
+    }
+
+    pub fn get_selected_tree_index(&self) -> Option<usize> {
+        self.model.filtered_results
+            .visible_items
+            .get(self.view.selected_index)
+            .copied()
+    }
+
+    /// Set every sibling of the node under the cursor to the node's state
+    pub fn match_siblings(&mut self) {
+        let Some(tree_index) = self.get_selected_tree_index() else {
+            return;
+        };
+        let changed = self.model.tree.match_siblings(tree_index, self.match_sibling_directories);
+        if changed > 0 {
+            self.mark_selection_changed();
+        }
+
+        let state = match self.model.tree.nodes[tree_index].state {
+            SelectionState::Included => "included",
+            SelectionState::Excluded => "excluded",
+            SelectionState::Partial => {
+                self.set_status_message("A partial directory has no state to copy".to_string());
+                return;
+            }
+        };
+        self.set_status_message(format!("{} sibling(s) now {}", changed, state));
+    }
+
+    /// Show what a bulk operation would change, to be applied with `confirm_bulk`
+    pub fn plan_bulk(&mut self, operation: BulkOperation) {
+        let plan = self.model.plan(operation);
+        if plan.changed.is_empty() {
+            self.set_status_message("Nothing would change".to_string());
+        } else {
+            self.bulk_plan = Some(plan);
+        }
+    }
+
+    /// Plan excluding every file with the extension of the file under the cursor
+    pub fn plan_exclude_extension(&mut self) {
+        let extension = self
+            .get_selected_tree_index()
+            .map(|tree_index| &self.model.tree.nodes[tree_index])
+            .filter(|node| !node.is_directory)
+            .and_then(|node| node.path.extension())
+            .map(|extension| extension.to_string_lossy().to_string());
+        match extension {
+            Some(extension) => self.plan_bulk(BulkOperation::ExcludeExtension(extension)),
+            None => self.set_status_message("No file with an extension under the cursor".to_string()),
+        }
+    }
+
+    /// The pending bulk operation's effect and keys, or the size of the visual range,
+    /// shown in place of any status message
+    pub fn bulk_prompt(&self) -> Option<String> {
+        if let Some(rows) = self.visual_range() {
+            return Some(format!("VISUAL ({} items) | Enter: Toggle, Esc: Cancel", rows.count()));
+        }
+        let plan = self.bulk_plan.as_ref()?;
+        Some(format!("{} | Enter: Apply, Esc: Cancel", plan.describe()))
+    }
+
+    /// Apply the pending bulk operation
+    pub fn confirm_bulk(&mut self) {
+        if let Some(plan) = self.bulk_plan.take() {
+            event_log::info("bulk.apply", &[
+                ("operation", &format!("{:?}", plan.operation)),
+                ("changed", &plan.changed.len()),
+                ("included_files", &plan.included_files),
+                ("excluded_files", &plan.excluded_files),
+            ]);
+            self.model.apply(&plan.operation);
+            self.mark_selection_changed();
+            self.set_status_message(plan.describe());
+        }
+    }
+
+    /// Files the bulk selection keys act on: the files listed, and those in directories
+    /// folded away in the tree view. While searching that's only the files that match,
+    /// never everything in a matching directory.
+    fn listed_files(&self) -> Vec<usize> {
+        let tree = &self.model.tree;
+        let results = &self.model.filtered_results;
+        let mut files = Vec::new();
+        for &index in results.visible_items.iter().filter(|index| !results.context.contains(index)) {
+            if !tree.nodes[index].is_directory {
+                files.push(index);
+            } else if self.tree_view && self.collapsed.contains(&index) && self.model.query.is_empty() {
+                let mut stack = tree.nodes[index].children.clone();
+                while let Some(index) = stack.pop() {
+                    match tree.nodes[index].is_directory {
+                        true => stack.extend(&tree.nodes[index].children),
+                        false => files.push(index),
+                    }
+                }
+            }
+        }
+        files
+    }
+
+    /// Ctrl+A: include every listed file (only the matches while searching), or exclude
+    /// them all when they're all included already
+    pub fn toggle_all(&mut self) {
+        let files = self.listed_files();
+        let verb = match self.toggle_nodes(&files) {
+            SelectionState::Included => "included",
+            _ => "excluded",
+        };
+        self.report_bulk_selection(files.len(), verb);
+    }
+
+    /// Ctrl+N: exclude every listed file
+    pub fn select_none(&mut self) {
+        let files = self.listed_files();
+        self.model.set_nodes(&files, SelectionState::Excluded);
+        self.report_bulk_selection(files.len(), "excluded");
+    }
+
+    /// Ctrl+T: flip every listed file between included and excluded
+    pub fn invert_selection(&mut self) {
+        let files = self.listed_files();
+        self.model.invert_files(&files);
+        self.report_bulk_selection(files.len(), "inverted");
+    }
+
+    /// Include all of `nodes`, or exclude them when they're all included already, and
+    /// return the state they were given. Locked nodes neither change nor count.
+    fn toggle_nodes(&mut self, nodes: &[usize]) -> SelectionState {
+        let tree = &self.model.tree;
+        let mut unlocked = nodes.iter().map(|&index| &tree.nodes[index]).filter(|node| !node.locked);
+        let state = match unlocked.all(|node| node.state == SelectionState::Included) {
+            true => SelectionState::Excluded,
+            false => SelectionState::Included,
+        };
+        self.model.set_nodes(nodes, state);
+        state
+    }
+
+    /// `v`: start a range at the cursor, or drop the one started
+    pub fn toggle_visual(&mut self) {
+        self.visual_anchor = match self.visual_anchor {
+            Some(_) => None,
+            None => self.get_selected_tree_index(),
+        };
+    }
+
+    /// Rows from the one `v` was pressed on to the cursor, either way round
+    pub fn visual_range(&self) -> Option<std::ops::RangeInclusive<usize>> {
+        let anchor = self.visual_anchor?;
+        let anchor_row = self.model.filtered_results.visible_items.iter().position(|&index| index == anchor)?;
+        let cursor_row = self.view.selected_index;
+        Some(anchor_row.min(cursor_row)..=anchor_row.max(cursor_row))
+    }
+
+    /// Enter on a visual range: include every row in it, or exclude them all when they're
+    /// all included already, in one step
+    pub fn apply_visual(&mut self) {
+        let Some(rows) = self.visual_range() else { return };
+        self.visual_anchor = None;
+        let results = &self.model.filtered_results;
+        let nodes: Vec<usize> = results.visible_items[rows]
+            .iter()
+            .copied()
+            .filter(|index| !results.context.contains(index))
+            .collect();
+        let state = self.toggle_nodes(&nodes);
+        let noun = if nodes.len() == 1 { "item" } else { "items" };
+        let verb = if state == SelectionState::Included { "included" } else { "excluded" };
+        self.set_status_message(format!("{} {} {}", nodes.len(), noun, verb));
+        self.mark_selection_changed();
+    }
+
+    fn report_bulk_selection(&mut self, files: usize, verb: &str) {
+        let noun = if files == 1 { "file" } else { "files" };
+        let scope = if self.model.query.is_empty() { "" } else { "matching " };
+        self.set_status_message(format!("{} {}{} {}", files, scope, noun, verb));
+        self.mark_selection_changed();
+    }
+
+    /// Start jumping by name without touching the query, or jump again with the same prefix
+    pub fn start_or_repeat_jump(&mut self) {
+        match &self.jump_prefix {
+            Some(prefix) if !prefix.is_empty() => self.jump(1),
+            Some(_) => {}
+            None => self.jump_prefix = Some(String::new()),
+        }
+    }
+
+    pub fn add_jump_char(&mut self, c: char) {
+        if let Some(prefix) = &mut self.jump_prefix {
+            prefix.push(c);
+            self.jump(0); // Stay on the current row while it still matches
+        }
+    }
+
+    pub fn jump_backspace(&mut self) {
+        if let Some(prefix) = &mut self.jump_prefix {
+            prefix.pop();
+        }
+    }
+
+    /// Move the cursor to the first listed row, `skip` or more rows down (wrapping around),
+    /// whose name starts with the jump prefix, ignoring case
+    fn jump(&mut self, skip: usize) {
+        let Some(prefix) = self.jump_prefix.as_ref().map(|prefix| prefix.to_lowercase()) else {
+            return;
+        };
+        let items = &self.model.filtered_results.visible_items;
+        let found = (0..items.len())
+            .map(|offset| (self.view.selected_index + skip + offset) % items.len())
+            .find(|&position| self.model.tree.nodes[items[position]].name.to_lowercase().starts_with(&prefix));
+        match found {
+            Some(position) => {
+                self.view.selected_index = position;
+                self.view.update_scroll();
+            }
+            None => self.set_status_message(format!("No listed name starts with \"{}\"", prefix)),
+        }
+    }
+
+    pub fn add_search_char(&mut self, c: char) {
+        self.model.query.insert(self.search_cursor, c);
+        // A combining mark joins the grapheme before it; keep the caret on a boundary
+        self.search_cursor = self.next_grapheme_boundary(self.search_cursor);
+        self.update_filtered_results();
+    }
+
+    /// Ctrl+R: read the query as fuzzy terms, a literal substring or a regex, in turn
+    pub fn cycle_match_mode(&mut self) {
+        self.model.match_mode = self.model.match_mode.next();
+        self.update_filtered_results();
+    }
+
+    /// Remove the whole grapheme before the caret, e.g. an emoji or an accented letter
+    pub fn search_backspace(&mut self) {
+        if self.search_cursor == 0 {
+            return;
+        }
+        let start = self.previous_grapheme_boundary(self.search_cursor);
+        self.model.query.replace_range(start..self.search_cursor, "");
+        self.search_cursor = start;
+        self.update_filtered_results();
+    }
+
+    /// Remove the whole grapheme after the caret
+    pub fn search_delete(&mut self) {
+        if self.search_cursor == self.model.query.len() {
+            return;
+        }
+        let end = self.next_grapheme_boundary(self.search_cursor);
+        self.model.query.replace_range(self.search_cursor..end, "");
+        self.update_filtered_results();
+    }
+
+    /// Move the caret one grapheme left (negative) or right (positive)
+    pub fn move_search_cursor(&mut self, direction: i32) {
+        self.search_cursor = if direction < 0 {
+            self.previous_grapheme_boundary(self.search_cursor)
+        } else {
+            self.next_grapheme_boundary(self.search_cursor)
+        };
+    }
+
+    pub fn search_cursor_home(&mut self) {
+        self.search_cursor = 0;
+    }
+
+    pub fn search_cursor_end(&mut self) {
+        self.search_cursor = self.model.query.len();
+    }
+
+    /// Terminal columns between the start of the query and the caret
+    pub fn search_cursor_column(&self) -> usize {
+        self.model.query[..self.search_cursor].width()
+    }
+
+    fn previous_grapheme_boundary(&self, offset: usize) -> usize {
+        self.model.query[..offset]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(index, _)| index)
+    }
+
+    fn next_grapheme_boundary(&self, offset: usize) -> usize {
+        self.model.query
+            .grapheme_indices(true)
+            .map(|(index, grapheme)| index + grapheme.len())
+            .find(|&end| end > offset)
+            .unwrap_or(self.model.query.len())
+    }
+
+    #[allow(dead_code)]
+    pub fn clear_search(&mut self) {
+        self.model.query.clear();
+        self.search_cursor = 0;
+        self.update_filtered_results();
+    }
+
+    pub fn focus_search(&mut self) {
+        self.view.focus = Focus::Search;
+    }
+
+    pub fn focus_list(&mut self) {
+        self.view.focus = Focus::List;
+    }
+
+    /// Whether the search bar should be drawn as the focused pane
+    pub fn is_search_focused(&self) -> bool {
+        self.legacy_typeahead || self.view.focus == Focus::Search
+    }
+
+    pub fn handle_click(&mut self, column: u16, row: u16) {
+        if !matches!(self.view.mode, AppMode::Main | AppMode::Preview) {
+            return;
+        }
+
+        let area = self.search_area;
+        let inside_search = column >= area.x
+            && column < area.x + area.width
+            && row >= area.y
+            && row < area.y + area.height;
+
+        if inside_search {
+            self.focus_search();
+        } else {
+            self.focus_list();
+        }
+    }
+
+    /// Re-read the rules file from disk and apply it on top of the current selection
+    pub fn reload_rules(&mut self) {
+        let Some(path) = self.rules.as_ref().map(|rules| rules.path.clone()) else {
+            self.set_status_message("No rules file loaded".to_string());
+            return;
+        };
+
+        match Rules::load(&path) {
+            Ok(rules) => {
+                let summary = rules.apply(&mut self.model.tree);
+                self.mark_selection_changed();
+                self.set_status_message(format!(
+                    "Rules reloaded: {} applied, {} matched nothing",
+                    summary.applied,
+                    summary.unmatched.len()
+                ));
+                self.rules = Some(rules);
+                self.rules_summary = Some(summary);
+            }
+            Err(e) => self.set_status_message(format!("{} {}", self.icons.warning, e)),
+        }
+    }
+
+    /// The selection as the profile would save it, with the reasons noted for excluded nodes
+    fn profile_to_save(&self) -> Option<Profile> {
+        let mut profile = self.profile.as_ref()?.with_selection(&self.model.tree);
+        if let Some(rules) = &self.rules {
+            profile.reasons.extend(rules.exclusion_reasons(&self.model.tree));
+        }
+        Some(profile)
+    }
+
+    /// Write the selection to the profile, as exporting and quitting do. Returns how many
+    /// paths were saved, or `None` without a profile to save to. There's no asking then, so
+    /// if another gthr saved the profile since it was loaded, theirs is kept and this one is
+    /// saved next to it, which the error says.
+    pub fn write_profile(&mut self) -> anyhow::Result<Option<usize>> {
+        let Some(mut profile) = self.profile_to_save() else {
+            return Ok(None);
+        };
+        let theirs = profile.save_keeping_theirs()?;
+        let saved = profile.states.len();
+        let path = profile.path.clone();
+        self.profile = Some(profile);
+        match theirs {
+            Some(theirs) => Err(anyhow::anyhow!(
+                "{} was saved by someone else since it was loaded; this selection went to {}",
+                theirs.display(),
+                path.display()
+            )),
+            None => Ok(Some(saved)),
+        }
+    }
+
+    /// Ctrl+S: save the selection to the profile now, or ask first if another gthr saved
+    /// the profile since it was loaded
+    pub fn save_profile(&mut self) {
+        let Some(profile) = self.profile_to_save() else {
+            self.set_status_message("Archives are read-only, no profile saved".to_string());
+            return;
+        };
+        if let Some(theirs) = profile.changed_on_disk() {
+            self.set_conflict = Some(SetConflict {
+                ours: SavedSelection::Profile(profile),
+                theirs: SavedSelection::Profile(theirs),
+                show_diff: false,
+                scroll: 0,
+            });
+            self.view.mode = AppMode::SetConflict;
+            return;
+        }
+        self.write_profile_now(profile);
+    }
+
+    fn write_profile_now(&mut self, mut profile: Profile) {
+        let message = match profile.save() {
+            Ok(()) => {
+                let path = profile.path.strip_prefix(self.model.tree.scan_root_path()).unwrap_or(&profile.path);
+                format!("{} Saved {} paths to {}", self.icons.success, profile.states.len(), path.display())
+            }
+            Err(e) => format!("{} {:#}", self.icons.warning, e),
+        };
+        self.set_status_message(message);
+        self.profile = Some(profile);
+    }
+
+    /// Write the included files to the loaded selection set, or to `.gthr-set` in the root.
+    /// Ranges and notes of files that are still included are kept.
+    pub fn save_selection_set(&mut self) {
+        if self.model.tree.archive.is_some() {
+            self.set_status_message("Archives are read-only, no selection set saved".to_string());
+            return;
+        }
+        let path = match &self.selection_set {
+            Some(selection_set) => selection_set.path.clone(),
+            None => SelectionSet::get_project_set_path(self.model.tree.scan_root_path()),
+        };
+        // An existing file's notes are worth keeping even if it wasn't loaded
+        let previous = match self.selection_set.take() {
+            Some(selection_set) => selection_set,
+            None => SelectionSet::load(&path).unwrap_or(SelectionSet { path, ..Default::default() }),
+        };
+
+        let selection_set = previous.with_selection(&self.model.tree);
+        // Another gthr saved over the file since: ask rather than lose their save
+        if let Some(theirs) = selection_set.changed_on_disk() {
+            self.set_conflict = Some(SetConflict {
+                ours: SavedSelection::Set(selection_set.clone()),
+                theirs: SavedSelection::Set(theirs),
+                show_diff: false,
+                scroll: 0,
+            });
+            self.selection_set = Some(selection_set);
+            self.view.mode = AppMode::SetConflict;
+            return;
+        }
+        self.write_selection_set(selection_set);
+    }
+
+    fn write_selection_set(&mut self, mut selection_set: SelectionSet) {
+        match selection_set.save() {
+            Ok(()) => self.set_status_message(format!(
+                "Saved {} paths to {}",
+                selection_set.entries.len(),
+                selection_set.path.display()
+            )),
+            Err(e) => self.set_status_message(format!("{} {:#}", self.icons.warning, e)),
+        }
+        self.selection_set = Some(selection_set);
+    }
+
+    /// Settle a held-back save: replace the file on disk with ours
+    pub fn overwrite_set(&mut self) {
+        if let Some(conflict) = self.set_conflict.take() {
+            self.view.mode = AppMode::Main;
+            match conflict.ours {
+                SavedSelection::Set(selection_set) => self.write_selection_set(selection_set),
+                SavedSelection::Profile(profile) => self.write_profile_now(profile),
+            }
+        }
+    }
+
+    /// Settle a held-back save: keep theirs and save ours next to it, where saving goes from now on
+    pub fn save_set_as_new(&mut self) {
+        if let Some(conflict) = self.set_conflict.take() {
+            self.view.mode = AppMode::Main;
+            match conflict.ours {
+                SavedSelection::Set(mut selection_set) => {
+                    selection_set.path = selection_set.free_path();
+                    selection_set.on_disk = None;
+                    self.write_selection_set(selection_set);
+                }
+                SavedSelection::Profile(mut profile) => {
+                    profile.path = profile.free_path();
+                    profile.on_disk = None;
+                    self.write_profile_now(profile);
+                }
+            }
+        }
+    }
+
+    pub fn toggle_set_conflict_diff(&mut self) {
+        if let Some(conflict) = &mut self.set_conflict {
+            conflict.show_diff = !conflict.show_diff;
+            conflict.scroll = 0;
+        }
+    }
+
+    pub fn set_status_message(&mut self, message: String) {
+        self.status_message = Some((message, Instant::now()));
+    }
+
+    /// The current status message, if it hasn't expired yet
+    pub fn get_status_message(&self) -> Option<&str> {
+        self.status_message
+            .as_ref()
+            .filter(|(_, shown_at)| shown_at.elapsed() < STATUS_MESSAGE_DURATION)
+            .map(|(message, _)| message.as_str())
+    }
+
+    /// Hide the status bar to give its rows to the file list, or bring it back
+    pub fn toggle_status_bar(&mut self) {
+        self.show_status_bar = !self.show_status_bar;
+        self.stats_flashed_at = None;
+        if !self.show_status_bar {
+            self.set_status_message("Status bar hidden, Ctrl+B shows it".to_string());
+        }
+    }
+
+    /// Whether the hidden status bar's text should briefly show over the file list,
+    /// after a selection change or while a status message is pending
+    pub fn status_flash_visible(&self) -> bool {
+        !self.show_status_bar
+            && (self.get_status_message().is_some()
+                || self.bulk_plan.is_some()
+                || self
+                    .stats_flashed_at
+                    .is_some_and(|flashed_at| flashed_at.elapsed() < STATUS_MESSAGE_DURATION))
+    }
+
+    pub fn set_mode(&mut self, mode: AppMode) {
+        self.view.mode = mode;
+    }
+
+    /// Show the file under the cursor beside the list, or go back to the list alone
+    pub fn toggle_preview(&mut self) {
+        match self.view.mode {
+            AppMode::Main => self.view.mode = AppMode::Preview,
+            AppMode::Preview => self.view.mode = AppMode::Main,
+            _ => {}
+        }
+    }
+
+    pub fn quit(&mut self) {
+        self.should_quit = true;
+    }
+
+    pub fn handle_escape(&mut self) {
+        if self.view.mode == AppMode::Help {
+            self.view.mode = AppMode::Main;
+        } else if self.view.mode == AppMode::ExportDiff {
+            self.view.mode = AppMode::Main;
+            self.export_diff = None;
+            self.export_diff_note = None;
+        } else if self.view.mode == AppMode::SmartInclude {
+            self.view.mode = AppMode::Main;
+            self.smart_include = None;
+        } else if self.view.mode == AppMode::Suggestions {
+            self.view.mode = AppMode::Main;
+            self.suggestions = None;
+        } else if self.view.mode == AppMode::Order {
+            match &mut self.export_order {
+                Some(order) if order.moving => order.moving = false,
+                _ => {
+                    self.view.mode = AppMode::Main;
+                    self.export_order = None;
+                }
+            }
+        } else if self.view.mode == AppMode::Info {
+            self.view.mode = AppMode::Main;
+            self.node_info = None;
+        } else if self.view.mode == AppMode::Recipes {
+            self.view.mode = AppMode::Main;
+        } else if self.view.mode == AppMode::Renames {
+            self.decline_renames();
+        } else if self.view.mode == AppMode::SetConflict {
+            self.view.mode = AppMode::Main;
+            if let Some(conflict) = self.set_conflict.take() {
+                self.set_status_message(format!("{} not saved", conflict.ours.kind()));
+            }
+        } else if self.view.mode == AppMode::WhatsNew {
+            self.view.mode = AppMode::Main;
+            self.whats_new.clear();
+        } else if self.view.mode == AppMode::FileDiff {
+            self.view.mode = AppMode::Main;
+            self.file_diff = None;
+        } else if self.view.mode == AppMode::Wizard {
+            self.view.mode = AppMode::Main;
+            self.wizard = None;
+            self.set_status_message("Setup skipped; set first_run_wizard = false to never see it".to_string());
+        } else if self.view.mode == AppMode::ChangeRoot {
+            self.view.mode = AppMode::Main;
+            self.root_input.clear();
+        } else if self.view.mode == AppMode::Command {
+            self.view.mode = AppMode::Main;
+            self.command_input.clear();
+        } else if self.view.mode == AppMode::ExclusionReason {
+            self.view.mode = AppMode::Main;
+            self.reason_input.clear();
+            self.reason_target = None;
+        } else if self.view.mode == AppMode::FileSave {
+            self.view.mode = AppMode::Main;
+            self.file_save_input.clear();
+            self.pending_content = None;
+            self.file_save_slot = None;
+        } else if self.bulk_plan.is_some() {
+            self.bulk_plan = None;
+        } else if self.jump_prefix.is_some() {
+            self.jump_prefix = None;
+        } else if let Some(scan) = self.scan.take() {
+            self.set_status_message(format!("Scan of {} cancelled", scan.root().display()));
+        } else if self.view.mode == AppMode::Preview {
+            self.view.mode = AppMode::Main;
+        } else if self.compare_pin.is_some() {
+            // Leave compare mode before touching the search
+            self.compare_pin = None;
+        } else if !self.model.query.is_empty() {
+            // Clear search text if there is any
+            self.model.query.clear();
+            self.search_cursor = 0;
+            self.update_filtered_results();
+        } else {
+            // Quit if search is empty
+            self.quit();
+        }
+    }
+
+    /// Open the popup comparing the current selection with the last export
+    pub fn show_export_diff(&mut self) {
+        self.finish_metadata();
+        self.export_diff = self
+            .session
+            .last_export
+            .as_ref()
+            .map(|snapshot| snapshot.diff(&ExportSnapshot::capture(&self.model.tree)));
+        self.export_diff_scroll = 0;
+        self.export_diff_note = None;
+        self.view.mode = AppMode::ExportDiff;
+    }
+
+    /// Whether the selection (or any included file) changed since the last export
+    pub fn changed_since_last_export(&self) -> bool {
+        self.export_diff.as_ref().is_none_or(|diff| !diff.is_empty())
+    }
+
+    /// Scroll whichever diff popup (or the changelog) is open
+    pub fn scroll_diff(&mut self, delta: isize) {
+        match self.view.mode {
+            AppMode::FileDiff => self.file_diff_scroll = self.file_diff_scroll.saturating_add_signed(delta),
+            AppMode::WhatsNew => self.whats_new_scroll = self.whats_new_scroll.saturating_add_signed(delta),
+            AppMode::SetConflict => {
+                if let Some(conflict) = &mut self.set_conflict {
+                    conflict.scroll = conflict.scroll.saturating_add_signed(delta);
+                }
+            }
+            AppMode::Preview => {
+                let last_line = self.preview_cache.as_ref().map_or(0, |preview| preview.lines().len().saturating_sub(1));
+                self.preview_scroll = self.preview_scroll.saturating_add_signed(delta).min(last_line);
+            }
+            _ => self.export_diff_scroll = self.export_diff_scroll.saturating_add_signed(delta),
+        }
+    }
+
+    /// Remember what was exported so the next session can diff against it
+    pub fn record_export(&mut self) -> anyhow::Result<()> {
+        self.session.last_export = Some(ExportSnapshot::capture(&self.model.tree));
+        self.session.save()
+    }
+
+    /// Ask where a slot used for the first time exports to
+    pub fn start_slot_setup(&mut self, number: u8) {
+        self.pending_content = None;
+        self.file_save_slot = Some(number);
+        self.file_save_input.clear();
+        self.view.mode = AppMode::FileSave;
+    }
+
+    /// Remember slot `number` for this project. The format is the one saving with the
+    /// output's extension, if any, else whatever format is configured when it's used.
+    pub fn set_slot(&mut self, number: u8, output: PathBuf) -> anyhow::Result<ExportSlot> {
+        let extension = output.extension().map(|extension| extension.to_string_lossy().to_string());
+        let format = extension.and_then(|extension| self.formatter.format_for_extension(&extension));
+        let slot = ExportSlot {
+            output,
+            format: format.map(str::to_string),
+        };
+        self.session.slots.insert(number.to_string(), slot.clone());
+        self.session.save()?;
+        Ok(slot)
+    }
+
+    pub fn start_file_save(&mut self, content: String) {
+        self.pending_content = Some(content);
+        self.file_save_input.clear();
+        self.view.mode = AppMode::FileSave;
+    }
+
+    pub fn add_file_save_char(&mut self, c: char) {
+        if self.view.mode == AppMode::FileSave {
+            self.file_save_input.push(c);
+        }
+    }
+
+    pub fn file_save_backspace(&mut self) {
+        if self.view.mode == AppMode::FileSave {
+            self.file_save_input.pop();
+        }
+    }
+
+    /// Export in the next registered format, for Ctrl+E and the export size estimate
+    pub fn cycle_format(&mut self) {
+        let formats = self.formatter.formats();
+        if formats.is_empty() {
+            return;
+        }
+        let current = formats.iter().position(|name| *name == self.formatter.format());
+        let next = formats[current.map_or(0, |current| (current + 1) % formats.len())];
+        self.formatter = self.formatter.clone().with_format(next);
+        self.mark_selection_changed();
+        self.set_status_message(format!("Export format: {}", next));
+    }
+
+    /// Defer recomputing selection totals until changes settle, so bulk toggles stay cheap
+    pub fn mark_selection_changed(&mut self) {
+        self.selection_changed_at = Some(Instant::now());
+        if !self.show_status_bar {
+            self.stats_flashed_at = Some(Instant::now());
+        }
+    }
+
+    /// Recompute selection totals once no change has happened for the debounce period
+    pub fn refresh_selection_totals(&mut self) {
+        if self
+            .selection_changed_at
+            .is_some_and(|changed_at| changed_at.elapsed() >= SELECTION_TOTALS_DEBOUNCE)
+        {
+            self.recompute_selection_totals();
+        }
+    }
+
+    /// Apply whatever the background metadata pass has collected so far, without blocking
+    pub fn apply_metadata_updates(&mut self) {
+        let Some(receiver) = &self.metadata_updates else {
+            return;
+        };
+
+        let mut updates = Vec::new();
+        loop {
+            match receiver.try_recv() {
+                Ok(update) => updates.push(update),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.metadata_updates = None;
+                    break;
+                }
+            }
+        }
+        self.apply_metadata(updates);
+    }
+
+    /// Wait for the background metadata pass to complete, for operations that need exact data
+    pub fn finish_metadata(&mut self) {
+        if let Some(receiver) = self.metadata_updates.take() {
+            let updates: Vec<MetadataUpdate> = receiver.iter().collect();
+            self.apply_metadata(updates);
+            self.recompute_selection_totals();
+        }
+    }
+
+    fn apply_metadata(&mut self, updates: Vec<MetadataUpdate>) {
+        if updates.is_empty() {
+            return;
+        }
+
+        let mut visibility_changed = false;
+        for update in &updates {
+            visibility_changed |= update.apply(&mut self.model.tree);
+        }
+        self.model.refresh_directory_sizes();
+        // Sizes arrive continuously, so update totals now rather than waiting for a quiet period
+        if self.selection_changed_at.is_none() {
+            self.recompute_selection_totals();
+        }
+
+        // Files found to be binary or too large drop out of the list; keep the cursor on its node
+        if visibility_changed {
+            self.refresh_rows();
+        }
+    }
+
+    pub fn metadata_pending(&self) -> bool {
+        self.metadata_updates.is_some()
+    }
+
+    fn recompute_selection_totals(&mut self) {
+        // Note a collision once, when it appears; exports always carry full relative paths
+        let new_names = self.model.recompute_totals(&self.formatter);
+        if !new_names.is_empty() {
+            let message = format!(
+                "{} Included files share the name {}, exports use full paths to tell them apart",
+                self.icons.warning,
+                new_names.join(", ")
+            );
+            self.set_status_message(message);
+        }
+        self.selection_changed_at = None;
+    }
+
+    pub fn get_stats(&self) -> SelectionStats {
+        SelectionStats {
+            calculating: self.selection_changed_at.is_some(),
+            approximate: self.metadata_pending(),
+            ..self.model.stats()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuzzy::matcher::MatchMode;
+    use crate::model::FilterTotals;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_selection_totals_debounced() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root.clone());
+        for name in ["a.rs", "b.rs"] {
+            std::fs::write(root.join(name), "fn main() {}").unwrap();
+            tree.add_node(root.join(name), false, &root);
+        }
+        let mut app = App::new(tree);
+        assert_eq!(app.get_stats().included_files, 0);
+
+        // Toggle both files one after another, as a quick bulk change would
+        for name in ["a.rs", "b.rs"] {
+            let tree_index = app.model.tree.path_to_index[&root.join(name)];
+            app.view.selected_index = app.model.filtered_results.visible_items.iter().position(|&i| i == tree_index).unwrap();
+            app.toggle_selection();
+        }
+        let stats = app.get_stats();
+        assert!(stats.calculating);
+        assert_eq!(stats.format_size(), "calculating…");
+
+        // Nothing is recomputed until changes have settled
+        app.refresh_selection_totals();
+        assert!(app.get_stats().calculating);
+
+        app.selection_changed_at = Some(Instant::now() - SELECTION_TOTALS_DEBOUNCE);
+        app.refresh_selection_totals();
+        let stats = app.get_stats();
+        assert!(!stats.calculating);
+        assert_eq!(stats.included_files, 2);
+    }
+
+    #[test]
+    fn test_toggle_partial_directory_message() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        let src = tree.add_node(root.join("src"), true, &root).unwrap();
+        for name in ["a.rs", "b.rs", "c.rs"] {
+            let index = tree.add_node(root.join("src").join(name), false, &root.join("src")).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        tree.toggle_state(tree.path_to_index[&root.join("src/a.rs")]);
+        let mut app = App::new(tree);
+        app.view.selected_index = app.model.filtered_results.visible_items.iter().position(|&i| i == src).unwrap();
+
+        app.toggle_selection();
+        assert_eq!(app.get_status_message(), Some("src/: partial → included, 2 files"));
+        app.toggle_selection();
+        assert_eq!(app.get_status_message(), Some("src/: included → excluded, 3 files"));
+        app.toggle_selection();
+        assert_eq!(app.get_status_message(), Some("src/: excluded → included, 3 files"));
+    }
+
+    #[test]
+    fn test_cycle_format() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        std::fs::write(root.join("a.rs"), "fn main() {}").unwrap();
+        let mut tree = DirectoryTree::new(root.clone());
+        let index = tree.add_node(root.join("a.rs"), false, &root).unwrap();
+        tree.nodes[index].is_text_file = true;
+        tree.nodes[index].size = Some(12);
+        tree.toggle_state(index);
+        let mut app = App::new(tree);
+        let markdown_size = app.get_stats().export_size;
+
+        // Every registered format in turn, then back to the first
+        let mut formats = Vec::new();
+        for _ in 0..4 {
+            app.cycle_format();
+            formats.push(app.formatter.format().to_string());
+        }
+        assert_eq!(formats, ["plain", "json", "xml", "markdown"]);
+        assert_eq!(app.get_status_message(), Some("Export format: markdown"));
+
+        // The export size estimate follows the format once totals settle
+        app.cycle_format();
+        app.selection_changed_at = Some(Instant::now() - SELECTION_TOTALS_DEBOUNCE);
+        app.refresh_selection_totals();
+        assert_ne!(app.get_stats().export_size, markdown_size);
+        assert_eq!(app.formatter.extension(), "txt");
+    }
+
+    #[test]
+    fn test_shared_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root.clone());
+        for directory in ["ui", "config"] {
+            std::fs::create_dir(root.join(directory)).unwrap();
+            std::fs::write(root.join(directory).join("mod.rs"), format!("// {}", directory)).unwrap();
+            tree.add_node(root.join(directory), true, &root);
+            let index = tree.add_node(root.join(directory).join("mod.rs"), false, &root.join(directory)).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        let mut app = App::new(tree).with_match_file_names(true);
+        assert_eq!(app.get_stats().shared_names, 0);
+
+        app.model.tree.toggle_state(app.model.tree.root_index);
+        app.recompute_selection_totals();
+        assert_eq!(app.get_stats().shared_names, 1);
+        assert!(app.get_status_message().unwrap().contains("share the name mod.rs"));
+
+        // Headers carry the full relative path even while the list shows bare names
+        let output = app.formatter.format_output(&app.model.tree).unwrap();
+        assert!(output.contains("ui/mod.rs") && output.contains("config/mod.rs"));
+    }
+
+    #[test]
+    fn test_bulk_selection() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("src"), true, &root);
+        tree.add_node(root.join("docs"), true, &root);
+        for path in ["src/a.rs", "src/b.rs", "src/notes.md", "docs/guide.md", "README.md"] {
+            let parent = root.join(path).parent().unwrap().to_path_buf();
+            let index = tree.add_node(root.join(path), false, &parent).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        let mut app = App::new(tree);
+        let state = |app: &App, path: &str| app.model.tree.nodes[app.model.tree.path_to_index[&root.join(path)]].state;
+
+        // While searching only the matching files change, not the directory they're in
+        for c in ".rs$".chars() {
+            app.add_search_char(c);
+        }
+        app.view.selected_index = 1;
+        app.toggle_all();
+        assert_eq!(app.get_status_message(), Some("2 matching files included"));
+        assert_eq!(app.view.selected_index, 1);
+        assert_eq!((state(&app, "src/a.rs"), state(&app, "src/notes.md")), (SelectionState::Included, SelectionState::Excluded));
+        assert_eq!(state(&app, "src"), SelectionState::Partial);
+        app.recompute_selection_totals();
+        assert_eq!(app.get_stats().included_files, 2);
+        // Again, with every match included, excludes them
+        app.toggle_all();
+        assert_eq!(app.get_status_message(), Some("2 matching files excluded"));
+        assert_eq!(state(&app, "src"), SelectionState::Excluded);
+        app.toggle_all();
+
+        app.clear_search();
+        app.invert_selection();
+        assert_eq!(app.get_status_message(), Some("5 files inverted"));
+        assert_eq!((state(&app, "src/a.rs"), state(&app, "src/notes.md")), (SelectionState::Excluded, SelectionState::Included));
+        app.recompute_selection_totals();
+        assert_eq!(app.get_stats().included_files, 3);
+
+        // Folded directories' files count as listed
+        app.toggle_tree_view();
+        app.collapsed.insert(app.model.tree.path_to_index[&root.join("docs")]);
+        app.update_filtered_results();
+        app.select_none();
+        assert_eq!(state(&app, "docs/guide.md"), SelectionState::Excluded);
+        assert_eq!(app.model.tree.nodes[app.model.tree.root_index].state, SelectionState::Excluded);
+    }
+
+    #[test]
+    fn test_reason_dropped_on_toggle() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root.clone());
+        for name in ["gen.rs", "main.rs", "vendored.rs"] {
+            let index = tree.add_node(root.join(name), false, &root).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        let rules_path = Rules::get_project_rules_path(&root);
+        let content = "+ **\n- gen.rs  # generated\n- vendored.rs  # vendored\n";
+        std::fs::write(&rules_path, content).unwrap();
+        let rules = Rules::load(&rules_path).unwrap();
+        let summary = rules.apply(&mut tree);
+        let mut app = App::new(tree).with_rules(Some(rules), Some(summary));
+        let gen_rs = app.model.tree.path_to_index[&root.join("gen.rs")];
+        assert_eq!(app.exclusion_reason(gen_rs), Some("generated"));
+
+        // Bulk changes and recomputed totals leave the shared file alone
+        app.toggle_all();
+        app.recompute_selection_totals();
+        app.toggle_all();
+        assert_eq!(std::fs::read_to_string(&rules_path).unwrap(), content);
+
+        // Toggling the node itself drops the reason with the rule that carried it
+        app.view.selected_index = app.model.filtered_results.visible_items.iter().position(|&index| index == gen_rs).unwrap();
+        app.toggle_selection();
+        assert!(app.model.tree.nodes[gen_rs].state.is_included());
+        assert_eq!(std::fs::read_to_string(&rules_path).unwrap(), "+ **\n- vendored.rs  # vendored\n");
+        assert!(app.get_status_message().unwrap().contains("removed its exclude rule"));
+    }
+
+    #[test]
+    fn test_visual_range() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("src"), true, &root);
+        for path in ["src/a.rs", "src/b.rs", "src/c.rs", "README.md"] {
+            let parent = root.join(path).parent().unwrap().to_path_buf();
+            let index = tree.add_node(root.join(path), false, &parent).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        let mut app = App::new(tree);
+        let state = |app: &App, path: &str| app.model.tree.nodes[app.model.tree.path_to_index[&root.join(path)]].state;
+        let row = |app: &App, path: &str| {
+            let index = app.model.tree.path_to_index[&root.join(path)];
+            app.model.filtered_results.visible_items.iter().position(|&row| row == index).unwrap()
+        };
+
+        // Anchored on b.rs and extended up to a.rs, either way round
+        app.view.selected_index = row(&app, "src/b.rs");
+        app.toggle_visual();
+        app.move_up();
+        assert_eq!(app.visual_range(), Some(row(&app, "src/a.rs")..=row(&app, "src/b.rs")));
+        assert_eq!(app.bulk_prompt().unwrap(), "VISUAL (2 items) | Enter: Toggle, Esc: Cancel");
+        app.apply_visual();
+        assert_eq!(app.get_status_message(), Some("2 items included"));
+        assert_eq!((state(&app, "src/a.rs"), state(&app, "src/c.rs")), (SelectionState::Included, SelectionState::Excluded));
+        assert_eq!(state(&app, "src"), SelectionState::Partial);
+        assert!(app.visual_range().is_none() && app.bulk_prompt().is_none());
+
+        // A range all included already is excluded, and the directories follow
+        app.toggle_visual();
+        app.move_down();
+        app.apply_visual();
+        assert_eq!(app.get_status_message(), Some("2 items excluded"));
+        assert_eq!(state(&app, "src"), SelectionState::Excluded);
+
+        // A locked excluded row doesn't stop the rest of the range being excluded
+        let c = app.model.tree.path_to_index[&root.join("src/c.rs")];
+        app.model.tree.nodes[c].locked = true;
+        app.view.selected_index = row(&app, "src/a.rs");
+        app.toggle_visual();
+        app.move_down();
+        app.apply_visual();
+        app.view.selected_index = row(&app, "src/a.rs");
+        app.toggle_visual();
+        app.move_down();
+        app.move_down();
+        app.apply_visual();
+        assert_eq!(app.get_status_message(), Some("3 items excluded"));
+        assert_eq!((state(&app, "src/a.rs"), state(&app, "src/c.rs")), (SelectionState::Excluded, SelectionState::Excluded));
+
+        // A range whose anchor is filtered away is dropped
+        app.toggle_visual();
+        for c in "README".chars() {
+            app.add_search_char(c);
+        }
+        assert!(app.visual_anchor.is_none());
+    }
+
+    #[test]
+    fn test_match_modes() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("src"), true, &root);
+        for path in ["src/config.rs", "src/config.test.ts", "src/config_v2.test.ts"] {
+            let index = tree.add_node(root.join(path), false, &root.join("src")).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        let mut app = App::new(tree);
+        let listed = |app: &App| -> Vec<String> {
+            app.model.filtered_results.visible_items.iter().map(|&index| app.model.tree.nodes[index].name.clone()).collect()
+        };
+        for c in "config.test.ts".chars() {
+            app.add_search_char(c);
+        }
+        // Fuzzily the letters can be anywhere, as a substring they can't
+        assert!(listed(&app).len() > 1);
+        app.cycle_match_mode();
+        assert_eq!(app.model.match_mode, MatchMode::Substring);
+        assert_eq!(listed(&app), ["config.test.ts"]);
+
+        app.cycle_match_mode();
+        app.clear_search();
+        for c in r"config\.(rs|test)".chars() {
+            app.add_search_char(c);
+        }
+        assert_eq!(listed(&app), ["config.rs", "config.test.ts"]);
+        // A regex that doesn't compile keeps the last matches listed
+        app.search_backspace();
+        assert!(app.model.invalid_query);
+        assert_eq!(listed(&app), ["config.rs", "config.test.ts"]);
+        app.add_search_char(')');
+        assert!(!app.model.invalid_query);
+
+        app.cycle_match_mode();
+        assert_eq!(app.model.match_mode, MatchMode::Fuzzy);
+    }
+
+    #[test]
+    fn test_smart_include() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        std::fs::write(root.join("main.rs"), "mod cli;\nmod ui;\n").unwrap();
+        std::fs::write(root.join("cli.rs"), "").unwrap();
+        std::fs::write(root.join("ui.rs"), "").unwrap();
+        let mut tree = DirectoryTree::new(root.clone());
+        for name in ["main.rs", "cli.rs", "ui.rs"] {
+            tree.add_node(root.join(name), false, &root);
+        }
+        let mut app = App::new(tree);
+        let main_index = app.model.tree.path_to_index[&root.join("main.rs")];
+        app.view.selected_index = app.model.filtered_results.visible_items.iter().position(|&i| i == main_index).unwrap();
+
+        app.start_smart_include();
+        assert_eq!(app.view.mode, AppMode::SmartInclude);
+        assert_eq!(app.smart_include.as_ref().unwrap().candidates.len(), 2);
+
+        // Uncheck ui.rs, then include the rest
+        app.move_smart_include(1);
+        app.toggle_smart_include_candidate();
+        app.confirm_smart_include();
+
+        let state = |name: &str| app.model.tree.nodes[app.model.tree.path_to_index[&root.join(name)]].state;
+        assert_eq!(app.view.mode, AppMode::Main);
+        assert_eq!(state("main.rs"), SelectionState::Included);
+        assert_eq!(state("cli.rs"), SelectionState::Included);
+        assert_eq!(state("ui.rs"), SelectionState::Excluded);
+    }
+
+    #[test]
+    fn test_restore_folds() {
+        let root = PathBuf::from("/project");
+        let tree = || {
+            let mut tree = DirectoryTree::new(root.clone());
+            for (path, is_directory) in [("src", true), ("src/ui", true), ("src/ui/b.rs", false), ("README.md", false)] {
+                let path = root.join(path);
+                tree.add_node(path.clone(), is_directory, path.parent().unwrap());
+            }
+            tree
+        };
+        let session = |collapsed: Option<&[&str]>| Session {
+            root: root.clone(),
+            collapsed: collapsed.map(|paths| paths.iter().map(|path| path.to_string()).collect()),
+            ..Session::default()
+        };
+
+        // Nothing saved yet: directories below the default fold level start folded, and
+        // aren't saved until the user folds or unfolds something
+        let mut app = App::new(tree()).with_fold_level(Some(2)).with_session(session(None));
+        assert_eq!(app.collapsed_paths(), ["src/ui"]);
+        app.save_folds().unwrap();
+        assert_eq!(app.session.collapsed, None);
+        let app = App::new(tree()).with_session(session(None));
+        assert!(app.collapsed.is_empty());
+
+        // Saved folds win over the default, and vanished directories are skipped
+        let mut app = App::new(tree()).with_fold_level(Some(2)).with_session(session(Some(&["src", "gone"])));
+        assert_eq!(app.collapsed_paths(), ["src"]);
+
+        // A search unfolds what leads to a match, but the folds it shows through stay saved
+        app.tree_view = true;
+        "b.rs".chars().for_each(|c| app.add_search_char(c));
+        assert_eq!(app.model.filtered_results.visible_items.len(), 3);
+        assert_eq!(app.collapsed_paths(), ["src"]);
+    }
+
+    #[test]
+    fn test_tree_view() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        for (path, is_directory) in [("src", true), ("src/a.rs", false), ("src/ui", true), ("src/ui/b.rs", false), ("README.md", false)] {
+            let path = root.join(path);
+            let index = tree.add_node(path.clone(), is_directory, path.parent().unwrap()).unwrap();
+            tree.nodes[index].is_text_file = !is_directory;
+        }
+        let mut app = App::new(tree);
+        let rows = |app: &App| -> Vec<String> {
+            app.model.filtered_results.visible_items.iter().map(|&index| app.model.tree.nodes[index].name.clone()).collect()
+        };
+        let index = |app: &App, path: &str| app.model.tree.path_to_index[&root.join(path)];
+        let select = |app: &mut App, path: &str| {
+            let tree_index = index(app, path);
+            app.view.selected_index = app.model.filtered_results.visible_items.iter().position(|&i| i == tree_index).unwrap();
+        };
+
+        // Each directory is followed by what's in it
+        app.toggle_tree_view();
+        assert_eq!(rows(&app), ["project", "src", "a.rs", "ui", "b.rs", "README.md"]);
+
+        // Folding hides the contents and keeps the cursor on the directory
+        select(&mut app, "src");
+        app.collapse_directory();
+        assert_eq!(rows(&app), ["project", "src", "README.md"]);
+        assert_eq!(app.get_selected_tree_index(), Some(index(&app, "src")));
+        // Toggling a folded directory still reaches what's hidden
+        app.toggle_selection();
+        assert_eq!(app.model.tree.nodes[index(&app, "src/ui/b.rs")].state, SelectionState::Included);
+        // Space unfolds and folds in place, leaving the selection alone
+        let state = app.model.tree.nodes[index(&app, "src")].state;
+        app.toggle_fold();
+        assert_eq!(rows(&app), ["project", "src", "a.rs", "ui", "b.rs", "README.md"]);
+        app.toggle_fold();
+        assert_eq!(rows(&app), ["project", "src", "README.md"]);
+        assert_eq!(app.get_selected_tree_index(), Some(index(&app, "src")));
+        assert_eq!(app.model.tree.nodes[index(&app, "src")].state, state);
+        // Folding it again moves up to its directory instead
+        app.collapse_directory();
+        assert_eq!(app.get_selected_tree_index(), Some(app.model.tree.root_index));
+        // Space does nothing on a file
+        select(&mut app, "README.md");
+        app.toggle_fold();
+        assert_eq!(app.get_selected_tree_index(), Some(index(&app, "README.md")));
+        select(&mut app, "src");
+        app.collapse_directory();
+
+        // A search unfolds what it needs and lists the way to each match without matching it
+        "b.rs$".chars().for_each(|c| app.add_search_char(c));
+        assert_eq!(rows(&app), ["src", "ui", "b.rs"]);
+        assert_eq!(app.get_stats().filtered_count, 1);
+        app.plan_bulk(BulkOperation::ExcludeMatches);
+        app.confirm_bulk();
+        assert_eq!(app.model.tree.nodes[index(&app, "src/a.rs")].state, SelectionState::Included);
+        assert_eq!(app.model.tree.nodes[index(&app, "src/ui/b.rs")].state, SelectionState::Excluded);
+
+        // Clearing it folds back, and unfolding shows the rest
+        app.model.query.clear();
+        app.search_cursor = 0;
+        app.update_filtered_results();
+        assert_eq!(rows(&app), ["project", "src", "README.md"]);
+        select(&mut app, "src");
+        app.expand_directory();
+        assert_eq!(rows(&app).len(), 6);
+
+        app.toggle_tree_view();
+        let flat = app.model.filtered_results.visible_items.iter().map(|&i| get_node_display_path(&app.model.tree, i));
+        assert_eq!(flat.collect::<Vec<_>>(), ["", "src", "src/a.rs", "src/ui", "src/ui/b.rs", "README.md"]);
+        assert_eq!(app.get_selected_tree_index(), Some(index(&app, "src")));
+    }
+
+    #[test]
+    fn test_exclusion_suggestions() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        for (name, size) in [("Cargo.lock", 40_000), ("main.rs", 8_000), ("lib.rs", 400)] {
+            let index = tree.add_node(root.join(name), false, &root).unwrap();
+            tree.nodes[index].is_text_file = true;
+            tree.nodes[index].size = Some(size);
+        }
+        tree.set_state(tree.root_index, SelectionState::Included);
+        let mut app = App::new(tree).with_token_budget(Some(5_000));
+        app.recompute_selection_totals();
+        assert!(app.over_budget());
+
+        app.start_suggestions();
+        assert_eq!(app.view.mode, AppMode::Suggestions);
+        let names: Vec<_> = app.suggestions.as_ref().unwrap().items.iter().map(|item| app.model.tree.nodes[item.index].name.clone()).collect();
+        assert_eq!(names, ["Cargo.lock", "main.rs", "lib.rs"]);
+
+        // Excluding is an ordinary selection change, and the projection follows it at once
+        let before = app.projected_tokens();
+        app.exclude_suggestion(Some(0));
+        let state = |app: &App, name: &str| app.model.tree.nodes[app.model.tree.path_to_index[&root.join(name)]].state;
+        assert_eq!(state(&app, "Cargo.lock"), SelectionState::Excluded);
+        assert_eq!(app.model.tree.nodes[app.model.tree.root_index].state, SelectionState::Partial);
+        assert!(before - app.projected_tokens() > 10_000); // Its content and its header
+        assert!(!app.over_budget() && !app.get_stats().calculating);
+        assert_eq!(app.suggestions.as_ref().unwrap().items.len(), 2);
+
+        // Out of range keys do nothing; the last suggestion closes the popup
+        app.exclude_suggestion(Some(5));
+        app.move_suggestion(1);
+        app.exclude_suggestion(None);
+        assert_eq!(state(&app, "lib.rs"), SelectionState::Excluded);
+        app.exclude_suggestion(None);
+        assert_eq!(app.view.mode, AppMode::Main);
+        assert!(app.suggestions.is_none());
+
+        // Without a budget there's nothing to fit
+        let mut app = App::new(DirectoryTree::new(root));
+        app.start_suggestions();
+        assert_eq!(app.view.mode, AppMode::Main);
+    }
+
+    #[test]
+    fn test_hidden_status_bar_flashes_stats() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("a.rs"), false, &root);
+        let mut app = App::new(tree).with_show_status_bar(false);
+        assert!(!app.status_flash_visible());
+
+        app.toggle_selection();
+        assert!(app.status_flash_visible());
+
+        // Showing the bar again ends any flash
+        app.toggle_status_bar();
+        assert!(app.show_status_bar && !app.status_flash_visible());
+
+        // Hiding it says how to get it back
+        app.toggle_status_bar();
+        assert!(app.status_flash_visible());
+        assert!(app.get_status_message().unwrap().contains("Ctrl+B"));
+    }
+
+    #[test]
+    fn test_match_siblings() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        let src = root.join("src");
+        tree.add_node(src.clone(), true, &root);
+        for name in ["a.rs", "b.rs", "c.rs", "nested"] {
+            tree.add_node(src.join(name), name == "nested", &src);
+        }
+        tree.add_node(src.join("nested/d.rs"), false, &src.join("nested"));
+        let index_of = |tree: &DirectoryTree, path: &str| tree.path_to_index[&root.join(path)];
+        tree.toggle_lock(index_of(&tree, "src/c.rs"));
+
+        let mut app = App::new(tree);
+        let a = index_of(&app.model.tree, "src/a.rs");
+        app.model.tree.toggle_state(a);
+        app.view.selected_index = app.model.filtered_results.visible_items.iter().position(|&i| i == a).unwrap();
+        app.match_siblings();
+
+        let state_of = |app: &App, path: &str| app.model.tree.nodes[index_of(&app.model.tree, path)].state;
+        assert_eq!(state_of(&app, "src/b.rs"), SelectionState::Included);
+        assert_eq!(state_of(&app, "src/c.rs"), SelectionState::Excluded); // Locked
+        assert_eq!(state_of(&app, "src/nested"), SelectionState::Excluded);
+        assert_eq!(state_of(&app, "src"), SelectionState::Partial);
+        assert_eq!(app.get_status_message(), Some("1 sibling(s) now included"));
+
+        app.match_sibling_directories = true;
+        app.match_siblings();
+        assert_eq!(state_of(&app, "src/nested/d.rs"), SelectionState::Included);
+    }
+
+    #[test]
+    fn test_bulk_preview() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        for name in ["a.rs", "b.rs", "notes.md"] {
+            let index = tree.add_node(root.join(name), false, &root).unwrap();
+            tree.nodes[index].is_text_file = true;
+            tree.nodes[index].size = Some(1024);
+        }
+        let mut app = App::new(tree);
+        let a = app.model.tree.path_to_index[&root.join("a.rs")];
+        app.model.tree.set_state(a, SelectionState::Included);
+        app.mark_selection_changed();
+        app.recompute_selection_totals();
+
+        // Cancelling leaves no trace
+        app.model.query = "md".to_string();
+        app.update_filtered_results();
+        app.plan_bulk(BulkOperation::InvertMatches);
+        let plan = app.bulk_plan.as_ref().unwrap();
+        assert_eq!(plan.describe(), "Invert matches: +1 included, −0 excluded, +1.0 KB");
+        assert_eq!(app.get_stats().included_files, 1);
+        app.handle_escape();
+        assert!(app.bulk_plan.is_none() && !app.should_quit);
+        assert_eq!(app.model.tree.nodes[a].state, SelectionState::Included);
+
+        app.model.query.clear();
+        app.update_filtered_results();
+        app.view.selected_index = app.model.filtered_results.visible_items.iter().position(|&i| i == a).unwrap();
+        app.plan_exclude_extension();
+        assert_eq!(app.bulk_plan.as_ref().map(|plan| plan.excluded_files), Some(1));
+        app.confirm_bulk();
+        app.recompute_selection_totals();
+        assert_eq!(app.get_stats().included_files, 0);
+
+        app.plan_exclude_extension();
+        assert!(app.bulk_plan.is_none());
+        assert_eq!(app.get_status_message(), Some("Nothing would change"));
+    }
+
+    #[test]
+    fn test_confirm_renames() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        for name in ["new.rs", "other.rs"] {
+            tree.add_node(root.join(name), false, &root);
+        }
+        let set = SelectionSet::parse("old.rs  # moved\nother.rs\n", Path::new("/project/.gthr-set")).unwrap();
+        set.apply(&mut tree);
+        let renames = vec![Rename { from: "old.rs".to_string(), to: "new.rs".to_string() }];
+
+        // Declining reports the path as missing
+        let mut app = App::new(tree).with_selection_set(Some(set));
+        app.offer_renames(renames.clone());
+        assert_eq!(app.view.mode, AppMode::Renames);
+        app.handle_escape();
+        assert_eq!(app.deferred_output.len(), 1);
+        assert!(app.deferred_output[0].ends_with("/project/.gthr-set: `old.rs` not found"));
+
+        app.offer_renames(renames);
+        app.confirm_renames();
+        assert_eq!(app.view.mode, AppMode::Main);
+        assert_eq!(app.model.tree.get_all_included_files().len(), 2);
+        assert!(app.model.tree.nodes[app.model.tree.path_to_index[&root.join("new.rs")]].state.is_included());
+        let set = app.selection_set.as_ref().unwrap();
+        assert_eq!(set.to_string(), "new.rs  # moved\nother.rs\n");
+    }
+
+    #[test]
+    fn test_confirm_profile_renames() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        for name in ["new.rs", "other.rs"] {
+            tree.add_node(root.join(name), false, &root);
+        }
+        tree.set_state(tree.root_index, SelectionState::Included);
+        tree.record_default_selection();
+        let mut profile = Profile { path: root.join(".gthr/state.toml"), locked: vec!["old.rs".to_string()], ..Profile::default() };
+        profile.states.insert("old.rs".to_string(), SelectionState::Excluded);
+        profile.reasons.insert("old.rs".to_string(), "generated".to_string());
+        let summary = profile.apply(&mut tree);
+        assert_eq!(summary.missing, ["old.rs"]);
+
+        // The renamed file gets the saved state and lock, not the set's include
+        let mut app = App::new(tree).with_profile(Some(profile));
+        app.offer_renames(vec![Rename { from: "old.rs".to_string(), to: "new.rs".to_string() }]);
+        app.confirm_renames();
+        let new = app.model.tree.path_to_index[&root.join("new.rs")];
+        assert_eq!(app.model.tree.nodes[new].state, SelectionState::Excluded);
+        assert!(app.model.tree.nodes[new].locked);
+        assert_eq!(app.exclusion_reason(new), Some("generated"));
+        let profile = app.profile.as_ref().unwrap();
+        assert_eq!(profile.locked, ["new.rs"]);
+        assert_eq!(profile.states.keys().collect::<Vec<_>>(), ["new.rs"]);
+    }
+
+    #[test]
+    fn test_mark_for_diff() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root.clone());
+        for (name, content) in [("a.rs", "one\n"), ("b.rs", "two\n")] {
+            std::fs::write(root.join(name), content).unwrap();
+            let index = tree.add_node(root.join(name), false, &root).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        let mut app = App::new(tree);
+        let select = |app: &mut App, name: &str| {
+            let tree_index = app.model.tree.path_to_index[&root.join(name)];
+            app.view.selected_index = app.model.filtered_results.visible_items.iter().position(|&i| i == tree_index).unwrap();
+        };
+
+        select(&mut app, "a.rs");
+        app.mark_for_diff();
+        assert!(app.diff_mark.is_some() && app.view.mode == AppMode::Main);
+
+        select(&mut app, "b.rs");
+        app.mark_for_diff();
+        assert_eq!(app.view.mode, AppMode::FileDiff);
+        assert!(app.file_diff.as_ref().unwrap().similarity < 0.5);
+
+        // Closing clears the mark, so the next D starts over
+        app.handle_escape();
+        assert!(app.file_diff.is_none() && app.diff_mark.is_none());
+    }
+
+    #[test]
+    fn test_changed_filter() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let repo = git2::Repository::init(&root).unwrap();
+        std::fs::write(root.join("kept.rs"), "same").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("kept.rs")).unwrap();
+        let tree_id = index.write_tree().unwrap();
+        let signature = git2::Signature::now("gthr", "gthr@example.com").unwrap();
+        let commit_tree = repo.find_tree(tree_id).unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "initial", &commit_tree, &[]).unwrap();
+        std::fs::write(root.join("new.rs"), "untracked").unwrap();
+
+        let mut tree = DirectoryTree::new(root.clone());
+        for name in ["kept.rs", "new.rs"] {
+            let index = tree.add_node(root.join(name), false, &root).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        let mut app = App::new(tree);
+        let visible_files = |app: &App| -> Vec<String> {
+            let items = app.model.filtered_results.visible_items.iter().map(|&i| &app.model.tree.nodes[i]);
+            items.filter(|node| !node.is_directory).map(|node| node.name.clone()).collect()
+        };
+        assert_eq!(visible_files(&app).len(), 2);
+
+        app.toggle_changed_filter();
+        assert_eq!(visible_files(&app), vec!["new.rs".to_string()]);
+
+        app.toggle_changed_filter();
+        assert!(app.model.changed_files.is_none());
+        assert_eq!(visible_files(&app).len(), 2);
+    }
+
+    #[test]
+    fn test_grapheme_query_editing() {
+        let mut app = App::new(DirectoryTree::new(PathBuf::from("/project")));
+        let type_text = |app: &mut App, text: &str| text.chars().for_each(|c| app.add_search_char(c));
+
+        // A family emoji is several chars joined into one grapheme
+        type_text(&mut app, "a👨\u{200d}👩\u{200d}👧");
+        assert_eq!(app.search_cursor_column(), 3);
+        app.search_backspace();
+        assert_eq!(app.model.query, "a");
+
+        // Decomposed accent: the combining mark joins the letter typed before it
+        type_text(&mut app, "e\u{301}x");
+        app.move_search_cursor(-1);
+        app.move_search_cursor(-1);
+        assert_eq!(app.search_cursor, 1);
+        app.search_delete();
+        assert_eq!(app.model.query, "ax");
+
+        // CJK is two columns wide, and typing inserts at the caret
+        app.search_cursor_end();
+        type_text(&mut app, "日本");
+        app.move_search_cursor(-1);
+        assert_eq!(app.search_cursor_column(), 4);
+        app.add_search_char('-');
+        assert_eq!(app.model.query, "ax日-本");
+        app.search_cursor_home();
+        app.search_backspace();
+        assert_eq!((app.model.query.as_str(), app.search_cursor), ("ax日-本", 0));
+    }
+
+    #[test]
+    fn test_replace_tree() {
+        let tree_with = |root: &str, files: &[&str]| {
+            let root = PathBuf::from(root);
+            let mut tree = DirectoryTree::new(root.clone());
+            for name in files {
+                let index = tree.add_node(root.join(name), false, &root).unwrap();
+                tree.nodes[index].is_text_file = true;
+            }
+            tree
+        };
+        let mut app = App::new(tree_with("/wrong", &["main.rs", "notes.md"]));
+        let main = app.model.tree.path_to_index[&PathBuf::from("/wrong/main.rs")];
+        app.model.tree.toggle_state(main);
+        app.add_search_char('m');
+
+        app.start_change_root();
+        assert_eq!(app.root_input, "/wrong");
+        app.root_input = "/does/not/exist".to_string();
+        app.confirm_change_root();
+        assert!(app.pending_root.is_none() && app.view.mode == AppMode::ChangeRoot);
+
+        // The selection follows relative paths, everything else starts over
+        app.replace_tree(tree_with("/right", &["main.rs", "lib.rs"]), None, true);
+        assert!(app.model.query.is_empty());
+        let included: Vec<_> = app.model.tree.get_all_included_files().iter().map(|node| node.name.clone()).collect();
+        assert_eq!(included, vec!["main.rs".to_string()]);
+
+        app.replace_tree(tree_with("/again", &["main.rs"]), None, false);
+        assert!(app.model.tree.get_all_included_files().is_empty());
+    }
+
+    #[test]
+    fn test_sticky_include_on_rescan() {
+        let tree_with = |root: &str, files: &[&str]| {
+            let root = PathBuf::from(root);
+            let mut tree = DirectoryTree::new(root.clone());
+            for directory in ["src", "docs"] {
+                tree.add_node(root.join(directory), true, &root).unwrap();
+            }
+            for name in files {
+                let path = root.join(name);
+                let index = tree.add_node(path.clone(), false, path.parent().unwrap()).unwrap();
+                tree.nodes[index].is_text_file = true;
+            }
+            tree
+        };
+        let index_of = |app: &App, path: &str| app.model.tree.path_to_index[&PathBuf::from(path)];
+        let mut app = App::new(tree_with("/project", &["src/main.rs", "docs/a.md", "docs/b.md"]));
+
+        // Included as a whole, and hand-tuned
+        let src = index_of(&app, "/project/src");
+        let docs = index_of(&app, "/project/docs");
+        app.model.tree.toggle_state(src);
+        app.model.tree.toggle_state(docs);
+        assert!(app.model.tree.nodes[src].sticky && app.model.tree.nodes[docs].sticky);
+        app.model.tree.toggle_state(index_of(&app, "/project/docs/b.md"));
+        app.model.tree.toggle_state(index_of(&app, "/project/docs/b.md"));
+        assert!(!app.model.tree.nodes[docs].sticky);
+
+        let rescanned = tree_with("/project", &["src/main.rs", "src/new.rs", "docs/a.md", "docs/b.md", "docs/new.md"]);
+        app.replace_tree(rescanned, None, true);
+        let state = |app: &App, path: &str| app.model.tree.nodes[index_of(app, path)].state;
+        assert_eq!(state(&app, "/project/src/new.rs"), SelectionState::Included);
+        assert!(app.model.tree.nodes[index_of(&app, "/project/src")].sticky);
+        assert_eq!(state(&app, "/project/docs/b.md"), SelectionState::Included);
+        assert_eq!(state(&app, "/project/docs/new.md"), SelectionState::Excluded);
+    }
+
+    #[test]
+    fn test_save_selection_set() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root.clone());
+        for name in ["a.rs", "b.rs"] {
+            std::fs::write(root.join(name), "fn main() {}").unwrap();
+            let index = tree.add_node(root.join(name), false, &root).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        // A hand-written note on a file that stays included
+        let set_path = root.join(".gthr-set");
+        std::fs::write(&set_path, "# Shared context\na.rs:2-3  # the entry point\n").unwrap();
+        let mut app = App::new(tree);
+        app.model.tree.toggle_state(app.model.tree.scan_root_index);
+
+        app.save_selection_set();
+        let saved = std::fs::read_to_string(&set_path).unwrap();
+        assert_eq!(saved, "a.rs:2-3  # the entry point\nb.rs\n");
+        assert_eq!(app.selection_set.as_ref().unwrap().path, set_path);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_select_command() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root.clone());
+        for name in ["a.rs", "b.rs"] {
+            let index = tree.add_node(root.join(name), false, &root).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        let mut app = App::new(tree);
+        let type_command = |app: &mut App, text: &str| {
+            app.start_command();
+            text.chars().for_each(|c| app.add_command_char(c));
+            app.confirm_command();
+        };
+
+        // Unknown commands and a bare select-cmd with nothing to re-run keep the prompt open
+        type_command(&mut app, "frobnicate now");
+        assert_eq!(app.get_status_message(), Some("Unknown command `frobnicate` (available: select-cmd, include, exclude, essentials, rules reload, root)"));
+        type_command(&mut app, "select-cmd");
+        assert!(app.pending_command.is_none() && app.view.mode == AppMode::Command);
+
+        type_command(&mut app, "select-cmd  printf 'b.rs\nc.rs\n'");
+        assert_eq!(app.view.mode, AppMode::Main);
+        let command = app.pending_command.take().unwrap();
+        assert_eq!(command, "printf 'b.rs\nc.rs\n'");
+        app.run_select_command(command.clone());
+        assert_eq!(app.get_status_message(), Some("select-cmd: 1 paths included, 1 not found"));
+        let included: Vec<_> = app.model.tree.get_all_included_files().iter().map(|node| node.name.clone()).collect();
+        assert_eq!(included, ["b.rs"]);
+
+        // A bare select-cmd runs the last one again; a failing one keeps the selection
+        type_command(&mut app, "select-cmd");
+        assert_eq!(app.pending_command.as_deref(), Some(command.as_str()));
+        app.run_select_command("echo 'unknown team' >&2; exit 2".to_string());
+        let message = app.get_status_message().unwrap();
+        assert!(message.contains("unknown team") && !message.contains('\n'), "{}", message);
+        assert_eq!(app.model.tree.get_all_included_files().len(), 1);
+
+        // `rules reload` re-reads the rules file and applies it on top of the selection
+        let rules_path = Rules::get_project_rules_path(&root);
+        std::fs::write(&rules_path, "+ b.rs\n").unwrap();
+        app.rules = Some(Rules::load(&rules_path).unwrap());
+        std::fs::write(&rules_path, "+ a.rs\n+ missing.rs\n").unwrap();
+        type_command(&mut app, "rules reload");
+        assert_eq!(app.view.mode, AppMode::Main);
+        assert_eq!(app.get_status_message(), Some("Rules reloaded: 1 applied, 1 matched nothing"));
+        assert_eq!(app.model.tree.get_all_included_files().len(), 2);
+
+        // `root` queues a scan like `O`; a path that doesn't exist keeps the prompt open
+        type_command(&mut app, "root missing");
+        assert!(app.pending_root.is_none() && app.view.mode == AppMode::Command);
+        std::fs::create_dir(root.join("other")).unwrap();
+        type_command(&mut app, "root other");
+        assert_eq!(app.pending_root, Some(root.join("other")));
+        assert_eq!(app.view.mode, AppMode::Main);
+
+        // Esc cancels a running scan before it clears the search or quits
+        let traverser = crate::directory::traversal::DirectoryTraverser::new(true, false, 1024, false);
+        app.scan = Some(BackgroundScan::spawn(traverser, &root.join("other")));
+        app.handle_escape();
+        assert!(app.scan.is_none() && !app.should_quit);
+        assert_eq!(app.get_status_message(), Some(format!("Scan of {} cancelled", root.join("other").display()).as_str()));
+    }
+
+    #[test]
+    fn test_query_commands() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        for path in ["src", "src/generated", "src/generated/proto"] {
+            tree.add_node(root.join(path), true, root.join(path).parent().unwrap());
+        }
+        for path in ["src/main.rs", "src/generated/api.rs", "src/generated/proto/types.rs", "README.md"] {
+            let index = tree.add_node(root.join(path), false, root.join(path).parent().unwrap()).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        tree.set_state(tree.root_index, SelectionState::Included);
+        let mut app = App::new(tree);
+        let included = |app: &App| {
+            let mut names: Vec<_> = app.model.tree.get_all_included_files().iter().map(|node| node.name.clone()).collect();
+            names.sort();
+            names
+        };
+        let run = |app: &mut App, text: &str| {
+            app.start_command();
+            text.chars().for_each(|c| app.add_command_char(c));
+            app.confirm_command();
+        };
+
+        // Everything under a directory named generated, at any depth, without searching
+        run(&mut app, "exclude dir:generated");
+        assert_eq!(app.view.mode, AppMode::Main);
+        assert_eq!(app.get_status_message(), Some("dir:generated: 3 matches excluded"));
+        assert_eq!(included(&app), ["README.md", "main.rs"]);
+        assert!(app.model.query.is_empty());
+
+        run(&mut app, "include dir:proto");
+        assert_eq!(included(&app), ["README.md", "main.rs", "types.rs"]);
+
+        // A query is required, and keeps the prompt open without one
+        run(&mut app, "exclude");
+        assert_eq!(app.view.mode, AppMode::Command);
+        assert_eq!(app.get_status_message(), Some("exclude needs a query, e.g. `exclude dir:generated`"));
+    }
+
+    #[test]
+    fn test_save_profile() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root.clone());
+        for name in ["a.rs", "b.rs"] {
+            let index = tree.add_node(root.join(name), false, &root).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        let mut app = App::new(tree);
+        app.save_profile();
+        assert!(app.get_status_message().unwrap().contains("no profile saved"));
+
+        let path = Profile::get_profile_path(&root, None).unwrap();
+        app.profile = Some(Profile { path: path.clone(), ..Profile::default() });
+        app.model.tree.toggle_state(app.model.tree.path_to_index[&root.join("b.rs")]);
+        app.save_profile();
+        assert!(app.get_status_message().unwrap().ends_with("Saved 1 paths to .gthr/state.toml"));
+        let saved = Profile::load(&path).unwrap();
+        assert_eq!(saved.states.keys().collect::<Vec<_>>(), ["b.rs"]);
+        assert_eq!(app.profile.as_ref().unwrap(), &saved);
+
+        // Another gthr saves over it: Ctrl+S asks, and the diff shows both selections
+        let mut theirs = saved.clone();
+        theirs.states.clear();
+        theirs.states.insert("a.rs".to_string(), SelectionState::Included);
+        theirs.save().unwrap();
+        app.save_profile();
+        assert_eq!(app.view.mode, AppMode::SetConflict);
+        let conflict = app.set_conflict.as_ref().unwrap();
+        assert_eq!(conflict.ours.diff(&conflict.theirs), (vec!["b.rs (included)".to_string()], vec!["a.rs (included)".to_string()]));
+        app.save_set_as_new();
+        assert_eq!(app.profile.as_ref().unwrap().path, root.join(".gthr/state-2.toml"));
+        assert_eq!(Profile::load(&path).unwrap().states, theirs.states);
+
+        // Quitting can't ask, so ours goes next to theirs again and the error says where
+        theirs.path = root.join(".gthr/state-2.toml");
+        theirs.on_disk = None;
+        theirs.save().unwrap();
+        let error = app.write_profile().unwrap_err().to_string();
+        assert!(error.ends_with(&format!("went to {}", root.join(".gthr/state-2-2.toml").display())), "{}", error);
+        assert_eq!(Profile::load(&root.join(".gthr/state-2-2.toml")).unwrap().states.keys().collect::<Vec<_>>(), ["b.rs"]);
+    }
+
+    #[test]
+    fn test_selection_set_save_conflict() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let tree = || {
+            let mut tree = DirectoryTree::new(root.clone());
+            for name in ["a.rs", "b.rs", "c.rs"] {
+                std::fs::write(root.join(name), "fn main() {}").unwrap();
+                let index = tree.add_node(root.join(name), false, &root).unwrap();
+                tree.nodes[index].is_text_file = true;
+            }
+            tree
+        };
+        let set_path = root.join(".gthr-set");
+        std::fs::write(&set_path, "a.rs\n").unwrap();
+        let include = |app: &mut App, name: &str| {
+            let index = app.model.tree.path_to_index[&root.join(name)];
+            app.model.tree.set_state_bulk(index, SelectionState::Included);
+        };
+
+        // Two instances load the same set
+        let load = || App::new(tree()).with_selection_set(Some(SelectionSet::load(&set_path).unwrap()));
+        let (mut first, mut second) = (load(), load());
+        include(&mut first, "b.rs");
+        include(&mut second, "c.rs");
+
+        // The first saves, then saves again: its own save is no conflict
+        first.save_selection_set();
+        first.save_selection_set();
+        assert_eq!(first.view.mode, AppMode::Main);
+        assert_eq!(std::fs::read_to_string(&set_path).unwrap(), "b.rs\n");
+
+        // The second would destroy that save, so it's held back
+        second.save_selection_set();
+        assert_eq!(second.view.mode, AppMode::SetConflict);
+        assert_eq!(std::fs::read_to_string(&set_path).unwrap(), "b.rs\n");
+        let conflict = second.set_conflict.as_ref().unwrap();
+        assert_eq!(conflict.ours.diff(&conflict.theirs), (vec!["c.rs".to_string()], vec!["b.rs".to_string()]));
+
+        // Cancelling writes nothing; saving under a new name keeps both
+        second.handle_escape();
+        assert_eq!((second.view.mode.clone(), second.set_conflict.is_none()), (AppMode::Main, true));
+        second.save_selection_set();
+        second.save_set_as_new();
+        let new_path = root.join(".gthr-set-2");
+        assert_eq!(std::fs::read_to_string(&set_path).unwrap(), "b.rs\n");
+        assert_eq!(std::fs::read_to_string(&new_path).unwrap(), "c.rs\n");
+        assert_eq!(second.selection_set.as_ref().unwrap().path, new_path);
+
+        // Overwriting replaces theirs, and the next save goes through
+        first.model.tree.set_state_bulk(first.model.tree.scan_root_index, SelectionState::Included);
+        std::fs::write(&set_path, "a.rs  # edited by hand\n").unwrap();
+        first.save_selection_set();
+        assert_eq!(first.view.mode, AppMode::SetConflict);
+        first.overwrite_set();
+        assert_eq!(std::fs::read_to_string(&set_path).unwrap(), "a.rs\nb.rs\nc.rs\n");
+        first.save_selection_set();
+        assert_eq!(first.view.mode, AppMode::Main);
+
+        // Touching the file without changing it is no conflict
+        let file = std::fs::File::options().append(true).open(&set_path).unwrap();
+        file.set_modified(std::time::SystemTime::now() + Duration::from_secs(5)).unwrap();
+        first.save_selection_set();
+        assert_eq!(first.view.mode, AppMode::Main);
+    }
+
+    #[test]
+    fn test_first_run_wizard() {
+        use crate::config::settings::Settings;
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root.clone());
+        std::fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+        let index = tree.add_node(root.join("main.rs"), false, &root).unwrap();
+        tree.nodes[index].is_text_file = true;
+
+        // Not for a project with a saved profile, named or not
+        assert!(Wizard::is_due(&root, &Settings::default()));
+        std::fs::create_dir_all(root.join(".gthr/profiles")).unwrap();
+        std::fs::write(root.join(".gthr/profiles/review.toml"), "").unwrap();
+        assert!(!Wizard::is_due(&root, &Settings::default()));
+        std::fs::rename(root.join(".gthr/profiles/review.toml"), root.join(".gthr/state.toml")).unwrap();
+        assert!(!Wizard::is_due(&root, &Settings::default()));
+        std::fs::remove_dir_all(root.join(".gthr")).unwrap();
+
+        let mut app = App::new(tree);
+        app.start_wizard(Wizard::new(&Settings::default(), app.formatter.formats()));
+        assert_eq!(app.view.mode, AppMode::Wizard);
+
+        // Include everything, keep gitignore, budget 500, next format
+        app.move_wizard_choice(1);
+        app.confirm_wizard_step();
+        app.confirm_wizard_step();
+        for c in "500".chars() {
+            app.add_wizard_char(c);
+        }
+        app.confirm_wizard_step();
+        app.move_wizard_choice(1);
+        app.confirm_wizard_step();
+
+        assert_eq!(app.view.mode, AppMode::Main);
+        assert_eq!(app.model.tree.get_all_included_files().len(), 1);
+        assert_eq!(app.token_budget, Some(500));
+        assert_eq!(app.formatter.extension(), "txt");
+        let settings = Settings::load_from_file(&root.join(".gthr.toml")).unwrap();
+        assert_eq!((settings.include_all, settings.format.as_str()), (true, "plain"));
+
+        // Skipping writes nothing
+        app.start_wizard(Wizard::new(&Settings::default(), app.formatter.formats()));
+        app.handle_escape();
+        assert!(app.wizard.is_none() && app.view.mode == AppMode::Main);
+    }
+
+    #[test]
+    fn test_preview_waits_for_cursor_to_rest() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root.clone());
+        for name in ["a.rs", "b.rs"] {
+            std::fs::write(root.join(name), format!("// {}", name)).unwrap();
+            tree.add_node(root.join(name), false, &root);
+        }
+        let mut app = App::new(tree).with_preview_debounce(Duration::from_secs(60));
+        let position = |app: &App, name: &str| {
+            let tree_index = app.model.tree.path_to_index[&root.join(name)];
+            app.model.filtered_results.visible_items.iter().position(|&i| i == tree_index).unwrap()
+        };
+
+        // Passing over a file reads nothing
+        app.view.selected_index = position(&app, "a.rs");
+        app.load_hovered_preview();
+        assert_eq!(app.preview_cache.as_ref().unwrap().content, PreviewContent::Loading);
+        assert!(app.pending_preview.is_none());
+
+        // Once the cursor has rested, the read runs in the background
+        app.view.selected_index = position(&app, "b.rs");
+        app.load_hovered_preview();
+        app.preview_debounce = Duration::ZERO;
+        let started = Instant::now();
+        while app.preview_cache.as_ref().unwrap().content == PreviewContent::Loading {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            app.load_hovered_preview();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(app.preview_cache.as_ref().unwrap().lines(), ["// b.rs"]);
+
+        // Coming back to a file previewed before shows it right away
+        app.preview_debounce = Duration::from_secs(60);
+        app.view.selected_index = position(&app, "a.rs");
+        app.load_hovered_preview();
+        app.view.selected_index = position(&app, "b.rs");
+        app.load_hovered_preview();
+        assert_eq!(app.preview_cache.as_ref().unwrap().lines(), ["// b.rs"]);
+        assert!(app.pending_preview.is_none());
+    }
+
+    #[test]
+    fn test_run_startup() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("src"), true, &root).unwrap();
+        tree.add_node(root.join("tests"), true, &root).unwrap();
+        for path in ["src/main.rs", "tests/cli.rs"] {
+            let parent = root.join(path).parent().unwrap().to_path_buf();
+            let index = tree.add_node(root.join(path), false, &parent).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        let mut app = App::new(tree);
+
+        // The unknown and unmatched steps are reported; the others still run
+        let steps = ["include-all", "open preview", "exclude tests/**", "exclude docs/**", "search main"]
+            .map(str::to_string);
+        app.run_startup(&steps);
+        let included: Vec<_> = app.model.tree.get_all_included_files().iter().map(|node| node.name.clone()).collect();
+        assert_eq!(included, vec!["main.rs"]);
+        assert_eq!(app.model.query, "main");
+        assert_eq!(app.search_cursor, 4);
+        assert_eq!(app.deferred_output.len(), 2);
+        assert!(app.deferred_output[0].contains("Startup step 2 `open preview`: unknown step"));
+        assert!(app.deferred_output[1].contains("Startup step 4 `exclude docs/**`: matched nothing"));
+    }
+
+    #[test]
+    fn test_apply_recipe() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        std::fs::write(root.join("review.rules"), "exclude **\ninclude src/**\n").unwrap();
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("src"), true, &root).unwrap();
+        for path in ["src/main.rs", "src/lib.rs", "README.md"] {
+            let parent = root.join(path).parent().unwrap().to_path_buf();
+            let index = tree.add_node(root.join(path), false, &parent).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        let recipe = Recipe {
+            name: "review".to_string(),
+            rules: Some(PathBuf::from("review.rules")),
+            steps: vec!["search main".to_string(), "exclude-all".to_string(), "include-matches".to_string()],
+            ..Recipe::default()
+        };
+        let mut app = App::new(tree);
+        app.recipes = vec![recipe.clone()];
+
+        app.show_recipes();
+        assert_eq!(app.view.mode, AppMode::Recipes);
+        let picked = app.confirm_recipe().unwrap();
+        assert_eq!(app.view.mode, AppMode::Main);
+
+        assert!(app.apply_recipe(&picked).unwrap().is_empty());
+        let included: Vec<_> = app.model.tree.get_all_included_files().iter().map(|node| node.name.clone()).collect();
+        assert_eq!(included, vec!["main.rs"]);
+        assert!(app.rules.as_ref().is_some_and(|rules| rules.path.ends_with("review.rules")));
+
+        // A missing rules file stops the recipe before any step runs
+        let broken = Recipe { rules: Some(PathBuf::from("missing.rules")), ..recipe.clone() };
+        let error = app.apply_recipe(&broken).unwrap_err().to_string();
+        assert!(error.starts_with("recipe `review`: "), "{}", error);
+
+        // A base recipe starting from a saved profile runs first
+        let path = Profile::get_profile_path(&root, Some("docs")).unwrap();
+        let states = [("README.md".to_string(), SelectionState::Included)].into_iter().collect();
+        Profile { path, states, ..Profile::default() }.save().unwrap();
+        let base = Recipe { name: "docs".to_string(), profile: Some("docs".to_string()), ..Recipe::default() };
+        let extended = Recipe {
+            name: "docs-and-main".to_string(),
+            bases: vec![base],
+            steps: vec!["include src/main.rs".to_string()],
+            ..Recipe::default()
+        };
+        assert!(app.apply_recipe(&extended).unwrap().is_empty());
+        let included: Vec<_> = app.model.tree.get_all_included_files().iter().map(|node| node.name.clone()).collect();
+        assert_eq!(included, vec!["main.rs", "README.md"]);
+    }
+
+    #[test]
+    fn test_jump_by_name() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        for name in ["main.rs", "Migrate.sql", "lib.rs", "migrations.md"] {
+            let index = tree.add_node(root.join(name), false, &root).unwrap();
+            tree.nodes[index].is_text_file = true;
+        }
+        let mut app = App::new(tree);
+        let name_under_cursor =
+            |app: &App| app.model.tree.nodes[app.get_selected_tree_index().unwrap()].name.clone();
+        let listed = app.model.filtered_results.len();
+
+        app.start_or_repeat_jump();
+        for c in "mig".chars() {
+            app.add_jump_char(c);
+        }
+        let first = name_under_cursor(&app);
+        assert!(first.to_lowercase().starts_with("mig"));
+
+        // `f` goes to the next match and wraps around; the list is never filtered
+        app.start_or_repeat_jump();
+        let second = name_under_cursor(&app);
+        assert!(second.to_lowercase().starts_with("mig") && second != first);
+        app.start_or_repeat_jump();
+        assert_eq!(name_under_cursor(&app), first);
+        assert_eq!(app.model.filtered_results.len(), listed);
+        assert!(app.model.query.is_empty());
+
+        // No match leaves the cursor alone; Esc only leaves jump mode
+        app.add_jump_char('x');
+        assert_eq!(name_under_cursor(&app), first);
+        app.handle_escape();
+        assert!(app.jump_prefix.is_none() && !app.should_quit);
+    }
+
+    #[test]
+    fn test_view_root() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("src"), true, &root);
+        tree.add_node(root.join("README.md"), false, &root);
+        tree.add_node(root.join("src/main.rs"), false, &root.join("src"));
+        for node in &mut tree.nodes {
+            node.is_text_file = !node.is_directory;
+        }
+        tree.set_state(tree.root_index, SelectionState::Included);
+        let mut app = App::new(tree);
+        let src_index = app.model.tree.path_to_index[&root.join("src")];
+        app.view.selected_index = app.model.filtered_results.visible_items.iter().position(|&i| i == src_index).unwrap();
+
+        app.set_view_root();
+        assert_eq!(app.view_root_breadcrumb(), "project › src");
+        // Counts settle together, never a fresh total over stale inclusions
+        assert!(app.get_stats().calculating);
+        app.recompute_selection_totals();
+        assert_eq!((app.get_stats().included_files, app.get_stats().total_files), (1, 1));
+        assert_eq!(app.model.tree.get_all_included_files().len(), 1);
+        assert!(app.model.filtered_results.visible_items.iter().all(|&i| app.model.tree.nodes[i].path.starts_with(root.join("src"))));
+
+        // Selection outside the view is kept
+        app.reset_view_root();
+        assert_eq!(app.get_selected_tree_index(), Some(src_index));
+        assert_eq!(app.model.tree.get_all_included_files().len(), 2);
+    }
+
+    #[test]
+    fn test_filter_totals() {
+        let root = PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        for name in ["main.rs", "main_test.rs", "lib.rs"] {
+            let index = tree.add_node(root.join(name), false, &root).unwrap();
+            tree.nodes[index].is_text_file = true;
+            tree.nodes[index].size = Some(100);
+        }
+        let main_index = tree.path_to_index[&root.join("main.rs")];
+        tree.set_state(main_index, SelectionState::Included);
+        let mut app = App::new(tree);
+        assert_eq!(app.get_stats().format_filter(), None);
+
+        for c in "main".chars() {
+            app.add_search_char(c);
+        }
+        let stats = app.get_stats();
+        assert_eq!(
+            stats.filter,
+            Some(FilterTotals {
+                matched_files: 2,
+                included_files: 1,
+                included_size: 100,
+            })
+        );
+        assert_eq!(stats.format_filter().unwrap(), "match: 1/2 · 100 B");
     }
 }
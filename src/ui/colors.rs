@@ -1,16 +1,31 @@
-use ratatui::style::{Color, Style};
+use ratatui::style::{Color, Modifier, Style};
+use serde::{Deserialize, Serialize};
 use crate::directory::state::SelectionState;
 
+/// Palette for selection states. Colors only reinforce the state: every state-colored
+/// element also differs by glyph or text, so the interface reads the same without them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Default, // Green, red and yellow
+    Colorblind, // Blue, orange and purple, told apart with deuteranopia or protanopia
+}
+
 pub struct ColorScheme {
     pub included: Style,
     pub excluded: Style,
     pub partial: Style,
+    #[allow(dead_code)]
     pub selected: Style,
     pub search_match: Style,
     pub background: Style,
     pub border: Style,
+    pub focused_border: Style,
     pub text: Style,
     pub help_text: Style,
+    pub pending_change: Style, // Rows a pending bulk operation would change
+    pub warning: Style, // The status bar's token estimate once past the budget
 }
 
 impl Default for ColorScheme {
@@ -23,13 +38,29 @@ impl Default for ColorScheme {
             search_match: Style::default().fg(Color::Cyan),
             background: Style::default(), // Remove solid black background for transparency
             border: Style::default().fg(Color::White),
+            focused_border: Style::default().fg(Color::Cyan),
             text: Style::default().fg(Color::White),
             help_text: Style::default().fg(Color::Gray),
+            pending_change: Style::default().add_modifier(Modifier::UNDERLINED),
+            warning: Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
         }
     }
 }
 
 impl ColorScheme {
+    pub fn new(theme: Theme) -> Self {
+        match theme {
+            Theme::Default => Self::default(),
+            Theme::Colorblind => Self {
+                included: Style::default().fg(Color::Indexed(33)),  // Blue
+                excluded: Style::default().fg(Color::Indexed(208)), // Orange
+                partial: Style::default().fg(Color::Indexed(170)),  // Purple
+                warning: Style::default().fg(Color::Indexed(208)).add_modifier(Modifier::BOLD),
+                ..Self::default()
+            },
+        }
+    }
+
     pub fn get_state_style(&self, state: SelectionState) -> Style {
         match state {
             SelectionState::Included => self.included,
@@ -38,6 +69,7 @@ impl ColorScheme {
         }
     }
 
+    #[allow(dead_code)]
     pub fn get_item_style(&self, state: SelectionState, is_selected: bool) -> Style {
         if is_selected {
             // Override with selected style for clear visibility
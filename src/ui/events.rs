@@ -1,11 +1,17 @@
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind,
+};
 use std::time::Duration;
 use anyhow::Result;
+use crate::config::slots::SLOT_COUNT;
+use crate::model::BulkOperation;
 
 #[derive(Debug, Clone)]
 pub enum AppEvent {
     Key(KeyEvent),
+    Click(u16, u16), // Left mouse button press at (column, row)
     Tick,
+    #[allow(dead_code)]
     Quit,
 }
 
@@ -26,6 +32,13 @@ impl EventHandler {
                         Ok(None)
                     }
                 }
+                Event::Mouse(mouse_event) => {
+                    if mouse_event.kind == MouseEventKind::Down(MouseButton::Left) {
+                        Ok(Some(AppEvent::Click(mouse_event.column, mouse_event.row)))
+                    } else {
+                        Ok(None)
+                    }
+                }
                 _ => Ok(None),
             }
         } else {
@@ -34,11 +47,16 @@ impl EventHandler {
     }
 }
 
-pub fn handle_key_event(key_event: KeyEvent, mode: &crate::ui::app::AppMode) -> Option<AppAction> {
-    use crate::ui::app::AppMode;
+pub fn handle_key_event(key_event: KeyEvent, app: &crate::ui::app::App) -> Option<AppAction> {
+    use crate::ui::app::{AppMode, Focus};
+
+    // Ctrl+C quits from anywhere, since raw mode swallows the signal
+    if key_event.modifiers.contains(KeyModifiers::CONTROL) && key_event.code == KeyCode::Char('c') {
+        return Some(AppAction::Quit);
+    }
 
     // Handle file save mode differently
-    if *mode == AppMode::FileSave {
+    if app.view.mode == AppMode::FileSave {
         match key_event.code {
             KeyCode::Esc => return Some(AppAction::Escape),
             KeyCode::Enter => return Some(AppAction::FileSaveConfirm),
@@ -49,6 +67,212 @@ pub fn handle_key_event(key_event: KeyEvent, mode: &crate::ui::app::AppMode) ->
             _ => return None,
         }
     }
+
+    if app.view.mode == AppMode::FileDiff {
+        return match key_event.code {
+            KeyCode::Esc | KeyCode::Char('q') => Some(AppAction::Escape),
+            KeyCode::Up | KeyCode::Char('k') => Some(AppAction::ScrollUp),
+            KeyCode::Down | KeyCode::Char('j') => Some(AppAction::ScrollDown),
+            KeyCode::PageUp => Some(AppAction::ScrollPageUp),
+            KeyCode::PageDown => Some(AppAction::ScrollPageDown),
+            _ => None,
+        };
+    }
+
+    if app.view.mode == AppMode::Wizard {
+        return match key_event.code {
+            KeyCode::Esc => Some(AppAction::Escape),
+            KeyCode::Enter => Some(AppAction::WizardConfirm),
+            KeyCode::Up | KeyCode::Left | KeyCode::BackTab => Some(AppAction::WizardMove(-1)),
+            KeyCode::Down | KeyCode::Right | KeyCode::Tab => Some(AppAction::WizardMove(1)),
+            KeyCode::Backspace => Some(AppAction::WizardBackspace),
+            KeyCode::Char(c) if is_typing_modifier(key_event.modifiers) => Some(AppAction::WizardChar(c)),
+            _ => None,
+        };
+    }
+
+    if app.view.mode == AppMode::ChangeRoot {
+        return match key_event.code {
+            KeyCode::Esc => Some(AppAction::Escape),
+            KeyCode::Enter => Some(AppAction::RootConfirm),
+            KeyCode::Backspace => Some(AppAction::RootBackspace),
+            KeyCode::Char(c) if is_typing_modifier(key_event.modifiers) => Some(AppAction::RootChar(c)),
+            _ => None,
+        };
+    }
+
+    if app.view.mode == AppMode::Command {
+        return match key_event.code {
+            KeyCode::Esc => Some(AppAction::Escape),
+            KeyCode::Enter => Some(AppAction::CommandConfirm),
+            KeyCode::Backspace => Some(AppAction::CommandBackspace),
+            KeyCode::Char(c) if is_typing_modifier(key_event.modifiers) => Some(AppAction::CommandChar(c)),
+            _ => None,
+        };
+    }
+
+    if app.view.mode == AppMode::ExclusionReason {
+        return match key_event.code {
+            KeyCode::Esc => Some(AppAction::Escape),
+            KeyCode::Enter => Some(AppAction::ReasonConfirm),
+            KeyCode::Backspace => Some(AppAction::ReasonBackspace),
+            KeyCode::Char(c) if is_typing_modifier(key_event.modifiers) => Some(AppAction::ReasonChar(c)),
+            _ => None,
+        };
+    }
+
+    if app.view.mode == AppMode::ExportDiff {
+        return match key_event.code {
+            KeyCode::Char('r') => Some(AppAction::ReexportIfChanged),
+            KeyCode::Up => Some(AppAction::ScrollUp),
+            KeyCode::Down => Some(AppAction::ScrollDown),
+            _ => Some(AppAction::Escape),
+        };
+    }
+
+    if app.view.mode == AppMode::SmartInclude {
+        return match key_event.code {
+            KeyCode::Up | KeyCode::Char('k') => Some(AppAction::SmartIncludeMove(-1)),
+            KeyCode::Down | KeyCode::Char('j') => Some(AppAction::SmartIncludeMove(1)),
+            KeyCode::Char(' ') => Some(AppAction::SmartIncludeToggle),
+            KeyCode::Enter => Some(AppAction::SmartIncludeConfirm),
+            KeyCode::Esc => Some(AppAction::Escape),
+            _ => None,
+        };
+    }
+
+    if app.view.mode == AppMode::Suggestions {
+        return match key_event.code {
+            KeyCode::Up | KeyCode::Char('k') => Some(AppAction::SuggestionMove(-1)),
+            KeyCode::Down | KeyCode::Char('j') => Some(AppAction::SuggestionMove(1)),
+            KeyCode::Enter | KeyCode::Char('x') => Some(AppAction::ExcludeSuggestion(None)),
+            KeyCode::Char(c @ '1'..='9') => Some(AppAction::ExcludeSuggestion(Some(c as usize - '1' as usize))),
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('T') => Some(AppAction::Escape),
+            _ => None,
+        };
+    }
+
+    if app.view.mode == AppMode::Order {
+        let moving = app.export_order.as_ref().is_some_and(|order| order.moving);
+        return match key_event.code {
+            KeyCode::Up | KeyCode::Char('k') => Some(AppAction::OrderMove(-1)),
+            KeyCode::Down | KeyCode::Char('j') => Some(AppAction::OrderMove(1)),
+            KeyCode::Char('M') | KeyCode::Char('m') | KeyCode::Enter => Some(AppAction::ToggleOrderMoving),
+            KeyCode::Char('c') if !moving => Some(AppAction::ClearManualOrder),
+            KeyCode::Esc | KeyCode::Char('q') => Some(AppAction::Escape),
+            _ => None,
+        };
+    }
+
+    if app.view.mode == AppMode::Recipes {
+        return match key_event.code {
+            KeyCode::Up | KeyCode::Char('k') => Some(AppAction::RecipeMove(-1)),
+            KeyCode::Down | KeyCode::Char('j') => Some(AppAction::RecipeMove(1)),
+            KeyCode::Enter => Some(AppAction::RecipeConfirm),
+            KeyCode::Esc => Some(AppAction::Escape),
+            _ => None,
+        };
+    }
+
+    if app.view.mode == AppMode::WhatsNew {
+        return match key_event.code {
+            KeyCode::Up | KeyCode::Char('k') => Some(AppAction::ScrollUp),
+            KeyCode::Down | KeyCode::Char('j') => Some(AppAction::ScrollDown),
+            KeyCode::PageUp => Some(AppAction::ScrollPageUp),
+            KeyCode::PageDown => Some(AppAction::ScrollPageDown),
+            _ => Some(AppAction::Escape),
+        };
+    }
+
+    if app.view.mode == AppMode::SetConflict {
+        return match key_event.code {
+            KeyCode::Char('o') => Some(AppAction::SetConflictOverwrite),
+            KeyCode::Char('n') => Some(AppAction::SetConflictSaveAsNew),
+            KeyCode::Char('d') => Some(AppAction::SetConflictDiff),
+            KeyCode::Up | KeyCode::Char('k') => Some(AppAction::ScrollUp),
+            KeyCode::Down | KeyCode::Char('j') => Some(AppAction::ScrollDown),
+            KeyCode::PageUp => Some(AppAction::ScrollPageUp),
+            KeyCode::PageDown => Some(AppAction::ScrollPageDown),
+            KeyCode::Esc => Some(AppAction::Escape),
+            _ => None,
+        };
+    }
+
+    if app.view.mode == AppMode::Renames {
+        return match key_event.code {
+            KeyCode::Enter | KeyCode::Char('y') => Some(AppAction::RenamesConfirm),
+            KeyCode::Esc | KeyCode::Char('n') => Some(AppAction::Escape),
+            _ => None,
+        };
+    }
+
+    if app.view.mode == AppMode::Info {
+        return match key_event.code {
+            KeyCode::Esc | KeyCode::Char('i') => Some(AppAction::Escape),
+            _ => None,
+        };
+    }
+
+    // A bulk operation waits for confirmation; the cursor can still move to look it over
+    if app.bulk_plan.is_some() && app.view.focus == Focus::List {
+        match key_event.code {
+            KeyCode::Enter | KeyCode::Char('y') => return Some(AppAction::BulkConfirm),
+            KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {}
+            KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home | KeyCode::End => {}
+            _ => return Some(AppAction::Escape),
+        }
+    }
+
+    // A visual range: the cursor extends it, Enter toggles it, `v` or Esc drops it
+    if app.visual_anchor.is_some() && app.view.focus == Focus::List {
+        match key_event.code {
+            KeyCode::Enter => return Some(AppAction::ApplyVisual),
+            KeyCode::Esc | KeyCode::Char('v') => return Some(AppAction::ToggleVisual),
+            _ => {}
+        }
+    }
+
+    // Jumping by name: letters extend the prefix, `f` jumps again (or starts the prefix),
+    // other keys work as usual
+    if let Some(prefix) = &app.jump_prefix
+        && app.view.focus == Focus::List
+    {
+        match key_event.code {
+            KeyCode::Esc => return Some(AppAction::Escape),
+            KeyCode::Backspace => return Some(AppAction::JumpBackspace),
+            KeyCode::Char('f') if key_event.modifiers == KeyModifiers::NONE && !prefix.is_empty() => {
+                return Some(AppAction::Jump);
+            }
+            KeyCode::Char(c) if is_typing_modifier(key_event.modifiers) => return Some(AppAction::JumpChar(c)),
+            _ => {}
+        }
+    }
+
+    // While the search bar has focus only query editing keys apply
+    if !app.legacy_typeahead && app.view.focus == Focus::Search {
+        return match key_event.code {
+            KeyCode::Esc | KeyCode::Enter => Some(AppAction::FocusList),
+            KeyCode::Backspace => Some(AppAction::SearchBackspace),
+            KeyCode::Delete => Some(AppAction::SearchDelete),
+            KeyCode::Left => Some(AppAction::SearchCursorMove(-1)),
+            KeyCode::Right => Some(AppAction::SearchCursorMove(1)),
+            KeyCode::Home => Some(AppAction::SearchCursorHome),
+            KeyCode::End => Some(AppAction::SearchCursorEnd),
+            KeyCode::F(number) if (1..=SLOT_COUNT).contains(&number) => Some(AppAction::ExportSlot(number)),
+            KeyCode::Char(c) if key_event.modifiers.contains(KeyModifiers::CONTROL) => match c {
+                'r' => Some(AppAction::CycleMatchMode),
+                'a' => Some(AppAction::ToggleAll),
+                'n' => Some(AppAction::SelectNone),
+                't' => Some(AppAction::InvertSelection),
+                _ => None,
+            },
+            KeyCode::Char(c) if is_typing_modifier(key_event.modifiers) => {
+                Some(AppAction::SearchChar(c))
+            }
+            _ => None,
+        };
+    }
+
     // Check for Ctrl combinations first
     if key_event.modifiers.contains(KeyModifiers::CONTROL) {
         match key_event.code {
@@ -56,37 +280,136 @@ pub fn handle_key_event(key_event: KeyEvent, mode: &crate::ui::app::AppMode) ->
             KeyCode::Char('h') => return Some(AppAction::ShowHelp),  // Ctrl+H for help
             KeyCode::Char('j') => return Some(AppAction::MoveDown),  // Ctrl+J for moving down
             KeyCode::Char('k') => return Some(AppAction::MoveUp),  // Ctrl+K for moving up
+            KeyCode::Char('l') => return Some(AppAction::ReloadRules),  // Ctrl+L for reloading rules
+            KeyCode::Char('p') => return Some(AppAction::TogglePreview),  // Ctrl+P for the preview pane
+            KeyCode::Char('b') => return Some(AppAction::ToggleStatusBar),  // Ctrl+B for hiding the status bar
+            KeyCode::Char('Y') => return Some(AppAction::CopyIncludedPaths),  // Ctrl+Shift+Y
+            KeyCode::Char('y') if key_event.modifiers.contains(KeyModifiers::SHIFT) => {
+                return Some(AppAction::CopyIncludedPaths);
+            }
+            KeyCode::Char('y') => return Some(AppAction::CopyExport),  // Ctrl+Y copies the export and keeps going
+            KeyCode::Char('s') => return Some(AppAction::SaveProfile),  // Ctrl+S saves the selection to the profile
+            KeyCode::Char('r') => return Some(AppAction::CycleMatchMode),  // Ctrl+R: fuzzy, substring, regex
+            KeyCode::Char('a') => return Some(AppAction::ToggleAll),  // Ctrl+A includes (or excludes) every listed file
+            KeyCode::Char('n') => return Some(AppAction::SelectNone),  // Ctrl+N excludes them
+            KeyCode::Char('t') => return Some(AppAction::InvertSelection),  // Ctrl+T flips them
+            // Ctrl+Shift+D, reported as plain Ctrl+D by terminals that can't tell them apart
+            KeyCode::Char('d') | KeyCode::Char('D') => return Some(AppAction::ShowExportDiff),
             _ => return None,  // Ignore other Ctrl combinations
         }
     }
 
     // Handle regular keys (no modifiers)
+    let preview_scrolls = app.view.mode == AppMode::Preview && key_event.modifiers.contains(KeyModifiers::SHIFT);
     match key_event.code {
         KeyCode::Esc => Some(AppAction::Escape),
+        KeyCode::Enter if key_event.modifiers.contains(KeyModifiers::ALT) => Some(AppAction::BudgetFill),
         KeyCode::Enter => Some(AppAction::ToggleSelection),
-        KeyCode::Backspace => Some(AppAction::SearchBackspace),
+        KeyCode::F(number) if (1..=SLOT_COUNT).contains(&number) => Some(AppAction::ExportSlot(number)),
+        KeyCode::Backspace if app.legacy_typeahead => Some(AppAction::SearchBackspace),
+
+        // Shift scrolls the preview pane instead of moving the cursor
+        KeyCode::Up if preview_scrolls => Some(AppAction::ScrollUp),
+        KeyCode::Down if preview_scrolls => Some(AppAction::ScrollDown),
+        KeyCode::PageUp if preview_scrolls => Some(AppAction::ScrollPageUp),
+        KeyCode::PageDown if preview_scrolls => Some(AppAction::ScrollPageDown),
 
         // Arrow keys for navigation
         KeyCode::Up => Some(AppAction::MoveUp),
         KeyCode::Down => Some(AppAction::MoveDown),
+        KeyCode::Left if app.tree_view => Some(AppAction::CollapseDirectory),
+        KeyCode::Right if app.tree_view => Some(AppAction::ExpandDirectory),
         KeyCode::Left => Some(AppAction::MoveUp),
         KeyCode::Right => Some(AppAction::MoveDown),
+        KeyCode::Tab => Some(AppAction::ToggleTreeView),
         KeyCode::PageUp => Some(AppAction::PageUp),
         KeyCode::PageDown => Some(AppAction::PageDown),
         KeyCode::Home => Some(AppAction::MoveToTop),
         KeyCode::End => Some(AppAction::MoveToBottom),
 
         // Characters type into search (only if no modifiers)
-        KeyCode::Char(c) if key_event.modifiers == KeyModifiers::NONE => Some(AppAction::SearchChar(c)),
+        KeyCode::Char(c) if app.legacy_typeahead && is_typing_modifier(key_event.modifiers) => {
+            Some(AppAction::SearchChar(c))
+        }
+
+        // Single-letter bindings, active while the list has focus
+        KeyCode::Char('/') => Some(AppAction::FocusSearch),
+        KeyCode::Char(' ') if key_event.modifiers.contains(KeyModifiers::SHIFT) => Some(AppAction::ToggleVisual),
+        KeyCode::Char(' ') if app.tree_view => Some(AppAction::ToggleFold),
+        KeyCode::Char('v') => Some(AppAction::ToggleVisual),
+        KeyCode::Char('f') => Some(AppAction::Jump),
+        KeyCode::Char('q') => Some(AppAction::Quit),
+        KeyCode::Char('c') => Some(AppAction::ToggleComparePin),
+        KeyCode::Char('s') => Some(AppAction::SmartInclude),
+        KeyCode::Char('E') => Some(AppAction::SelectEssentials),
+        KeyCode::Char('y') => Some(AppAction::CopyPath),
+        KeyCode::Char('Y') => Some(AppAction::CopyAbsolutePath),
+        KeyCode::Char('i') => Some(AppAction::ShowInfo),
+        KeyCode::Char('l') => Some(AppAction::ToggleLock),
+        KeyCode::Char('#') => Some(AppAction::EditExclusionReason),
+        KeyCode::Char('=') => Some(AppAction::MatchSiblings),
+        KeyCode::Char('+') => Some(AppAction::PlanBulk(BulkOperation::IncludeMatches)),
+        KeyCode::Char('-') => Some(AppAction::PlanBulk(BulkOperation::ExcludeMatches)),
+        KeyCode::Char('*') => Some(AppAction::PlanBulk(BulkOperation::InvertMatches)),
+        KeyCode::Char('X') => Some(AppAction::PlanExcludeExtension),
+        KeyCode::Char('D') => Some(AppAction::MarkForDiff),
+        KeyCode::Char('g') => Some(AppAction::ToggleChangedFilter),
+        KeyCode::Char('O') => Some(AppAction::ChangeRoot),
+        KeyCode::Char(':') => Some(AppAction::StartCommand),
+        KeyCode::Char('S') => Some(AppAction::SaveSelectionSet),
+        KeyCode::Char('R') => Some(AppAction::ShowRecipes),
+        KeyCode::Char('T') => Some(AppAction::ShowSuggestions),
+        KeyCode::Char('M') => Some(AppAction::ShowExportOrder),
+        KeyCode::Char('F') => Some(AppAction::CycleFormat),
+        KeyCode::Char('>') => Some(AppAction::SetViewRoot),
+        KeyCode::Char('<') => Some(AppAction::ResetViewRoot),
 
         _ => None,
     }
 }
 
+fn is_typing_modifier(modifiers: KeyModifiers) -> bool {
+    modifiers == KeyModifiers::NONE || modifiers == KeyModifiers::SHIFT
+}
+
 #[derive(Debug, Clone)]
 pub enum AppAction {
     Escape,
+    Quit,
     ToggleSelection,
+    BudgetFill,
+    ShowSuggestions,
+    TogglePreview,
+    ToggleTreeView,
+    CollapseDirectory,
+    ExpandDirectory,
+    ToggleFold,
+    CycleMatchMode,
+    ToggleAll,
+    ToggleVisual,
+    ApplyVisual,
+    SelectNone,
+    InvertSelection,
+    SuggestionMove(isize),
+    ExcludeSuggestion(Option<usize>), // Position in the list, or the highlighted one
+    ShowExportOrder,
+    OrderMove(isize),
+    ToggleOrderMoving,
+    ClearManualOrder,
+    CycleFormat,
+    CopyExport,
+    SaveProfile,
+    ToggleLock,
+    MatchSiblings,
+    PlanBulk(BulkOperation),
+    PlanExcludeExtension,
+    BulkConfirm,
+    RenamesConfirm,
+    SetConflictOverwrite,
+    SetConflictSaveAsNew,
+    SetConflictDiff,
+    SetViewRoot,
+    ResetViewRoot,
     MoveUp,
     MoveDown,
     PageUp,
@@ -97,9 +420,59 @@ pub enum AppAction {
     ShowHelp,
     SearchChar(char),
     SearchBackspace,
+    SearchDelete,
+    SearchCursorMove(i32),
+    SearchCursorHome,
+    SearchCursorEnd,
+    FocusSearch,
+    FocusList,
+    Jump,
+    JumpChar(char),
+    JumpBackspace,
     FileSaveChar(char),
     FileSaveBackspace,
     FileSaveConfirm,
+    ExportSlot(u8), // F1-F4
+    ShowExportDiff,
+    ShowInfo,
+    EditExclusionReason,
+    ReasonChar(char),
+    ReasonBackspace,
+    ReasonConfirm,
+    ReexportIfChanged,
+    ScrollUp,
+    ScrollDown,
+    ScrollPageUp,
+    ScrollPageDown,
+    MarkForDiff,
+    ToggleChangedFilter,
+    ChangeRoot,
+    RootChar(char),
+    RootBackspace,
+    RootConfirm,
+    StartCommand,
+    CommandChar(char),
+    CommandBackspace,
+    CommandConfirm,
+    WizardMove(isize),
+    WizardChar(char),
+    WizardBackspace,
+    WizardConfirm,
+    ToggleComparePin,
+    ReloadRules,
+    SaveSelectionSet,
+    ToggleStatusBar,
+    CopyPath,
+    CopyAbsolutePath,
+    CopyIncludedPaths,
+    SmartInclude,
+    SelectEssentials,
+    SmartIncludeMove(isize),
+    SmartIncludeToggle,
+    SmartIncludeConfirm,
+    ShowRecipes,
+    RecipeMove(isize),
+    RecipeConfirm,
 }
 
 impl Default for EventHandler {
@@ -107,3 +480,149 @@ impl Default for EventHandler {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directory::tree::DirectoryTree;
+    use crate::ui::app::{App, AppMode, ExportOrder};
+    use tempfile::TempDir;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_focus_routes_letters() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut app = App::new(DirectoryTree::new(temp_dir.path().to_path_buf()));
+
+        // List focus: letters are bindings, not search input
+        assert!(matches!(handle_key_event(key(KeyCode::Char('/')), &app), Some(AppAction::FocusSearch)));
+        assert!(handle_key_event(key(KeyCode::Char('x')), &app).is_none());
+
+        // Search focus: letters edit the query, Enter/Esc hand focus back
+        app.focus_search();
+        assert!(matches!(handle_key_event(key(KeyCode::Char('x')), &app), Some(AppAction::SearchChar('x'))));
+        assert!(matches!(handle_key_event(key(KeyCode::Enter), &app), Some(AppAction::FocusList)));
+        assert!(matches!(handle_key_event(key(KeyCode::Esc), &app), Some(AppAction::FocusList)));
+        assert!(handle_key_event(key(KeyCode::Down), &app).is_none());
+    }
+
+    #[test]
+    fn test_legacy_typeahead() {
+        let temp_dir = TempDir::new().unwrap();
+        let app = App::new(DirectoryTree::new(temp_dir.path().to_path_buf())).with_legacy_typeahead(true);
+
+        assert!(matches!(handle_key_event(key(KeyCode::Char('x')), &app), Some(AppAction::SearchChar('x'))));
+        assert!(matches!(handle_key_event(key(KeyCode::Enter), &app), Some(AppAction::ToggleSelection)));
+        assert!(matches!(handle_key_event(key(KeyCode::Down), &app), Some(AppAction::MoveDown)));
+    }
+
+    #[test]
+    fn test_copy_path_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let app = App::new(DirectoryTree::new(temp_dir.path().to_path_buf()));
+        let ctrl_shift = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL | KeyModifiers::SHIFT);
+
+        assert!(matches!(handle_key_event(key(KeyCode::Char('y')), &app), Some(AppAction::CopyPath)));
+        assert!(matches!(handle_key_event(key(KeyCode::Char('Y')), &app), Some(AppAction::CopyAbsolutePath)));
+        assert!(matches!(handle_key_event(ctrl_shift('Y'), &app), Some(AppAction::CopyIncludedPaths)));
+        assert!(matches!(handle_key_event(ctrl_shift('y'), &app), Some(AppAction::CopyIncludedPaths)));
+        let ctrl_y = KeyEvent::new(KeyCode::Char('y'), KeyModifiers::CONTROL);
+        assert!(matches!(handle_key_event(ctrl_y, &app), Some(AppAction::CopyExport)));
+        assert!(matches!(handle_key_event(key(KeyCode::Char(':')), &app), Some(AppAction::StartCommand)));
+        let ctrl_s = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        assert!(matches!(handle_key_event(ctrl_s, &app), Some(AppAction::SaveProfile)));
+        assert!(matches!(handle_key_event(key(KeyCode::Char('F')), &app), Some(AppAction::CycleFormat)));
+    }
+
+    #[test]
+    fn test_match_mode_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut app = App::new(DirectoryTree::new(temp_dir.path().to_path_buf()));
+        let ctrl_r = KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL);
+
+        // Ctrl+R works from the list and while typing the query
+        assert!(matches!(handle_key_event(ctrl_r, &app), Some(AppAction::CycleMatchMode)));
+        app.focus_search();
+        assert!(matches!(handle_key_event(ctrl_r, &app), Some(AppAction::CycleMatchMode)));
+        assert!(matches!(handle_key_event(key(KeyCode::Char('r')), &app), Some(AppAction::SearchChar('r'))));
+    }
+
+    #[test]
+    fn test_bulk_selection_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut app = App::new(DirectoryTree::new(temp_dir.path().to_path_buf()));
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+
+        // From the list, and straight from the search bar after filtering
+        for _ in 0..2 {
+            assert!(matches!(handle_key_event(ctrl('a'), &app), Some(AppAction::ToggleAll)));
+            assert!(matches!(handle_key_event(ctrl('n'), &app), Some(AppAction::SelectNone)));
+            assert!(matches!(handle_key_event(ctrl('t'), &app), Some(AppAction::InvertSelection)));
+            app.focus_search();
+        }
+
+        // `v` starts a range, which then takes Enter, `v` and Esc
+        app.focus_list();
+        assert!(matches!(handle_key_event(key(KeyCode::Char('v')), &app), Some(AppAction::ToggleVisual)));
+        let shift_space = KeyEvent::new(KeyCode::Char(' '), KeyModifiers::SHIFT);
+        assert!(matches!(handle_key_event(shift_space, &app), Some(AppAction::ToggleVisual)));
+        app.visual_anchor = Some(app.model.tree.root_index);
+        assert!(matches!(handle_key_event(key(KeyCode::Enter), &app), Some(AppAction::ApplyVisual)));
+        assert!(matches!(handle_key_event(key(KeyCode::Esc), &app), Some(AppAction::ToggleVisual)));
+        assert!(matches!(handle_key_event(key(KeyCode::Down), &app), Some(AppAction::MoveDown)));
+    }
+
+    #[test]
+    fn test_preview_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut app = App::new(DirectoryTree::new(temp_dir.path().to_path_buf()));
+        let ctrl_p = KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL);
+        let shift = |code| KeyEvent::new(code, KeyModifiers::SHIFT);
+
+        assert!(matches!(handle_key_event(ctrl_p, &app), Some(AppAction::TogglePreview)));
+        assert!(matches!(handle_key_event(shift(KeyCode::Down), &app), Some(AppAction::MoveDown)));
+
+        // With the pane open the list keeps its keys, and Shift scrolls the pane
+        app.toggle_preview();
+        assert!(matches!(handle_key_event(key(KeyCode::Down), &app), Some(AppAction::MoveDown)));
+        assert!(matches!(handle_key_event(key(KeyCode::Enter), &app), Some(AppAction::ToggleSelection)));
+        assert!(matches!(handle_key_event(shift(KeyCode::Down), &app), Some(AppAction::ScrollDown)));
+        assert!(matches!(handle_key_event(shift(KeyCode::PageUp), &app), Some(AppAction::ScrollPageUp)));
+        assert!(matches!(handle_key_event(ctrl_p, &app), Some(AppAction::TogglePreview)));
+    }
+
+    #[test]
+    fn test_export_order_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut app = App::new(DirectoryTree::new(temp_dir.path().to_path_buf()));
+        assert!(matches!(handle_key_event(key(KeyCode::Char('M')), &app), Some(AppAction::ShowExportOrder)));
+
+        app.view.mode = AppMode::Order;
+        app.export_order = Some(ExportOrder { selected: 0, moving: false });
+        assert!(matches!(handle_key_event(key(KeyCode::Up), &app), Some(AppAction::OrderMove(-1))));
+        assert!(matches!(handle_key_event(key(KeyCode::Char('j')), &app), Some(AppAction::OrderMove(1))));
+        assert!(matches!(handle_key_event(key(KeyCode::Char('M')), &app), Some(AppAction::ToggleOrderMoving)));
+        assert!(matches!(handle_key_event(key(KeyCode::Char('c')), &app), Some(AppAction::ClearManualOrder)));
+        app.toggle_order_moving();
+        assert!(handle_key_event(key(KeyCode::Char('c')), &app).is_none());
+        assert!(matches!(handle_key_event(key(KeyCode::Esc), &app), Some(AppAction::Escape)));
+    }
+
+    #[test]
+    fn test_quit_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut app = App::new(DirectoryTree::new(temp_dir.path().to_path_buf()));
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+
+        assert!(matches!(handle_key_event(key(KeyCode::Char('q')), &app), Some(AppAction::Quit)));
+        assert!(matches!(handle_key_event(ctrl_c, &app), Some(AppAction::Quit)));
+
+        // `q` is just a letter while typing a query, Ctrl+C still quits
+        app.focus_search();
+        assert!(matches!(handle_key_event(key(KeyCode::Char('q')), &app), Some(AppAction::SearchChar('q'))));
+        assert!(matches!(handle_key_event(ctrl_c, &app), Some(AppAction::Quit)));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::directory::tree::DirectoryTree;
+use crate::fuzzy::filter::get_node_display_path;
+use similar::{ChangeTag, TextDiff};
+
+/// Files larger than this are refused: diffing is quadratic in the worst case
+const DIFF_MAX_BYTES: usize = 512 * 1024;
+/// Unchanged lines kept around each change
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffLineKind {
+    Header,
+    Hunk,
+    Added,
+    Removed,
+    Context,
+}
+
+/// A unified diff between two files in the tree, for the `D` popup
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+    pub left: usize,
+    pub right: usize,
+    pub similarity: f32, // 0.0 to 1.0, by matching lines
+    pub lines: Vec<(DiffLineKind, String)>,
+}
+
+impl FileDiff {
+    /// Diff two text files, or explain why they can't be diffed
+    pub fn compute(tree: &DirectoryTree, left: usize, right: usize) -> Result<Self, String> {
+        let old = read_for_diff(tree, left)?;
+        let new = read_for_diff(tree, right)?;
+        let diff = TextDiff::from_lines(&old, &new);
+
+        let mut lines = vec![
+            (DiffLineKind::Header, format!("--- {}", get_node_display_path(tree, left))),
+            (DiffLineKind::Header, format!("+++ {}", get_node_display_path(tree, right))),
+        ];
+        for hunk in diff.unified_diff().context_radius(CONTEXT_LINES).iter_hunks() {
+            lines.push((DiffLineKind::Hunk, hunk.header().to_string()));
+            for change in hunk.iter_changes() {
+                let (kind, sign) = match change.tag() {
+                    ChangeTag::Delete => (DiffLineKind::Removed, '-'),
+                    ChangeTag::Insert => (DiffLineKind::Added, '+'),
+                    ChangeTag::Equal => (DiffLineKind::Context, ' '),
+                };
+                lines.push((kind, format!("{}{}", sign, change.value().trim_end_matches(['\n', '\r']))));
+            }
+        }
+
+        Ok(Self {
+            left,
+            right,
+            similarity: diff.ratio(),
+            lines,
+        })
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.lines.len() == 2 // Only the headers
+    }
+}
+
+fn read_for_diff(tree: &DirectoryTree, index: usize) -> Result<String, String> {
+    let node = tree.get_node(index).ok_or("The marked file is gone")?;
+    let name = get_node_display_path(tree, index);
+    if node.is_directory {
+        return Err(format!("{} is a directory", name));
+    }
+    if !node.is_text_file {
+        return Err(format!("{} is binary, only text files can be diffed", name));
+    }
+
+    // Refused before reading: archive entries go by the size listed, files by their size now
+    let size = std::fs::metadata(&node.path).map(|metadata| metadata.len()).ok().or(node.size);
+    if size.is_some_and(|size| size > DIFF_MAX_BYTES as u64) {
+        return Err(format!("{} is too large to diff", name));
+    }
+    let bytes = tree.read_file(&node.path).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    if bytes.len() > DIFF_MAX_BYTES {
+        return Err(format!("{} is too large to diff", name));
+    }
+    String::from_utf8(bytes).map_err(|_| format!("{} is binary, only text files can be diffed", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_file_diff() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join("a.rs"), "fn main() {\n    one();\n}\n").unwrap();
+        fs::write(root.join("b.rs"), "fn main() {\n    two();\n}\n").unwrap();
+        fs::write(root.join("blob"), [0xff, 0xfe]).unwrap();
+
+        let mut tree = DirectoryTree::new(root.to_path_buf());
+        let [a, b, blob] = ["a.rs", "b.rs", "blob"].map(|name| tree.add_node(root.join(name), false, root).unwrap());
+        for index in [a, b, blob] {
+            tree.nodes[index].is_text_file = true; // Content sniffing is left to the diff
+        }
+
+        let diff = FileDiff::compute(&tree, a, b).unwrap();
+        assert_eq!(diff.lines[0], (DiffLineKind::Header, "--- a.rs".to_string()));
+        assert!(diff.lines.contains(&(DiffLineKind::Removed, "-    one();".to_string())));
+        assert!(diff.lines.contains(&(DiffLineKind::Added, "+    two();".to_string())));
+        assert!(diff.lines.contains(&(DiffLineKind::Context, " fn main() {".to_string())));
+        assert!((diff.similarity - 2.0 / 3.0).abs() < 0.01);
+
+        assert!(FileDiff::compute(&tree, a, a).unwrap().is_identical());
+        assert!(FileDiff::compute(&tree, a, blob).unwrap_err().contains("binary"));
+
+        // Too large is known from the size, without reading
+        let unread = tree.add_node(root.join("unread.rs"), false, root).unwrap();
+        tree.nodes[unread].is_text_file = true;
+        tree.nodes[unread].size = Some(DIFF_MAX_BYTES as u64 + 1);
+        assert_eq!(FileDiff::compute(&tree, a, unread).unwrap_err(), "unread.rs is too large to diff");
+    }
+}
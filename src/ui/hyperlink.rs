@@ -0,0 +1,133 @@
+use crossterm::cursor::{MoveTo, RestorePosition, SavePosition};
+use crossterm::queue;
+use crossterm::style::{Attribute, Print, SetAttribute, SetBackgroundColor, SetForegroundColor};
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::Modifier;
+use std::io::{self, Write};
+use std::path::Path;
+use unicode_width::UnicodeWidthStr;
+
+/// Cells of the last frame that should link to a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hyperlink {
+    pub area: Rect, // One row
+    pub url: String,
+}
+
+/// Whether the terminal is known to turn OSC 8 sequences into links. `FORCE_HYPERLINK=1`
+/// or `0` overrides the guess, as in other tools that emit them.
+pub fn supports_hyperlinks() -> bool {
+    let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    if let Some(force) = var("FORCE_HYPERLINK") {
+        return force != "0";
+    }
+    if var("CI").is_some() || var("TERM").is_some_and(|term| term == "dumb") {
+        return false;
+    }
+    if var("WT_SESSION").is_some() || var("KITTY_WINDOW_ID").is_some() || var("WEZTERM_EXECUTABLE").is_some() {
+        return true;
+    }
+    if var("VTE_VERSION").and_then(|version| version.parse::<u32>().ok()).is_some_and(|version| version >= 5000) {
+        return true;
+    }
+    let known_program = var("TERM_PROGRAM")
+        .is_some_and(|program| ["iTerm.app", "WezTerm", "vscode", "ghostty", "Hyper"].contains(&program.as_str()));
+    let known_term = var("TERM").is_some_and(|term| ["xterm-kitty", "alacritty", "xterm-ghostty", "foot"].contains(&term.as_str()));
+    known_program || known_term
+}
+
+/// `file://` URL of an absolute path, percent-encoding everything but unreserved characters
+pub fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut url = String::from("file://");
+    if !path.starts_with('/') {
+        url.push('/'); // Windows drive letters
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => url.push(byte as char),
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    url
+}
+
+/// `text` wrapped in OSC 8 sequences; the escapes take up no columns
+pub fn hyperlink(text: &str, url: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+/// Reprint the linked cells of a drawn frame inside OSC 8 sequences. The cells keep their
+/// symbols and styles, so the screen (and ratatui's idea of it) doesn't change; only the
+/// terminal learns that those cells are links.
+pub fn write_hyperlinks(out: &mut impl Write, buffer: &Buffer, links: &[Hyperlink]) -> io::Result<()> {
+    if links.is_empty() {
+        return Ok(());
+    }
+    queue!(out, SavePosition)?;
+    for link in links {
+        let area = link.area.intersection(buffer.area);
+        if area.width == 0 || area.height == 0 {
+            continue;
+        }
+        queue!(out, MoveTo(area.x, area.y), Print(format!("\x1b]8;;{}\x1b\\", link.url)))?;
+        let mut x = area.left();
+        while x < area.right() {
+            let cell = buffer.get(x, area.y);
+            queue!(
+                out,
+                SetAttribute(Attribute::Reset),
+                SetForegroundColor(cell.fg.into()),
+                SetBackgroundColor(cell.bg.into())
+            )?;
+            for (modifier, attribute) in [
+                (Modifier::BOLD, Attribute::Bold),
+                (Modifier::DIM, Attribute::Dim),
+                (Modifier::ITALIC, Attribute::Italic),
+                (Modifier::UNDERLINED, Attribute::Underlined),
+                (Modifier::REVERSED, Attribute::Reverse),
+            ] {
+                if cell.modifier.contains(modifier) {
+                    queue!(out, SetAttribute(attribute))?;
+                }
+            }
+            queue!(out, Print(&cell.symbol))?;
+            // Wide characters cover the cell after them
+            x += cell.symbol.width().max(1) as u16;
+        }
+        queue!(out, Print("\x1b]8;;\x1b\\"), SetAttribute(Attribute::Reset))?;
+    }
+    queue!(out, RestorePosition)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::{Color, Style};
+
+    #[test]
+    fn test_file_url() {
+        assert_eq!(file_url(Path::new("/home/me/src/main.rs")), "file:///home/me/src/main.rs");
+        assert_eq!(file_url(Path::new("/tmp/a b#1.rs")), "file:///tmp/a%20b%231.rs");
+        assert_eq!(file_url(Path::new("/tmp/é")), "file:///tmp/%C3%A9");
+    }
+
+    #[test]
+    fn test_hyperlinks_take_no_columns() {
+        let linked = hyperlink("main.rs", "file:///main.rs");
+        assert_eq!(linked, "\x1b]8;;file:///main.rs\x1b\\main.rs\x1b]8;;\x1b\\");
+
+        // Reprinted cells are the frame's own, between the opening and closing sequence
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 10, 1));
+        buffer.set_string(0, 0, "a.rs 📁", Style::default().fg(Color::Green));
+        let links = [Hyperlink { area: Rect::new(0, 0, 7, 1), url: "file:///a.rs".to_string() }];
+        let mut out = Vec::new();
+        write_hyperlinks(&mut out, &buffer, &links).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\x1b]8;;file:///a.rs\x1b\\"));
+        assert_eq!(out.matches("📁").count(), 1);
+        assert!(out.find("📁").unwrap() < out.rfind("\x1b]8;;\x1b\\").unwrap());
+    }
+}
@@ -0,0 +1,134 @@
+use unicode_width::UnicodeWidthStr;
+
+/// Glyphs used for selection state, node type and console messages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IconSet {
+    pub included: &'static str,
+    pub excluded: &'static str,
+    pub partial: &'static str,
+    pub directory: &'static str,
+    pub file: &'static str,
+    pub cursor: &'static str,
+    pub locked: &'static str,
+    pub sticky: &'static str,
+    pub collapsed: &'static str, // Tree view directories, folded and not
+    pub expanded: &'static str,
+    pub transition: &'static str, // Between an old and a new state in messages
+    pub success: &'static str,
+    pub warning: &'static str,
+    ascii: bool,
+}
+
+pub const UNICODE_ICONS: IconSet = IconSet {
+    included: "✓",
+    excluded: "✗",
+    partial: "◐",
+    directory: "📁",
+    file: "📄",
+    cursor: "▶",
+    locked: "🔒",
+    sticky: "∞",
+    collapsed: "▸",
+    expanded: "▾",
+    transition: "→",
+    success: "✓",
+    warning: "⚠",
+    ascii: false,
+};
+
+/// For terminals and log collectors that mangle anything outside ASCII
+pub const ASCII_ICONS: IconSet = IconSet {
+    included: "[x]",
+    excluded: "[ ]",
+    partial: "[~]",
+    directory: "D",
+    file: "F",
+    cursor: ">",
+    locked: "[L]",
+    sticky: "[+]",
+    collapsed: "+",
+    expanded: "-",
+    transition: "->",
+    success: "OK:",
+    warning: "WARNING:",
+    ascii: true,
+};
+
+impl IconSet {
+    pub fn new(ascii: bool) -> &'static IconSet {
+        if ascii { &ASCII_ICONS } else { &UNICODE_ICONS }
+    }
+
+    /// Display widths of the list's cursor, state and type columns: each column's widest
+    /// glyph and a space, so a row's path starts in the same place whatever the row shows
+    pub fn columns(&self) -> [usize; 3] {
+        let widest = |glyphs: &[&str]| glyphs.iter().map(|glyph| glyph.width()).max().unwrap_or(0) + 1;
+        [
+            widest(&[self.cursor]),
+            widest(&[self.included, self.excluded, self.partial]),
+            widest(&[self.directory, self.file]),
+        ]
+    }
+
+    /// Free text (help lines, key hints, status messages) with unicode decoration
+    /// replaced by ASCII equivalents when this is the ASCII set
+    pub fn text(&self, text: &str) -> String {
+        if !self.ascii || text.is_ascii() {
+            return text.to_string();
+        }
+
+        let mut ascii = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '✓' => ascii.push_str(self.included),
+                '✗' => ascii.push_str(self.excluded),
+                '◐' => ascii.push_str(self.partial),
+                '📁' => ascii.push_str(self.directory),
+                '📄' => ascii.push_str(self.file),
+                '▶' => ascii.push_str(self.cursor),
+                '🔒' => ascii.push_str(self.locked),
+                '∞' => ascii.push_str(self.sticky),
+                '▸' => ascii.push_str(self.collapsed),
+                '▾' => ascii.push_str(self.expanded),
+                '⚠' => ascii.push_str(self.warning),
+                '↑' => ascii.push_str("Up"),
+                '↓' => ascii.push_str("Down"),
+                '←' => ascii.push_str("Left"),
+                '→' => ascii.push_str("Right"),
+                '↔' => ascii.push_str("<->"),
+                '…' => ascii.push_str("..."),
+                '›' => ascii.push('>'),
+                '·' => ascii.push('-'),
+                '×' => ascii.push('x'),
+                '≈' => ascii.push('~'),
+                '—' => ascii.push('-'),
+                c if c.is_ascii() => ascii.push(c),
+                _ => ascii.push('?'),
+            }
+        }
+        ascii
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_text() {
+        let icons = IconSet::new(true);
+        assert_eq!(icons.text("↑/↓: Move | Enter: Toggle ✓/✗"), "Up/Down: Move | Enter: Toggle [x]/[ ]");
+        assert_eq!(icons.text("⚠ calculating…"), "WARNING: calculating...");
+        assert!(icons.text("naïve").is_ascii());
+
+        // The unicode set leaves text alone
+        assert_eq!(IconSet::new(false).text("↑/↓"), "↑/↓");
+    }
+
+    #[test]
+    fn test_columns() {
+        // Emoji take two cells, so the type column is three wide
+        assert_eq!(UNICODE_ICONS.columns(), [2, 2, 3]);
+        assert_eq!(ASCII_ICONS.columns(), [2, 4, 2]);
+    }
+}
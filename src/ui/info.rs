@@ -0,0 +1,240 @@
+use crate::directory::essentials::is_generated;
+use crate::directory::tree::DirectoryTree;
+use crate::output::formatter::language_hint;
+use chrono::{DateTime, Local};
+use std::fs;
+use std::io::{self, BufRead, BufReader, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Files larger than this only have their head read to count lines
+pub const LINE_SAMPLE_BYTES: u64 = 16 * 1024 * 1024;
+/// Size of each read while counting lines, between checks for cancellation
+const COUNT_CHUNK_BYTES: usize = 64 * 1024;
+
+/// A `stat`-like summary of one node, shown in the info popup
+#[derive(Debug, Clone)]
+pub struct NodeInfo {
+    pub tree_index: usize,
+    pub path: PathBuf, // Absolute path, or the virtual path inside an archive
+    pub is_directory: bool,
+    pub size: Option<u64>,
+    pub included_size: u64, // Sum of included file sizes below a directory
+    pub included_files: usize,
+    pub line_count: Option<LineCount>,
+    pub modified: Option<DateTime<Local>>,
+    pub permissions: Option<String>,
+    pub language: &'static str,
+    pub is_binary: bool,
+    pub is_generated: bool,
+}
+
+impl NodeInfo {
+    /// Collect the summary; files over `line_sample_bytes` get an approximate line count.
+    /// `cached_lines`, from the content cache for an unchanged file, spares reading it.
+    pub fn collect(
+        tree: &DirectoryTree,
+        tree_index: usize,
+        line_sample_bytes: u64,
+        cached_lines: Option<usize>,
+    ) -> Option<Self> {
+        let node = tree.get_node(tree_index)?;
+
+        // Archive entries have no filesystem metadata of their own
+        let metadata = if tree.archive.is_some() { None } else { fs::metadata(&node.path).ok() };
+        let path = if tree.archive.is_some() {
+            node.path.clone()
+        } else {
+            node.path.canonicalize().unwrap_or_else(|_| node.path.clone())
+        };
+
+        let counts = tree.file_counts(tree_index);
+
+        let line_count = if node.is_directory || !node.is_text_file {
+            None
+        } else if let Some(lines) = cached_lines {
+            Some(LineCount::exact(lines))
+        } else if tree.archive.is_some() {
+            // Archive entries are already in memory
+            tree.read_file(&node.path).ok().map(|content| LineCount::exact(count_lines(&content)))
+        } else {
+            count_file_lines(&node.path, line_sample_bytes, &AtomicBool::new(false)).ok()
+        };
+
+        Some(Self {
+            tree_index,
+            path,
+            is_directory: node.is_directory,
+            size: node.size.or_else(|| metadata.as_ref().filter(|m| m.is_file()).map(|m| m.len())),
+            included_size: counts.included_size,
+            included_files: counts.included_files,
+            line_count,
+            modified: metadata.as_ref().and_then(|m| m.modified().ok()).map(DateTime::from),
+            permissions: metadata.as_ref().map(format_permissions),
+            language: if node.is_directory { "" } else { language_hint(&node.path) },
+            is_binary: !node.is_directory && !node.is_text_file,
+            is_generated: !node.is_directory && is_generated(&node.name),
+        })
+    }
+}
+
+/// Lines as an editor would count them: a final line without a newline still counts
+pub fn count_lines(content: &[u8]) -> usize {
+    let newlines = content.iter().filter(|&&byte| byte == b'\n').count();
+    if content.last().is_some_and(|&byte| byte != b'\n') {
+        newlines + 1
+    } else {
+        newlines
+    }
+}
+
+/// Lines in a file, extrapolated from its head when the file is larger than the sample
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineCount {
+    pub lines: usize,
+    pub approximate: bool,
+}
+
+impl LineCount {
+    pub fn exact(lines: usize) -> Self {
+        Self { lines, approximate: false }
+    }
+
+    /// Lines in the first `read` bytes of a file with `newlines` in them, scaled up to its `size`
+    pub fn extrapolated(newlines: usize, read: u64, size: u64) -> Self {
+        let lines = newlines as u128 * size as u128 / read.max(1) as u128;
+        Self {
+            lines: lines.try_into().unwrap_or(usize::MAX),
+            approximate: true,
+        }
+    }
+}
+
+impl std::fmt::Display for LineCount {
+    /// `340`, or `~1200000` when extrapolated
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", if self.approximate { "~" } else { "" }, self.lines)
+    }
+}
+
+/// Count a file's lines in chunks without holding it. Only the first `sample_bytes` are
+/// read (0 reads the whole file); past that the count is scaled up by the file's size and
+/// marked approximate. Fails with `Interrupted` once `cancelled` is set.
+pub fn count_file_lines(path: &Path, sample_bytes: u64, cancelled: &AtomicBool) -> io::Result<LineCount> {
+    let file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let limit = if sample_bytes == 0 { u64::MAX } else { sample_bytes };
+    let mut reader = BufReader::with_capacity(COUNT_CHUNK_BYTES, file);
+    let mut read = 0u64;
+    let mut newlines = 0usize;
+    let mut last_byte = b'\n';
+    while read < limit {
+        if cancelled.load(Ordering::Relaxed) {
+            return Err(io::Error::new(ErrorKind::Interrupted, "line count cancelled"));
+        }
+        let buffer = reader.fill_buf()?;
+        let length = buffer.len().min((limit - read).try_into().unwrap_or(usize::MAX));
+        let Some(&last) = buffer[..length].last() else {
+            break;
+        };
+        newlines += buffer[..length].iter().filter(|&&byte| byte == b'\n').count();
+        last_byte = last;
+        read += length as u64;
+        reader.consume(length);
+    }
+
+    if read < size {
+        return Ok(LineCount::extrapolated(newlines, read, size));
+    }
+    // A final line without a newline still counts
+    Ok(LineCount::exact(if last_byte != b'\n' { newlines + 1 } else { newlines }))
+}
+
+#[cfg(unix)]
+fn format_permissions(metadata: &fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = metadata.permissions().mode();
+    let flags: String = "rwxrwxrwx"
+        .chars()
+        .enumerate()
+        .map(|(position, flag)| if mode & (0o400 >> position) != 0 { flag } else { '-' })
+        .collect();
+    format!("{} ({:o})", flags, mode & 0o777)
+}
+
+#[cfg(not(unix))]
+fn format_permissions(metadata: &fs::Metadata) -> String {
+    if metadata.permissions().readonly() { "read-only" } else { "read-write" }.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directory::state::SelectionState;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_collect_node_info() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {\n}").unwrap();
+        fs::write(root.join("src/Cargo.lock"), "# lock\n").unwrap();
+
+        let mut tree = DirectoryTree::new(root.to_path_buf());
+        let src = tree.add_node(root.join("src"), true, root).unwrap();
+        let main = tree.add_node(root.join("src/main.rs"), false, &root.join("src")).unwrap();
+        let lock = tree.add_node(root.join("src/Cargo.lock"), false, &root.join("src")).unwrap();
+        for (index, size) in [(main, 13), (lock, 7)] {
+            tree.nodes[index].size = Some(size);
+            tree.nodes[index].is_text_file = true;
+        }
+        tree.set_state(main, SelectionState::Included);
+
+        let info = NodeInfo::collect(&tree, main, LINE_SAMPLE_BYTES, None).unwrap();
+        assert_eq!(info.size, Some(13));
+        assert_eq!(info.line_count, Some(LineCount::exact(2)));
+        assert_eq!(info.language, "rust");
+        assert!(info.path.is_absolute());
+        assert!(info.modified.is_some());
+        assert!(!info.is_binary && !info.is_generated);
+
+        // A line count from the content cache is taken as is
+        let info = NodeInfo::collect(&tree, main, LINE_SAMPLE_BYTES, Some(40)).unwrap();
+        assert_eq!(info.line_count, Some(LineCount::exact(40)));
+
+        assert!(NodeInfo::collect(&tree, lock, LINE_SAMPLE_BYTES, None).unwrap().is_generated);
+
+        let info = NodeInfo::collect(&tree, src, LINE_SAMPLE_BYTES, None).unwrap();
+        assert_eq!((info.included_size, info.included_files), (13, 1));
+        assert_eq!(info.line_count, None);
+    }
+
+    #[test]
+    fn test_count_lines_of_huge_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("huge.log");
+        // 2 MB of five-byte lines, then a sparse tail up to 400 MB that takes no disk space
+        fs::write(&path, "line\n".repeat(2 * 1024 * 1024 / 5)).unwrap();
+        let size = 400 * 1024 * 1024;
+        fs::File::options().write(true).open(&path).unwrap().set_len(size).unwrap();
+
+        // Only the first megabyte is read, and the count scaled to the whole file
+        let count = count_file_lines(&path, 1024 * 1024, &AtomicBool::new(false)).unwrap();
+        assert!(count.approximate);
+        let expected = size as usize / 5;
+        assert!(count.lines.abs_diff(expected) < expected / 100, "{} lines", count.lines);
+        assert!(count.to_string().starts_with('~'));
+
+        // Under the sample size, or with sampling off, lines are counted exactly
+        let small = temp_dir.path().join("small.rs");
+        fs::write(&small, "fn main() {\n}").unwrap();
+        assert_eq!(count_file_lines(&small, 1024, &AtomicBool::new(false)).unwrap(), LineCount::exact(2));
+        assert_eq!(count_file_lines(&small, 0, &AtomicBool::new(false)).unwrap().to_string(), "2");
+
+        let cancelled = AtomicBool::new(true);
+        let error = count_file_lines(&path, 0, &cancelled).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Interrupted);
+    }
+}
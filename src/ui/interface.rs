@@ -1,21 +1,104 @@
 use ratatui::{
     Frame,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
+    widgets::{
+        Block, Borders, Clear, List, ListItem, Paragraph, Wrap,
+        block::{Position, Title},
+    },
 };
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
+use crate::config::slots::default_output;
 use crate::directory::state::SelectionState;
+use crate::directory::tree::FileNode;
 use crate::fuzzy::filter::get_node_display_path;
-use crate::ui::app::{App, AppMode};
+use crate::fuzzy::matcher::MatchMode;
+use crate::output::tokens::format_tokens;
+use crate::ui::app::{App, AppMode, SavedSelection};
+use crate::ui::file_diff::DiffLineKind;
+use crate::ui::hyperlink::{Hyperlink, file_url};
+use crate::ui::preview::{FilePreview, PreviewContent, SpanKind, highlight_lines, unmatched_lines};
+
+/// Smallest terminal the full interface is drawn in
+const MIN_WIDTH: u16 = 40;
+const MIN_HEIGHT: u16 = 10;
+/// Below this height the search bar collapses to a single borderless row
+const COMPACT_HEIGHT: u16 = 14;
+/// Below this width the status bar drops its right (key hint) half
+const STATUS_SPLIT_MIN_WIDTH: u16 = 80;
+/// Below this width (inside its borders) the file list leaves out the size column
+pub const SIZE_COLUMN_MIN_WIDTH: u16 = 50;
+/// The size column: `1023.9 KB` right-aligned, after a space
+const SIZE_COLUMN_WIDTH: usize = 10;
 
 pub fn draw_ui(f: &mut Frame, app: &mut App) {
     let size = f.size();
+    app.hyperlinks.clear();
+
+    if size.width < MIN_WIDTH || size.height < MIN_HEIGHT {
+        draw_too_small(f, app, size);
+        return;
+    }
 
-    match app.mode {
-        AppMode::Main => draw_main_interface(f, app, size),
+    match app.view.mode {
+        AppMode::Main | AppMode::Preview => draw_main_interface(f, app, size),
         AppMode::Help => draw_help_interface(f, app, size),
         AppMode::FileSave => draw_file_save_dialog(f, app, size),
+        AppMode::ExportDiff => draw_export_diff_dialog(f, app, size),
+        AppMode::SmartInclude => {
+            draw_main_interface(f, app, size);
+            draw_smart_include_dialog(f, app, size);
+        }
+        AppMode::Info => {
+            draw_main_interface(f, app, size);
+            draw_info_dialog(f, app, size);
+        }
+        AppMode::FileDiff => {
+            draw_main_interface(f, app, size);
+            draw_file_diff_dialog(f, app, size);
+        }
+        AppMode::ExclusionReason => {
+            draw_main_interface(f, app, size);
+            draw_exclusion_reason_dialog(f, app, size);
+        }
+        AppMode::ChangeRoot => {
+            draw_main_interface(f, app, size);
+            draw_change_root_dialog(f, app, size);
+        }
+        AppMode::Command => {
+            draw_main_interface(f, app, size);
+            draw_command_dialog(f, app, size);
+        }
+        AppMode::Wizard => {
+            draw_main_interface(f, app, size);
+            draw_wizard_dialog(f, app, size);
+        }
+        AppMode::Recipes => {
+            draw_main_interface(f, app, size);
+            draw_recipes_dialog(f, app, size);
+        }
+        AppMode::Renames => {
+            draw_main_interface(f, app, size);
+            draw_renames_dialog(f, app, size);
+        }
+        AppMode::WhatsNew => {
+            draw_main_interface(f, app, size);
+            draw_whats_new_dialog(f, app, size);
+        }
+        AppMode::SetConflict => {
+            draw_main_interface(f, app, size);
+            draw_set_conflict_dialog(f, app, size);
+        }
+        AppMode::Suggestions => {
+            draw_main_interface(f, app, size);
+            draw_suggestions_dialog(f, app, size);
+        }
+        AppMode::Order => {
+            draw_main_interface(f, app, size);
+            draw_export_order_dialog(f, app, size);
+        }
     }
 }
 
@@ -23,44 +106,216 @@ fn draw_main_interface(f: &mut Frame, app: &mut App, area: Rect) {
     // Clear the background for transparency
     f.render_widget(Clear, area);
 
+    let search_height = if area.height < COMPACT_HEIGHT { 1 } else { 3 };
+
+    let mut constraints = vec![
+        Constraint::Length(search_height), // Search bar
+        Constraint::Min(0),                // File list
+    ];
+    if app.show_status_bar {
+        constraints.push(Constraint::Length(3)); // Status bar
+    }
     let chunks = Layout::default()
         .direction(Direction::Vertical)
+        .constraints(constraints)
+        .split(area);
+
+    // Remember where the search bar is so mouse clicks can focus it
+    app.search_area = chunks[0];
+
+    draw_search_bar(f, app, chunks[0]);
+    if app.compare_pin.is_some() {
+        draw_compare_view(f, app, chunks[1]);
+    } else if app.view.mode == AppMode::Preview {
+        draw_preview_view(f, app, chunks[1]);
+    } else {
+        draw_file_list(f, app, chunks[1]);
+    }
+    if app.show_status_bar {
+        draw_status_bar(f, app, chunks[2]);
+    } else if app.status_flash_visible() {
+        draw_status_flash(f, app, chunks[1]);
+    }
+}
+
+fn draw_preview_view(f: &mut Frame, app: &mut App, area: Rect) {
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
         .constraints([
-            Constraint::Length(3), // Search bar
-            Constraint::Min(0),    // File list
-            Constraint::Length(3), // Status bar
+            Constraint::Percentage(50), // File list
+            Constraint::Percentage(50), // File under the cursor
         ])
         .split(area);
 
-    draw_search_bar(f, app, chunks[0]);
-    draw_file_list(f, app, chunks[1]);
-    draw_status_bar(f, app, chunks[2]);
+    draw_file_list(f, app, columns[0]);
+    // Only read once the cursor rests on a node, and only that node
+    app.load_hovered_preview();
+
+    let app = &*app;
+    if let Some(preview) = &app.preview_cache {
+        let title = format!("Preview: {} (Shift+↑/↓: Scroll)", get_node_display_path(&app.model.tree, preview.tree_index));
+        let paragraph = preview_paragraph(app, app.icons.text(&title), preview, &[], app.color_scheme.text);
+        f.render_widget(paragraph.scroll((app.preview_scroll as u16, 0)), columns[1]);
+    }
+}
+
+fn draw_compare_view(f: &mut Frame, app: &mut App, area: Rect) {
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(40), // File list
+            Constraint::Percentage(30), // Pinned file
+            Constraint::Percentage(30), // File under the cursor
+        ])
+        .split(area);
+
+    draw_file_list(f, app, columns[0]);
+    app.load_hovered_preview();
+
+    let app = &*app;
+    let (Some(pinned), Some(hovered)) = (&app.compare_pin, &app.preview_cache) else {
+        return;
+    };
+
+    // Highlight lines that only exist on one side, marked in the gutter as well as colored
+    let pinned_unmatched = unmatched_lines(pinned.lines(), hovered.lines());
+    let hovered_unmatched = unmatched_lines(hovered.lines(), pinned.lines());
+
+    let pinned_title = format!("Pinned: {}", get_node_display_path(&app.model.tree, pinned.tree_index));
+    let hovered_title = format!("Cursor: {}", get_node_display_path(&app.model.tree, hovered.tree_index));
+
+    let pinned = preview_paragraph(app, pinned_title, pinned, &pinned_unmatched, app.color_scheme.excluded);
+    let hovered = preview_paragraph(app, hovered_title, hovered, &hovered_unmatched, app.color_scheme.included);
+    f.render_widget(pinned, columns[1]);
+    f.render_widget(hovered, columns[2]);
+}
+
+/// A preview pane, with lines marked in `highlighted` drawn in `highlight_style`
+fn preview_paragraph<'a>(
+    app: &App,
+    title: String,
+    preview: &'a FilePreview,
+    highlighted: &[bool],
+    highlight_style: Style,
+) -> Paragraph<'a> {
+    let mut lines: Vec<Line> = match &preview.content {
+        PreviewContent::Text(lines) => highlight_lines(preview.format, lines)
+            .into_iter()
+            .enumerate()
+            .map(|(index, spans)| {
+                let is_highlighted = highlighted.get(index).copied().unwrap_or(false);
+                let gutter = if is_highlighted { app.icons.text("› ") } else { "  ".to_string() };
+                let spans: Vec<Span> = std::iter::once(Span::styled(gutter, highlight_style))
+                    .chain(spans.into_iter()
+                    .map(|(kind, text)| {
+                        let style = if is_highlighted {
+                            highlight_style
+                        } else {
+                            get_span_style(app, kind)
+                        };
+                        Span::styled(text.replace('\t', "    "), style)
+                    }))
+                    .collect();
+                Line::from(spans)
+            })
+            .collect(),
+        PreviewContent::Binary => vec![Line::styled("(binary file)", app.color_scheme.help_text)],
+        PreviewContent::Directory => vec![Line::styled("(directory)", app.color_scheme.help_text)],
+        PreviewContent::Loading => vec![Line::styled(app.icons.text("(loading…)"), app.color_scheme.help_text)],
+        PreviewContent::Error(e) => vec![Line::styled(e.clone(), app.color_scheme.excluded)],
+    };
+
+    if let Some(note) = &preview.note {
+        lines.insert(0, Line::styled(format!("({})", note), app.color_scheme.help_text));
+    }
+
+    let mut block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .border_style(app.color_scheme.border);
+    // What the whole file would add to the export, for budgeting before including it
+    if let Some(contribution) = &preview.contribution {
+        let footer = format!(" {} ", app.icons.text(&contribution.describe()));
+        block = block.title(Title::from(footer).position(Position::Bottom).alignment(Alignment::Right));
+    }
+    Paragraph::new(lines).block(block)
+}
+
+fn get_span_style(app: &App, kind: SpanKind) -> Style {
+    match kind {
+        SpanKind::Plain => app.color_scheme.text,
+        SpanKind::Key => app.color_scheme.search_match,
+        SpanKind::Heading => app.color_scheme.text.add_modifier(Modifier::BOLD),
+        SpanKind::Code => app.color_scheme.help_text.add_modifier(Modifier::DIM),
+        SpanKind::Comment => app.color_scheme.help_text,
+    }
 }
 
 fn draw_search_bar(f: &mut Frame, app: &App, area: Rect) {
-    let search_text = if app.search_query.is_empty() {
+    let is_focused = app.is_search_focused();
+
+    let search_text = if !app.model.query.is_empty() {
+        &app.model.query
+    } else if is_focused {
         "Type to search files and directories..."
     } else {
-        &app.search_query
+        "Press / to search files and directories..."
     };
 
-    let style = if app.search_query.is_empty() {
+    let style = if app.model.query.is_empty() {
         app.color_scheme.help_text
     } else {
         app.color_scheme.text
     };
 
+    let border_style = if is_focused {
+        app.color_scheme.focused_border
+    } else {
+        app.color_scheme.border
+    };
+
+    // Not enough room for borders: a single "Search: query" row
+    if area.height < 3 {
+        let label = match app.model.match_mode {
+            MatchMode::Fuzzy => "Search: ".to_string(),
+            mode => format!("Search [{}]: ", mode.name()),
+        };
+        let line = Line::from(vec![
+            Span::styled(label.as_str(), border_style),
+            Span::styled(search_text, style),
+        ]);
+        f.render_widget(Paragraph::new(line), area);
+
+        if is_focused {
+            let query_width = app.search_cursor_column() as u16;
+            let cursor_x = area.x + label.len() as u16 + query_width;
+            f.set_cursor(cursor_x.min(area.right().saturating_sub(1)), area.y);
+        }
+        return;
+    }
+
+    let mut title = vec![Span::raw(format!("Search [{}]", app.model.match_mode.name()))];
+    if app.model.invalid_query {
+        title.push(Span::styled(" invalid regex", app.color_scheme.warning));
+    }
     let search_paragraph = Paragraph::new(search_text)
         .style(style)
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title("Search")
-                .border_style(app.color_scheme.border),
+                .title(Line::from(title))
+                .border_style(border_style),
         )
         .wrap(Wrap { trim: true });
 
     f.render_widget(search_paragraph, area);
+
+    // Show the block cursor at the caret while editing, measured in display columns
+    if is_focused && area.width > 2 {
+        let query_width = app.search_cursor_column() as u16;
+        let max_x = area.right().saturating_sub(2);
+        f.set_cursor((area.x + 1 + query_width).min(max_x), area.y + 1);
+    }
 }
 
 fn draw_file_list(f: &mut Frame, app: &mut App, area: Rect) {
@@ -69,20 +324,22 @@ fn draw_file_list(f: &mut Frame, app: &mut App, area: Rect) {
     let actual_viewport_height = area.height.saturating_sub(2) as usize;
 
     // Update the app's viewport height to match the actual visible area
-    app.viewport_height = actual_viewport_height;
+    app.view.viewport_height = actual_viewport_height;
+    let inner_width = area.width.saturating_sub(2) as usize;
 
-    let items: Vec<ListItem> = app
+    let items: Vec<ListItem> = app.model
         .filtered_results
         .visible_items
         .iter()
-        .skip(app.scroll_offset)
+        .skip(app.view.scroll_offset)
         .take(actual_viewport_height)
         .enumerate()
         .map(|(viewport_index, &tree_index)| {
             // viewport_index is now 0-based index within the visible viewport
             // The actual index in the filtered results is scroll_offset + viewport_index
-            let actual_index = app.scroll_offset + viewport_index;
-            create_list_item(app, tree_index, actual_index == app.selected_index)
+            let actual_index = app.view.scroll_offset + viewport_index;
+            let is_selected = actual_index == app.view.selected_index;
+            create_list_item(app, tree_index, is_selected, row_overlay(app, actual_index, tree_index), inner_width)
         })
         .collect();
 
@@ -90,92 +347,369 @@ fn draw_file_list(f: &mut Frame, app: &mut App, area: Rect) {
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title("Files and Directories (Enter = toggle ✓/✗)")
-                .border_style(app.color_scheme.border),
+                .title(list_title(app))
+                .border_style(if app.is_search_focused() {
+                    app.color_scheme.border
+                } else {
+                    app.color_scheme.focused_border
+                }),
         )
         .style(app.color_scheme.background);
 
     f.render_widget(list, area);
+
+    // Dialogs drawn over the list would get links in their cells too
+    if app.hyperlinks_enabled
+        && matches!(app.view.mode, AppMode::Main | AppMode::Preview)
+        && app.model.tree.archive.is_none()
+    {
+        link_list_rows(app, area);
+    }
+}
+
+/// Link each visible row's path to its file, clipped to the inside of the list
+fn link_list_rows(app: &mut App, area: Rect) {
+    let inner_width = area.width.saturating_sub(2) as usize;
+    // Paths end where the size column starts
+    let inner_right = area.x + 1 + (inner_width - size_column_width(app, inner_width)) as u16;
+    let mut links = Vec::new();
+    for (viewport_index, &tree_index) in app.model
+        .filtered_results
+        .visible_items
+        .iter()
+        .skip(app.view.scroll_offset)
+        .take(app.view.viewport_height)
+        .enumerate()
+    {
+        let Some(node) = app.model.tree.get_node(tree_index) else { continue };
+        let Ok(path) = std::path::absolute(&node.path) else { continue };
+        let (offset, width) = path_column(app, tree_index);
+        let x = area.x + 1 + offset;
+        let width = width.min(inner_right.saturating_sub(x));
+        if width > 0 {
+            links.push(Hyperlink {
+                area: Rect::new(x, area.y + 1 + viewport_index as u16, width, 1),
+                url: file_url(&path),
+            });
+        }
+    }
+    app.hyperlinks = links;
+}
+
+/// Column where a row's path starts, and its width, matching `create_list_item`
+fn path_column(app: &App, tree_index: usize) -> (u16, u16) {
+    let offset = app.icons.columns().iter().sum::<usize>() + tree_prefix(app, tree_index).width();
+    (offset as u16, list_item_path(app, tree_index).width() as u16)
+}
+
+/// In the tree view, a row's indentation for its depth below the root and, for a
+/// directory, whether it's folded. Nothing in the list of paths.
+fn tree_prefix(app: &App, tree_index: usize) -> String {
+    let tree = &app.model.tree;
+    let Some(node) = tree.get_node(tree_index).filter(|_| app.tree_view) else {
+        return String::new();
+    };
+    let depth = node.path.strip_prefix(&tree.nodes[tree.root_index].path).map_or(0, |path| path.components().count());
+    let folded = app.collapsed.contains(&tree_index) && app.model.query.is_empty();
+    let marker = match (node.is_directory, folded) {
+        (false, _) => " ".repeat(app.icons.expanded.width()),
+        (true, true) => app.icons.collapsed.to_string(),
+        (true, false) => app.icons.expanded.to_string(),
+    };
+    format!("{}{} ", "  ".repeat(depth), marker)
+}
+
+/// A glyph padded with spaces to a column's display width
+fn pad_glyph(glyph: &str, width: usize) -> String {
+    format!("{}{}", glyph, " ".repeat(width.saturating_sub(glyph.width())))
+}
+
+fn list_item_path(app: &App, tree_index: usize) -> String {
+    if app.model.match_file_names || app.tree_view {
+        app.model.tree.get_node(tree_index).map(|node| node.name.clone()).unwrap_or_default()
+    } else {
+        get_node_display_path(&app.model.tree, tree_index)
+    }
+}
+
+fn list_title(app: &App) -> String {
+    let mut title = format!(
+        "Files and Directories (Enter = toggle {}/{})",
+        app.icons.included, app.icons.excluded
+    );
+    if let Some(plan) = &app.bulk_plan {
+        title = format!("Preview: {} rows change | Enter: Apply, Esc: Cancel | {}", plan.changed.len(), title);
+    }
+    if let Some(prefix) = &app.jump_prefix {
+        title = format!("Jump: {}_ | f: Next, Esc: Done | {}", prefix, title);
+    }
+    if app.model.changed_files.is_some() {
+        title = format!("Changed vs HEAD | g: Show all | {}", title);
+    }
+    if app.model.tree.root_index == app.model.tree.scan_root_index {
+        return title;
+    }
+
+    // Breadcrumb for a re-rooted view
+    app.icons.text(&format!("{} | <: Back to full tree | {}", app.view_root_breadcrumb(), title))
+}
+
+/// Transient style laid over a row's own, for rows a pending bulk operation or the visual
+/// range would change
+fn row_overlay(app: &App, row: usize, tree_index: usize) -> Option<Style> {
+    if let Some(rows) = app.visual_range() {
+        return rows.contains(&row).then_some(app.color_scheme.pending_change);
+    }
+    let plan = app.bulk_plan.as_ref()?;
+    plan.changed.contains(&tree_index).then_some(app.color_scheme.pending_change)
+}
+
+/// Width of the size column at the right edge of a list `inner_width` wide, or 0 when
+/// the list is narrower than `size_column_min_width` and sizes are left out
+fn size_column_width(app: &App, inner_width: usize) -> usize {
+    if inner_width < app.size_column_min_width as usize { 0 } else { SIZE_COLUMN_WIDTH }
+}
+
+/// What the size column shows for a row: a file's size, the total under a directory, or
+/// `…` while sizes are still being collected
+fn size_label(app: &App, node: &FileNode, tree_index: usize) -> String {
+    let size = match (node.is_directory, node.metadata_pending) {
+        (true, _) => app.model.directory_size(tree_index),
+        (false, true) => None,
+        (false, false) => return node.size.map(format_file_size).unwrap_or_default(),
+    };
+    size.map(format_file_size).unwrap_or_else(|| app.icons.text("…"))
+}
+
+/// Cut spans down to `max_width` columns, ending in `ellipsis` if anything was cut
+fn truncate_spans<'a>(spans: Vec<Span<'a>>, max_width: usize, ellipsis: &str) -> Vec<Span<'a>> {
+    if spans.iter().map(|span| span.content.width()).sum::<usize>() <= max_width {
+        return spans;
+    }
+    let mut room = max_width.saturating_sub(ellipsis.width());
+    let mut kept = Vec::new();
+    for span in spans {
+        let width = span.content.width();
+        if width <= room {
+            room -= width;
+            kept.push(span);
+            continue;
+        }
+        let mut text = String::new();
+        for c in span.content.chars() {
+            let width = c.width().unwrap_or(0);
+            if width > room {
+                break;
+            }
+            room -= width;
+            text.push(c);
+        }
+        kept.push(Span::styled(text, span.style));
+        kept.push(Span::styled(ellipsis.to_string(), span.style));
+        break;
+    }
+    kept
 }
 
-fn create_list_item(app: &App, tree_index: usize, is_selected: bool) -> ListItem {
-    if let Some(node) = app.tree.get_node(tree_index) {
-        let display_path = get_node_display_path(&app.tree, tree_index);
+fn create_list_item(app: &App, tree_index: usize, is_selected: bool, overlay: Option<Style>, width: usize) -> ListItem<'_> {
+    if let Some(node) = app.model.tree.get_node(tree_index) {
+        let display_path = list_item_path(app, tree_index);
 
+        let icons = app.icons;
         let state_indicator = match node.state {
-            SelectionState::Included => "✓",
-            SelectionState::Excluded => "✗",
-            SelectionState::Partial => "◐",
+            SelectionState::Included => icons.included,
+            SelectionState::Excluded => icons.excluded,
+            SelectionState::Partial => icons.partial,
         };
 
-        let file_type_indicator = if node.is_directory { "📁" } else { "📄" };
-
-        let cursor_indicator = if is_selected { "▶ " } else { "  " };
+        let file_type_indicator = if node.is_directory { icons.directory } else { icons.file };
+        let cursor_indicator = if is_selected { icons.cursor } else { "" };
+        let [cursor_width, state_width, type_width] = icons.columns();
 
         // Get base style for the state, not influenced by selection
-        let base_style = app.color_scheme.get_state_style(node.state);
+        let mut base_style = app.color_scheme.get_state_style(node.state);
+        if let Some(overlay) = overlay {
+            base_style = base_style.patch(overlay);
+        }
 
-        let spans = vec![
-            Span::styled(cursor_indicator, app.color_scheme.text),
-            Span::styled(format!("{} ", state_indicator), base_style),
-            Span::styled(format!("{} ", file_type_indicator), app.color_scheme.text),
-            Span::styled(display_path, base_style),
+        let mut spans = vec![
+            Span::styled(pad_glyph(cursor_indicator, cursor_width), app.color_scheme.text),
+            Span::styled(pad_glyph(state_indicator, state_width), base_style),
+            Span::styled(pad_glyph(file_type_indicator, type_width), app.color_scheme.text),
         ];
 
-        if let Some(size) = node.size {
-            let size_str = format_file_size(size);
-            let line = Line::from(spans);
-
-            // Add size information for files
-            let mut full_spans = line.spans;
-            full_spans.push(Span::styled(
-                format!(" ({})", size_str),
-                app.color_scheme.help_text,
-            ));
+        if app.tree_view {
+            spans.push(Span::styled(tree_prefix(app, tree_index), app.color_scheme.help_text));
+            // Directories listed only to show where a match is
+            if app.model.filtered_results.context.contains(&tree_index) {
+                base_style = base_style.patch(app.color_scheme.help_text);
+            }
+        }
 
-            ListItem::new(Line::from(full_spans))
+        // Dim the directory prefix shared by every visible row so the tails stand out
+        let prefix_len = app.model.filtered_results.common_prefix_len;
+        if app.dim_common_prefix
+            && !app.model.match_file_names
+            && prefix_len > 0
+            && prefix_len < display_path.len()
+            && display_path.is_char_boundary(prefix_len)
+        {
+            let (prefix, tail) = display_path.split_at(prefix_len);
+            spans.push(Span::styled(prefix.to_string(), app.color_scheme.help_text));
+            spans.push(Span::styled(tail.to_string(), base_style));
         } else {
-            ListItem::new(Line::from(spans))
+            spans.push(Span::styled(display_path, base_style));
+        }
+
+        // Where a file is, when other results have the same name
+        if let Some(parents) = app.model.filtered_results.disambiguations.get(&tree_index) {
+            spans.push(Span::styled(format!("{}{}", app.icons.text(" — "), parents), app.color_scheme.help_text));
+        }
+
+        if app.diff_mark == Some(tree_index) {
+            spans.push(Span::styled(" [diff]", app.color_scheme.partial));
+        }
+
+        if let Some(reason) = app.exclusion_reason(tree_index) {
+            spans.push(Span::styled(app.icons.text(&format!(" · {}", reason)), app.color_scheme.help_text));
+        }
+
+        if node.sticky {
+            spans.push(Span::styled(format!(" {}", icons.sticky), app.color_scheme.included));
+        }
+
+        if node.locked {
+            spans.push(Span::styled(format!(" {}", icons.locked), app.color_scheme.help_text));
         }
+
+        // Sizes line up in a column at the right edge, the row cut short to make room
+        let column = size_column_width(app, width);
+        if column == 0 {
+            return ListItem::new(Line::from(spans));
+        }
+        let mut spans = truncate_spans(spans, width - column, &app.icons.text("…"));
+        let used: usize = spans.iter().map(|span| span.content.width()).sum();
+        spans.push(Span::raw(" ".repeat(width - column - used)));
+        spans.push(Span::styled(
+            format!("{:>1$}", size_label(app, node, tree_index), column),
+            app.color_scheme.help_text,
+        ));
+        ListItem::new(Line::from(spans))
     } else {
         ListItem::new("Invalid node")
     }
 }
 
-fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
+/// Stats and any status message over the file list's bottom border, in place of the hidden status bar
+fn draw_status_flash(f: &mut Frame, app: &App, list_area: Rect) {
+    if list_area.height < 3 {
+        return;
+    }
+    let area = Rect::new(list_area.x + 1, list_area.bottom() - 1, list_area.width.saturating_sub(2), 1);
+    let text = app
+        .bulk_prompt()
+        .or_else(|| app.get_status_message().map(str::to_string))
+        .unwrap_or_else(|| stats_text(app));
+
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(app.icons.text(&text)).style(app.color_scheme.text), area);
+}
+
+/// File counts, sizes, tokens and rule results: the status bar's left half
+fn stats_text(app: &App) -> String {
+    stats_spans(app).iter().map(|span| span.content.as_ref()).collect()
+}
+
+/// `stats_text` in pieces, with the token estimate colored once it's over the budget
+fn stats_spans(app: &App) -> Vec<Span<'static>> {
     let stats = app.get_stats();
 
-    let left_text = format!(
-        "Files: {}/{} | Size: {} | Filtered: {}",
+    let mut left_text = stats
+        .format_filter()
+        .map(|filter| format!("{} | ", app.icons.text(&filter)))
+        .unwrap_or_default();
+    left_text.push_str(&format!(
+        "Files: {}/{}{} | Size: {} | Filtered: {}",
         stats.included_files,
+        if stats.approximate { "~" } else { "" },
         stats.total_files,
         stats.format_size(),
         stats.filtered_count
-    );
+    ));
+
+    // Estimated from sizes as selections change, never by reading the files
+    let mut spans = Vec::new();
+    let tokens = stats.tokens;
+    let token_text = match app.token_budget {
+        Some(budget) => {
+            let warning = if tokens > budget { format!("{} ", app.icons.warning) } else { String::new() };
+            Some(format!("{}Tokens: ~{}/{}", warning, format_tokens(tokens), format_tokens(budget)))
+        }
+        None if stats.included_files > 0 => Some(format!("Tokens: ~{}", format_tokens(tokens))),
+        None => None,
+    };
+    if let Some(token_text) = token_text {
+        left_text.push_str(" | ");
+        spans.push(Span::raw(app.icons.text(&std::mem::take(&mut left_text))));
+        let style = if app.over_budget() { app.color_scheme.warning } else { Style::default() };
+        spans.push(Span::styled(token_text, style));
+    }
+
+    if stats.shared_names > 0 {
+        left_text.push_str(&format!(" | {} {} shared names", app.icons.warning, stats.shared_names));
+    }
+
+    if let Some(summary) = &app.rules_summary {
+        left_text.push_str(&format!(" | Rules: {}", summary.applied));
+        if !summary.unmatched.is_empty() {
+            left_text.push_str(&format!(" ({} unmatched)", summary.unmatched.len()));
+        }
+    }
+    spans.push(Span::raw(app.icons.text(&left_text)));
+    spans
+}
+
+fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
+    let stats = app.get_stats();
+    let left_spans = stats_spans(app);
+    let left_text = stats_text(app);
 
     // Adjust help text based on available width
     let available_width = area.width.saturating_sub(4) as usize; // Account for borders
     let left_text_len = left_text.len();
     let remaining_width = available_width.saturating_sub(left_text_len);
 
-    let right_text = if remaining_width > 80 {
-        "↑/↓: Move | Enter: Toggle ✓/✗ | Ctrl+E: Export | Ctrl+H: Help"
-    } else if remaining_width > 60 {
-        "↑/↓: Move | Enter: Toggle | Ctrl+E: Export | Ctrl+H: Help"
-    } else if remaining_width > 40 {
-        "↑/↓: Move | Ctrl+E: Export | Ctrl+H: Help"
-    } else if remaining_width > 25 {
-        "↑/↓: Move | Ctrl+E: Export"
+    let bulk_prompt = app.bulk_prompt();
+    let status_message = bulk_prompt.as_deref().or_else(|| app.get_status_message());
+
+    // The export hint names the format Ctrl+E writes; the longest set of hints that fits wins
+    let export = format!("Ctrl+E: Export {}", app.formatter.format());
+    let hints = [
+        format!("/: Search | ↑/↓: Move | Enter: Toggle ✓/✗ | {} | F: Format | Ctrl+H: Help", export),
+        format!("↑/↓: Move | Enter: Toggle | {} | Ctrl+H: Help", export),
+        format!("↑/↓: Move | {} | Ctrl+H: Help", export),
+        format!("↑/↓: Move | {}", export),
+    ];
+    let room = remaining_width.min((area.width / 2).saturating_sub(2) as usize);
+    let right_text = if let Some(message) = status_message {
+        message.to_string()
+    } else if app.is_search_focused() && !app.legacy_typeahead {
+        "Enter/Esc: Back to list".to_string()
+    } else if stats.included_files == 0 && !stats.calculating && remaining_width > 40 {
+        // Nothing selected yet: point new users at a starting selection
+        "E: Select project essentials | Ctrl+H: Help".to_string()
+    } else if app.over_budget() && !stats.calculating && remaining_width > 40 {
+        "T: Suggest files to exclude | Ctrl+H: Help".to_string()
     } else {
-        "Ctrl+E: Export"
+        hints.into_iter().find(|hint| hint.chars().count() <= room).unwrap_or(export)
     };
 
-    let status_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-        .split(area);
-
-    let left_paragraph = Paragraph::new(left_text)
+    // Narrow terminals only have room for one side; a pending message wins
+    let narrow = area.width < STATUS_SPLIT_MIN_WIDTH;
+    let left_paragraph = Paragraph::new(match status_message {
+        Some(message) if narrow => Line::from(app.icons.text(message)),
+        _ => Line::from(left_spans),
+    })
         .style(app.color_scheme.text)
         .block(
             Block::default()
@@ -183,7 +717,18 @@ fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
                 .border_style(app.color_scheme.border),
         );
 
-    let right_paragraph = Paragraph::new(right_text)
+    // Narrow terminals: the stats get the whole bar, key hints are dropped
+    if narrow {
+        f.render_widget(left_paragraph, area);
+        return;
+    }
+
+    let status_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+
+    let right_paragraph = Paragraph::new(app.icons.text(&right_text))
         .style(app.color_scheme.help_text)
         .alignment(Alignment::Right)
         .block(
@@ -201,35 +746,83 @@ fn draw_help_interface(f: &mut Frame, app: &App, area: Rect) {
         Line::from("gthr - Help"),
         Line::from(""),
         Line::from("Search:"),
+        Line::from("  /          Focus the search bar (or click it)"),
         Line::from("  Type       Add any character to search (letters, numbers, symbols)"),
-        Line::from("  Backspace  Delete search character"),
-        Line::from("  Esc        Clear search text (or quit if empty)"),
+        Line::from("  Backspace  Delete search character (Delete: the one after the caret)"),
+        Line::from(app.icons.text("  ←/→        Move the caret (Home/End: to either end)")),
+        Line::from("  Enter/Esc  Return focus to the file list"),
+        Line::from("  Syntax     app fuzzy, 'app exact, 'app' whole word, ^src start, .rs$ end,"),
+        Line::from("             !vendor without; space-separated terms must all match"),
+        Line::from("  Ctrl+R     Match fuzzily, as a literal substring or as a regex, in turn"),
         Line::from(""),
         Line::from("Navigation:"),
-        Line::from("  ↑/↓        Move up/down"),
-        Line::from("  ←/→        Move up/down (alternative)"),
+        Line::from(app.icons.text("  ↑/↓        Move up/down")),
+        Line::from(app.icons.text("  ←/→        Move up/down (alternative); in the tree view, fold/unfold")),
+        Line::from(app.icons.text("  Ctrl+P     Preview the file under the cursor beside the list (Shift+↑/↓ scrolls)")),
+        Line::from("  Tab        Switch between the list of paths and the tree view"),
+        Line::from("  Space      Fold/unfold the directory under the cursor (tree view)"),
+        Line::from("  f          Jump to names starting with what you type next (f: next)"),
         Line::from(""),
         Line::from("Selection:"),
-        Line::from("  Enter      Toggle ✓ included / ✗ excluded"),
+        Line::from(app.icons.text("  Enter      Toggle ✓ included / ✗ excluded (◐ partial: included first)")),
+        Line::from("  Alt+Enter  Include a directory's files until the token budget is reached"),
+        Line::from("  T          Suggest included files to exclude to get under the token budget"),
+        Line::from("  M          Place included files in the export order by hand (c: clear)"),
+        Line::from("  c          Pin file to compare side by side (Esc unpins)"),
+        Line::from("  E          Select project essentials (README, manifests, src/, lib/)"),
+        Line::from("  s          Smart include: add local files this file uses (Rust)"),
+        Line::from("  y / Y      Copy relative / absolute path under the cursor"),
+        Line::from("  i          Show exact size, lines, permissions and more for a node"),
+        Line::from("  =          Give all files in the same directory this node's state"),
+        Line::from("  + / - / *  Include / exclude / invert all matches, previewed first"),
+        Line::from("  v          Start a range at the cursor; move to extend it, Enter toggles it"),
+        Line::from("  Ctrl+A/N/T Toggle / exclude / invert every listed file (matches only)"),
+        Line::from("  X          Exclude every file with this file's extension, previewed first"),
+        Line::from("  O          Scan a different root without restarting (Esc cancels the scan)"),
+        Line::from("  :          Run a command: select-cmd CMD, include/exclude QUERY, essentials, rules reload, root PATH"),
+        Line::from("  S          Save the selection to .gthr-set (or the --set file)"),
+        Line::from("  g          Only list files changed since git HEAD (again: all)"),
+        Line::from("  D          Mark a file, then D on another to diff them"),
+        Line::from("  l          Lock/unlock a node against bulk changes (rules, essentials)"),
+        Line::from("  #          Note why an excluded node is excluded (saved in the rules file)"),
+        Line::from("  > / <      Use the directory under the cursor as root / back to full tree"),
         Line::from(""),
         Line::from("Actions:"),
         Line::from("  Ctrl+E     Export output and quit"),
+        Line::from("  Ctrl+Y     Copy the export to the clipboard and keep going"),
+        Line::from("  Ctrl+S     Save the selection to the profile (also on export and quit)"),
+        Line::from("  F          Switch the export format (markdown, plain, json, xml)"),
+        Line::from("  F1-F4      Export to slot 1-4 and keep going (asks for its file once)"),
         Line::from("  Ctrl+H     Show this help"),
+        Line::from("  Ctrl+L     Reload the rules file"),
+        Line::from("  R          Pick a recipe from the config and export with it"),
+        Line::from("  Ctrl+B     Hide/show the status bar"),
+        Line::from("  Ctrl+Shift+D  Show changes since the last export"),
+        Line::from("  Ctrl+Shift+Y  Copy paths of all included files"),
         Line::from("  Esc        Clear search (or quit if search empty)"),
+        Line::from("  q, Ctrl+C  Quit without exporting"),
         Line::from(""),
         Line::from("Colors:"),
         Line::from(vec![
-            Span::styled("  ✓ ", app.color_scheme.included),
+            Span::styled(format!("  {} ", app.icons.included), app.color_scheme.included),
             Span::from("Included"),
         ]),
         Line::from(vec![
-            Span::styled("  ✗ ", app.color_scheme.excluded),
+            Span::styled(format!("  {} ", app.icons.excluded), app.color_scheme.excluded),
             Span::from("Excluded"),
         ]),
         Line::from(vec![
-            Span::styled("  ◐ ", app.color_scheme.partial),
+            Span::styled(format!("  {} ", app.icons.partial), app.color_scheme.partial),
             Span::from("Partially included"),
         ]),
+        Line::from(vec![
+            Span::styled(format!("  {} ", app.icons.locked), app.color_scheme.help_text),
+            Span::from("Locked against bulk changes"),
+        ]),
+        Line::from(vec![
+            Span::styled(format!("  {} ", app.icons.sticky), app.color_scheme.included),
+            Span::from("Included as a whole: files found by a rescan are included too"),
+        ]),
         Line::from(""),
         Line::from("Press any key to return..."),
     ];
@@ -277,8 +870,12 @@ fn draw_file_save_dialog(f: &mut Frame, app: &App, area: Rect) {
     // Clear the popup area
     f.render_widget(Clear, popup_area);
 
+    let title = match app.file_save_slot {
+        Some(number) => format!("Set Up Slot {} (F{})", number, number),
+        None => "Save File".to_string(),
+    };
     let block = Block::default()
-        .title("Save File")
+        .title(title)
         .borders(Borders::ALL)
         .border_style(app.color_scheme.border)
         .style(app.color_scheme.background);
@@ -301,16 +898,23 @@ fn draw_file_save_dialog(f: &mut Frame, app: &App, area: Rect) {
         "Unknown".to_string()
     };
 
-    let instructions = Paragraph::new(format!(
-        "Output is too large for clipboard ({}). Enter file path to save:",
-        content_size
-    ))
+    let instructions = match app.file_save_slot {
+        Some(number) => format!(
+            "Slot {} has no output yet. Enter the file it exports to; the extension picks the format:",
+            number
+        ),
+        None => format!("Output is too large for clipboard ({}). Enter file path to save:", content_size),
+    };
+    let instructions = Paragraph::new(instructions)
     .style(app.color_scheme.text)
     .wrap(Wrap { trim: true });
 
     // Input field
     let input_text = if app.file_save_input.is_empty() {
-        "📁 Enter file path (or press Enter for default)".to_string()
+        match app.file_save_slot {
+            Some(number) => format!("{} Enter file path (or press Enter for {})", app.icons.directory, default_output(number).display()),
+            None => format!("{} Enter file path (or press Enter for default)", app.icons.directory),
+        }
     } else {
         app.file_save_input.clone()
     };
@@ -340,26 +944,1249 @@ fn draw_file_save_dialog(f: &mut Frame, app: &App, area: Rect) {
 
     // Position cursor in the input field
     if !app.file_save_input.is_empty() {
+        let max_x = popup_chunks[1].right().saturating_sub(2);
         f.set_cursor(
-            popup_chunks[1].x + app.file_save_input.len() as u16 + 1,
+            (popup_chunks[1].x + app.file_save_input.len() as u16 + 1).min(max_x),
             popup_chunks[1].y + 1,
         );
     }
 }
 
-fn format_file_size(size: u64) -> String {
-    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
-    let mut size_f = size as f64;
-    let mut unit_index = 0;
+fn draw_file_diff_dialog(f: &mut Frame, app: &mut App, area: Rect) {
+    let Some(diff) = &app.file_diff else {
+        return;
+    };
 
-    while size_f >= 1024.0 && unit_index < UNITS.len() - 1 {
-        size_f /= 1024.0;
-        unit_index += 1;
-    }
+    let popup_area = centered_rect(80, 80, area);
+    f.render_widget(Clear, popup_area);
 
-    if unit_index == 0 {
-        format!("{} {}", size, UNITS[unit_index])
+    let lines: Vec<Line> = if diff.is_identical() {
+        vec![Line::from("The files are identical.")]
     } else {
-        format!("{:.1} {}", size_f, UNITS[unit_index])
-    }
+        diff.lines
+            .iter()
+            .map(|(kind, text)| {
+                let style = match kind {
+                    DiffLineKind::Added => app.color_scheme.included,
+                    DiffLineKind::Removed => app.color_scheme.excluded,
+                    DiffLineKind::Hunk => app.color_scheme.partial,
+                    DiffLineKind::Header => app.color_scheme.help_text,
+                    DiffLineKind::Context => app.color_scheme.text,
+                };
+                Line::from(Span::styled(text.as_str(), style))
+            })
+            .collect()
+    };
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(popup_area);
+
+    // Keep the scroll offset within the content (minus the borders)
+    let inner_height = chunks[0].height.saturating_sub(2) as usize;
+    let max_scroll = lines.len().saturating_sub(inner_height);
+    app.file_diff_scroll = app.file_diff_scroll.min(max_scroll);
+
+    let title = app.icons.text(&format!(
+        "Diff: {} ↔ {} ({:.0}% similar)",
+        get_node_display_path(&app.model.tree, diff.left),
+        get_node_display_path(&app.model.tree, diff.right),
+        diff.similarity * 100.0
+    ));
+    let diff_paragraph = Paragraph::new(lines)
+        .style(app.color_scheme.text)
+        .scroll((app.file_diff_scroll as u16, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(app.color_scheme.border),
+        );
+
+    let help_text = Paragraph::new(app.icons.text("↑/↓ PgUp/PgDn: Scroll | Esc: Close"))
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+
+    f.render_widget(diff_paragraph, chunks[0]);
+    f.render_widget(help_text, chunks[1]);
+}
+
+fn draw_exclusion_reason_dialog(f: &mut Frame, app: &App, area: Rect) {
+    let Some(tree_index) = app.reason_target else {
+        return;
+    };
+
+    let popup_area = centered_rect(60, 25, area);
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title("Exclusion Reason")
+        .borders(Borders::ALL)
+        .border_style(app.color_scheme.border)
+        .style(app.color_scheme.background);
+
+    let popup_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Length(2), // Instructions
+            Constraint::Length(3), // Input field
+            Constraint::Length(1), // Help text
+        ])
+        .split(popup_area);
+
+    let instructions = Paragraph::new(format!(
+        "Why is {} excluded? Saved in the rules file for the team:",
+        get_node_display_path(&app.model.tree, tree_index)
+    ))
+    .style(app.color_scheme.text)
+    .wrap(Wrap { trim: true });
+
+    let input = Paragraph::new(app.reason_input.as_str())
+        .style(app.color_scheme.text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(app.color_scheme.border)
+                .title("Reason"),
+        );
+
+    let help_text = Paragraph::new("Enter: Save (empty clears) | Esc: Cancel")
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+
+    f.render_widget(block, popup_area);
+    f.render_widget(instructions, popup_chunks[0]);
+    f.render_widget(input, popup_chunks[1]);
+    f.render_widget(help_text, popup_chunks[2]);
+
+    let max_x = popup_chunks[1].right().saturating_sub(2);
+    f.set_cursor(
+        (popup_chunks[1].x + app.reason_input.chars().count() as u16 + 1).min(max_x),
+        popup_chunks[1].y + 1,
+    );
 }
+
+fn draw_wizard_dialog(f: &mut Frame, app: &App, area: Rect) {
+    let Some(wizard) = &app.wizard else {
+        return;
+    };
+
+    let popup_area = centered_rect(60, 40, area);
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(format!("Set up gthr for this project ({})", wizard.progress()))
+        .borders(Borders::ALL)
+        .border_style(app.color_scheme.border)
+        .style(app.color_scheme.background);
+
+    let popup_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Length(2), // Question
+            Constraint::Min(3),    // Choices or input field
+            Constraint::Length(1), // Help text
+        ])
+        .split(popup_area);
+
+    let question = Paragraph::new(wizard.question())
+        .style(app.color_scheme.text)
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(block, popup_area);
+    f.render_widget(question, popup_chunks[0]);
+
+    let help = match wizard.choices() {
+        Some((choices, chosen)) => {
+            let items: Vec<ListItem> = choices
+                .iter()
+                .enumerate()
+                .map(|(position, choice)| {
+                    if position == chosen {
+                        let line = format!("{} {}", app.icons.cursor, choice);
+                        ListItem::new(line).style(app.color_scheme.text.add_modifier(Modifier::REVERSED))
+                    } else {
+                        ListItem::new(format!("  {}", choice)).style(app.color_scheme.text)
+                    }
+                })
+                .collect();
+            f.render_widget(List::new(items), popup_chunks[1]);
+            "↑/↓: Choose | Enter: Next | Esc: Skip setup"
+        }
+        None => {
+            let input_area = Rect { height: popup_chunks[1].height.min(3), ..popup_chunks[1] };
+            let input = Paragraph::new(wizard.token_budget.as_str())
+                .style(app.color_scheme.text)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(app.color_scheme.border)
+                        .title("Tokens"),
+                );
+            f.render_widget(input, input_area);
+            f.set_cursor(input_area.x + wizard.token_budget.len() as u16 + 1, input_area.y + 1);
+            "Digits: Budget | Enter: Next | Esc: Skip setup"
+        }
+    };
+
+    let help_text = Paragraph::new(app.icons.text(help))
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+    f.render_widget(help_text, popup_chunks[2]);
+}
+
+fn draw_command_dialog(f: &mut Frame, app: &App, area: Rect) {
+    let popup_area = centered_rect(60, 25, area);
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title("Command")
+        .borders(Borders::ALL)
+        .border_style(app.color_scheme.border)
+        .style(app.color_scheme.background);
+
+    let popup_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Length(2), // Instructions
+            Constraint::Length(3), // Input field
+            Constraint::Length(1), // Help text
+        ])
+        .split(popup_area);
+
+    let instructions = match &app.select_command {
+        Some(command) => format!(
+            "select-cmd CMD selects what CMD prints (alone: `{}` again), include/exclude QUERY, essentials, rules reload, root PATH",
+            command
+        ),
+        None => "select-cmd CMD selects the paths CMD prints, include/exclude QUERY changes what it matches, essentials, rules reload, root PATH".to_string(),
+    };
+    let instructions = Paragraph::new(instructions)
+        .style(app.color_scheme.text)
+        .wrap(Wrap { trim: true });
+
+    let input = Paragraph::new(format!(":{}", app.command_input))
+        .style(app.color_scheme.text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(app.color_scheme.border)
+                .title("Command"),
+        );
+
+    let help_text = Paragraph::new("Enter: Run | Esc: Cancel")
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+
+    f.render_widget(block, popup_area);
+    f.render_widget(instructions, popup_chunks[0]);
+    f.render_widget(input, popup_chunks[1]);
+    f.render_widget(help_text, popup_chunks[2]);
+}
+
+fn draw_change_root_dialog(f: &mut Frame, app: &App, area: Rect) {
+    let popup_area = centered_rect(60, 25, area);
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title("Open Root")
+        .borders(Borders::ALL)
+        .border_style(app.color_scheme.border)
+        .style(app.color_scheme.background);
+
+    let popup_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Length(2), // Instructions
+            Constraint::Length(3), // Input field
+            Constraint::Length(1), // Help text
+        ])
+        .split(popup_area);
+
+    let instructions = Paragraph::new("Directory or archive to scan instead (relative to the current root):")
+        .style(app.color_scheme.text)
+        .wrap(Wrap { trim: true });
+
+    let input = Paragraph::new(app.root_input.as_str())
+        .style(app.color_scheme.text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(app.color_scheme.border)
+                .title("Path"),
+        );
+
+    let help_text = Paragraph::new("Enter: Scan | Esc: Cancel")
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+
+    f.render_widget(block, popup_area);
+    f.render_widget(instructions, popup_chunks[0]);
+    f.render_widget(input, popup_chunks[1]);
+    f.render_widget(help_text, popup_chunks[2]);
+
+    let max_x = popup_chunks[1].right().saturating_sub(2);
+    f.set_cursor(
+        (popup_chunks[1].x + app.root_input.width() as u16 + 1).min(max_x),
+        popup_chunks[1].y + 1,
+    );
+}
+
+fn draw_too_small(f: &mut Frame, app: &App, area: Rect) {
+    f.render_widget(Clear, area);
+
+    let message = vec![
+        Line::from("Terminal too small"),
+        Line::from(app.icons.text(&format!("(needs {}×{})", MIN_WIDTH, MIN_HEIGHT))),
+    ];
+
+    // Vertically center the message when there is room for it
+    let top = area.y + area.height.saturating_sub(message.len() as u16) / 2;
+    let message_area = Rect::new(area.x, top, area.width, area.bottom().saturating_sub(top));
+
+    let paragraph = Paragraph::new(message)
+        .style(app.color_scheme.text)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(paragraph, message_area);
+}
+
+fn draw_export_diff_dialog(f: &mut Frame, app: &mut App, area: Rect) {
+    let popup_area = centered_rect(70, 70, area);
+    f.render_widget(Clear, popup_area);
+
+    let mut lines = Vec::new();
+    match (&app.session.last_export, &app.export_diff) {
+        (Some(snapshot), Some(diff)) => {
+            lines.push(Line::from(format!("Last export: {}", snapshot.exported_at)));
+            lines.push(Line::from(""));
+
+            if diff.is_empty() {
+                lines.push(Line::from("No changes since the last export."));
+            }
+
+            let sections = [
+                ("Added", "+", &diff.added, app.color_scheme.included),
+                ("Removed", "-", &diff.removed, app.color_scheme.excluded),
+                ("Modified", "~", &diff.modified, app.color_scheme.partial),
+            ];
+            for (title, marker, entries, style) in sections {
+                if entries.is_empty() {
+                    continue;
+                }
+                lines.push(Line::from(format!("{} ({}):", title, entries.len())));
+                for (path, size) in entries {
+                    lines.push(Line::from(vec![
+                        Span::styled(format!("  {} {}", marker, path), style),
+                        Span::styled(format!(" ({})", format_file_size(*size)), app.color_scheme.help_text),
+                    ]));
+                }
+                lines.push(Line::from(""));
+            }
+        }
+        _ => lines.push(Line::from("No export recorded for this directory yet.")),
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(popup_area);
+
+    // Keep the scroll offset within the content (minus the borders)
+    let inner_height = chunks[0].height.saturating_sub(2) as usize;
+    let max_scroll = lines.len().saturating_sub(inner_height);
+    app.export_diff_scroll = app.export_diff_scroll.min(max_scroll);
+
+    let diff_paragraph = Paragraph::new(lines)
+        .style(app.color_scheme.text)
+        .scroll((app.export_diff_scroll as u16, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Changes Since Last Export")
+                .border_style(app.color_scheme.border),
+        );
+
+    let help_text = app
+        .export_diff_note
+        .clone()
+        .unwrap_or_else(|| "↑/↓: Scroll | r: Re-export if changed | Esc: Close".to_string());
+    let help_text = app.icons.text(&help_text);
+    let help_paragraph = Paragraph::new(help_text)
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+
+    f.render_widget(diff_paragraph, chunks[0]);
+    f.render_widget(help_paragraph, chunks[1]);
+}
+
+fn draw_smart_include_dialog(f: &mut Frame, app: &App, area: Rect) {
+    let Some(smart_include) = &app.smart_include else {
+        return;
+    };
+
+    let popup_area = centered_rect(60, 50, area);
+    f.render_widget(Clear, popup_area);
+
+    let items: Vec<ListItem> = smart_include
+        .candidates
+        .iter()
+        .enumerate()
+        .map(|(position, (tree_index, checked))| {
+            let (marker, style) = if *checked {
+                (app.icons.included, app.color_scheme.included)
+            } else {
+                (app.icons.excluded, app.color_scheme.excluded)
+            };
+            let mut line = vec![
+                Span::styled(format!("{} ", marker), style),
+                Span::styled(get_node_display_path(&app.model.tree, *tree_index), app.color_scheme.text),
+            ];
+            if app.model.tree.get_node(*tree_index).is_some_and(|node| node.state == SelectionState::Included) {
+                line.push(Span::styled(" (already included)", app.color_scheme.help_text));
+            }
+            let item = ListItem::new(Line::from(line));
+            if position == smart_include.selected {
+                item.style(app.color_scheme.text.add_modifier(Modifier::REVERSED))
+            } else {
+                item
+            }
+        })
+        .collect();
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(popup_area);
+
+    let title = format!(
+        "Dependencies of {}",
+        get_node_display_path(&app.model.tree, smart_include.source_index)
+    );
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(app.color_scheme.border),
+    );
+
+    let help_paragraph = Paragraph::new("Space: Toggle | Enter: Include checked | Esc: Cancel")
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+
+    f.render_widget(list, chunks[0]);
+    f.render_widget(help_paragraph, chunks[1]);
+}
+
+fn draw_suggestions_dialog(f: &mut Frame, app: &App, area: Rect) {
+    let Some(suggestions) = &app.suggestions else {
+        return;
+    };
+
+    let popup_area = centered_rect(70, 60, area);
+    f.render_widget(Clear, popup_area);
+
+    let items: Vec<ListItem> = suggestions
+        .items
+        .iter()
+        .enumerate()
+        .map(|(position, suggestion)| {
+            let key = if position < 9 { format!("{} ", position + 1) } else { "  ".to_string() };
+            let line = Line::from(vec![
+                Span::styled(key, app.color_scheme.help_text),
+                Span::styled(format!("~{:>6} ", format_tokens(suggestion.tokens)), app.color_scheme.text),
+                Span::styled(get_node_display_path(&app.model.tree, suggestion.index), app.color_scheme.text),
+                Span::styled(app.icons.text(&format!(" — {}", suggestion.reason)), app.color_scheme.help_text),
+            ]);
+            let item = ListItem::new(line);
+            if position == suggestions.selected {
+                item.style(app.color_scheme.text.add_modifier(Modifier::REVERSED))
+            } else {
+                item
+            }
+        })
+        .collect();
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(popup_area);
+
+    // The projected total follows every exclusion
+    let budget = app.token_budget.unwrap_or(0);
+    let tokens = app.projected_tokens();
+    let status = if tokens > budget { format!("{} over budget", app.icons.warning) } else { "within budget".to_string() };
+    let title = format!("Exclude to fit: ~{}/{} tokens, {}", format_tokens(tokens), format_tokens(budget), status);
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(app.icons.text(&title))
+            .border_style(app.color_scheme.border),
+    );
+
+    let help_paragraph = Paragraph::new("1-9/Enter: Exclude | ↑/↓: Move | Esc: Close")
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+
+    f.render_widget(list, chunks[0]);
+    f.render_widget(help_paragraph, chunks[1]);
+}
+
+fn draw_export_order_dialog(f: &mut Frame, app: &App, area: Rect) {
+    let Some(order) = &app.export_order else {
+        return;
+    };
+
+    let popup_area = centered_rect(70, 60, area);
+    f.render_widget(Clear, popup_area);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(popup_area);
+
+    // Keep the highlight in view: the list inside the borders scrolls with it
+    let files = app.export_order_files();
+    let height = chunks[0].height.saturating_sub(2).max(1) as usize;
+    let offset = order.selected.saturating_sub(height - 1);
+    let width = files.len().to_string().len();
+    let items: Vec<ListItem> = files
+        .iter()
+        .enumerate()
+        .skip(offset)
+        .take(height)
+        .map(|(position, &index)| {
+            let placed = app.model.tree.manual_order.contains(&index);
+            let line = Line::from(vec![
+                Span::styled(format!("{:>width$} ", position + 1, width = width), app.color_scheme.help_text),
+                Span::styled(if placed { "* " } else { "  " }, app.color_scheme.included),
+                Span::styled(get_node_display_path(&app.model.tree, index), app.color_scheme.text),
+            ]);
+            let item = ListItem::new(line);
+            match (position == order.selected, order.moving) {
+                (true, true) => item.style(app.color_scheme.text.add_modifier(Modifier::REVERSED | Modifier::BOLD)),
+                (true, false) => item.style(app.color_scheme.text.add_modifier(Modifier::REVERSED)),
+                _ => item,
+            }
+        })
+        .collect();
+
+    let title = if order.moving { "Export order: moving" } else { "Export order (* placed by hand)" };
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(app.color_scheme.border),
+    );
+
+    let help = if order.moving {
+        "↑/↓: Move file | M/Enter/Esc: Done"
+    } else {
+        "M/Enter: Move file | ↑/↓: Select | c: Clear order | Esc: Close"
+    };
+    let help_paragraph = Paragraph::new(app.icons.text(help))
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+
+    f.render_widget(list, chunks[0]);
+    f.render_widget(help_paragraph, chunks[1]);
+}
+
+fn draw_recipes_dialog(f: &mut Frame, app: &App, area: Rect) {
+    let popup_area = centered_rect(60, 50, area);
+    f.render_widget(Clear, popup_area);
+
+    let items: Vec<ListItem> = app
+        .recipes
+        .iter()
+        .enumerate()
+        .map(|(position, recipe)| {
+            let line = Line::from(vec![
+                Span::styled(recipe.name.clone(), app.color_scheme.text.add_modifier(Modifier::BOLD)),
+                Span::styled(app.icons.text(&format!(" — {}", recipe.summary())), app.color_scheme.help_text),
+            ]);
+            let item = ListItem::new(line);
+            if position == app.recipe_choice {
+                item.style(app.color_scheme.text.add_modifier(Modifier::REVERSED))
+            } else {
+                item
+            }
+        })
+        .collect();
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(popup_area);
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Recipes")
+            .border_style(app.color_scheme.border),
+    );
+
+    let help_paragraph = Paragraph::new("Enter: Apply and export | Esc: Cancel")
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+
+    f.render_widget(list, chunks[0]);
+    f.render_widget(help_paragraph, chunks[1]);
+}
+
+fn draw_renames_dialog(f: &mut Frame, app: &App, area: Rect) {
+    let popup_area = centered_rect(60, 50, area);
+    f.render_widget(Clear, popup_area);
+
+    let items: Vec<ListItem> = app
+        .pending_renames
+        .iter()
+        .map(|rename| {
+            ListItem::new(Line::from(vec![
+                Span::styled(rename.from.clone(), app.color_scheme.excluded),
+                Span::styled(" -> ", app.color_scheme.help_text),
+                Span::styled(rename.to.clone(), app.color_scheme.included),
+            ]))
+        })
+        .collect();
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(popup_area);
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Missing set paths that look renamed (same size and content)")
+            .border_style(app.color_scheme.border),
+    );
+
+    let help_paragraph = Paragraph::new("Enter: Remap and include | Esc: Keep as missing")
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+
+    f.render_widget(list, chunks[0]);
+    f.render_widget(help_paragraph, chunks[1]);
+}
+
+fn draw_whats_new_dialog(f: &mut Frame, app: &mut App, area: Rect) {
+    let popup_area = centered_rect(70, 70, area);
+    f.render_widget(Clear, popup_area);
+
+    let mut lines = Vec::new();
+    for release in &app.whats_new {
+        lines.push(Line::from(Span::styled(format!("gthr {}", release.version), app.color_scheme.partial)));
+        for change in release.changes {
+            lines.push(Line::from(format!("  - {}", change)));
+        }
+        lines.push(Line::from(""));
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(popup_area);
+
+    // Long changes wrap; count the rows they take (word wrapping may need a few more)
+    let inner_width = chunks[0].width.saturating_sub(2).max(1) as usize;
+    let inner_height = chunks[0].height.saturating_sub(2) as usize;
+    let rows: usize = lines.iter().map(|line| line.width().div_ceil(inner_width).max(1)).sum();
+    let max_scroll = rows.saturating_sub(inner_height);
+    app.whats_new_scroll = app.whats_new_scroll.min(max_scroll);
+
+    let title = match app.whats_new.first() {
+        Some(release) => format!("What's New in {}", release.version),
+        None => "What's New".to_string(),
+    };
+    let changes_paragraph = Paragraph::new(lines)
+        .style(app.color_scheme.text)
+        .wrap(Wrap { trim: false })
+        .scroll((app.whats_new_scroll as u16, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(app.color_scheme.border),
+        );
+
+    let help_paragraph = Paragraph::new(app.icons.text("↑/↓: Scroll | Any other key: Close"))
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+
+    f.render_widget(changes_paragraph, chunks[0]);
+    f.render_widget(help_paragraph, chunks[1]);
+}
+
+fn draw_set_conflict_dialog(f: &mut Frame, app: &mut App, area: Rect) {
+    let Some(conflict) = &mut app.set_conflict else {
+        return;
+    };
+    let popup_area = centered_rect(70, 60, area);
+    f.render_widget(Clear, popup_area);
+
+    let (only_ours, only_theirs) = conflict.ours.diff(&conflict.theirs);
+    let mut lines = vec![
+        Line::from(format!("{} was saved by someone else since it was loaded.", conflict.ours.path().display())),
+        Line::from(format!(
+            "Yours: {} paths, on disk: {} paths ({} only in yours, {} only on disk)",
+            conflict.ours.entries().len(),
+            conflict.theirs.entries().len(),
+            only_ours.len(),
+            only_theirs.len()
+        )),
+    ];
+    if conflict.show_diff {
+        lines.push(Line::from(""));
+        lines.extend(only_ours.iter().map(|path| Line::from(Span::styled(format!("+ {}", path), app.color_scheme.included))));
+        lines.extend(only_theirs.iter().map(|path| Line::from(Span::styled(format!("- {}", path), app.color_scheme.excluded))));
+        if only_ours.is_empty() && only_theirs.is_empty() {
+            lines.push(Line::from(match conflict.ours {
+                SavedSelection::Set(_) => "Same paths; only ranges or notes differ",
+                SavedSelection::Profile(_) => "Same states; only locks, order or reasons differ",
+            }));
+        }
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(popup_area);
+
+    let inner_height = chunks[0].height.saturating_sub(2) as usize;
+    conflict.scroll = conflict.scroll.min(lines.len().saturating_sub(inner_height));
+    let paragraph = Paragraph::new(lines)
+        .style(app.color_scheme.text)
+        .scroll((conflict.scroll as u16, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("{} changed on disk", conflict.ours.kind()))
+                .border_style(app.color_scheme.border),
+        );
+
+    let new_name = conflict.ours.free_path();
+    let new_name = new_name.file_name().unwrap_or_default().to_string_lossy();
+    let help = if conflict.show_diff { "d: Hide diff" } else { "d: Diff" };
+    let help_paragraph = Paragraph::new(format!("o: Overwrite | n: Save as {} | {} | Esc: Cancel", new_name, help))
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+
+    f.render_widget(paragraph, chunks[0]);
+    f.render_widget(help_paragraph, chunks[1]);
+}
+
+fn draw_info_dialog(f: &mut Frame, app: &App, area: Rect) {
+    let Some(info) = &app.node_info else {
+        return;
+    };
+
+    let popup_area = centered_rect(60, 50, area);
+    f.render_widget(Clear, popup_area);
+
+    let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+    let exact_size = |size: u64| format!("{} bytes ({})", size, format_file_size(size));
+    let mut rows: Vec<(&str, String)> = Vec::new();
+
+    if let Some(size) = info.size.filter(|_| !info.is_directory) {
+        rows.push(("Size", exact_size(size)));
+    }
+    if info.is_directory {
+        rows.push(("Included", format!("{} in {} file(s)", exact_size(info.included_size), info.included_files)));
+    }
+    if let Some(line_count) = info.line_count {
+        rows.push(("Lines", line_count.to_string()));
+    }
+    if let Some(modified) = info.modified {
+        rows.push(("Modified", modified.format("%Y-%m-%d %H:%M:%S").to_string()));
+    }
+    if let Some(permissions) = &info.permissions {
+        rows.push(("Permissions", permissions.clone()));
+    }
+    if !info.is_directory {
+        let language = if info.language.is_empty() { "unknown" } else { info.language };
+        rows.push(("Language", language.to_string()));
+        rows.push(("Binary", yes_no(info.is_binary).to_string()));
+        rows.push(("Generated", yes_no(info.is_generated).to_string()));
+    }
+    rows.push(("Path", info.path.display().to_string()));
+
+    let lines: Vec<Line> = rows
+        .into_iter()
+        .map(|(label, value)| {
+            Line::from(vec![
+                Span::styled(format!("{:<12}", label), app.color_scheme.help_text),
+                Span::styled(app.icons.text(&value), app.color_scheme.text),
+            ])
+        })
+        .collect();
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(popup_area);
+
+    let title = format!("Info: {}", get_node_display_path(&app.model.tree, info.tree_index));
+    let info_paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(app.icons.text(&title))
+                .border_style(app.color_scheme.border),
+        )
+        .wrap(Wrap { trim: false });
+
+    let help_paragraph = Paragraph::new("Esc: Close")
+        .style(app.color_scheme.help_text)
+        .alignment(Alignment::Center);
+
+    f.render_widget(info_paragraph, chunks[0]);
+    f.render_widget(help_paragraph, chunks[1]);
+}
+
+fn format_file_size(size: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut size_f = size as f64;
+    let mut unit_index = 0;
+
+    while size_f >= 1024.0 && unit_index < UNITS.len() - 1 {
+        size_f /= 1024.0;
+        unit_index += 1;
+    }
+
+    if unit_index == 0 {
+        format!("{} {}", size, UNITS[unit_index])
+    } else {
+        format!("{:.1} {}", size_f, UNITS[unit_index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directory::tree::DirectoryTree;
+    use crate::ui::colors::Theme;
+    use std::time::{Duration, Instant};
+    use ratatui::{Terminal, backend::TestBackend};
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn render(app: &mut App, width: u16, height: u16) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|f| draw_ui(f, app)).unwrap();
+
+        let buffer = terminal.backend().buffer();
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| buffer.get(x, y).symbol.as_str())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    fn test_app() -> (TempDir, App) {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+
+        let mut tree = DirectoryTree::new(temp_dir.path().to_path_buf());
+        let root_path = temp_dir.path().to_path_buf();
+        tree.add_node(root_path.join("main.rs"), false, &root_path);
+
+        (temp_dir, App::new(tree))
+    }
+
+    #[test]
+    fn test_too_small_20x5() {
+        let (_temp_dir, mut app) = test_app();
+        let lines = render(&mut app, 20, 5);
+        assert_eq!(
+            lines,
+            vec![
+                "",
+                " Terminal too small",
+                "    (needs 40×10)",
+                "",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_too_small_39x9() {
+        let (_temp_dir, mut app) = test_app();
+        let lines = render(&mut app, 39, 9);
+        assert_eq!(
+            lines,
+            vec![
+                "",
+                "",
+                "",
+                "          Terminal too small",
+                "             (needs 40×10)",
+                "",
+                "",
+                "",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_status_bar_names_format() {
+        let (_temp_dir, mut app) = test_app();
+        let root_index = app.model.tree.root_index;
+        app.model.tree.set_state(root_index, SelectionState::Included);
+        app.model.recompute_totals(&app.formatter.clone());
+
+        let status = |app: &mut App| render(app, 200, 10)[8].clone();
+        let line = status(&mut app);
+        assert!(line.contains("Ctrl+E: Export markdown | F: Format | Ctrl+H: Help│"), "{}", line);
+        app.cycle_format();
+        app.status_message = None;
+        assert!(status(&mut app).contains("Ctrl+E: Export plain"));
+    }
+
+    #[test]
+    fn test_status_bar_tokens() {
+        let (_temp_dir, mut app) = test_app();
+        assert!(!render(&mut app, 200, 10)[8].contains("Tokens"));
+        let root_index = app.model.tree.root_index;
+        app.model.tree.set_state(root_index, SelectionState::Included);
+        app.model.recompute_totals(&app.formatter.clone());
+        let tokens = format_tokens(app.get_stats().tokens);
+        assert!(render(&mut app, 200, 10)[8].contains(&format!("Filtered: 2 | Tokens: ~{} ", tokens)));
+
+        // Past the budget the estimate turns red
+        let token_color = |app: &mut App| {
+            let mut terminal = Terminal::new(TestBackend::new(200, 10)).unwrap();
+            terminal.draw(|f| draw_ui(f, app)).unwrap();
+            let buffer = terminal.backend().buffer();
+            let row: String = (0..200).map(|x| buffer.get(x, 8).symbol.as_str()).collect();
+            let column = row[..row.find("Tokens").unwrap()].chars().count() as u16;
+            buffer.get(column, 8).fg
+        };
+        app.token_budget = Some(1_000);
+        assert_eq!(token_color(&mut app), ratatui::style::Color::White);
+        app.token_budget = Some(1);
+        assert_eq!(token_color(&mut app), ratatui::style::Color::Red);
+    }
+
+    #[test]
+    fn test_minimum_size_40x10() {
+        let (_temp_dir, mut app) = test_app();
+        let lines = render(&mut app, 40, 10);
+
+        // Search collapses to one row, the list keeps its borders, and the
+        // status bar drops the key hints to keep the stats readable
+        assert_eq!(lines[0], "Search: Press / to search files and dire");
+        assert!(lines[1].starts_with("┌Files and Directories"));
+        assert!(lines[2].contains("▶ ✗"));
+        assert!(lines[3].contains("main.rs"));
+        assert!(lines[7].starts_with("┌"));
+        assert_eq!(lines[8], "│Files: 0/1 | Size: 0 B | Filtered: 2  │");
+        assert_eq!(app.view.viewport_height, 4);
+
+        // Popups must not underflow either
+        app.start_file_save("content".to_string());
+        app.file_save_input = "a-very-long-output-file-name.md".to_string();
+        render(&mut app, 40, 10);
+        app.whats_new = crate::changelog::RELEASES.iter().collect();
+        let set = crate::config::selection_set::SelectionSet::parse("a.rs\nb.rs\n", std::path::Path::new("/project/.gthr-set")).unwrap();
+        app.set_conflict = Some(crate::ui::app::SetConflict {
+            ours: SavedSelection::Set(set.clone()),
+            theirs: SavedSelection::Set(crate::config::selection_set::SelectionSet { path: set.path.clone(), ..Default::default() }),
+            show_diff: true,
+            scroll: 10,
+        });
+        app.suggestions = Some(crate::ui::app::ExclusionSuggestions {
+            items: vec![crate::directory::suggest::Suggestion {
+                index: 1,
+                tokens: 12_345,
+                reason: crate::directory::suggest::Reason::Deep(7),
+            }],
+            selected: 0,
+        });
+        app.token_budget = Some(1_000);
+        for mode in [AppMode::Help, AppMode::ExportDiff, AppMode::WhatsNew, AppMode::SetConflict, AppMode::Suggestions, AppMode::Preview, AppMode::Order] {
+            app.set_mode(mode);
+            render(&mut app, 40, 10);
+        }
+    }
+
+    #[test]
+    fn test_path_column_fixed() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let build_tree = || {
+            let mut tree = DirectoryTree::new(root.clone());
+            tree.add_node(root.join("dir"), true, &root);
+            let [sized, _, sticky, locked, pending] =
+                ["dir/sized.rs", "dir/plain.rs", "sticky.rs", "locked.rs", "pending.rs"].map(|name| {
+                    let path = root.join(name);
+                    tree.add_node(path.clone(), false, path.parent().unwrap()).unwrap()
+                });
+            tree.nodes[sized].size = Some(2_048);
+            tree.nodes[sticky].sticky = true;
+            tree.nodes[locked].locked = true;
+            tree.nodes[pending].metadata_pending = true;
+            tree.set_state(sized, SelectionState::Included);
+            tree.set_state(sticky, SelectionState::Included);
+            tree
+        };
+
+        for icons in [&crate::ui::icons::UNICODE_ICONS, &crate::ui::icons::ASCII_ICONS] {
+            let expected = 1 + icons.columns().iter().sum::<usize>(); // After the list's border
+            let mut app = App::new(build_tree()).with_icons(icons);
+            app.move_down(); // Cursor off the root and onto a row with other indicators
+            let mut terminal = Terminal::new(TestBackend::new(80, 16)).unwrap();
+            terminal.draw(|f| draw_ui(f, &mut app)).unwrap();
+            let buffer = terminal.backend().buffer();
+
+            // Every row's path starts in the same cell, whether it's a file or a directory,
+            // under the cursor or not, and whatever its state or trailing indicators
+            let mut seen = 0;
+            for y in 0..16 {
+                let cells: Vec<&str> = (0..80).map(|x| buffer.get(x, y).symbol.as_str()).collect();
+                for name in ["dir", "dir/sized.rs", "dir/plain.rs", "sticky.rs", "locked.rs", "pending.rs"] {
+                    let width = name.len();
+                    let found = (0..cells.len() - width).find(|&x| {
+                        cells[x..x + width].concat() == name && cells.get(x + width).is_none_or(|next| next.trim().is_empty())
+                    });
+                    if let Some(x) = found {
+                        assert_eq!(x, expected, "{:?} in {:?}", name, cells.concat());
+                        seen += 1;
+                    }
+                }
+            }
+            assert_eq!(seen, 6);
+        }
+    }
+
+    #[test]
+    fn test_search_title() {
+        let (_temp_dir, mut app) = test_app();
+        assert!(render(&mut app, 60, 20)[0].contains("Search [fuzzy]"));
+        app.cycle_match_mode();
+        app.cycle_match_mode();
+        app.add_search_char('(');
+        assert!(render(&mut app, 60, 20)[0].contains("Search [regex] invalid regex"));
+        // Without room for borders the mode goes in the label
+        assert!(render(&mut app, 60, 12)[0].starts_with("Search [regex]: ("));
+    }
+
+    #[test]
+    fn test_size_column() {
+        let root = std::path::PathBuf::from("/project");
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("src"), true, &root);
+        let sizes = [("src/main.rs", 12_345), ("src/a_rather_long_file_name_that_cannot_fit_anywhere.rs", 80), ("src/lib.rs", 2_048)];
+        for (path, size) in sizes {
+            let index = tree.add_node(root.join(path), false, &root.join("src")).unwrap();
+            tree.nodes[index].is_text_file = true;
+            tree.nodes[index].size = Some(size);
+        }
+        let pending = tree.add_node(root.join("NOTES.md"), false, &root).unwrap();
+        tree.nodes[pending].metadata_pending = true;
+        let mut app = App::new(tree);
+
+        // Every size ends at the right edge of the list, directories' totals included, with
+        // `…` for a size still to come and long paths cut short to make room
+        let rows = |app: &mut App, width: u16| -> Vec<String> {
+            render(app, width, 12)[2..8].iter().map(|row| row.trim_start_matches('│').to_string()).collect()
+        };
+        let listed = rows(&mut app, 60);
+        let sizes = ["…", "14.1 KB", "12.1 KB", "80 B", "2.0 KB", "…"];
+        for (row, size) in listed.iter().zip(sizes) {
+            let row = row.strip_suffix('│').unwrap_or_else(|| panic!("{}", row));
+            assert!(row.ends_with(&format!(" {}", size)), "{}", row);
+            assert_eq!(row.width(), listed[1].width() - 1, "{}", row);
+        }
+        assert!(listed[3].contains("src/a_rather_long_file_name_that_cannot_…"), "{}", listed[3]);
+
+        // A narrow list leaves sizes out and shows as much of the path as fits
+        app.size_column_min_width = 80;
+        let listed = rows(&mut app, 60);
+        assert!(listed.iter().all(|row| !row.contains("KB") && !row.contains('…')), "{:?}", listed);
+        assert!(listed[3].contains("src/a_rather_long_file_name_that_cannot_fit_anyw"), "{}", listed[3]);
+    }
+
+    #[test]
+    fn test_tree_view_rows() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let mut tree = DirectoryTree::new(root.clone());
+        tree.add_node(root.join("src"), true, &root);
+        let lib = tree.add_node(root.join("src/lib.rs"), false, &root.join("src")).unwrap();
+        tree.nodes[lib].is_text_file = true;
+        tree.nodes[lib].size = Some(2_048);
+        tree.set_state(lib, SelectionState::Included);
+        let mut app = App::new(tree);
+        app.toggle_tree_view();
+
+        // Names indented under their directory, with sizes and states as in the list of paths
+        let rows = |app: &mut App| -> Vec<String> {
+            render(app, 60, 12)[2..5].iter().map(|row| row.trim_matches('│').trim_end().to_string()).collect()
+        };
+        let name = root.file_name().unwrap().to_string_lossy().to_string();
+        let split = |row: &String| match row.rsplit_once("  ") {
+            Some((left, size)) => (left.trim_end().to_string(), size.to_string()),
+            None => (row.clone(), String::new()),
+        };
+        let (names, sizes): (Vec<_>, Vec<_>) = rows(&mut app).iter().map(split).unzip();
+        assert_eq!(names, [format!("▶ ✓ 📁  ▾ {}", name), "  ✓ 📁    ▾ src".to_string(), "  ✓ 📄        lib.rs".to_string()]);
+        assert_eq!(sizes, ["2.0 KB"; 3]);
+
+        app.move_down();
+        app.collapse_directory();
+        assert_eq!(split(&rows(&mut app)[1]).0, "▶ ✓ 📁    ▸ src");
+        assert_eq!(rows(&mut app)[2], "");
+    }
+
+    #[test]
+    fn test_preview_pane() {
+        let (temp_dir, mut app) = test_app();
+        let lines: String = (1..=300).map(|line| format!("line {}\n", line)).collect();
+        fs::write(temp_dir.path().join("main.rs"), lines).unwrap();
+        app.preview_debounce = Duration::ZERO;
+        app.toggle_preview();
+        assert_eq!(app.view.mode, AppMode::Preview);
+
+        // Nothing is read until the cursor is on the file
+        render(&mut app, 100, 20);
+        assert_eq!(app.preview_cache.as_ref().map(|preview| preview.tree_index), Some(app.model.tree.root_index));
+        app.move_down();
+        let started = Instant::now();
+        while app.preview_cache.as_ref().is_none_or(|preview| preview.content == PreviewContent::Loading) {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            app.load_hovered_preview();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let lines = render(&mut app, 100, 20);
+        assert!(lines[3].contains("Preview: main.rs"));
+        assert!(lines[4].ends_with("││  line 1                                        │"));
+
+        // Scrolling stops at the last previewed line and starts over on another file
+        app.scroll_diff(5);
+        let lines = render(&mut app, 100, 20);
+        assert!(lines[4].ends_with("││  line 6                                        │"));
+        app.scroll_diff(1_000);
+        assert_eq!(app.preview_scroll, crate::ui::preview::PREVIEW_MAX_LINES - 1);
+        app.move_up();
+        render(&mut app, 100, 20);
+        assert_eq!(app.preview_scroll, 0);
+
+        app.handle_escape();
+        assert_eq!(app.view.mode, AppMode::Main);
+    }
+
+    #[test]
+    fn test_export_order_dialog() {
+        let (temp_dir, mut app) = test_app();
+        let root = temp_dir.path().to_path_buf();
+        for name in ["lib.rs", "util.rs"] {
+            fs::write(root.join(name), "").unwrap();
+            app.model.tree.add_node(root.join(name), false, &root);
+        }
+        app.start_export_order();
+        assert_eq!(app.view.mode, AppMode::Main); // Nothing included yet
+
+        let root_index = app.model.tree.root_index;
+        app.model.tree.set_state(root_index, SelectionState::Included);
+        app.model.tree.manual_order = vec![app.model.tree.path_to_index[&root.join("util.rs")]];
+        app.move_down(); // Cursor onto main.rs
+        app.start_export_order();
+        assert_eq!(app.view.mode, AppMode::Order);
+        assert_eq!(app.export_order.as_ref().unwrap().selected, 1);
+
+        let lines = render(&mut app, 80, 24);
+        let rows: Vec<&String> = lines.iter().filter(|line| line.contains(".rs")).collect();
+        assert!(rows[0].contains("1 * util.rs"), "{:?}", rows);
+        assert!(rows[1].contains("2   main.rs"), "{:?}", rows);
+        assert!(rows[2].contains("3   lib.rs"), "{:?}", rows);
+
+        // Esc leaves move mode first, then closes
+        app.move_in_export_order(5);
+        assert_eq!(app.export_order.as_ref().unwrap().selected, 2);
+        app.toggle_order_moving();
+        assert!(render(&mut app, 80, 24).iter().any(|line| line.contains("Export order: moving")));
+        app.handle_escape();
+        assert_eq!(app.view.mode, AppMode::Order);
+        app.handle_escape();
+        assert_eq!(app.view.mode, AppMode::Main);
+        assert!(app.export_order.is_none());
+    }
+
+    #[test]
+    fn test_compare_view() {
+        let (_temp_dir, mut app) = test_app();
+        app.move_down(); // Cursor onto main.rs
+        app.toggle_compare_pin();
+        assert!(app.compare_pin.is_some());
+
+        let lines = render(&mut app, 100, 20);
+        assert!(lines[3].contains("Pinned: main.rs"));
+        assert!(lines[3].contains("Cursor: main.rs"));
+        assert!(lines[4].contains("fn main() {}"));
+
+        app.handle_escape();
+        assert!(app.compare_pin.is_none());
+    }
+
+    #[test]
+    fn test_states_without_color() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src/a.rs"), "fn a() {}\nfn shared() {}\n").unwrap();
+        fs::write(root.join("src/b.rs"), "fn b() {}\nfn shared() {}\n").unwrap();
+        let build_tree = || {
+            let mut tree = DirectoryTree::new(root.clone());
+            tree.add_node(root.join("src"), true, &root);
+            let a = tree.add_node(root.join("src/a.rs"), false, &root.join("src")).unwrap();
+            tree.add_node(root.join("src/b.rs"), false, &root.join("src"));
+            tree.set_state(a, SelectionState::Included);
+            tree
+        };
+
+        // With colors stripped, every state still reads differently: the snapshot is of
+        // symbols only, and is the same under either theme
+        let mut snapshots = Vec::new();
+        for theme in [Theme::Default, Theme::Colorblind] {
+            let mut app = App::new(build_tree()).with_theme(theme);
+            snapshots.push(render(&mut app, 60, 12));
+        }
+        assert_eq!(snapshots[0], snapshots[1]);
+        let rows: Vec<&str> = snapshots[0][3..6]
+            .iter()
+            .map(|row| row.trim_start_matches('│').trim_end_matches('│').trim_end_matches("0 B").trim_end())
+            .collect();
+        assert_eq!(rows, ["  ◐ 📁  src", "  ✓ 📄  src/a.rs", "  ✗ 📄  src/b.rs"]);
+
+        // Lines found on only one side of the compare view are marked in the gutter
+        let mut app = App::new(build_tree());
+        app.move_down();
+        app.move_down(); // Cursor onto src/a.rs
+        app.toggle_compare_pin();
+        app.move_down();
+        app.preview_debounce = Duration::ZERO;
+        let started = Instant::now();
+        while app.preview_cache.as_ref().is_none_or(|preview| preview.content == PreviewContent::Loading) {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            app.load_hovered_preview();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let lines = render(&mut app, 100, 20);
+        assert!(lines[4].contains("│› fn a() {}"));
+        assert!(lines[4].ends_with("│› fn b() {}                 │"));
+        assert!(lines[5].contains("│  fn shared() {}"));
+    }
+}
+
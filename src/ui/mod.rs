@@ -1,5 +1,13 @@
 pub mod app;
 pub mod interface;
 pub mod events;
+pub mod icons;
+pub mod info;
+pub mod file_diff;
+pub mod hyperlink;
+pub mod terminal_title;
 pub mod colors;
+pub mod preview;
+pub mod view;
+pub mod wizard;
 
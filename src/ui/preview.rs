@@ -0,0 +1,739 @@
+use crate::output::formatter::format_file_size;
+use crate::output::tokens::{estimate_tokens, format_tokens};
+use crate::ui::info::{LineCount, count_file_lines, count_lines};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::time::SystemTime;
+
+/// Number of lines shown for a previewed file
+pub const PREVIEW_MAX_LINES: usize = 200;
+/// Upper bound on bytes read for a preview, so huge files don't stall the UI
+const PREVIEW_MAX_BYTES: usize = 256 * 1024;
+/// Structured files larger than this are shown as raw text instead of being parsed
+const STRUCTURED_MAX_BYTES: u64 = 1024 * 1024;
+
+/// Size of each read while loading a preview, between checks for cancellation
+const READ_CHUNK_BYTES: usize = 64 * 1024;
+/// Default for the `preview_cache_bytes` setting
+pub const PREVIEW_CACHE_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreviewContent {
+    Text(Vec<String>),
+    Binary,
+    Directory,
+    Error(String),
+    Loading, // Waiting for the cursor to rest, or for the background read
+}
+
+/// File formats that get formatted or highlighted in the preview
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreviewFormat {
+    Plain,
+    Json,
+    Yaml,
+    Toml,
+    Markdown,
+}
+
+impl PreviewFormat {
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "json" => PreviewFormat::Json,
+            "yaml" | "yml" => PreviewFormat::Yaml,
+            "toml" => PreviewFormat::Toml,
+            "md" | "markdown" => PreviewFormat::Markdown,
+            _ => PreviewFormat::Plain,
+        }
+    }
+
+    /// Formats that are parsed before display rather than just highlighted
+    pub fn is_structured(self) -> bool {
+        matches!(self, PreviewFormat::Json | PreviewFormat::Toml)
+    }
+}
+
+/// How a piece of a preview line should be styled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpanKind {
+    Plain,
+    Key,
+    Heading,
+    Code,
+    Comment,
+}
+
+/// A loaded preview, remembered by tree index so redraws don't re-read the file
+#[derive(Debug, Clone)]
+pub struct FilePreview {
+    pub tree_index: usize,
+    pub content: PreviewContent,
+    pub format: PreviewFormat,
+    pub note: Option<String>, // Why a structured file is shown as raw text
+    pub contribution: Option<Contribution>, // Text files only
+}
+
+/// What a whole file adds to an export, not just the previewed head
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contribution {
+    pub bytes: u64,
+    pub lines: usize,
+    pub approximate: bool, // Lines extrapolated from the head of a gigantic file
+}
+
+impl Contribution {
+    fn of_bytes(bytes: &[u8]) -> Self {
+        Self {
+            bytes: bytes.len() as u64,
+            lines: count_lines(bytes),
+            approximate: false,
+        }
+    }
+
+    /// A file whose line count the content cache has, unchanged since it was recorded
+    fn of_cached(path: &Path, lines: usize) -> std::io::Result<Self> {
+        Ok(Self {
+            bytes: fs::metadata(path)?.len(),
+            lines,
+            approximate: false,
+        })
+    }
+
+    /// Stream the file rather than holding it, it may be far larger than the preview.
+    /// Past `line_sample_bytes` the line count is extrapolated from the head.
+    fn of_file(path: &Path, line_sample_bytes: u64, cancelled: &AtomicBool) -> std::io::Result<Self> {
+        let LineCount { lines, approximate } = count_file_lines(path, line_sample_bytes, cancelled)?;
+        Ok(Self {
+            bytes: fs::metadata(path)?.len(),
+            lines,
+            approximate,
+        })
+    }
+
+    pub fn tokens(&self) -> u64 {
+        estimate_tokens(self.bytes)
+    }
+
+    /// `12.3 KB · 340 lines · ≈ 3.1k tokens`
+    pub fn describe(&self) -> String {
+        format!(
+            "{} · {}{} line{} · ≈ {} tokens",
+            format_file_size(self.bytes),
+            if self.approximate { "~" } else { "" },
+            self.lines,
+            if self.lines == 1 { "" } else { "s" },
+            format_tokens(self.tokens())
+        )
+    }
+}
+
+impl FilePreview {
+    /// Read the head of the file; only the line count looks further, up to `line_sample_bytes`,
+    /// and not at all with `cached_lines` from the content cache
+    pub fn load(tree_index: usize, path: &Path, is_directory: bool, line_sample_bytes: u64, cached_lines: Option<usize>) -> Self {
+        Self::load_unless_cancelled(tree_index, path, is_directory, line_sample_bytes, cached_lines, &AtomicBool::new(false))
+            .unwrap_or_else(|| Self::placeholder(tree_index, PreviewContent::Loading))
+    }
+
+    /// `load`, giving up between chunks once `cancelled` is set
+    fn load_unless_cancelled(
+        tree_index: usize,
+        path: &Path,
+        is_directory: bool,
+        line_sample_bytes: u64,
+        cached_lines: Option<usize>,
+        cancelled: &AtomicBool,
+    ) -> Option<Self> {
+        if is_directory {
+            return Some(Self::placeholder(tree_index, PreviewContent::Directory));
+        }
+
+        let limit = preview_limit(path);
+        match read_head(path, limit, cancelled) {
+            Ok(bytes) => {
+                let mut preview = Self::from_bytes(tree_index, path, &bytes);
+                // Only the head was read; count the rest without keeping it
+                if preview.contribution.is_some() && bytes.len() as u64 == limit {
+                    let contribution = match cached_lines {
+                        Some(lines) => Contribution::of_cached(path, lines),
+                        None => Contribution::of_file(path, line_sample_bytes, cancelled),
+                    };
+                    match contribution {
+                        Err(e) if e.kind() == ErrorKind::Interrupted => return None,
+                        contribution => preview.contribution = contribution.ok(),
+                    }
+                }
+                Some(preview)
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => None,
+            Err(e) => Some(Self::error(tree_index, e.to_string())),
+        }
+    }
+
+    pub fn error(tree_index: usize, message: String) -> Self {
+        Self::placeholder(tree_index, PreviewContent::Error(message))
+    }
+
+    /// A preview with nothing to measure: directories, errors and pending loads
+    pub fn placeholder(tree_index: usize, content: PreviewContent) -> Self {
+        Self {
+            tree_index,
+            content,
+            format: PreviewFormat::Plain,
+            note: None,
+            contribution: None,
+        }
+    }
+
+    /// Preview of the first `preview_limit` bytes of a `size`-byte archive entry. A longer
+    /// entry's line count is extrapolated from them.
+    pub fn from_head(tree_index: usize, path: &Path, head: &[u8], size: u64) -> Self {
+        let mut preview = Self::from_bytes(tree_index, path, head);
+        if let Some(contribution) = &mut preview.contribution
+            && (head.len() as u64) < size
+        {
+            let newlines = head.iter().filter(|&&byte| byte == b'\n').count();
+            let LineCount { lines, approximate } = LineCount::extrapolated(newlines, head.len() as u64, size);
+            *contribution = Contribution { bytes: size, lines, approximate };
+        }
+        preview
+    }
+
+    /// Preview of contents already in memory, e.g. an archive entry
+    pub fn from_bytes(tree_index: usize, path: &Path, bytes: &[u8]) -> Self {
+        let mut format = PreviewFormat::from_path(path);
+        let mut note = None;
+
+        let content = if format.is_structured() {
+            match format_structured(bytes, format) {
+                Ok(content) => content,
+                Err(reason) => {
+                    format = PreviewFormat::Plain;
+                    note = Some(reason);
+                    preview_text(bytes)
+                }
+            }
+        } else {
+            preview_text(bytes)
+        };
+
+        let contribution = matches!(content, PreviewContent::Text(_)).then(|| Contribution::of_bytes(bytes));
+        Self {
+            tree_index,
+            content,
+            format,
+            note,
+            contribution,
+        }
+    }
+
+    pub fn lines(&self) -> &[String] {
+        match &self.content {
+            PreviewContent::Text(lines) => lines,
+            _ => &[],
+        }
+    }
+
+    /// Roughly what the preview takes up in memory
+    fn approximate_bytes(&self) -> usize {
+        let text: usize = self.lines().iter().map(|line| line.capacity() + size_of::<String>()).sum();
+        size_of::<Self>() + text + self.note.as_ref().map_or(0, String::len)
+    }
+}
+
+/// How a `PreviewCache` has fared, reported with `--verbose`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PreviewCacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+    pub peak_bytes: usize,
+}
+
+/// Recently loaded previews, so going back to a file shows it without reading it again.
+/// Least recently used previews are dropped past `budget` bytes; a preview that doesn't
+/// fit at all just isn't kept, and a file modified since it was cached is read again.
+#[derive(Debug)]
+pub struct PreviewCache {
+    budget: usize,
+    entries: VecDeque<(FilePreview, Option<SystemTime>, usize)>, // Preview, mtime and size; most recent last
+    bytes: usize,
+    pub stats: PreviewCacheStats,
+}
+
+impl PreviewCache {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            entries: VecDeque::new(),
+            bytes: 0,
+            stats: PreviewCacheStats::default(),
+        }
+    }
+
+    /// The cached preview of `tree_index`, unless the file at `path` changed since
+    pub fn get(&mut self, tree_index: usize, path: &Path) -> Option<FilePreview> {
+        let Some(position) = self.entries.iter().position(|(preview, _, _)| preview.tree_index == tree_index) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        let entry = self.entries.remove(position)?;
+        if entry.1 != modified(path) {
+            self.bytes -= entry.2;
+            self.stats.misses += 1;
+            return None;
+        }
+        let preview = entry.0.clone();
+        self.entries.push_back(entry);
+        self.stats.hits += 1;
+        Some(preview)
+    }
+
+    /// Keep a finished preview; placeholders aren't worth keeping
+    pub fn insert(&mut self, preview: FilePreview, path: &Path) {
+        if preview.content == PreviewContent::Loading {
+            return;
+        }
+        if let Some(position) = self.entries.iter().position(|(cached, _, _)| cached.tree_index == preview.tree_index)
+            && let Some((_, _, size)) = self.entries.remove(position)
+        {
+            self.bytes -= size;
+        }
+        let size = preview.approximate_bytes();
+        if size > self.budget {
+            return;
+        }
+        while self.bytes + size > self.budget {
+            let Some((_, _, evicted)) = self.entries.pop_front() else { break };
+            self.bytes -= evicted;
+            self.stats.evictions += 1;
+        }
+        self.entries.push_back((preview, modified(path), size));
+        self.bytes += size;
+        self.stats.peak_bytes = self.stats.peak_bytes.max(self.bytes);
+    }
+
+    /// Forget everything, e.g. when tree indices stop meaning the same files
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+    }
+
+    /// `12 hits, 5 misses, 0 evictions, peak 1.2 MB of 32.0 MB`
+    pub fn describe(&self) -> String {
+        format!(
+            "{} hits, {} misses, {} evictions, peak {} of {}",
+            self.stats.hits,
+            self.stats.misses,
+            self.stats.evictions,
+            format_file_size(self.stats.peak_bytes as u64),
+            format_file_size(self.budget as u64)
+        )
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Read up to `limit` bytes in chunks, failing with `Interrupted` once `cancelled` is set
+fn read_head(path: &Path, limit: u64, cancelled: &AtomicBool) -> std::io::Result<Vec<u8>> {
+    let mut reader = fs::File::open(path)?.take(limit);
+    let mut buffer = Vec::new();
+    loop {
+        check_cancelled(cancelled)?;
+        let read = (&mut reader).take(READ_CHUNK_BYTES as u64).read_to_end(&mut buffer)?;
+        if read == 0 {
+            return Ok(buffer);
+        }
+    }
+}
+
+fn check_cancelled(cancelled: &AtomicBool) -> std::io::Result<()> {
+    if cancelled.load(Ordering::Relaxed) {
+        return Err(std::io::Error::new(ErrorKind::Interrupted, "preview cancelled"));
+    }
+    Ok(())
+}
+
+/// A preview being read on a background thread. Dropping it cancels the read, so
+/// moving the cursor on never leaves a backlog of reads for files already passed.
+pub struct PendingPreview {
+    cancelled: Arc<AtomicBool>,
+    receiver: Receiver<FilePreview>,
+}
+
+impl PendingPreview {
+    pub fn spawn(tree_index: usize, path: PathBuf, is_directory: bool, line_sample_bytes: u64, cached_lines: Option<usize>) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let thread_cancelled = Arc::clone(&cancelled);
+        std::thread::spawn(move || {
+            if let Some(preview) = FilePreview::load_unless_cancelled(tree_index, &path, is_directory, line_sample_bytes, cached_lines, &thread_cancelled) {
+                let _ = sender.send(preview);
+            }
+        });
+        Self { cancelled, receiver }
+    }
+
+    /// The preview, once the read has finished
+    pub fn try_take(&self) -> Option<FilePreview> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Drop for PendingPreview {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Bytes of a file a preview reads: structured files are read whole (up to the cap) so
+/// they can be parsed
+pub fn preview_limit(path: &Path) -> u64 {
+    if PreviewFormat::from_path(path).is_structured() {
+        STRUCTURED_MAX_BYTES + 1
+    } else {
+        PREVIEW_MAX_BYTES as u64
+    }
+}
+
+fn preview_text(bytes: &[u8]) -> PreviewContent {
+    let buffer = &bytes[..bytes.len().min(PREVIEW_MAX_BYTES)];
+
+    let text = match std::str::from_utf8(buffer) {
+        Ok(text) => text,
+        // Reading stopped in the middle of a multi-byte character
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&buffer[..e.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return PreviewContent::Binary,
+    };
+
+    if text.contains('\0') {
+        return PreviewContent::Binary;
+    }
+
+    PreviewContent::Text(text.lines().take(PREVIEW_MAX_LINES).map(str::to_string).collect())
+}
+
+/// Parse JSON (pretty-printed) or TOML (validated), failing with a reason to show raw text
+fn format_structured(bytes: &[u8], format: PreviewFormat) -> Result<PreviewContent, String> {
+    if bytes.len() as u64 > STRUCTURED_MAX_BYTES {
+        return Err("too large to format, showing raw text".to_string());
+    }
+
+    let text = std::str::from_utf8(bytes).map_err(|_| "not valid UTF-8, showing raw text".to_string())?;
+
+    let text = match format {
+        PreviewFormat::Json => {
+            let value: serde_json::Value = serde_json::from_str(text)
+                .map_err(|e| format!("invalid JSON ({}), showing raw text", e))?;
+            serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?
+        }
+        PreviewFormat::Toml => {
+            text.parse::<toml::Table>()
+                .map_err(|e| format!("invalid TOML ({}), showing raw text", e.message()))?;
+            text.to_string()
+        }
+        _ => text.to_string(),
+    };
+
+    Ok(PreviewContent::Text(
+        text.lines().take(PREVIEW_MAX_LINES).map(str::to_string).collect(),
+    ))
+}
+
+/// Split each line into styled pieces: keys for JSON/YAML/TOML, headings and code for markdown
+pub fn highlight_lines(format: PreviewFormat, lines: &[String]) -> Vec<Vec<(SpanKind, String)>> {
+    let mut in_code_block = false;
+
+    lines
+        .iter()
+        .map(|line| match format {
+            PreviewFormat::Plain => vec![(SpanKind::Plain, line.clone())],
+            PreviewFormat::Json => highlight_json_line(line),
+            PreviewFormat::Yaml => highlight_key_line(line, ':', '#'),
+            PreviewFormat::Toml => highlight_key_line(line, '=', '#'),
+            PreviewFormat::Markdown => highlight_markdown_line(line, &mut in_code_block),
+        })
+        .collect()
+}
+
+fn highlight_json_line(line: &str) -> Vec<(SpanKind, String)> {
+    // Pretty-printed JSON puts each key at the start of its line: `  "key": value`
+    let trimmed = line.trim_start();
+    if trimmed.starts_with('"')
+        && let Some(end) = trimmed[1..].find("\":").map(|index| index + 2)
+    {
+        let indent = line.len() - trimmed.len();
+        return vec![
+            (SpanKind::Plain, line[..indent].to_string()),
+            (SpanKind::Key, trimmed[..end].to_string()),
+            (SpanKind::Plain, trimmed[end..].to_string()),
+        ];
+    }
+    vec![(SpanKind::Plain, line.to_string())]
+}
+
+fn highlight_key_line(line: &str, separator: char, comment: char) -> Vec<(SpanKind, String)> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with(comment) {
+        return vec![(SpanKind::Comment, line.to_string())];
+    }
+    // TOML table headers
+    if separator == '=' && trimmed.starts_with('[') {
+        return vec![(SpanKind::Heading, line.to_string())];
+    }
+
+    let indent = line.len() - trimmed.len();
+    let key_start = if trimmed.starts_with("- ") { indent + 2 } else { indent };
+    if let Some(offset) = line[key_start..].find(separator) {
+        let key_end = key_start + offset;
+        let key = &line[key_start..key_end];
+        // YAML keys are followed by whitespace, which rules out URLs like `http://`
+        let separated = separator != ':'
+            || line[key_end + 1..].chars().next().is_none_or(char::is_whitespace);
+        let is_quoted = key.starts_with('"') && key.trim_end().ends_with('"');
+        let is_bare = !key.trim().is_empty() && !key.contains(['"', '\'', '{', '[']);
+        if separated && (is_quoted || is_bare) {
+            return vec![
+                (SpanKind::Plain, line[..key_start].to_string()),
+                (SpanKind::Key, key.to_string()),
+                (SpanKind::Plain, line[key_end..].to_string()),
+            ];
+        }
+    }
+    vec![(SpanKind::Plain, line.to_string())]
+}
+
+fn highlight_markdown_line(line: &str, in_code_block: &mut bool) -> Vec<(SpanKind, String)> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+        *in_code_block = !*in_code_block;
+        return vec![(SpanKind::Code, line.to_string())];
+    }
+    if *in_code_block {
+        return vec![(SpanKind::Code, line.to_string())];
+    }
+    if trimmed.starts_with('#') {
+        return vec![(SpanKind::Heading, line.to_string())];
+    }
+
+    // Inline `code` spans alternate with plain text
+    if line.matches('`').count() % 2 == 1 {
+        return vec![(SpanKind::Plain, line.to_string())];
+    }
+    line.split('`')
+        .enumerate()
+        .filter(|(_, part)| !part.is_empty())
+        .map(|(index, part)| {
+            if index % 2 == 1 {
+                (SpanKind::Code, format!("`{}`", part))
+            } else {
+                (SpanKind::Plain, part.to_string())
+            }
+        })
+        .collect()
+}
+
+/// Lines of `lines` that have no counterpart in `other` (counting duplicates),
+/// as indices into `lines`
+pub fn unmatched_lines(lines: &[String], other: &[String]) -> Vec<bool> {
+    let mut remaining: HashMap<&str, usize> = HashMap::new();
+    for line in other {
+        *remaining.entry(line.as_str()).or_default() += 1;
+    }
+
+    lines
+        .iter()
+        .map(|line| match remaining.get_mut(line.as_str()) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::info::LINE_SAMPLE_BYTES;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_preview() {
+        let temp_dir = TempDir::new().unwrap();
+        let text_path = temp_dir.path().join("main.rs");
+        let binary_path = temp_dir.path().join("image.bin");
+        fs::write(&text_path, "fn main() {\n}\n").unwrap();
+        fs::write(&binary_path, [0xff, 0xfe, 0x00, 0x01]).unwrap();
+
+        let preview = FilePreview::load(1, &text_path, false, LINE_SAMPLE_BYTES, None);
+        assert_eq!(preview.lines(), ["fn main() {", "}"]);
+
+        let preview = FilePreview::load(2, &binary_path, false, LINE_SAMPLE_BYTES, None);
+        assert_eq!(preview.content, PreviewContent::Binary);
+
+        let preview = FilePreview::load(3, &temp_dir.path().join("missing.rs"), false, LINE_SAMPLE_BYTES, None);
+        assert!(matches!(preview.content, PreviewContent::Error(_)));
+    }
+
+    #[test]
+    fn test_preview_cache_budget() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths: Vec<PathBuf> = (0..3).map(|index| temp_dir.path().join(format!("{}.rs", index))).collect();
+        for path in &paths {
+            fs::write(path, "// line\n".repeat(100)).unwrap();
+        }
+        let preview = |index: usize| FilePreview::load(index, &paths[index], false, 0, None);
+        let size = preview(0).approximate_bytes();
+
+        // Room for two: the least recently used one goes
+        let mut cache = PreviewCache::new(size * 2);
+        cache.insert(preview(0), &paths[0]);
+        cache.insert(preview(1), &paths[1]);
+        assert!(cache.get(0, &paths[0]).is_some());
+        cache.insert(preview(2), &paths[2]);
+        assert!(cache.get(1, &paths[1]).is_none());
+        assert_eq!(cache.get(0, &paths[0]).unwrap().lines().len(), 100);
+
+        // A changed file is read again, and a preview over the whole budget isn't kept
+        fs::File::options().write(true).open(&paths[2]).unwrap()
+            .set_modified(SystemTime::now() - std::time::Duration::from_secs(60)).unwrap();
+        assert!(cache.get(2, &paths[2]).is_none());
+        let mut tiny = PreviewCache::new(size / 2);
+        tiny.insert(preview(0), &paths[0]);
+        assert!(tiny.get(0, &paths[0]).is_none());
+
+        assert_eq!(cache.stats, PreviewCacheStats { hits: 2, misses: 2, evictions: 1, peak_bytes: size * 2 });
+    }
+
+    #[test]
+    fn test_cancelled_preview() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("main.rs");
+        fs::write(&path, "fn main() {}\n").unwrap();
+
+        let cancelled = AtomicBool::new(true);
+        assert!(FilePreview::load_unless_cancelled(1, &path, false, LINE_SAMPLE_BYTES, None, &cancelled).is_none());
+
+        let pending = PendingPreview::spawn(1, path, false, LINE_SAMPLE_BYTES, None);
+        let cancelled = Arc::clone(&pending.cancelled);
+        let preview = pending.receiver.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+        assert_eq!(preview.lines(), ["fn main() {}"]);
+        drop(pending);
+        assert!(cancelled.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_file_contribution() {
+        let temp_dir = TempDir::new().unwrap();
+        let small_path = temp_dir.path().join("small.rs");
+        let large_path = temp_dir.path().join("large.txt");
+        fs::write(&small_path, "fn main() {\n}").unwrap();
+        // Longer than the preview reads, so the rest is counted from disk
+        fs::write(&large_path, "line\n".repeat(PREVIEW_MAX_BYTES / 5 + 100)).unwrap();
+
+        let small = FilePreview::load(0, &small_path, false, LINE_SAMPLE_BYTES, None).contribution.unwrap();
+        assert_eq!((small.bytes, small.lines, small.tokens()), (13, 2, 4));
+
+        let large = FilePreview::load(1, &large_path, false, LINE_SAMPLE_BYTES, None).contribution.unwrap();
+        assert_eq!(large.lines, PREVIEW_MAX_BYTES / 5 + 100);
+        assert_eq!(large.bytes, large.lines as u64 * 5);
+
+        // With the line count cached, the rest of the file isn't read
+        let cached = FilePreview::load(1, &large_path, false, LINE_SAMPLE_BYTES, Some(7)).contribution.unwrap();
+        assert_eq!((cached.bytes, cached.lines, cached.approximate), (large.bytes, 7, false));
+
+        assert!(FilePreview::load(2, temp_dir.path(), true, LINE_SAMPLE_BYTES, None).contribution.is_none());
+
+        // An archive entry's head stands in for the rest of it
+        let head = "line\n".repeat(10);
+        let entry = FilePreview::from_head(3, Path::new("notes.txt"), head.as_bytes(), 500).contribution.unwrap();
+        assert_eq!((entry.bytes, entry.lines, entry.approximate), (500, 100, true));
+        let whole = FilePreview::from_head(3, Path::new("notes.txt"), head.as_bytes(), 50).contribution.unwrap();
+        assert_eq!((whole.lines, whole.approximate), (10, false));
+    }
+
+    #[test]
+    fn test_gigantic_file_preview() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("dump.sql");
+        // A 2 MB head of real lines, then a sparse tail up to 600 MB
+        fs::write(&path, "insert;\n".repeat(2 * 1024 * 1024 / 8)).unwrap();
+        let size = 600 * 1024 * 1024;
+        fs::File::options().write(true).open(&path).unwrap().set_len(size).unwrap();
+
+        let preview = FilePreview::load(0, &path, false, 1024 * 1024, None);
+        assert_eq!(preview.lines().len(), PREVIEW_MAX_LINES);
+        let contribution = preview.contribution.unwrap();
+        assert_eq!(contribution.bytes, size);
+        assert!(contribution.approximate);
+        assert!(contribution.lines.abs_diff(size as usize / 8) < size as usize / 800);
+        assert!(contribution.describe().contains("600.0 MB · ~"));
+    }
+
+    #[test]
+    fn test_structured_preview() {
+        let temp_dir = TempDir::new().unwrap();
+        let json_path = temp_dir.path().join("package.json");
+        let broken_path = temp_dir.path().join("broken.json");
+        fs::write(&json_path, r#"{"name":"gthr","tags":["cli"]}"#).unwrap();
+        fs::write(&broken_path, r#"{"name": "#).unwrap();
+
+        let preview = FilePreview::load(1, &json_path, false, LINE_SAMPLE_BYTES, None);
+        assert_eq!(preview.format, PreviewFormat::Json);
+        assert_eq!(preview.lines(), ["{", "  \"name\": \"gthr\",", "  \"tags\": [", "    \"cli\"", "  ]", "}"]);
+        assert!(preview.note.is_none());
+
+        // Parse failures fall back to raw text with a note
+        let preview = FilePreview::load(2, &broken_path, false, LINE_SAMPLE_BYTES, None);
+        assert_eq!(preview.format, PreviewFormat::Plain);
+        assert_eq!(preview.lines(), [r#"{"name": "#]);
+        assert!(preview.note.unwrap().starts_with("invalid JSON"));
+    }
+
+    #[test]
+    fn test_highlight_lines() {
+        let lines = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let kinds = |line: &Vec<(SpanKind, String)>| line.iter().map(|(kind, _)| *kind).collect::<Vec<_>>();
+
+        let yaml = highlight_lines(PreviewFormat::Yaml, &lines(&["# config", "name: gthr", "see http://x", "- key: 1"]));
+        assert_eq!(kinds(&yaml[0]), [SpanKind::Comment]);
+        assert_eq!(yaml[1][1], (SpanKind::Key, "name".to_string()));
+        assert_eq!(kinds(&yaml[2]), [SpanKind::Plain]);
+        assert_eq!(yaml[3][1], (SpanKind::Key, "key".to_string()));
+
+        let toml = highlight_lines(PreviewFormat::Toml, &lines(&["[package]", "name = \"gthr\""]));
+        assert_eq!(kinds(&toml[0]), [SpanKind::Heading]);
+        assert_eq!(toml[1][1], (SpanKind::Key, "name ".to_string()));
+
+        let markdown = highlight_lines(PreviewFormat::Markdown, &lines(&["# Title", "Run `gthr`", "```", "# not a heading", "```"]));
+        assert_eq!(kinds(&markdown[0]), [SpanKind::Heading]);
+        assert_eq!(kinds(&markdown[1]), [SpanKind::Plain, SpanKind::Code]);
+        assert_eq!(kinds(&markdown[3]), [SpanKind::Code]);
+    }
+
+    #[test]
+    fn test_unmatched_lines() {
+        let lines = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let left = lines(&["a", "b", "b", "c"]);
+        let right = lines(&["a", "b", "d"]);
+
+        assert_eq!(unmatched_lines(&left, &right), vec![false, false, true, true]);
+        assert_eq!(unmatched_lines(&right, &left), vec![false, false, true]);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::output::formatter::format_file_size;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+/// Taskbar or tab progress, as reported with ConEmu's OSC 9;4 sequence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Progress {
+    Hidden,
+    Indeterminate,
+}
+
+/// The window title and progress sequences gthr sends while it runs. The title in place
+/// before is pushed onto the terminal's title stack and popped back on `finish` (or drop).
+#[derive(Debug)]
+pub struct TerminalIntegration {
+    title_enabled: bool,
+    progress_enabled: bool,
+    title_pushed: bool,
+    last_title: Option<String>,
+    last_progress: Option<Progress>,
+}
+
+impl TerminalIntegration {
+    /// Whatever the terminal is known to handle, or nothing with `--no-term-integration`
+    pub fn detect(enabled: bool) -> Self {
+        let enabled = enabled && io::stdout().is_terminal();
+        Self::new(enabled && supports_title(), enabled && supports_progress())
+    }
+
+    fn new(title_enabled: bool, progress_enabled: bool) -> Self {
+        Self {
+            title_enabled,
+            progress_enabled,
+            title_pushed: false,
+            last_title: None,
+            last_progress: None,
+        }
+    }
+
+    /// Set the title and progress, writing only what changed since the last call
+    pub fn update(&mut self, out: &mut impl Write, title: &str, progress: Progress) -> io::Result<()> {
+        if self.title_enabled && self.last_title.as_deref() != Some(title) {
+            if !self.title_pushed {
+                write!(out, "\x1b[22;0t")?; // Save the current title (XTWINOPS)
+                self.title_pushed = true;
+            }
+            let printable: String = title.chars().filter(|c| !c.is_control()).collect();
+            write!(out, "\x1b]2;{}\x07", printable)?;
+            self.last_title = Some(title.to_string());
+        }
+        self.set_progress(out, progress)?;
+        out.flush()
+    }
+
+    /// Show or hide progress, leaving the title alone
+    pub fn set_progress(&mut self, out: &mut impl Write, progress: Progress) -> io::Result<()> {
+        if !self.progress_enabled || self.last_progress == Some(progress) {
+            return Ok(());
+        }
+        match progress {
+            Progress::Hidden => write!(out, "\x1b]9;4;0;0\x07")?,
+            Progress::Indeterminate => write!(out, "\x1b]9;4;3;0\x07")?,
+        }
+        self.last_progress = Some(progress);
+        out.flush()
+    }
+
+    /// Hide any progress and restore the title from before
+    pub fn finish(&mut self, out: &mut impl Write) -> io::Result<()> {
+        if self.last_progress.is_some_and(|progress| progress != Progress::Hidden) {
+            self.set_progress(out, Progress::Hidden)?;
+        }
+        if self.title_pushed {
+            write!(out, "\x1b[23;0t")?;
+            self.title_pushed = false;
+            self.last_title = None;
+        }
+        out.flush()
+    }
+}
+
+impl Drop for TerminalIntegration {
+    fn drop(&mut self) {
+        let _ = self.finish(&mut io::stdout());
+    }
+}
+
+/// `gthr — ~/src/project (42 files, 18.0 KB selected)`, with the home directory as `~`
+pub fn window_title(root: &Path, included_files: usize, total_size: u64) -> String {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let root = match home.as_deref().and_then(|home| root.strip_prefix(home).ok()) {
+        Some(relative) if relative.as_os_str().is_empty() => PathBuf::from("~"),
+        Some(relative) => Path::new("~").join(relative),
+        None => root.to_path_buf(),
+    };
+    format!(
+        "gthr — {} ({} files, {} selected)",
+        root.display(),
+        included_files,
+        format_file_size(total_size)
+    )
+}
+
+/// Multiplexers pass titles through only when configured to, and garble them otherwise
+fn supports_title() -> bool {
+    let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    !(var("TERM").is_none_or(|term| term == "dumb") || var("TMUX").is_some() || var("STY").is_some())
+}
+
+/// Only terminals that put OSC 9;4 progress on the taskbar or tab; others print it as text
+fn supports_progress() -> bool {
+    let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    if !supports_title() {
+        return false;
+    }
+    var("WT_SESSION").is_some()
+        || var("ConEmuANSI").is_some_and(|value| value == "ON")
+        || var("TERM_PROGRAM").is_some_and(|program| ["ghostty", "WezTerm"].contains(&program.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_and_progress_sequences() {
+        let mut integration = TerminalIntegration::new(true, true);
+        let mut out = Vec::new();
+        let title = window_title(Path::new("/project"), 42, 18 * 1024);
+        assert_eq!(title, "gthr — /project (42 files, 18.0 KB selected)");
+
+        integration.update(&mut out, &title, Progress::Indeterminate).unwrap();
+        integration.update(&mut out, &title, Progress::Indeterminate).unwrap();
+        integration.update(&mut out, "gthr\x1b]2;evil", Progress::Hidden).unwrap();
+        integration.finish(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out,
+            "\x1b[22;0t\x1b]2;gthr — /project (42 files, 18.0 KB selected)\x07\x1b]9;4;3;0\x07\
+             \x1b]2;gthr]2;evil\x07\x1b]9;4;0;0\x07\x1b[23;0t"
+        );
+
+        // Progress set without a title is hidden on finish, and only once: dropping after
+        // finishing, or on an early return, writes nothing more
+        let mut startup = TerminalIntegration::new(true, true);
+        let mut out = Vec::new();
+        startup.set_progress(&mut out, Progress::Indeterminate).unwrap();
+        startup.finish(&mut out).unwrap();
+        startup.finish(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\x1b]9;4;3;0\x07\x1b]9;4;0;0\x07");
+
+        // Nothing is written when the terminal supports neither
+        let mut disabled = TerminalIntegration::new(false, false);
+        let mut out = Vec::new();
+        disabled.update(&mut out, &title, Progress::Indeterminate).unwrap();
+        disabled.finish(&mut out).unwrap();
+        assert!(out.is_empty());
+    }
+}
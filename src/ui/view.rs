@@ -0,0 +1,122 @@
+use crate::ui::app::{AppMode, Focus};
+
+/// Where the interface is looking: the cursor and scroll position in the result list, the
+/// number of rows that fit, and which mode and pane have the keyboard. Nothing here changes
+/// what gets exported; see `SelectionModel` for that.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewState {
+    pub selected_index: usize, // Position in the filtered results
+    pub scroll_offset: usize,
+    pub viewport_height: usize, // Rows of the file list, updated on every draw
+    pub mode: AppMode,
+    pub focus: Focus,
+}
+
+impl Default for ViewState {
+    fn default() -> Self {
+        Self {
+            selected_index: 0,
+            scroll_offset: 0,
+            viewport_height: 20, // Default, will be updated by UI
+            mode: AppMode::Main,
+            focus: Focus::List,
+        }
+    }
+}
+
+impl ViewState {
+    /// Keep the cursor within `len` results after they changed, back at the top of the list
+    pub fn reset_scroll(&mut self, len: usize) {
+        self.scroll_offset = 0;
+
+        // Adjust selected index if it's out of bounds
+        if self.selected_index >= len && len > 0 {
+            self.selected_index = len - 1;
+        } else if len == 0 {
+            self.selected_index = 0;
+        }
+
+        self.update_scroll();
+    }
+
+    pub fn move_up(&mut self) {
+        if self.selected_index > 0 {
+            self.selected_index -= 1;
+            self.update_scroll_for_move_up();
+        }
+    }
+
+    pub fn move_down(&mut self, len: usize) {
+        if self.selected_index + 1 < len {
+            self.selected_index += 1;
+            self.update_scroll_for_move_down();
+        }
+    }
+
+    pub fn page_up(&mut self) {
+        let page_size = self.viewport_height.saturating_sub(1);
+        let old_index = self.selected_index;
+        self.selected_index = self.selected_index.saturating_sub(page_size);
+
+        if old_index != self.selected_index {
+            // Scroll to show the selected item at the top of the viewport
+            self.scroll_offset = self.selected_index;
+        }
+    }
+
+    pub fn page_down(&mut self, len: usize) {
+        let page_size = self.viewport_height.saturating_sub(1);
+        let old_index = self.selected_index;
+        self.selected_index = (self.selected_index + page_size).min(len.saturating_sub(1));
+
+        if old_index != self.selected_index {
+            // Scroll to show the selected item at the bottom of the viewport
+            self.scroll_to_bottom_of_viewport();
+        }
+    }
+
+    pub fn move_to_top(&mut self) {
+        self.selected_index = 0;
+        self.scroll_offset = 0;
+    }
+
+    pub fn move_to_bottom(&mut self, len: usize) {
+        if len > 0 {
+            self.selected_index = len - 1;
+
+            // Position the last item at the bottom of the viewport
+            self.scroll_to_bottom_of_viewport();
+        }
+    }
+
+    fn scroll_to_bottom_of_viewport(&mut self) {
+        if self.selected_index >= self.viewport_height {
+            self.scroll_offset = self.selected_index.saturating_sub(self.viewport_height.saturating_sub(1));
+        } else {
+            self.scroll_offset = 0;
+        }
+    }
+
+    fn update_scroll_for_move_up(&mut self) {
+        // If the selected index is now above the visible area, scroll up
+        if self.selected_index < self.scroll_offset {
+            self.scroll_offset = self.selected_index;
+        }
+    }
+
+    fn update_scroll_for_move_down(&mut self) {
+        // If the selected index is now below the visible area, scroll down
+        if self.selected_index >= self.scroll_offset + self.viewport_height {
+            self.scroll_offset = self.selected_index.saturating_sub(self.viewport_height.saturating_sub(1));
+        }
+    }
+
+    pub fn update_scroll(&mut self) {
+        // General scroll update - ensures selected item is visible
+        if self.selected_index < self.scroll_offset {
+            self.scroll_offset = self.selected_index;
+        } else if self.selected_index >= self.scroll_offset + self.viewport_height {
+            self.scroll_offset = self.selected_index.saturating_sub(self.viewport_height.saturating_sub(1));
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use crate::config::profile::Profile;
+use crate::config::session::{Session, canonical_root};
+use crate::config::settings::Settings;
+use anyhow::{Result, bail};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Questions of the first-run wizard, in the order they're asked
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WizardStep {
+    Selection,
+    Gitignore,
+    TokenBudget,
+    Format,
+}
+
+const STEPS: [WizardStep; 4] = [
+    WizardStep::Selection,
+    WizardStep::Gitignore,
+    WizardStep::TokenBudget,
+    WizardStep::Format,
+];
+
+/// Answers collected by the wizard shown when a project has no config, session or profile yet.
+/// Finishing it writes them to the project's `.gthr.toml`.
+#[derive(Debug, Clone)]
+pub struct Wizard {
+    pub step: usize, // Index into the steps
+    pub include_all: bool,
+    pub respect_gitignore: bool,
+    pub token_budget: String, // Digits typed so far; empty means no budget
+    pub formats: Vec<&'static str>,
+    pub format: usize, // Index into `formats`
+    initial_include_all: bool,
+    initial_respect_gitignore: bool,
+}
+
+impl Wizard {
+    /// Start from the current settings, so pressing Enter throughout keeps them
+    pub fn new(settings: &Settings, formats: Vec<&'static str>) -> Self {
+        let format = formats.iter().position(|name| *name == settings.format).unwrap_or(0);
+        Self {
+            step: 0,
+            include_all: settings.include_all,
+            respect_gitignore: settings.respect_gitignore,
+            token_budget: settings.token_budget.map(|budget| budget.to_string()).unwrap_or_default(),
+            formats,
+            format,
+            initial_include_all: settings.include_all,
+            initial_respect_gitignore: settings.respect_gitignore,
+        }
+    }
+
+    /// Whether the project at `root` is run for the first time: nothing has been set up or
+    /// remembered for it yet, no config, session or profile
+    pub fn is_due(root: &Path, settings: &Settings) -> bool {
+        settings.first_run_wizard
+            && !Settings::get_project_config_path(root).exists()
+            && !Session::get_session_path(&canonical_root(root)).exists()
+            && !Profile::any_saved(root)
+    }
+
+    /// Whether the answer differs from what the current tree was scanned with
+    pub fn selection_changed(&self) -> bool {
+        self.include_all != self.initial_include_all
+    }
+
+    pub fn gitignore_changed(&self) -> bool {
+        self.respect_gitignore != self.initial_respect_gitignore
+    }
+
+    pub fn current_step(&self) -> WizardStep {
+        STEPS[self.step]
+    }
+
+    /// `2/4`, for the dialog title
+    pub fn progress(&self) -> String {
+        format!("{}/{}", self.step + 1, STEPS.len())
+    }
+
+    pub fn question(&self) -> &'static str {
+        match self.current_step() {
+            WizardStep::Selection => "Should files start out included or excluded?",
+            WizardStep::Gitignore => "Hide files ignored by .gitignore?",
+            WizardStep::TokenBudget => "Token budget for exports (empty for none):",
+            WizardStep::Format => "Export format:",
+        }
+    }
+
+    /// The choices of the current step with the chosen one's position, or `None`
+    /// for the token budget, which is typed in
+    pub fn choices(&self) -> Option<(Vec<&'static str>, usize)> {
+        match self.current_step() {
+            WizardStep::Selection => Some((vec!["Excluded", "Included"], self.include_all as usize)),
+            WizardStep::Gitignore => Some((vec!["Yes", "No"], !self.respect_gitignore as usize)),
+            WizardStep::TokenBudget => None,
+            WizardStep::Format => Some((self.formats.clone(), self.format)),
+        }
+    }
+
+    /// Choose the next or previous option, wrapping around
+    pub fn move_choice(&mut self, delta: isize) {
+        match self.current_step() {
+            WizardStep::Selection => self.include_all = !self.include_all,
+            WizardStep::Gitignore => self.respect_gitignore = !self.respect_gitignore,
+            WizardStep::TokenBudget => {}
+            WizardStep::Format => {
+                let count = self.formats.len().max(1) as isize;
+                self.format = (self.format as isize + delta).rem_euclid(count) as usize;
+            }
+        }
+    }
+
+    /// Type into the token budget; anything but digits is ignored
+    pub fn add_char(&mut self, c: char) {
+        if self.current_step() == WizardStep::TokenBudget && c.is_ascii_digit() && self.token_budget.len() < 12 {
+            self.token_budget.push(c);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if self.current_step() == WizardStep::TokenBudget {
+            self.token_budget.pop();
+        }
+    }
+
+    /// Go to the next question. Returns `true` once every question has been answered.
+    pub fn next(&mut self) -> bool {
+        if self.step + 1 < STEPS.len() {
+            self.step += 1;
+            false
+        } else {
+            true
+        }
+    }
+
+    pub fn budget(&self) -> Option<u64> {
+        self.token_budget.parse().ok().filter(|&budget| budget > 0)
+    }
+
+    pub fn chosen_format(&self) -> &'static str {
+        self.formats.get(self.format).copied().unwrap_or(crate::output::formatter::DEFAULT_FORMAT)
+    }
+
+    /// The answers as TOML, only the keys the wizard asked about
+    pub fn to_toml(&self) -> String {
+        let budget = match self.budget() {
+            Some(budget) => format!("token_budget = {}", budget),
+            None => "# token_budget = 100000".to_string(),
+        };
+        format!(
+            "# Written by the gthr first-run wizard; see the README for every setting\n\
+             \n\
+             # Start with every file included instead of excluded (like -I)\n\
+             include_all = {}\n\
+             \n\
+             # Hide files ignored by .gitignore\n\
+             respect_gitignore = {}\n\
+             \n\
+             # Estimated tokens an export should stay under, shown in the status bar\n\
+             {}\n\
+             \n\
+             # Export format\n\
+             format = \"{}\"\n",
+            self.include_all,
+            self.respect_gitignore,
+            budget,
+            self.chosen_format()
+        )
+    }
+
+    /// Write the answers to `.gthr.toml` in `project_root`, never replacing an existing file
+    pub fn save(&self, project_root: &Path) -> Result<PathBuf> {
+        let path = Settings::get_project_config_path(project_root);
+        if path.exists() {
+            bail!("{} already exists", path.display());
+        }
+        fs::write(&path, self.to_toml())?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_wizard_round_trip() -> Result<()> {
+        let mut wizard = Wizard::new(&Settings::default(), vec!["markdown", "plain", "json", "xml"]);
+        assert_eq!(wizard.current_step(), WizardStep::Selection);
+        wizard.move_choice(1);
+        assert!(!wizard.next());
+        wizard.move_choice(1);
+        assert!(!wizard.next());
+
+        // Only digits make it into the budget
+        for c in "12k0000".chars() {
+            wizard.add_char(c);
+        }
+        wizard.backspace();
+        assert!(!wizard.next());
+        wizard.move_choice(-1);
+        assert_eq!(wizard.choices(), Some((vec!["markdown", "plain", "json", "xml"], 3)));
+        assert!(wizard.next());
+
+        let temp_dir = TempDir::new()?;
+        let path = wizard.save(temp_dir.path())?;
+        let settings = Settings::load_from_file(&path)?;
+        assert!(settings.include_all);
+        assert!(!settings.respect_gitignore);
+        assert_eq!(settings.token_budget, Some(12000));
+        assert_eq!(settings.format, "xml");
+
+        // A second run never overwrites the file
+        assert!(wizard.save(temp_dir.path()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_wizard_defaults() -> Result<()> {
+        // Enter on every question keeps the current settings
+        let mut wizard = Wizard::new(&Settings::default(), vec!["markdown", "plain"]);
+        while !wizard.next() {}
+        let temp_dir = TempDir::new()?;
+        let settings = Settings::load_from_file(&wizard.save(temp_dir.path())?)?;
+        let defaults = Settings::default();
+        assert_eq!(settings.include_all, defaults.include_all);
+        assert_eq!(settings.respect_gitignore, defaults.respect_gitignore);
+        assert_eq!(settings.token_budget, None);
+        assert_eq!(settings.format, defaults.format);
+        Ok(())
+    }
+}